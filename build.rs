@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // generates the types and services of the gRPC API (see src/committee/grpc.rs),
+    // which is only part of the `node` feature (as is tonic-build, so that other builds don't need protoc)
+    #[cfg(feature = "node")]
+    tonic_build::compile_protos("proto/zkbitcoin.proto")?;
+    Ok(())
+}
@@ -0,0 +1,18 @@
+pragma circom 2.1.3;
+
+// A step of a folded zkapp: a single state transition, moving `amount_in` into the zkapp and `amount_out` out of it.
+// The running values are [state, truncated_txid, amount_out, amount_in].
+template Step() {
+    signal input step_in[4];
+    signal output step_out[4];
+
+    signal input amount_out;
+    signal input amount_in;
+
+    step_out[0] <== step_in[0] + amount_in - amount_out;
+    step_out[1] <== step_in[1];
+    step_out[2] <== step_in[2] + amount_out;
+    step_out[3] <== step_in[3] + amount_in;
+}
+
+component main{public [step_in]} = Step();
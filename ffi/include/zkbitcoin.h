@@ -0,0 +1,52 @@
+/*
+ * C bindings to zkBitcoin (see ffi/src/lib.rs).
+ *
+ * Transactions are passed around hex-encoded, and everything else in JSON.
+ * Functions returning a string return NULL on error, and functions returning an int return -1
+ * (and 0 on success). The error can then be retrieved with zkbitcoin_last_error().
+ * Every string returned must be freed with zkbitcoin_string_free().
+ */
+
+#ifndef ZKBITCOIN_H
+#define ZKBITCOIN_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Errors and strings */
+
+char *zkbitcoin_last_error(void);
+void zkbitcoin_string_free(char *s);
+
+/* Configuration (each can only be set once, before anything else) */
+
+int zkbitcoin_set_network(const char *network);
+int zkbitcoin_set_protocol_config(const char *config_json);
+
+/* Alice: deploying a zkapp (initial_state is NULL for stateless zkapps) */
+
+char *zkbitcoin_deploy_transaction(const char *vk_json, const char *initial_state,
+                                   uint64_t satoshi_amount);
+char *zkbitcoin_parse_zkapp(const char *zkapp_tx_hex);
+
+/* Bob: spending a zkapp (new_state is NULL for stateless zkapps, fee_schedule_json for the fee of the protocol) */
+
+char *zkbitcoin_spend_transaction(const char *zkapp_tx_hex, const char *recipients_json,
+                                  const char *new_state, uint64_t amount_in,
+                                  uint64_t amount_out, const char *fee_schedule_json);
+char *zkbitcoin_truncated_txid(const char *tx_hex);
+char *zkbitcoin_package_request(const char *tx_hex, const char *zkapp_tx_hex,
+                                const char *vk_json, const char *proof_json,
+                                const char *public_inputs_json, const char *recipients_json,
+                                const char *prev_outs_json, const char *nonce_hex);
+char *zkbitcoin_unlock_funds_payload(const char *request_json);
+char *zkbitcoin_parse_response(const char *response_json);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ZKBITCOIN_H */
@@ -0,0 +1,354 @@
+//! C bindings to the core of zkBitcoin, so that wallets not written in Rust (C, C++, Swift, ...)
+//! can deploy zkapps and build the requests spending them (see `include/zkbitcoin.h`).
+//!
+//! The bindings don't talk to Bitcoin Core, to the orchestrator, or to a prover:
+//! the wallet funds and signs the transactions, proves the circuit, and sends the requests itself.
+//! Transactions are passed around hex-encoded, and everything else in JSON
+//! (verifier keys, proofs, and public inputs as produced by snarkjs).
+//!
+//! Functions returning a string return `NULL` on error, and functions returning an `int` return `-1`
+//! (and `0` on success). The error can then be retrieved with [zkbitcoin_last_error].
+//! Every string returned must be freed with [zkbitcoin_string_free].
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    ptr,
+};
+
+use anyhow::{Context, Result};
+use bitcoin::consensus::encode;
+use zkbitcoin::{
+    alice_sign_tx::unsigned_deploy,
+    bindings::{self, parse_network, parse_tx},
+    config::{set_protocol_config, ProtocolConfig},
+    plonk, set_network, truncate_txid,
+};
+
+//
+// Errors and strings
+//
+
+thread_local! {
+    /// The error of the last call that failed on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(err: anyhow::Error) {
+    // error messages can't contain a nul byte, but better be safe than sorry
+    let err = CString::new(format!("{err:#}").replace('\0', "")).unwrap();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(err));
+}
+
+/// Returns a string allocated by Rust (see [zkbitcoin_string_free]), or `NULL` on error.
+fn string_result(f: impl FnOnce() -> Result<String>) -> *mut c_char {
+    match f().and_then(|s| CString::new(s).context("the result contains a nul byte")) {
+        Ok(s) => s.into_raw(),
+        Err(err) => {
+            set_last_error(err);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Returns `0`, or `-1` on error.
+fn status_result(f: impl FnOnce() -> Result<()>) -> c_int {
+    match f() {
+        Ok(()) => 0,
+        Err(err) => {
+            set_last_error(err);
+            -1
+        }
+    }
+}
+
+/// Reads a (nul-terminated, UTF-8) string passed by the caller.
+///
+/// # Safety
+///
+/// `s` must be `NULL` or point to a nul-terminated string that outlives the call.
+unsafe fn read_str<'a>(s: *const c_char, name: &str) -> Result<&'a str> {
+    read_opt_str(s, name)?.with_context(|| format!("{name} can't be NULL"))
+}
+
+/// Same as [read_str], but `NULL` is read as `None`.
+///
+/// # Safety
+///
+/// Same as [read_str].
+unsafe fn read_opt_str<'a>(s: *const c_char, name: &str) -> Result<Option<&'a str>> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    let s = CStr::from_ptr(s)
+        .to_str()
+        .with_context(|| format!("{name} is not valid UTF-8"))?;
+    Ok(Some(s))
+}
+
+/// Returns the error of the last call that failed on the calling thread (or `NULL` if none did).
+/// The string must be freed with [zkbitcoin_string_free].
+#[no_mangle]
+pub extern "C" fn zkbitcoin_last_error() -> *mut c_char {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some(err) => err.clone().into_raw(),
+        None => ptr::null_mut(),
+    })
+}
+
+/// Frees a string returned by the library.
+///
+/// # Safety
+///
+/// `s` must be `NULL` or a string returned by the library, which must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn zkbitcoin_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+//
+// Configuration
+//
+
+/// Sets the network (`mainnet`, `testnet`, `signet`, or `regtest`), testnet by default.
+/// This can only be done once, before anything else.
+///
+/// # Safety
+///
+/// `network` must point to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn zkbitcoin_set_network(network: *const c_char) -> c_int {
+    status_result(|| {
+        let network = parse_network(read_str(network, "network")?)?;
+        set_network(network)
+    })
+}
+
+/// Sets the protocol configuration (given in JSON, see `ProtocolConfig`), the default one otherwise.
+/// This can only be done once, before anything else.
+///
+/// # Safety
+///
+/// `config_json` must point to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn zkbitcoin_set_protocol_config(config_json: *const c_char) -> c_int {
+    status_result(|| {
+        let config: ProtocolConfig = serde_json::from_str(read_str(config_json, "config")?)
+            .context("couldn't parse the protocol config")?;
+        set_protocol_config(config)
+    })
+}
+
+//
+// Alice: deploying a zkapp
+//
+
+/// Creates the (unfunded) transaction deploying a zkapp locking `satoshi_amount`,
+/// that can be unlocked with proofs verified by `vk_json` (the verifier key of the circuit).
+/// Stateful zkapps also take their `initial_state` (`NULL` for stateless zkapps).
+/// Returns the transaction in hex, for the wallet to fund, sign, and broadcast.
+///
+/// # Safety
+///
+/// `vk_json` must point to a nul-terminated string, and `initial_state` too unless `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn zkbitcoin_deploy_transaction(
+    vk_json: *const c_char,
+    initial_state: *const c_char,
+    satoshi_amount: u64,
+) -> *mut c_char {
+    string_result(|| {
+        let vk: plonk::VerifierKey = serde_json::from_str(read_str(vk_json, "vk")?)
+            .context("couldn't parse the verifier key")?;
+        let initial_state = read_opt_str(initial_state, "initial_state")?.map(str::to_string);
+        let tx = unsigned_deploy(&vk.hash(), initial_state.as_ref(), satoshi_amount)?;
+        Ok(encode::serialize_hex(&tx))
+    })
+}
+
+/// Parses the zkapp deployed (or last updated) by a transaction,
+/// and returns its `txid`, `vout`, `locked_value` (in satoshis), `vk_hash`, and `state` (if stateful) in JSON.
+///
+/// # Safety
+///
+/// `zkapp_tx_hex` must point to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn zkbitcoin_parse_zkapp(zkapp_tx_hex: *const c_char) -> *mut c_char {
+    string_result(|| {
+        let zkapp = bindings::parse_zkapp(read_str(zkapp_tx_hex, "zkapp_tx")?)?;
+        Ok(zkapp.to_string())
+    })
+}
+
+//
+// Bob: spending a zkapp
+//
+
+/// Creates the unsigned transaction spending the zkapp deployed (or last updated) by `zkapp_tx_hex`,
+/// paying `recipients_json` (a JSON array of `address:amount` in satoshis, or just `address`).
+/// Stateful zkapps also take the `new_state` computed by the circuit (`NULL` for stateless zkapps),
+/// and the amounts deposited (`amount_in`) and withdrawn (`amount_out`) in satoshis.
+/// The fee of the committee follows `fee_schedule_json`, the `schedule` of a quote from the orchestrator
+/// (its `fee_quote` method), or the fee of the protocol configuration if `NULL`.
+///
+/// Returns the transaction (as `tx`) and the outputs paying the recipients (as `recipients`) in JSON.
+/// The latter have to be passed to [zkbitcoin_package_request] once the transaction is funded.
+///
+/// # Safety
+///
+/// `zkapp_tx_hex` and `recipients_json` must point to nul-terminated strings,
+/// and `new_state` and `fee_schedule_json` too unless `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn zkbitcoin_spend_transaction(
+    zkapp_tx_hex: *const c_char,
+    recipients_json: *const c_char,
+    new_state: *const c_char,
+    amount_in: u64,
+    amount_out: u64,
+    fee_schedule_json: *const c_char,
+) -> *mut c_char {
+    string_result(|| {
+        let recipients: Vec<String> =
+            serde_json::from_str(read_str(recipients_json, "recipients")?)
+                .context("couldn't parse the recipients")?;
+        let spend = bindings::build_spend(
+            read_str(zkapp_tx_hex, "zkapp_tx")?,
+            &recipients,
+            read_opt_str(new_state, "new_state")?,
+            amount_in,
+            amount_out,
+            read_opt_str(fee_schedule_json, "fee_schedule")?,
+        )?;
+        Ok(spend.to_string())
+    })
+}
+
+/// Returns the `truncated_txid` public input of the circuit, for the (funded) transaction spending the zkapp.
+///
+/// # Safety
+///
+/// `tx_hex` must point to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn zkbitcoin_truncated_txid(tx_hex: *const c_char) -> *mut c_char {
+    string_result(|| {
+        let tx = parse_tx(read_str(tx_hex, "tx")?)?;
+        Ok(truncate_txid(tx.txid()))
+    })
+}
+
+/// Packages a proof into a request (see `BobRequest::package`), returned in JSON.
+/// `recipients_json` are the recipient outputs returned by [zkbitcoin_spend_transaction],
+/// and `prev_outs_json` the outputs spent by every input of the funded transaction, in order.
+/// `nonce_hex` is a fresh random nonce of 32 bytes, hex-encoded (see `BobRequest::nonce`),
+/// which committees protecting against replays require (`NULL` for none).
+///
+/// # Safety
+///
+/// All arguments must point to nul-terminated strings, except `nonce_hex` which can be `NULL`.
+#[no_mangle]
+pub unsafe extern "C" fn zkbitcoin_package_request(
+    tx_hex: *const c_char,
+    zkapp_tx_hex: *const c_char,
+    vk_json: *const c_char,
+    proof_json: *const c_char,
+    public_inputs_json: *const c_char,
+    recipients_json: *const c_char,
+    prev_outs_json: *const c_char,
+    nonce_hex: *const c_char,
+) -> *mut c_char {
+    string_result(|| {
+        bindings::package_request(
+            read_str(tx_hex, "tx")?,
+            read_str(zkapp_tx_hex, "zkapp_tx")?,
+            read_str(vk_json, "vk")?,
+            read_str(proof_json, "proof")?,
+            read_str(public_inputs_json, "public_inputs")?,
+            read_str(recipients_json, "recipients")?,
+            read_str(prev_outs_json, "prev_outs")?,
+            read_opt_str(nonce_hex, "nonce")?,
+        )
+    })
+}
+
+/// Returns the JSON RPC body asking the orchestrator to unlock the funds of a request (see [zkbitcoin_package_request]).
+///
+/// # Safety
+///
+/// `request_json` must point to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn zkbitcoin_unlock_funds_payload(
+    request_json: *const c_char,
+) -> *mut c_char {
+    string_result(|| {
+        let payload = bindings::unlock_funds_payload(read_str(request_json, "request")?)?;
+        Ok(payload.to_string())
+    })
+}
+
+/// Parses the orchestrator's answer to a JSON RPC request (see [zkbitcoin_unlock_funds_payload]),
+/// and returns the transaction unlocking the zkapp in hex.
+/// The zkapp's input is signed by the committee, the others still have to be signed by the wallet.
+/// A refusal of the orchestrator is returned as the error, with its message and its data (if any).
+///
+/// # Safety
+///
+/// `response_json` must point to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn zkbitcoin_parse_response(response_json: *const c_char) -> *mut c_char {
+    string_result(|| {
+        let unlocked_tx = bindings::parse_response(read_str(response_json, "response")?)?;
+        Ok(encode::serialize_hex(&unlocked_tx))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calls a function returning a string, and takes ownership of the result.
+    fn call(s: *mut c_char) -> Result<String, String> {
+        let take = |s: *mut c_char| unsafe {
+            let owned = CStr::from_ptr(s).to_str().unwrap().to_string();
+            zkbitcoin_string_free(s);
+            owned
+        };
+        if s.is_null() {
+            Err(take(zkbitcoin_last_error()))
+        } else {
+            Ok(take(s))
+        }
+    }
+
+    #[test]
+    fn test_deploy_transaction() {
+        let vk = CString::new(include_str!("../../examples/circuit/vk.json")).unwrap();
+        let tx_hex =
+            call(unsafe { zkbitcoin_deploy_transaction(vk.as_ptr(), ptr::null(), 1000) }).unwrap();
+
+        let tx_hex = CString::new(tx_hex).unwrap();
+        let zkapp = call(unsafe { zkbitcoin_parse_zkapp(tx_hex.as_ptr()) }).unwrap();
+        let zkapp: serde_json::Value = serde_json::from_str(&zkapp).unwrap();
+        assert_eq!(zkapp["locked_value"], 1000);
+        assert_eq!(zkapp["vout"], 0);
+        assert!(zkapp["state"].is_null());
+
+        // errors are surfaced
+        let err = call(unsafe { zkbitcoin_parse_zkapp(ptr::null()) }).unwrap_err();
+        assert_eq!(err, "zkapp_tx can't be NULL");
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = CString::new(
+            r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"invalid proof","data":"wrong txid"},"id":"zkbitcoin"}"#,
+        )
+        .unwrap();
+        let err = call(unsafe { zkbitcoin_parse_response(response.as_ptr()) }).unwrap_err();
+        assert_eq!(
+            err,
+            "the orchestrator refused the request: invalid proof (wrong txid)"
+        );
+    }
+}
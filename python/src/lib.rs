@@ -0,0 +1,243 @@
+//! Python bindings to zkBitcoin, to drive zkapps from notebooks and test harnesses.
+//!
+//! ```python
+//! import zkbitcoin
+//!
+//! proof, public_inputs, vk = zkbitcoin.prove("examples/circuit/stateless.circom", {"truncated_txid": ["0"]})
+//! zkbitcoin.verify_proof(vk, public_inputs, proof)
+//!
+//! request = zkbitcoin.bob_request(txid, "examples/circuit/stateless.circom", {}, ["tb1q...:1000"])
+//! orchestrator = zkbitcoin.OrchestratorClient("http://127.0.0.1:8888")
+//! print(orchestrator.unlock_funds(request))
+//! ```
+//!
+//! Verifier keys, proofs, requests, and responses are exchanged as Python dicts (as they would be in JSON),
+//! and errors are raised as `zkbitcoin.ZkBitcoinError`.
+
+use std::{collections::HashMap, future::Future, path::PathBuf, str::FromStr, sync::OnceLock};
+
+use anyhow::Context;
+use bitcoin::{
+    hashes::hex::FromHex,
+    secp256k1::{Keypair, Secp256k1},
+    Txid,
+};
+use pyo3::{create_exception, exceptions::PyException, prelude::*};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::runtime::Runtime;
+use zkbitcoin::{
+    bindings,
+    bob_request::BobRequest,
+    chain::BackendKind,
+    client::{self, ZkappPolicy, ZkappRegistration},
+    coin_selection::Funding,
+    json_rpc_stuff::RpcCtx,
+    plonk, proof_system,
+    prover::LocalProver,
+};
+
+create_exception!(zkbitcoin, ZkBitcoinError, PyException);
+
+//
+// Helpers
+//
+
+/// Raises an error (and its context) in Python.
+fn py_err(err: anyhow::Error) -> PyErr {
+    ZkBitcoinError::new_err(format!("{err:#}"))
+}
+
+/// The runtime on which the async functions of the library are run to completion.
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Runtime::new().expect("couldn't start the tokio runtime"))
+}
+
+/// Runs a future to completion, releasing the GIL meanwhile so that other Python threads can run.
+fn block_on<T: Send>(
+    py: Python<'_>,
+    future: impl Future<Output = anyhow::Result<T>> + Send,
+) -> PyResult<T> {
+    py.allow_threads(|| runtime().block_on(future))
+        .map_err(py_err)
+}
+
+/// Converts a value to a Python object, through JSON.
+fn to_py(py: Python<'_>, value: &impl Serialize) -> PyResult<PyObject> {
+    let json = serde_json::to_string(value).map_err(|err| py_err(err.into()))?;
+    let value = py.import("json")?.call_method1("loads", (json,))?;
+    Ok(value.into())
+}
+
+/// Converts a Python object to a value, through JSON.
+fn from_py<T: DeserializeOwned>(py: Python<'_>, value: &PyAny, name: &str) -> PyResult<T> {
+    let json: String = py
+        .import("json")?
+        .call_method1("dumps", (value,))?
+        .extract()?;
+    serde_json::from_str(&json)
+        .map_err(|err| ZkBitcoinError::new_err(format!("couldn't parse {name}: {err}")))
+}
+
+//
+// Proving
+//
+
+/// Compiles a circuit, and proves it on the given inputs (which requires the tools of its proof system, e.g. circom and snarkjs).
+/// Returns the proof, the public inputs, and the verifier key.
+#[pyfunction]
+fn prove(
+    py: Python<'_>,
+    circuit_path: PathBuf,
+    proof_inputs: HashMap<String, Vec<String>>,
+) -> PyResult<(PyObject, Vec<String>, PyObject)> {
+    let (proof, public_inputs, vk) =
+        block_on(py, proof_system::prove(&circuit_path, &proof_inputs))?;
+    Ok((to_py(py, &proof)?, public_inputs.0, to_py(py, &vk)?))
+}
+
+/// Verifies a proof (which requires the tools of its proof system, e.g. snarkjs), and raises if it's invalid.
+#[pyfunction]
+fn verify_proof(
+    py: Python<'_>,
+    vk: &PyAny,
+    public_inputs: Vec<String>,
+    proof: &PyAny,
+) -> PyResult<()> {
+    let vk: plonk::VerifierKey = from_py(py, vk, "the verifier key")?;
+    let proof: plonk::Proof = from_py(py, proof, "the proof")?;
+    py.allow_threads(|| proof_system::verify_proof(&vk, &public_inputs, &proof))
+        .map_err(py_err)
+}
+
+//
+// Bob
+//
+
+/// Creates a request to use the zkapp deployed (or last updated) by `txid`,
+/// paying `recipients` (given as `address:amount` in satoshis, or just `address`).
+/// The transaction is funded by the wallet of the Bitcoin Core node at `rpc_address`,
+/// while transactions are fetched from the `chain` backend (`core`, `esplora`, or `electrum` at `chain_url`).
+/// The request gets a fresh random nonce, unless one is given (32 hex-encoded bytes).
+#[pyfunction]
+#[pyo3(signature = (
+    txid,
+    circuit_path,
+    proof_inputs,
+    recipients,
+    rpc_address = None,
+    rpc_wallet = None,
+    rpc_auth = None,
+    chain = "core",
+    chain_url = None,
+    nonce = None,
+))]
+#[allow(clippy::too_many_arguments)]
+fn bob_request(
+    py: Python<'_>,
+    txid: &str,
+    circuit_path: PathBuf,
+    proof_inputs: HashMap<String, Vec<String>>,
+    recipients: Vec<String>,
+    rpc_address: Option<String>,
+    rpc_wallet: Option<String>,
+    rpc_auth: Option<String>,
+    chain: &str,
+    chain_url: Option<&str>,
+    nonce: Option<&str>,
+) -> PyResult<PyObject> {
+    let txid = Txid::from_str(txid)
+        .map_err(|err| ZkBitcoinError::new_err(format!("invalid txid: {err}")))?;
+    let recipients = bindings::parse_recipients(&recipients).map_err(py_err)?;
+    let rpc_ctx = RpcCtx::new(Some("2.0"), rpc_wallet, rpc_address, rpc_auth);
+    let chain = BackendKind::from_str(chain)
+        .map_err(|err| ZkBitcoinError::new_err(format!("invalid chain backend: {err}")))?
+        .connect(chain_url, &rpc_ctx)
+        .map_err(py_err)?;
+
+    let request = block_on(
+        py,
+        BobRequest::new(
+            &rpc_ctx,
+            chain.as_ref(),
+            &recipients,
+            txid,
+            &LocalProver::new(circuit_path),
+            proof_inputs,
+            &Funding::default(),
+        ),
+    )?;
+    let request = match nonce {
+        Some(nonce) => request.with_nonce(nonce).map_err(py_err)?,
+        None => request,
+    };
+    to_py(py, &request)
+}
+
+//
+// Orchestrator
+//
+
+/// A client to an orchestrator (see `zkbitcoin::client::OrchestratorClient`).
+#[pyclass]
+struct OrchestratorClient(client::OrchestratorClient);
+
+#[pymethods]
+impl OrchestratorClient {
+    #[new]
+    fn new(address: String) -> Self {
+        Self(client::OrchestratorClient::new(address))
+    }
+
+    /// Asks the orchestrator about itself.
+    fn info(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let info = block_on(py, self.0.info())?;
+        to_py(py, &info)
+    }
+
+    /// Has the committee sign the spend of a zkapp, and returns the response of the orchestrator.
+    fn unlock_funds(&self, py: Python<'_>, request: &PyAny) -> PyResult<PyObject> {
+        let request: BobRequest = from_py(py, request, "the request")?;
+        let response = block_on(py, self.0.unlock_funds(&request))?;
+        to_py(py, &response)
+    }
+
+    /// Has the committee sign the spend of several zkapps in the same transaction.
+    fn unlock_funds_batch(&self, py: Python<'_>, requests: &PyAny) -> PyResult<PyObject> {
+        let requests: Vec<BobRequest> = from_py(py, requests, "the requests")?;
+        let response = block_on(py, self.0.unlock_funds_batch(&requests))?;
+        to_py(py, &response)
+    }
+
+    /// Registers the policy of a zkapp (by hex-encoded verifier key hash), signed with the hex-encoded secret key
+    /// of its deployer (whose public key the zkapp was deployed with), and returns its verifier key hash.
+    fn register_zkapp(
+        &self,
+        py: Python<'_>,
+        vk_hash: &str,
+        policy: &PyAny,
+        deployer_key: &str,
+    ) -> PyResult<String> {
+        let policy: ZkappPolicy = from_py(py, policy, "the policy")?;
+        let registration = (|| -> anyhow::Result<ZkappRegistration> {
+            let vk_hash = <[u8; 32]>::from_hex(vk_hash)
+                .context("the vk hash is not a hex-encoded 32-byte value")?;
+            let deployer = Keypair::from_seckey_str(&Secp256k1::new(), deployer_key)
+                .context("the deployer key is not a hex-encoded secret key")?;
+            ZkappRegistration::sign(&vk_hash, policy, &deployer)
+        })()
+        .map_err(py_err)?;
+        block_on(py, self.0.register_zkapp(&registration))
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "zkbitcoin")]
+fn zkbitcoin_py(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("ZkBitcoinError", py.get_type::<ZkBitcoinError>())?;
+    m.add_function(wrap_pyfunction!(prove, m)?)?;
+    m.add_function(wrap_pyfunction!(verify_proof, m)?)?;
+    m.add_function(wrap_pyfunction!(bob_request, m)?)?;
+    m.add_class::<OrchestratorClient>()?;
+    Ok(())
+}
@@ -0,0 +1,55 @@
+//! Aggregated verification of independent proofs.
+//!
+//! Groth16 proofs of the same verifier key are verified at once, by checking a random linear combination of their
+//! verification equations: `n` proofs then cost a single multi-pairing of `n + 3` pairings instead of `n` of 4.
+//! If the aggregate check fails, the proofs are verified one by one to find out which ones are wrong.
+//! Proofs of the other proof systems (or alone with their verifier key) are verified individually.
+//! The aggregation needs the `arkworks` feature: without it, every proof is verified individually.
+
+use anyhow::Result;
+
+use crate::{plonk, proof_system::verify_proof};
+
+/// A proof, with what it is verified against.
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub vk: plonk::VerifierKey,
+    pub public_inputs: Vec<String>,
+    pub proof: plonk::Proof,
+}
+
+impl Statement {
+    /// Whether the proof can be verified with others (only groth16 proofs are, with the `arkworks` feature).
+    pub fn is_aggregatable(vk: &plonk::VerifierKey, proof: &plonk::Proof) -> bool {
+        cfg!(feature = "arkworks")
+            && matches!(
+                (vk, proof),
+                (plonk::VerifierKey::Groth16(_), plonk::Proof::Groth16(_))
+            )
+    }
+}
+
+/// Verifies many independent proofs, aggregating the ones that can be, and returns the result of each (in order).
+pub fn verify_all(statements: &[Statement]) -> Vec<Result<()>> {
+    #[cfg(feature = "arkworks")]
+    let results = verify_groups(statements);
+    #[cfg(not(feature = "arkworks"))]
+    let results = statements.iter().map(|_| None);
+
+    statements
+        .iter()
+        .zip(results)
+        .map(|(statement, result)| {
+            result.unwrap_or_else(|| {
+                verify_proof(&statement.vk, &statement.public_inputs, &statement.proof)
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "arkworks")]
+mod groth16;
+#[cfg(feature = "arkworks")]
+pub use groth16::verify_groth16_batch;
+#[cfg(feature = "arkworks")]
+use groth16::verify_groups;
@@ -0,0 +1,311 @@
+//! Aggregated verification of groth16 proofs (with the `arkworks` feature).
+
+use std::{collections::HashMap, str::FromStr};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use ark_bn254::{Bn254, Fq, Fq2, Fr, G1Affine, G1Projective, G2Affine};
+use ark_ec::{pairing::Pairing, AffineRepr, CurveGroup};
+use ark_ff::{One, Zero};
+use log::debug;
+
+use super::Statement;
+use crate::plonk::{self, Groth16Proof, Groth16VerifierKey};
+
+/// Verifies the groth16 proofs of the same verifier key at once,
+/// and returns the result of each statement (in order) if it was verified.
+pub(super) fn verify_groups(statements: &[Statement]) -> Vec<Option<Result<()>>> {
+    let mut results: Vec<Option<Result<()>>> = statements.iter().map(|_| None).collect();
+
+    // group the groth16 proofs by verifier key
+    let mut groups: HashMap<[u8; 32], Vec<usize>> = HashMap::new();
+    for (index, statement) in statements.iter().enumerate() {
+        if let (plonk::VerifierKey::Groth16(vk), plonk::Proof::Groth16(_)) =
+            (&statement.vk, &statement.proof)
+        {
+            if statement.vk.is_consistent() {
+                groups.entry(statement.vk.hash()).or_default().push(index);
+                continue;
+            }
+            debug!("- not aggregating a proof with a malformed verifier key {vk:?}");
+        }
+    }
+
+    for indices in groups.values().filter(|indices| indices.len() > 1) {
+        let plonk::VerifierKey::Groth16(vk) = &statements[indices[0]].vk else {
+            unreachable!("only groth16 proofs are grouped");
+        };
+        let proofs = indices
+            .iter()
+            .filter_map(|&index| match &statements[index].proof {
+                plonk::Proof::Groth16(proof) => {
+                    Some((statements[index].public_inputs.as_slice(), proof))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        match verify_groth16_batch(vk, &proofs) {
+            Ok(()) => {
+                debug!("- verified {} groth16 proofs at once", indices.len());
+                for &index in indices {
+                    results[index] = Some(Ok(()));
+                }
+            }
+            // one of the proofs is wrong: the ones left to verify are verified one by one
+            Err(err) => debug!("- couldn't verify the groth16 proofs at once: {err:#}"),
+        }
+    }
+
+    results
+}
+
+/// A groth16 verifier key, parsed.
+struct PreparedKey {
+    alpha: G1Affine,
+    beta: G2Affine,
+    gamma: G2Affine,
+    delta: G2Affine,
+    ic: Vec<G1Affine>,
+}
+
+impl PreparedKey {
+    fn new(vk: &Groth16VerifierKey) -> Result<Self> {
+        ensure!(
+            vk.IC.len() == vk.nPublic + 1,
+            "the verifier key must have {} IC points",
+            vk.nPublic + 1
+        );
+        Ok(Self {
+            alpha: g1(&vk.vk_alpha_1)?,
+            beta: g2(&vk.vk_beta_2)?,
+            gamma: g2(&vk.vk_gamma_2)?,
+            delta: g2(&vk.vk_delta_2)?,
+            ic: vk.IC.iter().map(|point| g1(point)).collect::<Result<_>>()?,
+        })
+    }
+
+    /// The linear combination of the IC points with the public inputs.
+    fn vk_x(&self, public_inputs: &[String]) -> Result<G1Projective> {
+        ensure!(
+            public_inputs.len() + 1 == self.ic.len(),
+            "expected {} public inputs, got {}",
+            self.ic.len() - 1,
+            public_inputs.len()
+        );
+        let mut vk_x = self.ic[0].into_group();
+        for (input, point) in public_inputs.iter().zip(&self.ic[1..]) {
+            vk_x += *point * scalar(input)?;
+        }
+        Ok(vk_x)
+    }
+}
+
+/// Verifies groth16 proofs of the same verifier key at once.
+/// Each proof must satisfy `e(A, B) = e(alpha, beta) e(vk_x, gamma) e(C, delta)`,
+/// so with random `r_i`, `prod e(r_i A_i, B_i) = e(sum(r_i) alpha, beta) e(sum(r_i vk_x_i), gamma) e(sum(r_i C_i), delta)`
+/// holds for all of them, and fails (except with negligible probability) if one of them doesn't.
+pub fn verify_groth16_batch(
+    vk: &Groth16VerifierKey,
+    proofs: &[(&[String], &Groth16Proof)],
+) -> Result<()> {
+    ensure!(!proofs.is_empty(), "there are no proofs to verify");
+    let key = PreparedKey::new(vk).context("malformed verifier key")?;
+
+    let mut g1s = vec![];
+    let mut g2s = vec![];
+    let mut sum_r = Fr::zero();
+    let mut sum_vk_x = G1Projective::zero();
+    let mut sum_c = G1Projective::zero();
+    for (public_inputs, proof) in proofs {
+        let a = g1(&proof.pi_a).context("malformed proof")?;
+        let b = g2(&proof.pi_b).context("malformed proof")?;
+        let c = g1(&proof.pi_c).context("malformed proof")?;
+        let vk_x = key.vk_x(public_inputs)?;
+
+        // 128 bits of randomness are enough for a negligible soundness error
+        let r = Fr::from(rand::random::<u128>());
+        g1s.push(-(a * r));
+        g2s.push(b);
+        sum_r += r;
+        sum_vk_x += vk_x * r;
+        sum_c += c * r;
+    }
+    g1s.extend([key.alpha * sum_r, sum_vk_x, sum_c]);
+    g2s.extend([key.beta, key.gamma, key.delta]);
+
+    let g1s = G1Projective::normalize_batch(&g1s);
+    ensure!(
+        Bn254::multi_pairing(g1s, g2s).0.is_one(),
+        "failed to verify proofs"
+    );
+    Ok(())
+}
+
+fn scalar(value: &str) -> Result<Fr> {
+    Fr::from_str(value).map_err(|_| anyhow!("`{value}` is not a field element"))
+}
+
+fn base(value: &str) -> Result<Fq> {
+    Fq::from_str(value).map_err(|_| anyhow!("`{value}` is not a coordinate"))
+}
+
+fn extension(value: &[String]) -> Result<Fq2> {
+    match value {
+        [c0, c1] => Ok(Fq2::new(base(c0)?, base(c1)?)),
+        _ => Err(anyhow!("a G2 coordinate must have 2 elements")),
+    }
+}
+
+/// Parses a point of G1, in the (projective) format of snarkjs: `[x, y, z]` with `z` 1 (or 0 for the point at infinity).
+fn g1(coordinates: &[String]) -> Result<G1Affine> {
+    let [x, y, z] = coordinates else {
+        return Err(anyhow!("a G1 point must have 3 coordinates"));
+    };
+    if z == "0" {
+        return Ok(G1Affine::zero());
+    }
+    ensure!(z == "1", "the G1 point must be normalized");
+    let point = G1Affine::new_unchecked(base(x)?, base(y)?);
+    ensure!(
+        point.is_on_curve() && point.is_in_correct_subgroup_assuming_on_curve(),
+        "not a G1 point"
+    );
+    Ok(point)
+}
+
+/// Parses a point of G2, in the format of snarkjs: `[[x_0, x_1], [y_0, y_1], [z_0, z_1]]` with `z` 1 (or 0).
+fn g2(coordinates: &[Vec<String>]) -> Result<G2Affine> {
+    let [x, y, z] = coordinates else {
+        return Err(anyhow!("a G2 point must have 3 coordinates"));
+    };
+    let z = extension(z)?;
+    if z.is_zero() {
+        return Ok(G2Affine::zero());
+    }
+    ensure!(z.is_one(), "the G2 point must be normalized");
+    let point = G2Affine::new_unchecked(extension(x)?, extension(y)?);
+    ensure!(
+        point.is_on_curve() && point.is_in_correct_subgroup_assuming_on_curve(),
+        "not a G2 point"
+    );
+    Ok(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use ark_ff::Field;
+    use serde_json::json;
+
+    use super::*;
+
+    fn g1_json(point: G1Affine) -> serde_json::Value {
+        json!([point.x.to_string(), point.y.to_string(), "1"])
+    }
+
+    fn g2_json(point: G2Affine) -> serde_json::Value {
+        json!([
+            [point.x.c0.to_string(), point.x.c1.to_string()],
+            [point.y.c0.to_string(), point.y.c1.to_string()],
+            ["1", "0"]
+        ])
+    }
+
+    /// Creates a verifier key with a known trapdoor, to forge valid proofs of any public inputs.
+    struct Trapdoor {
+        alpha: Fr,
+        beta: Fr,
+        gamma: Fr,
+        delta: Fr,
+        ic: Vec<Fr>,
+    }
+
+    impl Trapdoor {
+        fn new(n_public: usize) -> Self {
+            let random = || Fr::from(rand::random::<u128>());
+            Self {
+                alpha: random(),
+                beta: random(),
+                gamma: random(),
+                delta: random(),
+                ic: (0..=n_public).map(|_| random()).collect(),
+            }
+        }
+
+        fn vk(&self) -> Groth16VerifierKey {
+            let g1 = |s: Fr| g1_json((G1Affine::generator() * s).into_affine());
+            let g2 = |s: Fr| g2_json((G2Affine::generator() * s).into_affine());
+            serde_json::from_value(json!({
+                "protocol": "groth16",
+                "curve": "bn128",
+                "nPublic": self.ic.len() - 1,
+                "vk_alpha_1": g1(self.alpha),
+                "vk_beta_2": g2(self.beta),
+                "vk_gamma_2": g2(self.gamma),
+                "vk_delta_2": g2(self.delta),
+                "vk_alphabeta_12": [],
+                "IC": self.ic.iter().map(|&s| g1(s)).collect::<Vec<_>>(),
+            }))
+            .unwrap()
+        }
+
+        /// A proof with A = a G1, B = b G2, and C such that `ab = alpha beta + vk_x gamma + c delta`.
+        fn prove(&self, public_inputs: &[String]) -> Groth16Proof {
+            let vk_x = public_inputs
+                .iter()
+                .zip(&self.ic[1..])
+                .fold(self.ic[0], |acc, (input, ic)| {
+                    acc + scalar(input).unwrap() * ic
+                });
+            let (a, b) = (Fr::from(3u64), Fr::from(5u64));
+            let c = (a * b - self.alpha * self.beta - vk_x * self.gamma)
+                * self.delta.inverse().unwrap();
+            serde_json::from_value(json!({
+                "pi_a": g1_json((G1Affine::generator() * a).into_affine()),
+                "pi_b": g2_json((G2Affine::generator() * b).into_affine()),
+                "pi_c": g1_json((G1Affine::generator() * c).into_affine()),
+                "protocol": "groth16",
+                "curve": "bn128",
+            }))
+            .unwrap()
+        }
+    }
+
+    #[test]
+    fn test_verify_groth16_batch() {
+        let trapdoor = Trapdoor::new(2);
+        let vk = trapdoor.vk();
+        let inputs_1 = vec!["1".to_string(), "2".to_string()];
+        let inputs_2 = vec!["3".to_string(), "4".to_string()];
+        let proof_1 = trapdoor.prove(&inputs_1);
+        let proof_2 = trapdoor.prove(&inputs_2);
+
+        verify_groth16_batch(&vk, &[(&inputs_1, &proof_1)]).unwrap();
+        verify_groth16_batch(&vk, &[(&inputs_1, &proof_1), (&inputs_2, &proof_2)]).unwrap();
+
+        // a proof checked against the wrong public inputs makes the whole batch fail
+        assert!(
+            verify_groth16_batch(&vk, &[(&inputs_1, &proof_1), (&inputs_1, &proof_2)]).is_err()
+        );
+        assert!(verify_groth16_batch(&vk, &[(&inputs_1[..1], &proof_1)]).is_err());
+        assert!(verify_groth16_batch(&vk, &[]).is_err());
+    }
+
+    #[test]
+    fn test_points() {
+        let generator = G1Affine::generator();
+        assert_eq!(
+            g1(&[
+                generator.x.to_string(),
+                generator.y.to_string(),
+                "1".to_string()
+            ])
+            .unwrap(),
+            generator
+        );
+        assert!(g1(&["1".to_string(), "3".to_string(), "1".to_string()]).is_err());
+        assert!(g1(&["1".to_string(), "2".to_string()]).is_err());
+        assert_eq!(
+            g1(&["0".to_string(), "1".to_string(), "0".to_string()]).unwrap(),
+            G1Affine::zero()
+        );
+    }
+}
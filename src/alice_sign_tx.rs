@@ -1,70 +1,96 @@
-use std::str::FromStr;
+use anyhow::{Context, Result};
+use bitcoin::{absolute::LockTime, transaction::Version, Amount, Psbt, Transaction, TxOut};
+use log::debug;
+#[cfg(feature = "node")]
+use log::info;
+
+use crate::{amounts::check_deploy_amount, zkapp_data::ZkappData};
+#[cfg(feature = "node")]
+use crate::{
+    coin_selection::Funding,
+    json_rpc_stuff::{
+        send_raw_transaction, sign_transaction, wallet_process_psbt, RpcCtx, TransactionOrHex,
+    },
+};
 
-use anyhow::Result;
-use bitcoin::{absolute::LockTime, transaction::Version, Amount, PublicKey, Transaction, TxOut};
-use log::{debug, info};
+/// Creates the (unfunded) transaction deploying a zkapp.
+/// Specifically, this sends some given amount in satoshis to 0xzkBitcoin,
+/// and authenticates the verifier key `vk` that can unlock the founds (along with its initial state, if stateful,
+/// and the hash of its metadata, if any: see [ZkappData]).
+/// The wallet of Alice then adds the inputs (and change) paying for it.
+pub fn unsigned_deploy(data: &ZkappData, satoshi_amount: u64) -> Result<Transaction> {
+    check_deploy_amount(satoshi_amount)?;
+
+    let mut outputs = vec![];
+    // first output is a P2PK to 0xzkBitcoin (or to the scripts of a recoverable zkapp, see [crate::recovery])
+    {
+        outputs.push(TxOut {
+            value: Amount::from_sat(satoshi_amount),
+            script_pubkey: data.script_pubkey()?,
+        });
+    }
 
-use crate::constants::ZKBITCOIN_PUBKEY;
-use crate::json_rpc_stuff::{
-    fund_raw_transaction, send_raw_transaction, sign_transaction, RpcCtx, TransactionOrHex,
-};
-use crate::{op_return_script_for, p2tr_script_to};
+    // second output is VK + initial state
+    {
+        let script_pubkey = data.to_script().context("incorrect initial state given")?;
+        let value = script_pubkey.dust_value();
+        outputs.push(TxOut {
+            value,
+            script_pubkey,
+        });
+    }
 
-/// Generates and broadcasts a transaction to the network.
-/// Specifically, this sends a transaction to 0xzkBitcoin, for some given amount in satoshis,
-/// and authenticates the verifier key `vk` that can unlock the founds.
-pub async fn generate_and_broadcast_transaction(
+    // build tx
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO, // no lock time
+        // we don't need to specify inputs at this point, the wallet will fill that for us
+        input: vec![],
+        output: outputs,
+    };
+    debug!(
+        "- Alice's raw tx for 0xzkBitcoin (in hex): {}",
+        bitcoin::consensus::encode::serialize_hex(&tx)
+    );
+
+    Ok(tx)
+}
+
+/// Generates a funded (but unsigned) transaction (see [unsigned_deploy]).
+#[cfg(feature = "node")]
+async fn generate_funded_transaction(
     ctx: &RpcCtx,
-    vk_hash: &[u8; 32],
-    initial_state: Option<&String>,
+    data: &ZkappData,
     satoshi_amount: u64,
-) -> Result<bitcoin::Txid> {
+    funding: &Funding,
+) -> Result<(String, Transaction)> {
     // 1. create transaction based on VK + amount
-    // https://developer.bitcoin.org/reference/rpc/createrawtransaction.html
     //
-    let (_tx, tx_hex) = {
-        let mut outputs = vec![];
-        // first output is a P2PK to 0xzkBitcoin
-        {
-            let zkbitcoin_pubkey: PublicKey = PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap();
-            outputs.push(TxOut {
-                value: Amount::from_sat(satoshi_amount),
-                script_pubkey: p2tr_script_to(zkbitcoin_pubkey),
-            });
-        }
-
-        // second output is VK + initial state
-        {
-            let script_pubkey = op_return_script_for(vk_hash, initial_state)?;
-            let value = script_pubkey.dust_value();
-            outputs.push(TxOut {
-                value,
-                script_pubkey,
-            });
-        }
-
-        // build tx
-        let tx = Transaction {
-            version: Version::TWO,
-            lock_time: LockTime::ZERO, // no lock time
-            // we don't need to specify inputs at this point, the wallet will fill that for us
-            input: vec![],
-            output: outputs,
-        };
-        let tx_hex = bitcoin::consensus::encode::serialize_hex(&tx);
-
-        debug!("- Alice's raw tx for 0xzkBitcoin (in hex): {tx_hex}");
-
-        (tx, tx_hex)
-    };
+    let tx = unsigned_deploy(data, satoshi_amount)?;
 
-    // 2. ask wallet to add inputs to fund the transaction
+    // 2. ask wallet to add inputs to fund the transaction (unless we picked them ourselves)
     // https://developer.bitcoin.org/reference/rpc/fundrawtransaction.html
     //
-    let (raw_tx_with_inputs_hex, _raw_tx_with_inputs, fee) =
-        fund_raw_transaction(ctx, TransactionOrHex::Hex(tx_hex)).await?;
+    let (raw_tx_with_inputs_hex, raw_tx_with_inputs, fee) =
+        funding.fund(ctx, tx, Amount::ZERO).await?;
     info!("- funded transaction with fee: {fee}");
 
+    Ok((raw_tx_with_inputs_hex, raw_tx_with_inputs))
+}
+
+/// Generates and broadcasts a transaction to the network.
+/// Specifically, this sends a transaction to 0xzkBitcoin, for some given amount in satoshis,
+/// and authenticates the verifier key `vk` that can unlock the founds.
+#[cfg(feature = "node")]
+pub async fn generate_and_broadcast_transaction(
+    ctx: &RpcCtx,
+    data: &ZkappData,
+    satoshi_amount: u64,
+    funding: &Funding,
+) -> Result<bitcoin::Txid> {
+    let (raw_tx_with_inputs_hex, _raw_tx_with_inputs) =
+        generate_funded_transaction(ctx, data, satoshi_amount, funding).await?;
+
     // 3. sign transaction
     // https://developer.bitcoin.org/reference/rpc/signrawtransactionwithwallet.html
     //
@@ -80,7 +106,25 @@ pub async fn generate_and_broadcast_transaction(
     Ok(txid)
 }
 
-#[cfg(test)]
+/// Generates the same transaction as [generate_and_broadcast_transaction],
+/// but instead of signing and broadcasting it,
+/// returns it as a (base64-encoded) PSBT that can be signed by an external signer.
+#[cfg(feature = "node")]
+pub async fn generate_psbt(
+    ctx: &RpcCtx,
+    data: &ZkappData,
+    satoshi_amount: u64,
+    funding: &Funding,
+) -> Result<String> {
+    let (_raw_tx_with_inputs_hex, raw_tx_with_inputs) =
+        generate_funded_transaction(ctx, data, satoshi_amount, funding).await?;
+
+    // let the wallet fill in the information about the inputs it funded
+    let psbt = Psbt::from_unsigned_tx(raw_tx_with_inputs)?;
+    wallet_process_psbt(ctx, &psbt).await
+}
+
+#[cfg(all(test, feature = "node"))]
 mod tests {
     use bitcoincore_rpc::RpcApi;
     use itertools::Itertools;
@@ -145,9 +189,14 @@ mod tests {
 
         let ctx = RpcCtx::for_testing();
 
-        let response = generate_and_broadcast_transaction(&ctx, &vk, None, satoshi_amount)
-            .await
-            .unwrap();
+        let response = generate_and_broadcast_transaction(
+            &ctx,
+            &ZkappData::new(vk, None),
+            satoshi_amount,
+            &Funding::default(),
+        )
+        .await
+        .unwrap();
 
         println!("{:?}", response);
     }
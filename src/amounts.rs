@@ -0,0 +1,166 @@
+//! Amounts as users write them, and sanity checks of the amounts moved by zkBitcoin transactions
+//! (made before the transactions are).
+//!
+//! Amounts can be given in bitcoins (`0.001btc`) or in satoshis (`100000sat`, or just `100000`), see [HumanAmount].
+//! Outputs below the dust limit of their script aren't relayed by the network,
+//! so they are refused upfront with a precise error rather than when the transaction is broadcast.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{Amount, Denomination, PublicKey, TxOut};
+
+use crate::{config::protocol_config, p2tr_script_to};
+
+/// The number of satoshis in a bitcoin.
+const SATS_PER_BTC: u64 = 100_000_000;
+
+/// The smallest amount formatted in bitcoins (smaller ones are formatted in satoshis).
+const MIN_DISPLAYED_IN_BTC: u64 = 100_000;
+
+//
+// Parsing
+//
+
+/// An amount as written by users: in bitcoins (e.g. `0.001btc`), or in satoshis (e.g. `100000sat`, or just `100000`).
+/// Units are case-insensitive, and can be separated from the number by a space.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HumanAmount(pub Amount);
+
+impl FromStr for HumanAmount {
+    type Err = anyhow::Error;
+
+    /// Parses an amount, which can't be more precise than a satoshi, nor more than all the bitcoins there will ever be.
+    fn from_str(s: &str) -> Result<Self> {
+        let lowercase = s.trim().to_ascii_lowercase();
+        let (number, denomination) = if let Some(number) = lowercase.strip_suffix("btc") {
+            (number, Denomination::Bitcoin)
+        } else if let Some(number) = lowercase
+            .strip_suffix("sats")
+            .or_else(|| lowercase.strip_suffix("sat"))
+        {
+            (number, Denomination::Satoshi)
+        } else {
+            (lowercase.as_str(), Denomination::Satoshi)
+        };
+        let amount = Amount::from_str_in(number.trim(), denomination)
+            .with_context(|| format!("{s} is not an amount (e.g. 0.001btc or 100000sat)"))?;
+        check_money_range("the amount", amount)?;
+        Ok(Self(amount))
+    }
+}
+
+impl fmt::Display for HumanAmount {
+    /// Formats an amount the way [HumanAmount::from_str] parses it:
+    /// in satoshis below 0.001 BTC (e.g. `5000sat`), and in bitcoins otherwise (e.g. `0.0015btc`).
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sats = self.0.to_sat();
+        if sats < MIN_DISPLAYED_IN_BTC {
+            return write!(f, "{sats}sat");
+        }
+        let btc = format!("{}.{:08}", sats / SATS_PER_BTC, sats % SATS_PER_BTC);
+        write!(f, "{}btc", btc.trim_end_matches('0').trim_end_matches('.'))
+    }
+}
+
+impl From<HumanAmount> for Amount {
+    fn from(amount: HumanAmount) -> Self {
+        amount.0
+    }
+}
+
+//
+// Checks
+//
+
+/// Ensures that an amount is no more than all the bitcoins there will ever be.
+pub fn check_money_range(what: &str, amount: Amount) -> Result<()> {
+    ensure!(
+        amount <= Amount::MAX_MONEY,
+        "{what} of {} sats is more than all the bitcoins there will ever be",
+        amount.to_sat()
+    );
+    Ok(())
+}
+
+/// Ensures that an output is relayed by the network, i.e. that it holds no less than the dust limit of its script
+/// (which is zero for `OP_RETURN` outputs).
+pub fn check_not_dust(what: &str, output: &TxOut) -> Result<()> {
+    check_money_range(what, output.value)?;
+    let dust = output.script_pubkey.dust_value();
+    ensure!(
+        output.value >= dust,
+        "{what} of {} sats is below the dust limit ({} sats)",
+        output.value.to_sat(),
+        dust.to_sat()
+    );
+    Ok(())
+}
+
+/// Ensures that the amount locked in a zkapp being deployed can be spent later on.
+pub fn check_deploy_amount(satoshi_amount: u64) -> Result<()> {
+    let zkbitcoin_pubkey = PublicKey::from_str(&protocol_config().zkbitcoin_pubkey)
+        .context("the zkBitcoin public key is not a valid public key")?;
+    check_not_dust(
+        "the zkapp output",
+        &TxOut {
+            value: Amount::from_sat(satoshi_amount),
+            script_pubkey: p2tr_script_to(zkbitcoin_pubkey),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zkapp_data::ZkappData;
+
+    #[test]
+    fn test_human_amount() {
+        let parse = |s: &str| HumanAmount::from_str(s).map(|amount| amount.0.to_sat());
+        assert_eq!(parse("0.001btc").unwrap(), 100_000);
+        assert_eq!(parse("0.001 BTC").unwrap(), 100_000);
+        assert_eq!(parse("100000sat").unwrap(), 100_000);
+        assert_eq!(parse("100000 sats").unwrap(), 100_000);
+        assert_eq!(parse("100000").unwrap(), 100_000);
+        assert_eq!(parse("21000000btc").unwrap(), Amount::MAX_MONEY.to_sat());
+
+        // no fractions of satoshis, negative amounts, or more than there will ever be
+        assert!(parse("0.000000001btc").is_err());
+        assert!(parse("1.5sat").is_err());
+        assert!(parse("-1sat").is_err());
+        assert!(parse("21000001btc").is_err());
+        assert!(parse("btc").is_err());
+        assert!(parse("1 eth").is_err());
+
+        // amounts are formatted the way they're parsed
+        for (sats, s) in [
+            (5_000, "5000sat"),
+            (150_000, "0.0015btc"),
+            (200_000_000, "2btc"),
+        ] {
+            let amount = HumanAmount(Amount::from_sat(sats));
+            assert_eq!(amount.to_string(), s);
+            assert_eq!(HumanAmount::from_str(s).unwrap(), amount);
+        }
+    }
+
+    #[test]
+    fn test_dust() {
+        // P2TR outputs are dust below 330 sats
+        assert!(check_deploy_amount(330).is_ok());
+        let err = check_deploy_amount(329).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the zkapp output of 329 sats is below the dust limit (330 sats)"
+        );
+        assert!(check_deploy_amount(Amount::MAX_MONEY.to_sat() + 1).is_err());
+
+        // OP_RETURN outputs can hold nothing
+        let op_return = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: ZkappData::new([0; 32], None).to_script().unwrap(),
+        };
+        assert!(check_not_dust("the state output", &op_return).is_ok());
+    }
+}
@@ -0,0 +1,169 @@
+//! A content-addressed cache of compiled circuits, under `~/.zkbitcoin/artifacts`.
+//!
+//! Compiling a circuit and setting up its plonk keys takes minutes, but is deterministic:
+//! the same sources, compiled by the same circom and set up by the same snarkjs with the same powers of tau,
+//! always give the same keys. So the artifacts of a compilation are kept under a hash of all of these,
+//! and reused by every later deployment or use of the same zkapp.
+//! Set `ZKBITCOIN_NO_ARTIFACT_CACHE` to always compile from scratch.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use bitcoin::hashes::{sha256, Hash};
+
+use crate::{doctor::tool_version, ptau::CEREMONY, zkbitcoin_folder};
+
+/// The environment variable disabling the cache.
+pub const NO_CACHE_ENV: &str = "ZKBITCOIN_NO_ARTIFACT_CACHE";
+
+/// Returns true unless the cache was disabled.
+pub fn enabled() -> bool {
+    std::env::var_os(NO_CACHE_ENV).is_none()
+}
+
+/// Returns the directory of the cache (`~/.zkbitcoin/artifacts`).
+pub fn cache_dir() -> PathBuf {
+    zkbitcoin_folder().join("artifacts")
+}
+
+/// The key of the artifacts of a circuit: a hash of its sources (and of the files they include),
+/// of the versions of circom and snarkjs, and of the powers of tau ceremony.
+pub fn key(circom_circuit_path: &Path) -> Result<String> {
+    let circom = tool_version("circom", &["--version"])?;
+    let snarkjs = tool_version("snarkjs", &["--version"])?;
+    let mut preimage =
+        format!("circom:{circom}\nsnarkjs:{snarkjs}\nptau:{CEREMONY}\n").into_bytes();
+
+    // the artifacts are named after the circuit
+    let name = circom_circuit_path
+        .file_stem()
+        .context("failed to get circuit name from filename")?;
+    preimage.extend_from_slice(name.to_string_lossy().as_bytes());
+
+    hash_sources(circom_circuit_path, &mut HashSet::new(), &mut preimage)?;
+    Ok(hex::encode(sha256::Hash::hash(&preimage).to_byte_array()))
+}
+
+/// Appends a source file, and the files it includes (recursively), to `preimage`.
+fn hash_sources(path: &Path, visited: &mut HashSet<PathBuf>, preimage: &mut Vec<u8>) -> Result<()> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("couldn't find {}", path.display()))?;
+    if !visited.insert(path.clone()) {
+        return Ok(());
+    }
+    let source = std::fs::read_to_string(&path)
+        .with_context(|| format!("couldn't read {}", path.display()))?;
+    preimage.extend_from_slice(format!("\n{}:", source.len()).as_bytes());
+    preimage.extend_from_slice(source.as_bytes());
+
+    let dir = path
+        .parent()
+        .context("a source file has no parent directory")?;
+    for include in includes(&source) {
+        hash_sources(&dir.join(include), visited, preimage)?;
+    }
+    Ok(())
+}
+
+/// Returns the paths of the files included by a circom source file.
+fn includes(source: &str) -> Vec<&str> {
+    source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("include"))
+        .filter_map(|rest| rest.split('"').nth(1))
+        .collect()
+}
+
+/// Returns the directory holding the artifacts stored under `key`, if any.
+pub fn lookup(key: &str) -> Option<PathBuf> {
+    let dir = cache_dir().join(key);
+    dir.is_dir().then_some(dir)
+}
+
+/// Stores the artifacts of a compilation (everything in `compiled_dir`) under `key`,
+/// and returns the directory they were stored in.
+pub fn store(key: &str, compiled_dir: &Path) -> Result<PathBuf> {
+    let dir = cache_dir().join(key);
+
+    // copy to a staging directory first, so that only complete artifacts are ever looked up
+    let staging = cache_dir().join(format!("{key}.{}.tmp", std::process::id()));
+    copy_dir(compiled_dir, &staging)?;
+    if let Err(err) = std::fs::rename(&staging, &dir) {
+        let _ = std::fs::remove_dir_all(&staging);
+        // another process might have stored the same artifacts in the meantime
+        if !dir.is_dir() {
+            return Err(err).with_context(|| format!("couldn't create {}", dir.display()));
+        }
+    }
+    Ok(dir)
+}
+
+pub(crate) fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to).with_context(|| format!("couldn't create {}", to.display()))?;
+    for entry in
+        std::fs::read_dir(from).with_context(|| format!("couldn't read {}", from.display()))?
+    {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)
+                .with_context(|| format!("couldn't copy {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_sources() {
+        let tmp_dir = tempdir::TempDir::new("zkbitcoin_artifacts").unwrap();
+        let lib_dir = tmp_dir.path().join("lib");
+        std::fs::create_dir(&lib_dir).unwrap();
+        let circuit = tmp_dir.path().join("circuit.circom");
+        std::fs::write(
+            &circuit,
+            "include \"./lib/a.circom\";\ninclude \"lib/b.circom\";\n",
+        )
+        .unwrap();
+        std::fs::write(lib_dir.join("a.circom"), "include \"./b.circom\";").unwrap();
+        std::fs::write(lib_dir.join("b.circom"), "template B() {}").unwrap();
+
+        let hash = |circuit: &Path| {
+            let mut preimage = vec![];
+            hash_sources(circuit, &mut HashSet::new(), &mut preimage).unwrap();
+            preimage
+        };
+        let before = hash(&circuit);
+
+        // a change in an included file changes the key
+        std::fs::write(lib_dir.join("b.circom"), "template B() { signal input x; }").unwrap();
+        assert_ne!(hash(&circuit), before);
+
+        // missing includes can't be hashed
+        std::fs::remove_file(lib_dir.join("b.circom")).unwrap();
+        assert!(hash_sources(&circuit, &mut HashSet::new(), &mut vec![]).is_err());
+    }
+
+    #[test]
+    fn test_copy_dir() {
+        let tmp_dir = tempdir::TempDir::new("zkbitcoin_artifacts").unwrap();
+        let from = tmp_dir.path().join("from");
+        std::fs::create_dir_all(from.join("circuit_js")).unwrap();
+        std::fs::write(from.join("circuit.r1cs"), "r1cs").unwrap();
+        std::fs::write(from.join("circuit_js").join("circuit.wasm"), "wasm").unwrap();
+
+        let to = tmp_dir.path().join("to");
+        copy_dir(&from, &to).unwrap();
+        let wasm = std::fs::read_to_string(to.join("circuit_js").join("circuit.wasm")).unwrap();
+        assert_eq!(wasm, "wasm");
+    }
+}
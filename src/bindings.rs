@@ -0,0 +1,208 @@
+//! What the bindings have in common: the C bindings (see `ffi/`), the Python bindings (see `python/`),
+//! and the WebAssembly bindings (see the `wasm` feature) only convert their arguments and errors,
+//! and call the functions of this module to do the rest.
+//!
+//! Transactions are passed around hex-encoded, and everything else in JSON
+//! (verifier keys, proofs, and public inputs as produced by snarkjs).
+
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use bitcoin::{consensus::encode, Amount, Network, Transaction, TxOut};
+
+use crate::{
+    bob_request::{
+        extract_smart_contract_from_tx, unsigned_spend, BobRequest, BobResponse, Recipient,
+    },
+    config::FeeSchedule,
+    plonk::{self, PublicInputs},
+};
+
+//
+// Parsing
+//
+
+/// Parses a network (`mainnet`, `testnet`, `signet`, or `regtest`).
+pub fn parse_network(network: &str) -> Result<Network> {
+    match network {
+        "mainnet" => Ok(Network::Bitcoin),
+        network => Network::from_str(network).with_context(|| format!("unknown network {network}")),
+    }
+}
+
+/// Parses a hex-encoded transaction.
+pub fn parse_tx(tx_hex: &str) -> Result<Transaction> {
+    let bytes = hex::decode(tx_hex.trim()).context("the transaction is not hex-encoded")?;
+    encode::deserialize(&bytes).context("couldn't parse the transaction")
+}
+
+/// Parses recipients given as `address:amount` (see [Recipient]), or just `address`.
+pub fn parse_recipients(recipients: &[String]) -> Result<Vec<Recipient>> {
+    recipients
+        .iter()
+        .map(|recipient| Recipient::from_str(recipient))
+        .collect()
+}
+
+//
+// Zkapps
+//
+
+/// Parses the zkapp deployed (or last updated) by a transaction,
+/// and returns its `txid`, `vout`, `locked_value` (in satoshis), `vk_hash`, and `state` (if stateful).
+pub fn parse_zkapp(zkapp_tx_hex: &str) -> Result<serde_json::Value> {
+    let zkapp_tx = parse_tx(zkapp_tx_hex)?;
+    let smart_contract = extract_smart_contract_from_tx(&zkapp_tx)?;
+    Ok(serde_json::json!({
+        "txid": smart_contract.txid,
+        "vout": smart_contract.vout_of_zkbitcoin_utxo,
+        "locked_value": smart_contract.locked_value.to_sat(),
+        "vk_hash": hex::encode(smart_contract.vk_hash),
+        "state": smart_contract.state,
+    }))
+}
+
+/// Creates the unsigned transaction spending the zkapp deployed (or last updated) by `zkapp_tx_hex`
+/// (see [unsigned_spend]), with the fee of the committee following `fee_schedule_json` if given,
+/// and returns the transaction (as `tx`) and the outputs paying the recipients (as `recipients`).
+pub fn build_spend(
+    zkapp_tx_hex: &str,
+    recipients: &[String],
+    new_state: Option<&str>,
+    amount_in: u64,
+    amount_out: u64,
+    fee_schedule_json: Option<&str>,
+) -> Result<serde_json::Value> {
+    let zkapp_tx = parse_tx(zkapp_tx_hex)?;
+    let fee_schedule: FeeSchedule = match fee_schedule_json {
+        Some(json) => serde_json::from_str(json).context("couldn't parse the fee schedule")?,
+        None => FeeSchedule::default(),
+    };
+    let smart_contract = extract_smart_contract_from_tx(&zkapp_tx)?;
+    let (tx, recipient_outputs) = unsigned_spend(
+        &smart_contract,
+        &parse_recipients(recipients)?,
+        new_state,
+        Amount::from_sat(amount_in),
+        Amount::from_sat(amount_out),
+        &fee_schedule,
+    )?;
+    Ok(serde_json::json!({
+        "tx": encode::serialize_hex(&tx),
+        "recipients": recipient_outputs,
+    }))
+}
+
+//
+// Requests
+//
+
+/// Packages a proof into a request (see [BobRequest::package]), returned in JSON.
+#[allow(clippy::too_many_arguments)]
+pub fn package_request(
+    tx_hex: &str,
+    zkapp_tx_hex: &str,
+    vk_json: &str,
+    proof_json: &str,
+    public_inputs_json: &str,
+    recipients_json: &str,
+    prev_outs_json: &str,
+    nonce_hex: Option<&str>,
+) -> Result<String> {
+    let tx = parse_tx(tx_hex)?;
+    let zkapp_tx = parse_tx(zkapp_tx_hex)?;
+    let vk: plonk::VerifierKey =
+        serde_json::from_str(vk_json).context("couldn't parse the verifier key")?;
+    let proof: plonk::Proof =
+        serde_json::from_str(proof_json).context("couldn't parse the proof")?;
+    let public_inputs: PublicInputs =
+        serde_json::from_str(public_inputs_json).context("couldn't parse the public inputs")?;
+    let recipients: Vec<TxOut> =
+        serde_json::from_str(recipients_json).context("couldn't parse the recipients")?;
+    let prev_outs: Vec<TxOut> = serde_json::from_str(prev_outs_json)
+        .context("couldn't parse the outputs spent by the transaction")?;
+
+    let bob_request = BobRequest::package(
+        tx,
+        zkapp_tx,
+        vk,
+        proof,
+        &public_inputs,
+        recipients,
+        prev_outs,
+        nonce_hex,
+    )?;
+    Ok(serde_json::to_string(&bob_request)?)
+}
+
+/// Returns the JSON RPC body asking the orchestrator to unlock the funds of a request (given in JSON).
+pub fn unlock_funds_payload(request_json: &str) -> Result<serde_json::Value> {
+    let bob_request: BobRequest =
+        serde_json::from_str(request_json).context("couldn't parse the request")?;
+    Ok(serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": "zkbitcoin",
+        "method": "unlock_funds",
+        "params": [bob_request],
+    }))
+}
+
+/// Parses the orchestrator's answer to a JSON RPC request (see [unlock_funds_payload]),
+/// and returns the transaction unlocking the zkapp.
+/// An error of the orchestrator is returned with its message, and its data if any (e.g. the reason of a rejection).
+pub fn parse_response(response_json: &str) -> Result<Transaction> {
+    let response: serde_json::Value =
+        serde_json::from_str(response_json).context("couldn't parse the response")?;
+    if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+        let message = match error.get("message").and_then(|message| message.as_str()) {
+            Some(message) => message.to_string(),
+            None => error.to_string(),
+        };
+        match error.get("data").filter(|data| !data.is_null()) {
+            Some(serde_json::Value::String(data)) => {
+                bail!("the orchestrator refused the request: {message} ({data})")
+            }
+            Some(data) => bail!("the orchestrator refused the request: {message} ({data})"),
+            None => bail!("the orchestrator refused the request: {message}"),
+        }
+    }
+    let result = response
+        .get("result")
+        .context("the response contains no result")?;
+    let bob_response: BobResponse = serde_json::from_value(result.clone())
+        .context("couldn't parse the orchestrator's response")?;
+    Ok(bob_response.unlocked_tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_network() {
+        assert_eq!(parse_network("mainnet").unwrap(), Network::Bitcoin);
+        assert_eq!(parse_network("regtest").unwrap(), Network::Regtest);
+        assert!(parse_network("moonnet").is_err());
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let refused = |error: &str| {
+            let response = format!(r#"{{"jsonrpc":"2.0","error":{error},"id":"zkbitcoin"}}"#);
+            format!("{:#}", parse_response(&response).unwrap_err())
+        };
+        assert_eq!(
+            refused(r#"{"code":-32000,"message":"invalid proof"}"#),
+            "the orchestrator refused the request: invalid proof"
+        );
+        assert_eq!(
+            refused(r#"{"code":-32000,"message":"invalid proof","data":"wrong txid"}"#),
+            "the orchestrator refused the request: invalid proof (wrong txid)"
+        );
+        assert_eq!(
+            refused(r#"{"code":-32000,"message":"held","data":{"retry_after":60}}"#),
+            r#"the orchestrator refused the request: held ({"retry_after":60})"#
+        );
+        assert!(parse_response(r#"{"jsonrpc":"2.0","id":"zkbitcoin"}"#).is_err());
+    }
+}
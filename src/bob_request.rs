@@ -1,37 +1,50 @@
-use std::{collections::HashMap, path::Path, str::FromStr, vec};
+#[cfg(feature = "node")]
+use std::collections::HashMap;
+use std::{str::FromStr, vec};
 
-use anyhow::{bail, ensure, Context, Result};
+use anyhow::{ensure, Context, Result};
 use bitcoin::{
-    opcodes::all::OP_RETURN, script::Instruction, Address, Amount, Denomination, OutPoint,
-    PublicKey, Transaction, TxOut, Txid,
+    absolute::LockTime, transaction::Version, Address, Amount, Denomination, OutPoint, Psbt,
+    ScriptBuf, Sequence, TapSighashType, Transaction, TxIn, TxOut, Txid, Witness,
 };
-use log::{debug, info};
+use itertools::Itertools;
+use log::debug;
+#[cfg(feature = "node")]
+use log::info;
+#[cfg(feature = "node")]
 use num_bigint::BigUint;
 use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
 
 use crate::{
-    circom_field_from_bytes, circom_field_to_bytes,
+    amounts::{check_money_range, check_not_dust, HumanAmount},
+    config::{protocol_config, FeeSchedule},
     constants::{
-        FEE_ZKBITCOIN_SAT, MINIMUM_CONFIRMATIONS, STATEFUL_ZKAPP_PUBLIC_INPUT_LEN,
-        ZKBITCOIN_FEE_PUBKEY, ZKBITCOIN_PUBKEY,
+        ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN, NONCE_LEN, NO_UPGRADE,
+        STATEFUL_ZKAPP_PUBLIC_INPUT_LEN, UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN,
     },
-    json_rpc_stuff::{
-        createrawtransaction, fund_raw_transaction, get_transaction, json_rpc_request,
-        TransactionOrHex,
-    },
-    p2tr_script_to,
-    plonk::PublicInputs,
-    snarkjs::{self, verify_proof},
-    taproot_addr_from, truncate_txid,
+    get_network,
+    plonk::{self, PublicInputs},
+    recovery::Recovery,
+    taproot_addr_from,
+    timelock::Timelock,
+    truncate_txid,
+    zkapp_data::{ZkappData, METADATA_HASH_LEN},
+};
+#[cfg(feature = "node")]
+use crate::{
+    chain::ChainBackend, client::OrchestratorClient, coin_selection::Funding,
+    constants::MINIMUM_CONFIRMATIONS, json_rpc_stuff::RpcCtx, lint, proof_system::verify_proof,
+    prover::Prover, recovery::zkapp_script_pubkey, truncate_vk_hash,
 };
-use crate::{json_rpc_stuff::RpcCtx, plonk};
 
 //
 // Helpers
 //
 
-/// Converts a string to some Bitcoin [Amount].
-fn string_to_amount(amount: &str) -> Result<Amount> {
+/// Converts a string (in satoshis, as given to circuits) to some Bitcoin [Amount].
+#[cfg(feature = "node")]
+pub(crate) fn string_to_amount(amount: &str) -> Result<Amount> {
     // TODO: need to write a test here, once we have tested this we need to figure out which one to keep :D
     let big = BigUint::from_str(amount).context("amount is not a u64 (err_code: 1)")?;
     let big_u64s = big.to_u64_digits();
@@ -47,10 +60,343 @@ fn string_to_amount(amount: &str) -> Result<Amount> {
     Ok(res)
 }
 
+/// Returns a fresh nonce for a request (see [BobRequest::nonce]).
+#[cfg(feature = "node")]
+pub fn new_nonce() -> String {
+    hex::encode(rand::random::<[u8; NONCE_LEN]>())
+}
+
+/// Checks that a nonce is made of [NONCE_LEN] hex-encoded bytes.
+fn check_nonce(nonce: &str) -> Result<()> {
+    let bytes = hex::decode(nonce).context("the nonce is not hex-encoded")?;
+    ensure!(
+        bytes.len() == NONCE_LEN,
+        "the nonce must be {NONCE_LEN} bytes, not {}",
+        bytes.len()
+    );
+    // a nonce has a single encoding, so that it can't be reused in another case
+    ensure!(hex::encode(bytes) == nonce, "the nonce must be lowercase");
+    Ok(())
+}
+
+/// Splits `total` between the given recipients.
+/// The recipient without an explicit amount (if any) receives what remains.
+/// There can only be no recipient when nothing is withdrawn.
+fn recipient_outputs(recipients: &[Recipient], total: Amount) -> Result<Vec<TxOut>> {
+    if recipients.is_empty() {
+        ensure!(total == Amount::ZERO, "at least one recipient is needed");
+        return Ok(vec![]);
+    }
+
+    // no address can be used twice (bitcoind refuses duplicate outputs)
+    let num_unique = recipients.iter().map(|r| &r.address).unique().count();
+    ensure!(
+        num_unique == recipients.len(),
+        "the same recipient address was given more than once"
+    );
+
+    // at most one recipient can receive the remainder
+    let num_remainders = recipients.iter().filter(|r| r.amount.is_none()).count();
+    ensure!(
+        num_remainders <= 1,
+        "only one recipient can be given without an amount"
+    );
+
+    let assigned = recipients
+        .iter()
+        .filter_map(|r| r.amount)
+        .try_fold(Amount::ZERO, |acc, amount| acc.checked_add(amount))
+        .context("the recipient amounts overflow")?;
+    ensure!(
+        assigned <= total,
+        "the recipients are assigned {assigned}, but only {total} is being withdrawn"
+    );
+    let remainder = total - assigned;
+    ensure!(
+        num_remainders == 1 || remainder == Amount::ZERO,
+        "the recipients are assigned {assigned}, but {total} is being withdrawn"
+    );
+
+    // every recipient (including the one receiving the remainder) must get more than dust
+    let outputs: Vec<_> = recipients
+        .iter()
+        .map(|r| TxOut {
+            value: r.amount.unwrap_or(remainder),
+            script_pubkey: r.address.script_pubkey(),
+        })
+        .collect();
+    for (recipient, output) in recipients.iter().zip(&outputs) {
+        check_not_dust(&format!("the output to {}", recipient.address), output)?;
+    }
+    Ok(outputs)
+}
+
+/// Creates the (unfunded) transaction spending a zkapp, and returns it with the outputs paying the recipients.
+/// Its first output pays the zkBitcoin fee (following the committee's `fee_schedule`, and left out if the fee is zero,
+/// e.g. when paid over Lightning). For stateful zkapps, it is followed by the updated zkapp
+/// (holding `amount_in` more and `amount_out` less) and its `new_state`, and only `amount_out` goes to the recipients.
+/// The wallet of Bob then adds the inputs (and change) paying for the fee and `amount_in`.
+pub fn unsigned_spend(
+    smart_contract: &SmartContract,
+    recipients: &[Recipient],
+    new_state: Option<&str>,
+    amount_in: Amount,
+    amount_out: Amount,
+    fee_schedule: &FeeSchedule,
+) -> Result<(Transaction, Vec<TxOut>)> {
+    ensure!(
+        new_state.is_some() == smart_contract.is_stateful(),
+        "a new state must be given for stateful zkapps, and only for them"
+    );
+
+    // first output is to zkBitcoinFund (its value depends on the amount withdrawn, see below)
+    let fee_address = taproot_addr_from(&protocol_config().zkbitcoin_fee_pubkey)?;
+    let mut outputs = vec![TxOut {
+        value: Amount::ZERO,
+        script_pubkey: fee_address.script_pubkey(),
+    }];
+
+    let amount_withdrawn = match new_state {
+        // move all the funds to the recipients
+        None => smart_contract.locked_value,
+        Some(new_state) => {
+            check_money_range("the amount deposited", amount_in)?;
+            let new_value = smart_contract
+                .locked_value
+                .checked_add(amount_in)
+                .and_then(|value| value.checked_sub(amount_out))
+                .with_context(|| {
+                    format!(
+                        "withdrawing {} sats, but the zkapp only holds {} sats (and {} sats are deposited)",
+                        amount_out.to_sat(),
+                        smart_contract.locked_value.to_sat(),
+                        amount_in.to_sat()
+                    )
+                })?;
+            debug!(
+                "- stateful: Bob is attempting to deposit {amount_in}, and withdraw {amount_out}, from the zkapp's {}",
+                smart_contract.locked_value
+            );
+
+            // the updated zkapp, and its vk + new state
+            // (the metadata, the administrator, the timelock, the recovery, and the deployer of the zkapp are kept along with it)
+            let new_zkapp = ZkappData {
+                metadata_hash: smart_contract.metadata_hash,
+                admin: smart_contract.admin.clone(),
+                timelock: smart_contract.timelock,
+                recovery: smart_contract.recovery,
+                deployer: smart_contract.deployer,
+                ..ZkappData::new(smart_contract.vk_hash, Some(new_state.to_string()))
+            };
+            let zkapp_output = TxOut {
+                value: new_value,
+                script_pubkey: new_zkapp.script_pubkey()?,
+            };
+            debug!(
+                "- stateful: second output is to the zkapp: {} for {new_value}",
+                zkapp_output.script_pubkey
+            );
+            check_not_dust("the updated zkapp output", &zkapp_output)?;
+            outputs.push(zkapp_output);
+            outputs.push(TxOut {
+                value: Amount::ZERO,
+                script_pubkey: new_zkapp.to_script().context("incorrect new state given")?,
+            });
+
+            // Bob can only withdraw amount_out
+            amount_out
+        }
+    };
+
+    // the committee takes its fee on the withdrawn funds
+    let fee = fee_schedule.fee(amount_withdrawn);
+    if fee == Amount::ZERO {
+        outputs.remove(0);
+    } else {
+        debug!("- first output is to zkBitcoinFund: {fee_address} for {fee}");
+        outputs[0].value = fee;
+        check_not_dust("the fee output", &outputs[0])?;
+    }
+
+    // the withdrawn funds are split among the recipients
+    let recipient_outputs = recipient_outputs(recipients, amount_withdrawn)?;
+    for (recipient, output) in recipients.iter().zip(&recipient_outputs) {
+        debug!(
+            "- output to recipient {} for {}",
+            recipient.address, output.value
+        );
+    }
+    outputs.extend(recipient_outputs.iter().cloned());
+
+    Ok((spending(smart_contract, outputs), recipient_outputs))
+}
+
+/// Adds a priority fee to what a transaction pays to the zkBitcoin fund, for the orchestrator to handle it first
+/// when it's busy (see `committee::queue`).
+pub fn pay_priority_fee(mut tx: Transaction, priority_fee: Amount) -> Result<Transaction> {
+    if priority_fee == Amount::ZERO {
+        return Ok(tx);
+    }
+    let fee_script = taproot_addr_from(&protocol_config().zkbitcoin_fee_pubkey)?.script_pubkey();
+    match tx
+        .output
+        .iter_mut()
+        .find(|output| output.script_pubkey == fee_script)
+    {
+        Some(output) => output.value += priority_fee,
+        // the fee might be paid over Lightning
+        None => {
+            let output = TxOut {
+                value: priority_fee,
+                script_pubkey: fee_script,
+            };
+            check_not_dust("the priority fee output", &output)?;
+            tx.output.insert(0, output);
+        }
+    }
+    debug!("- paying a priority fee of {priority_fee}");
+    Ok(tx)
+}
+
+/// Creates the (unfunded) transaction upgrading a stateful zkapp to another circuit:
+/// the upgraded zkapp keeps all the funds (and its administrator, timelock, and recovery), but commits to `new_zkapp`
+/// (its verifier key, migrated state, and metadata).
+/// Like in [unsigned_spend], its first output pays the zkBitcoin fee (on nothing withdrawn),
+/// unless the `fee_schedule` doesn't charge for it.
+pub fn unsigned_upgrade(
+    smart_contract: &SmartContract,
+    new_zkapp: &ZkappData,
+    fee_schedule: &FeeSchedule,
+) -> Result<Transaction> {
+    ensure!(
+        smart_contract.is_stateful() && new_zkapp.state.is_some(),
+        "only stateful zkapps can be upgraded, to stateful zkapps"
+    );
+    ensure!(
+        new_zkapp.vk_hash != smart_contract.vk_hash,
+        "the zkapp is upgraded to the verifier key it already has"
+    );
+    ensure!(
+        new_zkapp.admin == smart_contract.admin,
+        "the upgraded zkapp must keep the admin key of the zkapp"
+    );
+    ensure!(
+        new_zkapp.timelock == smart_contract.timelock,
+        "the upgraded zkapp must keep the timelock of the zkapp"
+    );
+    ensure!(
+        new_zkapp.recovery == smart_contract.recovery,
+        "the upgraded zkapp must keep the recovery of the zkapp"
+    );
+    ensure!(
+        new_zkapp.deployer == smart_contract.deployer,
+        "the upgraded zkapp must keep the deployer key of the zkapp"
+    );
+
+    let mut outputs = vec![];
+    let fee = fee_schedule.fee(Amount::ZERO);
+    if fee != Amount::ZERO {
+        let fee_address = taproot_addr_from(&protocol_config().zkbitcoin_fee_pubkey)?;
+        debug!("- first output is to zkBitcoinFund: {fee_address} for {fee}");
+        let fee_output = TxOut {
+            value: fee,
+            script_pubkey: fee_address.script_pubkey(),
+        };
+        check_not_dust("the fee output", &fee_output)?;
+        outputs.push(fee_output);
+    }
+
+    // the upgraded zkapp, and its new vk + migrated state
+    outputs.push(TxOut {
+        value: smart_contract.locked_value,
+        script_pubkey: new_zkapp.script_pubkey()?,
+    });
+    outputs.push(TxOut {
+        value: Amount::ZERO,
+        script_pubkey: new_zkapp.to_script().context("incorrect new state given")?,
+    });
+
+    Ok(spending(smart_contract, outputs))
+}
+
+/// The transaction spending a zkapp (signaling replaceability, see BIP 125) to `outputs`,
+/// honoring the timelock of the zkapp (if any).
+fn spending(smart_contract: &SmartContract, outputs: Vec<TxOut>) -> Transaction {
+    let mut tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(
+                smart_contract.txid,
+                smart_contract.vout_of_zkbitcoin_utxo,
+            ),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: outputs,
+    };
+    if let Some(timelock) = smart_contract.timelock {
+        timelock.apply(&mut tx, 0);
+    }
+    tx
+}
+
 //
 // Bob's side: form a request and send it to an endpoint
 //
 
+/// A recipient of the funds unlocked from a zkapp.
+#[derive(Clone, Debug)]
+pub struct Recipient {
+    /// The address receiving the funds.
+    pub address: Address,
+
+    /// The amount received, or `None` to receive whatever is not assigned to other recipients.
+    pub amount: Option<Amount>,
+}
+
+impl FromStr for Recipient {
+    type Err = anyhow::Error;
+
+    /// Parses a recipient given as `address:amount` (in bitcoins or satoshis, see [HumanAmount]), or just `address`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (address, amount) = match s.split_once(':') {
+            Some((address, amount)) => (address, Some(amount)),
+            None => (s, None),
+        };
+
+        let address = Address::from_str(address)
+            .context("couldn't parse recipient address")?
+            .require_network(get_network())
+            .context("the recipient address is not for the current network")?;
+        let amount = amount
+            .map(|amount| HumanAmount::from_str(amount).map(Amount::from))
+            .transpose()?;
+        if let Some(amount) = amount {
+            check_not_dust(
+                &format!("the output to {address}"),
+                &TxOut {
+                    value: amount,
+                    script_pubkey: address.script_pubkey(),
+                },
+            )?;
+        }
+
+        Ok(Self { address, amount })
+    }
+}
+
+impl std::fmt::Display for Recipient {
+    /// Formats a recipient the way [Recipient::from_str] parses it.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.amount {
+            Some(amount) => write!(f, "{}:{}", self.address, amount.to_sat()),
+            None => write!(f, "{}", self.address),
+        }
+    }
+}
+
 /// An update to a _stateful_ zkapp.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Update {
@@ -69,6 +415,20 @@ pub struct Update {
 
     /// The amount being deposited into the zkapp.
     pub amount_in: String,
+
+    /// For upgradable zkapps (see [UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN]), the truncated hash of the verifier key
+    /// the zkapp is upgraded to (see [crate::truncate_vk_hash]), or [NO_UPGRADE] if it isn't.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upgrade_to: Option<String>,
+}
+
+impl Update {
+    /// Whether the update upgrades the zkapp to another circuit.
+    pub fn upgrades(&self) -> bool {
+        self.upgrade_to
+            .as_deref()
+            .is_some_and(|upgrade_to| upgrade_to != NO_UPGRADE)
+    }
 }
 
 /// A request from Bob to unlock funds from a smart contract.
@@ -98,34 +458,82 @@ pub struct BobRequest {
     /// In case of stateful zkapps, the update that can be converted as public inputs.
     pub update: Option<Update>,
 
+    /// The outputs paying the recipients of the funds withdrawn from the zkapp.
+    /// They must add up to `amount_out` for stateful zkapps, or to the locked value for stateless ones.
+    pub recipients: Vec<TxOut>,
+
     /// List of all the [TxOut] pointed out by the inputs.
     /// (This is needed to sign the transaction.)
     /// We can trust this because if Bob sends us wrong data the signature we create simply won't verify.
     pub prev_outs: Vec<TxOut>,
+
+    /// Whether the transaction is a template whose fees are paid by a sponsor (see [crate::sponsor]):
+    /// it only spends the zkapp, which the committee signs with `SIGHASH_ALL|ANYONECANPAY`,
+    /// so that the sponsor can attach its inputs afterwards.
+    #[serde(default)]
+    pub sponsored: bool,
+
+    /// The hex-encoded payment hash of the Lightning invoice paying the committee's fee,
+    /// when it's paid over Lightning instead of with an output of `tx` (see `committee::lightning`).
+    #[serde(default)]
+    pub fee_payment_hash: Option<String>,
+
+    /// When (as a UNIX timestamp) Bob stops wanting the request signed, if ever:
+    /// the orchestrator drops it if it's still queued by then (see `committee::queue`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<u64>,
+
+    /// A hex-encoded random value of [NONCE_LEN] bytes, unique to the request,
+    /// so that the committee can tell a replayed request from a new one (see `committee::replay`).
+    /// Neither the proof nor the transaction commit to it: the txid the proof is bound to is the real replay guard.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+
+    /// The zkapp UTXO the request spends, which the request is bound to (along with its nonce).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outpoint: Option<OutPoint>,
 }
 
 impl BobRequest {
+    /// Creates a request to use the zkapp deployed (or last updated) by `txid`.
+    /// Transactions are fetched through `chain`,
+    /// while the fee is paid by the wallet behind `rpc_ctx` (following `funding`),
+    /// and the circuit of the zkapp is proven by `prover`.
+    #[cfg(feature = "node")]
     pub async fn new(
         rpc_ctx: &RpcCtx,
-        bob_address: Address,
+        chain: &dyn ChainBackend,
+        recipients: &[Recipient],
         txid: bitcoin::Txid, // of zkapp
-        circom_circuit_path: &Path,
+        prover: &dyn Prover,
         mut proof_inputs: HashMap<String, Vec<String>>,
+        funding: &Funding,
     ) -> Result<Self> {
-        // fetch transaction + metadata based on txid
-        debug!("- fetching txid {txid}");
-        let (_, zkapp_tx, confirmations) = get_transaction(rpc_ctx, txid).await?;
-
-        // enforce that the smart contract was confirmed
-        ensure!(
-            confirmations >= MINIMUM_CONFIRMATIONS,
-            "Smart contract has not been confirmed yet"
-        );
+        let zkapp_tx = fetch_zkapp_tx(chain, txid).await?;
 
         // fetch smart contract we want to use
         let smart_contract = extract_smart_contract_from_tx(&zkapp_tx)?;
         debug!("- smart contract being used: {smart_contract:?}",);
 
+        // check the inputs against what the circuit expects, before proving (and funding) anything
+        if let Some(layout) = prover.layout()? {
+            lint::check_inputs(&layout, smart_contract.state.is_some(), &proof_inputs)
+                .context("the proof inputs don't fit the circuit")?;
+
+            // using an upgradable zkapp doesn't upgrade it (see [crate::upgrade])
+            if layout.public_inputs.iter().any(|name| name == "upgrade_to") {
+                proof_inputs.insert("upgrade_to".to_string(), vec![NO_UPGRADE.to_string()]);
+            }
+
+            // and administered zkapps are given the admin key recorded on-chain
+            if layout.public_inputs.iter().any(|name| name == "admin") {
+                let admin = smart_contract.admin.clone().context(
+                    "the circuit takes an admin key, but the zkapp wasn't deployed with one",
+                )?;
+                proof_inputs.insert("admin".to_string(), vec![admin]);
+            }
+        }
+
         // create a proof with a 0 txid
         // (we expect the proof to give the same `new_state` with the correct `truncated_txid` later)
         // we need to do this because we need to include the `new_state` in a stateful zkapp transaction
@@ -138,7 +546,7 @@ impl BobRequest {
 
             // prove
             let (_proof, public_inputs, _vk) =
-                snarkjs::prove(circom_circuit_path, &proof_inputs).await?;
+                prover.prove(&smart_contract.vk_hash, &proof_inputs).await?;
 
             // extract new_state
             let new_state = public_inputs
@@ -153,141 +561,85 @@ impl BobRequest {
         };
 
         // create funded transaction
-        let tx = {
-            let inputs = vec![
-                // the zkapp being used
-                serde_json::json!({
-                    "txid": txid.to_string(),
-                    "vout": smart_contract.vout_of_zkbitcoin_utxo,
-                }),
-            ];
-
-            let fee_address = taproot_addr_from(ZKBITCOIN_FEE_PUBKEY)?;
-            let fee = Amount::from_sat(FEE_ZKBITCOIN_SAT).to_string_in(Denomination::Bitcoin);
-            debug!(
-                "- first output is to zkBitcoinFund: {} for {} BTC",
-                fee_address, fee
-            );
-
-            let mut outputs = vec![
-                // first output is to zkBitcoinFund
-                // TODO: we need to create a taproot address here
-                serde_json::json!({
-                    fee_address.to_string(): fee,
-                }),
-            ];
-
-            if smart_contract.is_stateless() {
-                // move all the funds to Bob's address
-                let amount_out = smart_contract
-                    .locked_value
-                    .to_string_in(Denomination::Bitcoin);
-                debug!(
-                    "- stateless: second output is to ourselves: {} for {} BTC",
-                    bob_address, amount_out
-                );
-                outputs.push(serde_json::json!({
-                    bob_address.to_string(): amount_out,
-                }));
-            } else {
-                // the new locked value to zkBitcoin
-                let amount_in = string_to_amount(
-                    proof_inputs
-                        .get("amount_in")
-                        .and_then(|x| x.get(0))
-                        .context("amount_in in proof inputs must be of length 1")?,
-                )?;
-                let amount_out = string_to_amount(
-                    proof_inputs
-                        .get("amount_out")
-                        .and_then(|x| x.get(0))
-                        .context("amount_out in proof inputs must be of length 1")?,
-                )?;
-                let new_value = smart_contract.locked_value + amount_in - amount_out;
-
-                // convert to BTC as expected by API
-                let amount_in = amount_in.to_string_in(Denomination::Bitcoin);
-                let new_value = new_value.to_string_in(Denomination::Bitcoin);
-                let amount_out = amount_out.to_string_in(Denomination::Bitcoin);
-                let locked = smart_contract
-                    .locked_value
-                    .to_string_in(Denomination::Bitcoin);
-
-                debug!("- stateful: Bob is attempting to deposit {amount_in} BTC, and withdraw {amount_out} BTC, from the zkapp's {locked} BTC");
-                debug!(
-                    "- there will be {new_value} BTC locked in the zkapp after this transaction"
-                );
-
-                // the updated zkapp
-                let zkbitcoin_address = taproot_addr_from(ZKBITCOIN_PUBKEY)?;
-                debug!(
-                    "- stateful: second output is to zkBitcoin: {} for {} BTC",
-                    zkbitcoin_address, new_value
-                );
-                outputs.push(serde_json::json!({
-                    zkbitcoin_address.to_string(): new_value
-                }));
-
-                // Bob can only withdraw amount_out
-                debug!("- Bob is receiving amount_out: {}", amount_out);
-                outputs.push(serde_json::json!({
-                    bob_address.to_string(): amount_out,
-                }));
-
-                // its vk + new state
-                let new_state = new_state.as_ref().context("no new state")?;
-                let mut data = smart_contract.vk_hash.to_vec();
-                data.extend(circom_field_to_bytes(new_state).context("incorrect new state given")?);
-                outputs.push(serde_json::json!({
-                    "data": hex::encode(data),
-                }));
-            }
-
-            // call createrawtransaction
-            let (tx_hex, tx) = createrawtransaction(rpc_ctx, inputs, outputs, 0).await?;
-            debug!("- tx created: {tx:?}");
-
-            // fund that transaction
-            let (_tx_hex, tx, fee) =
-                fund_raw_transaction(rpc_ctx, TransactionOrHex::Hex(tx_hex)).await?;
-
-            info!("- funded tx with fee {fee}");
-            debug!("- tx funded: {tx:?}");
-
-            tx
-        };
+        let prepared = PreparedSpend::new(
+            rpc_ctx,
+            chain,
+            recipients,
+            zkapp_tx,
+            new_state.as_deref(),
+            &proof_inputs,
+            funding,
+        )
+        .await?;
 
         // create a proof with the correct txid this time
-        let truncated_txid = truncate_txid(tx.txid());
-        proof_inputs.insert("truncated_txid".to_string(), vec![truncated_txid]);
+        proof_inputs.insert(
+            "truncated_txid".to_string(),
+            vec![prepared.truncated_txid.clone()],
+        );
 
-        let (proof, public_inputs, vk) = snarkjs::prove(circom_circuit_path, &proof_inputs).await?;
+        let (proof, public_inputs, vk) =
+            prover.prove(&smart_contract.vk_hash, &proof_inputs).await?;
         debug!(
             "- public_inputs used to create the proof: {:?}",
             public_inputs.0
         );
 
+        // ensure it created the same new_state
+        if let Some(new_state) = &new_state {
+            ensure!(
+                public_inputs.0.first() == Some(new_state),
+                "the circuit must return the same output given different txid"
+            );
+        }
+
+        // create request, with a fresh nonce
+        let res = prepared
+            .into_request(vk, proof, &public_inputs)?
+            .with_nonce(&new_nonce())?;
+
+        debug!("- Bob's request: {res:?}");
+
+        Ok(res)
+    }
+
+    /// Packages a proof (and the transaction it's bound to) into a request.
+    /// `tx` is the funded transaction spending the zkapp deployed (or last updated) by `zkapp_tx`,
+    /// `recipients` are the outputs paying the recipients (see [unsigned_spend]),
+    /// `prev_outs` are the outputs spent by every input of `tx`, in order,
+    /// and `nonce` is a fresh random nonce (see [BobRequest::nonce]), which committees protecting against replays require.
+    #[allow(clippy::too_many_arguments)]
+    pub fn package(
+        tx: Transaction,
+        zkapp_tx: Transaction,
+        vk: plonk::VerifierKey,
+        proof: plonk::Proof,
+        public_inputs: &PublicInputs,
+        recipients: Vec<TxOut>,
+        prev_outs: Vec<TxOut>,
+        nonce: Option<&str>,
+    ) -> Result<Self> {
+        if let Some(nonce) = nonce {
+            check_nonce(nonce)?;
+        }
+        let smart_contract = extract_smart_contract_from_tx(&zkapp_tx)?;
+
         // sanity check
         ensure!(
             vk.hash() == smart_contract.vk_hash,
             "the zkapp being used does not match the circuit passed"
         );
 
-        // and ensure it created the same new_state
         let update = if smart_contract.is_stateful() {
-            let new_state = new_state.unwrap();
             ensure!(
-                public_inputs.0.len()
-                    == 1 * 2 /* prev/new_state */ + 1 /* truncated txid */ + 1 /* amount_out */ + 1, /* amount_in */
+                matches!(
+                    public_inputs.0.len(),
+                    STATEFUL_ZKAPP_PUBLIC_INPUT_LEN
+                        | UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN
+                        | ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN
+                ),
                 "the number of public inputs is not correct"
             );
-
-            let new_state2 = &public_inputs.0[0];
-            ensure!(
-                &new_state == new_state2,
-                "the circuit must return the same output given different txid"
-            );
-
             Some(public_inputs.to_update())
         } else {
             None
@@ -297,45 +649,77 @@ impl BobRequest {
         let zkapp_input = tx
             .input
             .iter()
-            .enumerate()
-            .find(|(_, x)| x.previous_output.txid == txid)
-            .context("internal error: the transaction does not contain the zkapp being used")?
-            .0;
+            .position(|x| x.previous_output.txid == smart_contract.txid)
+            .context("the transaction does not contain the zkapp being used")?;
 
-        // compute prev_outs as all the TxOut pointed out by the inputs
-        let mut prev_outs = vec![];
-        for (input_idx, input) in tx.input.iter().enumerate() {
-            let (_, tx, confirmations) =
-                get_transaction(rpc_ctx, input.previous_output.txid).await?;
-            // TODO: this is not useful as the transaction itself has received enough confirmation at this point
-            ensure!(
-                confirmations >= MINIMUM_CONFIRMATIONS,
-                "one of the input ({}) is not confirmed yet",
-                input.previous_output.txid
-            );
-
-            prev_outs.push(
-                tx.output
-                    .get(input.previous_output.vout as usize)
-                    .context(format!("the input {input_idx} does not exist"))?
-                    .clone(),
-            );
-        }
+        ensure!(
+            prev_outs.len() == tx.input.len(),
+            "expected an output spent for each of the {} inputs of the transaction, got {}",
+            tx.input.len(),
+            prev_outs.len()
+        );
+        let outpoint = tx.input[zkapp_input].previous_output;
 
-        // create request
-        let res = Self {
+        Ok(Self {
             tx,
             zkapp_tx,
             zkapp_input,
             vk,
             proof,
             update,
+            recipients,
             prev_outs,
+            sponsored: false,
+            fee_payment_hash: None,
+            deadline: None,
+            nonce: nonce.map(str::to_string),
+            outpoint: Some(outpoint),
+        })
+    }
+
+    /// Sets the nonce of the request (see [BobRequest::nonce]), which must be [NONCE_LEN] hex-encoded bytes.
+    pub fn with_nonce(self, nonce: &str) -> Result<Self> {
+        check_nonce(nonce)?;
+        Ok(Self {
+            nonce: Some(nonce.to_string()),
+            ..self
+        })
+    }
+
+    /// Checks that the request is bound to the zkapp UTXO it spends (and has a well-formed nonce).
+    /// Requests without a nonce or an outpoint are only accepted if `required` is false.
+    pub fn check_binding(&self, required: bool) -> Result<()> {
+        match &self.nonce {
+            Some(nonce) => check_nonce(nonce)?,
+            None => ensure!(!required, "the request must have a nonce"),
+        }
+        let Some(outpoint) = self.outpoint else {
+            ensure!(
+                !required,
+                "the request must be bound to the zkapp UTXO it spends"
+            );
+            return Ok(());
         };
 
-        debug!("- Bob's request: {res:?}");
+        ensure!(
+            self.zkapp_outpoint()? == outpoint,
+            "the request is bound to zkapp UTXO {outpoint}, but its transaction doesn't spend it"
+        );
+        let smart_contract = extract_smart_contract_from_tx(&self.zkapp_tx)?;
+        ensure!(
+            outpoint == OutPoint::new(self.zkapp_tx.txid(), smart_contract.vout_of_zkbitcoin_utxo),
+            "the request is bound to zkapp UTXO {outpoint}, but the zkapp_tx given doesn't create it"
+        );
+        Ok(())
+    }
 
-        Ok(res)
+    /// The sighash type the committee signs the zkapp input with.
+    pub fn sighash_type(&self) -> TapSighashType {
+        if self.sponsored {
+            TapSighashType::AllPlusAnyoneCanPay
+        } else {
+            TapSighashType::All
+        }
     }
 
     /// The transaction ID and output index of the zkapp used in the request.
@@ -353,19 +737,28 @@ impl BobRequest {
         Ok(self.zkapp_outpoint()?.txid)
     }
 
+    /// Hashes a request.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Keccak256::new();
+        hasher.update(serde_json::to_string(&self).unwrap());
+        let hash = hasher.finalize().to_vec();
+        hash.try_into().unwrap()
+    }
+
     /// Validate the unsigned transaction contained in Bob's request.
     /// It checks outputs, but not inputs.
     /// The caller will be in charge of retrieving the smart contract and verifying its execution.
+    #[cfg(feature = "node")]
     fn validate_transaction(
         tx: &Transaction,
         smart_contract: &SmartContract,
         update: Option<&Update>,
+        recipients: &[TxOut],
     ) -> Result<()> {
         // TODO: we need to make sure that amount_out < smart_contract.locked_value
 
         // it must contain an output fee paid to zkBitcoinFund
-        let pay_to_zkbitcoin_fund_script =
-            p2tr_script_to(PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap());
+        let pay_to_zkbitcoin_fund_script = zkapp_script_pubkey(smart_contract.recovery.as_ref())?;
         debug!(
             "- pay_to_zkbitcoin_fund_script: {:?}",
             pay_to_zkbitcoin_fund_script
@@ -385,10 +778,52 @@ impl BobRequest {
             // if the zkapp is stateful, it must also produce a new stateful zkapp as output
             let new_zkapp = extract_smart_contract_from_tx(tx)?;
 
-            // it contains the same VK
+            if update.upgrades() {
+                // it contains the VK the proof upgrades the zkapp to (and its metadata can change with it)
+                ensure!(
+                    new_zkapp.vk_hash != smart_contract.vk_hash,
+                    "the zkapp is upgraded to the VK it already has"
+                );
+                ensure!(
+                    update.upgrade_to == Some(truncate_vk_hash(&new_zkapp.vk_hash)),
+                    "the upgraded zkapp doesn't contain the VK the proof upgrades it to"
+                );
+            } else {
+                // it contains the same VK
+                ensure!(
+                    new_zkapp.vk_hash == smart_contract.vk_hash,
+                    "the updated zkapp is not the same as the previous zkapp"
+                );
+
+                // it keeps the metadata of the zkapp
+                ensure!(
+                    new_zkapp.metadata_hash == smart_contract.metadata_hash,
+                    "the updated zkapp doesn't keep the metadata of the previous zkapp"
+                );
+            }
+
+            // it keeps the administrator of the zkapp (even when upgraded)
+            ensure!(
+                new_zkapp.admin == smart_contract.admin,
+                "the updated zkapp doesn't keep the admin key of the previous zkapp"
+            );
+
+            // and its timelock
+            ensure!(
+                new_zkapp.timelock == smart_contract.timelock,
+                "the updated zkapp doesn't keep the timelock of the previous zkapp"
+            );
+
+            // and who can recover it
             ensure!(
-                new_zkapp.vk_hash == smart_contract.vk_hash,
-                "the updated zkapp is not the same as the previous zkapp"
+                new_zkapp.recovery == smart_contract.recovery,
+                "the updated zkapp doesn't keep the recovery of the previous zkapp"
+            );
+
+            // and who can register its policy
+            ensure!(
+                new_zkapp.deployer == smart_contract.deployer,
+                "the updated zkapp doesn't keep the deployer key of the previous zkapp"
             );
 
             // it contains the correct new state
@@ -405,17 +840,84 @@ impl BobRequest {
             let expected_value = {
                 let amount_out = Amount::from_str_in(&update.amount_out, Denomination::Satoshi)?;
                 let amount_in = Amount::from_str_in(&update.amount_in, Denomination::Satoshi)?;
-                smart_contract.locked_value + amount_in - amount_out
+                smart_contract
+                    .locked_value
+                    .checked_add(amount_in)
+                    .and_then(|value| value.checked_sub(amount_out))
+                    .with_context(|| {
+                        format!(
+                            "the update withdraws {} sats, but the zkapp only holds {} sats (and {} sats are deposited)",
+                            amount_out.to_sat(),
+                            smart_contract.locked_value.to_sat(),
+                            amount_in.to_sat()
+                        )
+                    })?
             };
             ensure!(expected_value == new_value, "the updated zkapp does not contain the correct locked value after withdrawl and funding");
         }
 
+        // the recipients must all be paid by the transaction
+        // (a stateful zkapp can be updated, or upgraded, without withdrawing anything, see below)
+        ensure!(
+            !recipients.is_empty() || update.is_some(),
+            "the request does not contain any recipient"
+        );
+        ensure!(
+            recipients.iter().map(|r| &r.script_pubkey).unique().count() == recipients.len(),
+            "the request contains the same recipient more than once"
+        );
+        for recipient in recipients {
+            ensure!(
+                tx.output.contains(recipient),
+                "the transaction does not pay the recipient {:?}",
+                recipient.script_pubkey
+            );
+        }
+
+        // and no output can be dust (the transaction wouldn't be relayed)
+        for (vout, output) in tx.output.iter().enumerate() {
+            check_not_dust(&format!("output {vout}"), output)?;
+        }
+
+        // and they must receive exactly what is withdrawn from the zkapp
+        let total = recipients
+            .iter()
+            .try_fold(Amount::ZERO, |acc, r| acc.checked_add(r.value))
+            .context("the recipient amounts overflow")?;
+        let withdrawn = match update {
+            Some(update) => Amount::from_str_in(&update.amount_out, Denomination::Satoshi)?,
+            None => smart_contract.locked_value,
+        };
+        ensure!(
+            total == withdrawn,
+            "the recipients receive {total}, but {withdrawn} is withdrawn from the zkapp"
+        );
+
         //
         Ok(())
     }
 
     /// Validates a request received from Bob.
+    #[cfg(feature = "node")]
     pub async fn validate_request(&self) -> Result<SmartContract> {
+        let (smart_contract, public_inputs) = self.validate_request_except_proof().await?;
+
+        // verify proof (with the proof system of the VK),
+        // on a blocking thread so that a slow proof doesn't hold up other requests
+        debug!("- attempting to verify proof");
+        let (vk, proof) = (self.vk.clone(), self.proof.clone());
+        tokio::task::spawn_blocking(move || verify_proof(&vk, &public_inputs, &proof))
+            .await
+            .context("the proof verification panicked")??;
+
+        //
+        Ok(smart_contract)
+    }
+
+    /// Validates a request received from Bob, except for its proof,
+    /// and returns the public inputs the proof must be verified against.
+    #[cfg(feature = "node")]
+    pub async fn validate_request_except_proof(&self) -> Result<(SmartContract, Vec<String>)> {
         // extract smart contract from tx
         let smart_contract = extract_smart_contract_from_tx(&self.zkapp_tx)?;
 
@@ -430,8 +932,28 @@ impl BobRequest {
             "the zkapp_tx given is not the one being used"
         );
 
+        // a sponsored template only spends the zkapp (the sponsor's inputs are attached once signed)
+        if self.sponsored {
+            ensure!(
+                self.tx.input.len() == 1 && self.prev_outs.len() == 1,
+                "a sponsored transaction must only spend the zkapp"
+            );
+        }
+
         // validate the unsigned transaction
-        Self::validate_transaction(&self.tx, &smart_contract, self.update.as_ref())?;
+        Self::validate_transaction(
+            &self.tx,
+            &smart_contract,
+            self.update.as_ref(),
+            &self.recipients,
+        )?;
+
+        // a timelocked zkapp can only be spent by a transaction that can't be mined before the timelock expires
+        if let Some(timelock) = smart_contract.timelock {
+            timelock
+                .check(&self.tx, self.zkapp_input)
+                .context("the transaction doesn't honor the timelock of the zkapp")?;
+        }
 
         // ensure that the hash of the VK correctly gives us the vk_hash
         ensure!(
@@ -439,7 +961,13 @@ impl BobRequest {
             "VK does not match the VK hash in the smart contract"
         );
 
-        // TODO: do we need to check that vk.nPublic makes sense?
+        // TODO: do we need to check that vk.n_public() makes sense?
+
+        // the proof system is part of the VK, and thus committed to by its hash
+        ensure!(
+            self.vk.is_consistent(),
+            "the VK is malformed (its protocol is not the one of its format)"
+        );
 
         // create truncated txid of Bob's transaction
         let bob_txid = self.tx.txid();
@@ -454,16 +982,28 @@ impl BobRequest {
                 .context("an update was expected as the smart contract is stateful")?;
 
             // ensure that the smart contract expects the correct number of public inputs
+            // (upgradable zkapps also take the VK they are upgraded to, and administered ones their admin key)
             ensure!(
-                self.vk.nPublic == STATEFUL_ZKAPP_PUBLIC_INPUT_LEN,
-                "the smart contract is malformed, we observed {nPublic} public inputs, but expected {STATEFUL_ZKAPP_PUBLIC_INPUT_LEN} for a stateful zkapp", nPublic=self.vk.nPublic
+                smart_contract.admin.is_none() || update.upgrade_to.is_some(),
+                "the zkapp has an administrator, so the update must say whether it upgrades it"
+            );
+            let expected = match (&update.upgrade_to, &smart_contract.admin) {
+                (Some(_), Some(_)) => ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN,
+                (Some(_), None) => UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN,
+                (None, _) => STATEFUL_ZKAPP_PUBLIC_INPUT_LEN,
+            };
+            ensure!(
+                self.vk.n_public().is_none() || self.vk.n_public() == Some(expected),
+                "the smart contract is malformed, we observed {n_public:?} public inputs, but expected {expected} for a stateful zkapp", n_public=self.vk.n_public()
             );
 
             // ensure that the previous state used is correctly used
             ensure!(prev_state == &update.prev_state);
 
-            //
-            PublicInputs::from_update(update, truncated_txid)?.0
+            // the admin key is the one recorded on-chain, not one given by Bob
+            let mut public_inputs = PublicInputs::from_update(update, truncated_txid)?.0;
+            public_inputs.extend(smart_contract.admin.clone());
+            public_inputs
         } else {
             vec![truncated_txid]
         };
@@ -473,12 +1013,292 @@ impl BobRequest {
         // TODO: we need to make sure that new_locked = prev_locked + amount_in - amount_out and that amount_out < prev_locked + amount_in
         //smart_contract.check_remaining_funds(&self)?;
 
-        // verify proof using snarkjs
-        debug!("- attempting to verify proof");
-        verify_proof(&self.vk, &public_inputs, &self.proof)?;
-
         //
-        Ok(smart_contract)
+        Ok((smart_contract, public_inputs))
+    }
+}
+
+/// A funded transaction spending a zkapp, waiting for the proof it is bound to.
+/// This is what a request is made of before being proven,
+/// so that the proof can be made elsewhere (see [PreparedSpend::into_request]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreparedSpend {
+    /// The funded transaction spending the zkapp.
+    pub tx: Transaction,
+
+    /// The transaction that deployed (or last updated) the zkapp.
+    pub zkapp_tx: Transaction,
+
+    /// The outputs paying the recipients (see [unsigned_spend]).
+    pub recipients: Vec<TxOut>,
+
+    /// The outputs spent by every input of `tx`, in order.
+    pub prev_outs: Vec<TxOut>,
+
+    /// The new state of a stateful zkapp, that the proof must output.
+    pub new_state: Option<String>,
+
+    /// The state of a stateful zkapp being updated, that the proof must take (as `prev_state`).
+    pub prev_state: Option<String>,
+
+    /// The truncated txid of `tx`, that the proof must take (as `truncated_txid`).
+    pub truncated_txid: String,
+
+    /// The truncated hash of the VK an upgradable zkapp is upgraded to, that the proof must take (as `upgrade_to`),
+    /// if `tx` upgrades it (see [PreparedSpend::upgrade]).
+    #[serde(default)]
+    pub upgrade_to: Option<String>,
+
+    /// Whether `tx` is left unfunded, for a sponsor to pay its fees (see [BobRequest::sponsored]).
+    #[serde(default)]
+    pub sponsored: bool,
+
+    /// The payment hash of the Lightning invoice paying the committee's fee, instead of an output of `tx`
+    /// (see [BobRequest::fee_payment_hash]).
+    #[serde(default)]
+    pub fee_payment_hash: Option<String>,
+}
+
+impl PreparedSpend {
+    /// Creates and funds the transaction using the zkapp deployed (or last updated) by `zkapp_tx`,
+    /// moving the `amount_in` and `amount_out` of `proof_inputs` for stateful zkapps (which are updated to `new_state`).
+    /// Transactions are fetched through `chain`,
+    /// while the fee is paid by the wallet behind `rpc_ctx` (following `funding`).
+    #[cfg(feature = "node")]
+    pub async fn new(
+        rpc_ctx: &RpcCtx,
+        chain: &dyn ChainBackend,
+        recipients: &[Recipient],
+        zkapp_tx: Transaction,
+        new_state: Option<&str>,
+        proof_inputs: &HashMap<String, Vec<String>>,
+        funding: &Funding,
+    ) -> Result<Self> {
+        let smart_contract = extract_smart_contract_from_tx(&zkapp_tx)?;
+
+        // create transaction
+        let (tx, recipient_outputs) = {
+            let (amount_in, amount_out) = if smart_contract.is_stateful() {
+                let amount = |name: &str| {
+                    proof_inputs
+                        .get(name)
+                        .and_then(|x| x.get(0))
+                        .with_context(|| format!("{name} in proof inputs must be of length 1"))
+                        .and_then(|amount| string_to_amount(amount))
+                };
+                (amount("amount_in")?, amount("amount_out")?)
+            } else {
+                (Amount::ZERO, Amount::ZERO)
+            };
+            unsigned_spend(
+                &smart_contract,
+                recipients,
+                new_state,
+                amount_in,
+                amount_out,
+                &Self::fee_schedule(funding),
+            )?
+        };
+        let tx = pay_priority_fee(tx, funding.priority_fee)?;
+        debug!("- tx created: {tx:?}");
+
+        Self::fund(
+            rpc_ctx,
+            chain,
+            zkapp_tx,
+            tx,
+            recipient_outputs,
+            new_state.map(str::to_string),
+            funding,
+        )
+        .await
+    }
+
+    /// Creates and funds the transaction upgrading the zkapp deployed (or last updated) by `zkapp_tx`
+    /// to `new_zkapp` (see [unsigned_upgrade]), with the new state the proof must output.
+    /// Transactions are fetched through `chain`,
+    /// while the fee is paid by the wallet behind `rpc_ctx` (following `funding`).
+    #[cfg(feature = "node")]
+    pub async fn upgrade(
+        rpc_ctx: &RpcCtx,
+        chain: &dyn ChainBackend,
+        zkapp_tx: Transaction,
+        new_zkapp: &ZkappData,
+        funding: &Funding,
+    ) -> Result<Self> {
+        let smart_contract = extract_smart_contract_from_tx(&zkapp_tx)?;
+        let tx = unsigned_upgrade(&smart_contract, new_zkapp, &Self::fee_schedule(funding))?;
+        debug!("- tx created: {tx:?}");
+
+        let prepared = Self::fund(
+            rpc_ctx,
+            chain,
+            zkapp_tx,
+            tx,
+            vec![],
+            new_zkapp.state.clone(),
+            funding,
+        )
+        .await?;
+        Ok(Self {
+            upgrade_to: Some(truncate_vk_hash(&new_zkapp.vk_hash)),
+            ..prepared
+        })
+    }
+
+    /// The fee schedule of the committee the transaction pays
+    /// (a fee paid over Lightning isn't paid by the transaction).
+    #[cfg(feature = "node")]
+    fn fee_schedule(funding: &Funding) -> FeeSchedule {
+        match funding.fee_invoice {
+            Some(_) => FeeSchedule::Fixed { sats: 0 },
+            None => funding.zkbitcoin_fee.clone(),
+        }
+    }
+
+    /// Funds the transaction `tx` spending the zkapp deployed (or last updated) by `zkapp_tx`,
+    /// and collects what the proof must be made for.
+    #[cfg(feature = "node")]
+    async fn fund(
+        rpc_ctx: &RpcCtx,
+        chain: &dyn ChainBackend,
+        zkapp_tx: Transaction,
+        tx: Transaction,
+        recipients: Vec<TxOut>,
+        new_state: Option<String>,
+        funding: &Funding,
+    ) -> Result<Self> {
+        let smart_contract = extract_smart_contract_from_tx(&zkapp_tx)?;
+
+        // fund that transaction (the zkapp's input already brings its locked value),
+        // unless a sponsor pays for it once signed, with the wallet unless it isn't needed
+        let tx = if funding.sponsored {
+            info!("- leaving the fees to a sponsor");
+            tx
+        } else {
+            let (_tx_hex, tx, fee) = if funding.without_wallet() {
+                funding
+                    .fund_without_wallet(chain, tx, smart_contract.locked_value)
+                    .await?
+            } else {
+                funding
+                    .fund(rpc_ctx, tx, smart_contract.locked_value)
+                    .await?
+            };
+            info!("- funded tx with fee {fee}");
+            tx
+        };
+        debug!("- tx funded: {tx:?}");
+
+        // compute prev_outs as all the TxOut pointed out by the inputs
+        let mut prev_outs = vec![];
+        for (input_idx, input) in tx.input.iter().enumerate() {
+            let (tx, confirmations) = chain.get_transaction(input.previous_output.txid).await?;
+            // TODO: this is not useful as the transaction itself has received enough confirmation at this point
+            ensure!(
+                confirmations >= MINIMUM_CONFIRMATIONS,
+                "one of the input ({}) is not confirmed yet",
+                input.previous_output.txid
+            );
+
+            prev_outs.push(
+                tx.output
+                    .get(input.previous_output.vout as usize)
+                    .context(format!("the input {input_idx} does not exist"))?
+                    .clone(),
+            );
+        }
+
+        Ok(Self {
+            truncated_txid: truncate_txid(tx.txid()),
+            upgrade_to: None,
+            tx,
+            zkapp_tx,
+            recipients,
+            prev_outs,
+            new_state,
+            prev_state: smart_contract.state,
+            sponsored: funding.sponsored,
+            fee_payment_hash: funding
+                .fee_invoice
+                .as_ref()
+                .map(|invoice| invoice.payment_hash.clone()),
+        })
+    }
+
+    /// Packages the proof of the spend into a request (see [BobRequest::package]),
+    /// once checked to be made for it. The request has no nonce yet (see [BobRequest::with_nonce]).
+    pub fn into_request(
+        self,
+        vk: plonk::VerifierKey,
+        proof: plonk::Proof,
+        public_inputs: &PublicInputs,
+    ) -> Result<BobRequest> {
+        match &self.new_state {
+            Some(new_state) => {
+                ensure!(
+                    matches!(
+                        public_inputs.0.len(),
+                        STATEFUL_ZKAPP_PUBLIC_INPUT_LEN
+                            | UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN
+                            | ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN
+                    ),
+                    "expected {STATEFUL_ZKAPP_PUBLIC_INPUT_LEN} public inputs for a stateful zkapp (or {UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN} for an upgradable one, and {ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN} for an administered one), got {}",
+                    public_inputs.0.len()
+                );
+                ensure!(
+                    &public_inputs.new_state() == new_state,
+                    "the proof outputs the new state {}, but the transaction updates the zkapp to {new_state}",
+                    public_inputs.new_state()
+                );
+                ensure!(
+                    Some(public_inputs.prev_state()) == self.prev_state,
+                    "the proof was not made for the current state of the zkapp"
+                );
+                ensure!(
+                    public_inputs.truncated_txid() == self.truncated_txid,
+                    "the proof was not made for this transaction (its truncated txid is {})",
+                    self.truncated_txid
+                );
+                let upgrade_to = public_inputs.upgrade_to();
+                match &self.upgrade_to {
+                    Some(_) => ensure!(
+                        upgrade_to == self.upgrade_to,
+                        "the proof doesn't upgrade the zkapp to the VK the transaction upgrades it to"
+                    ),
+                    None => ensure!(
+                        upgrade_to.is_none() || upgrade_to.as_deref() == Some(NO_UPGRADE),
+                        "the proof upgrades the zkapp, but the transaction doesn't"
+                    ),
+                }
+                let admin = extract_smart_contract_from_tx(&self.zkapp_tx)?.admin;
+                ensure!(
+                    public_inputs.admin() == admin,
+                    "the proof was not made for the admin key of the zkapp"
+                );
+            }
+            None => ensure!(
+                public_inputs.0 == [self.truncated_txid.clone()],
+                "the proof was not made for this transaction (its truncated txid is {})",
+                self.truncated_txid
+            ),
+        }
+
+        let bob_request = BobRequest::package(
+            self.tx,
+            self.zkapp_tx,
+            vk,
+            proof,
+            public_inputs,
+            self.recipients,
+            self.prev_outs,
+            None,
+        )?;
+        Ok(BobRequest {
+            sponsored: self.sponsored,
+            fee_payment_hash: self.fee_payment_hash,
+            ..bob_request
+        })
     }
 }
 
@@ -487,28 +1307,54 @@ pub struct BobResponse {
     pub unlocked_tx: Transaction,
 }
 
-pub async fn send_bob_request(address: &str, request: BobRequest) -> Result<BobResponse> {
-    let ctx = RpcCtx {
-        version: Some("2.0"),
-        wallet: None,
-        address: Some(address.to_string()),
-        auth: None,
-    };
+impl BobResponse {
+    /// Converts the unlocked transaction into a PSBT, so that the remaining inputs can be signed by an external signer.
+    /// The zkapp input keeps the witness produced by the committee,
+    /// and all inputs get the output they spend from `prev_outs`.
+    pub fn to_psbt(&self, prev_outs: &[TxOut]) -> Result<Psbt> {
+        ensure!(
+            prev_outs.len() == self.unlocked_tx.input.len(),
+            "the number of previous outputs doesn't match the number of inputs"
+        );
 
-    let resp = json_rpc_request(
-        &ctx,
-        "unlock_funds",
-        &[serde_json::value::to_raw_value(&request).unwrap()],
-    )
-    .await
-    .context("couldn't send unlock_funds request to orchestrator")?;
+        // a PSBT is created from a transaction stripped of its witnesses
+        let mut unsigned_tx = self.unlocked_tx.clone();
+        let witnesses: Vec<Witness> = unsigned_tx
+            .input
+            .iter_mut()
+            .map(|input| std::mem::take(&mut input.witness))
+            .collect();
+
+        let mut psbt = Psbt::from_unsigned_tx(unsigned_tx)?;
+        for ((input, witness), prev_out) in psbt.inputs.iter_mut().zip(witnesses).zip(prev_outs) {
+            input.witness_utxo = Some(prev_out.clone());
+            if !witness.is_empty() {
+                input.final_script_witness = Some(witness);
+            }
+        }
+
+        Ok(psbt)
+    }
+}
 
-    // TODO: get rid of unwrap in here
-    let response: bitcoincore_rpc::jsonrpc::Response =
-        serde_json::from_str(&resp).context("couldn't deserialize orchestrator's response")?;
-    let bob_response: BobResponse = response.result().context("bob request failed")?;
+/// Sends a request to the orchestrator (see [OrchestratorClient::unlock_funds]).
+#[cfg(feature = "node")]
+pub async fn send_bob_request(address: &str, request: BobRequest) -> Result<BobResponse> {
+    OrchestratorClient::new(address)
+        .unlock_funds(&request)
+        .await
+}
 
-    Ok(bob_response)
+/// Sends a batch of requests spending several zkapps in the same transaction.
+/// See [crate::committee::orchestrator::Orchestrator::handle_batch] for the constraints on the batch.
+#[cfg(feature = "node")]
+pub async fn send_bob_batch_request(
+    address: &str,
+    requests: Vec<BobRequest>,
+) -> Result<BobResponse> {
+    OrchestratorClient::new(address)
+        .unlock_funds_batch(&requests)
+        .await
 }
 
 //
@@ -522,18 +1368,28 @@ pub struct SmartContract {
     pub locked_value: Amount,
     pub vk_hash: [u8; 32],
     pub state: Option<String>,
+    /// The hash of the zkapp's metadata, if it was deployed with some (see [crate::zkapp_data::ZkappMetadata]).
+    pub metadata_hash: Option<[u8; METADATA_HASH_LEN]>,
+    /// The public key of the zkapp's administrator, if it was deployed with one (see [ZkappData::admin]).
+    pub admin: Option<String>,
+    /// When the zkapp can be spent, if it was deployed with a timelock (see [ZkappData::timelock]).
+    pub timelock: Option<Timelock>,
+    /// Who can recover the zkapp should the committee stop signing, if it is recoverable (see [crate::recovery]).
+    pub recovery: Option<Recovery>,
+    /// Who can register the zkapp's policy, if it was deployed with a deployer key (see [ZkappData::deployer]).
+    pub deployer: Option<secp256k1::XOnlyPublicKey>,
     pub vout_of_zkbitcoin_utxo: u32,
 }
 
 impl SmartContract {
     /// Returns true if the smart contract is stateless.
-    fn is_stateless(&self) -> bool {
+    pub fn is_stateless(&self) -> bool {
         // a stateless contract expects no public input
         self.state.is_none()
     }
 
     /// Returns true if the smart contract is stateful.
-    fn is_stateful(&self) -> bool {
+    pub fn is_stateful(&self) -> bool {
         self.state.is_some()
     }
 
@@ -613,94 +1469,69 @@ impl SmartContract {
     // }
 }
 
-pub fn parse_op_return_data(script: &bitcoin::ScriptBuf) -> Result<Vec<u8>> {
-    // we expect [OP_RETURN, OP_PUSHBYTES]
-    // anything else we won't accept
-    let mut instructions = script.instructions();
-    let inst = instructions
-        .next()
-        .expect("caller should have checked that this is an OP_RETURN")
-        .expect("not sure why there are two layers of result")
-        .opcode()
-        .expect("come on");
-    assert_eq!(
-        inst, OP_RETURN,
-        "caller should have checked that this is an OP_RETURN"
-    );
-
-    let inst = instructions
-        .next()
-        .context("no data was pushed as the last instruction of the script")?;
-
-    let res = if let Ok(Instruction::PushBytes(bytes)) = inst {
-        bytes.as_bytes().to_vec()
-    } else {
-        bail!("last instruction of the script was not a pushdata");
-    };
-
-    ensure!(
-        instructions.next().is_none(),
-        "we only expect one pushdata in an OP_RETURN"
-    );
-
-    Ok(res)
-}
-
 /// Extracts smart contract information as a [SmartContract] from a transaction.
 pub fn extract_smart_contract_from_tx(raw_tx: &Transaction) -> Result<SmartContract> {
-    // extract zkapp locked amount
-    let zkbitcoin_pubkey: PublicKey = PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap();
-    let expected_script = p2tr_script_to(zkbitcoin_pubkey);
-    let (vout, output) = raw_tx
-        .output
-        .iter()
-        .enumerate()
-        .find(|(_, x)| x.script_pubkey == expected_script)
-        .context("Transaction does not contain an output for 0xzkBitcoin")?;
-    let locked_value = output.value;
-
     // extract OP_RETURN data
-    let (vk_hash, state) = {
+    let data = {
         let output = raw_tx
             .output
             .iter()
             .find(|x| x.script_pubkey.is_op_return())
             .context("Transaction has no OP_RETURN")?;
-        let data = parse_op_return_data(&output.script_pubkey)?;
-
-        // ensure that the list at least contains the VK hash
-        // other elements in the list are presumed to contain public inputs
-        ensure!(data.len() >= 32, "OP_RETURN output is too small, it should at least contain the 32-byte hash of the verifier key");
-
-        let (vk_hash, state) = data.split_at(32);
-        let vk_hash: [u8; 32] = vk_hash.try_into().unwrap();
+        ZkappData::from_script(&output.script_pubkey)?
+    };
 
-        // parse state
-        let state = if state.is_empty() {
-            None
-        } else {
-            let res = circom_field_from_bytes(state)?;
-            Some(res)
-        };
+    // extract zkapp locked amount (from the zkBitcoin address, or the output of a recoverable zkapp)
+    let expected_script = data.script_pubkey()?;
+    let (vout, output) = raw_tx
+        .output
+        .iter()
+        .enumerate()
+        .find(|(_, x)| x.script_pubkey == expected_script)
+        .context("Transaction does not contain an output for 0xzkBitcoin")?;
+    let locked_value = output.value;
 
-        (vk_hash, state)
-    };
+    let ZkappData {
+        vk_hash,
+        state,
+        metadata_hash,
+        admin,
+        timelock,
+        recovery,
+        deployer,
+    } = data;
 
     let smart_contract = SmartContract {
         txid: raw_tx.txid(),
         locked_value,
         vk_hash,
         state,
+        metadata_hash,
+        admin,
+        timelock,
+        recovery,
+        deployer,
         vout_of_zkbitcoin_utxo: vout as u32,
     };
     Ok(smart_contract)
 }
 
 /// Fetch the smart contract on-chain from the txid.
-pub async fn fetch_smart_contract(ctx: &RpcCtx, txid: bitcoin::Txid) -> Result<SmartContract> {
+#[cfg(feature = "node")]
+pub async fn fetch_smart_contract(
+    chain: &dyn ChainBackend,
+    txid: bitcoin::Txid,
+) -> Result<SmartContract> {
+    // parse transaction
+    extract_smart_contract_from_tx(&fetch_zkapp_tx(chain, txid).await?)
+}
+
+/// Fetch the transaction that deployed (or last updated) a zkapp, once confirmed.
+#[cfg(feature = "node")]
+pub async fn fetch_zkapp_tx(chain: &dyn ChainBackend, txid: bitcoin::Txid) -> Result<Transaction> {
     // fetch transaction + metadata based on txid
     debug!("- fetching txid {txid}", txid = txid);
-    let (_tx_hex, transaction, confirmations) = get_transaction(ctx, txid).await?;
+    let (transaction, confirmations) = chain.get_transaction(txid).await?;
 
     // enforce that the smart contract was confirmed
     ensure!(
@@ -708,6 +1539,335 @@ pub async fn fetch_smart_contract(ctx: &RpcCtx, txid: bitcoin::Txid) -> Result<S
         "Smart contract has not been confirmed yet"
     );
 
-    // parse transaction
-    extract_smart_contract_from_tx(&transaction)
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipient(address: &str, amount: Option<u64>) -> Recipient {
+        Recipient {
+            address: Address::from_str(address).unwrap().assume_checked(),
+            amount: amount.map(Amount::from_sat),
+        }
+    }
+
+    #[test]
+    fn test_recipient_outputs() {
+        let alice = "tb1q6nkpv2j9lxrm6h3w4skrny3thswgdcca8cx9k6";
+        let bob = "tb1q6vjawwska63qxf77rrm5uwqev0ma8as8d0mkrt";
+        let total = Amount::from_sat(1000);
+
+        // the recipient without an amount gets the remainder
+        let outputs =
+            recipient_outputs(&[recipient(alice, Some(300)), recipient(bob, None)], total).unwrap();
+        assert_eq!(outputs[0].value, Amount::from_sat(300));
+        assert_eq!(outputs[1].value, Amount::from_sat(700));
+
+        // explicit amounts must add up to the total
+        assert!(recipient_outputs(
+            &[recipient(alice, Some(300)), recipient(bob, Some(700))],
+            total
+        )
+        .is_ok());
+        assert!(recipient_outputs(
+            &[recipient(alice, Some(300)), recipient(bob, Some(600))],
+            total
+        )
+        .is_err());
+        assert!(recipient_outputs(&[recipient(alice, Some(1001))], total).is_err());
+
+        // nobody can receive dust (P2WPKH outputs are dust below 294 sats), not even the remainder
+        let err = recipient_outputs(&[recipient(alice, Some(800)), recipient(bob, None)], total)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("the output to {bob} of 200 sats is below the dust limit (294 sats)")
+        );
+        assert!(Recipient::from_str(&format!("{alice}:293")).is_err());
+
+        // no duplicates, and at most one remainder
+        assert!(
+            recipient_outputs(&[recipient(alice, None), recipient(alice, None)], total).is_err()
+        );
+        assert!(recipient_outputs(&[], total).is_err());
+
+        // unless nothing is withdrawn
+        assert!(recipient_outputs(&[], Amount::ZERO).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_recipient_display_roundtrip() {
+        let alice = "tb1q6nkpv2j9lxrm6h3w4skrny3thswgdcca8cx9k6";
+        for s in [alice.to_string(), format!("{alice}:300")] {
+            assert_eq!(Recipient::from_str(&s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_unsigned_spend() {
+        let alice = "tb1q6nkpv2j9lxrm6h3w4skrny3thswgdcca8cx9k6";
+        let mut smart_contract = SmartContract {
+            txid: Txid::from_str(
+                "e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836",
+            )
+            .unwrap(),
+            locked_value: Amount::from_sat(10_000),
+            vk_hash: [0; 32],
+            state: None,
+            metadata_hash: None,
+            admin: None,
+            timelock: None,
+            recovery: None,
+            deployer: None,
+            vout_of_zkbitcoin_utxo: 1,
+        };
+
+        // stateless: the fee, then everything to the recipients
+        let (tx, recipients) = unsigned_spend(
+            &smart_contract,
+            &[recipient(alice, None)],
+            None,
+            Amount::ZERO,
+            Amount::ZERO,
+            &FeeSchedule::default(),
+        )
+        .unwrap();
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(
+            tx.input[0].previous_output,
+            OutPoint::new(smart_contract.txid, 1)
+        );
+        assert!(tx.input[0].sequence.is_rbf());
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[0].value, protocol_config().fee());
+        assert_eq!(recipients, tx.output[1..]);
+        assert_eq!(recipients[0].value, Amount::from_sat(10_000));
+
+        // no fee output when the fee is paid over Lightning
+        let (tx, recipients) = unsigned_spend(
+            &smart_contract,
+            &[recipient(alice, None)],
+            None,
+            Amount::ZERO,
+            Amount::ZERO,
+            &FeeSchedule::Fixed { sats: 0 },
+        )
+        .unwrap();
+        assert_eq!(tx.output, recipients);
+
+        // stateful: the fee (on what's withdrawn), the updated zkapp and its state, then what's withdrawn to the recipients
+        let percentage = FeeSchedule::Percentage {
+            basis_points: 2_000,
+            min_sats: 330,
+        };
+        smart_contract.state = Some("1".to_string());
+        smart_contract.metadata_hash = Some([3; 16]);
+        smart_contract.admin = Some("42".to_string());
+        smart_contract.timelock = Some(Timelock::Relative(144));
+        let recovery: Recovery = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+            .parse()
+            .unwrap();
+        smart_contract.recovery = Some(recovery);
+        let (tx, recipients) = unsigned_spend(
+            &smart_contract,
+            &[recipient(alice, None)],
+            Some("2"),
+            Amount::from_sat(500),
+            Amount::from_sat(2_000),
+            &percentage,
+        )
+        .unwrap();
+        assert_eq!(tx.output.len(), 4);
+        assert_eq!(tx.output[0].value, Amount::from_sat(400));
+        assert_eq!(tx.output[1].value, Amount::from_sat(8_500));
+        assert!(tx.output[2].script_pubkey.is_op_return());
+        assert_eq!(recipients[0].value, Amount::from_sat(2_000));
+        let new_zkapp = extract_smart_contract_from_tx(&tx).unwrap();
+        assert_eq!(new_zkapp.state.as_deref(), Some("2"));
+        assert_eq!(new_zkapp.metadata_hash, Some([3; 16]));
+        assert_eq!(new_zkapp.admin.as_deref(), Some("42"));
+
+        // and the timelock, which the transaction honors
+        assert_eq!(new_zkapp.timelock, Some(Timelock::Relative(144)));
+        assert_eq!(tx.input[0].sequence, Sequence::from_consensus(144));
+        Timelock::Relative(144).check(&tx, 0).unwrap();
+
+        // and the recovery, whose output the funds stay locked in
+        assert_eq!(new_zkapp.recovery, Some(recovery));
+        assert_eq!(
+            tx.output[1].script_pubkey,
+            recovery.script_pubkey().unwrap()
+        );
+
+        // the zkapp can't be overdrawn, and stateful zkapps need a new state
+        assert!(unsigned_spend(
+            &smart_contract,
+            &[recipient(alice, None)],
+            Some("2"),
+            Amount::ZERO,
+            Amount::from_sat(20_000),
+            &percentage,
+        )
+        .is_err());
+        assert!(unsigned_spend(
+            &smart_contract,
+            &[recipient(alice, None)],
+            None,
+            Amount::ZERO,
+            Amount::ZERO,
+            &FeeSchedule::default(),
+        )
+        .is_err());
+
+        // nor drained down to dust, or charged a dust fee
+        assert!(unsigned_spend(
+            &smart_contract,
+            &[recipient(alice, None)],
+            Some("2"),
+            Amount::ZERO,
+            Amount::from_sat(9_800),
+            &percentage,
+        )
+        .is_err());
+        assert!(unsigned_spend(
+            &smart_contract,
+            &[recipient(alice, None)],
+            Some("2"),
+            Amount::ZERO,
+            Amount::from_sat(2_000),
+            &FeeSchedule::Fixed { sats: 100 },
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_pay_priority_fee() {
+        let alice = "tb1q6nkpv2j9lxrm6h3w4skrny3thswgdcca8cx9k6";
+        let smart_contract = SmartContract {
+            txid: Txid::from_str(
+                "e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836",
+            )
+            .unwrap(),
+            locked_value: Amount::from_sat(10_000),
+            vk_hash: [0; 32],
+            state: None,
+            metadata_hash: None,
+            admin: None,
+            timelock: None,
+            recovery: None,
+            deployer: None,
+            vout_of_zkbitcoin_utxo: 1,
+        };
+        let spend = |fee_schedule| {
+            unsigned_spend(
+                &smart_contract,
+                &[recipient(alice, None)],
+                None,
+                Amount::ZERO,
+                Amount::ZERO,
+                &fee_schedule,
+            )
+            .unwrap()
+            .0
+        };
+
+        // the priority fee goes to the fee output
+        let tx = pay_priority_fee(spend(FeeSchedule::default()), Amount::from_sat(5_000)).unwrap();
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(
+            tx.output[0].value,
+            protocol_config().fee() + Amount::from_sat(5_000)
+        );
+
+        // which is added if the fee is paid over Lightning
+        let tx = pay_priority_fee(
+            spend(FeeSchedule::Fixed { sats: 0 }),
+            Amount::from_sat(5_000),
+        )
+        .unwrap();
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[0].value, Amount::from_sat(5_000));
+        assert!(
+            pay_priority_fee(spend(FeeSchedule::Fixed { sats: 0 }), Amount::from_sat(1)).is_err()
+        );
+    }
+
+    #[test]
+    fn test_unsigned_upgrade() {
+        let smart_contract = SmartContract {
+            txid: Txid::from_str(
+                "e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836",
+            )
+            .unwrap(),
+            locked_value: Amount::from_sat(10_000),
+            vk_hash: [0; 32],
+            state: Some("1".to_string()),
+            metadata_hash: Some([3; 16]),
+            admin: None,
+            timelock: None,
+            recovery: None,
+            deployer: None,
+            vout_of_zkbitcoin_utxo: 1,
+        };
+        let new_zkapp = ZkappData::new([1; 32], Some("2".to_string()));
+
+        // the fee (on nothing withdrawn), then the upgraded zkapp with all the funds, and its new vk and state
+        let fee_schedule = FeeSchedule::Fixed { sats: 1_000 };
+        let tx = unsigned_upgrade(&smart_contract, &new_zkapp, &fee_schedule).unwrap();
+        assert_eq!(
+            tx.input[0].previous_output,
+            OutPoint::new(smart_contract.txid, 1)
+        );
+        assert_eq!(tx.output.len(), 3);
+        assert_eq!(tx.output[0].value, Amount::from_sat(1_000));
+        assert_eq!(tx.output[1].value, Amount::from_sat(10_000));
+        let upgraded = extract_smart_contract_from_tx(&tx).unwrap();
+        assert_eq!(upgraded.vk_hash, [1; 32]);
+        assert_eq!(upgraded.state.as_deref(), Some("2"));
+        assert_eq!(upgraded.metadata_hash, None);
+
+        // the committee signs it for a proof upgrading the zkapp to that vk (and nothing else)
+        let update = |upgrade_to: Option<String>| Update {
+            new_state: "2".to_string(),
+            prev_state: "1".to_string(),
+            truncated_txid: None,
+            amount_out: "0".to_string(),
+            amount_in: "0".to_string(),
+            upgrade_to,
+        };
+        let upgrade = update(Some(truncate_vk_hash(&[1; 32])));
+        assert!(upgrade.upgrades());
+        BobRequest::validate_transaction(&tx, &smart_contract, Some(&upgrade), &[]).unwrap();
+        let other = update(Some(truncate_vk_hash(&[2; 32])));
+        assert!(BobRequest::validate_transaction(&tx, &smart_contract, Some(&other), &[]).is_err());
+        for no_upgrade in [update(None), update(Some(NO_UPGRADE.to_string()))] {
+            assert!(!no_upgrade.upgrades());
+            assert!(
+                BobRequest::validate_transaction(&tx, &smart_contract, Some(&no_upgrade), &[])
+                    .is_err()
+            );
+        }
+
+        // only stateful zkapps are upgraded, to another vk
+        let stateless = ZkappData::new([1; 32], None);
+        assert!(unsigned_upgrade(&smart_contract, &stateless, &fee_schedule).is_err());
+        let same = ZkappData::new([0; 32], Some("2".to_string()));
+        assert!(unsigned_upgrade(&smart_contract, &same, &fee_schedule).is_err());
+
+        // and keep their administrator
+        let administered = SmartContract {
+            admin: Some("42".to_string()),
+            ..smart_contract.clone()
+        };
+        assert!(unsigned_upgrade(&administered, &new_zkapp, &fee_schedule).is_err());
+        assert!(BobRequest::validate_transaction(&tx, &administered, Some(&upgrade), &[]).is_err());
+        let new_zkapp = ZkappData {
+            admin: administered.admin.clone(),
+            ..new_zkapp
+        };
+        let tx = unsigned_upgrade(&administered, &new_zkapp, &fee_schedule).unwrap();
+        BobRequest::validate_transaction(&tx, &administered, Some(&upgrade), &[]).unwrap();
+    }
 }
@@ -0,0 +1,480 @@
+//! Access to the Bitcoin chain, through Bitcoin Core or a lighter backend.
+//!
+//! Using a zkapp only requires fetching transactions and broadcasting the unlocking transaction,
+//! which public Esplora instances (e.g. blockstream.info) or Electrum servers can do.
+//! This way, Bob doesn't need a node that indexes (or holds in its wallet) the zkapps being used.
+
+use std::{str::FromStr, time::Duration};
+
+use anyhow::{bail, ensure, Context, Result};
+use async_trait::async_trait;
+use bitcoin::{
+    hashes::{sha256, Hash},
+    Script, Transaction, Txid,
+};
+use log::{debug, warn};
+use serde::{de::DeserializeOwned, Deserialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::{
+    get_network,
+    json_rpc_stuff::{
+        connect_tcp, get_raw_transaction_info, get_transaction, http_client, send_raw_transaction,
+        RpcCtx, TransactionOrHex,
+    },
+};
+
+//
+// Constants
+//
+
+/// Timeout (in seconds) for requests to Esplora and Electrum servers.
+const BACKEND_TIMEOUT: u64 = 10;
+
+/// Interval (in seconds) at which the chain is polled when waiting for a transaction to be confirmed.
+pub const CONFIRMATION_POLL_INTERVAL: u64 = 10;
+
+/// How many polls in a row can fail to fetch a transaction being waited on, before it's considered dropped
+/// from the mempool (e.g. evicted, or replaced by another transaction).
+pub const MAX_MISSING_POLLS: usize = 30;
+
+/// The Esplora API of blockstream.info for mainnet.
+const BLOCKSTREAM_MAINNET: &str = "https://blockstream.info/api";
+
+/// The Esplora API of blockstream.info for testnet.
+const BLOCKSTREAM_TESTNET: &str = "https://blockstream.info/testnet/api";
+
+//
+// Interface
+//
+
+/// What zkBitcoin needs from the chain.
+#[async_trait]
+pub trait ChainBackend: Send + Sync {
+    /// Fetches a transaction, along with its number of confirmations (0 if it is unconfirmed).
+    async fn get_transaction(&self, txid: Txid) -> Result<(Transaction, usize)>;
+
+    /// Broadcasts a signed transaction to the network.
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid>;
+}
+
+/// Polls the chain until a transaction is buried `confirmations` blocks deep,
+/// calling `progress` with its number of confirmations whenever it changes.
+/// Errors (e.g. a backend that doesn't know about a transaction that was just broadcast) are retried,
+/// until the transaction couldn't be fetched [MAX_MISSING_POLLS] times in a row: it is then likely gone from the mempool
+/// (evicted, or replaced by another transaction spending the same inputs), and an error is returned.
+pub async fn wait_for_confirmations(
+    chain: &dyn ChainBackend,
+    txid: Txid,
+    confirmations: usize,
+    poll_interval: Duration,
+    mut progress: impl FnMut(usize),
+) -> Result<()> {
+    let mut last = None;
+    let mut missing = 0;
+    loop {
+        match chain.get_transaction(txid).await {
+            Ok((_, current)) => {
+                missing = 0;
+                if last != Some(current) {
+                    progress(current);
+                    last = Some(current);
+                }
+                if current >= confirmations {
+                    return Ok(());
+                }
+            }
+            Err(err) => {
+                missing += 1;
+                if missing >= MAX_MISSING_POLLS {
+                    return Err(err.context(format!(
+                        "{txid} was likely dropped from the mempool or replaced (it couldn't be fetched {missing} times in a row)"
+                    )));
+                }
+                warn!("- couldn't fetch transaction {txid}: {err}");
+            }
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+/// The kinds of backends available.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BackendKind {
+    /// The Bitcoin Core node (see `RPC_ADDRESS`).
+    #[default]
+    Core,
+
+    /// An Esplora HTTP API (e.g. blockstream.info).
+    Esplora,
+
+    /// An Electrum server.
+    Electrum,
+}
+
+impl FromStr for BackendKind {
+    type Err = anyhow::Error;
+
+    /// Parses the name of a backend (`core`, `esplora`, or `electrum`, in any case).
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "core" => Ok(BackendKind::Core),
+            "esplora" => Ok(BackendKind::Esplora),
+            "electrum" => Ok(BackendKind::Electrum),
+            _ => bail!("unknown backend {s} (expected core, esplora, or electrum)"),
+        }
+    }
+}
+
+impl BackendKind {
+    /// Creates the backend.
+    /// `url` is the URL of the Esplora API (defaults to blockstream.info), or the `host:port` of the Electrum server.
+    pub fn connect(self, url: Option<&str>, rpc_ctx: &RpcCtx) -> Result<Box<dyn ChainBackend>> {
+        let backend: Box<dyn ChainBackend> = match self {
+            BackendKind::Core => Box::new(rpc_ctx.clone()),
+            BackendKind::Esplora => Box::new(EsploraBackend::new(url)),
+            BackendKind::Electrum => {
+                let address = url.context("the address of the Electrum server is required")?;
+                Box::new(ElectrumBackend::new(address))
+            }
+        };
+        Ok(backend)
+    }
+}
+
+//
+// Bitcoin Core
+//
+
+#[async_trait]
+impl ChainBackend for RpcCtx {
+    async fn get_transaction(&self, txid: Txid) -> Result<(Transaction, usize)> {
+        // the wallet knows about its own transactions, even without a transaction index
+        match get_transaction(self, txid).await {
+            Ok((_, tx, confirmations)) => Ok((tx, confirmations)),
+            Err(err) => {
+                debug!("- gettransaction failed ({err}), trying getrawtransaction");
+                get_raw_transaction_info(self, txid).await.with_context(|| {
+                    format!(
+                        "couldn't find transaction {txid} (is the node running with -txindex=1?)"
+                    )
+                })
+            }
+        }
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        send_raw_transaction(self, TransactionOrHex::Transaction(tx)).await
+    }
+}
+
+//
+// Esplora
+//
+
+/// An Esplora HTTP API (see https://github.com/Blockstream/esplora/blob/master/API.md).
+pub struct EsploraBackend {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct EsploraTxStatus {
+    confirmed: bool,
+    block_height: Option<u64>,
+}
+
+impl EsploraBackend {
+    /// Creates a client of the Esplora API at `url`, or of blockstream.info for the current network.
+    pub fn new(url: Option<&str>) -> Self {
+        let url = url.unwrap_or(match get_network() {
+            bitcoin::Network::Bitcoin => BLOCKSTREAM_MAINNET,
+            _ => BLOCKSTREAM_TESTNET,
+        });
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Returns the body of a response, or an error containing it if the request failed.
+    async fn text(&self, request: reqwest::RequestBuilder, what: &str) -> Result<String> {
+        let response = request
+            .timeout(Duration::from_secs(BACKEND_TIMEOUT))
+            .send()
+            .await
+            .with_context(|| format!("couldn't reach Esplora at {}", self.url))?;
+        let status = response.status();
+        let body = response.text().await?;
+        ensure!(status.is_success(), "{what} failed ({status}): {body}");
+        Ok(body)
+    }
+
+    async fn get(&self, path: &str) -> Result<String> {
+        let url = format!("{}{path}", self.url);
+        debug!("- GET {url}");
+        self.text(http_client().get(&url), &url).await
+    }
+}
+
+#[async_trait]
+impl ChainBackend for EsploraBackend {
+    async fn get_transaction(&self, txid: Txid) -> Result<(Transaction, usize)> {
+        let tx_hex = self.get(&format!("/tx/{txid}/hex")).await?;
+        let tx: Transaction =
+            bitcoin::consensus::encode::deserialize(&hex::decode(tx_hex.trim())?)?;
+        // the server isn't trusted to return the transaction asked for
+        ensure!(
+            tx.txid() == txid,
+            "the Esplora API returned another transaction ({}) for {txid}",
+            tx.txid()
+        );
+
+        let status: EsploraTxStatus =
+            serde_json::from_str(&self.get(&format!("/tx/{txid}/status")).await?)?;
+        let confirmations = match (status.confirmed, status.block_height) {
+            (true, Some(height)) => {
+                let tip: u64 = self.get("/blocks/tip/height").await?.trim().parse()?;
+                (tip + 1).saturating_sub(height) as usize
+            }
+            _ => 0,
+        };
+
+        Ok((tx, confirmations))
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let tx_hex = bitcoin::consensus::encode::serialize_hex(tx);
+        let request = http_client().post(format!("{}/tx", self.url)).body(tx_hex);
+        let txid = self.text(request, "broadcast").await?;
+        Ok(txid.trim().parse()?)
+    }
+}
+
+//
+// Electrum
+//
+
+/// An Electrum server (see https://electrumx.readthedocs.io/en/latest/protocol.html).
+/// Only plain TCP connections are supported (through the proxy, if one is set).
+pub struct ElectrumBackend {
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct ElectrumHistoryEntry {
+    tx_hash: Txid,
+    height: i64,
+}
+
+#[derive(Deserialize)]
+struct ElectrumHeader {
+    height: u64,
+}
+
+impl ElectrumBackend {
+    /// Creates a client of the Electrum server at `host:port`.
+    pub fn new(address: &str) -> Self {
+        let address = address.trim_start_matches("tcp://").to_string();
+        Self { address }
+    }
+
+    /// Sends a request to the Electrum server, and returns its result.
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        params: serde_json::Value,
+    ) -> Result<T> {
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "method": method,
+            "params": params,
+        });
+        debug!("- sending request to Electrum server: {request}");
+
+        let exchange = async {
+            let mut stream = connect_tcp(&self.address).await?;
+            stream.write_all(format!("{request}\n").as_bytes()).await?;
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).await?;
+            anyhow::Ok(line)
+        };
+        let response = tokio::time::timeout(Duration::from_secs(BACKEND_TIMEOUT), exchange)
+            .await
+            .context("the Electrum server timed out")?
+            .with_context(|| format!("couldn't reach the Electrum server at {}", self.address))?;
+
+        let response: serde_json::Value =
+            serde_json::from_str(&response).context("couldn't parse the Electrum response")?;
+        if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+            bail!("{method} error: {error}");
+        }
+        Ok(serde_json::from_value(response["result"].clone())?)
+    }
+}
+
+/// Returns the Electrum script hash of a script (its reversed SHA-256 digest, in hex).
+fn electrum_script_hash(script: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script.as_bytes()).to_byte_array();
+    hash.reverse();
+    hex::encode(hash)
+}
+
+#[async_trait]
+impl ChainBackend for ElectrumBackend {
+    async fn get_transaction(&self, txid: Txid) -> Result<(Transaction, usize)> {
+        let tx_hex: String = self
+            .request(
+                "blockchain.transaction.get",
+                serde_json::json!([txid, false]),
+            )
+            .await?;
+        let tx: Transaction = bitcoin::consensus::encode::deserialize(&hex::decode(tx_hex)?)?;
+        // the server isn't trusted to return the transaction asked for
+        ensure!(
+            tx.txid() == txid,
+            "the Electrum server returned another transaction ({}) for {txid}",
+            tx.txid()
+        );
+
+        // Electrum servers only know the height of a transaction through the history of its scripts
+        let script = tx
+            .output
+            .iter()
+            .map(|output| &output.script_pubkey)
+            .find(|script| !script.is_op_return())
+            .context("the transaction has no output to look it up with")?;
+        let history: Vec<ElectrumHistoryEntry> = self
+            .request(
+                "blockchain.scripthash.get_history",
+                serde_json::json!([electrum_script_hash(script)]),
+            )
+            .await?;
+        let height = history
+            .iter()
+            .find(|entry| entry.tx_hash == txid)
+            .map_or(0, |entry| entry.height);
+
+        // a height of 0 or -1 means that the transaction is in the mempool
+        let confirmations = if height > 0 {
+            let tip: ElectrumHeader = self
+                .request("blockchain.headers.subscribe", serde_json::json!([]))
+                .await?;
+            (tip.height + 1).saturating_sub(height as u64) as usize
+        } else {
+            0
+        };
+
+        Ok((tx, confirmations))
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        let tx_hex = bitcoin::consensus::encode::serialize_hex(tx);
+        self.request(
+            "blockchain.transaction.broadcast",
+            serde_json::json!([tx_hex]),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use bitcoin::{absolute::LockTime, transaction, ScriptBuf};
+
+    use super::*;
+
+    /// A chain on which a transaction gets one more confirmation every time it's fetched.
+    struct MiningChain {
+        confirmations: Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl ChainBackend for MiningChain {
+        async fn get_transaction(&self, _txid: Txid) -> Result<(Transaction, usize)> {
+            let mut confirmations = self.confirmations.lock().unwrap();
+            let tx = Transaction {
+                version: transaction::Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: vec![],
+                output: vec![],
+            };
+            let res = (tx, *confirmations);
+            *confirmations += 1;
+            Ok(res)
+        }
+
+        async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+            Ok(tx.txid())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_confirmations() {
+        let chain = MiningChain {
+            confirmations: Mutex::new(0),
+        };
+        let mut seen = vec![];
+        wait_for_confirmations(
+            &chain,
+            Txid::all_zeros(),
+            3,
+            Duration::ZERO,
+            |confirmations| seen.push(confirmations),
+        )
+        .await
+        .unwrap();
+        assert_eq!(seen, vec![0, 1, 2, 3]);
+    }
+
+    /// A chain that dropped every transaction.
+    struct EmptyChain;
+
+    #[async_trait]
+    impl ChainBackend for EmptyChain {
+        async fn get_transaction(&self, txid: Txid) -> Result<(Transaction, usize)> {
+            bail!("no such transaction {txid}")
+        }
+
+        async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+            Ok(tx.txid())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dropped_transaction() {
+        let mut seen = vec![];
+        let res = wait_for_confirmations(
+            &EmptyChain,
+            Txid::all_zeros(),
+            1,
+            Duration::ZERO,
+            |confirmations| seen.push(confirmations),
+        )
+        .await;
+        assert!(res.is_err());
+        assert!(seen.is_empty());
+    }
+
+    #[test]
+    fn test_electrum_script_hash() {
+        // the example of the Electrum protocol documentation
+        let script =
+            ScriptBuf::from_hex("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac").unwrap();
+        assert_eq!(
+            electrum_script_hash(&script),
+            "8b01df4e368ea28f8dc0423bcf7a4923e3a12d307c875e47a0cfbf90b5c39161"
+        );
+    }
+
+    #[test]
+    fn test_parse_backend_kind() {
+        assert_eq!(BackendKind::from_str("core").unwrap(), BackendKind::Core);
+        assert_eq!(
+            BackendKind::from_str("Esplora").unwrap(),
+            BackendKind::Esplora
+        );
+        assert!(BackendKind::from_str("bitcoind").is_err());
+    }
+}
@@ -0,0 +1,508 @@
+//! A typed client for the JSON RPC APIs of orchestrators and committee nodes,
+//! so that wallets can integrate with zkBitcoin without reimplementing its requests.
+//!
+//! All the types exchanged with the orchestrator and the committee are re-exported here.
+//! The orchestrator also describes its API with an OpenAPI document (see [OPENAPI]),
+//! served at `GET /openapi.json` and by its `openapi` method.
+//!
+//! ```no_run
+//! # async fn example(bob_request: zkbitcoin::client::BobRequest) -> anyhow::Result<()> {
+//! use zkbitcoin::client::OrchestratorClient;
+//!
+//! let orchestrator = OrchestratorClient::new("http://127.0.0.1:8888");
+//! println!("{:?}", orchestrator.info().await?);
+//! let bob_response = orchestrator.unlock_funds(&bob_request).await?;
+//! println!("signed {}", bob_response.unlocked_tx.txid());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bitcoin::{Amount, Txid};
+use log::debug;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde_json::value::{to_raw_value, RawValue};
+
+use crate::json_rpc_stuff::{json_rpc_request, json_rpc_request_with_client, proxy, RpcCtx};
+
+pub use crate::{
+    bob_request::{BobRequest, BobResponse, Update},
+    committee::{
+        anomaly::{Anomaly, AnomalyAction, AnomalyPolicy, HeldRequest},
+        attestation::{AttestationRequest, SignedAttestation},
+        events::{Event, EventKind},
+        governance::{CommitteePolicy, Proposal, RatifiedProposal},
+        heartbeat::{CommitteeStatus, MemberStatus},
+        misbehavior::{Evidence, MisbehaviorKind, QuarantinedMember, SignedEvidence},
+        node::{NodeInfo, Round1Response, Round2Request, Round2Response},
+        orchestrator::{CommitteeConfig, Member, OrchestratorInfo},
+        policy::{ZkappPolicy, ZkappRegistration},
+        sweep::{EmergencySweep, SweepRequest},
+        transparency::{InclusionProof, LogLeaf, SignedTreeHead},
+    },
+    config::{FeeQuote, FeeSchedule, FeeTier, LightningInvoice},
+    watchtower::{Alert, Spend},
+};
+
+//
+// Constants
+//
+
+/// The OpenAPI description of the orchestrator's API.
+pub const OPENAPI: &str = include_str!("openapi.json");
+
+/// Timeout (in seconds) of requests to committee members.
+const MEMBER_TIMEOUT: u64 = 10;
+
+/// Interval (in seconds) at which idle connections to committee members are health-checked.
+const MEMBER_KEEP_ALIVE_INTERVAL: u64 = 15;
+
+/// Sends a JSON RPC 2.0 request, and parses its result.
+/// Without a `client`, the request goes through the shared one (see [json_rpc_request]), and is retried if needed.
+async fn call<T: DeserializeOwned>(
+    client: Option<&Client>,
+    address: &str,
+    method: &'static str,
+    params: &[Box<RawValue>],
+) -> Result<T> {
+    call_as(client, None, address, method, params).await
+}
+
+/// Same as [call], authenticated with `user:password` if `auth` is given (see [crate::committee::admin]).
+async fn call_as<T: DeserializeOwned>(
+    client: Option<&Client>,
+    auth: Option<&str>,
+    address: &str,
+    method: &'static str,
+    params: &[Box<RawValue>],
+) -> Result<T> {
+    let rpc_ctx = RpcCtx {
+        version: Some("2.0"),
+        wallet: None,
+        address: Some(address.to_string()),
+        auth: auth.map(str::to_string),
+        cookie_file: None,
+    };
+    let resp = match client {
+        Some(client) => json_rpc_request_with_client(client, &rpc_ctx, method, params).await,
+        None => json_rpc_request(&rpc_ctx, method, params).await,
+    }
+    .with_context(|| format!("couldn't send {method} request to {address}"))?;
+    debug!("- {method} response from {address}: {resp}");
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&resp)
+        .with_context(|| format!("couldn't deserialize {method} response"))?;
+    response
+        .result()
+        .with_context(|| format!("{method} request failed"))
+}
+
+//
+// Orchestrator
+//
+
+/// A client to an orchestrator.
+#[derive(Debug, Clone)]
+pub struct OrchestratorClient {
+    address: String,
+    admin_credentials: Option<String>,
+}
+
+impl OrchestratorClient {
+    /// Creates a client to the orchestrator at `address` (e.g. `http://127.0.0.1:8888`).
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            admin_credentials: None,
+        }
+    }
+
+    /// Authenticates the requests of the client with the `user:password` of the admin API of the orchestrator
+    /// (see [crate::committee::admin]), which `address` must then be the address of.
+    pub fn with_admin_credentials(self, credentials: impl Into<String>) -> Self {
+        Self {
+            admin_credentials: Some(credentials.into()),
+            ..self
+        }
+    }
+
+    /// Sends a request to the admin API of the orchestrator.
+    async fn admin_call<T: DeserializeOwned>(
+        &self,
+        method: &'static str,
+        params: &[Box<RawValue>],
+    ) -> Result<T> {
+        let credentials = self
+            .admin_credentials
+            .as_deref()
+            .context("the admin API of the orchestrator needs credentials")?;
+        call_as(None, Some(credentials), &self.address, method, params).await
+    }
+
+    /// Asks the orchestrator about itself.
+    pub async fn info(&self) -> Result<OrchestratorInfo> {
+        call(None, &self.address, "orchestrator_info", &[]).await
+    }
+
+    /// Has the committee sign the spend of a zkapp.
+    pub async fn unlock_funds(&self, bob_request: &BobRequest) -> Result<BobResponse> {
+        call(
+            None,
+            &self.address,
+            "unlock_funds",
+            &[to_raw_value(bob_request)?],
+        )
+        .await
+    }
+
+    /// Has the committee sign the spend of several zkapps in the same transaction.
+    /// See [crate::committee::orchestrator::Orchestrator::handle_batch] for the constraints on the batch.
+    pub async fn unlock_funds_batch(&self, bob_requests: &[BobRequest]) -> Result<BobResponse> {
+        call(
+            None,
+            &self.address,
+            "unlock_funds_batch",
+            &[to_raw_value(bob_requests)?],
+        )
+        .await
+    }
+
+    /// Asks for the fee the committee would take when `amount` is withdrawn to the recipients of a zkapp,
+    /// along with an invoice to pay it over Lightning if `lightning` is set.
+    pub async fn fee_quote(&self, amount: Amount, lightning: bool) -> Result<FeeQuote> {
+        call(
+            None,
+            &self.address,
+            "fee_quote",
+            &[to_raw_value(&amount.to_sat())?, to_raw_value(&lightning)?],
+        )
+        .await
+    }
+
+    /// Registers the policy of a zkapp, and returns its verifier key hash.
+    pub async fn register_zkapp(&self, registration: &ZkappRegistration) -> Result<String> {
+        call(
+            None,
+            &self.address,
+            "register_zkapp",
+            &[to_raw_value(registration)?],
+        )
+        .await
+    }
+
+    /// Asks the orchestrator about the health of the committee (admin API).
+    pub async fn committee_status(&self) -> Result<CommitteeStatus> {
+        self.admin_call("committee_status", &[]).await
+    }
+
+    /// Fetches the evidence of misbehavior of committee members recorded by the orchestrator
+    /// (which should be checked with [SignedEvidence::verify]).
+    pub async fn misbehavior_evidence(&self) -> Result<Vec<SignedEvidence>> {
+        call(None, &self.address, "misbehavior_evidence", &[]).await
+    }
+
+    /// Asks the orchestrator which committee members it quarantined.
+    pub async fn quarantined_members(&self) -> Result<Vec<QuarantinedMember>> {
+        call(None, &self.address, "quarantined_members", &[]).await
+    }
+
+    /// Has the orchestrator release a quarantined committee member (admin API).
+    pub async fn release_member(&self, member_id: frost_secp256k1_tr::Identifier) -> Result<()> {
+        self.admin_call("release_member", &[to_raw_value(&member_id)?])
+            .await
+    }
+
+    /// Sends an alert of a watchtower to the orchestrator, to halt it (see [crate::watchtower]).
+    pub async fn halt(&self, alert: &Alert) -> Result<()> {
+        call(None, &self.address, "halt", &[to_raw_value(alert)?]).await
+    }
+
+    /// Asks the orchestrator which anomalous requests it holds until an operator approves them (admin API).
+    pub async fn held_requests(&self) -> Result<Vec<HeldRequest>> {
+        self.admin_call("held_requests", &[]).await
+    }
+
+    /// Has the orchestrator approve a held request (by hex-encoded hash), so that it's signed the next time it's sent
+    /// (admin API).
+    pub async fn approve_request(&self, request_hash: &str) -> Result<HeldRequest> {
+        self.admin_call("approve_request", &[to_raw_value(request_hash)?])
+            .await
+    }
+
+    /// Has the orchestrator reload its committee configuration, and returns the new one (admin API).
+    pub async fn reload_committee_cfg(&self) -> Result<CommitteeConfig> {
+        self.admin_call("reload_committee_cfg", &[]).await
+    }
+
+    /// Fetches the current head of the orchestrator's transparency log.
+    pub async fn transparency_tree_head(&self) -> Result<SignedTreeHead> {
+        call(None, &self.address, "transparency_tree_head", &[]).await
+    }
+
+    /// Asks the orchestrator for a proof that the signature of input `zkapp_input` of `txid` is in its transparency log
+    /// (which should be checked with [InclusionProof::verify]).
+    pub async fn inclusion_proof(&self, txid: Txid, zkapp_input: usize) -> Result<InclusionProof> {
+        call(
+            None,
+            &self.address,
+            "inclusion_proof",
+            &[to_raw_value(&txid)?, to_raw_value(&zkapp_input)?],
+        )
+        .await
+    }
+
+    /// Has the committee attest to `data` (see [crate::committee::attestation]) (admin API).
+    /// The attestation should be checked with [SignedAttestation::verify].
+    pub async fn attest(&self, data: &[u8]) -> Result<SignedAttestation> {
+        self.admin_call("attest", &[to_raw_value(&AttestationRequest::new(data))?])
+            .await
+    }
+
+    /// Puts a proposal to the vote of the committee, and returns it once ratified (see [crate::committee::governance]).
+    pub async fn ratify_proposal(&self, proposal: &Proposal) -> Result<RatifiedProposal> {
+        call(
+            None,
+            &self.address,
+            "ratify_proposal",
+            &[to_raw_value(proposal)?],
+        )
+        .await
+    }
+
+    /// Fetches the proposals ratified by the committee (which should be checked with [RatifiedProposal::verify]).
+    pub async fn ratified_proposals(&self) -> Result<Vec<RatifiedProposal>> {
+        call(None, &self.address, "ratified_proposals", &[]).await
+    }
+
+    /// Fetches the OpenAPI description of the orchestrator's API.
+    pub async fn openapi(&self) -> Result<serde_json::Value> {
+        call(None, &self.address, "openapi", &[]).await
+    }
+}
+
+//
+// Committee node
+//
+
+/// What the orchestrator needs from a committee member:
+/// a [NodeClient] to a remote node, or a node running in-process (see [crate::committee::node::NodeState]).
+#[async_trait]
+pub trait CommitteeMember: Send + Sync {
+    /// Has the member validate a request, and commit to nonces for it.
+    async fn round_1_signing(&self, bob_request: &BobRequest) -> Result<Round1Response>;
+
+    /// Has the member produce a signature share for a request it committed to in round 1.
+    async fn round_2_signing(&self, round2_request: &Round2Request) -> Result<Round2Response>;
+
+    /// Has the member check that its operator approved an emergency sweep, and commit to nonces to sign one of its inputs.
+    async fn sweep_round_1_signing(&self, sweep_request: &SweepRequest) -> Result<Round1Response>;
+
+    /// Has the member produce a signature share for an input of a sweep it committed to in round 1.
+    async fn sweep_round_2_signing(&self, round2_request: &Round2Request)
+        -> Result<Round2Response>;
+
+    /// Has the member commit to nonces to attest to some data.
+    async fn attest_round_1_signing(
+        &self,
+        attestation_request: &AttestationRequest,
+    ) -> Result<Round1Response>;
+
+    /// Has the member produce a signature share of data it committed to attest to
+    /// (or of a proposal it committed to vote for) in round 1.
+    async fn attest_round_2_signing(
+        &self,
+        round2_request: &Round2Request,
+    ) -> Result<Round2Response>;
+
+    /// Has the member check that its operator voted for a proposal, and commit to nonces to sign it.
+    async fn vote_round_1_signing(&self, proposal: &Proposal) -> Result<Round1Response>;
+
+    /// Asks the member about itself (which is how the orchestrator checks that it's up).
+    async fn info(&self) -> Result<NodeInfo>;
+}
+
+/// A client to a committee member, which keeps its connections alive across signing sessions.
+/// When the member speaks HTTP/2, the messages of concurrent sessions are multiplexed on a single connection,
+/// which is regularly pinged so that a dead connection is detected before a round is sent over it.
+pub struct NodeClient {
+    address: String,
+    client: Client,
+}
+
+impl NodeClient {
+    pub fn new(member: &Member) -> Result<Self> {
+        let mut builder = Client::builder()
+            .timeout(Duration::from_secs(MEMBER_TIMEOUT))
+            .tcp_keepalive(Duration::from_secs(MEMBER_KEEP_ALIVE_INTERVAL))
+            .tcp_nodelay(true)
+            .pool_idle_timeout(None)
+            .http2_keep_alive_interval(Duration::from_secs(MEMBER_KEEP_ALIVE_INTERVAL))
+            .http2_keep_alive_timeout(Duration::from_secs(MEMBER_TIMEOUT))
+            .http2_keep_alive_while_idle(true);
+        if member.http2 {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(proxy) = proxy() {
+            builder = builder.proxy(proxy.clone());
+        }
+
+        Ok(Self {
+            address: member.address.clone(),
+            client: builder.build()?,
+        })
+    }
+
+    /// Asks the member about itself.
+    pub async fn info(&self) -> Result<NodeInfo> {
+        call(Some(&self.client), &self.address, "node_info", &[]).await
+    }
+
+    /// Has the member validate a request, and commit to nonces for it.
+    pub async fn round_1_signing(&self, bob_request: &BobRequest) -> Result<Round1Response> {
+        call(
+            Some(&self.client),
+            &self.address,
+            "round_1_signing",
+            &[to_raw_value(bob_request)?],
+        )
+        .await
+    }
+
+    /// Has the member produce a signature share for a request it committed to in round 1.
+    pub async fn round_2_signing(&self, round2_request: &Round2Request) -> Result<Round2Response> {
+        call(
+            Some(&self.client),
+            &self.address,
+            "round_2_signing",
+            &[to_raw_value(round2_request)?],
+        )
+        .await
+    }
+
+    /// Has the member check that its operator approved an emergency sweep, and commit to nonces to sign one of its inputs.
+    pub async fn sweep_round_1_signing(
+        &self,
+        sweep_request: &SweepRequest,
+    ) -> Result<Round1Response> {
+        call(
+            Some(&self.client),
+            &self.address,
+            "sweep_round_1_signing",
+            &[to_raw_value(sweep_request)?],
+        )
+        .await
+    }
+
+    /// Has the member produce a signature share for an input of a sweep it committed to in round 1.
+    pub async fn sweep_round_2_signing(
+        &self,
+        round2_request: &Round2Request,
+    ) -> Result<Round2Response> {
+        call(
+            Some(&self.client),
+            &self.address,
+            "sweep_round_2_signing",
+            &[to_raw_value(round2_request)?],
+        )
+        .await
+    }
+
+    /// Has the member commit to nonces to attest to some data.
+    pub async fn attest_round_1_signing(
+        &self,
+        attestation_request: &AttestationRequest,
+    ) -> Result<Round1Response> {
+        call(
+            Some(&self.client),
+            &self.address,
+            "attest_round_1_signing",
+            &[to_raw_value(attestation_request)?],
+        )
+        .await
+    }
+
+    /// Has the member produce a signature share of data it committed to attest to in round 1.
+    pub async fn attest_round_2_signing(
+        &self,
+        round2_request: &Round2Request,
+    ) -> Result<Round2Response> {
+        call(
+            Some(&self.client),
+            &self.address,
+            "attest_round_2_signing",
+            &[to_raw_value(round2_request)?],
+        )
+        .await
+    }
+
+    /// Has the member check that its operator voted for a proposal, and commit to nonces to sign it.
+    pub async fn vote_round_1_signing(&self, proposal: &Proposal) -> Result<Round1Response> {
+        call(
+            Some(&self.client),
+            &self.address,
+            "vote_round_1_signing",
+            &[to_raw_value(proposal)?],
+        )
+        .await
+    }
+}
+
+#[async_trait]
+impl CommitteeMember for NodeClient {
+    async fn round_1_signing(&self, bob_request: &BobRequest) -> Result<Round1Response> {
+        NodeClient::round_1_signing(self, bob_request).await
+    }
+
+    async fn round_2_signing(&self, round2_request: &Round2Request) -> Result<Round2Response> {
+        NodeClient::round_2_signing(self, round2_request).await
+    }
+
+    async fn sweep_round_1_signing(&self, sweep_request: &SweepRequest) -> Result<Round1Response> {
+        NodeClient::sweep_round_1_signing(self, sweep_request).await
+    }
+
+    async fn sweep_round_2_signing(
+        &self,
+        round2_request: &Round2Request,
+    ) -> Result<Round2Response> {
+        NodeClient::sweep_round_2_signing(self, round2_request).await
+    }
+
+    async fn attest_round_1_signing(
+        &self,
+        attestation_request: &AttestationRequest,
+    ) -> Result<Round1Response> {
+        NodeClient::attest_round_1_signing(self, attestation_request).await
+    }
+
+    async fn attest_round_2_signing(
+        &self,
+        round2_request: &Round2Request,
+    ) -> Result<Round2Response> {
+        NodeClient::attest_round_2_signing(self, round2_request).await
+    }
+
+    async fn vote_round_1_signing(&self, proposal: &Proposal) -> Result<Round1Response> {
+        NodeClient::vote_round_1_signing(self, proposal).await
+    }
+
+    async fn info(&self) -> Result<NodeInfo> {
+        NodeClient::info(self).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_is_valid_json() {
+        let openapi: serde_json::Value = serde_json::from_str(OPENAPI).unwrap();
+        assert_eq!(openapi["info"]["title"], "zkBitcoin orchestrator");
+        assert!(openapi["paths"]["/#unlock_funds"].is_object());
+    }
+}
@@ -0,0 +1,689 @@
+//! Coin selection: choosing which of the wallet's UTXOs fund a transaction, and where the change goes.
+//!
+//! By default, we let the Bitcoin Core wallet pick the inputs when funding a transaction.
+//! Users who want more control can pick a strategy implemented here (or the inputs themselves),
+//! in which case the wallet is only asked to compute the fee and the change.
+//! Similarly, the change goes to an address of the wallet's choosing unless told otherwise.
+//! Given the inputs, a fee rate, and a change address, a transaction can also be funded without a wallet at all
+//! (see [Funding::fund_without_wallet]), e.g. when the chain is followed through Esplora or Electrum.
+
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::{
+    consensus::encode::serialize_hex, Address, Amount, FeeRate, OutPoint, ScriptBuf, Sequence,
+    Transaction, TxIn, TxOut, Witness,
+};
+use log::{debug, info};
+
+use crate::{
+    chain::ChainBackend,
+    config::{FeeSchedule, LightningInvoice},
+    get_network,
+    json_rpc_stuff::{fund_raw_transaction, list_unspent, RpcCtx, TransactionOrHex},
+};
+
+//
+// Constants
+//
+
+/// The size (in vbytes) of a transaction without its inputs and outputs.
+const TX_OVERHEAD_VSIZE: u64 = 11;
+
+/// The (upper bound of the) size (in vbytes) of a wallet input, assuming P2WPKH.
+const INPUT_VSIZE: u64 = 68;
+
+/// The (upper bound of the) size (in vbytes) of a change output, assuming P2TR.
+const CHANGE_OUTPUT_VSIZE: u64 = 43;
+
+/// The maximum number of combinations explored by branch-and-bound.
+const BNB_MAX_TRIES: usize = 100_000;
+
+//
+// Data structures
+//
+
+/// A strategy to select the inputs funding a transaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Let the Bitcoin Core wallet decide.
+    #[default]
+    Wallet,
+
+    /// Spend the largest UTXOs first (fewer inputs, consolidates less).
+    LargestFirst,
+
+    /// Look for a set of UTXOs that doesn't need a change output,
+    /// falling back to largest-first if there's none.
+    BranchAndBound,
+}
+
+/// How to select the inputs funding a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoinSelection {
+    /// Use a strategy.
+    Strategy(Strategy),
+
+    /// Use exactly these UTXOs.
+    Manual(Vec<OutPoint>),
+}
+
+impl Default for CoinSelection {
+    fn default() -> Self {
+        Self::Strategy(Strategy::Wallet)
+    }
+}
+
+impl CoinSelection {
+    /// Uses the given UTXOs (as `txid:vout`) if there are any, or the strategy otherwise.
+    pub fn new(strategy: Strategy, inputs: &[String]) -> Result<Self> {
+        if inputs.is_empty() {
+            return Ok(Self::Strategy(strategy));
+        }
+        let outpoints = inputs
+            .iter()
+            .map(|input| {
+                OutPoint::from_str(input).with_context(|| format!("invalid input `{input}`"))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self::Manual(outpoints))
+    }
+}
+
+/// The type of address the wallet generates for change outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeType {
+    Legacy,
+    P2shSegwit,
+    Bech32,
+    Bech32m,
+}
+
+impl ChangeType {
+    /// The name of the address type, as expected by bitcoind.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Legacy => "legacy",
+            Self::P2shSegwit => "p2sh-segwit",
+            Self::Bech32 => "bech32",
+            Self::Bech32m => "bech32m",
+        }
+    }
+}
+
+/// Where the change of a transaction goes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Change {
+    /// To a new address of the wallet, of the wallet's default type.
+    #[default]
+    Wallet,
+
+    /// To a new address of the wallet, of the given type.
+    Type(ChangeType),
+
+    /// To the given address.
+    Address(Address),
+}
+
+impl Change {
+    /// Sends the change to the given address if there's one,
+    /// or to an address of the given type (if any) of the wallet otherwise.
+    pub fn new(address: Option<&str>, change_type: Option<ChangeType>) -> Result<Self> {
+        match (address, change_type) {
+            (Some(_), Some(_)) => bail!("a change address and a change type can't both be given"),
+            (Some(address), None) => {
+                let address = Address::from_str(address)
+                    .context("invalid change address")?
+                    .require_network(get_network())
+                    .context("the change address is not for the current network")?;
+                Ok(Self::Address(address))
+            }
+            (None, Some(change_type)) => Ok(Self::Type(change_type)),
+            (None, None) => Ok(Self::Wallet),
+        }
+    }
+}
+
+/// How to fund a transaction with the wallet.
+#[derive(Debug, Clone, Default)]
+pub struct Funding {
+    /// The fee rate to pay (if not given, the wallet decides).
+    pub fee_rate: Option<FeeRate>,
+
+    /// How to select the inputs.
+    pub coin_selection: CoinSelection,
+
+    /// Where the change goes.
+    pub change: Change,
+
+    /// The fee the committee takes when the transaction spends a zkapp (see [crate::spend::Transport::fee_quote]).
+    pub zkbitcoin_fee: FeeSchedule,
+
+    /// Leave the transaction spending a zkapp unfunded, for a sponsor to pay its fees once signed (see [crate::sponsor]).
+    pub sponsored: bool,
+
+    /// The Lightning invoice paying the fee of the committee instead, in which case the transaction
+    /// has no fee output (see [crate::committee::lightning]).
+    pub fee_invoice: Option<LightningInvoice>,
+
+    /// Paid to the zkBitcoin fund on top of the committee's fee, for the orchestrator to handle the request first
+    /// when it's busy (see [crate::committee::queue]).
+    pub priority_fee: Amount,
+}
+
+/// A UTXO of the wallet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Utxo {
+    pub outpoint: OutPoint,
+    pub value: Amount,
+}
+
+//
+// Helpers
+//
+
+/// Returns the fee (in satoshis) to pay for some vbytes.
+fn fee_for(fee_rate: FeeRate, vsize: u64) -> Result<u64> {
+    fee_rate
+        .fee_vb(vsize)
+        .map(Amount::to_sat)
+        .context("fee overflow")
+}
+
+/// Returns the UTXOs that the wallet can spend.
+async fn wallet_utxos(ctx: &RpcCtx) -> Result<Vec<Utxo>> {
+    Ok(list_unspent(ctx)
+        .await?
+        .into_iter()
+        .filter(|entry| entry.spendable && entry.safe)
+        .map(|entry| Utxo {
+            outpoint: OutPoint::new(entry.txid, entry.vout),
+            value: entry.amount,
+        })
+        .collect())
+}
+
+/// Returns the value of a UTXO minus the fee to spend it.
+fn effective_value(utxo: &Utxo, fee_rate: FeeRate) -> Result<i64> {
+    let value = i64::try_from(utxo.value.to_sat())?;
+    let fee = i64::try_from(fee_for(fee_rate, INPUT_VSIZE)?)?;
+    Ok(value - fee)
+}
+
+//
+// Strategies
+//
+
+/// Picks the largest UTXOs until their effective value covers the target.
+pub fn largest_first(utxos: &[Utxo], target: Amount, fee_rate: FeeRate) -> Result<Vec<Utxo>> {
+    let mut sorted = utxos.to_vec();
+    sorted.sort_by(|a, b| b.value.cmp(&a.value));
+
+    let target = i64::try_from(target.to_sat())?;
+    let mut selected = vec![];
+    let mut total = 0;
+    for utxo in sorted {
+        let value = effective_value(&utxo, fee_rate)?;
+        if value <= 0 {
+            // the remaining UTXOs cost more to spend than they're worth
+            break;
+        }
+        selected.push(utxo);
+        total += value;
+        if total >= target {
+            return Ok(selected);
+        }
+    }
+
+    bail!("the wallet doesn't have enough funds")
+}
+
+/// Looks for a set of UTXOs whose effective value covers the target
+/// without exceeding it by more than what a change output would cost
+/// (so that the excess can be given to the miners instead).
+/// Returns `None` if there's no such set.
+pub fn branch_and_bound(
+    utxos: &[Utxo],
+    target: Amount,
+    fee_rate: FeeRate,
+    cost_of_change: Amount,
+) -> Result<Option<Vec<Utxo>>> {
+    let mut pool = vec![];
+    for utxo in utxos {
+        let value = effective_value(utxo, fee_rate)?;
+        if value > 0 {
+            pool.push((value, *utxo));
+        }
+    }
+    pool.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let mut search = Search {
+        pool: &pool,
+        target: i64::try_from(target.to_sat())?,
+        upper_bound: i64::try_from((target + cost_of_change).to_sat())?,
+        tries: BNB_MAX_TRIES,
+        selected: vec![],
+        best: None,
+    };
+    let available = pool.iter().map(|(value, _)| value).sum();
+    search.explore(0, 0, available);
+
+    Ok(search
+        .best
+        .map(|(_, indices)| indices.into_iter().map(|i| pool[i].1).collect()))
+}
+
+/// The state of a branch-and-bound search.
+struct Search<'a> {
+    /// The UTXOs and their effective values, sorted by decreasing effective value.
+    pool: &'a [(i64, Utxo)],
+    target: i64,
+    upper_bound: i64,
+    tries: usize,
+    selected: Vec<usize>,
+    /// The selection with the least excess found so far.
+    best: Option<(i64, Vec<usize>)>,
+}
+
+impl Search<'_> {
+    fn explore(&mut self, index: usize, total: i64, remaining: i64) {
+        if self.tries == 0 {
+            return;
+        }
+        self.tries -= 1;
+
+        // prune branches that overshoot, or that can't reach the target anymore
+        if total > self.upper_bound || total + remaining < self.target {
+            return;
+        }
+
+        if total >= self.target {
+            let excess = total - self.target;
+            let better = match &self.best {
+                Some((best, _)) => excess < *best,
+                None => true,
+            };
+            if better {
+                self.best = Some((excess, self.selected.clone()));
+            }
+            return;
+        }
+
+        if index == self.pool.len() {
+            return;
+        }
+
+        let value = self.pool[index].0;
+
+        // include the UTXO
+        self.selected.push(index);
+        self.explore(index + 1, total + value, remaining - value);
+        self.selected.pop();
+
+        // or don't
+        self.explore(index + 1, total, remaining - value);
+    }
+}
+
+//
+// Funding
+//
+
+/// Selects the wallet UTXOs that should fund a transaction (on top of its current inputs),
+/// or returns `None` if the wallet should decide.
+/// `inputs_value` is the value of the inputs already in the transaction.
+pub async fn select_inputs(
+    ctx: &RpcCtx,
+    coin_selection: &CoinSelection,
+    tx: &Transaction,
+    inputs_value: Amount,
+    fee_rate: FeeRate,
+) -> Result<Option<Vec<OutPoint>>> {
+    let strategy = match coin_selection {
+        CoinSelection::Strategy(Strategy::Wallet) => return Ok(None),
+        CoinSelection::Strategy(strategy) => *strategy,
+        CoinSelection::Manual(outpoints) => {
+            let utxos = wallet_utxos(ctx).await?;
+            for outpoint in outpoints {
+                ensure!(
+                    utxos.iter().any(|utxo| utxo.outpoint == *outpoint),
+                    "{outpoint} is not a spendable UTXO of the wallet"
+                );
+            }
+            return Ok(Some(outpoints.clone()));
+        }
+    };
+
+    let target = shortfall(tx, inputs_value, fee_rate)?;
+    debug!("- selecting inputs for {target} with strategy {strategy:?}");
+
+    let utxos = wallet_utxos(ctx).await?;
+    let selected = match strategy {
+        Strategy::LargestFirst => largest_first(&utxos, target, fee_rate)?,
+        Strategy::BranchAndBound => {
+            let cost_of_change =
+                Amount::from_sat(fee_for(fee_rate, CHANGE_OUTPUT_VSIZE + INPUT_VSIZE)?);
+            match branch_and_bound(&utxos, target, fee_rate, cost_of_change)? {
+                Some(selected) => selected,
+                None => {
+                    info!("- no changeless selection of inputs found, using the largest inputs");
+                    largest_first(&utxos, target, fee_rate)?
+                }
+            }
+        }
+        Strategy::Wallet => bail!("the wallet selects its own inputs"),
+    };
+
+    Ok(Some(
+        selected.into_iter().map(|utxo| utxo.outpoint).collect(),
+    ))
+}
+
+/// What the wallet needs to pay for a transaction (its outputs and its fee), besides the new inputs.
+/// `inputs_value` is the value of the inputs already in the transaction.
+fn shortfall(tx: &Transaction, inputs_value: Amount, fee_rate: FeeRate) -> Result<Amount> {
+    let outputs_vsize: u64 = tx
+        .output
+        .iter()
+        .map(|output| 9 + output.script_pubkey.len() as u64)
+        .sum();
+    let base_vsize = TX_OVERHEAD_VSIZE + tx.input.len() as u64 * INPUT_VSIZE + outputs_vsize;
+    let outputs_value: Amount = tx.output.iter().map(|output| output.value).sum();
+    Ok(
+        (outputs_value + Amount::from_sat(fee_for(fee_rate, base_vsize)?))
+            .checked_sub(inputs_value)
+            .unwrap_or(Amount::ZERO),
+    )
+}
+
+/// Selects the wallet UTXOs paying for a sponsored transaction (see [crate::sponsor]).
+/// Its outputs are already signed for, so it can't get a change output:
+/// the UTXOs must cover what it lacks without exceeding it by more than `max_overpay` (which goes to the miners).
+pub async fn select_sponsoring_inputs(
+    ctx: &RpcCtx,
+    tx: &Transaction,
+    inputs_value: Amount,
+    fee_rate: FeeRate,
+    max_overpay: Amount,
+) -> Result<Vec<Utxo>> {
+    let target = shortfall(tx, inputs_value, fee_rate)?;
+    debug!("- selecting sponsoring inputs for {target}");
+    let utxos = wallet_utxos(ctx).await?;
+    branch_and_bound(&utxos, target, fee_rate, max_overpay)?.with_context(|| {
+        format!("the wallet has no UTXOs adding up to {target} (or at most {max_overpay} more), to pay for the transaction without change")
+    })
+}
+
+impl Funding {
+    /// Adds inputs (and change) to a transaction so that it pays for its outputs and its fee.
+    /// `inputs_value` is the value of the inputs already in the transaction.
+    /// Returns the funded transaction (and its hex encoding) along with the fee it pays.
+    pub async fn fund(
+        &self,
+        ctx: &RpcCtx,
+        mut tx: Transaction,
+        inputs_value: Amount,
+    ) -> Result<(String, Transaction, Amount)> {
+        let selection_fee_rate = self.fee_rate.unwrap_or(FeeRate::BROADCAST_MIN);
+        let selected = select_inputs(
+            ctx,
+            &self.coin_selection,
+            &tx,
+            inputs_value,
+            selection_fee_rate,
+        )
+        .await?;
+
+        let (fee_rate, add_wallet_inputs) = match selected {
+            Some(outpoints) => {
+                info!(
+                    "- funding transaction with {} selected inputs",
+                    outpoints.len()
+                );
+                add_inputs(&mut tx, &outpoints);
+                // the wallet must use the fee rate the inputs were selected for
+                (Some(selection_fee_rate), false)
+            }
+            None => (self.fee_rate, true),
+        };
+
+        fund_raw_transaction(
+            ctx,
+            TransactionOrHex::Transaction(&tx),
+            fee_rate,
+            add_wallet_inputs,
+            &self.change,
+        )
+        .await
+    }
+
+    /// Whether the transaction can be funded without a wallet (see [Funding::fund_without_wallet]):
+    /// with the given inputs, at a given fee rate, and with the change going to a given address.
+    pub fn without_wallet(&self) -> bool {
+        matches!(self.coin_selection, CoinSelection::Manual(_))
+            && matches!(self.change, Change::Address(_))
+            && self.fee_rate.is_some()
+    }
+
+    /// Like [Funding::fund], but without a wallet: the values of the given inputs are looked up on `chain`,
+    /// and the change (if worth an output) goes to the given address.
+    /// The inputs are left for an external signer to sign.
+    pub async fn fund_without_wallet(
+        &self,
+        chain: &dyn ChainBackend,
+        mut tx: Transaction,
+        inputs_value: Amount,
+    ) -> Result<(String, Transaction, Amount)> {
+        let (CoinSelection::Manual(outpoints), Change::Address(change), Some(fee_rate)) =
+            (&self.coin_selection, &self.change, self.fee_rate)
+        else {
+            bail!("funding a transaction without a wallet needs its inputs, a change address, and a fee rate");
+        };
+
+        let mut total = inputs_value;
+        for outpoint in outpoints {
+            let (prev_tx, _) = chain.get_transaction(outpoint.txid).await?;
+            let prev_out = prev_tx
+                .output
+                .get(outpoint.vout as usize)
+                .with_context(|| format!("{outpoint} doesn't exist"))?;
+            total = total
+                .checked_add(prev_out.value)
+                .context("amount overflow")?;
+        }
+        info!(
+            "- funding transaction with {} given inputs",
+            outpoints.len()
+        );
+        add_inputs(&mut tx, outpoints);
+
+        // the outputs and the fee, without a change output
+        let needed = shortfall(&tx, Amount::ZERO, fee_rate)?;
+        let excess = total.checked_sub(needed).with_context(|| {
+            format!(
+                "the given inputs lack {} to pay for the transaction",
+                needed - total
+            )
+        })?;
+
+        // give the excess back, unless it would be dust once the change output is paid for
+        let script_pubkey = change.script_pubkey();
+        let change_fee = Amount::from_sat(fee_for(fee_rate, CHANGE_OUTPUT_VSIZE)?);
+        let change_value = excess.checked_sub(change_fee).unwrap_or(Amount::ZERO);
+        if change_value >= script_pubkey.minimal_non_dust() {
+            tx.output.push(TxOut {
+                value: change_value,
+                script_pubkey,
+            });
+        }
+        let outputs_value: Amount = tx.output.iter().map(|output| output.value).sum();
+        let fee = total - outputs_value;
+
+        Ok((serialize_hex(&tx), tx, fee))
+    }
+}
+
+/// Adds inputs to a transaction (signaling replaceability, like the ones added by the wallet).
+pub fn add_inputs(tx: &mut Transaction, outpoints: &[OutPoint]) {
+    tx.input.extend(outpoints.iter().map(|outpoint| TxIn {
+        previous_output: *outpoint,
+        script_sig: ScriptBuf::new(),
+        sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+        witness: Witness::new(),
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Txid;
+
+    use super::*;
+
+    fn utxos(values: &[u64]) -> Vec<Utxo> {
+        let txid =
+            Txid::from_str("e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836")
+                .unwrap();
+        values
+            .iter()
+            .enumerate()
+            .map(|(vout, value)| Utxo {
+                outpoint: OutPoint::new(txid, vout as u32),
+                value: Amount::from_sat(*value),
+            })
+            .collect()
+    }
+
+    fn values(selected: &[Utxo]) -> Vec<u64> {
+        selected.iter().map(|utxo| utxo.value.to_sat()).collect()
+    }
+
+    #[test]
+    fn test_manual_inputs() {
+        assert_eq!(
+            CoinSelection::new(Strategy::LargestFirst, &[]).unwrap(),
+            CoinSelection::Strategy(Strategy::LargestFirst)
+        );
+
+        let input =
+            "e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836:1".to_string();
+        let selection = CoinSelection::new(Strategy::Wallet, &[input]).unwrap();
+        assert!(matches!(selection, CoinSelection::Manual(outpoints) if outpoints[0].vout == 1));
+
+        assert!(CoinSelection::new(Strategy::Wallet, &["nope".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_change() {
+        assert_eq!(Change::new(None, None).unwrap(), Change::Wallet);
+        assert_eq!(
+            Change::new(None, Some(ChangeType::Bech32m)).unwrap(),
+            Change::Type(ChangeType::Bech32m)
+        );
+        assert!(Change::new(Some("nope"), None).is_err());
+        assert!(Change::new(
+            Some("tb1q6nkpv2j9lxrm6h3w4skrny3thswgdcca8cx9k6"),
+            Some(ChangeType::Bech32)
+        )
+        .is_err());
+    }
+
+    /// A chain with a single transaction.
+    struct OneTx(Transaction);
+
+    #[async_trait::async_trait]
+    impl ChainBackend for OneTx {
+        async fn get_transaction(&self, _txid: Txid) -> Result<(Transaction, usize)> {
+            Ok((self.0.clone(), 1))
+        }
+
+        async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+            Ok(tx.txid())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fund_without_wallet() {
+        let change = Address::from_str("tb1q6nkpv2j9lxrm6h3w4skrny3thswgdcca8cx9k6")
+            .unwrap()
+            .assume_checked();
+        let output = |sats| TxOut {
+            value: Amount::from_sat(sats),
+            script_pubkey: change.script_pubkey(),
+        };
+        let funding_tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: vec![],
+            output: vec![output(100_000)],
+        };
+        let chain = OneTx(funding_tx.clone());
+        let tx = Transaction {
+            output: vec![output(50_000)],
+            ..funding_tx.clone()
+        };
+
+        // without the inputs, a change address, or a fee rate, the wallet is needed
+        let mut funding = Funding {
+            fee_rate: Some(FeeRate::from_sat_per_vb(2).unwrap()),
+            ..Default::default()
+        };
+        assert!(!funding.without_wallet());
+        funding.coin_selection = CoinSelection::Manual(vec![OutPoint::new(funding_tx.txid(), 0)]);
+        funding.change = Change::Address(change.clone());
+        assert!(funding.without_wallet());
+
+        // the excess goes back to the change address
+        let (_, funded, fee) = funding
+            .fund_without_wallet(&chain, tx.clone(), Amount::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(funded.input.len(), 1);
+        assert_eq!(funded.output.len(), 2);
+        let vsize = TX_OVERHEAD_VSIZE + INPUT_VSIZE + (9 + 22) + CHANGE_OUTPUT_VSIZE;
+        assert_eq!(fee.to_sat(), 2 * vsize);
+        assert_eq!(funded.output[1].value + fee, Amount::from_sat(50_000));
+
+        // and the inputs must pay for the outputs
+        let tx = Transaction {
+            output: vec![output(100_000)],
+            ..tx
+        };
+        assert!(funding
+            .fund_without_wallet(&chain, tx, Amount::ZERO)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_largest_first() {
+        let utxos = utxos(&[1_000, 50_000, 20_000]);
+        let fee_rate = FeeRate::from_sat_per_vb(1).unwrap();
+
+        let selected = largest_first(&utxos, Amount::from_sat(60_000), fee_rate).unwrap();
+        assert_eq!(values(&selected), vec![50_000, 20_000]);
+
+        assert!(largest_first(&utxos, Amount::from_sat(100_000), fee_rate).is_err());
+    }
+
+    #[test]
+    fn test_branch_and_bound() {
+        let utxos = utxos(&[10_068, 5_068, 30_068, 2_068]);
+        let fee_rate = FeeRate::from_sat_per_vb(1).unwrap();
+        let cost_of_change = Amount::from_sat(100);
+
+        // 10_000 + 5_000 (in effective value) is an exact match
+        let selected = branch_and_bound(&utxos, Amount::from_sat(15_000), fee_rate, cost_of_change)
+            .unwrap()
+            .unwrap();
+        let mut selected = values(&selected);
+        selected.sort();
+        assert_eq!(selected, vec![5_068, 10_068]);
+
+        // nothing lands between 1_000 and 1_100
+        assert!(
+            branch_and_bound(&utxos, Amount::from_sat(1_000), fee_rate, cost_of_change)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
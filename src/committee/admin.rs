@@ -0,0 +1,167 @@
+//! The admin API of the orchestrator.
+//!
+//! Methods that let an operator act on the orchestrator (approving held requests, releasing quarantined members,
+//! reloading the committee configuration) or that expose its internals (the health of the members, every event of
+//! the signing sessions) aren't served on the public JSON RPC server. They're served on a separate listener
+//! (see [AdminConfig]), which only answers requests authenticated with HTTP basic auth (see [AdminAuthLayer]).
+//! The listener binds to localhost by default, and should never be exposed without TLS in front of it.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::{ensure, Result};
+use base64::{engine::general_purpose, Engine};
+use hyper::{header::AUTHORIZATION, Body, Request, Response, StatusCode};
+use tower::{Layer, Service};
+
+//
+// Constants
+//
+
+/// The address the admin API listens on, by default.
+pub const DEFAULT_ADMIN_ADDRESS: &str = "127.0.0.1:6667";
+
+//
+// Configuration
+//
+
+/// Where the admin API is served, and the credentials it's authenticated with.
+#[derive(Clone)]
+pub struct AdminConfig {
+    /// The address to listen on (e.g. `127.0.0.1:6667`).
+    pub address: String,
+
+    /// The `user:password` requests must be authenticated with.
+    pub credentials: String,
+}
+
+impl AdminConfig {
+    pub fn new(address: Option<&str>, credentials: &str) -> Result<Self> {
+        let (user, password) = credentials.split_once(':').unwrap_or_default();
+        ensure!(
+            !user.is_empty() && password.len() >= 16,
+            "the admin credentials must be given as user:password, with a password of at least 16 characters"
+        );
+        Ok(Self {
+            address: address.unwrap_or(DEFAULT_ADMIN_ADDRESS).to_string(),
+            credentials: credentials.to_string(),
+        })
+    }
+}
+
+//
+// Authentication
+//
+
+/// Rejects the HTTP requests (including WebSocket upgrades) that don't carry the admin credentials.
+#[derive(Clone)]
+pub struct AdminAuthLayer {
+    expected: Arc<str>,
+}
+
+impl AdminAuthLayer {
+    pub fn new(credentials: &str) -> Self {
+        let encoded = general_purpose::STANDARD.encode(credentials);
+        Self {
+            expected: format!("Basic {encoded}").into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for AdminAuthLayer {
+    type Service = AdminAuth<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AdminAuth {
+            inner,
+            expected: self.expected.clone(),
+        }
+    }
+}
+
+/// See [AdminAuthLayer].
+#[derive(Clone)]
+pub struct AdminAuth<S> {
+    inner: S,
+    expected: Arc<str>,
+}
+
+impl<S> AdminAuth<S> {
+    fn authorized(&self, request: &Request<Body>) -> bool {
+        request
+            .headers()
+            .get(AUTHORIZATION)
+            .is_some_and(|auth| constant_time_eq(auth.as_bytes(), self.expected.as_bytes()))
+    }
+}
+
+impl<S> Service<Request<Body>> for AdminAuth<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+    S::Future: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        if self.authorized(&request) {
+            return Box::pin(self.inner.call(request));
+        }
+        let mut response = Response::new(Body::from("unauthorized"));
+        *response.status_mut() = StatusCode::UNAUTHORIZED;
+        Box::pin(async move { Ok(response) })
+    }
+}
+
+/// Compares two byte strings in time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_admin_auth() {
+        assert!(AdminConfig::new(None, "admin:short").is_err());
+        let config = AdminConfig::new(None, "admin:correct horse battery").unwrap();
+        assert_eq!(config.address, DEFAULT_ADMIN_ADDRESS);
+
+        let inner = tower::service_fn(|_: Request<Body>| async {
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        });
+        let mut service = AdminAuthLayer::new(&config.credentials).layer(inner);
+        let request = |auth: Option<&str>| {
+            let mut request = Request::new(Body::empty());
+            if let Some(auth) = auth {
+                let encoded = general_purpose::STANDARD.encode(auth);
+                request
+                    .headers_mut()
+                    .insert(AUTHORIZATION, format!("Basic {encoded}").parse().unwrap());
+            }
+            request
+        };
+
+        for (auth, status) in [
+            (None, StatusCode::UNAUTHORIZED),
+            (Some("admin:wrong"), StatusCode::UNAUTHORIZED),
+            (Some("admin:correct horse battery"), StatusCode::OK),
+        ] {
+            let response = service.call(request(auth)).await.unwrap();
+            assert_eq!(response.status(), status);
+        }
+    }
+}
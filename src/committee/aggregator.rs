@@ -0,0 +1,159 @@
+//! Aggregated verification of the proofs of pending requests, to keep up with high request volumes.
+//!
+//! Instead of verifying the proof of each request as it comes, the orchestrator hands it to the [Aggregator],
+//! which waits a short window for the proofs of other requests, and verifies all the pending proofs at once
+//! (see [crate::aggregation]). Only groth16 proofs gain anything from it, so the orchestrator verifies the proofs of
+//! the other proof systems directly (see [Statement::is_aggregatable]).
+//!
+//! Batches are verified on blocking threads, several at a time (see [AggregationConfig::max_concurrent_batches]),
+//! so that the next batch is collected while the previous ones are being verified.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use log::{debug, error};
+use tokio::{
+    sync::{mpsc, oneshot, Semaphore},
+    time::Instant,
+};
+
+use crate::aggregation::{self, Statement};
+
+//
+// Constants
+//
+
+/// The most batches verified at the same time (by default).
+pub const DEFAULT_MAX_CONCURRENT_BATCHES: usize = 4;
+
+/// How the proofs of pending requests are batched.
+#[derive(Debug, Clone, Copy)]
+pub struct AggregationConfig {
+    /// How long to wait for other proofs, once a proof is pending.
+    pub window: Duration,
+
+    /// The most proofs verified at once.
+    pub max_batch: usize,
+
+    /// The most batches verified at the same time (once reached, proofs wait for a batch to be done).
+    pub max_concurrent_batches: usize,
+}
+
+/// A proof waiting to be verified.
+struct Pending {
+    statement: Statement,
+    verified: oneshot::Sender<Result<()>>,
+}
+
+/// Verifies the proofs of pending requests in batches.
+pub struct Aggregator {
+    sender: mpsc::Sender<Pending>,
+}
+
+impl Aggregator {
+    /// Starts verifying proofs in the background.
+    pub fn spawn(config: AggregationConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(config.max_batch.max(1));
+        tokio::spawn(run(config, receiver));
+        Self { sender }
+    }
+
+    /// Verifies a proof, along with the other proofs pending at the same time.
+    pub async fn verify(&self, statement: Statement) -> Result<()> {
+        let (verified, result) = oneshot::channel();
+        self.sender
+            .send(Pending {
+                statement,
+                verified,
+            })
+            .await
+            .ok()
+            .context("the aggregator stopped")?;
+        result.await.context("the aggregator stopped")?
+    }
+}
+
+async fn run(config: AggregationConfig, mut receiver: mpsc::Receiver<Pending>) {
+    let verifying = Arc::new(Semaphore::new(config.max_concurrent_batches.max(1)));
+    while let Some(first) = receiver.recv().await {
+        // wait for other proofs
+        let mut batch = vec![first];
+        let deadline = Instant::now() + config.window;
+        while batch.len() < config.max_batch {
+            match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                Ok(Some(pending)) => batch.push(pending),
+                Ok(None) | Err(_) => break,
+            }
+        }
+
+        // wait for a batch to be done if too many are being verified, then collect the next one meanwhile
+        let Ok(permit) = verifying.clone().acquire_owned().await else {
+            return;
+        };
+        let (statements, senders): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .map(|pending| (pending.statement, pending.verified))
+            .unzip();
+        debug!("- verifying {} pending proofs", statements.len());
+        tokio::spawn(async move {
+            let results =
+                tokio::task::spawn_blocking(move || aggregation::verify_all(&statements)).await;
+            drop(permit);
+            let results = match results {
+                Ok(results) => results,
+                // the requests are failed (by dropping their senders)
+                Err(err) => {
+                    error!("couldn't verify the pending proofs: {err}");
+                    return;
+                }
+            };
+
+            for (verified, result) in senders.into_iter().zip(results) {
+                // the request might have been dropped
+                let _ = verified.send(result);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_malformed_proofs() {
+        let aggregator = Aggregator::spawn(AggregationConfig {
+            window: Duration::from_millis(10),
+            max_batch: 4,
+            max_concurrent_batches: DEFAULT_MAX_CONCURRENT_BATCHES,
+        });
+        let vk: crate::plonk::VerifierKey = serde_json::from_value(serde_json::json!({
+            "protocol": "halo2",
+            "circuit": "counter",
+            "k": 5,
+            "nPublic": 5,
+            "pinned_hash": "00",
+        }))
+        .unwrap();
+        let proof: crate::plonk::Proof = serde_json::from_value(serde_json::json!({
+            "protocol": "risc0",
+            "receipt": "00",
+        }))
+        .unwrap();
+        let statement = Statement {
+            vk,
+            public_inputs: vec![],
+            proof,
+        };
+
+        assert!(!Statement::is_aggregatable(&statement.vk, &statement.proof));
+
+        // each proof gets its own result, even when verified with others
+        let (first, second) = tokio::join!(
+            aggregator.verify(statement.clone()),
+            aggregator.verify(statement)
+        );
+        assert!(first.is_err());
+        assert!(second.is_err());
+    }
+}
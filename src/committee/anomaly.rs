@@ -0,0 +1,440 @@
+//! Detection of anomalous spends of zkapps.
+//!
+//! The orchestrator records what every request it signs withdraws (see [SpendRecord], kept in its storage),
+//! so that it knows how much, and how often, each zkapp is usually spent.
+//! The requests of a zkapp registered with an [AnomalyPolicy] (see [super::policy]) are compared with its history:
+//! a request withdrawing much more than the zkapp usually does, or coming while the zkapp is spent much more often than usual,
+//! is anomalous (see [AnomalyPolicy::check]). Zkapps with too little history aren't checked.
+//!
+//! Depending on the policy, anomalous requests are only flagged (logged, and the operators alerted, see [super::alerts]),
+//! or held until an operator approves them (see `zkbtc orchestrator held`), after which they can be sent again.
+//! Held requests and approvals are only kept in memory, so they don't survive a restart of the orchestrator
+//! (unless it shares its state with other replicas, see [super::shared]). Requests are only held for a day,
+//! and at most [MAX_HELD_REQUESTS] of them, the oldest being dropped first: a dropped request can just be sent again.
+//! Operators list and approve them through the admin API (see [super::admin]).
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    sync::Mutex,
+};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{Amount, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::bob_request::BobRequest;
+
+use super::{fees::withdrawn, storage::now};
+
+//
+// Constants
+//
+
+/// How many times more than usual a zkapp must be spent (or withdrawn from) for a request to be anomalous, by default.
+pub const DEFAULT_ANOMALY_FACTOR: f64 = 10.0;
+
+/// The number of past spends of a zkapp needed before its requests are checked, by default.
+pub const DEFAULT_MIN_HISTORY: usize = 5;
+
+/// The period (in seconds) over which the recent frequency of spends is measured.
+const FREQUENCY_WINDOW: u64 = 24 * 60 * 60;
+
+/// How many requests are held at most.
+pub const MAX_HELD_REQUESTS: usize = 1_000;
+
+/// How long (in seconds) a request is held, if no operator approves it.
+pub const HOLD_EXPIRY: u64 = 24 * 60 * 60;
+
+//
+// Data structures
+//
+
+/// What a spend signed by the committee withdrew from its zkapp.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendRecord {
+    /// When the request was signed (UNIX timestamp in seconds).
+    pub signed_at: u64,
+
+    /// The transaction signed.
+    pub txid: Txid,
+
+    /// The transaction that deployed (or last updated) the zkapp.
+    pub zkapp_txid: Txid,
+
+    /// The hex-encoded hash of the zkapp's verifier key.
+    pub vk_hash: String,
+
+    /// What the request withdrew to its recipients.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub withdrawn: Amount,
+}
+
+impl SpendRecord {
+    /// Records what the (validated) requests of a signed transaction withdrew.
+    pub fn for_requests(bob_requests: &[BobRequest], signed_at: u64) -> Result<Vec<Self>> {
+        bob_requests
+            .iter()
+            .map(|bob_request| {
+                Ok(Self {
+                    signed_at,
+                    txid: bob_request.tx.txid(),
+                    zkapp_txid: bob_request.txid()?,
+                    vk_hash: hex::encode(bob_request.vk.hash()),
+                    withdrawn: withdrawn(bob_request),
+                })
+            })
+            .collect()
+    }
+}
+
+/// What to do with the anomalous requests of a zkapp.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyAction {
+    /// Sign them, but log them and alert the operators.
+    #[default]
+    Flag,
+
+    /// Don't sign them until an operator approves them.
+    Hold,
+}
+
+fn default_factor() -> f64 {
+    DEFAULT_ANOMALY_FACTOR
+}
+
+fn default_min_history() -> usize {
+    DEFAULT_MIN_HISTORY
+}
+
+/// How the requests of a zkapp are compared with its history.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnomalyPolicy {
+    #[serde(default)]
+    pub action: AnomalyAction,
+
+    /// A request withdrawing more than this many times the average withdrawal of the zkapp is anomalous.
+    #[serde(default = "default_factor")]
+    pub volume_factor: f64,
+
+    /// A request is anomalous if, counting it, the zkapp was spent more than this many times as often as usual in the last day.
+    #[serde(default = "default_factor")]
+    pub frequency_factor: f64,
+
+    /// The number of past spends of the zkapp needed before its requests are checked.
+    #[serde(default = "default_min_history")]
+    pub min_history: usize,
+}
+
+impl Default for AnomalyPolicy {
+    fn default() -> Self {
+        Self {
+            action: AnomalyAction::default(),
+            volume_factor: DEFAULT_ANOMALY_FACTOR,
+            frequency_factor: DEFAULT_ANOMALY_FACTOR,
+            min_history: DEFAULT_MIN_HISTORY,
+        }
+    }
+}
+
+/// How a request deviates from the history of its zkapp.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "anomaly", rename_all = "snake_case")]
+pub enum Anomaly {
+    /// The request withdraws much more than the zkapp usually does.
+    Volume {
+        #[serde(with = "bitcoin::amount::serde::as_sat")]
+        withdrawn: Amount,
+        #[serde(with = "bitcoin::amount::serde::as_sat")]
+        average: Amount,
+    },
+
+    /// The zkapp is spent much more often than usual.
+    Frequency {
+        /// The number of spends in the last day (counting the request).
+        recent: usize,
+        /// The number of spends in a day, on average before that.
+        usual: f64,
+    },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::Volume { withdrawn, average } => write!(
+                f,
+                "it withdraws {withdrawn}, while the zkapp's withdrawals average {average}"
+            ),
+            Anomaly::Frequency { recent, usual } => write!(
+                f,
+                "the zkapp was spent {recent} times in the last day, while it's usually spent {usual:.1} times a day"
+            ),
+        }
+    }
+}
+
+impl AnomalyPolicy {
+    /// Ensures that the policy is well-formed.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.volume_factor >= 1.0 && self.frequency_factor >= 1.0,
+            "the anomaly factors must be at least 1"
+        );
+        ensure!(
+            self.min_history > 0,
+            "the history needed to detect anomalies can't be empty"
+        );
+        Ok(())
+    }
+
+    /// Compares a request withdrawing `withdrawn` at `now` with the past spends of its zkapp.
+    pub fn check(&self, history: &[SpendRecord], withdrawn: Amount, now: u64) -> Option<Anomaly> {
+        if history.len() < self.min_history {
+            return None;
+        }
+
+        let total: u64 = history.iter().map(|spend| spend.withdrawn.to_sat()).sum();
+        let average = Amount::from_sat(total / history.len() as u64);
+        if withdrawn.to_sat() as f64 > self.volume_factor * average.to_sat().max(1) as f64 {
+            return Some(Anomaly::Volume { withdrawn, average });
+        }
+
+        // the usual number of spends in a window, before the last one
+        let since = now.saturating_sub(FREQUENCY_WINDOW);
+        let first = history.iter().map(|spend| spend.signed_at).min()?;
+        let recent = 1 + history
+            .iter()
+            .filter(|spend| spend.signed_at >= since)
+            .count();
+        let older = history.len() + 1 - recent;
+        let span = since.saturating_sub(first).max(FREQUENCY_WINDOW);
+        let usual = older as f64 * FREQUENCY_WINDOW as f64 / span as f64;
+        if recent as f64 > self.frequency_factor * usual.max(1.0) {
+            return Some(Anomaly::Frequency { recent, usual });
+        }
+
+        None
+    }
+}
+
+//
+// Held requests
+//
+
+/// A request held until an operator approves it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeldRequest {
+    /// The hex-encoded hash of the request, which operators approve it with.
+    pub request_hash: String,
+
+    /// The hex-encoded hash of the zkapp's verifier key.
+    pub vk_hash: String,
+
+    /// The transaction to sign.
+    pub txid: Txid,
+
+    /// What the request withdraws to its recipients.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub withdrawn: Amount,
+
+    /// How the request deviates from the history of its zkapp.
+    pub anomaly: Anomaly,
+
+    /// When the request was held (UNIX timestamp in seconds).
+    pub held_at: u64,
+}
+
+impl HeldRequest {
+    fn expired(&self, now: u64) -> bool {
+        self.held_at + HOLD_EXPIRY <= now
+    }
+}
+
+/// The held requests (by hash) to drop before holding a new one at `now`:
+/// the expired ones, and the oldest ones if that's not enough to make room.
+pub fn evictions(held: &[HeldRequest], now: u64) -> Vec<String> {
+    let (mut evicted, mut kept): (Vec<_>, Vec<_>) = held.iter().partition(|held| held.expired(now));
+    kept.sort_by_key(|held| held.held_at);
+    let excess = (kept.len() + 1).saturating_sub(MAX_HELD_REQUESTS);
+    evicted.extend(kept.into_iter().take(excess));
+    evicted
+        .into_iter()
+        .map(|held| held.request_hash.clone())
+        .collect()
+}
+
+#[derive(Debug, Default)]
+struct HoldState {
+    held: BTreeMap<String, HeldRequest>,
+    approved: HashSet<String>,
+}
+
+/// The requests held until an operator approves them, and the ones approved (until they're sent again).
+#[derive(Debug, Default)]
+pub struct Holds {
+    state: Mutex<HoldState>,
+}
+
+impl Holds {
+    /// Holds a request (held at [HeldRequest::held_at]), making room for it if needed.
+    pub fn hold(&self, held: HeldRequest) {
+        let mut state = self.state.lock().unwrap();
+        let all: Vec<_> = state.held.values().cloned().collect();
+        for request_hash in evictions(&all, held.held_at) {
+            state.held.remove(&request_hash);
+        }
+        state.held.insert(held.request_hash.clone(), held);
+    }
+
+    /// The requests held (and not expired yet).
+    pub fn list(&self) -> Vec<HeldRequest> {
+        let now = now();
+        self.state
+            .lock()
+            .unwrap()
+            .held
+            .values()
+            .filter(|held| !held.expired(now))
+            .cloned()
+            .collect()
+    }
+
+    /// Approves a held request, so that it's signed the next time it's sent.
+    pub fn approve(&self, request_hash: &str) -> Result<HeldRequest> {
+        let mut state = self.state.lock().unwrap();
+        let held = state
+            .held
+            .remove(request_hash)
+            .filter(|held| !held.expired(now()))
+            .with_context(|| format!("request {request_hash} is not held"))?;
+        state.approved.insert(request_hash.to_string());
+        Ok(held)
+    }
+
+    /// Whether a request was approved (the approval being used up).
+    pub fn take_approval(&self, request_hash: &str) -> bool {
+        self.state.lock().unwrap().approved.remove(request_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn spend(signed_at: u64, withdrawn: u64) -> SpendRecord {
+        SpendRecord {
+            signed_at,
+            txid: Txid::all_zeros(),
+            zkapp_txid: Txid::all_zeros(),
+            vk_hash: hex::encode([7u8; 32]),
+            withdrawn: Amount::from_sat(withdrawn),
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let policy = AnomalyPolicy::default();
+        policy.validate().unwrap();
+
+        // a spend a day, of 1000 sats, for the last 10 days
+        let history: Vec<_> = (1..=10).map(|day| spend(NOW - day * DAY, 1000)).collect();
+        assert_eq!(policy.check(&history, Amount::from_sat(5000), NOW), None);
+
+        // not enough history
+        assert_eq!(
+            policy.check(&history[..4], Amount::from_sat(1_000_000), NOW),
+            None
+        );
+
+        // withdrawing much more than usual
+        assert_eq!(
+            policy.check(&history, Amount::from_sat(10_001), NOW),
+            Some(Anomaly::Volume {
+                withdrawn: Amount::from_sat(10_001),
+                average: Amount::from_sat(1000),
+            })
+        );
+
+        // spending much more often than usual
+        let mut busy = history.clone();
+        busy.extend((0..8).map(|i| spend(NOW - i * 60, 1000)));
+        assert_eq!(policy.check(&busy, Amount::from_sat(1000), NOW), None);
+        busy.push(spend(NOW - 600, 1000));
+        assert!(matches!(
+            policy.check(&busy, Amount::from_sat(1000), NOW),
+            Some(Anomaly::Frequency { recent: 11, .. })
+        ));
+
+        let policy = AnomalyPolicy {
+            volume_factor: 0.5,
+            ..Default::default()
+        };
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn test_holds() {
+        let holds = Holds::default();
+        let held = HeldRequest {
+            request_hash: "ab".repeat(32),
+            vk_hash: hex::encode([7u8; 32]),
+            txid: Txid::all_zeros(),
+            withdrawn: Amount::from_sat(10_001),
+            anomaly: Anomaly::Volume {
+                withdrawn: Amount::from_sat(10_001),
+                average: Amount::from_sat(1000),
+            },
+            held_at: now(),
+        };
+        holds.hold(held.clone());
+        assert_eq!(holds.list(), vec![held.clone()]);
+        assert!(!holds.take_approval(&held.request_hash));
+
+        // approvals are used up
+        assert_eq!(holds.approve(&held.request_hash).unwrap(), held);
+        assert!(holds.list().is_empty());
+        assert!(holds.approve(&held.request_hash).is_err());
+        assert!(holds.take_approval(&held.request_hash));
+        assert!(!holds.take_approval(&held.request_hash));
+    }
+
+    #[test]
+    fn test_hold_limits() {
+        let held = |i: u64, held_at: u64| HeldRequest {
+            request_hash: format!("{i:064x}"),
+            vk_hash: hex::encode([7u8; 32]),
+            txid: Txid::all_zeros(),
+            withdrawn: Amount::from_sat(10_001),
+            anomaly: Anomaly::Frequency {
+                recent: 11,
+                usual: 1.0,
+            },
+            held_at,
+        };
+
+        // expired requests are dropped
+        let all = vec![held(0, NOW - HOLD_EXPIRY), held(1, NOW - 1)];
+        assert_eq!(evictions(&all, NOW), vec![format!("{:064x}", 0)]);
+
+        // and the oldest ones make room for new ones
+        let all: Vec<_> = (0..MAX_HELD_REQUESTS as u64)
+            .map(|i| held(i, NOW - MAX_HELD_REQUESTS as u64 + i))
+            .collect();
+        assert_eq!(evictions(&all, NOW), vec![format!("{:064x}", 0)]);
+        assert!(evictions(&all[1..], NOW).is_empty());
+    }
+
+    #[test]
+    fn test_policy_defaults() {
+        let policy: AnomalyPolicy = serde_json::from_str(r#"{"action":"hold"}"#).unwrap();
+        assert_eq!(policy.action, AnomalyAction::Hold);
+        assert_eq!(policy.volume_factor, DEFAULT_ANOMALY_FACTOR);
+        assert_eq!(policy.min_history, DEFAULT_MIN_HISTORY);
+    }
+}
@@ -0,0 +1,245 @@
+//! Attestations: signatures of the committee on arbitrary data (see `zkbtc attest`),
+//! so that state attestations, price feeds, or announcements of the operators can be published under the committee identity.
+//!
+//! The committee signs the BIP 340 tagged hash of the data (see [digest]), with the tag [ATTESTATION_TAG].
+//! As the sighashes of transactions are tagged hashes too (with another tag),
+//! an attestation can't be passed off as the signature of a spend of the zkapps.
+//! Attestations are signed for the output key of the zkBitcoin address (see [super::signer::output_key]),
+//! so they verify under the key zkapps are locked to.
+//!
+//! Only the operators of the orchestrator can request attestations (through its admin API), and nodes only sign
+//! the data their own operator approved (see [read_approvals]), so that an attestation is backed by a threshold of them.
+
+use std::{fs, path::Path};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use secp256k1::{schnorr, Message, Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+
+use super::signer::{even_y, output_key};
+
+/// The tag of the hashes the committee signs as attestations.
+pub const ATTESTATION_TAG: &str = "zkBitcoin/attestation";
+
+/// The digest the committee signs to attest to `data`: its BIP 340 tagged hash, with the tag [ATTESTATION_TAG].
+pub fn digest(data: &[u8]) -> [u8; 32] {
+    tagged_hash(ATTESTATION_TAG, data)
+}
+
+/// The BIP 340 tagged hash of `data`, with the tag `tag`.
+pub fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_byte_array());
+    engine.input(tag.as_byte_array());
+    engine.input(data);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Reads the digests (see [digest]) of the data an operator approves attesting to: one hex-encoded digest per line
+/// (blank lines and lines starting with `#` are skipped).
+pub fn read_approvals(path: &Path) -> Result<Vec<[u8; 32]>> {
+    let approvals = fs::read_to_string(path).with_context(|| {
+        format!(
+            "couldn't read the approved attestations in {}",
+            path.display()
+        )
+    })?;
+    approvals
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            hex::decode(line)
+                .ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .with_context(|| format!("{line} is not the hex-encoded digest of an attestation"))
+        })
+        .collect()
+}
+
+/// A request to have the committee attest to some data.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestationRequest {
+    /// The hex-encoded data.
+    pub data: String,
+}
+
+impl AttestationRequest {
+    pub fn new(data: &[u8]) -> Self {
+        Self {
+            data: hex::encode(data),
+        }
+    }
+
+    /// The digest to sign (see [digest]).
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        let data = hex::decode(&self.data).context("the data to attest to is not hex-encoded")?;
+        Ok(digest(&data))
+    }
+}
+
+/// Data attested to by the committee.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedAttestation {
+    /// The hex-encoded data.
+    pub data: String,
+
+    /// The hex-encoded digest signed (see [digest]).
+    pub digest: String,
+
+    /// The hex-encoded x-only key the attestation verifies under (the output key of the zkBitcoin address).
+    pub pubkey: String,
+
+    /// The hex-encoded BIP 340 signature of the digest.
+    pub signature: String,
+}
+
+impl SignedAttestation {
+    /// Attests to `data` with `signature`, made by the committee whose key is `committee_pubkey`.
+    pub fn new(
+        data: &[u8],
+        committee_pubkey: bitcoin::PublicKey,
+        signature: &schnorr::Signature,
+    ) -> Self {
+        Self {
+            data: hex::encode(data),
+            digest: hex::encode(digest(data)),
+            pubkey: output_key(committee_pubkey).to_string(),
+            signature: signature.to_string(),
+        }
+    }
+
+    /// Checks that the attestation was signed by the committee whose key is `zkbitcoin_pubkey`
+    /// (e.g. the one of the protocol configuration).
+    pub fn verify(&self, zkbitcoin_pubkey: bitcoin::PublicKey) -> Result<()> {
+        let data = hex::decode(&self.data).context("the data is not hex-encoded")?;
+        let digest = digest(&data);
+        ensure!(
+            self.digest == hex::encode(digest),
+            "the digest doesn't match the data"
+        );
+
+        let pubkey = output_key(even_y(zkbitcoin_pubkey));
+        ensure!(
+            self.pubkey.parse::<XOnlyPublicKey>().ok() == Some(pubkey),
+            "the attestation wasn't signed by the committee {zkbitcoin_pubkey}"
+        );
+        let signature: schnorr::Signature = self
+            .signature
+            .parse()
+            .context("the signature is not a hex-encoded BIP 340 signature")?;
+        Secp256k1::verification_only()
+            .verify_schnorr(&signature, &Message::from_digest(digest), &pubkey)
+            .context("the signature of the attestation doesn't verify")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use secp256k1::{Keypair, SecretKey};
+
+    use super::*;
+    use crate::{
+        client::CommitteeMember,
+        committee::{
+            dealer,
+            node::NodeState,
+            orchestrator::Orchestrator,
+            signer::{SignatureAggregator, SignerKind},
+        },
+    };
+
+    #[test]
+    fn test_attestation() {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let committee_pubkey = even_y(bitcoin::PublicKey::new(secret_key.public_key(&secp)));
+
+        // sign for the output key, like the committee does
+        let keypair = Keypair::from_secret_key(&secp, &secret_key);
+        let tweaked = bitcoin::key::TapTweak::tap_tweak(keypair, &secp, None).to_inner();
+        assert_eq!(tweaked.x_only_public_key().0, output_key(committee_pubkey));
+
+        let data = b"block 840000 has 3 zkapps";
+        let signature =
+            secp.sign_schnorr_no_aux_rand(&Message::from_digest(digest(data)), &tweaked);
+        let attestation = SignedAttestation::new(data, committee_pubkey, &signature);
+        attestation.verify(committee_pubkey).unwrap();
+        assert_eq!(
+            AttestationRequest::new(data).digest().unwrap(),
+            digest(data)
+        );
+
+        // the data can't be changed
+        let mut tampered = attestation.clone();
+        tampered.data = hex::encode(b"block 840000 has 4 zkapps");
+        assert!(tampered.verify(committee_pubkey).is_err());
+
+        // nor the committee
+        let other =
+            bitcoin::PublicKey::new(SecretKey::from_slice(&[8; 32]).unwrap().public_key(&secp));
+        assert!(attestation.verify(other).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_committee_attestation() {
+        let data = b"price: 64000 USD";
+        let dir = tempdir::TempDir::new("zkbitcoin_attestation").unwrap();
+        let approvals_path = dir.path().join("approvals");
+        fs::write(
+            &approvals_path,
+            format!("# the price feed\n{}\n", hex::encode(digest(data))),
+        )
+        .unwrap();
+        assert_eq!(read_approvals(&approvals_path).unwrap(), vec![digest(data)]);
+
+        for (num, threshold, signer) in [(3, 2, SignerKind::Frost), (2, 2, SignerKind::Musig2)] {
+            let committee = dealer::generate(num, threshold, 0, signer).unwrap();
+
+            // only the operators of the first `approvers` members approve the data
+            let orchestrator = |approvers: usize| {
+                let members: HashMap<_, Box<dyn CommitteeMember>> = committee
+                    .key_packages
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (id, key_package))| {
+                        let mut node =
+                            NodeState::new(key_package.clone(), committee.pubkey_package.clone());
+                        if i < approvers {
+                            node.attestation_approvals = Some(approvals_path.clone());
+                        }
+                        (*id, Box::new(node) as Box<dyn CommitteeMember>)
+                    })
+                    .collect();
+                Orchestrator::with_members(
+                    committee.pubkey_package.clone(),
+                    committee.config.clone(),
+                    members,
+                )
+            };
+
+            assert!(orchestrator(threshold - 1).attest(data).await.is_err());
+            assert!(orchestrator(num).attest(b"unapproved").await.is_err());
+
+            let orchestrator = orchestrator(threshold);
+            let attestation = orchestrator.attest(data).await.unwrap();
+            let committee_pubkey = committee.pubkey_package.pubkey().unwrap();
+            attestation.verify(committee_pubkey).unwrap();
+            assert_eq!(attestation.pubkey, output_key(committee_pubkey).to_string());
+        }
+    }
+
+    #[test]
+    fn test_digest_is_tagged() {
+        // an attestation isn't a signature of the untagged hash of the data
+        assert_ne!(
+            digest(b"zkBitcoin"),
+            sha256::Hash::hash(b"zkBitcoin").to_byte_array()
+        );
+        assert_ne!(digest(b""), digest(b"\0"));
+    }
+}
@@ -0,0 +1,411 @@
+//! An append-only log of the signing sessions of the orchestrator, or of a committee node,
+//! so that operators can audit exactly what the committee signed, and when.
+//!
+//! Each entry records a session (the request, the transaction and zkapp it spends, the amounts, the members involved,
+//! and the signature produced), commits to the previous entry (like the digest log of [super::storage]),
+//! and is signed with the key of the log (a Schnorr key kept next to it), so that entries can't be altered,
+//! removed, or reordered without it showing (see [verify]).
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{hashes::Hash, Transaction, Txid};
+use log::debug;
+use rand::RngCore;
+use secp256k1::{schnorr, Keypair, Message, Secp256k1, SecretKey, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use crate::bob_request::SmartContract;
+
+use super::{storage::now, sweep::EmergencySweep};
+
+/// The digest that starts the hash chain.
+const GENESIS_DIGEST: [u8; 32] = [0u8; 32];
+
+/// The file the key of the log is kept in (next to the log).
+pub const KEY_FILE: &str = "audit-key";
+
+//
+// Data structures
+//
+
+/// An output of a signed transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditedOutput {
+    /// The hex-encoded script of the output.
+    pub script_pubkey: String,
+
+    /// The value of the output (in satoshis).
+    pub value: u64,
+}
+
+/// A signing session, as recorded in the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRecord {
+    /// The hex-encoded hash of the request.
+    pub request_hash: String,
+
+    /// The transaction signed.
+    pub txid: Txid,
+
+    /// The input of the transaction spending the zkapp.
+    pub zkapp_input: usize,
+
+    /// The transaction that deployed (or last updated) the zkapp.
+    pub zkapp_txid: Txid,
+
+    /// The value locked in the zkapp (in satoshis).
+    pub locked_value: u64,
+
+    /// The outputs of the transaction signed.
+    pub outputs: Vec<AuditedOutput>,
+
+    /// The members that took part in the session.
+    pub members: Vec<frost_secp256k1_tr::Identifier>,
+
+    /// The hex-encoded signature produced: the final signature for the orchestrator,
+    /// or the signature share of a node.
+    pub signature: String,
+}
+
+impl SessionRecord {
+    /// Records the session that signed input `zkapp_input` of `tx`, spending `smart_contract`.
+    pub fn new(
+        request_hash: [u8; 32],
+        tx: &Transaction,
+        zkapp_input: usize,
+        smart_contract: &SmartContract,
+        members: Vec<frost_secp256k1_tr::Identifier>,
+        signature: &[u8],
+    ) -> Self {
+        Self {
+            request_hash: hex::encode(request_hash),
+            txid: tx.txid(),
+            zkapp_input,
+            zkapp_txid: smart_contract.txid,
+            locked_value: smart_contract.locked_value.to_sat(),
+            outputs: audited_outputs(tx),
+            members,
+            signature: hex::encode(signature),
+        }
+    }
+
+    /// Records the session that signed input `input` of an emergency sweep (see [super::sweep]),
+    /// the hash of the sweep standing for the hash of the request.
+    pub fn sweep(
+        sweep_hash: [u8; 32],
+        sweep: &EmergencySweep,
+        input: usize,
+        members: Vec<frost_secp256k1_tr::Identifier>,
+        signature: &[u8],
+    ) -> Self {
+        Self {
+            request_hash: hex::encode(sweep_hash),
+            txid: sweep.tx.txid(),
+            zkapp_input: input,
+            zkapp_txid: sweep.tx.input[input].previous_output.txid,
+            locked_value: sweep.prev_outs[input].value.to_sat(),
+            outputs: audited_outputs(&sweep.tx),
+            members,
+            signature: hex::encode(signature),
+        }
+    }
+
+    /// Records the session that attested to some data (see [super::attestation]) or voted for a proposal (see [super::governance]),
+    /// the digest signed standing for the hash of the request (no transaction being signed).
+    pub fn attestation(
+        digest: [u8; 32],
+        members: Vec<frost_secp256k1_tr::Identifier>,
+        signature: &[u8],
+    ) -> Self {
+        Self {
+            request_hash: hex::encode(digest),
+            txid: Txid::all_zeros(),
+            zkapp_input: 0,
+            zkapp_txid: Txid::all_zeros(),
+            locked_value: 0,
+            outputs: vec![],
+            members,
+            signature: hex::encode(signature),
+        }
+    }
+}
+
+fn audited_outputs(tx: &Transaction) -> Vec<AuditedOutput> {
+    tx.output
+        .iter()
+        .map(|output| AuditedOutput {
+            script_pubkey: hex::encode(output.script_pubkey.as_bytes()),
+            value: output.value.to_sat(),
+        })
+        .collect()
+}
+
+/// An entry of the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position of the entry in the log.
+    pub index: u64,
+
+    /// When the session was signed (UNIX timestamp in seconds).
+    pub signed_at: u64,
+
+    /// The session.
+    pub session: SessionRecord,
+
+    /// The digest of the previous entry.
+    pub prev_digest: String,
+
+    /// The digest of this entry, which commits to all the fields above.
+    pub digest: String,
+
+    /// The hex-encoded (x-only) public key of the log.
+    pub signer: String,
+
+    /// The hex-encoded Schnorr signature of the digest, by the key of the log.
+    pub signature: String,
+}
+
+impl AuditEntry {
+    fn compute(
+        index: u64,
+        signed_at: u64,
+        session: &SessionRecord,
+        prev_digest: &str,
+    ) -> Result<[u8; 32]> {
+        let mut hasher = Keccak256::new();
+        hasher.update(index.to_be_bytes());
+        hasher.update(signed_at.to_be_bytes());
+        hasher.update(serde_json::to_string(session)?.as_bytes());
+        hasher.update(prev_digest.as_bytes());
+        Ok(hasher.finalize().into())
+    }
+}
+
+//
+// Log
+//
+
+/// The audit log, in a directory (as `audit.jsonl`, along with its key in [KEY_FILE]).
+pub struct AuditLog {
+    path: PathBuf,
+    keypair: Keypair,
+    /// The last entry of the log (also serializes writes to it).
+    last: Mutex<Option<AuditEntry>>,
+}
+
+impl AuditLog {
+    /// Opens (and creates if needed) the audit log in `dir`, and checks it.
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+        let keypair = load_or_create_key(&dir.join(KEY_FILE))?;
+        let path = dir.join("audit.jsonl");
+
+        let entries = read(&path)?;
+        verify(&entries, Some(&keypair.x_only_public_key().0))
+            .with_context(|| format!("the audit log {} was tampered with", path.display()))?;
+        Ok(Self {
+            path,
+            keypair,
+            last: Mutex::new(entries.into_iter().last()),
+        })
+    }
+
+    /// The public key the entries of the log are signed with.
+    pub fn pubkey(&self) -> XOnlyPublicKey {
+        self.keypair.x_only_public_key().0
+    }
+
+    /// Appends a session to the log.
+    pub fn append(&self, session: SessionRecord) -> Result<AuditEntry> {
+        let mut last = self.last.lock().unwrap();
+        let (index, prev_digest) = match last.as_ref() {
+            Some(last) => (last.index + 1, last.digest.clone()),
+            None => (0, hex::encode(GENESIS_DIGEST)),
+        };
+        let signed_at = now();
+        let digest = AuditEntry::compute(index, signed_at, &session, &prev_digest)?;
+        let signature = Secp256k1::signing_only()
+            .sign_schnorr_no_aux_rand(&Message::from_digest(digest), &self.keypair);
+        let entry = AuditEntry {
+            index,
+            signed_at,
+            session,
+            prev_digest,
+            digest: hex::encode(digest),
+            signer: hex::encode(self.pubkey().serialize()),
+            signature: hex::encode(signature.as_ref()),
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("couldn't open the audit log")?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        file.sync_data()?;
+
+        debug!("- audited the signature of {}", entry.session.txid);
+        *last = Some(entry.clone());
+        Ok(entry)
+    }
+}
+
+/// Loads the key of the logs kept in a directory, or creates it.
+pub fn load_or_create_key(path: &Path) -> Result<Keypair> {
+    let secp = Secp256k1::new();
+    if path.exists() {
+        let hex = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read {}", path.display()))?;
+        let secret_key = SecretKey::from_slice(&hex::decode(hex.trim())?)
+            .with_context(|| format!("{} is not a secret key", path.display()))?;
+        return Ok(Keypair::from_secret_key(&secp, &secret_key));
+    }
+
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let secret_key = SecretKey::from_slice(&bytes)?;
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("couldn't create {}", path.display()))?;
+    writeln!(file, "{}", hex::encode(secret_key.secret_bytes()))?;
+    Ok(Keypair::from_secret_key(&secp, &secret_key))
+}
+
+/// Reads the entries of an audit log (none if it doesn't exist yet).
+pub fn read(path: &Path) -> Result<Vec<AuditEntry>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let file = fs::File::open(path).with_context(|| format!("couldn't open {}", path.display()))?;
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line).context("malformed audit log entry")?);
+    }
+    Ok(entries)
+}
+
+/// Checks that the entries of an audit log chain to each other, and are all signed by the same key
+/// (`pubkey`, if given, which should be the one published by the operator).
+/// Returns the number of entries verified.
+pub fn verify(entries: &[AuditEntry], pubkey: Option<&XOnlyPublicKey>) -> Result<usize> {
+    let secp = Secp256k1::verification_only();
+    let mut signer = pubkey.map(|pubkey| hex::encode(pubkey.serialize()));
+    let mut prev_digest = hex::encode(GENESIS_DIGEST);
+    for (index, entry) in entries.iter().enumerate() {
+        ensure!(
+            entry.index == index as u64,
+            "audit log entry {index} has an unexpected index {}",
+            entry.index
+        );
+        ensure!(
+            entry.prev_digest == prev_digest,
+            "audit log entry {index} does not chain to the previous entry"
+        );
+        let digest = AuditEntry::compute(
+            entry.index,
+            entry.signed_at,
+            &entry.session,
+            &entry.prev_digest,
+        )?;
+        ensure!(
+            entry.digest == hex::encode(digest),
+            "audit log entry {index} has an incorrect digest"
+        );
+
+        let signer = signer.get_or_insert_with(|| entry.signer.clone());
+        ensure!(
+            &entry.signer == signer,
+            "audit log entry {index} is signed by another key ({})",
+            entry.signer
+        );
+        let pubkey = XOnlyPublicKey::from_slice(&hex::decode(&entry.signer)?)
+            .with_context(|| format!("audit log entry {index} has an invalid signer"))?;
+        let signature = schnorr::Signature::from_slice(&hex::decode(&entry.signature)?)
+            .with_context(|| format!("audit log entry {index} has an invalid signature"))?;
+        secp.verify_schnorr(&signature, &Message::from_digest(digest), &pubkey)
+            .with_context(|| format!("audit log entry {index} has an incorrect signature"))?;
+
+        prev_digest = entry.digest.clone();
+    }
+    Ok(entries.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute::LockTime, transaction::Version, Amount};
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn session(value: u64) -> SessionRecord {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![bitcoin::TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: bitcoin::ScriptBuf::new(),
+            }],
+        };
+        let smart_contract = SmartContract {
+            txid: Txid::all_zeros(),
+            locked_value: Amount::from_sat(value),
+            vk_hash: [0; 32],
+            state: None,
+            metadata_hash: None,
+            admin: None,
+            timelock: None,
+            recovery: None,
+            deployer: None,
+            vout_of_zkbitcoin_utxo: 0,
+        };
+        let member = frost_secp256k1_tr::Identifier::try_from(1).unwrap();
+        SessionRecord::new([1; 32], &tx, 0, &smart_contract, vec![member], &[2; 64])
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let dir = TempDir::new("zkbitcoin_audit").unwrap();
+        let audit_log = AuditLog::open(dir.path()).unwrap();
+        audit_log.append(session(1000)).unwrap();
+        audit_log.append(session(2000)).unwrap();
+
+        // the log survives restarts, with the same key
+        let pubkey = audit_log.pubkey();
+        drop(audit_log);
+        let audit_log = AuditLog::open(dir.path()).unwrap();
+        assert_eq!(audit_log.pubkey(), pubkey);
+        audit_log.append(session(3000)).unwrap();
+
+        let path = dir.path().join("audit.jsonl");
+        let entries = read(&path).unwrap();
+        assert_eq!(verify(&entries, Some(&pubkey)).unwrap(), 3);
+        assert_eq!(entries[1].session.locked_value, 2000);
+
+        // altering, removing, or re-signing entries shows
+        let mut altered = entries.clone();
+        altered[1].session.outputs[0].value = 1;
+        assert!(verify(&altered, None).is_err());
+        let mut removed = entries.clone();
+        removed.remove(1);
+        assert!(verify(&removed, None).is_err());
+        let other =
+            Keypair::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[3; 32]).unwrap());
+        assert!(verify(&entries, Some(&other.x_only_public_key().0)).is_err());
+    }
+}
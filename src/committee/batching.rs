@@ -0,0 +1,374 @@
+//! Accumulation of the requests spending zkapps in the same transaction.
+//!
+//! The proof of a request is bound to the transaction it unlocks (through its truncated txid), so the orchestrator
+//! can't merge requests made for different transactions: a transaction spending several zkapps is built by the clients
+//! (e.g. each contributing the input of their zkapp), and a request is made for each of its zkapp inputs.
+//! Clients can send all the requests of such a transaction at once (see `unlock_funds_batch`), or each send their own:
+//! with batching enabled (see [Batcher]), the orchestrator then holds the first request of a transaction for a short
+//! window, accumulating the other requests made for it, and signs all of their inputs in one go
+//! (see [super::orchestrator::Orchestrator::handle_batch]). Every request of the batch gets the same transaction,
+//! with the witnesses of all the zkapp inputs, or the error of the batch if any of its requests was rejected.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bitcoin::{Transaction, Txid};
+use tokio::sync::{watch, Notify};
+
+use crate::bob_request::{BobRequest, BobResponse};
+
+//
+// Constants
+//
+
+/// The most requests accumulated for the same transaction (by default).
+pub const DEFAULT_MAX_BATCH: usize = 16;
+
+//
+// Data structures
+//
+
+/// What requests are accumulated by.
+pub trait BatchItem: Send {
+    /// The transaction the request is made for.
+    fn txid(&self) -> Txid;
+
+    /// The input of the transaction the request unlocks.
+    fn input(&self) -> usize;
+
+    /// Identifies the request, so that a request sent again joins the batch it's already in.
+    fn request_hash(&self) -> [u8; 32];
+}
+
+impl BatchItem for BobRequest {
+    fn txid(&self) -> Txid {
+        self.tx.txid()
+    }
+
+    fn input(&self) -> usize {
+        self.zkapp_input
+    }
+
+    fn request_hash(&self) -> [u8; 32] {
+        self.hash()
+    }
+}
+
+/// The outcome of a batch, shared by all of its requests.
+type Outcome = Option<Result<BobResponse, String>>;
+
+/// The requests accumulated for a transaction, until the window closes.
+struct Pending<R> {
+    requests: Vec<R>,
+    full: Arc<Notify>,
+    outcome: watch::Receiver<Outcome>,
+}
+
+/// Accumulates the requests made for the same transaction, and handles them together.
+pub struct Batcher<R = BobRequest> {
+    /// How long the first request of a transaction waits for the other ones.
+    pub window: Duration,
+
+    /// The most requests accumulated for a transaction (the batch is handled as soon as it has that many).
+    pub max_batch: usize,
+
+    pending: Mutex<HashMap<Txid, Pending<R>>>,
+}
+
+/// Forgets the requests accumulated for a transaction if the request they wait on is dropped,
+/// so that the next request for the transaction starts a new batch.
+struct PendingGuard<'a, R> {
+    pending: &'a Mutex<HashMap<Txid, Pending<R>>>,
+    txid: Txid,
+}
+
+impl<R> Drop for PendingGuard<'_, R> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.txid);
+    }
+}
+
+impl<R: BatchItem> Batcher<R> {
+    pub fn new(window: Duration, max_batch: usize) -> Self {
+        Self {
+            window,
+            max_batch: max_batch.max(1),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Adds a request to the batch of its transaction, and returns the outcome of the batch.
+    /// The first request of a transaction waits for the other ones, then has `handle` handle the whole batch.
+    pub async fn submit<F, Fut>(&self, request: R, handle: F) -> Result<BobResponse>
+    where
+        F: FnOnce(Vec<R>) -> Fut,
+        Fut: Future<Output = Result<BobResponse>>,
+    {
+        let txid = request.txid();
+        let (full, outcome_sender) = {
+            let mut pending = self.pending.lock().unwrap();
+            if let Some(batch) = pending.get_mut(&txid) {
+                let outcome = batch.outcome.clone();
+                let request_hash = request.request_hash();
+                match batch.requests.iter().find(|r| r.input() == request.input()) {
+                    Some(batched) if batched.request_hash() == request_hash => (),
+                    Some(_) => bail!(
+                        "another request for input {} of {txid} is already batched",
+                        request.input()
+                    ),
+                    None => {
+                        batch.requests.push(request);
+                        if batch.requests.len() >= self.max_batch {
+                            batch.full.notify_one();
+                        }
+                    }
+                }
+                drop(pending);
+                return wait_for(outcome).await;
+            }
+
+            let full = Arc::new(Notify::new());
+            let (outcome_sender, outcome) = watch::channel(None);
+            pending.insert(
+                txid,
+                Pending {
+                    requests: vec![request],
+                    full: full.clone(),
+                    outcome,
+                },
+            );
+            (full, outcome_sender)
+        };
+
+        // wait for the other requests of the transaction (unless the batch is already full)
+        let guard = PendingGuard {
+            pending: &self.pending,
+            txid,
+        };
+        if self.max_batch > 1 {
+            let _ = tokio::time::timeout(self.window, full.notified()).await;
+        }
+        let requests = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&txid)
+            .map(|batch| batch.requests)
+            .context("the batch is gone")?;
+        drop(guard);
+
+        let res = handle(requests).await;
+        // the other requests might have been dropped
+        let _ = outcome_sender.send(Some(res.as_ref().cloned().map_err(|e| format!("{e:#}"))));
+        res
+    }
+}
+
+/// Waits for the outcome of the batch a request joined.
+async fn wait_for(mut outcome: watch::Receiver<Outcome>) -> Result<BobResponse> {
+    loop {
+        if let Some(outcome) = outcome.borrow_and_update().clone() {
+            return outcome.map_err(|err| anyhow!("the batch of the request failed: {err}"));
+        }
+        outcome
+            .changed()
+            .await
+            .ok()
+            .context("the batch of the request was dropped")?;
+    }
+}
+
+/// Returns `tx` with the witnesses of the inputs unlocked separately (each given along with the transaction unlocking it).
+pub fn combine_witnesses(
+    tx: &Transaction,
+    unlocked: impl IntoIterator<Item = (usize, Transaction)>,
+) -> Result<Transaction> {
+    let mut transaction = tx.clone();
+    for (input, unlocked_tx) in unlocked {
+        let witness = unlocked_tx
+            .input
+            .get(input)
+            .context("couldn't find zkapp input in unlocked transaction")?
+            .witness
+            .clone();
+        transaction
+            .input
+            .get_mut(input)
+            .context("couldn't find zkapp input in transaction")?
+            .witness = witness;
+    }
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use bitcoin::{absolute::LockTime, hashes::Hash, transaction, OutPoint, TxIn, Witness};
+
+    use super::*;
+
+    struct TestRequest {
+        txid: Txid,
+        input: usize,
+        id: u8,
+        valid: bool,
+    }
+
+    impl BatchItem for TestRequest {
+        fn txid(&self) -> Txid {
+            self.txid
+        }
+
+        fn input(&self) -> usize {
+            self.input
+        }
+
+        fn request_hash(&self) -> [u8; 32] {
+            [self.id; 32]
+        }
+    }
+
+    fn transaction(inputs: usize) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: (0..inputs)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(Txid::all_zeros(), vout as u32),
+                    ..TxIn::default()
+                })
+                .collect(),
+            output: vec![],
+        }
+    }
+
+    /// Handles a batch like the orchestrator does, failing it if any of its requests is invalid.
+    async fn handle(
+        tx: &Transaction,
+        requests: Vec<TestRequest>,
+        handled: &AtomicUsize,
+    ) -> Result<BobResponse> {
+        handled.fetch_add(1, Ordering::SeqCst);
+        if let Some(invalid) = requests.iter().find(|r| !r.valid) {
+            bail!("input {} didn't validate", invalid.input);
+        }
+        let unlocked = requests.iter().map(|r| {
+            let mut unlocked_tx = tx.clone();
+            unlocked_tx.input[r.input].witness = Witness::from_slice(&[vec![r.input as u8]]);
+            (r.input, unlocked_tx)
+        });
+        Ok(BobResponse {
+            unlocked_tx: combine_witnesses(tx, unlocked)?,
+        })
+    }
+
+    #[test]
+    fn test_combine_witnesses() {
+        let tx = transaction(3);
+        let mut first = tx.clone();
+        first.input[0].witness = Witness::from_slice(&[vec![0]]);
+        let mut second = tx.clone();
+        second.input[2].witness = Witness::from_slice(&[vec![2]]);
+
+        let combined = combine_witnesses(&tx, [(0, first), (2, second)]).unwrap();
+        assert_eq!(combined.input[0].witness.to_vec(), vec![vec![0]]);
+        assert!(combined.input[1].witness.is_empty());
+        assert_eq!(combined.input[2].witness.to_vec(), vec![vec![2]]);
+
+        assert!(combine_witnesses(&tx, [(3, tx.clone())]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_batch_of_several_requests() {
+        let batcher = Batcher::new(Duration::from_secs(60), 3);
+        let tx = transaction(3);
+        let handled = AtomicUsize::new(0);
+        let submit = |input| {
+            let request = TestRequest {
+                txid: tx.txid(),
+                input,
+                id: input as u8,
+                valid: true,
+            };
+            batcher.submit(request, |requests| handle(&tx, requests, &handled))
+        };
+
+        // the batch is handled once, as soon as it's full, and every request gets all the witnesses
+        let responses = futures::future::try_join_all([submit(0), submit(1), submit(2)])
+            .await
+            .unwrap();
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+        for BobResponse { unlocked_tx } in responses {
+            for (input, txin) in unlocked_tx.input.iter().enumerate() {
+                assert_eq!(txin.witness.to_vec(), vec![vec![input as u8]]);
+            }
+        }
+
+        // a request left alone is handled once the window closes
+        let batcher = Batcher::new(Duration::from_millis(10), 3);
+        let request = TestRequest {
+            txid: tx.txid(),
+            input: 1,
+            id: 1,
+            valid: true,
+        };
+        let BobResponse { unlocked_tx } = batcher
+            .submit(request, |requests| handle(&tx, requests, &handled))
+            .await
+            .unwrap();
+        assert_eq!(handled.load(Ordering::SeqCst), 2);
+        assert!(unlocked_tx.input[0].witness.is_empty());
+        assert_eq!(unlocked_tx.input[1].witness.to_vec(), vec![vec![1]]);
+    }
+
+    #[tokio::test]
+    async fn test_batch_with_failing_request() {
+        let batcher = Batcher::new(Duration::from_secs(60), 2);
+        let tx = transaction(2);
+        let handled = AtomicUsize::new(0);
+        let submit = |input, valid| {
+            let request = TestRequest {
+                txid: tx.txid(),
+                input,
+                id: input as u8,
+                valid,
+            };
+            batcher.submit(request, |requests| handle(&tx, requests, &handled))
+        };
+
+        // the whole batch fails with the request that didn't validate
+        let (first, second) = tokio::join!(submit(0, true), submit(1, false));
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+        for res in [first, second] {
+            assert!(format!("{:#}", res.unwrap_err()).contains("input 1 didn't validate"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_requests() {
+        let batcher = Batcher::new(Duration::from_millis(10), 3);
+        let tx = transaction(1);
+        let handled = AtomicUsize::new(0);
+        let submit = |id| {
+            let request = TestRequest {
+                txid: tx.txid(),
+                input: 0,
+                id,
+                valid: true,
+            };
+            batcher.submit(request, |requests| handle(&tx, requests, &handled))
+        };
+
+        // a request sent again joins its batch, but another request can't spend the same input
+        let (first, again, conflicting) = tokio::join!(submit(1), submit(1), submit(2));
+        assert_eq!(first.unwrap().unlocked_tx, again.unwrap().unlocked_tx);
+        assert!(conflicting.is_err());
+        assert_eq!(handled.load(Ordering::SeqCst), 1);
+    }
+}
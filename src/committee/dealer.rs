@@ -0,0 +1,184 @@
+//! Generating a committee with a trusted dealer (what `zkbtc generate-committee` does).
+//! Ideally this is just used for testing as it is more secure to do a DKG.
+
+use std::{collections::BTreeMap, fs::File, path::Path};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use frost_secp256k1_tr as frost_tr;
+use rand::{thread_rng, CryptoRng, RngCore};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    committee::{
+        orchestrator::{CommitteeConfig, Member},
+        signer::{
+            self, even_y_key_package, even_y_pubkey_package, CommitteeKey, KeyShare,
+            SignatureAggregator, SignerKind,
+        },
+    },
+    frost,
+};
+
+/// The prefix of the addresses of the members of a generated committee, followed by their index.
+const MEMBER_ADDRESS_PREFIX: &str = "http://127.0.0.1:889";
+
+/// The keys and configuration of a committee (see [generate]).
+#[derive(Debug, Clone)]
+pub struct GeneratedCommittee {
+    /// The key package of every member.
+    pub key_packages: BTreeMap<frost_tr::Identifier, KeyShare>,
+
+    /// The public key package of the committee.
+    pub pubkey_package: CommitteeKey,
+
+    /// The configuration of the orchestrator, with members listening on `127.0.0.1:8890` onwards.
+    pub config: CommitteeConfig,
+}
+
+/// Deals the keys of a `threshold`-of-`num` committee signing with `signer`
+/// (MuSig2 committees being `num`-of-`num` ones).
+pub fn generate(
+    num: u16,
+    threshold: u16,
+    min_confirmations: u32,
+    signer: SignerKind,
+) -> Result<GeneratedCommittee> {
+    generate_with_rng(num, threshold, min_confirmations, signer, &mut thread_rng())
+}
+
+/// Same as [generate], with the given source of randomness
+/// (a seeded one gives the same committee every time).
+pub fn generate_with_rng(
+    num: u16,
+    threshold: u16,
+    min_confirmations: u32,
+    signer: SignerKind,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<GeneratedCommittee> {
+    ensure!(
+        signer != SignerKind::Musig2 || threshold == num,
+        "MuSig2 committees sign with all of their {num} members, so their threshold can't be {threshold}"
+    );
+
+    // the committee key is taken with an even Y coordinate, whatever the one dealt (see [signer::even_y])
+    let (key_packages, pubkey_package): (BTreeMap<_, KeyShare>, CommitteeKey) = match signer {
+        SignerKind::Frost => {
+            let (key_packages, pubkey_package) =
+                frost::gen_frost_keys_with_rng(num, threshold, &mut *rng)
+                    .map_err(|err| anyhow!("couldn't deal the keys of the committee: {err}"))?;
+            let key_packages = key_packages
+                .iter()
+                .map(|(id, key_package)| Ok((*id, even_y_key_package(key_package)?.into())))
+                .collect::<Result<_>>()?;
+            (key_packages, even_y_pubkey_package(&pubkey_package)?.into())
+        }
+        SignerKind::Musig2 => {
+            let (key_shares, committee_key) = signer::gen_musig2_keys(num, &mut *rng)?;
+            let key_shares = key_shares
+                .into_iter()
+                .map(|(id, key_share)| (id, KeyShare::Musig2(key_share)))
+                .collect();
+            (key_shares, CommitteeKey::Musig2(committee_key))
+        }
+    };
+
+    let config = CommitteeConfig {
+        threshold: threshold as usize,
+        members: key_packages
+            .keys()
+            .enumerate()
+            .map(|(id, member_id)| {
+                (
+                    *member_id,
+                    Member {
+                        address: format!("{MEMBER_ADDRESS_PREFIX}{id}"),
+                        http2: false,
+                    },
+                )
+            })
+            .collect(),
+        min_confirmations,
+        fee_schedule: None,
+        lightning_fees: false,
+        emergency_sweep_descriptor: None,
+        signer,
+        max_spend_sats: None,
+        governance: false,
+        replay_protection: false,
+    };
+
+    Ok(GeneratedCommittee {
+        key_packages,
+        pubkey_package,
+        config,
+    })
+}
+
+impl GeneratedCommittee {
+    /// The public key of the committee.
+    pub fn pubkey(&self) -> [u8; 33] {
+        self.pubkey_package
+            .pubkey()
+            .expect("the dealt committee key is a valid public key")
+            .inner
+            .serialize()
+    }
+
+    /// Writes `key-{id}.json` for every member, `publickey-package.json`, and `committee-cfg.json` to `output_dir`.
+    pub fn save(&self, output_dir: &Path) -> Result<()> {
+        for (id, key_package) in self.key_packages.values().enumerate() {
+            save_json(&output_dir.join(format!("key-{id}.json")), key_package)?;
+        }
+        save_json(
+            &output_dir.join("publickey-package.json"),
+            &self.pubkey_package,
+        )?;
+        save_json(&output_dir.join("committee-cfg.json"), &self.config)
+    }
+}
+
+fn save_json(path: &Path, value: &impl Serialize) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("couldn't create {}", path.display()))?;
+    serde_json::to_writer_pretty(file, value)
+        .with_context(|| format!("couldn't write {}", path.display()))
+}
+
+/// Reads one of the files written by [GeneratedCommittee::save].
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let file = File::open(path).with_context(|| format!("couldn't open {}", path.display()))?;
+    serde_json::from_reader(file).with_context(|| format!("couldn't parse {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let committee = generate(3, 2, 1, SignerKind::Frost).unwrap();
+        assert_eq!(committee.pubkey()[0], 2);
+        assert_eq!(committee.key_packages.len(), 3);
+        assert_eq!(committee.config.members.len(), 3);
+        assert_eq!(committee.config.threshold, 2);
+
+        let output_dir = tempdir::TempDir::new("zkbitcoin_committee").unwrap();
+        committee.save(output_dir.path()).unwrap();
+        let config: CommitteeConfig =
+            load_json(&output_dir.path().join("committee-cfg.json")).unwrap();
+        assert_eq!(config.min_confirmations, 1);
+        let _: frost::KeyPackage = load_json(&output_dir.path().join("key-2.json")).unwrap();
+    }
+
+    #[test]
+    fn test_generate_musig2() {
+        assert!(generate(3, 2, 0, SignerKind::Musig2).is_err());
+        let committee = generate(3, 3, 0, SignerKind::Musig2).unwrap();
+        assert_eq!(committee.pubkey()[0], 2);
+        assert_eq!(committee.config.signer, SignerKind::Musig2);
+
+        let output_dir = tempdir::TempDir::new("zkbitcoin_committee").unwrap();
+        committee.save(output_dir.path()).unwrap();
+        let key_share: KeyShare = load_json(&output_dir.path().join("key-0.json")).unwrap();
+        assert!(matches!(key_share, KeyShare::Musig2(_)));
+    }
+}
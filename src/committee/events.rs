@@ -0,0 +1,116 @@
+//! Events emitted by the orchestrator as it handles requests,
+//! streamed over WebSocket (see the `subscribe_events` method of the orchestrator)
+//! so that wallets and dashboards can show the progress of a spend in real time.
+//! The events of every spend are only streamed to operators (see `subscribe_all_events` on the admin API).
+
+use std::time::Duration;
+
+use bitcoin::Txid;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::json_rpc_stuff::{get_raw_transaction_info, RpcCtx};
+
+//
+// Constants
+//
+
+/// The number of events kept for subscribers that are lagging behind.
+pub const EVENT_CAPACITY: usize = 1024;
+
+/// Interval (in seconds) at which the node is asked whether a signed transaction was broadcast.
+const BROADCAST_POLL_INTERVAL: u64 = 5;
+
+/// How long (in seconds) to wait for a signed transaction to be broadcast.
+const BROADCAST_TIMEOUT: u64 = 30 * 60;
+
+//
+// Events
+//
+
+/// An event of the signing session of a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Event {
+    /// The transaction spending the zkapp.
+    pub txid: Txid,
+
+    /// The transaction that created the zkapp being spent.
+    pub zkapp_txid: Txid,
+
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    /// The orchestrator received a request to spend the zkapp.
+    RequestReceived,
+
+    /// The request (and its proof) validated.
+    ProofVerified,
+
+    /// The committee members committed to their nonces.
+    Round1Complete,
+
+    /// The committee signed the transaction.
+    Signed,
+
+    /// The signed transaction showed up in the mempool (or chain) of the node the orchestrator follows.
+    Broadcast,
+
+    /// The request was refused, or the signing session failed.
+    Failed { error: String },
+}
+
+/// Waits (for a while) for a signed transaction to be broadcast,
+/// and emits a [EventKind::Broadcast] event once the node knows about it.
+pub fn watch_broadcast(
+    rpc_ctx: RpcCtx,
+    events: broadcast::Sender<Event>,
+    txid: Txid,
+    zkapp_txid: Txid,
+) {
+    tokio::spawn(async move {
+        let attempts = BROADCAST_TIMEOUT / BROADCAST_POLL_INTERVAL;
+        for _ in 0..attempts {
+            tokio::time::sleep(Duration::from_secs(BROADCAST_POLL_INTERVAL)).await;
+            if get_raw_transaction_info(&rpc_ctx, txid).await.is_ok() {
+                let _ = events.send(Event {
+                    txid,
+                    zkapp_txid,
+                    kind: EventKind::Broadcast,
+                });
+                return;
+            }
+        }
+        debug!("- transaction {txid} wasn't broadcast within {BROADCAST_TIMEOUT} seconds");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn test_event_serialization() {
+        let txid =
+            Txid::from_str("e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836")
+                .unwrap();
+        let event = Event {
+            txid,
+            zkapp_txid: txid,
+            kind: EventKind::Failed {
+                error: "nope".to_string(),
+            },
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["event"], "failed");
+        assert_eq!(json["error"], "nope");
+        assert_eq!(json["txid"], txid.to_string());
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), event);
+    }
+}
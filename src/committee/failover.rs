@@ -0,0 +1,296 @@
+//! Failover between orchestrator instances.
+//!
+//! Several orchestrators can share the same storage directory (e.g. on a network file system),
+//! with only one of them serving requests at a time: the leader, which holds a [Lease] in `leader.json`
+//! and renews it regularly (see [LeaderElection::keep]). The other instances wait on standby
+//! (see [LeaderElection::acquire]), and take over once the lease expires, which happens when the leader crashes.
+//!
+//! The requests the leader was handling when it crashed are kept in the storage until they complete,
+//! so that the new leader can resume them (see [super::orchestrator::Orchestrator::resume_pending]).
+//! Leases expire based on the clocks of the instances, which must thus be (roughly) in sync.
+
+use std::{
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::storage::now;
+
+//
+// Constants
+//
+
+/// How long (in seconds) a lease lasts, by default.
+pub const DEFAULT_LEASE_DURATION: u64 = 15;
+
+/// The file holding the lease of the leader.
+const LEASE_FILE: &str = "leader.json";
+
+/// The file that serializes updates to the lease.
+const LOCK_FILE: &str = "leader.lock";
+
+/// After how long the lock is considered abandoned by a crashed instance.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+//
+// Data structures
+//
+
+/// The lease of the orchestrator instance currently serving requests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lease {
+    /// The identifier of the instance holding the lease.
+    pub instance: String,
+
+    /// The address the instance serves requests at.
+    pub address: String,
+
+    /// When (as a UNIX timestamp) the lease expires, if it isn't renewed.
+    pub expires_at: u64,
+}
+
+impl Lease {
+    fn expired(&self, now: u64) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// Elects a single leader among the orchestrator instances sharing a storage directory.
+pub struct LeaderElection {
+    dir: PathBuf,
+    instance: String,
+    address: String,
+    duration: Duration,
+}
+
+impl LeaderElection {
+    pub fn new(dir: &Path, instance: String, address: String, duration: Duration) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            instance,
+            address,
+            duration,
+        }
+    }
+
+    fn lease_path(&self) -> PathBuf {
+        self.dir.join(LEASE_FILE)
+    }
+
+    fn lock_path(&self) -> PathBuf {
+        self.dir.join(LOCK_FILE)
+    }
+
+    /// How often the lease is renewed (or, on standby, checked).
+    fn poll_interval(&self) -> Duration {
+        self.duration / 3
+    }
+
+    /// Runs `f` while holding the lock on the lease.
+    fn with_lock<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let lock_path = self.lock_path();
+        if let Err(err) = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            // the lock is only held for an instant, unless its holder crashed
+            let abandoned = fs::metadata(&lock_path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                .is_some_and(|age| age > LOCK_TIMEOUT);
+            if !abandoned {
+                return Err(err).context("the lease is being updated by another instance");
+            }
+            warn!("- taking over the abandoned lock {}", lock_path.display());
+            fs::remove_file(&lock_path)?;
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&lock_path)
+                .context("couldn't lock the lease")?;
+        }
+
+        let res = f();
+        fs::remove_file(&lock_path).context("couldn't unlock the lease")?;
+        res
+    }
+
+    fn read_lease(&self) -> Result<Option<Lease>> {
+        let path = self.lease_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let lease = fs::read_to_string(&path).context("couldn't read the lease")?;
+        Ok(Some(
+            serde_json::from_str(&lease).context("malformed lease")?,
+        ))
+    }
+
+    fn write_lease(&self, lease: &Lease) -> Result<()> {
+        // write then rename, so that the lease is never half-written
+        let tmp_path = self.dir.join(format!("{LEASE_FILE}.tmp"));
+        fs::write(&tmp_path, serde_json::to_string(lease)?).context("couldn't write the lease")?;
+        fs::rename(tmp_path, self.lease_path()).context("couldn't write the lease")?;
+        Ok(())
+    }
+
+    /// Returns the current leader, if its lease hasn't expired.
+    pub fn leader(&self) -> Result<Option<Lease>> {
+        Ok(self.read_lease()?.filter(|lease| !lease.expired(now())))
+    }
+
+    /// Takes (or renews) the lease, unless another instance holds it.
+    /// Returns whether this instance is the leader.
+    pub fn try_acquire(&self) -> Result<bool> {
+        self.with_lock(|| {
+            let now = now();
+            if let Some(lease) = self.read_lease()? {
+                if lease.instance != self.instance && !lease.expired(now) {
+                    return Ok(false);
+                }
+            }
+            self.write_lease(&Lease {
+                instance: self.instance.clone(),
+                address: self.address.clone(),
+                expires_at: now + self.duration.as_secs(),
+            })?;
+            Ok(true)
+        })
+    }
+
+    /// Waits on standby until this instance becomes the leader.
+    pub async fn acquire(&self) -> Result<()> {
+        let mut standby = false;
+        loop {
+            match self.try_acquire() {
+                Ok(true) => break,
+                Ok(false) => {
+                    if !standby {
+                        if let Some(lease) = self.leader()? {
+                            info!(
+                                "- on standby, {} is the leader at {}",
+                                lease.instance, lease.address
+                            );
+                        }
+                        standby = true;
+                    }
+                }
+                Err(err) => warn!("- couldn't take the lease: {err:#}"),
+            }
+            tokio::time::sleep(self.poll_interval()).await;
+        }
+
+        info!("- {} is now the leader", self.instance);
+        Ok(())
+    }
+
+    /// Keeps renewing the lease, and only returns if it's lost.
+    /// The leader must then stop serving requests, as another instance might take over.
+    pub async fn keep(&self) -> Result<()> {
+        let mut expires_at = now() + self.duration.as_secs();
+        loop {
+            tokio::time::sleep(self.poll_interval()).await;
+            match self.try_acquire() {
+                Ok(true) => expires_at = now() + self.duration.as_secs(),
+                Ok(false) => {
+                    let leader = self.leader()?.map(|lease| lease.instance);
+                    bail!(
+                        "the lease was taken over by {}",
+                        leader.as_deref().unwrap_or("another instance")
+                    );
+                }
+                // the storage might be briefly unreachable, but the lease might not be ours anymore once it expires
+                Err(err) if now() < expires_at => warn!("- couldn't renew the lease: {err:#}"),
+                Err(err) => return Err(err).context("the lease expired"),
+            }
+        }
+    }
+
+    /// Gives up the lease (if this instance holds it), so that a standby instance can take over right away.
+    pub fn release(&self) -> Result<()> {
+        self.with_lock(|| {
+            if let Some(lease) = self.read_lease()? {
+                if lease.instance == self.instance {
+                    fs::remove_file(self.lease_path()).context("couldn't remove the lease")?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn election(dir: &Path, instance: &str) -> LeaderElection {
+        LeaderElection::new(
+            dir,
+            instance.to_string(),
+            format!("{instance}:6666"),
+            Duration::from_secs(DEFAULT_LEASE_DURATION),
+        )
+    }
+
+    #[test]
+    fn test_single_leader() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        let first = election(tmp_dir.path(), "first");
+        let second = election(tmp_dir.path(), "second");
+        assert!(first.leader().unwrap().is_none());
+
+        assert!(first.try_acquire().unwrap());
+        assert!(!second.try_acquire().unwrap());
+        // the leader can renew its lease
+        assert!(first.try_acquire().unwrap());
+        assert_eq!(second.leader().unwrap().unwrap().instance, "first");
+
+        // releasing the lease lets the other instance take over
+        first.release().unwrap();
+        assert!(second.try_acquire().unwrap());
+        assert!(!first.try_acquire().unwrap());
+    }
+
+    #[test]
+    fn test_expired_lease() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        let first = election(tmp_dir.path(), "first");
+        let second = election(tmp_dir.path(), "second");
+        assert!(first.try_acquire().unwrap());
+
+        // the leader crashed, and didn't renew its lease
+        first
+            .write_lease(&Lease {
+                instance: "first".to_string(),
+                address: "first:6666".to_string(),
+                expires_at: now() - 1,
+            })
+            .unwrap();
+        assert!(first.leader().unwrap().is_none());
+        assert!(second.try_acquire().unwrap());
+        assert_eq!(first.leader().unwrap().unwrap().instance, "second");
+    }
+
+    #[test]
+    fn test_lock() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        let first = election(tmp_dir.path(), "first");
+
+        // another instance is updating the lease
+        fs::write(first.lock_path(), "").unwrap();
+        assert!(first.try_acquire().is_err());
+
+        fs::remove_file(first.lock_path()).unwrap();
+        assert!(first.try_acquire().unwrap());
+        assert!(!first.lock_path().exists());
+    }
+}
@@ -0,0 +1,399 @@
+//! Accounting of the fees collected by the committee.
+//!
+//! Every transaction signed by the orchestrator pays a fee to the zkBitcoin fund (`ZKBITCOIN_FEE_PUBKEY`),
+//! which is recorded in the orchestrator's storage (see [super::storage::Storage::record_fee]).
+//! Operators can then export, for each zkapp and each day, how many requests were signed and what they paid
+//! (see `zkbtc fees report`).
+//!
+//! How much is owed is set by the committee (see [FeeSchedule]), and checked by the orchestrator and every node
+//! before signing (see [FeesOwed]). It can also be paid over Lightning instead (see [super::lightning]).
+//!
+//! The zkapps spent by the same transaction (see [super::batching]) share its fee output,
+//! so the transaction has to pay the fees owed by all of its zkapp inputs.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{Amount, Transaction, Txid};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bob_request::BobRequest,
+    config::{protocol_config, FeeSchedule},
+    taproot_addr_from,
+};
+
+/// Number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// How many transactions the fees owed by their zkapp inputs are remembered for (by default),
+/// the oldest ones being forgotten first.
+pub const DEFAULT_MAX_FEE_TXS: usize = 100_000;
+
+//
+// Data structures
+//
+
+/// The fee paid by a request signed by the committee.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeRecord {
+    /// When the request was signed (UNIX timestamp in seconds).
+    pub collected_at: u64,
+
+    /// The transaction signed.
+    pub txid: Txid,
+
+    /// The transaction that deployed (or last updated) the zkapp.
+    pub zkapp_txid: Txid,
+
+    /// The hex-encoded hash of the zkapp's verifier key.
+    pub vk_hash: String,
+
+    /// The fee paid to the zkBitcoin fund.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub fee: Amount,
+}
+
+impl FeeRecord {
+    /// Records the fees paid by the (validated) requests of a signed transaction.
+    /// Requests batched in the same transaction share its fee, which is split evenly between them
+    /// (the first one paying the remainder).
+    pub fn for_requests(bob_requests: &[BobRequest], collected_at: u64) -> Result<Vec<Self>> {
+        let first = bob_requests.first().context("no request to record")?;
+        let fee = fee_paid(&first.tx)?;
+        let count = bob_requests.len() as u64;
+        let share = Amount::from_sat(fee.to_sat() / count);
+        let remainder = Amount::from_sat(fee.to_sat() % count);
+
+        bob_requests
+            .iter()
+            .enumerate()
+            .map(|(i, bob_request)| {
+                Ok(Self {
+                    collected_at,
+                    txid: bob_request.tx.txid(),
+                    zkapp_txid: bob_request.txid()?,
+                    vk_hash: hex::encode(bob_request.vk.hash()),
+                    fee: if i == 0 { share + remainder } else { share },
+                })
+            })
+            .collect()
+    }
+}
+
+/// The fees paid by a transaction to the zkBitcoin fund.
+pub fn fee_paid(tx: &Transaction) -> Result<Amount> {
+    let fee_script = taproot_addr_from(&protocol_config().zkbitcoin_fee_pubkey)?.script_pubkey();
+    Ok(tx
+        .output
+        .iter()
+        .filter(|output| output.script_pubkey == fee_script)
+        .map(|output| output.value)
+        .sum())
+}
+
+/// What a request withdraws to its recipients.
+pub fn withdrawn(bob_request: &BobRequest) -> Amount {
+    bob_request
+        .recipients
+        .iter()
+        .map(|output| output.value)
+        .sum()
+}
+
+/// The fee of the committee's schedule, for what a request withdraws to its recipients.
+pub fn fee_owed(schedule: &FeeSchedule, bob_request: &BobRequest) -> Amount {
+    schedule.fee(withdrawn(bob_request))
+}
+
+/// Ensures that the transaction shared by a batch of requests pays the fees owed by all of them
+/// (except those paid over Lightning).
+pub fn check_batch_fee(schedule: &FeeSchedule, bob_requests: &[BobRequest]) -> Result<()> {
+    let Some(first) = bob_requests.first() else {
+        return Ok(());
+    };
+    let owed = bob_requests
+        .iter()
+        .filter(|bob_request| bob_request.fee_payment_hash.is_none())
+        .map(|bob_request| fee_owed(schedule, bob_request))
+        .sum();
+    ensure_paid(first.tx.txid(), fee_paid(&first.tx)?, owed)
+}
+
+fn ensure_paid(txid: Txid, paid: Amount, owed: Amount) -> Result<()> {
+    ensure!(
+        paid >= owed,
+        "transaction {txid} pays a fee of {paid} to the zkBitcoin fund, but the committee takes {owed} on its zkapp inputs"
+    );
+    Ok(())
+}
+
+#[derive(Default)]
+struct Owed {
+    /// The fee owed by each zkapp input checked, by transaction.
+    inputs: HashMap<Txid, BTreeMap<usize, Amount>>,
+    /// The transactions, oldest first.
+    order: VecDeque<Txid>,
+}
+
+/// The fees owed by the zkapp inputs of the transactions checked lately.
+/// The requests of a batch are signed one by one, so each of them is checked against the fees owed
+/// by all the zkapp inputs of its transaction checked so far (see [FeesOwed::check]).
+pub struct FeesOwed {
+    max_txs: usize,
+    owed: Mutex<Owed>,
+}
+
+impl Default for FeesOwed {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_FEE_TXS)
+    }
+}
+
+impl FeesOwed {
+    pub fn new(max_txs: usize) -> Self {
+        Self {
+            max_txs: max_txs.max(1),
+            owed: Mutex::new(Owed::default()),
+        }
+    }
+
+    /// Ensures that the transaction of a request pays the fee of the committee's schedule for what the request withdraws,
+    /// on top of the fees owed by the other zkapp inputs of the transaction, and records it.
+    pub fn check(&self, schedule: &FeeSchedule, bob_request: &BobRequest) -> Result<()> {
+        self.record(
+            bob_request.tx.txid(),
+            bob_request.zkapp_input,
+            fee_owed(schedule, bob_request),
+            fee_paid(&bob_request.tx)?,
+        )
+    }
+
+    /// Records the fee owed by a zkapp input of a transaction paying `paid`,
+    /// unless the transaction doesn't pay for all of its zkapp inputs.
+    fn record(&self, txid: Txid, input: usize, owed: Amount, paid: Amount) -> Result<()> {
+        let mut fees = self.owed.lock().unwrap();
+        let others: Amount = fees
+            .inputs
+            .get(&txid)
+            .into_iter()
+            .flatten()
+            .filter(|(other, _)| **other != input)
+            .map(|(_, owed)| *owed)
+            .sum();
+        ensure_paid(txid, paid, others + owed)?;
+
+        if !fees.inputs.contains_key(&txid) {
+            if fees.order.len() >= self.max_txs {
+                if let Some(oldest) = fees.order.pop_front() {
+                    fees.inputs.remove(&oldest);
+                }
+            }
+            fees.order.push_back(txid);
+        }
+        fees.inputs.entry(txid).or_default().insert(input, owed);
+        Ok(())
+    }
+}
+
+/// The fees collected from a zkapp on a day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeRow {
+    /// The day (as `YYYY-MM-DD`, in UTC).
+    pub day: String,
+
+    /// The hex-encoded hash of the zkapp's verifier key.
+    pub vk_hash: String,
+
+    /// The number of requests signed.
+    pub requests: u64,
+
+    /// The fees collected.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub fees: Amount,
+}
+
+//
+// Reports
+//
+
+/// Sums the fees collected per zkapp and per day, between `from` and `to` (UNIX timestamps, inclusive and exclusive).
+pub fn report(records: &[FeeRecord], from: Option<u64>, to: Option<u64>) -> Vec<FeeRow> {
+    let mut rows: BTreeMap<(u64, &str), FeeRow> = BTreeMap::new();
+    for record in records {
+        if from.is_some_and(|from| record.collected_at < from)
+            || to.is_some_and(|to| record.collected_at >= to)
+        {
+            continue;
+        }
+        let day = record.collected_at / SECONDS_PER_DAY;
+        let row = rows
+            .entry((day, record.vk_hash.as_str()))
+            .or_insert_with(|| FeeRow {
+                day: format_day(day),
+                vk_hash: record.vk_hash.clone(),
+                requests: 0,
+                fees: Amount::ZERO,
+            });
+        row.requests += 1;
+        row.fees += record.fee;
+    }
+    rows.into_values().collect()
+}
+
+/// Formats a report as CSV (with a header).
+pub fn to_csv(rows: &[FeeRow]) -> String {
+    let mut csv = "day,vk_hash,requests,fees_sat\n".to_string();
+    for row in rows {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            row.day,
+            row.vk_hash,
+            row.requests,
+            row.fees.to_sat()
+        ));
+    }
+    csv
+}
+
+//
+// Dates
+//
+
+/// Parses a day (as `YYYY-MM-DD`, in UTC) into the UNIX timestamp of its start.
+pub fn parse_day(day: &str) -> Result<u64> {
+    let parse = || -> Option<(i64, u64, u64)> {
+        let mut parts = day.splitn(3, '-');
+        let year = parts.next()?.parse().ok()?;
+        let month = parts.next()?.parse().ok()?;
+        let day = parts.next()?.parse().ok()?;
+        Some((year, month, day))
+    };
+    let (year, month, day_of_month) =
+        parse().with_context(|| format!("{day} is not a day (expected YYYY-MM-DD)"))?;
+    ensure!(
+        (1..=12).contains(&month) && (1..=31).contains(&day_of_month),
+        "{day} is not a day (expected YYYY-MM-DD)"
+    );
+    let days = days_from_civil(year, month, day_of_month);
+    ensure!(days >= 0, "{day} is before 1970");
+    Ok(days as u64 * SECONDS_PER_DAY)
+}
+
+/// Formats the day (counted from the UNIX epoch) as `YYYY-MM-DD`.
+fn format_day(days: u64) -> String {
+    let (year, month, day) = civil_from_days(days as i64);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+// see http://howardhinnant.github.io/date_algorithms.html
+
+fn days_from_civil(year: i64, month: u64, day: u64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400) as u64;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era as i64 - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u64, u64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097) as u64;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era as i64 + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    fn record(collected_at: u64, vk_hash: &str, fee: u64) -> FeeRecord {
+        FeeRecord {
+            collected_at,
+            txid: Txid::all_zeros(),
+            zkapp_txid: Txid::all_zeros(),
+            vk_hash: vk_hash.to_string(),
+            fee: Amount::from_sat(fee),
+        }
+    }
+
+    #[test]
+    fn test_batch_paying_for_one_request() {
+        let fees = FeesOwed::new(2);
+        let (txid, paid) = (Txid::all_zeros(), Amount::from_sat(1000));
+        let owed = Amount::from_sat(1000);
+
+        // the first request of the batch is paid for, and can be checked again
+        fees.record(txid, 0, owed, paid).unwrap();
+        fees.record(txid, 0, owed, paid).unwrap();
+
+        // but the transaction doesn't pay for the second one
+        let err = fees.record(txid, 1, owed, paid).unwrap_err();
+        let takes = format!("the committee takes {}", owed * 2);
+        assert!(err.to_string().contains(&takes));
+
+        // a transaction paying for both goes through
+        let other = Txid::from_byte_array([1; 32]);
+        fees.record(other, 0, owed, paid * 2).unwrap();
+        fees.record(other, 1, owed, paid * 2).unwrap();
+
+        // the oldest transactions are forgotten
+        fees.record(Txid::from_byte_array([2; 32]), 0, owed, paid)
+            .unwrap();
+        fees.record(txid, 1, owed, paid).unwrap();
+    }
+
+    #[test]
+    fn test_days() {
+        assert_eq!(parse_day("1970-01-01").unwrap(), 0);
+        assert_eq!(parse_day("2024-03-01").unwrap(), 1_709_251_200);
+        assert_eq!(format_day(1_709_251_200 / SECONDS_PER_DAY), "2024-03-01");
+        assert_eq!(
+            format_day(parse_day("2000-02-29").unwrap() / SECONDS_PER_DAY),
+            "2000-02-29"
+        );
+        assert!(parse_day("2024-13-01").is_err());
+        assert!(parse_day("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_report() {
+        let day = parse_day("2024-03-01").unwrap();
+        let records = vec![
+            record(day + 10, "aa", 1000),
+            record(day + 20, "aa", 1000),
+            record(day + 30, "bb", 500),
+            record(day + SECONDS_PER_DAY, "aa", 1000),
+        ];
+
+        let rows = report(&records, None, None);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].day, "2024-03-01");
+        assert_eq!(
+            (rows[0].requests, rows[0].fees),
+            (2, Amount::from_sat(2000))
+        );
+        assert_eq!(rows[2].day, "2024-03-02");
+
+        // the end of the range is excluded
+        let rows = report(&records, Some(day), Some(day + SECONDS_PER_DAY));
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            to_csv(&rows),
+            "day,vk_hash,requests,fees_sat\n2024-03-01,aa,2,2000\n2024-03-01,bb,1,500\n"
+        );
+    }
+}
@@ -0,0 +1,431 @@
+//! Governance of the policies of the committee (see `zkbtc governance`):
+//! the fee schedule, the confirmation depth of zkapps, and the maximum spend of a use of a zkapp.
+//!
+//! A committee configuration with `governance` set has its policy changed by proposals (see [Proposal]) that the members vote for:
+//! each operator lists the hashes of the proposals they vote for in a file given to their node (see [read_votes]),
+//! and a node only signs a proposal it was told to vote for.
+//! Once a threshold of the members signed a proposal, it is ratified (see [RatifiedProposal]),
+//! and the orchestrator logs it (see [GovernanceLog]) and enforces its policy.
+//! The orchestrator then ignores the policy of the committee configuration, and only enforces the last ratified one
+//! (the default policy, until a first proposal is ratified).
+//!
+//! The members sign the BIP 340 tagged hash of the proposal (with the tag [GOVERNANCE_TAG]),
+//! so that a vote can't be passed off as an attestation (see [super::attestation]) or the signature of a transaction.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use anyhow::{ensure, Context, Result};
+use log::info;
+use secp256k1::{schnorr, Message, Secp256k1};
+use serde::{Deserialize, Serialize};
+
+use crate::config::FeeSchedule;
+
+use super::{
+    attestation::tagged_hash,
+    orchestrator::CommitteeConfig,
+    signer::{even_y, output_key},
+    storage::now,
+};
+
+/// The tag of the hashes of the proposals the committee signs.
+pub const GOVERNANCE_TAG: &str = "zkBitcoin/governance";
+
+/// The file the ratified proposals are logged to, in the storage of the orchestrator.
+const LOG_FILE: &str = "governance.jsonl";
+
+//
+// Proposals
+//
+
+/// The policies of the committee that are governed by proposals.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommitteePolicy {
+    /// The fee the committee takes on every use of a zkapp (the one of the protocol configuration if not set).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_schedule: Option<FeeSchedule>,
+
+    /// The minimum number of confirmations of a zkapp before the committee signs a spend of it.
+    #[serde(default)]
+    pub min_confirmations: u32,
+
+    /// The most a use of a zkapp can withdraw to its recipients (in satoshis), if capped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_spend_sats: Option<u64>,
+}
+
+impl CommitteePolicy {
+    /// Ensures that the fee schedule is valid.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(fee_schedule) = &self.fee_schedule {
+            fee_schedule.validate().context("invalid fee schedule")?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the policy of a committee configuration with this one.
+    pub fn apply(&self, committee_cfg: &mut CommitteeConfig) {
+        committee_cfg.fee_schedule = self.fee_schedule.clone();
+        committee_cfg.min_confirmations = self.min_confirmations;
+        committee_cfg.max_spend_sats = self.max_spend_sats;
+    }
+
+    /// The policy of a committee configuration.
+    pub fn of(committee_cfg: &CommitteeConfig) -> Self {
+        Self {
+            fee_schedule: committee_cfg.fee_schedule.clone(),
+            min_confirmations: committee_cfg.min_confirmations,
+            max_spend_sats: committee_cfg.max_spend_sats,
+        }
+    }
+}
+
+/// A change of the policy of the committee, put to the vote of its members.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proposal {
+    /// The position of the proposal among the ratified ones (starting at 1),
+    /// so that a ratified proposal can't be applied again later.
+    pub sequence: u64,
+
+    /// The policy of the committee, once the proposal is ratified.
+    pub policy: CommitteePolicy,
+
+    /// What the proposal is about, for the operators voting on it.
+    #[serde(default)]
+    pub description: String,
+}
+
+impl Proposal {
+    /// The hash of the proposal, which the members vote for and sign
+    /// (its BIP 340 tagged hash, with the tag [GOVERNANCE_TAG]).
+    pub fn hash(&self) -> Result<[u8; 32]> {
+        Ok(tagged_hash(GOVERNANCE_TAG, &serde_json::to_vec(self)?))
+    }
+}
+
+/// A proposal signed by a threshold of the committee.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RatifiedProposal {
+    /// The proposal.
+    pub proposal: Proposal,
+
+    /// When the proposal was ratified (UNIX timestamp in seconds).
+    pub ratified_at: u64,
+
+    /// The hex-encoded BIP 340 signature of the hash of the proposal,
+    /// for the output key of the zkBitcoin address (see [super::signer::output_key]).
+    pub signature: String,
+}
+
+impl RatifiedProposal {
+    /// Checks that the proposal was signed by the committee whose key is `committee_pubkey`.
+    pub fn verify(&self, committee_pubkey: bitcoin::PublicKey) -> Result<()> {
+        let signature: schnorr::Signature = self
+            .signature
+            .parse()
+            .context("the signature is not a hex-encoded BIP 340 signature")?;
+        Secp256k1::verification_only()
+            .verify_schnorr(
+                &signature,
+                &Message::from_digest(self.proposal.hash()?),
+                &output_key(even_y(committee_pubkey)),
+            )
+            .with_context(|| {
+                format!(
+                    "proposal #{} wasn't ratified by the committee",
+                    self.proposal.sequence
+                )
+            })
+    }
+}
+
+//
+// Votes
+//
+
+/// Reads the hashes of the proposals an operator votes for: one hex-encoded hash per line
+/// (blank lines and lines starting with `#` are skipped).
+pub fn read_votes(path: &Path) -> Result<Vec<[u8; 32]>> {
+    let votes = fs::read_to_string(path)
+        .with_context(|| format!("couldn't read the votes in {}", path.display()))?;
+    votes
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_proposal_hash)
+        .collect()
+}
+
+/// Parses the hex-encoded hash of a proposal.
+pub fn parse_proposal_hash(hash: &str) -> Result<[u8; 32]> {
+    hex::decode(hash)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .with_context(|| format!("{hash} is not the hex-encoded hash of a proposal"))
+}
+
+//
+// Log
+//
+
+/// The proposals ratified by the committee, logged by the orchestrator (as `governance.jsonl` in its storage).
+pub struct GovernanceLog {
+    path: PathBuf,
+    committee_pubkey: bitcoin::PublicKey,
+    ratified: RwLock<Vec<RatifiedProposal>>,
+}
+
+impl GovernanceLog {
+    /// Opens (and creates if needed) the log in `dir`,
+    /// and checks that its proposals were ratified in sequence by the committee whose key is `committee_pubkey`.
+    pub fn open(dir: &Path, committee_pubkey: bitcoin::PublicKey) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+        let path = dir.join(LOG_FILE);
+
+        let mut ratified = vec![];
+        if path.exists() {
+            let file = fs::File::open(&path)
+                .with_context(|| format!("couldn't open {}", path.display()))?;
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                if line.is_empty() {
+                    continue;
+                }
+                let proposal: RatifiedProposal =
+                    serde_json::from_str(&line).context("malformed ratified proposal")?;
+                check_next(&ratified, &proposal, committee_pubkey)
+                    .with_context(|| format!("the governance log {} is invalid", path.display()))?;
+                ratified.push(proposal);
+            }
+        }
+
+        Ok(Self {
+            path,
+            committee_pubkey,
+            ratified: RwLock::new(ratified),
+        })
+    }
+
+    /// The proposals ratified so far, in sequence.
+    pub fn ratified(&self) -> Vec<RatifiedProposal> {
+        self.ratified.read().unwrap().clone()
+    }
+
+    /// The sequence the next proposal must have.
+    pub fn next_sequence(&self) -> u64 {
+        self.ratified.read().unwrap().len() as u64 + 1
+    }
+
+    /// The policy of the last ratified proposal (the default one if none was ratified yet).
+    pub fn policy(&self) -> CommitteePolicy {
+        self.ratified
+            .read()
+            .unwrap()
+            .last()
+            .map(|ratified| ratified.proposal.policy.clone())
+            .unwrap_or_default()
+    }
+
+    /// Logs a proposal ratified by the committee, whose sequence must be the next one.
+    pub fn append(
+        &self,
+        proposal: Proposal,
+        signature: &schnorr::Signature,
+    ) -> Result<RatifiedProposal> {
+        let ratified_proposal = RatifiedProposal {
+            proposal,
+            ratified_at: now(),
+            signature: signature.to_string(),
+        };
+
+        let mut ratified = self.ratified.write().unwrap();
+        check_next(&ratified, &ratified_proposal, self.committee_pubkey)?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("couldn't open the governance log")?;
+        writeln!(file, "{}", serde_json::to_string(&ratified_proposal)?)?;
+        file.sync_data()?;
+
+        info!(
+            "- proposal #{} ratified by the committee",
+            ratified_proposal.proposal.sequence
+        );
+        ratified.push(ratified_proposal.clone());
+        Ok(ratified_proposal)
+    }
+}
+
+/// Checks that a proposal was ratified by the committee, and comes next after the ones ratified so far.
+fn check_next(
+    ratified: &[RatifiedProposal],
+    proposal: &RatifiedProposal,
+    committee_pubkey: bitcoin::PublicKey,
+) -> Result<()> {
+    let expected = ratified.len() as u64 + 1;
+    ensure!(
+        proposal.proposal.sequence == expected,
+        "proposal #{} comes out of sequence (the next one is #{expected})",
+        proposal.proposal.sequence
+    );
+    proposal.verify(committee_pubkey)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use secp256k1::{Keypair, SecretKey};
+
+    use super::*;
+    use crate::{
+        client::CommitteeMember,
+        committee::{
+            dealer,
+            node::NodeState,
+            orchestrator::Orchestrator,
+            signer::{SignatureAggregator, SignerKind},
+        },
+    };
+
+    fn ratify(keypair: &Keypair, proposal: &Proposal) -> schnorr::Signature {
+        let secp = Secp256k1::new();
+        let tweaked = bitcoin::key::TapTweak::tap_tweak(*keypair, &secp, None).to_inner();
+        secp.sign_schnorr_no_aux_rand(&Message::from_digest(proposal.hash().unwrap()), &tweaked)
+    }
+
+    #[test]
+    fn test_governance_log() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[7; 32]).unwrap());
+        let committee_pubkey = bitcoin::PublicKey::new(keypair.public_key());
+        let dir = tempdir::TempDir::new("zkbitcoin_governance").unwrap();
+
+        let log = GovernanceLog::open(dir.path(), committee_pubkey).unwrap();
+        assert_eq!(log.policy(), CommitteePolicy::default());
+        assert_eq!(log.next_sequence(), 1);
+
+        let proposal = Proposal {
+            sequence: 1,
+            policy: CommitteePolicy {
+                fee_schedule: None,
+                min_confirmations: 6,
+                max_spend_sats: Some(1_000_000),
+            },
+            description: "require 6 confirmations, and cap spends at 0.01 BTC".to_string(),
+        };
+
+        // proposals must come in sequence
+        let mut out_of_sequence = proposal.clone();
+        out_of_sequence.sequence = 2;
+        assert!(log
+            .append(out_of_sequence.clone(), &ratify(&keypair, &out_of_sequence))
+            .is_err());
+
+        // and be signed by the committee
+        let other = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[8; 32]).unwrap());
+        assert!(log
+            .append(proposal.clone(), &ratify(&other, &proposal))
+            .is_err());
+
+        let ratified = log
+            .append(proposal.clone(), &ratify(&keypair, &proposal))
+            .unwrap();
+        ratified.verify(committee_pubkey).unwrap();
+        assert_eq!(log.policy(), proposal.policy);
+
+        // a ratified proposal can't be applied again
+        assert!(log
+            .append(proposal.clone(), &ratify(&keypair, &proposal))
+            .is_err());
+
+        // the log is checked when it's opened again
+        let log = GovernanceLog::open(dir.path(), committee_pubkey).unwrap();
+        assert_eq!(log.ratified(), vec![ratified]);
+        assert_eq!(log.next_sequence(), 2);
+        let other_pubkey = bitcoin::PublicKey::new(other.public_key());
+        assert!(GovernanceLog::open(dir.path(), other_pubkey).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ratify() {
+        let mut committee = dealer::generate(3, 2, 0, SignerKind::Frost).unwrap();
+        committee.config.governance = true;
+        committee.config.min_confirmations = 3;
+        let committee_pubkey = committee.pubkey_package.pubkey().unwrap();
+        let dir = tempdir::TempDir::new("zkbitcoin_governance").unwrap();
+
+        let proposal = Proposal {
+            sequence: 1,
+            policy: CommitteePolicy {
+                max_spend_sats: Some(50_000),
+                ..Default::default()
+            },
+            description: "cap spends".to_string(),
+        };
+        let votes_path = dir.path().join("votes");
+        fs::write(&votes_path, hex::encode(proposal.hash().unwrap())).unwrap();
+
+        // only the operators of the first `voters` members vote for the proposal
+        let orchestrator = |voters: usize| {
+            let members: HashMap<_, Box<dyn CommitteeMember>> = committee
+                .key_packages
+                .iter()
+                .enumerate()
+                .map(|(i, (id, key_package))| {
+                    let mut node =
+                        NodeState::new(key_package.clone(), committee.pubkey_package.clone());
+                    if i < voters {
+                        node.governance_votes = Some(votes_path.clone());
+                    }
+                    (*id, Box::new(node) as Box<dyn CommitteeMember>)
+                })
+                .collect();
+            let mut orchestrator = Orchestrator::with_members(
+                committee.pubkey_package.clone(),
+                committee.config.clone(),
+                members,
+            );
+            orchestrator.governance =
+                Some(GovernanceLog::open(&dir.path().join("log"), committee_pubkey).unwrap());
+            orchestrator
+        };
+
+        assert!(orchestrator(1).ratify(&proposal).await.is_err());
+
+        let orchestrator = orchestrator(2);
+        let ratified = orchestrator.ratify(&proposal).await.unwrap();
+        ratified.verify(committee_pubkey).unwrap();
+
+        // the ratified policy replaces the one of the configuration
+        let committee_cfg = orchestrator.committee_cfg();
+        assert_eq!(committee_cfg.max_spend_sats, Some(50_000));
+        assert_eq!(committee_cfg.min_confirmations, 0);
+        orchestrator
+            .set_committee_cfg(committee.config.clone())
+            .unwrap();
+        assert_eq!(orchestrator.committee_cfg().max_spend_sats, Some(50_000));
+
+        // and the proposal can't be ratified twice
+        assert!(orchestrator.ratify(&proposal).await.is_err());
+    }
+
+    #[test]
+    fn test_read_votes() {
+        let dir = tempdir::TempDir::new("zkbitcoin_votes").unwrap();
+        let path = dir.path().join("votes");
+        let hash = [3u8; 32];
+        fs::write(&path, format!("# fee change\n{}\n\n", hex::encode(hash))).unwrap();
+        assert_eq!(read_votes(&path).unwrap(), vec![hash]);
+
+        fs::write(&path, "deadbeef\n").unwrap();
+        assert!(read_votes(&path).is_err());
+    }
+}
@@ -0,0 +1,471 @@
+//! The gRPC API of orchestrators and committee nodes (see `proto/zkbitcoin.proto`),
+//! served alongside their JSON RPC API for integrators who prefer typed, versioned interfaces.
+//! Both APIs share the same logic: only the encoding of messages differs.
+
+use std::{net::SocketAddr, pin::Pin, str::FromStr, sync::Arc};
+
+use anyhow::{Context, Result};
+use bitcoin::{consensus, Amount, ScriptBuf, TxOut, Txid};
+use futures::Stream;
+use log::{debug, info, warn};
+use tokio::sync::broadcast::error::RecvError;
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::bob_request::{BobRequest, BobResponse, Update};
+
+use super::{
+    events::{Event, EventKind},
+    node::{NodeState, Round1Response, Round2Request, Round2Response},
+    orchestrator::Orchestrator,
+    storage::redact,
+};
+
+/// The types and services generated from `proto/zkbitcoin.proto`.
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("zkbitcoin.v1");
+}
+
+use proto::{
+    committee_node_server::{CommitteeNode, CommitteeNodeServer},
+    orchestrator_server::{self, OrchestratorServer},
+};
+
+//
+// Conversions
+//
+
+impl From<&TxOut> for proto::TxOut {
+    fn from(txout: &TxOut) -> Self {
+        Self {
+            value: txout.value.to_sat(),
+            script_pubkey: txout.script_pubkey.to_bytes(),
+        }
+    }
+}
+
+impl From<proto::TxOut> for TxOut {
+    fn from(txout: proto::TxOut) -> Self {
+        Self {
+            value: Amount::from_sat(txout.value),
+            script_pubkey: ScriptBuf::from_bytes(txout.script_pubkey),
+        }
+    }
+}
+
+impl From<&Update> for proto::Update {
+    fn from(update: &Update) -> Self {
+        Self {
+            new_state: update.new_state.clone(),
+            prev_state: update.prev_state.clone(),
+            amount_out: update.amount_out.clone(),
+            amount_in: update.amount_in.clone(),
+            upgrade_to: update.upgrade_to.clone(),
+        }
+    }
+}
+
+impl From<proto::Update> for Update {
+    fn from(update: proto::Update) -> Self {
+        Self {
+            new_state: update.new_state,
+            prev_state: update.prev_state,
+            truncated_txid: None,
+            amount_out: update.amount_out,
+            amount_in: update.amount_in,
+            upgrade_to: update.upgrade_to,
+        }
+    }
+}
+
+impl TryFrom<&BobRequest> for proto::BobRequest {
+    type Error = anyhow::Error;
+
+    fn try_from(bob_request: &BobRequest) -> Result<Self> {
+        Ok(Self {
+            tx: consensus::serialize(&bob_request.tx),
+            zkapp_tx: consensus::serialize(&bob_request.zkapp_tx),
+            zkapp_input: bob_request.zkapp_input.try_into()?,
+            vk_json: serde_json::to_string(&bob_request.vk)?,
+            proof_json: serde_json::to_string(&bob_request.proof)?,
+            update: bob_request.update.as_ref().map(Into::into),
+            recipients: bob_request.recipients.iter().map(Into::into).collect(),
+            prev_outs: bob_request.prev_outs.iter().map(Into::into).collect(),
+            sponsored: bob_request.sponsored,
+            fee_payment_hash: bob_request.fee_payment_hash.clone(),
+            deadline: bob_request.deadline,
+            nonce: bob_request.nonce.clone(),
+            outpoint: bob_request.outpoint.as_ref().map(ToString::to_string),
+        })
+    }
+}
+
+impl TryFrom<proto::BobRequest> for BobRequest {
+    type Error = anyhow::Error;
+
+    fn try_from(bob_request: proto::BobRequest) -> Result<Self> {
+        Ok(Self {
+            tx: consensus::deserialize(&bob_request.tx).context("invalid transaction")?,
+            zkapp_tx: consensus::deserialize(&bob_request.zkapp_tx)
+                .context("invalid zkapp transaction")?,
+            zkapp_input: bob_request.zkapp_input.try_into()?,
+            vk: serde_json::from_str(&bob_request.vk_json).context("invalid verifier key")?,
+            proof: serde_json::from_str(&bob_request.proof_json).context("invalid proof")?,
+            update: bob_request.update.map(Into::into),
+            recipients: bob_request.recipients.into_iter().map(Into::into).collect(),
+            prev_outs: bob_request.prev_outs.into_iter().map(Into::into).collect(),
+            sponsored: bob_request.sponsored,
+            fee_payment_hash: bob_request.fee_payment_hash,
+            deadline: bob_request.deadline,
+            nonce: bob_request.nonce,
+            outpoint: bob_request
+                .outpoint
+                .map(|outpoint| outpoint.parse())
+                .transpose()
+                .context("invalid outpoint")?,
+        })
+    }
+}
+
+impl From<&BobResponse> for proto::BobResponse {
+    fn from(bob_response: &BobResponse) -> Self {
+        Self {
+            unlocked_tx: consensus::serialize(&bob_response.unlocked_tx),
+        }
+    }
+}
+
+impl TryFrom<proto::BobResponse> for BobResponse {
+    type Error = anyhow::Error;
+
+    fn try_from(bob_response: proto::BobResponse) -> Result<Self> {
+        Ok(Self {
+            unlocked_tx: consensus::deserialize(&bob_response.unlocked_tx)
+                .context("invalid unlocked transaction")?,
+        })
+    }
+}
+
+impl From<&Event> for proto::SessionEvent {
+    fn from(event: &Event) -> Self {
+        let (kind, error) = match &event.kind {
+            EventKind::RequestReceived => (proto::EventKind::RequestReceived, String::new()),
+            EventKind::ProofVerified => (proto::EventKind::ProofVerified, String::new()),
+            EventKind::Round1Complete => (proto::EventKind::Round1Complete, String::new()),
+            EventKind::Signed => (proto::EventKind::Signed, String::new()),
+            EventKind::Broadcast => (proto::EventKind::Broadcast, String::new()),
+            EventKind::Failed { error } => (proto::EventKind::Failed, error.clone()),
+        };
+        Self {
+            txid: event.txid.to_string(),
+            zkapp_txid: event.zkapp_txid.to_string(),
+            kind: kind.into(),
+            error,
+        }
+    }
+}
+
+impl TryFrom<&Round1Response> for proto::Round1Response {
+    type Error = anyhow::Error;
+
+    fn try_from(response: &Round1Response) -> Result<Self> {
+        Ok(Self {
+            commitments: serde_json::to_vec(&response.commitments)?,
+        })
+    }
+}
+
+impl TryFrom<proto::Round1Response> for Round1Response {
+    type Error = anyhow::Error;
+
+    fn try_from(response: proto::Round1Response) -> Result<Self> {
+        Ok(Self {
+            commitments: serde_json::from_slice(&response.commitments)
+                .context("invalid commitments")?,
+        })
+    }
+}
+
+impl TryFrom<&Round2Request> for proto::Round2Request {
+    type Error = anyhow::Error;
+
+    fn try_from(request: &Round2Request) -> Result<Self> {
+        let commitments_map = request
+            .commitments_map
+            .iter()
+            .map(|(identifier, commitments)| {
+                Ok(proto::Commitments {
+                    identifier: serde_json::to_vec(identifier)?,
+                    commitments: serde_json::to_vec(commitments)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            txid: request.txid.to_string(),
+            proof_hash: request.proof_hash.to_vec(),
+            commitments_map,
+            message: request.message.to_vec(),
+            zkapp_input: request.zkapp_input.try_into()?,
+        })
+    }
+}
+
+impl TryFrom<proto::Round2Request> for Round2Request {
+    type Error = anyhow::Error;
+
+    fn try_from(request: proto::Round2Request) -> Result<Self> {
+        let commitments_map = request
+            .commitments_map
+            .into_iter()
+            .map(|entry| {
+                Ok((
+                    serde_json::from_slice(&entry.identifier).context("invalid identifier")?,
+                    serde_json::from_slice(&entry.commitments).context("invalid commitments")?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            txid: Txid::from_str(&request.txid)?,
+            zkapp_input: request.zkapp_input.try_into()?,
+            proof_hash: request
+                .proof_hash
+                .try_into()
+                .ok()
+                .context("the proof hash must be 32 bytes")?,
+            commitments_map,
+            message: request
+                .message
+                .try_into()
+                .ok()
+                .context("the message must be 32 bytes")?,
+        })
+    }
+}
+
+impl TryFrom<&Round2Response> for proto::Round2Response {
+    type Error = anyhow::Error;
+
+    fn try_from(response: &Round2Response) -> Result<Self> {
+        Ok(Self {
+            signature_share: serde_json::to_vec(&response.signature_share)?,
+        })
+    }
+}
+
+impl TryFrom<proto::Round2Response> for Round2Response {
+    type Error = anyhow::Error;
+
+    fn try_from(response: proto::Round2Response) -> Result<Self> {
+        Ok(Self {
+            signature_share: serde_json::from_slice(&response.signature_share)
+                .context("invalid signature share")?,
+        })
+    }
+}
+
+/// The status of a request that couldn't be decoded.
+fn invalid_argument(err: anyhow::Error) -> Status {
+    Status::invalid_argument(format!("{err:#}"))
+}
+
+/// The status of a request that couldn't be handled, like [jsonrpsee_types::error::UNKNOWN_ERROR_CODE] in the JSON RPC API.
+fn unknown(err: anyhow::Error) -> Status {
+    Status::unknown(format!("{err:#}"))
+}
+
+//
+// Orchestrator
+//
+
+/// The gRPC service of an orchestrator.
+pub struct OrchestratorService(pub Arc<Orchestrator>);
+
+#[tonic::async_trait]
+impl orchestrator_server::Orchestrator for OrchestratorService {
+    async fn unlock_funds(
+        &self,
+        request: Request<proto::BobRequest>,
+    ) -> Result<Response<proto::BobResponse>, Status> {
+        let bob_request = BobRequest::try_from(request.into_inner()).map_err(invalid_argument)?;
+        info!("received request for {} over gRPC", bob_request.tx.txid());
+        debug!("- request: {}", redact(&format!("{bob_request:?}")));
+
+        let bob_response = self
+            .0
+            .unlock_funds(&bob_request)
+            .await
+            .context("the request didn't validate")
+            .map_err(unknown)?;
+        Ok(Response::new((&bob_response).into()))
+    }
+
+    async fn unlock_funds_batch(
+        &self,
+        request: Request<proto::UnlockFundsBatchRequest>,
+    ) -> Result<Response<proto::BobResponse>, Status> {
+        let bob_requests = request
+            .into_inner()
+            .requests
+            .into_iter()
+            .map(BobRequest::try_from)
+            .collect::<Result<Vec<_>>>()
+            .map_err(invalid_argument)?;
+        info!(
+            "received batch of {} requests over gRPC",
+            bob_requests.len()
+        );
+
+        let bob_response = self
+            .0
+            .unlock_funds_batch(&bob_requests)
+            .await
+            .context("the batch didn't validate")
+            .map_err(unknown)?;
+        Ok(Response::new((&bob_response).into()))
+    }
+
+    type SubscribeEventsStream =
+        Pin<Box<dyn Stream<Item = Result<proto::SessionEvent, Status>> + Send>>;
+
+    async fn subscribe_events(
+        &self,
+        request: Request<proto::SubscribeEventsRequest>,
+    ) -> Result<Response<Self::SubscribeEventsStream>, Status> {
+        // the events of every session are only streamed on the admin API
+        let txid = request
+            .into_inner()
+            .txid
+            .ok_or_else(|| Status::invalid_argument("a txid is required"))?;
+        let txid = Txid::from_str(&txid)
+            .map_err(|err| Status::invalid_argument(format!("invalid txid: {err}")))?;
+
+        let events = futures::stream::unfold(self.0.subscribe(), move |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) if event.txid == txid => {
+                        return Some((Ok((&event).into()), events));
+                    }
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("- a gRPC event subscriber missed {missed} events");
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+
+    async fn get_info(
+        &self,
+        _request: Request<proto::GetInfoRequest>,
+    ) -> Result<Response<proto::OrchestratorInfo>, Status> {
+        let committee_cfg = self.0.committee_cfg();
+        Ok(Response::new(proto::OrchestratorInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            threshold: committee_cfg.threshold as u32,
+            num_members: committee_cfg.members.len() as u32,
+        }))
+    }
+}
+
+/// Serves the gRPC API of an orchestrator, until it fails.
+pub async fn serve_orchestrator(
+    address: SocketAddr,
+    orchestrator: Arc<Orchestrator>,
+) -> Result<()> {
+    info!("- serving the gRPC API of the orchestrator at http://{address}");
+    let context = orchestrator.clone();
+    Server::builder()
+        .add_service(OrchestratorServer::new(OrchestratorService(orchestrator)))
+        .serve_with_shutdown(address, async move { context.shutdown.started().await })
+        .await?;
+    Ok(())
+}
+
+//
+// Committee node
+//
+
+/// The gRPC service of a committee node.
+pub struct CommitteeNodeService(pub Arc<NodeState>);
+
+#[tonic::async_trait]
+impl CommitteeNode for CommitteeNodeService {
+    async fn round1_signing(
+        &self,
+        request: Request<proto::BobRequest>,
+    ) -> Result<Response<proto::Round1Response>, Status> {
+        let bob_request = BobRequest::try_from(request.into_inner()).map_err(invalid_argument)?;
+        info!("received request for {} over gRPC", bob_request.tx.txid());
+        debug!("- request: {}", redact(&format!("{bob_request:?}")));
+
+        let response = self.0.round_1(&bob_request).await.map_err(unknown)?;
+        Ok(Response::new((&response).try_into().map_err(unknown)?))
+    }
+
+    async fn round2_signing(
+        &self,
+        request: Request<proto::Round2Request>,
+    ) -> Result<Response<proto::Round2Response>, Status> {
+        let round2request =
+            Round2Request::try_from(request.into_inner()).map_err(invalid_argument)?;
+        info!(
+            "received round 2 request for {} over gRPC",
+            round2request.txid
+        );
+        debug!("- request: {}", redact(&format!("{round2request:?}")));
+
+        let response = self.0.round_2(&round2request).map_err(unknown)?;
+        Ok(Response::new((&response).try_into().map_err(unknown)?))
+    }
+}
+
+/// Serves the gRPC API of a committee node, until it fails.
+pub async fn serve_node(address: SocketAddr, node: Arc<NodeState>) -> Result<()> {
+    info!("- serving the gRPC API of the node at http://{address}");
+    let context = node.clone();
+    Server::builder()
+        .add_service(CommitteeNodeServer::new(CommitteeNodeService(node)))
+        .serve_with_shutdown(address, async move { context.shutdown.started().await })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute::LockTime, transaction::Version, Transaction, TxIn};
+
+    use super::*;
+
+    #[test]
+    fn test_bob_response_roundtrip() {
+        let bob_response = BobResponse {
+            unlocked_tx: Transaction {
+                version: Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: vec![TxIn::default()],
+                output: vec![TxOut {
+                    value: Amount::from_sat(1000),
+                    script_pubkey: ScriptBuf::new(),
+                }],
+            },
+        };
+        let encoded = proto::BobResponse::from(&bob_response);
+        let decoded = BobResponse::try_from(encoded).unwrap();
+        assert_eq!(decoded.unlocked_tx, bob_response.unlocked_tx);
+    }
+
+    #[test]
+    fn test_round2_request_rejects_short_hashes() {
+        let request = proto::Round2Request {
+            txid: "e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836".to_string(),
+            proof_hash: vec![0; 31],
+            commitments_map: vec![],
+            message: vec![0; 32],
+            zkapp_input: 0,
+        };
+        let err = Round2Request::try_from(request).unwrap_err();
+        assert_eq!(err.to_string(), "the proof hash must be 32 bytes");
+    }
+}
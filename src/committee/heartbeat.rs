@@ -0,0 +1,258 @@
+//! The health of the committee, as seen by the orchestrator.
+//!
+//! The orchestrator regularly pings every member (see [run]), and keeps track of which ones answer and how fast
+//! in a [Roster]. Members that stopped answering are left out of signing sessions (as long as a threshold is left),
+//! and operators can look at the roster with the `committee_status` method of the admin API (see [super::admin])
+//! (or with `zkbtc committee-status`).
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use frost_secp256k1_tr::Identifier;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    node::NodeInfo,
+    orchestrator::{CommitteeConfig, Orchestrator},
+    storage::now,
+};
+
+/// How often (in seconds) members are pinged, by default.
+pub const DEFAULT_HEARTBEAT_INTERVAL: u64 = 30;
+
+/// What the orchestrator knows about the health of a member.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberStatus {
+    /// The identifier of the member in the committee.
+    pub identifier: Identifier,
+
+    /// The address of the member.
+    pub address: String,
+
+    /// Whether the member answered the last ping (or hasn't been pinged yet).
+    pub available: bool,
+
+    /// How long (in milliseconds) the member took to answer the last ping it answered.
+    pub latency_ms: Option<u64>,
+
+    /// The version of zkBitcoin the member runs, as of the last ping it answered.
+    pub version: Option<String>,
+
+    /// When (as a UNIX timestamp) the member last answered a ping.
+    pub last_seen: Option<u64>,
+
+    /// When (as a UNIX timestamp) the member was last pinged.
+    pub last_checked: Option<u64>,
+
+    /// The number of pings the member failed to answer since it last answered one.
+    pub consecutive_failures: u32,
+
+    /// Why the member failed to answer the last ping (if it did).
+    pub last_error: Option<String>,
+}
+
+/// The health of the committee.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitteeStatus {
+    /// The number of members needed to sign.
+    pub threshold: usize,
+
+    /// The number of members available.
+    pub available: usize,
+
+    /// The status of each member (by identifier).
+    pub members: Vec<MemberStatus>,
+}
+
+/// The status of every member of the committee.
+#[derive(Debug)]
+pub struct Roster {
+    statuses: Mutex<HashMap<Identifier, MemberStatus>>,
+}
+
+impl Roster {
+    /// A roster of the members of the committee, which haven't been pinged yet.
+    pub fn new(committee_cfg: &CommitteeConfig) -> Self {
+        let statuses = committee_cfg
+            .members
+            .iter()
+            .map(|(&identifier, member)| {
+                let status = MemberStatus {
+                    identifier,
+                    address: member.address.clone(),
+                    available: true,
+                    latency_ms: None,
+                    version: None,
+                    last_seen: None,
+                    last_checked: None,
+                    consecutive_failures: 0,
+                    last_error: None,
+                };
+                (identifier, status)
+            })
+            .collect();
+        Self {
+            statuses: Mutex::new(statuses),
+        }
+    }
+
+    /// Records that a member answered a ping.
+    pub fn record_success(&self, identifier: Identifier, latency: Duration, info: &NodeInfo) {
+        let mut statuses = self.statuses.lock().unwrap();
+        if let Some(status) = statuses.get_mut(&identifier) {
+            let timestamp = now();
+            status.available = true;
+            status.latency_ms = Some(latency.as_millis() as u64);
+            status.version = Some(info.version.clone());
+            status.last_seen = Some(timestamp);
+            status.last_checked = Some(timestamp);
+            status.consecutive_failures = 0;
+            status.last_error = None;
+        }
+    }
+
+    /// Records that a member failed to answer a ping.
+    pub fn record_failure(&self, identifier: Identifier, err: &anyhow::Error) {
+        let mut statuses = self.statuses.lock().unwrap();
+        if let Some(status) = statuses.get_mut(&identifier) {
+            if status.available {
+                warn!(
+                    "committee member {} is unavailable: {err:#}",
+                    status.address
+                );
+            }
+            status.available = false;
+            status.last_checked = Some(now());
+            status.consecutive_failures += 1;
+            status.last_error = Some(format!("{err:#}"));
+        }
+    }
+
+    /// Adds the new members of the committee, forgets the removed ones,
+    /// and starts over for the ones whose address changed.
+    pub fn update(&self, committee_cfg: &CommitteeConfig) {
+        let fresh = Roster::new(committee_cfg).statuses.into_inner().unwrap();
+        let mut statuses = self.statuses.lock().unwrap();
+        statuses.retain(|id, status| {
+            fresh
+                .get(id)
+                .is_some_and(|fresh| fresh.address == status.address)
+        });
+        for (id, status) in fresh {
+            statuses.entry(id).or_insert(status);
+        }
+    }
+
+    /// The members that failed to answer the last ping.
+    pub fn unavailable(&self) -> HashSet<Identifier> {
+        self.statuses
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|status| !status.available)
+            .map(|status| status.identifier)
+            .collect()
+    }
+
+    /// The health of the committee.
+    pub fn status(&self, threshold: usize) -> CommitteeStatus {
+        let mut members: Vec<_> = self.statuses.lock().unwrap().values().cloned().collect();
+        members.sort_by_key(|status| status.identifier);
+        CommitteeStatus {
+            threshold,
+            available: members.iter().filter(|status| status.available).count(),
+            members,
+        }
+    }
+}
+
+/// Pings every member of the committee every `interval`, forever.
+pub async fn run(orchestrator: Arc<Orchestrator>, interval: Duration) {
+    loop {
+        orchestrator.check_members().await;
+        let status = orchestrator.committee_status();
+        debug!(
+            "- {}/{} committee members available",
+            status.available,
+            status.members.len()
+        );
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::committee::orchestrator::Member;
+
+    #[test]
+    fn test_roster() {
+        let ids: Vec<_> = (1..=3u16)
+            .map(|id| Identifier::try_from(id).unwrap())
+            .collect();
+        let committee_cfg = CommitteeConfig {
+            threshold: 2,
+            members: ids
+                .iter()
+                .map(|&id| {
+                    let member = Member {
+                        address: "127.0.0.1:8887".to_string(),
+                        http2: false,
+                    };
+                    (id, member)
+                })
+                .collect(),
+            min_confirmations: 0,
+            fee_schedule: None,
+            lightning_fees: false,
+            emergency_sweep_descriptor: None,
+            signer: Default::default(),
+            max_spend_sats: None,
+            governance: false,
+            replay_protection: false,
+        };
+        let roster = Roster::new(&committee_cfg);
+        assert_eq!(roster.status(2).available, 3);
+
+        let info = NodeInfo {
+            version: "0.1.0".to_string(),
+            identifier: ids[0],
+            light_client: false,
+            min_confirmations: 0,
+            emergency_sweep: false,
+        };
+        roster.record_success(ids[0], Duration::from_millis(12), &info);
+        roster.record_failure(ids[1], &anyhow!("connection refused"));
+        roster.record_failure(ids[1], &anyhow!("connection refused"));
+
+        let status = roster.status(2);
+        assert_eq!(status.available, 2);
+        assert_eq!(status.members[0].latency_ms, Some(12));
+        assert_eq!(status.members[1].consecutive_failures, 2);
+        assert_eq!(
+            status.members[1].last_error.as_deref(),
+            Some("connection refused")
+        );
+        assert_eq!(roster.unavailable(), HashSet::from([ids[1]]));
+
+        // members recover as soon as they answer again
+        roster.record_success(ids[1], Duration::from_millis(40), &info);
+        assert!(roster.unavailable().is_empty());
+
+        // reloading the committee forgets removed members, and starts over for moved ones
+        let mut reloaded = committee_cfg.clone();
+        reloaded.members.remove(&ids[2]);
+        reloaded.members.get_mut(&ids[1]).unwrap().address = "127.0.0.1:9999".to_string();
+        roster.update(&reloaded);
+        let status = roster.status(2);
+        assert_eq!(status.members.len(), 2);
+        assert_eq!(status.members[0].latency_ms, Some(12));
+        assert_eq!(status.members[1].latency_ms, None);
+    }
+}
@@ -0,0 +1,196 @@
+//! App-level validation hooks.
+//!
+//! zkapp developers can register a small [Rhai](https://rhai.rs) script for their zkapp,
+//! which the orchestrator runs against every request using that zkapp before signing.
+//! This provides a safety net on top of the circuit itself
+//! (e.g. "the balance must never decrease by more than 10% per transaction").
+//!
+//! Scripts are looked up by verifier key hash in a hooks directory, as `<hex vk hash>.rhai`.
+//! They have access to the following variables and must evaluate to a boolean:
+//!
+//! - `is_stateful`: whether the zkapp is stateful.
+//! - `prev_state` and `new_state`: the states (as decimal strings) before and after the update (empty for stateless zkapps).
+//! - `amount_in` and `amount_out`: the amounts (in satoshis) deposited into and withdrawn from the zkapp.
+//! - `locked_value` and `new_locked_value`: the value (in satoshis) locked in the zkapp before and after the update.
+//!
+//! For example:
+//!
+//! ```rhai
+//! new_locked_value * 10 >= locked_value * 9
+//! ```
+
+use std::{collections::HashMap, fs, path::Path};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use bitcoin::{Amount, Denomination};
+use log::{debug, info};
+use rhai::{Engine, Scope, AST};
+
+use crate::bob_request::{BobRequest, SmartContract};
+
+/// Maximum number of operations a script can run, so that a hook can't stall the orchestrator.
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// The validation hooks registered by zkapps, indexed by verifier key hash.
+pub struct ValidationHooks {
+    engine: Engine,
+    scripts: HashMap<[u8; 32], AST>,
+}
+
+impl ValidationHooks {
+    /// Loads (and compiles) all the `<hex vk hash>.rhai` scripts contained in a directory.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+
+        let mut scripts = HashMap::new();
+        for entry in fs::read_dir(dir).context("couldn't read hooks directory")? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("rhai") {
+                continue;
+            }
+
+            let vk_hash = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| hex::decode(stem).ok())
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .with_context(|| {
+                    format!(
+                        "hook {} is not named after a hex-encoded vk hash",
+                        path.display()
+                    )
+                })?;
+
+            let script = fs::read_to_string(&path)?;
+            let ast = engine
+                .compile(script)
+                .map_err(|err| anyhow!("couldn't compile hook {}: {err}", path.display()))?;
+
+            info!(
+                "- loaded validation hook for vk hash {}",
+                hex::encode(vk_hash)
+            );
+            scripts.insert(vk_hash, ast);
+        }
+
+        Ok(Self { engine, scripts })
+    }
+
+    /// Runs the hook registered for the zkapp being used (if any) against a request.
+    pub fn check(&self, smart_contract: &SmartContract, bob_request: &BobRequest) -> Result<()> {
+        let ast = match self.scripts.get(&smart_contract.vk_hash) {
+            Some(ast) => ast,
+            None => return Ok(()),
+        };
+
+        let (prev_state, new_state, amount_in, amount_out) = match &bob_request.update {
+            Some(update) => (
+                update.prev_state.clone(),
+                update.new_state.clone(),
+                Amount::from_str_in(&update.amount_in, Denomination::Satoshi)?,
+                Amount::from_str_in(&update.amount_out, Denomination::Satoshi)?,
+            ),
+            None => (
+                String::new(),
+                String::new(),
+                Amount::ZERO,
+                smart_contract.locked_value,
+            ),
+        };
+        let new_locked_value = (smart_contract.locked_value + amount_in)
+            .checked_sub(amount_out)
+            .context("the zkapp doesn't have enough funds")?;
+
+        let mut scope = Scope::new();
+        scope.push("is_stateful", smart_contract.is_stateful());
+        scope.push("prev_state", prev_state);
+        scope.push("new_state", new_state);
+        scope.push("amount_in", to_int(amount_in)?);
+        scope.push("amount_out", to_int(amount_out)?);
+        scope.push("locked_value", to_int(smart_contract.locked_value)?);
+        scope.push("new_locked_value", to_int(new_locked_value)?);
+
+        let accepted = self
+            .engine
+            .eval_ast_with_scope::<bool>(&mut scope, ast)
+            .map_err(|err| anyhow!("the zkapp's validation hook failed: {err}"))?;
+        debug!("- validation hook returned {accepted}");
+
+        ensure!(
+            accepted,
+            "the request was rejected by the zkapp's validation hook"
+        );
+        Ok(())
+    }
+}
+
+/// Converts an amount to the integer type used by scripts.
+fn to_int(amount: Amount) -> Result<rhai::INT> {
+    rhai::INT::try_from(amount.to_sat()).context("amount too large for a validation hook")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bitcoin::Txid;
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn smart_contract(vk_hash: [u8; 32], locked_value: u64) -> SmartContract {
+        SmartContract {
+            txid: Txid::from_str(
+                "e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836",
+            )
+            .unwrap(),
+            locked_value: Amount::from_sat(locked_value),
+            vk_hash,
+            state: None,
+            metadata_hash: None,
+            admin: None,
+            timelock: None,
+            recovery: None,
+            deployer: None,
+            vout_of_zkbitcoin_utxo: 0,
+        }
+    }
+
+    #[test]
+    fn test_load_and_compile_hooks() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        let vk_hash = [1u8; 32];
+        fs::write(
+            tmp_dir
+                .path()
+                .join(format!("{}.rhai", hex::encode(vk_hash))),
+            "new_locked_value * 10 >= locked_value * 9",
+        )
+        .unwrap();
+
+        let hooks = ValidationHooks::load(tmp_dir.path()).unwrap();
+        assert!(hooks.scripts.contains_key(&vk_hash));
+        assert!(!hooks.scripts.contains_key(&[2u8; 32]));
+
+        // a stateless zkapp is always emptied, which this hook forbids
+        let ast = &hooks.scripts[&vk_hash];
+        let contract = smart_contract(vk_hash, 1000);
+        let mut scope = Scope::new();
+        scope.push("locked_value", to_int(contract.locked_value).unwrap());
+        let emptied: rhai::INT = 0;
+        scope.push("new_locked_value", emptied);
+        let accepted = hooks
+            .engine
+            .eval_ast_with_scope::<bool>(&mut scope, ast)
+            .unwrap();
+        assert!(!accepted);
+    }
+
+    #[test]
+    fn test_badly_named_hook() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        fs::write(tmp_dir.path().join("hello.rhai"), "true").unwrap();
+        assert!(ValidationHooks::load(tmp_dir.path()).is_err());
+    }
+}
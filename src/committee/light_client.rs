@@ -0,0 +1,1009 @@
+//! A BIP157/158 light client, so that committee members can check zkapps on chain
+//! without trusting a single (fully-indexed) Bitcoin Core node.
+//!
+//! The client syncs block headers from a set of peers (keeping the chain with the most work),
+//! starting from a trusted checkpoint, along with the compact block filters of these blocks.
+//! Every zkapp is locked to the zkBitcoin address, and BIP158 filters cover
+//! both the scripts a block pays to and the scripts it spends from,
+//! so only the blocks matching the zkBitcoin script need to be downloaded
+//! to learn about every zkapp deployed, updated, or spent.
+//! The filter headers of all peers must agree, so that a single peer can't hide a block from us.
+//!
+//! The checkpoint must be the first block of a difficulty period, so that the difficulty of every header after it
+//! can be checked against the retargeting rules (the checkpoint's header, fetched once, gives the difficulty and the
+//! start time of its period).
+//!
+//! The client syncs in the background (see [LightClient::spawn_sync]), and requests are checked against the tip
+//! it last synced to, as long as it synced recently enough (see [MAX_SYNC_AGE]).
+
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use bitcoin::{
+    bip158::{BlockFilter, FilterHeader},
+    block::Header,
+    hashes::Hash,
+    p2p::{
+        message::{NetworkMessage, RawNetworkMessage},
+        message_blockdata::{GetHeadersMessage, Inventory},
+        message_filter::{CFHeaders, GetCFHeaders, GetCFilters},
+        message_network::VersionMessage,
+        Address, Magic, ServiceFlags,
+    },
+    Amount, Block, BlockHash, CompactTarget, Network, OutPoint, PublicKey, ScriptBuf, Target, Work,
+};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    sync::Mutex,
+};
+
+use crate::{
+    bob_request::SmartContract, config::protocol_config, get_network, json_rpc_stuff::connect_tcp,
+    p2tr_script_to, zkbitcoin_folder,
+};
+
+//
+// Constants
+//
+
+/// The P2P protocol version we speak (BIP157 needs at least 70015).
+const PROTOCOL_VERSION: u32 = 70016;
+
+/// The basic filter type of BIP158.
+const BASIC_FILTER: u8 = 0;
+
+/// The maximum number of headers returned by a `getheaders` message.
+const MAX_HEADERS: usize = 2000;
+
+/// The maximum number of filter headers that can be requested at once.
+const MAX_CFHEADERS: usize = 2000;
+
+/// The maximum number of filters that can be requested at once.
+const MAX_CFILTERS: usize = 1000;
+
+/// The number of blocks between difficulty adjustments.
+const DIFFICULTY_ADJUSTMENT_INTERVAL: u64 = 2016;
+
+/// The time (in seconds) a difficulty period should take.
+const TARGET_TIMESPAN: u64 = 14 * 24 * 60 * 60;
+
+/// The time (in seconds) a block should take.
+const TARGET_SPACING: u32 = 10 * 60;
+
+/// How often (in seconds) the light client syncs in the background.
+pub const SYNC_INTERVAL: u64 = 30;
+
+/// How long after its last successful sync the light client still answers from the tip it synced to.
+pub const MAX_SYNC_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// Timeout (in seconds) for the messages of peers.
+const PEER_TIMEOUT: u64 = 30;
+
+//
+// Configuration
+//
+
+/// A block we trust to be in the best chain, from which we start syncing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub height: u64,
+    pub hash: BlockHash,
+}
+
+impl FromStr for Checkpoint {
+    type Err = anyhow::Error;
+
+    /// Parses a checkpoint given as `height:hash`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (height, hash) = s
+            .split_once(':')
+            .context("expected a checkpoint as `height:hash`")?;
+        Ok(Self {
+            height: height.parse().context("invalid checkpoint height")?,
+            hash: BlockHash::from_str(hash).context("invalid checkpoint hash")?,
+        })
+    }
+}
+
+pub struct LightClientConfig {
+    /// The `host:port` of the peers to sync from (they must serve compact filters).
+    pub peers: Vec<String>,
+
+    /// The block to start syncing from.
+    /// Zkapps deployed before it are unknown to the client.
+    pub checkpoint: Checkpoint,
+
+    /// Where the synced chain is persisted.
+    pub state_path: PathBuf,
+}
+
+impl LightClientConfig {
+    /// Returns the default location of the synced chain (`~/.zkbitcoin/light-client.json`).
+    pub fn default_state_path() -> PathBuf {
+        zkbitcoin_folder().join("light-client.json")
+    }
+}
+
+//
+// Chain state
+//
+
+/// The lowest difficulty of a network (as the compact encoding of its highest target).
+fn pow_limit(network: Network) -> CompactTarget {
+    CompactTarget::from_consensus(match network {
+        Network::Signet => 0x1e0377ae,
+        Network::Regtest => 0x207fffff,
+        _ => 0x1d00ffff,
+    })
+}
+
+/// Returns `target * mul / div`, or None if it overflows.
+fn scale_target(target: Target, mul: u64, div: u64) -> Option<Target> {
+    let bytes = target.to_be_bytes();
+    let limbs: Vec<u64> = bytes
+        .chunks(8)
+        .map(|chunk| u64::from_be_bytes(chunk.try_into().unwrap()))
+        .collect();
+
+    // multiply, from the least significant limb
+    let mut product = [0u64; 5];
+    let mut carry = 0u128;
+    for (idx, limb) in limbs.iter().enumerate().rev() {
+        let value = u128::from(*limb) * u128::from(mul) + carry;
+        product[idx + 1] = value as u64;
+        carry = value >> 64;
+    }
+    product[0] = carry as u64;
+
+    // divide, from the most significant limb
+    let mut quotient = [0u64; 5];
+    let mut remainder = 0u128;
+    for (idx, limb) in product.iter().enumerate() {
+        let value = (remainder << 64) | u128::from(*limb);
+        quotient[idx] = (value / u128::from(div)) as u64;
+        remainder = value % u128::from(div);
+    }
+    if quotient[0] != 0 {
+        return None;
+    }
+
+    let mut bytes = [0u8; 32];
+    for (chunk, limb) in bytes.chunks_mut(8).zip(&quotient[1..]) {
+        chunk.copy_from_slice(&limb.to_be_bytes());
+    }
+    Some(Target::from_be_bytes(bytes))
+}
+
+/// Returns the difficulty of the period following the one that started at `first_time` and ended with `last`
+/// (see `CalculateNextWorkRequired` in Bitcoin Core).
+fn next_work_required(last: &Header, first_time: u32, pow_limit: CompactTarget) -> CompactTarget {
+    let timespan = u64::from(last.time)
+        .saturating_sub(u64::from(first_time))
+        .clamp(TARGET_TIMESPAN / 4, TARGET_TIMESPAN * 4);
+    let pow_limit = Target::from_compact(pow_limit);
+    let target = scale_target(Target::from_compact(last.bits), timespan, TARGET_TIMESPAN)
+        .filter(|target| *target <= pow_limit)
+        .unwrap_or(pow_limit);
+    target.to_compact_lossy()
+}
+
+/// An output paying to the zkBitcoin address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ZkappOutput {
+    outpoint: OutPoint,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    value: Amount,
+    height: u64,
+    spent_height: Option<u64>,
+}
+
+/// What the light client knows of the chain, since the checkpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChainState {
+    checkpoint: Checkpoint,
+
+    /// The header of the checkpoint, once fetched from a peer.
+    #[serde(default)]
+    checkpoint_header: Option<Header>,
+
+    /// The headers of the blocks after the checkpoint.
+    headers: Vec<Header>,
+
+    /// The filter header of the checkpoint, as agreed on by the peers.
+    checkpoint_filter_header: Option<FilterHeader>,
+
+    /// The filter headers of the blocks after the checkpoint.
+    filter_headers: Vec<FilterHeader>,
+
+    /// The number of blocks after the checkpoint whose filters were scanned.
+    scanned: usize,
+
+    /// The outputs to the zkBitcoin address found in the scanned blocks.
+    zkapps: Vec<ZkappOutput>,
+}
+
+impl ChainState {
+    fn new(checkpoint: Checkpoint) -> Self {
+        Self {
+            checkpoint,
+            checkpoint_header: None,
+            headers: vec![],
+            checkpoint_filter_header: None,
+            filter_headers: vec![],
+            scanned: 0,
+            zkapps: vec![],
+        }
+    }
+
+    /// Returns the height of the block at the given index of `headers`.
+    fn height(&self, idx: usize) -> u64 {
+        self.checkpoint.height + 1 + idx as u64
+    }
+
+    fn tip_height(&self) -> u64 {
+        self.checkpoint.height + self.headers.len() as u64
+    }
+
+    fn hash_at(&self, len: usize) -> BlockHash {
+        match len {
+            0 => self.checkpoint.hash,
+            len => self.headers[len - 1].block_hash(),
+        }
+    }
+
+    /// Returns the hashes of the last blocks we know of, most recent first (see `getheaders`).
+    fn locator(&self) -> Vec<BlockHash> {
+        let mut locator: Vec<_> = (0..=self.headers.len())
+            .rev()
+            .take(10)
+            .map(|len| self.hash_at(len))
+            .collect();
+        if !self.headers.is_empty() && locator.last() != Some(&self.checkpoint.hash) {
+            locator.push(self.checkpoint.hash);
+        }
+        locator
+    }
+
+    /// Checks that the difficulty of a header follows the retargeting rules of the network,
+    /// given the previous headers since the start of the difficulty period of its parent (at least).
+    /// `previous[0]` is the header at `first_height`.
+    fn check_difficulty(
+        previous: &[Header],
+        first_height: u64,
+        header: &Header,
+        height: u64,
+    ) -> Result<()> {
+        let at = |height: u64| {
+            height
+                .checked_sub(first_height)
+                .and_then(|idx| previous.get(idx as usize))
+                .with_context(|| format!("missing the header of block {height}"))
+        };
+        let last = at(height - 1)?;
+        let network = get_network();
+        let pow_limit = pow_limit(network);
+
+        let expected = if network == Network::Regtest {
+            // regtest doesn't retarget
+            last.bits
+        } else if height % DIFFICULTY_ADJUSTMENT_INTERVAL == 0 {
+            let first = at(height - DIFFICULTY_ADJUSTMENT_INTERVAL)?;
+            next_work_required(last, first.time, pow_limit)
+        } else if network == Network::Testnet && header.time > last.time + 2 * TARGET_SPACING {
+            // the testnet 20-minute rule
+            pow_limit
+        } else if network == Network::Testnet {
+            // the difficulty of the last block of the period not mined under the 20-minute rule
+            let period_start = height - height % DIFFICULTY_ADJUSTMENT_INTERVAL;
+            let mut bits = last.bits;
+            for height in (period_start..height).rev() {
+                bits = at(height)?.bits;
+                if bits != pow_limit {
+                    break;
+                }
+            }
+            bits
+        } else {
+            last.bits
+        };
+        ensure!(
+            header.bits == expected,
+            "the difficulty of block {height} is {:#x}, not {:#x}",
+            header.bits.to_consensus(),
+            expected.to_consensus()
+        );
+        Ok(())
+    }
+
+    /// Connects headers sent by a peer to our chain, if they lead to a chain with more work.
+    /// Returns true if our chain changed.
+    fn connect_headers(&mut self, headers: &[Header]) -> Result<bool> {
+        let Some(first) = headers.first() else {
+            return Ok(false);
+        };
+
+        // find where the headers fork from our chain
+        let fork = (0..=self.headers.len())
+            .rev()
+            .find(|len| self.hash_at(*len) == first.prev_blockhash)
+            .context("the headers don't connect to our chain")?;
+
+        // validate them (the difficulty only depends on the headers since the start of the period of the fork,
+        // which is the checkpoint at the latest)
+        let checkpoint_header = self
+            .checkpoint_header
+            .context("the header of the checkpoint wasn't fetched yet")?;
+        let fork_height = self.checkpoint.height + fork as u64;
+        let context_start = (fork_height % DIFFICULTY_ADJUSTMENT_INTERVAL) as usize;
+        let context_start = fork - context_start.min(fork);
+        let first_height = self.checkpoint.height + context_start as u64;
+        let mut previous: Vec<Header> = std::iter::once(checkpoint_header)
+            .chain(self.headers[..fork].iter().copied())
+            .skip(context_start)
+            .collect();
+        for header in headers {
+            let height = first_height + previous.len() as u64;
+            let previous_hash = previous.last().expect("the fork").block_hash();
+            ensure!(
+                header.prev_blockhash == previous_hash,
+                "the header of block {height} doesn't follow the previous one"
+            );
+            header
+                .validate_pow(header.target())
+                .with_context(|| format!("invalid proof of work for block {height}"))?;
+            Self::check_difficulty(&previous, first_height, header, height)?;
+            previous.push(*header);
+        }
+
+        // only switch to a chain with more work
+        let work = |headers: &[Header]| {
+            headers
+                .iter()
+                .fold(Work::from_be_bytes([0; 32]), |acc, header| {
+                    acc + header.work()
+                })
+        };
+        let new_headers = &previous[fork + 1 - context_start..];
+        if work(new_headers) <= work(&self.headers[fork..]) {
+            return Ok(false);
+        }
+
+        if fork < self.headers.len() {
+            info!(
+                "- reorg: replacing {} blocks from height {}",
+                self.headers.len() - fork,
+                self.height(fork)
+            );
+            self.rollback(fork);
+        }
+        self.headers.extend_from_slice(new_headers);
+        Ok(true)
+    }
+
+    /// Forgets the blocks after the first `len` blocks following the checkpoint.
+    fn rollback(&mut self, len: usize) {
+        let height = self.height(len);
+        self.headers.truncate(len);
+        self.filter_headers.truncate(len);
+        self.scanned = self.scanned.min(len);
+        self.zkapps.retain(|zkapp| zkapp.height < height);
+        for zkapp in &mut self.zkapps {
+            if zkapp.spent_height >= Some(height) {
+                zkapp.spent_height = None;
+            }
+        }
+    }
+
+    /// Returns the filter header preceding the block at the given index.
+    fn previous_filter_header(&self, idx: usize) -> Option<FilterHeader> {
+        match idx {
+            0 => self.checkpoint_filter_header,
+            idx => self.filter_headers.get(idx - 1).copied(),
+        }
+    }
+
+    /// Records the outputs to (and spends from) the zkBitcoin address of a block.
+    fn scan_block(&mut self, height: u64, block: &Block, script: &ScriptBuf) {
+        for tx in &block.txdata {
+            for input in &tx.input {
+                let spent = self.zkapps.iter_mut().find(|zkapp| {
+                    zkapp.outpoint == input.previous_output && zkapp.spent_height.is_none()
+                });
+                if let Some(zkapp) = spent {
+                    debug!("- zkapp {} spent at height {height}", zkapp.outpoint);
+                    zkapp.spent_height = Some(height);
+                }
+            }
+
+            let txid = tx.txid();
+            for (vout, output) in tx.output.iter().enumerate() {
+                if output.script_pubkey == *script {
+                    debug!("- zkapp {txid}:{vout} found at height {height}");
+                    self.zkapps.push(ZkappOutput {
+                        outpoint: OutPoint::new(txid, vout as u32),
+                        value: output.value,
+                        height,
+                        spent_height: None,
+                    });
+                }
+            }
+        }
+    }
+}
+
+//
+// Peers
+//
+
+/// A connection to a Bitcoin node serving compact filters.
+struct Peer {
+    address: String,
+    stream: TcpStream,
+    magic: Magic,
+}
+
+impl Peer {
+    /// Connects to a peer (through the proxy, if one is set) and goes through the version handshake.
+    async fn connect(address: &str) -> Result<Self> {
+        let stream = tokio::time::timeout(Duration::from_secs(PEER_TIMEOUT), connect_tcp(address))
+            .await
+            .context("timed out")??;
+        // the address of the peer isn't resolved here when going through the proxy, and peers don't rely on it
+        let socket_addr = address
+            .parse()
+            .unwrap_or_else(|_| SocketAddr::from(([0, 0, 0, 0], 0)));
+
+        let mut peer = Self {
+            address: address.to_string(),
+            stream,
+            magic: get_network().magic(),
+        };
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let version = VersionMessage::new(
+            ServiceFlags::NONE,
+            timestamp,
+            Address::new(&socket_addr, ServiceFlags::NONE),
+            Address::new(&SocketAddr::from(([0, 0, 0, 0], 0)), ServiceFlags::NONE),
+            rand::random(),
+            format!("/zkbitcoin:{}/", env!("CARGO_PKG_VERSION")),
+            0,
+        );
+        peer.send(NetworkMessage::Version(VersionMessage {
+            version: PROTOCOL_VERSION,
+            relay: false,
+            ..version
+        }))
+        .await?;
+
+        let their_version = peer
+            .wait_for(|msg| match msg {
+                NetworkMessage::Version(version) => Some(version),
+                _ => None,
+            })
+            .await?;
+        ensure!(
+            their_version
+                .services
+                .has(ServiceFlags::COMPACT_FILTERS | ServiceFlags::WITNESS),
+            "peer {address} doesn't serve compact filters (is it running with -peerblockfilters=1?)"
+        );
+        peer.send(NetworkMessage::Verack).await?;
+        peer.wait_for(|msg| matches!(msg, NetworkMessage::Verack).then_some(()))
+            .await?;
+
+        Ok(peer)
+    }
+
+    async fn send(&mut self, msg: NetworkMessage) -> Result<()> {
+        let raw = RawNetworkMessage::new(self.magic, msg);
+        self.stream
+            .write_all(&bitcoin::consensus::serialize(&raw))
+            .await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<NetworkMessage> {
+        // magic (4 bytes), command (12 bytes), payload length (4 bytes), checksum (4 bytes)
+        let mut bytes = vec![0u8; 24];
+        self.stream.read_exact(&mut bytes).await?;
+        let len = u32::from_le_bytes(bytes[16..20].try_into().unwrap()) as usize;
+        ensure!(len <= 32 * 1024 * 1024, "message too large");
+        bytes.resize(24 + len, 0);
+        self.stream.read_exact(&mut bytes[24..]).await?;
+
+        let raw: RawNetworkMessage = bitcoin::consensus::deserialize(&bytes)?;
+        ensure!(*raw.magic() == self.magic, "peer is on another network");
+        Ok(raw.into_payload())
+    }
+
+    /// Waits for a message that `extract` accepts, ignoring (or answering) the others.
+    async fn wait_for<T>(&mut self, extract: impl Fn(NetworkMessage) -> Option<T>) -> Result<T> {
+        loop {
+            let msg = tokio::time::timeout(Duration::from_secs(PEER_TIMEOUT), self.receive())
+                .await
+                .with_context(|| format!("peer {} timed out", self.address))??;
+            if let NetworkMessage::Ping(nonce) = msg {
+                self.send(NetworkMessage::Pong(nonce)).await?;
+                continue;
+            }
+            if let Some(res) = extract(msg) {
+                return Ok(res);
+            }
+        }
+    }
+
+    async fn get_headers(&mut self, locator: Vec<BlockHash>) -> Result<Vec<Header>> {
+        self.send(NetworkMessage::GetHeaders(GetHeadersMessage::new(
+            locator,
+            BlockHash::from_byte_array([0; 32]),
+        )))
+        .await?;
+        self.wait_for(|msg| match msg {
+            NetworkMessage::Headers(headers) => Some(headers),
+            _ => None,
+        })
+        .await
+    }
+
+    async fn get_cfheaders(
+        &mut self,
+        start_height: u64,
+        stop_hash: BlockHash,
+    ) -> Result<CFHeaders> {
+        self.send(NetworkMessage::GetCFHeaders(GetCFHeaders {
+            filter_type: BASIC_FILTER,
+            start_height: start_height as u32,
+            stop_hash,
+        }))
+        .await?;
+        self.wait_for(|msg| match msg {
+            NetworkMessage::CFHeaders(cfheaders) if cfheaders.stop_hash == stop_hash => {
+                Some(cfheaders)
+            }
+            _ => None,
+        })
+        .await
+    }
+
+    async fn get_cfilters(
+        &mut self,
+        start_height: u64,
+        stop_hash: BlockHash,
+        count: usize,
+    ) -> Result<Vec<(BlockHash, BlockFilter)>> {
+        self.send(NetworkMessage::GetCFilters(GetCFilters {
+            filter_type: BASIC_FILTER,
+            start_height: start_height as u32,
+            stop_hash,
+        }))
+        .await?;
+        let mut filters = Vec::with_capacity(count);
+        while filters.len() < count {
+            let filter = self
+                .wait_for(|msg| match msg {
+                    NetworkMessage::CFilter(cfilter) => {
+                        Some((cfilter.block_hash, BlockFilter::new(&cfilter.filter)))
+                    }
+                    _ => None,
+                })
+                .await?;
+            filters.push(filter);
+        }
+        Ok(filters)
+    }
+
+    async fn get_block(&mut self, hash: BlockHash) -> Result<Block> {
+        self.send(NetworkMessage::GetData(vec![Inventory::WitnessBlock(hash)]))
+            .await?;
+        let block = self
+            .wait_for(|msg| match msg {
+                NetworkMessage::Block(block) if block.block_hash() == hash => Some(block),
+                _ => None,
+            })
+            .await?;
+        ensure!(
+            block.check_merkle_root() && block.check_witness_commitment(),
+            "peer {} sent an invalid block {hash}",
+            self.address
+        );
+        Ok(block)
+    }
+}
+
+//
+// Client
+//
+
+/// The light client of a committee member.
+pub struct LightClient {
+    config: LightClientConfig,
+    /// The chain, as of the last sync.
+    state: Mutex<ChainState>,
+    /// Held while syncing, so that only one sync runs at once.
+    syncing: Mutex<()>,
+    /// When the last sync succeeded.
+    synced_at: std::sync::Mutex<Option<Instant>>,
+}
+
+impl LightClient {
+    /// Creates a light client, resuming from its persisted state if it has one (for the same checkpoint).
+    pub fn open(config: LightClientConfig) -> Result<Self> {
+        ensure!(
+            !config.peers.is_empty(),
+            "the light client needs at least one peer"
+        );
+        ensure!(
+            config.checkpoint.height % DIFFICULTY_ADJUSTMENT_INTERVAL == 0,
+            "the checkpoint must be the first block of a difficulty period (at a height multiple of {DIFFICULTY_ADJUSTMENT_INTERVAL}), not block {}",
+            config.checkpoint.height
+        );
+
+        let state = match Self::load_state(&config.state_path)? {
+            Some(state) if state.checkpoint == config.checkpoint => state,
+            _ => ChainState::new(config.checkpoint),
+        };
+        info!(
+            "- light client resuming at height {} ({} zkapp outputs known)",
+            state.tip_height(),
+            state.zkapps.len()
+        );
+
+        Ok(Self {
+            config,
+            state: Mutex::new(state),
+            syncing: Mutex::new(()),
+            synced_at: std::sync::Mutex::new(None),
+        })
+    }
+
+    fn load_state(path: &Path) -> Result<Option<ChainState>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = fs::File::open(path)?;
+        let state = serde_json::from_reader(file).context("couldn't parse light client state")?;
+        Ok(Some(state))
+    }
+
+    fn save_state(&self, state: &ChainState) -> Result<()> {
+        let file = fs::File::create(&self.config.state_path)
+            .context("couldn't create light client state")?;
+        serde_json::to_writer(file, state)?;
+        Ok(())
+    }
+
+    async fn connect_peers(&self) -> Result<Vec<Peer>> {
+        let mut peers = vec![];
+        for address in &self.config.peers {
+            match Peer::connect(address).await {
+                Ok(peer) => peers.push(peer),
+                Err(err) => warn!("- couldn't connect to peer {address}: {err:#}"),
+            }
+        }
+        ensure!(!peers.is_empty(), "couldn't connect to any peer");
+        Ok(peers)
+    }
+
+    /// Syncs every [SYNC_INTERVAL] seconds in the background.
+    pub fn spawn_sync(self: &Arc<Self>) {
+        let light_client = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(SYNC_INTERVAL));
+            loop {
+                interval.tick().await;
+                if let Err(err) = light_client.sync().await {
+                    error!("couldn't sync the light client: {err:#}");
+                }
+            }
+        });
+    }
+
+    /// Syncs headers, filter headers, and filters with the peers, up to the best chain's tip.
+    /// The sync works on a copy of the chain, so that zkapps are checked against the last synced tip meanwhile.
+    pub async fn sync(&self) -> Result<()> {
+        let _syncing = self.syncing.lock().await;
+        let mut state = self.state.lock().await.clone();
+        let res = self.sync_state(&mut state).await;
+
+        // keep what was synced, even if the peers failed us along the way
+        self.save_state(&state)?;
+        *self.state.lock().await = state;
+        res?;
+        *self.synced_at.lock().unwrap() = Some(Instant::now());
+        Ok(())
+    }
+
+    async fn sync_state(&self, state: &mut ChainState) -> Result<()> {
+        let mut peers = self.connect_peers().await?;
+
+        // the header of the checkpoint, which the difficulty of the next blocks depends on
+        if state.checkpoint_header.is_none() {
+            let block = peers[0].get_block(state.checkpoint.hash).await?;
+            state.checkpoint_header = Some(block.header);
+        }
+
+        // headers: keep the chain with the most work
+        for peer in &mut peers {
+            loop {
+                let headers = peer.get_headers(state.locator()).await?;
+                let changed = state
+                    .connect_headers(&headers)
+                    .with_context(|| format!("peer {} sent invalid headers", peer.address))?;
+                if !changed || headers.len() < MAX_HEADERS {
+                    break;
+                }
+            }
+        }
+        debug!("- synced headers up to height {}", state.tip_height());
+
+        // filter headers: all peers must agree
+        while state.filter_headers.len() < state.headers.len() {
+            let start = state.filter_headers.len();
+            let stop = (start + MAX_CFHEADERS).min(state.headers.len()) - 1;
+            let stop_hash = state.headers[stop].block_hash();
+
+            let mut agreed: Option<(FilterHeader, Vec<FilterHeader>)> = None;
+            for peer in &mut peers {
+                let cfheaders = peer.get_cfheaders(state.height(start), stop_hash).await?;
+                let mut previous = cfheaders.previous_filter_header;
+                let filter_headers: Vec<_> = cfheaders
+                    .filter_hashes
+                    .iter()
+                    .map(|filter_hash| {
+                        previous = filter_hash.filter_header(&previous);
+                        previous
+                    })
+                    .collect();
+                ensure!(
+                    filter_headers.len() == stop - start + 1,
+                    "peer {} sent the wrong number of filter headers",
+                    peer.address
+                );
+
+                let received = (cfheaders.previous_filter_header, filter_headers);
+                match &agreed {
+                    None => agreed = Some(received),
+                    Some(agreed) => ensure!(
+                        *agreed == received,
+                        "peers disagree on the filters of blocks {} to {} (peer {} might be lying)",
+                        state.height(start),
+                        state.height(stop),
+                        peer.address
+                    ),
+                }
+            }
+
+            let (previous, filter_headers) = agreed.expect("at least one peer");
+            match state.previous_filter_header(start) {
+                Some(expected) => ensure!(
+                    previous == expected,
+                    "the filter headers don't connect to the previous ones"
+                ),
+                None => state.checkpoint_filter_header = Some(previous),
+            }
+            state.filter_headers.extend(filter_headers);
+        }
+
+        // filters: download the blocks paying to or spending from the zkBitcoin address
+        let zkbitcoin_pubkey = PublicKey::from_str(&protocol_config().zkbitcoin_pubkey)?;
+        let script = p2tr_script_to(zkbitcoin_pubkey);
+        let peer = &mut peers[0];
+        while state.scanned < state.filter_headers.len() {
+            let start = state.scanned;
+            let stop = (start + MAX_CFILTERS).min(state.filter_headers.len()) - 1;
+            let stop_hash = state.headers[stop].block_hash();
+            let filters = peer
+                .get_cfilters(state.height(start), stop_hash, stop - start + 1)
+                .await?;
+
+            for (idx, (block_hash, filter)) in (start..=stop).zip(filters) {
+                ensure!(
+                    block_hash == state.headers[idx].block_hash(),
+                    "peer {} sent filters out of order",
+                    peer.address
+                );
+                let previous = state
+                    .previous_filter_header(idx)
+                    .ok_or_else(|| anyhow!("missing filter header"))?;
+                ensure!(
+                    filter.filter_header(&previous) == state.filter_headers[idx],
+                    "peer {} sent a filter that doesn't match its header",
+                    peer.address
+                );
+
+                if filter.match_any(&block_hash, std::iter::once(script.as_bytes()))? {
+                    let block = peer.get_block(block_hash).await?;
+                    let height = state.height(idx);
+                    state.scan_block(height, &block, &script);
+                }
+                state.scanned = idx + 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a zkapp is unspent, and buried at least `min_confirmations` deep in the best chain.
+    pub async fn check_zkapp(
+        &self,
+        smart_contract: &SmartContract,
+        min_confirmations: u32,
+    ) -> Result<()> {
+        let synced_at = *self.synced_at.lock().unwrap();
+        ensure!(
+            synced_at.is_some_and(|synced_at| synced_at.elapsed() <= MAX_SYNC_AGE),
+            "the light client didn't sync in the last {}s",
+            MAX_SYNC_AGE.as_secs()
+        );
+
+        let state = self.state.lock().await;
+        let outpoint = OutPoint::new(smart_contract.txid, smart_contract.vout_of_zkbitcoin_utxo);
+        let zkapp = state
+            .zkapps
+            .iter()
+            .find(|zkapp| zkapp.outpoint == outpoint)
+            .with_context(|| {
+                format!(
+                    "zkapp {outpoint} wasn't found in the blocks since height {}",
+                    state.checkpoint.height
+                )
+            })?;
+        ensure!(
+            zkapp.value == smart_contract.locked_value,
+            "zkapp {outpoint} locks {} on chain, not {}",
+            zkapp.value,
+            smart_contract.locked_value
+        );
+        if let Some(height) = zkapp.spent_height {
+            bail!("zkapp {outpoint} was already spent at height {height}");
+        }
+        let confirmations = (state.tip_height() + 1).saturating_sub(zkapp.height);
+        ensure!(
+            confirmations >= u64::from(min_confirmations),
+            "zkapp {outpoint} has {confirmations} confirmations, but the committee requires {min_confirmations}"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{block::Version, TxMerkleNode};
+
+    use super::*;
+
+    /// Mines a header on top of another, at the (lowest) difficulty of regtest.
+    fn mine(prev_blockhash: BlockHash, time: u32) -> Header {
+        let mut header = Header {
+            version: Version::ONE,
+            prev_blockhash,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 0,
+        };
+        while header.validate_pow(header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    fn mine_chain(prev_blockhash: BlockHash, len: usize, time: u32) -> Vec<Header> {
+        let mut headers: Vec<Header> = vec![];
+        for _ in 0..len {
+            let prev = headers
+                .last()
+                .map_or(prev_blockhash, |header| header.block_hash());
+            headers.push(mine(prev, time));
+        }
+        headers
+    }
+
+    #[test]
+    fn test_checkpoint_from_str() {
+        let checkpoint = Checkpoint::from_str(
+            "100:000000000000000000000000000000000000000000000000000000000000abcd",
+        )
+        .unwrap();
+        assert_eq!(checkpoint.height, 100);
+        assert!(Checkpoint::from_str("100").is_err());
+    }
+
+    fn header(time: u32, bits: u32) -> Header {
+        Header {
+            version: Version::ONE,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits: CompactTarget::from_consensus(bits),
+            nonce: 0,
+        }
+    }
+
+    #[test]
+    fn test_next_work_required() {
+        // the test vectors of Bitcoin Core (blocks of mainnet)
+        let pow_limit = pow_limit(Network::Bitcoin);
+        for (first_time, last_time, last_bits, expected) in [
+            (1261130161, 1262152739, 0x1d00ffff, 0x1d00d86a),
+            (1231006505, 1233061996, 0x1d00ffff, 0x1d00ffff),
+            (1279008237, 1279297671, 0x1c05a3f4, 0x1c0168fd),
+            (1263163443, 1269211443, 0x1c387f6f, 0x1d00e1fd),
+        ] {
+            let last = header(last_time, last_bits);
+            assert_eq!(
+                next_work_required(&last, first_time, pow_limit).to_consensus(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_difficulty() {
+        let previous = [header(0, 0x1c05a3f4), header(600, 0x1c05a3f4)];
+        let check =
+            |time, bits| ChainState::check_difficulty(&previous, 2016, &header(time, bits), 2018);
+        check(1200, 0x1c05a3f4).unwrap();
+        assert!(check(1200, 0x1c05a3f5).is_err());
+        // a retarget needs the first block of the period
+        assert!(
+            ChainState::check_difficulty(&previous, 2016, &header(1200, 0x1c05a3f4), 4032).is_err()
+        );
+    }
+
+    #[test]
+    fn test_connect_headers_and_reorg() {
+        let checkpoint_header = mine(BlockHash::all_zeros(), 1);
+        let checkpoint = Checkpoint {
+            height: 100_800,
+            hash: checkpoint_header.block_hash(),
+        };
+        let mut state = ChainState::new(checkpoint);
+
+        // the difficulty can't be checked without the header of the checkpoint
+        let chain = mine_chain(checkpoint.hash, 3, 1);
+        assert!(state.connect_headers(&chain).is_err());
+        state.checkpoint_header = Some(checkpoint_header);
+
+        assert!(state.connect_headers(&chain).unwrap());
+        assert_eq!(state.tip_height(), 100_803);
+
+        // headers that don't connect are refused
+        assert!(state
+            .connect_headers(&mine_chain(BlockHash::hash(b"elsewhere"), 1, 1))
+            .is_err());
+
+        // a zkapp found in the last block
+        state.scanned = 3;
+        state.zkapps.push(ZkappOutput {
+            outpoint: OutPoint::null(),
+            value: Amount::from_sat(1000),
+            height: 100_803,
+            spent_height: None,
+        });
+
+        // a fork with less work is ignored
+        let fork = mine_chain(chain[1].block_hash(), 1, 2);
+        assert!(!state.connect_headers(&fork).unwrap());
+        assert_eq!(state.headers, chain);
+
+        // a fork with more work replaces our chain
+        let fork = mine_chain(chain[1].block_hash(), 2, 2);
+        assert!(state.connect_headers(&fork).unwrap());
+        assert_eq!(state.tip_height(), 100_804);
+        assert_eq!(state.headers[2..], fork[..]);
+        assert_eq!(state.scanned, 2);
+        assert!(state.zkapps.is_empty());
+    }
+}
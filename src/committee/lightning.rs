@@ -0,0 +1,343 @@
+//! Payment of the committee's fee over Lightning, instead of with an output of the transaction signed.
+//!
+//! When asked for, the orchestrator attaches to its fee quote an invoice issued by its Lightning node
+//! (see [LightningNode]). Bob then makes a transaction without the fee output, refers to the invoice
+//! in his request (by its payment hash), and pays it: the orchestrator only starts signing once it's settled.
+//! An invoice pays for the use of a single zkapp (the first request referring to it binds it).
+//!
+//! Nodes can't see Lightning payments: they accept requests without a fee output
+//! if the committee configuration enables `lightning_fees`, trusting the orchestrator to have been paid.
+//! Invoices are only tracked in memory, so the ones issued before the orchestrator restarts are not honored.
+
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use anyhow::{ensure, Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine};
+use bitcoin::{Amount, OutPoint};
+use log::{debug, info};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use tokio::time::Instant;
+
+use crate::{
+    config::LightningInvoice,
+    json_rpc_stuff::{http_client, proxy},
+};
+
+use super::storage::now;
+
+//
+// Constants
+//
+
+/// How long (in seconds) an invoice can be paid (and used in a request) for.
+pub const INVOICE_EXPIRY: u64 = 3600;
+
+/// How long (in seconds) the orchestrator waits for an invoice to be paid by default, once it receives the request.
+pub const DEFAULT_PAYMENT_TIMEOUT: u64 = 600;
+
+/// Interval (in seconds) at which the orchestrator checks whether an invoice is paid.
+const PAYMENT_POLL_INTERVAL: u64 = 2;
+
+/// Timeout (in seconds) for requests to the Lightning node.
+const LND_TIMEOUT: u64 = 10;
+
+/// The description of the invoices issued.
+const INVOICE_MEMO: &str = "zkBitcoin committee fee";
+
+//
+// Interface
+//
+
+/// What the orchestrator needs from its Lightning node.
+#[async_trait]
+pub trait LightningNode: Send + Sync {
+    /// Issues an invoice for `amount`.
+    async fn create_invoice(&self, amount: Amount, memo: &str) -> Result<LightningInvoice>;
+
+    /// Whether the invoice of a (hex-encoded) payment hash is settled.
+    async fn is_paid(&self, payment_hash: &str) -> Result<bool>;
+}
+
+//
+// LND
+//
+
+/// An LND node, through its REST API.
+/// (LND only issues BOLT11 invoices.)
+pub struct Lnd {
+    url: String,
+    /// The hex-encoded macaroon authenticating requests (which must allow creating and reading invoices).
+    macaroon: String,
+    client: reqwest::Client,
+}
+
+#[derive(Deserialize)]
+struct LndAddedInvoice {
+    /// The base64-encoded payment hash.
+    r_hash: String,
+    payment_request: String,
+}
+
+#[derive(Deserialize)]
+struct LndInvoice {
+    state: String,
+}
+
+impl Lnd {
+    /// Creates a client of the REST API of LND at `url`.
+    /// `tls_cert` is the PEM certificate of the node, if it's self-signed (as LND's is by default).
+    pub fn new(url: &str, macaroon: &[u8], tls_cert: Option<&[u8]>) -> Result<Self> {
+        let client = match tls_cert {
+            Some(pem) => {
+                let mut builder = reqwest::Client::builder().add_root_certificate(
+                    reqwest::Certificate::from_pem(pem).context("invalid TLS certificate")?,
+                );
+                if let Some(proxy) = proxy() {
+                    builder = builder.proxy(proxy.clone());
+                }
+                builder.build()?
+            }
+            None => http_client().clone(),
+        };
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            macaroon: hex::encode(macaroon),
+            client,
+        })
+    }
+
+    /// Sends an authenticated request, and parses its response.
+    async fn call<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+        what: &str,
+    ) -> Result<T> {
+        let response = request
+            .header("Grpc-Metadata-macaroon", &self.macaroon)
+            .timeout(Duration::from_secs(LND_TIMEOUT))
+            .send()
+            .await
+            .with_context(|| format!("couldn't reach LND at {}", self.url))?;
+        let status = response.status();
+        let body = response.text().await?;
+        ensure!(status.is_success(), "{what} failed ({status}): {body}");
+        serde_json::from_str(&body)
+            .with_context(|| format!("unexpected response from LND when {what}: {body}"))
+    }
+}
+
+#[async_trait]
+impl LightningNode for Lnd {
+    async fn create_invoice(&self, amount: Amount, memo: &str) -> Result<LightningInvoice> {
+        let url = format!("{}/v1/invoices", self.url);
+        debug!("- POST {url}");
+        let body = json!({
+            "value": amount.to_sat().to_string(),
+            "memo": memo,
+            "expiry": INVOICE_EXPIRY.to_string(),
+        });
+        let added: LndAddedInvoice = self
+            .call(self.client.post(&url).json(&body), "creating an invoice")
+            .await?;
+        let payment_hash = general_purpose::STANDARD
+            .decode(&added.r_hash)
+            .context("LND returned an invalid payment hash")?;
+        Ok(LightningInvoice {
+            bolt11: added.payment_request,
+            payment_hash: hex::encode(payment_hash),
+            amount,
+            expires_at: now() + INVOICE_EXPIRY,
+        })
+    }
+
+    async fn is_paid(&self, payment_hash: &str) -> Result<bool> {
+        let url = format!("{}/v1/invoice/{payment_hash}", self.url);
+        debug!("- GET {url}");
+        let invoice: LndInvoice = self
+            .call(self.client.get(&url), "looking up an invoice")
+            .await?;
+        Ok(invoice.state == "SETTLED")
+    }
+}
+
+//
+// Invoices
+//
+
+/// An invoice issued by the orchestrator.
+struct Issued {
+    invoice: LightningInvoice,
+
+    /// The zkapp whose use the invoice pays for, once a request referred to it.
+    zkapp: Option<OutPoint>,
+}
+
+/// The invoices issued by the orchestrator for the committee's fee, and the node issuing them.
+pub struct Lightning {
+    node: Box<dyn LightningNode>,
+
+    /// How long a request waits for its invoice to be paid.
+    pub payment_timeout: Duration,
+
+    /// The invoices that haven't expired, by payment hash.
+    invoices: Mutex<HashMap<String, Issued>>,
+}
+
+impl Lightning {
+    pub fn new(node: Box<dyn LightningNode>) -> Self {
+        Self {
+            node,
+            payment_timeout: Duration::from_secs(DEFAULT_PAYMENT_TIMEOUT),
+            invoices: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Issues an invoice for a fee.
+    pub async fn invoice(&self, fee: Amount) -> Result<LightningInvoice> {
+        let invoice = self
+            .node
+            .create_invoice(fee, INVOICE_MEMO)
+            .await
+            .context("couldn't issue a Lightning invoice")?;
+
+        let mut invoices = self.invoices.lock().unwrap();
+        let now = now();
+        invoices.retain(|_, issued| issued.invoice.expires_at > now);
+        invoices.insert(
+            invoice.payment_hash.clone(),
+            Issued {
+                invoice: invoice.clone(),
+                zkapp: None,
+            },
+        );
+        Ok(invoice)
+    }
+
+    /// The fee an invoice issued (and not expired) is for.
+    pub fn fee(&self, payment_hash: &str) -> Option<Amount> {
+        let invoices = self.invoices.lock().unwrap();
+        invoices
+            .get(payment_hash)
+            .map(|issued| issued.invoice.amount)
+    }
+
+    /// Ensures that an invoice issued by the orchestrator covers the fee `owed` for the use of `zkapp`
+    /// (binding the invoice to it), then waits for it to be paid.
+    pub async fn wait_paid(&self, payment_hash: &str, owed: Amount, zkapp: OutPoint) -> Result<()> {
+        {
+            let mut invoices = self.invoices.lock().unwrap();
+            let issued = invoices
+                .get_mut(payment_hash)
+                .filter(|issued| issued.invoice.expires_at > now())
+                .with_context(|| {
+                    format!("the fee invoice {payment_hash} wasn't issued by the orchestrator, or expired")
+                })?;
+            ensure!(
+                issued.invoice.amount >= owed,
+                "the fee invoice is for {}, but the committee takes {owed}",
+                issued.invoice.amount
+            );
+            match issued.zkapp {
+                Some(paid_for) => ensure!(
+                    paid_for == zkapp,
+                    "the fee invoice {payment_hash} pays for the use of another zkapp ({paid_for})"
+                ),
+                None => issued.zkapp = Some(zkapp),
+            }
+        }
+
+        info!("- waiting for the fee invoice {payment_hash} to be paid");
+        let deadline = Instant::now() + self.payment_timeout;
+        loop {
+            if self.node.is_paid(payment_hash).await? {
+                return Ok(());
+            }
+            ensure!(
+                Instant::now() < deadline,
+                "the fee invoice {payment_hash} wasn't paid in time"
+            );
+            tokio::time::sleep(Duration::from_secs(PAYMENT_POLL_INTERVAL)).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Arc,
+    };
+
+    use bitcoin::{hashes::Hash, Txid};
+
+    use super::*;
+
+    /// A node issuing invoices that are paid as soon as `paid` is set.
+    #[derive(Default)]
+    struct MockNode {
+        issued: AtomicU8,
+        paid: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl LightningNode for MockNode {
+        async fn create_invoice(&self, amount: Amount, _memo: &str) -> Result<LightningInvoice> {
+            let i = self.issued.fetch_add(1, Ordering::SeqCst);
+            Ok(LightningInvoice {
+                bolt11: format!("lnbcrt{i}"),
+                payment_hash: hex::encode([i; 32]),
+                amount,
+                expires_at: now() + INVOICE_EXPIRY,
+            })
+        }
+
+        async fn is_paid(&self, _payment_hash: &str) -> Result<bool> {
+            Ok(self.paid.load(Ordering::SeqCst))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_paid() {
+        let node = MockNode::default();
+        let paid = node.paid.clone();
+        let mut lightning = Lightning::new(Box::new(node));
+        lightning.payment_timeout = Duration::ZERO;
+        let zkapp = OutPoint::new(Txid::all_zeros(), 0);
+        let other_zkapp = OutPoint::new(Txid::all_zeros(), 1);
+
+        let invoice = lightning.invoice(Amount::from_sat(1000)).await.unwrap();
+        assert_eq!(
+            lightning.fee(&invoice.payment_hash),
+            Some(Amount::from_sat(1000))
+        );
+
+        // unknown invoices, and invoices for less than what's owed, are refused
+        assert!(lightning
+            .wait_paid(&hex::encode([9; 32]), Amount::from_sat(1000), zkapp)
+            .await
+            .is_err());
+        assert!(lightning
+            .wait_paid(&invoice.payment_hash, Amount::from_sat(2000), zkapp)
+            .await
+            .is_err());
+
+        // the invoice must be paid
+        assert!(lightning
+            .wait_paid(&invoice.payment_hash, Amount::from_sat(1000), zkapp)
+            .await
+            .is_err());
+        paid.store(true, Ordering::SeqCst);
+        lightning
+            .wait_paid(&invoice.payment_hash, Amount::from_sat(1000), zkapp)
+            .await
+            .unwrap();
+
+        // and only pays for the zkapp it was first used with
+        assert!(lightning
+            .wait_paid(&invoice.payment_hash, Amount::from_sat(1000), other_zkapp)
+            .await
+            .is_err());
+    }
+}
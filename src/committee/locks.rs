@@ -0,0 +1,329 @@
+//! Locks on the zkapp UTXOs being spent.
+//!
+//! Two requests spending the same zkapp (e.g. two users of a stateful zkapp, each proving a transition from its
+//! current state) would both be signed, and only one of the transactions would make it on chain.
+//! The orchestrator thus only lets one spend of a zkapp UTXO be in flight (see [UtxoLocks]):
+//! other requests spending it wait for it to be signed or to fail. If it's signed, they're rejected, as the zkapp
+//! moved to a new state, which they must be made against instead.
+//!
+//! Requests can be made against the expected new state of a zkapp right away, spending the zkapp output of a spend
+//! still in flight: they wait for that spend to be signed (and are rejected if it fails).
+//!
+//! Once a spend is signed, its UTXO is remembered as spent (see [SIGNED_SPEND_TTL]), so that other transactions
+//! spending it are rejected until the signed one confirms (after which the index of the orchestrator, if any,
+//! knows the zkapp is spent). Only the transaction that was signed can be signed again (e.g. when retried).
+//! Locks are only held by the orchestrator taking the requests, so replicas (see [super::shared]) don't see each other's.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use bitcoin::{OutPoint, Txid};
+use log::debug;
+use tokio::sync::watch;
+
+/// How long a request waits (by default) for the spend of the zkapp it uses to be signed or to fail.
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How long a zkapp UTXO is remembered as spent once a spend of it was signed (long enough for it to confirm).
+pub const SIGNED_SPEND_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A spend of a zkapp in flight, and whether it was signed (once it's done).
+struct InFlight {
+    txid: Txid,
+    done: watch::Receiver<Option<bool>>,
+}
+
+/// The zkapp UTXOs being spent, by the transactions spending them.
+pub struct UtxoLocks {
+    /// How long the UTXOs whose spend was signed are remembered.
+    pub signed_ttl: Duration,
+    in_flight: Mutex<HashMap<OutPoint, InFlight>>,
+    /// The UTXOs whose spend was signed, with the transaction spending them and when it was signed.
+    signed: Mutex<HashMap<OutPoint, (Txid, Instant)>>,
+}
+
+impl Default for UtxoLocks {
+    fn default() -> Self {
+        Self::new(SIGNED_SPEND_TTL)
+    }
+}
+
+/// A lock on a zkapp UTXO, released when dropped (as failed, unless [UtxoLock::signed] was called).
+pub struct UtxoLock<'a> {
+    locks: &'a UtxoLocks,
+    outpoint: OutPoint,
+    done: watch::Sender<Option<bool>>,
+    signed: bool,
+}
+
+impl UtxoLock<'_> {
+    /// Records that the spend was signed, so that the requests waiting on the UTXO are rejected
+    /// (and the ones spending its new state go ahead).
+    pub fn signed(&mut self) {
+        self.signed = true;
+    }
+}
+
+impl Drop for UtxoLock<'_> {
+    fn drop(&mut self) {
+        let in_flight = self.locks.in_flight.lock().unwrap().remove(&self.outpoint);
+        if let Some(in_flight) = in_flight.filter(|_| self.signed) {
+            self.locks
+                .signed
+                .lock()
+                .unwrap()
+                .insert(self.outpoint, (in_flight.txid, Instant::now()));
+        }
+        self.done.send_replace(Some(self.signed));
+    }
+}
+
+impl UtxoLocks {
+    pub fn new(signed_ttl: Duration) -> Self {
+        Self {
+            signed_ttl,
+            in_flight: Mutex::new(HashMap::new()),
+            signed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the transaction whose spend of `outpoint` was signed lately, if any.
+    fn signed_spend(&self, outpoint: &OutPoint) -> Option<Txid> {
+        let mut signed = self.signed.lock().unwrap();
+        signed.retain(|_, (_, signed_at)| signed_at.elapsed() < self.signed_ttl);
+        signed.get(outpoint).map(|(txid, _)| *txid)
+    }
+
+    /// The zkapp UTXOs being spent, and the transactions spending them.
+    pub fn in_flight(&self) -> Vec<(OutPoint, Txid)> {
+        self.in_flight
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(outpoint, in_flight)| (*outpoint, in_flight.txid))
+            .collect()
+    }
+
+    /// Locks the zkapp UTXO `outpoint` for its spend by `txid`, once the spends it depends on are done
+    /// (waiting for at most `timeout` on each).
+    pub async fn lock(
+        &self,
+        outpoint: OutPoint,
+        txid: Txid,
+        timeout: Duration,
+    ) -> Result<UtxoLock<'_>> {
+        loop {
+            if let Some(spent_by) = self
+                .signed_spend(&outpoint)
+                .filter(|spent_by| *spent_by != txid)
+            {
+                bail!("zkapp {outpoint} was already spent by {spent_by}, the request must be made against its new state");
+            }
+            let (in_flight, mut done, builds_on) = {
+                let mut spends = self.in_flight.lock().unwrap();
+
+                // the request might be made against the new state of a zkapp still being spent
+                let parent = spends
+                    .values()
+                    .find(|spend| spend.txid == outpoint.txid)
+                    .map(|spend| (spend.txid, spend.done.clone(), true));
+                let spend = spends
+                    .get(&outpoint)
+                    .map(|spend| (spend.txid, spend.done.clone(), false));
+                match parent.or(spend) {
+                    Some((in_flight, _, false)) if in_flight == txid => {
+                        bail!("{txid} is already being signed")
+                    }
+                    Some(waiting_on) => waiting_on,
+                    None => {
+                        let (done, receiver) = watch::channel(None);
+                        spends.insert(
+                            outpoint,
+                            InFlight {
+                                txid,
+                                done: receiver,
+                            },
+                        );
+                        return Ok(UtxoLock {
+                            locks: self,
+                            outpoint,
+                            done,
+                            signed: false,
+                        });
+                    }
+                }
+            };
+
+            debug!("- waiting for the spend of the zkapp by {in_flight}");
+            let signed = match tokio::time::timeout(timeout, done.wait_for(Option::is_some)).await {
+                Ok(Ok(signed)) => *signed == Some(true),
+                // the lock was released without an outcome
+                Ok(Err(_)) => false,
+                Err(_) => bail!(
+                    "the zkapp is still being spent by {in_flight} after {}s, try again later",
+                    timeout.as_secs()
+                ),
+            };
+            match (builds_on, signed) {
+                (true, false) => bail!(
+                    "the spend of the zkapp this request is made against ({in_flight}) wasn't signed"
+                ),
+                (false, true) => bail!(
+                    "zkapp {outpoint} was just spent by {in_flight}, the request must be made against its new state"
+                ),
+                // the UTXO might be free now
+                _ => continue,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    fn txid(byte: u8) -> Txid {
+        Txid::from_byte_array([byte; 32])
+    }
+
+    #[tokio::test]
+    async fn test_one_spend_in_flight() {
+        let locks = Arc::new(UtxoLocks::default());
+        let zkapp = OutPoint::new(txid(0), 0);
+
+        // a failed spend lets the next one go
+        let first = locks
+            .lock(zkapp, txid(1), DEFAULT_LOCK_TIMEOUT)
+            .await
+            .unwrap();
+        let waiting = {
+            let locks = locks.clone();
+            tokio::spawn(async move {
+                locks
+                    .lock(zkapp, txid(2), DEFAULT_LOCK_TIMEOUT)
+                    .await
+                    .map(|_| ())
+            })
+        };
+        tokio::task::yield_now().await;
+        assert_eq!(locks.in_flight(), vec![(zkapp, txid(1))]);
+        drop(first);
+        waiting.await.unwrap().unwrap();
+
+        // a signed one rejects the others
+        let mut second = locks
+            .lock(zkapp, txid(2), DEFAULT_LOCK_TIMEOUT)
+            .await
+            .unwrap();
+        let waiting = {
+            let locks = locks.clone();
+            tokio::spawn(async move {
+                locks
+                    .lock(zkapp, txid(3), DEFAULT_LOCK_TIMEOUT)
+                    .await
+                    .map(|_| ())
+            })
+        };
+        tokio::task::yield_now().await;
+        second.signed();
+        drop(second);
+        let err = waiting.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("new state"));
+
+        // and waiting is bounded
+        let other = OutPoint::new(txid(0), 1);
+        let _third = locks
+            .lock(other, txid(3), DEFAULT_LOCK_TIMEOUT)
+            .await
+            .unwrap();
+        assert!(locks
+            .lock(other, txid(4), Duration::from_millis(10))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_signed_spends_are_remembered() {
+        let locks = UtxoLocks::new(Duration::from_millis(50));
+        let zkapp = OutPoint::new(txid(0), 0);
+        locks
+            .lock(zkapp, txid(1), DEFAULT_LOCK_TIMEOUT)
+            .await
+            .unwrap()
+            .signed();
+
+        // only the signed transaction can spend the UTXO again
+        let err = locks
+            .lock(zkapp, txid(2), DEFAULT_LOCK_TIMEOUT)
+            .await
+            .map(|_| ())
+            .unwrap_err();
+        assert!(err.to_string().contains("already spent by"));
+        drop(
+            locks
+                .lock(zkapp, txid(1), DEFAULT_LOCK_TIMEOUT)
+                .await
+                .unwrap(),
+        );
+
+        // until it's forgotten
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        locks
+            .lock(zkapp, txid(2), DEFAULT_LOCK_TIMEOUT)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_expected_new_state() {
+        let locks = Arc::new(UtxoLocks::default());
+        let zkapp = OutPoint::new(txid(0), 0);
+        let new_zkapp = OutPoint::new(txid(1), 1);
+
+        // spends of the new state wait for the spend creating it to be signed
+        let mut spend = locks
+            .lock(zkapp, txid(1), DEFAULT_LOCK_TIMEOUT)
+            .await
+            .unwrap();
+        let waiting = {
+            let locks = locks.clone();
+            tokio::spawn(async move {
+                locks
+                    .lock(new_zkapp, txid(2), DEFAULT_LOCK_TIMEOUT)
+                    .await
+                    .map(|_| ())
+            })
+        };
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+        spend.signed();
+        drop(spend);
+        waiting.await.unwrap().unwrap();
+
+        // and fail if it fails
+        let spend = locks
+            .lock(new_zkapp, txid(2), DEFAULT_LOCK_TIMEOUT)
+            .await
+            .unwrap();
+        let waiting = {
+            let locks = locks.clone();
+            tokio::spawn(async move {
+                locks
+                    .lock(OutPoint::new(txid(2), 1), txid(3), DEFAULT_LOCK_TIMEOUT)
+                    .await
+                    .map(|_| ())
+            })
+        };
+        tokio::task::yield_now().await;
+        drop(spend);
+        assert!(waiting.await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,299 @@
+//! Versioned migrations of the orchestrator storage layout.
+//!
+//! The version of a storage directory is written in its `VERSION` file
+//! (storages created before versioning was introduced don't have one, and are at version 1).
+//! Each migration moves a storage from the previous version to its own version, and can be rolled back.
+//! Migrations only ever move files around with atomic renames and can be resumed if interrupted,
+//! while the version is bumped only once a migration is complete.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{ensure, Context, Result};
+use log::info;
+
+//
+// Constants
+//
+
+/// The storage version expected by this version of the orchestrator.
+pub const CURRENT_VERSION: u32 = 2;
+
+/// The file containing the version of a storage directory.
+const VERSION_FILE: &str = "VERSION";
+
+/// The version of storages created before versioning was introduced.
+const UNVERSIONED: u32 = 1;
+
+//
+// Data structures
+//
+
+/// A migration from version `version - 1` to `version`.
+pub struct Migration {
+    /// The version this migration upgrades to.
+    pub version: u32,
+
+    /// What the migration does.
+    pub description: &'static str,
+
+    up: fn(&Path) -> Result<()>,
+    down: fn(&Path) -> Result<()>,
+}
+
+/// All the migrations, in order.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    version: 2,
+    description: "shard request records by the first byte of their hash",
+    up: shard_records,
+    down: unshard_records,
+}];
+
+/// A step of a migration plan.
+pub struct Step {
+    /// The migration to apply or roll back.
+    pub migration: &'static Migration,
+
+    /// Whether the migration is rolled back.
+    pub rollback: bool,
+}
+
+impl Step {
+    /// The version of the storage once this step is done.
+    pub fn target(&self) -> u32 {
+        if self.rollback {
+            self.migration.version - 1
+        } else {
+            self.migration.version
+        }
+    }
+}
+
+//
+// Versioning
+//
+
+fn version_path(dir: &Path) -> PathBuf {
+    dir.join(VERSION_FILE)
+}
+
+/// Returns the version of the storage at the given directory.
+/// A directory that doesn't contain a storage yet is considered to be at the current version.
+pub fn version(dir: &Path) -> Result<u32> {
+    let path = version_path(dir);
+    if path.exists() {
+        let version = fs::read_to_string(&path).context("couldn't read storage version")?;
+        return version
+            .trim()
+            .parse()
+            .context("the storage version is malformed");
+    }
+
+    if dir.join("requests").exists() {
+        Ok(UNVERSIONED)
+    } else {
+        Ok(CURRENT_VERSION)
+    }
+}
+
+/// Writes the version of the storage at the given directory.
+pub fn set_version(dir: &Path, version: u32) -> Result<()> {
+    // write then rename, so that the version is never half-written
+    let tmp_path = dir.join(format!("{VERSION_FILE}.tmp"));
+    fs::write(&tmp_path, format!("{version}\n")).context("couldn't write storage version")?;
+    fs::rename(tmp_path, version_path(dir)).context("couldn't write storage version")?;
+    Ok(())
+}
+
+//
+// Migrating
+//
+
+/// Returns the steps needed to go from version `from` to version `to`.
+pub fn plan(from: u32, to: u32) -> Result<Vec<Step>> {
+    ensure!(
+        (UNVERSIONED..=CURRENT_VERSION).contains(&to),
+        "unknown storage version {to} (versions go from {UNVERSIONED} to {CURRENT_VERSION})"
+    );
+    ensure!(
+        from <= CURRENT_VERSION,
+        "the storage is at version {from}, which is more recent than this orchestrator (version {CURRENT_VERSION})"
+    );
+
+    let steps = if from <= to {
+        MIGRATIONS
+            .iter()
+            .filter(|migration| migration.version > from && migration.version <= to)
+            .map(|migration| Step {
+                migration,
+                rollback: false,
+            })
+            .collect()
+    } else {
+        MIGRATIONS
+            .iter()
+            .rev()
+            .filter(|migration| migration.version > to && migration.version <= from)
+            .map(|migration| Step {
+                migration,
+                rollback: true,
+            })
+            .collect()
+    };
+    Ok(steps)
+}
+
+/// Migrates the storage at the given directory to version `to` (upgrading or rolling back as needed).
+/// In dry-run mode, only returns the steps that would be run.
+pub fn migrate(dir: &Path, to: u32, dry_run: bool) -> Result<Vec<Step>> {
+    let from = version(dir)?;
+    let steps = plan(from, to)?;
+    if dry_run {
+        return Ok(steps);
+    }
+
+    for step in &steps {
+        let Migration {
+            version,
+            description,
+            up,
+            down,
+        } = step.migration;
+        if step.rollback {
+            info!("- rolling back migration {version}: {description}");
+            down(dir).with_context(|| format!("couldn't roll back migration {version}"))?;
+        } else {
+            info!("- applying migration {version}: {description}");
+            up(dir).with_context(|| format!("couldn't apply migration {version}"))?;
+        }
+        set_version(dir, step.target())?;
+    }
+
+    Ok(steps)
+}
+
+/// Rolls back the last migration applied to the storage at the given directory.
+pub fn rollback(dir: &Path, dry_run: bool) -> Result<Vec<Step>> {
+    let from = version(dir)?;
+    ensure!(
+        from > UNVERSIONED,
+        "the storage is at version {from}, there is no migration to roll back"
+    );
+    migrate(dir, from - 1, dry_run)
+}
+
+//
+// Migrations
+//
+
+/// Version 2: moves `requests/<hash>.json` to `requests/<first byte of hash>/<hash>.json`,
+/// so that directories don't grow too large.
+fn shard_records(dir: &Path) -> Result<()> {
+    let requests_dir = dir.join("requests");
+    if !requests_dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(&requests_dir)? {
+        let path = entry?.path();
+        // records that were already moved are in sub-directories
+        if !path.is_file() {
+            continue;
+        }
+
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .context("malformed record file name")?;
+        let shard = file_name.get(..2).context("malformed record file name")?;
+        let shard_dir = requests_dir.join(shard);
+        fs::create_dir_all(&shard_dir)?;
+        fs::rename(&path, shard_dir.join(file_name))?;
+    }
+
+    Ok(())
+}
+
+/// Rolls back version 2.
+fn unshard_records(dir: &Path) -> Result<()> {
+    let requests_dir = dir.join("requests");
+    if !requests_dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(&requests_dir)? {
+        let shard_dir = entry?.path();
+        if !shard_dir.is_dir() {
+            continue;
+        }
+
+        for entry in fs::read_dir(&shard_dir)? {
+            let path = entry?.path();
+            let file_name = path.file_name().context("malformed record file name")?;
+            fs::rename(&path, requests_dir.join(file_name))?;
+        }
+        fs::remove_dir(&shard_dir)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let steps = plan(1, CURRENT_VERSION).unwrap();
+        assert_eq!(steps.len(), MIGRATIONS.len());
+        assert!(steps.iter().all(|step| !step.rollback));
+        assert_eq!(steps.last().unwrap().target(), CURRENT_VERSION);
+
+        let steps = plan(CURRENT_VERSION, 1).unwrap();
+        assert!(steps.iter().all(|step| step.rollback));
+        assert_eq!(steps.last().unwrap().target(), 1);
+
+        assert!(plan(CURRENT_VERSION, CURRENT_VERSION).unwrap().is_empty());
+        assert!(plan(1, CURRENT_VERSION + 1).is_err());
+        assert!(plan(CURRENT_VERSION + 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_migrate_and_rollback() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        let dir = tmp_dir.path();
+
+        // an unversioned storage
+        let record = format!("{:064x}.json", 0xab);
+        fs::create_dir_all(dir.join("requests")).unwrap();
+        fs::write(dir.join("requests").join(&record), "{}").unwrap();
+        assert_eq!(version(dir).unwrap(), 1);
+
+        // dry runs don't touch anything
+        assert_eq!(migrate(dir, 2, true).unwrap().len(), 1);
+        assert_eq!(version(dir).unwrap(), 1);
+
+        migrate(dir, 2, false).unwrap();
+        assert_eq!(version(dir).unwrap(), 2);
+        assert!(dir.join("requests").join("00").join(&record).exists());
+
+        // migrating is idempotent
+        assert!(migrate(dir, 2, false).unwrap().is_empty());
+
+        rollback(dir, false).unwrap();
+        assert_eq!(version(dir).unwrap(), 1);
+        assert!(dir.join("requests").join(&record).exists());
+        assert!(!dir.join("requests").join("00").exists());
+        assert!(rollback(dir, false).is_err());
+    }
+
+    #[test]
+    fn test_fresh_storage_is_current() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        assert_eq!(version(tmp_dir.path()).unwrap(), CURRENT_VERSION);
+    }
+}
@@ -0,0 +1,567 @@
+//! Misbehavior of committee members, and their quarantine.
+//!
+//! A member misbehaves when it sends a signature share that doesn't verify ([MisbehaviorKind::InvalidShare]),
+//! when it sends something the protocol of the committee doesn't expect, like nonces or shares of the other signing protocol
+//! ([MisbehaviorKind::OffProtocol]), or when it fails to answer in time [MAX_CONSECUTIVE_TIMEOUTS] times in a row
+//! ([MisbehaviorKind::Timeout]). The orchestrator then records evidence of it ([Evidence]),
+//! signed with the key of its audit log and appended to [LOG_FILE] (see [EvidenceLog]),
+//! and quarantines the member ([Quarantine]): the member is left out of signing sessions until an operator releases it
+//! (or for [TIMEOUT_QUARANTINE] seconds, if it only timed out).
+//!
+//! Operators can look at the evidence and at the quarantined members with the `misbehavior_evidence` and
+//! `quarantined_members` methods, and release members with `release_member` of the admin API (see [super::admin])
+//! (or with `zkbtc orchestrator misbehavior`).
+//! The quarantine is kept in memory, so restarting the orchestrator releases every member (the evidence stays).
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{ensure, Context, Result};
+use frost_secp256k1_tr::Identifier;
+use log::debug;
+use secp256k1::{schnorr, Keypair, Message, Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use super::{
+    audit::{load_or_create_key, KEY_FILE},
+    storage::now,
+};
+
+/// The file the evidence is logged in (in the storage directory of the orchestrator).
+pub const LOG_FILE: &str = "misbehavior.jsonl";
+
+/// The number of times in a row a member can fail to answer in time before being quarantined.
+pub const MAX_CONSECUTIVE_TIMEOUTS: u32 = 3;
+
+/// How long (in seconds) a member that failed to answer in time too many times in a row is quarantined.
+pub const TIMEOUT_QUARANTINE: u64 = 600;
+
+//
+// Data structures
+//
+
+/// The ways a member can misbehave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MisbehaviorKind {
+    /// The member sent a signature share that doesn't verify.
+    InvalidShare,
+
+    /// The member failed to answer in time (too many times in a row).
+    Timeout,
+
+    /// The member sent something the protocol of the committee doesn't expect.
+    OffProtocol,
+}
+
+impl fmt::Display for MisbehaviorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MisbehaviorKind::InvalidShare => write!(f, "sent an invalid signature share"),
+            MisbehaviorKind::Timeout => write!(f, "didn't answer in time"),
+            MisbehaviorKind::OffProtocol => write!(f, "went off-protocol"),
+        }
+    }
+}
+
+/// The error of a session that failed because of a member,
+/// which the orchestrator records evidence of (see [super::orchestrator::Orchestrator]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Misbehavior {
+    pub member: Identifier,
+    pub kind: MisbehaviorKind,
+    pub details: String,
+}
+
+impl Misbehavior {
+    pub fn new(member: Identifier, kind: MisbehaviorKind, details: impl Into<String>) -> Self {
+        Self {
+            member,
+            kind,
+            details: details.into(),
+        }
+    }
+
+    /// The misbehavior an error is caused by, if any.
+    pub fn of(err: &anyhow::Error) -> Option<&Self> {
+        err.downcast_ref()
+    }
+}
+
+impl fmt::Display for Misbehavior {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "member {:?} {}: {}",
+            self.member, self.kind, self.details
+        )
+    }
+}
+
+impl std::error::Error for Misbehavior {}
+
+/// Evidence of the misbehavior of a member in a signing session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Evidence {
+    /// The member that misbehaved.
+    pub member: Identifier,
+
+    /// How it misbehaved.
+    pub kind: MisbehaviorKind,
+
+    /// When (as a UNIX timestamp) the misbehavior was observed.
+    pub observed_at: u64,
+
+    /// The hex-encoded digest signed in the session.
+    pub message: String,
+
+    /// The members taking part in the session.
+    pub signers: Vec<Identifier>,
+
+    /// What the member did (e.g. the hex-encoded share it sent).
+    pub details: String,
+}
+
+impl Evidence {
+    /// Evidence of `misbehavior` in a session signing `message` with `signers`.
+    pub fn new(misbehavior: &Misbehavior, message: &[u8; 32], signers: &[Identifier]) -> Self {
+        Self {
+            member: misbehavior.member,
+            kind: misbehavior.kind,
+            observed_at: now(),
+            message: hex::encode(message),
+            signers: signers.to_vec(),
+            details: misbehavior.details.clone(),
+        }
+    }
+}
+
+/// Evidence, as recorded in the log of the orchestrator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedEvidence {
+    /// Position of the evidence in the log.
+    pub index: u64,
+
+    /// The evidence.
+    pub evidence: Evidence,
+
+    /// The hex-encoded (x-only) public key of the orchestrator (the one of its audit log).
+    pub signer: String,
+
+    /// The hex-encoded Schnorr signature of the evidence (and of its position), by the key of the orchestrator.
+    pub signature: String,
+}
+
+impl SignedEvidence {
+    fn digest(index: u64, evidence: &Evidence) -> Result<[u8; 32]> {
+        let mut hasher = Keccak256::new();
+        hasher.update(index.to_be_bytes());
+        hasher.update(serde_json::to_string(evidence)?.as_bytes());
+        Ok(hasher.finalize().into())
+    }
+
+    /// Checks the signature of the evidence, and that it was signed with `pubkey` (if given).
+    pub fn verify(&self, pubkey: Option<&XOnlyPublicKey>) -> Result<()> {
+        let signer: XOnlyPublicKey = self
+            .signer
+            .parse()
+            .context("the signer is not an x-only public key")?;
+        if let Some(pubkey) = pubkey {
+            ensure!(
+                &signer == pubkey,
+                "evidence {} was signed by {signer}, not {pubkey}",
+                self.index
+            );
+        }
+        let signature = schnorr::Signature::from_slice(
+            &hex::decode(&self.signature).context("the signature is not hex-encoded")?,
+        )?;
+        Secp256k1::verification_only()
+            .verify_schnorr(
+                &signature,
+                &Message::from_digest(Self::digest(self.index, &self.evidence)?),
+                &signer,
+            )
+            .with_context(|| format!("the signature of evidence {} doesn't verify", self.index))
+    }
+}
+
+/// A member left out of signing sessions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantinedMember {
+    /// The identifier of the member in the committee.
+    pub member: Identifier,
+
+    /// How the member misbehaved.
+    pub kind: MisbehaviorKind,
+
+    /// When (as a UNIX timestamp) the member was quarantined.
+    pub since: u64,
+
+    /// When (as a UNIX timestamp) the quarantine ends, if it isn't until an operator releases the member.
+    pub until: Option<u64>,
+}
+
+//
+// Quarantine
+//
+
+/// The members quarantined by the orchestrator, and how many times in a row each member failed to answer in time.
+#[derive(Debug, Default)]
+pub struct Quarantine {
+    members: Mutex<HashMap<Identifier, QuarantinedMember>>,
+    timeouts: Mutex<HashMap<Identifier, u32>>,
+}
+
+impl Quarantine {
+    /// Records misbehavior, and returns true if the member is quarantined for it
+    /// (which timeouts only lead to after [MAX_CONSECUTIVE_TIMEOUTS] in a row).
+    pub fn record(&self, evidence: &Evidence) -> bool {
+        let until = match evidence.kind {
+            MisbehaviorKind::Timeout => {
+                let mut timeouts = self.timeouts.lock().unwrap();
+                let count = timeouts.entry(evidence.member).or_default();
+                *count += 1;
+                if *count < MAX_CONSECUTIVE_TIMEOUTS {
+                    return false;
+                }
+                *count = 0;
+                Some(evidence.observed_at + TIMEOUT_QUARANTINE)
+            }
+            MisbehaviorKind::InvalidShare | MisbehaviorKind::OffProtocol => None,
+        };
+        let quarantined = QuarantinedMember {
+            member: evidence.member,
+            kind: evidence.kind,
+            since: evidence.observed_at,
+            until,
+        };
+        self.members
+            .lock()
+            .unwrap()
+            .insert(evidence.member, quarantined);
+        true
+    }
+
+    /// Records that a member answered in time.
+    pub fn record_answer(&self, member: Identifier) {
+        self.timeouts.lock().unwrap().remove(&member);
+    }
+
+    /// The members currently quarantined.
+    pub fn list(&self) -> Vec<QuarantinedMember> {
+        let mut members = self.members.lock().unwrap();
+        let timestamp = now();
+        members.retain(|_, quarantined| quarantined.until.map_or(true, |until| until > timestamp));
+        let mut list: Vec<_> = members.values().cloned().collect();
+        list.sort_by_key(|quarantined| quarantined.member);
+        list
+    }
+
+    /// The identifiers of the members currently quarantined.
+    pub fn members(&self) -> HashSet<Identifier> {
+        self.list()
+            .into_iter()
+            .map(|quarantined| quarantined.member)
+            .collect()
+    }
+
+    /// Releases a member, and returns false if it wasn't quarantined.
+    pub fn release(&self, member: Identifier) -> bool {
+        self.timeouts.lock().unwrap().remove(&member);
+        self.members.lock().unwrap().remove(&member).is_some()
+    }
+}
+
+//
+// Log
+//
+
+/// The log of evidence, in a directory (as [LOG_FILE], signed with the key of the audit log in [KEY_FILE]).
+pub struct EvidenceLog {
+    path: PathBuf,
+    keypair: Keypair,
+    entries: Mutex<Vec<SignedEvidence>>,
+}
+
+impl EvidenceLog {
+    /// Opens (and creates if needed) the log of evidence in `dir`, and checks it.
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+        let keypair = load_or_create_key(&dir.join(KEY_FILE))?;
+        let path = dir.join(LOG_FILE);
+
+        let entries = read(&path)?;
+        let pubkey = keypair.x_only_public_key().0;
+        for (index, entry) in entries.iter().enumerate() {
+            ensure!(
+                entry.index == index as u64,
+                "evidence {index} is missing from {}",
+                path.display()
+            );
+            entry
+                .verify(Some(&pubkey))
+                .with_context(|| format!("{} was tampered with", path.display()))?;
+        }
+        Ok(Self {
+            path,
+            keypair,
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// The public key the evidence is signed with.
+    pub fn pubkey(&self) -> XOnlyPublicKey {
+        self.keypair.x_only_public_key().0
+    }
+
+    /// The evidence recorded so far.
+    pub fn entries(&self) -> Vec<SignedEvidence> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Signs evidence, and appends it to the log.
+    pub fn append(&self, evidence: Evidence) -> Result<SignedEvidence> {
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries.len() as u64;
+        let digest = SignedEvidence::digest(index, &evidence)?;
+        let signature = Secp256k1::signing_only()
+            .sign_schnorr_no_aux_rand(&Message::from_digest(digest), &self.keypair);
+        let entry = SignedEvidence {
+            index,
+            evidence,
+            signer: hex::encode(self.pubkey().serialize()),
+            signature: hex::encode(signature.as_ref()),
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("couldn't open the log of misbehavior")?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        file.sync_data()?;
+
+        debug!(
+            "- recorded evidence against member {:?}",
+            entry.evidence.member
+        );
+        entries.push(entry.clone());
+        Ok(entry)
+    }
+}
+
+/// Reads a log of evidence (empty if it doesn't exist yet).
+pub fn read(path: &Path) -> Result<Vec<SignedEvidence>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let file = fs::File::open(path).with_context(|| format!("couldn't open {}", path.display()))?;
+    let mut entries = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line).context("malformed evidence")?);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::{
+        bob_request::BobRequest,
+        client::CommitteeMember,
+        committee::{
+            attestation::{self, AttestationRequest},
+            dealer,
+            governance::Proposal,
+            node::{NodeInfo, NodeState, Round1Response, Round2Request, Round2Response},
+            orchestrator::Orchestrator,
+            signer::{KeyShare, SecretNonces, SignerBackend, SignerKind},
+            sweep::SweepRequest,
+        },
+    };
+
+    fn id(id: u16) -> Identifier {
+        Identifier::try_from(id).unwrap()
+    }
+
+    fn evidence(member: u16, kind: MisbehaviorKind) -> Evidence {
+        let misbehavior = Misbehavior::new(id(member), kind, "share 00");
+        Evidence::new(&misbehavior, &[1; 32], &[id(1), id(2)])
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let quarantine = Quarantine::default();
+
+        // invalid shares are quarantined right away, until released
+        assert!(quarantine.record(&evidence(1, MisbehaviorKind::InvalidShare)));
+        assert_eq!(quarantine.members(), HashSet::from([id(1)]));
+        assert_eq!(quarantine.list()[0].until, None);
+
+        // timeouts only once too many of them are in a row
+        for _ in 1..MAX_CONSECUTIVE_TIMEOUTS {
+            assert!(!quarantine.record(&evidence(2, MisbehaviorKind::Timeout)));
+        }
+        quarantine.record_answer(id(2));
+        for _ in 1..MAX_CONSECUTIVE_TIMEOUTS {
+            assert!(!quarantine.record(&evidence(2, MisbehaviorKind::Timeout)));
+        }
+        assert!(quarantine.record(&evidence(2, MisbehaviorKind::Timeout)));
+        assert_eq!(quarantine.members(), HashSet::from([id(1), id(2)]));
+        assert!(quarantine.list()[1].until.is_some());
+
+        assert!(quarantine.release(id(1)));
+        assert!(!quarantine.release(id(1)));
+        assert_eq!(quarantine.members(), HashSet::from([id(2)]));
+    }
+
+    #[test]
+    fn test_evidence_log() {
+        let dir = tempdir::TempDir::new("zkbitcoin_misbehavior").unwrap();
+        let log = EvidenceLog::open(dir.path()).unwrap();
+        log.append(evidence(1, MisbehaviorKind::InvalidShare))
+            .unwrap();
+        let entry = log
+            .append(evidence(2, MisbehaviorKind::OffProtocol))
+            .unwrap();
+        assert_eq!(entry.index, 1);
+        entry.verify(Some(&log.pubkey())).unwrap();
+
+        // the evidence is checked when the log is reopened
+        let pubkey = log.pubkey();
+        drop(log);
+        let log = EvidenceLog::open(dir.path()).unwrap();
+        assert_eq!(log.pubkey(), pubkey);
+        assert_eq!(log.entries().len(), 2);
+
+        // and can't be altered
+        let mut tampered = log.entries()[0].clone();
+        tampered.evidence.member = id(3);
+        assert!(tampered.verify(None).is_err());
+        let contents = fs::read_to_string(dir.path().join(LOG_FILE)).unwrap();
+        fs::write(
+            dir.path().join(LOG_FILE),
+            contents.replace("share 00", "share 01"),
+        )
+        .unwrap();
+        assert!(EvidenceLog::open(dir.path()).is_err());
+    }
+
+    /// A member that signs attestations of the wrong data.
+    struct FaultyMember {
+        key_share: KeyShare,
+        nonces: Mutex<Option<SecretNonces>>,
+    }
+
+    #[async_trait]
+    impl CommitteeMember for FaultyMember {
+        async fn round_1_signing(&self, _: &BobRequest) -> Result<Round1Response> {
+            unimplemented!()
+        }
+
+        async fn round_2_signing(&self, _: &Round2Request) -> Result<Round2Response> {
+            unimplemented!()
+        }
+
+        async fn sweep_round_1_signing(&self, _: &SweepRequest) -> Result<Round1Response> {
+            unimplemented!()
+        }
+
+        async fn sweep_round_2_signing(&self, _: &Round2Request) -> Result<Round2Response> {
+            unimplemented!()
+        }
+
+        async fn attest_round_1_signing(&self, _: &AttestationRequest) -> Result<Round1Response> {
+            let (nonces, commitments) = self.key_share.commit()?;
+            *self.nonces.lock().unwrap() = Some(nonces);
+            Ok(Round1Response { commitments })
+        }
+
+        async fn attest_round_2_signing(
+            &self,
+            round2_request: &Round2Request,
+        ) -> Result<Round2Response> {
+            let nonces = self.nonces.lock().unwrap().take().unwrap();
+            let signature_share =
+                self.key_share
+                    .sign(nonces, &round2_request.commitments_map, &[0; 32])?;
+            Ok(Round2Response { signature_share })
+        }
+
+        async fn vote_round_1_signing(&self, _: &Proposal) -> Result<Round1Response> {
+            unimplemented!()
+        }
+
+        async fn info(&self) -> Result<NodeInfo> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_invalid_share() {
+        let committee = dealer::generate(3, 2, 0, SignerKind::Frost).unwrap();
+        let faulty = *committee.key_packages.keys().next().unwrap();
+        let dir = tempdir::TempDir::new("zkbitcoin_misbehavior").unwrap();
+        let approvals_path = dir.path().join("approvals");
+        let approvals = [b"price: 64000 USD".as_slice(), b"price: 65000 USD"]
+            .map(|data| hex::encode(attestation::digest(data)));
+        fs::write(&approvals_path, approvals.join("\n")).unwrap();
+        let members: HashMap<_, Box<dyn CommitteeMember>> = committee
+            .key_packages
+            .iter()
+            .map(|(id, key_share)| {
+                let member: Box<dyn CommitteeMember> = if *id == faulty {
+                    Box::new(FaultyMember {
+                        key_share: key_share.clone(),
+                        nonces: Mutex::new(None),
+                    })
+                } else {
+                    let mut node =
+                        NodeState::new(key_share.clone(), committee.pubkey_package.clone());
+                    node.attestation_approvals = Some(approvals_path.clone());
+                    Box::new(node)
+                };
+                (*id, member)
+            })
+            .collect();
+        let mut orchestrator = Orchestrator::with_members(
+            committee.pubkey_package.clone(),
+            committee.config.clone(),
+            members,
+        );
+        orchestrator.evidence_log = Some(EvidenceLog::open(dir.path()).unwrap());
+
+        // every member takes part in one of three sessions (in turn),
+        // and the ones the faulty member takes part in are restarted without it
+        for _ in 0..3 {
+            orchestrator.attest(b"price: 64000 USD").await.unwrap();
+        }
+        assert_eq!(orchestrator.quarantine.members(), HashSet::from([faulty]));
+        let evidence = orchestrator.evidence_log.as_ref().unwrap().entries();
+        assert_eq!(evidence.len(), 1);
+        assert_eq!(evidence[0].evidence.member, faulty);
+        assert_eq!(evidence[0].evidence.kind, MisbehaviorKind::InvalidShare);
+
+        // once quarantined, the member is left out of sessions
+        orchestrator.attest(b"price: 65000 USD").await.unwrap();
+        assert_eq!(
+            orchestrator.evidence_log.as_ref().unwrap().entries().len(),
+            1
+        );
+        assert!(orchestrator.quarantine.release(faulty));
+    }
+}
@@ -1,2 +1,35 @@
+pub mod admin;
+pub mod aggregator;
+pub mod alerts;
+pub mod anomaly;
+pub mod attestation;
+pub mod audit;
+pub mod batching;
+pub mod dealer;
+pub mod events;
+pub mod failover;
+pub mod fees;
+pub mod governance;
+pub mod grpc;
+pub mod heartbeat;
+pub mod hooks;
+pub mod light_client;
+pub mod lightning;
+pub mod locks;
+pub mod migrations;
+pub mod misbehavior;
 pub mod node;
 pub mod orchestrator;
+pub mod policy;
+pub mod queue;
+pub mod reorg;
+pub mod replay;
+pub mod selection;
+pub mod shared;
+pub mod shutdown;
+pub mod signer;
+pub mod storage;
+pub mod sweep;
+pub mod transparency;
+
+pub use dealer::generate;
@@ -1,25 +1,45 @@
 use std::{
     collections::{BTreeMap, HashMap},
     net::SocketAddr,
+    path::PathBuf,
     sync::{Arc, RwLock},
+    time::Duration,
 };
 
-use bitcoin::{Transaction, TxOut, Txid};
-use frost_secp256k1_tr::round1;
+use anyhow::{ensure, Context, Result};
+use async_trait::async_trait;
+use bitcoin::{TapSighashType, Transaction, TxOut, Txid};
+use itertools::Itertools;
 use jsonrpsee::{
     server::{RpcModule, Server},
     types::Params,
 };
 use jsonrpsee_core::RpcResult;
 use jsonrpsee_types::ErrorObjectOwned;
-use log::info;
-use rand::thread_rng;
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     bob_request::{BobRequest, SmartContract},
-    frost,
+    client::CommitteeMember,
+    config::FeeSchedule,
     mpc_sign_tx::get_digest_to_hash,
+    service,
+};
+
+use super::{
+    attestation::{read_approvals, AttestationRequest},
+    audit::{AuditLog, SessionRecord},
+    fees::FeesOwed,
+    governance::{read_votes, Proposal},
+    grpc,
+    light_client::LightClient,
+    orchestrator::DEFAULT_MIN_CONFIRMATIONS,
+    replay::SeenNonces,
+    shutdown::{self, Shutdown},
+    signer::{Commitment, CommitteeKey, KeyShare, SecretNonces, SignatureShare, SignerBackend},
+    storage::redact,
+    sweep::{zkapp_script, EmergencySweep, SweepApproval, SweepRequest},
 };
 
 //
@@ -28,38 +48,494 @@ use crate::{
 
 /// State of a node.
 pub struct NodeState {
-    /// The secret key stuff they need.
-    pub key_package: frost::KeyPackage,
+    /// The secret key stuff they need (a FROST key package, or a MuSig2 key, see [super::signer]).
+    pub key_package: KeyShare,
 
     /// The public key stuff they need.
-    pub pubkey_package: frost::PublicKeyPackage,
+    pub pubkey_package: CommitteeKey,
 
+    /// The signing sessions committed to in round 1, by zkapp spent (see [BobRequest::txid]) and zkapp input
+    /// (so that the zkapp inputs of a batch, which share a transaction, can be signed concurrently).
     // TODO: ensure that this cannot grow like crazy? prune old tasks?
-    pub signing_tasks: RwLock<HashMap<Txid, LocalSigningTask>>,
+    pub signing_tasks: RwLock<HashMap<(Txid, usize), LocalSigningTask>>,
+
+    /// A light client to check that zkapps are on chain (if any).
+    pub light_client: Option<Arc<LightClient>>,
+
+    /// The minimum number of confirmations of a zkapp before signing a spend of it (see [super::orchestrator::CommitteeConfig]).
+    pub min_confirmations: u32,
+
+    /// The fee the committee takes on every use of a zkapp (see [super::orchestrator::CommitteeConfig]).
+    pub fee_schedule: FeeSchedule,
+
+    /// Whether the fee can be paid over Lightning to the orchestrator, which the node trusts to check it
+    /// (see [super::orchestrator::CommitteeConfig]).
+    pub lightning_fees: bool,
+
+    /// Whether requests must be bound to the zkapp UTXO they spend (see [super::orchestrator::CommitteeConfig]).
+    pub replay_protection: bool,
+
+    /// The nonces of the requests committed to, to reject replayed requests (see [super::replay]).
+    pub seen_nonces: SeenNonces,
+
+    /// The fees owed by the zkapp inputs of the transactions committed to,
+    /// so that the requests batched in a transaction are paid for altogether (see [super::fees]).
+    pub fees_owed: FeesOwed,
+
+    /// Whether the node is shutting down (and not committing to new signing sessions).
+    pub shutdown: Shutdown,
+
+    /// The log of the signature shares produced (if any).
+    pub audit_log: Option<AuditLog>,
+
+    /// The emergency sweeps approved by the operator, if the node is in break-glass mode
+    /// (in which it signs them, and no spend of zkapps, see [super::sweep]).
+    pub emergency_sweep: Option<SweepApproval>,
+
+    /// The sessions signing an input of an emergency sweep committed to in round 1, by transaction and input.
+    pub sweep_tasks: RwLock<HashMap<(Txid, usize), SweepSigningTask>>,
+
+    /// The nonces committed to in round 1 of attestations (see [super::attestation]) and votes (see [super::governance]),
+    /// by digest signed.
+    pub attestation_tasks: RwLock<HashMap<[u8; 32], SecretNonces>>,
+
+    /// The file listing the proposals the operator votes for (see [super::governance::read_votes]), if any.
+    pub governance_votes: Option<PathBuf>,
+
+    /// The file listing the data the operator approves attesting to (see [super::attestation::read_approvals]), if any.
+    pub attestation_approvals: Option<PathBuf>,
 }
 
-#[derive(Clone)]
 pub struct LocalSigningTask {
     /// So we know if we're processing the same request twice.
     pub proof_hash: [u8; 32],
+    /// The hash of the whole request (for the audit log).
+    pub request_hash: [u8; 32],
     /// The smart contract that locked the value.
     pub smart_contract: SmartContract,
     /// transaction to sign.
     pub tx: Transaction,
     /// The previous outputs that are being spent by the transaction (needed to sign).
     pub prev_outs: Vec<TxOut>,
+    /// The sighash type to sign with (see [BobRequest::sighash_type]).
+    pub sighash_type: TapSighashType,
     /// The nonces behind these commitments
-    pub nonces: round1::SigningNonces,
+    pub nonces: SecretNonces,
     // TODO: should we keep track of commitments here also to double check?
 }
 
+pub struct SweepSigningTask {
+    /// The hash of the sweep, as approved by the operator.
+    pub sweep_hash: [u8; 32],
+    /// The sweep to sign.
+    pub sweep: EmergencySweep,
+    /// The nonces behind these commitments.
+    pub nonces: SecretNonces,
+}
+
 //
 // Methods
 //
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Round1Response {
-    pub commitments: frost_secp256k1_tr::round1::SigningCommitments,
+    pub commitments: Commitment,
+}
+
+impl NodeState {
+    /// The state of a node that doesn't check zkapps on chain, and doesn't keep an audit log.
+    pub fn new(key_package: impl Into<KeyShare>, pubkey_package: impl Into<CommitteeKey>) -> Self {
+        Self {
+            key_package: key_package.into(),
+            pubkey_package: pubkey_package.into(),
+            signing_tasks: RwLock::new(HashMap::new()),
+            light_client: None,
+            min_confirmations: DEFAULT_MIN_CONFIRMATIONS,
+            fee_schedule: FeeSchedule::default(),
+            lightning_fees: false,
+            replay_protection: false,
+            seen_nonces: SeenNonces::default(),
+            fees_owed: FeesOwed::default(),
+            shutdown: Shutdown::default(),
+            audit_log: None,
+            emergency_sweep: None,
+            sweep_tasks: RwLock::new(HashMap::new()),
+            attestation_tasks: RwLock::new(HashMap::new()),
+            governance_votes: None,
+            attestation_approvals: None,
+        }
+    }
+
+    /// What the node reports about itself.
+    pub fn info(&self) -> NodeInfo {
+        NodeInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            identifier: self.key_package.identifier(),
+            light_client: self.light_client.is_some(),
+            min_confirmations: self.min_confirmations,
+            emergency_sweep: self.emergency_sweep.is_some(),
+        }
+    }
+
+    /// Validates Bob's request, and commits to nonces for it (round 1).
+    pub async fn round_1(&self, bob_request: &BobRequest) -> Result<Round1Response> {
+        ensure!(
+            !self.shutdown.is_draining(),
+            "the node is shutting down, try again later"
+        );
+        ensure!(
+            self.emergency_sweep.is_none(),
+            "the node is in break-glass mode, and only signs emergency sweeps"
+        );
+
+        // check if we already have a local signing task under that txid
+        let txid = bob_request
+            .txid()
+            .context("couldn't get txid for zkapp in request")?;
+
+        // check that the request is bound to the zkapp UTXO it spends
+        bob_request
+            .check_binding(self.replay_protection)
+            .context("the request isn't bound to the zkapp it spends")?;
+
+        // validate request
+        let smart_contract = bob_request
+            .validate_request()
+            .await
+            .context("the request didn't validate")?;
+
+        // check that the committee is paid (fees paid over Lightning are checked by the orchestrator)
+        if bob_request.fee_payment_hash.is_some() {
+            ensure!(
+                self.lightning_fees,
+                "the committee doesn't take fees over Lightning"
+            );
+        } else {
+            self.fees_owed.check(&self.fee_schedule, bob_request)?;
+        }
+
+        // check that the zkapp is confirmed and unspent
+        if let Some(light_client) = &self.light_client {
+            light_client
+                .check_zkapp(&smart_contract, self.min_confirmations)
+                .await
+                .context("the zkapp couldn't be found on chain")?;
+        }
+
+        // check that the request isn't a replay of another one
+        if let Some(nonce) = &bob_request.nonce {
+            self.seen_nonces.use_nonce(nonce, bob_request.hash())?;
+        }
+
+        // round 1
+        let (nonces, commitments) = self.key_package.commit()?;
+
+        // store it locally
+        {
+            let mut signing_tasks = self.signing_tasks.write().unwrap();
+            signing_tasks.insert(
+                (txid, bob_request.zkapp_input),
+                LocalSigningTask {
+                    proof_hash: bob_request.proof.hash(),
+                    request_hash: bob_request.hash(),
+                    smart_contract,
+                    tx: bob_request.tx.clone(),
+                    nonces,
+                    prev_outs: bob_request.prev_outs.clone(),
+                    sighash_type: bob_request.sighash_type(),
+                },
+            );
+        }
+
+        // response
+        Ok(Round1Response { commitments })
+    }
+
+    /// Produces a signature share for a request committed to in round 1 (round 2).
+    pub fn round_2(&self, round2request: &Round2Request) -> Result<Round2Response> {
+        // retrieve metadata for this task (and prune it)
+        let LocalSigningTask {
+            proof_hash,
+            request_hash,
+            smart_contract,
+            tx,
+            nonces,
+            prev_outs,
+            sighash_type,
+        } = self
+            .signing_tasks
+            .write()
+            .unwrap()
+            .remove(&(round2request.txid, round2request.zkapp_input))
+            .context("no signing task found for this txid and zkapp input")?;
+        ensure!(
+            proof_hash == round2request.proof_hash,
+            "proof hash doesn't match"
+        );
+
+        // deterministically create transaction
+        let message = get_digest_to_hash(&prev_outs, &tx, &smart_contract, sighash_type)
+            .context("error while hashing")?;
+
+        // sanity check
+        ensure!(round2request.message == message, "message doesn't match");
+
+        // the signing package is recreated, as we want to ensure that we agree on what is being signed (should be a deterministic process).
+        let signature_share =
+            self.key_package
+                .sign(nonces, &round2request.commitments_map, &message)?;
+
+        // keep track of what we signed, before letting it out
+        if let Some(audit_log) = &self.audit_log {
+            let session = SessionRecord::new(
+                request_hash,
+                &tx,
+                round2request.zkapp_input,
+                &smart_contract,
+                round2request.commitments_map.keys().copied().collect(),
+                &signature_share.serialize(),
+            );
+            audit_log
+                .append(session)
+                .context("couldn't write the audit log")?;
+        }
+
+        // return signature shares
+        Ok(Round2Response { signature_share })
+    }
+
+    /// Checks that the operator approved the sweep, and commits to nonces to sign one of its inputs
+    /// (round 1, see [super::sweep]).
+    pub fn sweep_round_1(&self, sweep_request: &SweepRequest) -> Result<Round1Response> {
+        ensure!(
+            !self.shutdown.is_draining(),
+            "the node is shutting down, try again later"
+        );
+        let approval = self
+            .emergency_sweep
+            .as_ref()
+            .context("the node is not in break-glass mode, and signs no emergency sweep")?;
+        let sweep = &sweep_request.sweep;
+        let sweep_hash = approval.check(&zkapp_script(&self.pubkey_package)?, sweep)?;
+        ensure!(
+            sweep_request.input < sweep.tx.input.len(),
+            "the sweep has no input {}",
+            sweep_request.input
+        );
+
+        let (nonces, commitments) = self.key_package.commit()?;
+
+        self.sweep_tasks.write().unwrap().insert(
+            (sweep.tx.txid(), sweep_request.input),
+            SweepSigningTask {
+                sweep_hash,
+                sweep: sweep.clone(),
+                nonces,
+            },
+        );
+
+        Ok(Round1Response { commitments })
+    }
+
+    /// Produces a signature share for an input of a sweep committed to in round 1 (round 2).
+    /// The hash of the sweep is given in place of the hash of a proof.
+    pub fn sweep_round_2(&self, round2request: &Round2Request) -> Result<Round2Response> {
+        let SweepSigningTask {
+            sweep_hash,
+            sweep,
+            nonces,
+        } = self
+            .sweep_tasks
+            .write()
+            .unwrap()
+            .remove(&(round2request.txid, round2request.zkapp_input))
+            .context("no sweep signing task found for this txid and input")?;
+        ensure!(
+            sweep_hash == round2request.proof_hash,
+            "sweep hash doesn't match"
+        );
+
+        let message = sweep.sighash(round2request.zkapp_input)?;
+        ensure!(round2request.message == message, "message doesn't match");
+
+        let signature_share =
+            self.key_package
+                .sign(nonces, &round2request.commitments_map, &message)?;
+
+        // keep track of what we signed, before letting it out
+        if let Some(audit_log) = &self.audit_log {
+            let session = SessionRecord::sweep(
+                sweep_hash,
+                &sweep,
+                round2request.zkapp_input,
+                round2request.commitments_map.keys().copied().collect(),
+                &signature_share.serialize(),
+            );
+            audit_log
+                .append(session)
+                .context("couldn't write the audit log")?;
+        }
+
+        Ok(Round2Response { signature_share })
+    }
+
+    /// Checks that the operator approved attesting to some data, and commits to nonces to sign it
+    /// (round 1, see [super::attestation]).
+    pub fn attest_round_1(
+        &self,
+        attestation_request: &AttestationRequest,
+    ) -> Result<Round1Response> {
+        ensure!(
+            !self.shutdown.is_draining(),
+            "the node is shutting down, try again later"
+        );
+        ensure!(
+            self.emergency_sweep.is_none(),
+            "the node is in break-glass mode, and only signs emergency sweeps"
+        );
+        let approvals = self
+            .attestation_approvals
+            .as_deref()
+            .context("the operator of the node doesn't approve attestations")?;
+        let digest = attestation_request.digest()?;
+        ensure!(
+            read_approvals(approvals)?.contains(&digest),
+            "the operator of the node didn't approve attesting to {}",
+            hex::encode(digest)
+        );
+
+        let (nonces, commitments) = self.key_package.commit()?;
+
+        // (a session restarted by the orchestrator replaces the nonces of the previous one)
+        self.attestation_tasks
+            .write()
+            .unwrap()
+            .insert(digest, nonces);
+
+        Ok(Round1Response { commitments })
+    }
+
+    /// Checks that the operator voted for a proposal, and commits to nonces to sign it (round 1, see [super::governance]).
+    /// Round 2 is the one of attestations (see [Self::attest_round_2]).
+    pub fn vote_round_1(&self, proposal: &Proposal) -> Result<Round1Response> {
+        ensure!(
+            !self.shutdown.is_draining(),
+            "the node is shutting down, try again later"
+        );
+        ensure!(
+            self.emergency_sweep.is_none(),
+            "the node is in break-glass mode, and only signs emergency sweeps"
+        );
+        let votes = self
+            .governance_votes
+            .as_deref()
+            .context("the operator of the node doesn't vote on proposals")?;
+        let digest = proposal.hash()?;
+        ensure!(
+            read_votes(votes)?.contains(&digest),
+            "the operator of the node didn't vote for proposal #{} ({})",
+            proposal.sequence,
+            hex::encode(digest)
+        );
+
+        let (nonces, commitments) = self.key_package.commit()?;
+
+        // (a session restarted by the orchestrator replaces the nonces of the previous one)
+        self.attestation_tasks
+            .write()
+            .unwrap()
+            .insert(digest, nonces);
+
+        Ok(Round1Response { commitments })
+    }
+
+    /// Produces a signature share of data attested to (or of a proposal voted for) in round 1 (round 2).
+    /// The digest signed is given as the message (and in place of the hash of a proof), and the txid is unused.
+    pub fn attest_round_2(&self, round2request: &Round2Request) -> Result<Round2Response> {
+        let digest = round2request.message;
+        let nonces = self
+            .attestation_tasks
+            .write()
+            .unwrap()
+            .remove(&digest)
+            .context("no attestation task found for this digest")?;
+        ensure!(
+            round2request.proof_hash == digest,
+            "the digest doesn't match"
+        );
+
+        let signature_share =
+            self.key_package
+                .sign(nonces, &round2request.commitments_map, &digest)?;
+
+        // keep track of what we signed, before letting it out
+        if let Some(audit_log) = &self.audit_log {
+            let session = SessionRecord::attestation(
+                digest,
+                round2request.commitments_map.keys().copied().collect(),
+                &signature_share.serialize(),
+            );
+            audit_log
+                .append(session)
+                .context("couldn't write the audit log")?;
+        }
+
+        Ok(Round2Response { signature_share })
+    }
+}
+
+/// A node running in-process (e.g. in tests, see `crate::testing`).
+#[async_trait]
+impl CommitteeMember for NodeState {
+    async fn round_1_signing(&self, bob_request: &BobRequest) -> Result<Round1Response> {
+        self.round_1(bob_request).await
+    }
+
+    async fn round_2_signing(&self, round2_request: &Round2Request) -> Result<Round2Response> {
+        self.round_2(round2_request)
+    }
+
+    async fn sweep_round_1_signing(&self, sweep_request: &SweepRequest) -> Result<Round1Response> {
+        self.sweep_round_1(sweep_request)
+    }
+
+    async fn sweep_round_2_signing(
+        &self,
+        round2_request: &Round2Request,
+    ) -> Result<Round2Response> {
+        self.sweep_round_2(round2_request)
+    }
+
+    async fn attest_round_1_signing(
+        &self,
+        attestation_request: &AttestationRequest,
+    ) -> Result<Round1Response> {
+        self.attest_round_1(attestation_request)
+    }
+
+    async fn attest_round_2_signing(
+        &self,
+        round2_request: &Round2Request,
+    ) -> Result<Round2Response> {
+        self.attest_round_2(round2_request)
+    }
+
+    async fn vote_round_1_signing(&self, proposal: &Proposal) -> Result<Round1Response> {
+        self.vote_round_1(proposal)
+    }
+
+    async fn info(&self) -> Result<NodeInfo> {
+        Ok(NodeState::info(self))
+    }
+}
+
+/// Converts an error to a JSON RPC error, with its outermost context as message.
+fn rpc_error(err: anyhow::Error) -> ErrorObjectOwned {
+    ErrorObjectOwned::owned(
+        jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+        err.to_string(),
+        Some(format!("{err:#}")),
+    )
 }
 
 /// Bob's request to unlock funds from a smart contract.
@@ -70,62 +546,29 @@ async fn round_1_signing(
     // get bob request
     let bob_request: [BobRequest; 1] = params.parse()?;
     let bob_request = &bob_request[0];
-    info!("received request: {:?}", bob_request);
-
-    // check if we already have a local signing task under that txid
-    let txid = bob_request.txid().map_err(|e| {
-        ErrorObjectOwned::owned(
-            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
-            "couldn't get txid for zkapp in request",
-            Some(format!("{e}")),
-        )
-    })?;
+    info!("received request for {}", bob_request.tx.txid());
+    debug!("- request: {}", redact(&format!("{bob_request:?}")));
 
-    // validate request
-    let smart_contract = bob_request.validate_request().await.map_err(|err| {
-        ErrorObjectOwned::owned(
-            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
-            "the request didn't validate",
-            Some(format!("{err}")),
-        )
-    })?;
-
-    // round 1 of FROST
-    let rng = &mut thread_rng();
-    let (nonces, commitments) =
-        frost_secp256k1_tr::round1::commit(context.key_package.signing_share(), rng);
-
-    // store it locally
-    {
-        let mut signing_tasks = context.signing_tasks.write().unwrap();
-        signing_tasks.insert(
-            txid,
-            LocalSigningTask {
-                proof_hash: bob_request.proof.hash(),
-                smart_contract,
-                tx: bob_request.tx.clone(),
-                nonces,
-                prev_outs: bob_request.prev_outs.clone(),
-            },
-        );
-    }
-
-    // response
-    let resp = Round1Response { commitments };
-    RpcResult::Ok(resp)
+    context.round_1(bob_request).await.map_err(rpc_error)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Round2Request {
-    /// The txid that we're referring to.
+    /// The transaction that deployed (or last updated) the zkapp being spent (see [BobRequest::txid]),
+    /// not the transaction being signed. For emergency sweeps, the sweep transaction.
     pub txid: Txid,
 
+    /// The input of the transaction being signed that spends the zkapp.
+    #[serde(default)]
+    pub zkapp_input: usize,
+
     /// Hash of the proof. Useful to make sure that we're signing the request/proof.
+    /// For emergency sweeps, the hash of the sweep (see [super::sweep::EmergencySweep::hash]),
+    /// and for attestations, the digest of the data (see [super::attestation::digest]).
     pub proof_hash: [u8; 32],
 
-    /// The FROST data needed by the MPC participants in the second round.
-    pub commitments_map:
-        BTreeMap<frost_secp256k1_tr::Identifier, frost_secp256k1_tr::round1::SigningCommitments>,
+    /// The commitments of the MPC participants, needed in the second round.
+    pub commitments_map: BTreeMap<frost_secp256k1_tr::Identifier, Commitment>,
 
     /// Digest to hash.
     /// While not necessary as nodes will recompute it themselves, it is good to double check that everyone is on the same page.
@@ -134,7 +577,27 @@ pub struct Round2Request {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Round2Response {
-    pub signature_share: frost_secp256k1_tr::round2::SignatureShare,
+    pub signature_share: SignatureShare,
+}
+
+/// What a committee node reports about itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeInfo {
+    /// The version of zkBitcoin the node runs.
+    pub version: String,
+
+    /// The identifier of the node in the committee.
+    pub identifier: frost_secp256k1_tr::Identifier,
+
+    /// Whether the node checks zkapps on chain with a light client.
+    pub light_client: bool,
+
+    /// The minimum number of confirmations of a zkapp before the node signs a spend of it.
+    pub min_confirmations: u32,
+
+    /// Whether the node is in break-glass mode, signing emergency sweeps (see [super::sweep]).
+    #[serde(default)]
+    pub emergency_sweep: bool,
 }
 
 async fn round_2_signing(
@@ -144,71 +607,82 @@ async fn round_2_signing(
     // get commitments from params
     let round2request: [Round2Request; 1] = params.parse()?;
     let round2request = &round2request[0];
-    info!("received request: {:?}", round2request);
-
-    // retrieve metadata for this task (and prune it)
-    let LocalSigningTask {
-        proof_hash: _,
-        smart_contract,
-        tx,
-        nonces,
-        prev_outs,
-    } = {
-        let mut signing_tasks = context.signing_tasks.write().unwrap();
-        if let Some(local_signing_task) = signing_tasks.remove(&round2request.txid) {
-            if local_signing_task.proof_hash != round2request.proof_hash {
-                return RpcResult::Err(ErrorObjectOwned::owned(
-                    jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
-                    "proof hash doesn't match",
-                    Some("proof hash doesn't match".to_string()),
-                ));
-            }
+    info!("received round 2 request for {}", round2request.txid);
+    debug!("- request: {}", redact(&format!("{round2request:?}")));
 
-            local_signing_task.clone()
-        } else {
-            return RpcResult::Err(ErrorObjectOwned::owned(
-                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
-                "no signing task found for this txid",
-                Some("no signing task found for this txid".to_string()),
-            ));
-        }
-    };
-
-    // deterministically create transaction
-    let message = get_digest_to_hash(&prev_outs, &tx, &smart_contract).map_err(|err| {
-        ErrorObjectOwned::owned(
-            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
-            "error while hashing",
-            Some(format!("the request didn't validate: {err}")),
-        )
-    })?;
+    context.round_2(round2request).map_err(rpc_error)
+}
 
-    // sanity check
-    if round2request.message != message {
-        return RpcResult::Err(ErrorObjectOwned::owned(
-            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
-            "message doesn't match",
-            Some("message doesn't match".to_string()),
-        ));
-    }
-
-    // signing package should be recreated no? as we want to ensure that we agree on what is being signed (should be a deterministic process).
-    let signing_package =
-        frost_secp256k1_tr::SigningPackage::new(round2request.commitments_map.clone(), &message);
-    let signature_share =
-        frost_secp256k1_tr::round2::sign(&signing_package, &nonces, &context.key_package).map_err(
-            |err| {
-                ErrorObjectOwned::owned(
-                    jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
-                    "error while signing",
-                    Some(format!("the request didn't validate: {err}")),
-                )
-            },
-        )?;
+/// A request to sign an input of an emergency sweep.
+async fn sweep_round_1_signing(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<Round1Response> {
+    let sweep_request: [SweepRequest; 1] = params.parse()?;
+    let sweep_request = &sweep_request[0];
+    info!(
+        "received a request to sign input {} of the sweep {}",
+        sweep_request.input,
+        sweep_request.sweep.tx.txid()
+    );
+
+    context.sweep_round_1(sweep_request).map_err(rpc_error)
+}
+
+async fn sweep_round_2_signing(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<Round2Response> {
+    let round2request: [Round2Request; 1] = params.parse()?;
+    let round2request = &round2request[0];
+    info!("received round 2 request for {}", round2request.txid);
+    debug!("- request: {}", redact(&format!("{round2request:?}")));
 
-    // return signature shares
-    let round2_response = Round2Response { signature_share };
-    RpcResult::Ok(round2_response)
+    context.sweep_round_2(round2request).map_err(rpc_error)
+}
+
+/// A request to attest to some data (see [super::attestation]).
+async fn attest_round_1_signing(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<Round1Response> {
+    let attestation_request: [AttestationRequest; 1] = params.parse()?;
+    let attestation_request = &attestation_request[0];
+    info!(
+        "received a request to attest to {}",
+        attestation_request.data
+    );
+
+    context
+        .attest_round_1(attestation_request)
+        .map_err(rpc_error)
+}
+
+/// A request to vote for a proposal (see [super::governance]).
+async fn vote_round_1_signing(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<Round1Response> {
+    let proposal: [Proposal; 1] = params.parse()?;
+    let proposal = &proposal[0];
+    info!(
+        "received a request to vote for proposal #{}",
+        proposal.sequence
+    );
+
+    context.vote_round_1(proposal).map_err(rpc_error)
+}
+
+async fn attest_round_2_signing(
+    params: Params<'static>,
+    context: Arc<NodeState>,
+) -> RpcResult<Round2Response> {
+    let round2request: [Round2Request; 1] = params.parse()?;
+    let round2request = &round2request[0];
+    info!("received round 2 request for {}", round2request.txid);
+    debug!("- request: {}", redact(&format!("{round2request:?}")));
+
+    context.attest_round_2(round2request).map_err(rpc_error)
 }
 
 //
@@ -217,32 +691,111 @@ async fn round_2_signing(
 
 pub async fn run_server(
     address: Option<&str>,
-    key_package: frost::KeyPackage,
-    pubkey_package: frost::PublicKeyPackage,
+    grpc_address: Option<&str>,
+    state: NodeState,
+    shutdown_timeout: Duration,
 ) -> anyhow::Result<SocketAddr> {
     let address = address.unwrap_or("127.0.0.1:6666");
     info!(
         "- starting node for identifier {id:?} at address http://{address}",
-        id = key_package.identifier()
+        id = state.key_package.identifier()
+    );
+
+    let min_confirmations = state.min_confirmations;
+    anyhow::ensure!(
+        min_confirmations == 0 || state.light_client.is_some(),
+        "the committee requires {min_confirmations} confirmations, but the node can't see the chain (use --peer and --checkpoint)"
     );
 
-    let ctx = NodeState {
-        key_package,
-        pubkey_package,
-        signing_tasks: RwLock::new(HashMap::new()),
-    };
+    if let Some(light_client) = &state.light_client {
+        info!("- syncing the light client");
+        light_client.sync().await?;
+        light_client.spawn_sync();
+    }
+
+    if let Some(audit_log) = &state.audit_log {
+        info!("- audit log signed by {}", audit_log.pubkey());
+    }
+
+    if let Some(approval) = &state.emergency_sweep {
+        warn!(
+            "- break-glass mode: only signing the emergency sweeps {} to {}",
+            approval.approved.iter().map(hex::encode).join(", "),
+            approval.descriptor
+        );
+    }
+
+    let ctx = Arc::new(state);
+
+    // the gRPC API shares the node's state with the JSON RPC one
+    if let Some(grpc_address) = grpc_address {
+        let grpc_address = grpc_address.parse::<SocketAddr>()?;
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            if let Err(err) = grpc::serve_node(grpc_address, ctx).await {
+                error!("the gRPC server stopped: {err}");
+            }
+        });
+    }
 
     let server = Server::builder()
         .build(address.parse::<SocketAddr>()?)
         .await?;
-    let mut module = RpcModule::new(ctx);
-    module.register_async_method("round_1_signing", round_1_signing)?;
-    module.register_async_method("round_2_signing", round_2_signing)?;
+    let info = ctx.info();
+    let mut module = RpcModule::new(());
+    module.register_method("node_info", move |_, _| RpcResult::Ok(info.clone()))?;
+    let context = ctx.clone();
+    module.register_async_method("round_1_signing", move |params, _| {
+        round_1_signing(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("round_2_signing", move |params, _| {
+        round_2_signing(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("sweep_round_1_signing", move |params, _| {
+        sweep_round_1_signing(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("sweep_round_2_signing", move |params, _| {
+        sweep_round_2_signing(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("attest_round_1_signing", move |params, _| {
+        attest_round_1_signing(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("attest_round_2_signing", move |params, _| {
+        attest_round_2_signing(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("vote_round_1_signing", move |params, _| {
+        vote_round_1_signing(params, context.clone())
+    })?;
 
     let addr = server.local_addr()?;
     let handle = server.start(module);
 
-    handle.stopped().await;
+    // let systemd know that the node is up (and still responsive, if it watches it)
+    let context = ctx.clone();
+    service::notify_ready(move || context.signing_tasks.read().is_ok());
+
+    // when asked to terminate, stop committing to new sessions, and give the ones committed to a chance to finish
+    tokio::select! {
+        _ = handle.clone().stopped() => {}
+        _ = shutdown::terminated() => {
+            info!("- shutting down, after the signing sessions committed to");
+            service::notify("STOPPING=1");
+            ctx.shutdown.begin();
+            ctx.shutdown
+                .drain(shutdown_timeout, || ctx.signing_tasks.read().unwrap().len())
+                .await;
+            // the server was already stopped otherwise
+            let _ = handle.stop();
+            handle.stopped().await;
+            info!("- node stopped");
+        }
+    }
 
     Ok(addr)
 }
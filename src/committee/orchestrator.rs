@@ -1,36 +1,72 @@
 use std::{
     collections::{BTreeMap, HashMap},
+    future::Future,
     net::SocketAddr,
+    path::PathBuf,
     str::FromStr,
-    sync::Arc,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
 };
 
-use anyhow::{Context, Result};
-use bitcoin::{
-    hex::DisplayHex,
-    key::{TapTweak, UntweakedPublicKey},
-    secp256k1, taproot, TapSighashType, Witness,
-};
-use frost_secp256k1_tr::Ciphersuite;
-use frost_secp256k1_tr::Group;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use bitcoin::{hashes::Hash, taproot, Amount, OutPoint, Txid, Witness};
 use itertools::Itertools;
-use jsonrpsee::{server::Server, RpcModule};
-use jsonrpsee_core::RpcResult;
+use jsonrpsee::{
+    server::{middleware::http::ProxyGetRequestLayer, Server, ServerHandle},
+    PendingSubscriptionSink, RpcModule, SubscriptionMessage,
+};
+use jsonrpsee_core::{RpcResult, SubscriptionResult};
 use jsonrpsee_types::{ErrorObjectOwned, Params};
-use log::{debug, error, info};
-use secp256k1::XOnlyPublicKey;
+use log::{debug, error, info, warn};
+use secp256k1::{schnorr, XOnlyPublicKey};
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 
 use crate::{
-    bob_request::{BobRequest, BobResponse},
-    committee::node::Round1Response,
-    constants::ZKBITCOIN_PUBKEY,
-    frost,
-    json_rpc_stuff::{json_rpc_request, RpcCtx},
+    aggregation::Statement,
+    bob_request::{BobRequest, BobResponse, SmartContract},
+    client::{CommitteeMember, NodeClient, OrchestratorClient, OPENAPI},
+    config::{protocol_config, FeeQuote, FeeSchedule},
+    indexer::Indexer,
     mpc_sign_tx::get_digest_to_hash,
+    service,
+    watchtower::{Alert, ALERT_VALIDITY},
 };
 
-use super::node::{Round2Request, Round2Response};
+use super::{
+    admin::{AdminAuthLayer, AdminConfig},
+    aggregator::Aggregator,
+    alerts::{self, AlertKind, Alerts, OperatorAlert},
+    anomaly::{AnomalyAction, AnomalyPolicy, HeldRequest, Holds, SpendRecord},
+    attestation::{AttestationRequest, SignedAttestation},
+    audit::{AuditLog, SessionRecord},
+    batching::{combine_witnesses, Batcher},
+    dealer::load_json,
+    events::{watch_broadcast, Event, EventKind, EVENT_CAPACITY},
+    fees::{check_batch_fee, fee_owed, fee_paid, withdrawn, FeeRecord, FeesOwed},
+    governance::{CommitteePolicy, GovernanceLog, Proposal, RatifiedProposal},
+    grpc,
+    heartbeat::{self, CommitteeStatus, Roster},
+    hooks::ValidationHooks,
+    lightning::Lightning,
+    locks::{UtxoLocks, DEFAULT_LOCK_TIMEOUT},
+    misbehavior::{
+        Evidence, EvidenceLog, Misbehavior, MisbehaviorKind, Quarantine, QuarantinedMember,
+        SignedEvidence,
+    },
+    node::Round2Request,
+    policy::{ZkappPolicy, ZkappRegistration},
+    queue::{Priority, RequestQueue},
+    reorg::ReorgMonitor,
+    replay::SeenNonces,
+    selection::MemberSelector,
+    shared::{SharedState, SESSION_TTL},
+    shutdown::{self, Shutdown, DEFAULT_SHUTDOWN_TIMEOUT},
+    signer::{even_y, Commitment, CommitteeKey, SignatureAggregator, SignatureShare, SignerKind},
+    storage::{now, redact, Storage},
+    sweep::SweepDescriptor,
+    transparency::{InclusionProof, LogLeaf, SignedTreeHead, TransparencyLog},
+};
 
 //
 // Orchestration logic
@@ -40,197 +76,1124 @@ use super::node::{Round2Request, Round2Response};
 pub struct CommitteeConfig {
     pub threshold: usize,
     pub members: HashMap<frost_secp256k1_tr::Identifier, Member>,
+    /// The minimum number of confirmations the deployment of a zkapp must have
+    /// before the committee signs a spend of it ([DEFAULT_MIN_CONFIRMATIONS] if not set).
+    #[serde(default = "default_min_confirmations")]
+    pub min_confirmations: u32,
+    /// The fee the committee takes on every use of a zkapp
+    /// (the one of the protocol configuration if not set).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_schedule: Option<FeeSchedule>,
+    /// Whether the fee can be paid over Lightning to the orchestrator, instead of with an output of the transaction
+    /// (see [super::lightning]). Nodes then trust the orchestrator to have been paid.
+    #[serde(default)]
+    pub lightning_fees: bool,
+    /// The recovery descriptor the zkapps are swept to, should the committee be compromised (see [super::sweep]).
+    /// Nodes only sign emergency sweeps to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emergency_sweep_descriptor: Option<SweepDescriptor>,
+    /// The threshold-signing protocol the committee signs with (see [super::signer]).
+    /// MuSig2 committees sign with all of their members, so their threshold is their number of members.
+    #[serde(default)]
+    pub signer: SignerKind,
+    /// The most a use of a zkapp can withdraw to its recipients (in satoshis), if capped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_spend_sats: Option<u64>,
+    /// Whether the policy of the committee (fee schedule, minimum confirmations, and maximum spend)
+    /// is only changed by proposals ratified by its members (see [super::governance]), instead of by this configuration.
+    #[serde(default)]
+    pub governance: bool,
+    /// Whether every request must carry a nonce and be bound to the zkapp UTXO it spends (see [super::replay]).
+    /// Requests that do are checked either way.
+    #[serde(default)]
+    pub replay_protection: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl CommitteeConfig {
+    /// The fee the committee takes on every use of a zkapp.
+    pub fn fee_schedule(&self) -> FeeSchedule {
+        self.fee_schedule.clone().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Member {
     /// e.g. "127.0.0.1:8887"
     pub address: String,
+    /// Whether the member can be talked to in HTTP/2 directly, without negotiating it first
+    /// (which is not the case if it sits behind an HTTP/1.1 reverse proxy).
+    #[serde(default)]
+    pub http2: bool,
+}
+
+/// The minimum number of confirmations of zkapps, for committees that don't set one.
+/// Spends of unconfirmed zkapps are signed, so that spends of a zkapp can be chained
+/// while the previous one is in flight (see [super::locks]).
+pub const DEFAULT_MIN_CONFIRMATIONS: u32 = 0;
+
+fn default_min_confirmations() -> u32 {
+    DEFAULT_MIN_CONFIRMATIONS
+}
+
+/// The number of times a signing session aborted by a reorg is restarted.
+const REORG_RETRIES: usize = 2;
+
+/// The round a signing session is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Round {
+    /// The request is being validated (and its proof verified).
+    Validating,
+    /// The committee commits to nonces.
+    Round1,
+    /// The committee produces signature shares.
+    Round2,
+}
+
+/// A signing session in progress.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionStatus {
+    /// The transaction that deployed (or last updated) the zkapp being spent (see [BobRequest::txid]),
+    /// not the transaction being signed.
+    pub txid: Txid,
+    /// The input of the transaction being signed that spends the zkapp.
+    pub zkapp_input: usize,
+    /// The round the session is in.
+    pub round: Round,
+}
+
+/// A committee member that failed to answer (in time), along with why.
+type MemberError = (frost_secp256k1_tr::Identifier, anyhow::Error);
+
+/// The commitments and signature shares of the members taking part in a signing session.
+type SessionShares = (
+    BTreeMap<frost_secp256k1_tr::Identifier, Commitment>,
+    BTreeMap<frost_secp256k1_tr::Identifier, SignatureShare>,
+);
+
+/// What the members are asked to sign, in a session signing a digest instead of a transaction.
+#[derive(Clone, Copy)]
+enum DigestRequest<'a> {
+    /// Data to attest to (see [super::attestation]).
+    Attestation(&'a AttestationRequest),
+    /// A proposal to vote for (see [super::governance]).
+    Vote(&'a Proposal),
+}
+
+/// A signing session, tracked by the orchestrator until it's dropped
+/// (and claimed in the state shared with the other replicas, if any).
+struct Session<'a> {
+    sessions: &'a Mutex<HashMap<(Txid, usize), Round>>,
+    key: (Txid, usize),
+    shared_state: Option<Arc<dyn SharedState>>,
+}
+
+impl Session<'_> {
+    fn advance(&self, round: Round) {
+        debug!("- {} (input {}) in {round:?}", self.key.0, self.key.1);
+        self.sessions.lock().unwrap().insert(self.key, round);
+        if let Some(shared_state) = self.shared_state.clone() {
+            let session = SessionStatus {
+                txid: self.key.0,
+                zkapp_input: self.key.1,
+                round,
+            };
+            tokio::spawn(async move {
+                if let Err(err) = shared_state.update_session(&session, SESSION_TTL).await {
+                    warn!("couldn't share the round of the signing session: {err:#}");
+                }
+            });
+        }
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        self.sessions.lock().unwrap().remove(&self.key);
+        if let Some(shared_state) = self.shared_state.take() {
+            let (txid, zkapp_input) = self.key;
+            tokio::spawn(async move {
+                if let Err(err) = shared_state.release_session(txid, zkapp_input).await {
+                    warn!("couldn't release the signing session (it will expire): {err:#}");
+                }
+            });
+        }
+    }
+}
+
+/// The committee the orchestrator talks to, replaced as a whole when its configuration is reloaded
+/// (signing sessions in progress keep the one they started with).
+struct Committee {
+    config: CommitteeConfig,
+    /// A client per committee member, reused across requests (and reloads, if the member didn't change).
+    members: HashMap<frost_secp256k1_tr::Identifier, Arc<dyn CommitteeMember>>,
 }
 
 pub struct Orchestrator {
-    pub pubkey_package: frost_secp256k1_tr::keys::PublicKeyPackage,
-    pub committee_cfg: CommitteeConfig,
+    /// The committee key (a FROST public key package, or the keys of a MuSig2 committee, see [super::signer]).
+    pub pubkey_package: CommitteeKey,
+    /// Where the committee configuration is reloaded from (see [Orchestrator::reload_committee_cfg]), if anywhere.
+    pub committee_cfg_path: Option<PathBuf>,
+    /// Where received requests are persisted (if anywhere).
+    pub storage: Option<Storage>,
+    /// Where the signatures produced are logged (if anywhere).
+    pub audit_log: Option<AuditLog>,
+    /// Where the signatures produced are published, for anyone to check (if anywhere).
+    pub transparency_log: Option<TransparencyLog>,
+    /// The proposals ratified by the committee, if its policy is governed (see [super::governance]).
+    pub governance: Option<GovernanceLog>,
+    /// Where evidence of the misbehavior of members is recorded (if anywhere, see [super::misbehavior]).
+    pub evidence_log: Option<EvidenceLog>,
+    /// The app-level validation hooks registered by zkapps (if any).
+    pub hooks: Option<ValidationHooks>,
+    /// An index of the zkapps on chain, to reject requests spending zkapps that are already spent.
+    pub indexer: Option<Arc<Indexer>>,
+    /// A monitor of the chain, to abort signing sessions affected by reorgs.
+    pub reorg_monitor: Option<Arc<ReorgMonitor>>,
+    /// Verifies the proofs of pending requests together (instead of one by one), if set.
+    pub aggregator: Option<Aggregator>,
+    /// Limits how many requests are handled at once, and schedules the other ones (see [super::queue]), if set.
+    pub queue: Option<RequestQueue>,
+    /// Accumulates the requests made for the same transaction, to sign them together (see [super::batching]), if set.
+    pub batcher: Option<Batcher>,
+    /// Issues the Lightning invoices paying the committee's fee (if the committee takes them), and checks they're paid.
+    pub lightning: Option<Lightning>,
+    /// Picks the members signing each request, and replaces the ones that don't answer in time.
+    pub selector: MemberSelector,
+    /// How often members are pinged to check that they're up (never if unset).
+    pub heartbeat_interval: Option<Duration>,
+    /// Where the operators are alerted about critical events (if anywhere, see [super::alerts]).
+    pub alerts: Option<Alerts>,
+    /// Which members are up, as of the last time they were pinged.
+    roster: Roster,
+    /// The members left out of signing sessions for misbehaving.
+    pub quarantine: Quarantine,
+    /// The anomalous requests held until an operator approves them (see [super::anomaly]).
+    pub holds: Holds,
+    /// The state shared with other replicas of the orchestrator, if any (see [super::shared]),
+    /// which then takes over the tracking of signing sessions, halts, and held requests.
+    pub shared_state: Option<Arc<dyn SharedState>>,
+    /// The keys of the watchtowers whose alerts halt the orchestrator (see [crate::watchtower]).
+    pub watchtowers: Vec<XOnlyPublicKey>,
+    /// Where the admin API is served, if anywhere (see [super::admin]).
+    pub admin: Option<AdminConfig>,
+    /// The alert the orchestrator was halted with (until it restarts), if any.
+    halted: Mutex<Option<Alert>>,
+    /// Whether the orchestrator is shutting down (and not taking new requests).
+    pub shutdown: Shutdown,
+    /// How long the signing sessions in progress are waited for when shutting down.
+    pub shutdown_timeout: Duration,
+    /// The committee, as last configured.
+    committee: RwLock<Arc<Committee>>,
+    /// The zkapp UTXOs being spent (see [super::locks]).
+    utxo_locks: UtxoLocks,
+    /// The nonces of the requests received, when they aren't kept in the storage (see [super::replay]).
+    seen_nonces: SeenNonces,
+    /// The fees owed by the zkapp inputs of the transactions signed, so that a batch pays for all of its requests.
+    fees_owed: FeesOwed,
+    /// The signing sessions in progress (by zkapp spent, see [BobRequest::txid], and zkapp input),
+    /// each running on its own and tracking its own round, so that a slow one doesn't hold up the others.
+    sessions: Mutex<HashMap<(Txid, usize), Round>>,
+    /// Where the events of signing sessions are sent, for subscribers to stream.
+    events: broadcast::Sender<Event>,
 }
 
 impl Orchestrator {
     pub fn new(
-        pubkey_package: frost_secp256k1_tr::keys::PublicKeyPackage,
+        pubkey_package: impl Into<CommitteeKey>,
+        committee_cfg: CommitteeConfig,
+    ) -> Result<Self> {
+        let members = committee_cfg
+            .members
+            .iter()
+            .map(|(id, member)| {
+                let client: Box<dyn CommitteeMember> = Box::new(NodeClient::new(member)?);
+                Ok((*id, client))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self::with_members(pubkey_package, committee_cfg, members))
+    }
+
+    /// Creates an orchestrator talking to the given members,
+    /// instead of the ones at the addresses of the committee configuration.
+    pub fn with_members(
+        pubkey_package: impl Into<CommitteeKey>,
         committee_cfg: CommitteeConfig,
+        members: HashMap<frost_secp256k1_tr::Identifier, Box<dyn CommitteeMember>>,
     ) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let roster = Roster::new(&committee_cfg);
+        let committee = Committee {
+            config: committee_cfg,
+            members: members
+                .into_iter()
+                .map(|(id, member)| (id, Arc::from(member)))
+                .collect(),
+        };
+
         Self {
-            pubkey_package,
-            committee_cfg,
+            pubkey_package: pubkey_package.into(),
+            committee_cfg_path: None,
+            storage: None,
+            audit_log: None,
+            transparency_log: None,
+            governance: None,
+            evidence_log: None,
+            hooks: None,
+            indexer: None,
+            reorg_monitor: None,
+            aggregator: None,
+            queue: None,
+            batcher: None,
+            lightning: None,
+            selector: MemberSelector::default(),
+            heartbeat_interval: None,
+            alerts: None,
+            roster,
+            quarantine: Quarantine::default(),
+            holds: Holds::default(),
+            shared_state: None,
+            watchtowers: vec![],
+            admin: None,
+            halted: Mutex::new(None),
+            shutdown: Shutdown::default(),
+            shutdown_timeout: Duration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT),
+            committee: RwLock::new(Arc::new(committee)),
+            events,
+            utxo_locks: UtxoLocks::default(),
+            seen_nonces: SeenNonces::default(),
+            fees_owed: FeesOwed::default(),
+            sessions: Mutex::new(HashMap::new()),
         }
     }
 
-    /// Handles bob request from A to Z.
-    pub async fn handle_request(&self, bob_request: &BobRequest) -> Result<BobResponse> {
-        // Validate transaction before forwarding it, and get smart contract
-        let smart_contract = bob_request.validate_request().await?;
+    /// The committee, as last configured.
+    fn committee(&self) -> Arc<Committee> {
+        self.committee.read().unwrap().clone()
+    }
 
-        // TODO: we might want to check that the zkapp/UTXO is unspent here, but this requires us to have access to a bitcoin node, so for now we don't do it :o)
+    /// Returns the committee configuration in use.
+    pub fn committee_cfg(&self) -> CommitteeConfig {
+        self.committee().config.clone()
+    }
 
-        //
-        // Round 1
-        //
+    /// Quotes the fee the committee takes when `amount` is withdrawn to the recipients of a zkapp,
+    /// with an invoice paying it over Lightning if asked for.
+    pub async fn fee_quote(&self, amount: Amount, lightning: bool) -> Result<FeeQuote> {
+        let committee_cfg = self.committee_cfg();
+        let mut quote = FeeQuote::new(&committee_cfg.fee_schedule(), amount);
+        if lightning {
+            let node = self
+                .lightning
+                .as_ref()
+                .filter(|_| committee_cfg.lightning_fees)
+                .context("the committee doesn't take fees over Lightning")?;
+            quote.invoice = Some(node.invoice(quote.fee).await?);
+        }
+        Ok(quote)
+    }
 
-        let mut commitments_map = BTreeMap::new();
+    /// Replaces the committee configuration (member addresses, threshold, minimum confirmations, and fee schedule).
+    /// Signing sessions in progress finish with the previous one, and new ones use this one.
+    /// The committee must still hold shares of the same key, so members can only be added back or removed
+    /// (and not even removed from a MuSig2 committee, which signs with all of its members).
+    /// If the policy of the committee is governed, the last one it ratified replaces the one of the configuration.
+    pub fn set_committee_cfg(&self, mut committee_cfg: CommitteeConfig) -> Result<()> {
+        if committee_cfg.governance {
+            let governance = self.governance.as_ref().context(
+                "the policy of the committee is governed, but the orchestrator keeps no governance log",
+            )?;
+            let policy = governance.policy();
+            if CommitteePolicy::of(&committee_cfg) != policy {
+                warn!("- ignoring the policy of the committee configuration, for the one last ratified by the committee");
+            }
+            policy.apply(&mut committee_cfg);
+        }
+        ensure!(
+            committee_cfg.signer == self.pubkey_package.kind(),
+            "the committee signs with {:?}, but its key is a {:?} one",
+            committee_cfg.signer,
+            self.pubkey_package.kind()
+        );
+        ensure!(
+            committee_cfg.threshold > 0 && committee_cfg.threshold <= committee_cfg.members.len(),
+            "the threshold must be between 1 and the number of members ({}), not {}",
+            committee_cfg.members.len(),
+            committee_cfg.threshold
+        );
+        ensure!(
+            committee_cfg.signer != SignerKind::Musig2
+                || committee_cfg.threshold == committee_cfg.members.len(),
+            "MuSig2 committees sign with all of their {} members, so their threshold can't be {}",
+            committee_cfg.members.len(),
+            committee_cfg.threshold
+        );
+        if let Some(unknown) = committee_cfg
+            .members
+            .keys()
+            .find(|id| !self.pubkey_package.holds_share(id))
+        {
+            bail!("member {unknown:?} doesn't hold a share of the committee key");
+        }
+        ensure!(
+            committee_cfg.min_confirmations == 0 || self.can_count_confirmations(),
+            "the committee requires {} confirmations, but the orchestrator can't see the chain",
+            committee_cfg.min_confirmations
+        );
+        committee_cfg
+            .fee_schedule()
+            .validate()
+            .context("invalid fee schedule")?;
 
-        // pick a threshold of members at random
-        // TODO: AT RANDOM!
-        let threshold_of_members = self
-            .committee_cfg
+        // keep the clients (and their connections) of the members that didn't change
+        let previous = self.committee();
+        let members = committee_cfg
             .members
             .iter()
-            .take(self.committee_cfg.threshold)
-            .collect_vec();
-
-        // TODO: do this concurrently with async
-        // TODO: take a random sample instead of the first `threshold` members
-        // TODO: what if we get a timeout or can't meet that threshold? loop? send to more members?
-        for (member_id, member) in &threshold_of_members {
-            // send json RPC request
-            let rpc_ctx = RpcCtx {
-                version: Some("2.0"),
-                wallet: None,
-                address: Some(member.address.clone()),
-                auth: None,
+            .map(|(id, member)| {
+                let client = match previous.members.get(id) {
+                    Some(client) if previous.config.members.get(id) == Some(member) => {
+                        client.clone()
+                    }
+                    _ => Arc::new(NodeClient::new(member)?) as Arc<dyn CommitteeMember>,
+                };
+                Ok((*id, client))
+            })
+            .collect::<Result<_>>()?;
+
+        self.roster.update(&committee_cfg);
+        *self.committee.write().unwrap() = Arc::new(Committee {
+            config: committee_cfg,
+            members,
+        });
+        Ok(())
+    }
+
+    /// Reloads the committee configuration from [Orchestrator::committee_cfg_path] (see [Orchestrator::set_committee_cfg]).
+    pub fn reload_committee_cfg(&self) -> Result<CommitteeConfig> {
+        let path = self
+            .committee_cfg_path
+            .as_deref()
+            .context("the orchestrator wasn't started from a committee configuration file")?;
+        let committee_cfg: CommitteeConfig = load_json(path)?;
+        self.set_committee_cfg(committee_cfg)
+            .with_context(|| format!("couldn't reload {}", path.display()))?;
+        let committee_cfg = self.committee_cfg();
+        info!(
+            "- reloaded the committee configuration ({}-of-{})",
+            committee_cfg.threshold,
+            committee_cfg.members.len()
+        );
+        Ok(committee_cfg)
+    }
+
+    /// Pings every member, and records which ones answered (in time) and how fast.
+    pub async fn check_members(&self) {
+        let committee = self.committee();
+        futures::future::join_all(committee.members.iter().map(
+            |(&member_id, client)| async move {
+                let start = Instant::now();
+                match tokio::time::timeout(self.selector.timeout, client.info()).await {
+                    Ok(Ok(info)) => {
+                        let latency = start.elapsed();
+                        self.selector.record(member_id, latency);
+                        self.roster.record_success(member_id, latency, &info);
+                    }
+                    Ok(Err(err)) => self.roster.record_failure(member_id, &err),
+                    Err(_) => self.roster.record_failure(
+                        member_id,
+                        &anyhow!(
+                            "didn't answer within {}s",
+                            self.selector.timeout.as_secs_f64()
+                        ),
+                    ),
+                }
+            },
+        ))
+        .await;
+    }
+
+    /// Whether the state of the orchestrator can still be used
+    /// (this blocks if it's deadlocked, and fails if a thread panicked while holding it).
+    pub fn is_healthy(&self) -> bool {
+        self.sessions.lock().is_ok() && self.committee.read().is_ok()
+    }
+
+    /// Returns the health of the committee.
+    pub fn committee_status(&self) -> CommitteeStatus {
+        self.roster.status(self.committee().config.threshold)
+    }
+
+    /// Returns the signing sessions in progress on all the replicas (if the orchestrator shares its state),
+    /// or on this orchestrator.
+    pub async fn all_sessions(&self) -> Result<Vec<SessionStatus>> {
+        match &self.shared_state {
+            Some(shared_state) => shared_state.sessions().await,
+            None => Ok(self.sessions()),
+        }
+    }
+
+    /// Returns the signing sessions in progress.
+    pub fn sessions(&self) -> Vec<SessionStatus> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&(txid, zkapp_input), &round)| SessionStatus {
+                txid,
+                zkapp_input,
+                round,
+            })
+            .collect()
+    }
+
+    /// Fails if the orchestrator isn't taking new signing sessions (because it's shutting down, or halted).
+    async fn check_accepting(&self) -> Result<()> {
+        ensure!(
+            !self.shutdown.is_draining(),
+            "the orchestrator is shutting down, try again later"
+        );
+        // another replica might have been halted
+        if let Some(shared_state) = &self.shared_state {
+            if let Some(alert) = shared_state.halted().await? {
+                *self.halted.lock().unwrap() = Some(alert);
+            }
+        }
+        if let Some(alert) = &*self.halted.lock().unwrap() {
+            bail!(
+                "the orchestrator was halted by watchtower {} (at {}), as the zkBitcoin address was spent without a recorded signing session",
+                alert.signer,
+                alert.raised_at
+            );
+        }
+        Ok(())
+    }
+
+    /// Halts the orchestrator on an alert of a trusted watchtower (see [crate::watchtower]):
+    /// it stops taking signing sessions until it is restarted (as do the other replicas, if it shares its state).
+    pub async fn halt(&self, alert: &Alert) -> Result<()> {
+        ensure!(!alert.spends.is_empty(), "the alert is about no spend");
+        let signer = alert.signer()?;
+        ensure!(
+            self.watchtowers.contains(&signer),
+            "watchtower {signer} is not trusted"
+        );
+        alert.verify(Some(&signer))?;
+        ensure!(
+            now() <= alert.raised_at + ALERT_VALIDITY,
+            "the alert was raised more than {ALERT_VALIDITY}s ago"
+        );
+
+        for spend in &alert.spends {
+            error!(
+                "- watchtower {signer} saw {} spend {} without a recorded signing session",
+                spend.txid, spend.outpoint
+            );
+        }
+        error!("- halting: no signing session will start until the orchestrator is restarted");
+        *self.halted.lock().unwrap() = Some(alert.clone());
+        if let Some(shared_state) = &self.shared_state {
+            shared_state
+                .halt(alert)
+                .await
+                .context("couldn't halt the other replicas")?;
+        }
+        Ok(())
+    }
+
+    /// The alert the orchestrator was halted with, if any.
+    pub fn halted(&self) -> Option<Alert> {
+        self.halted.lock().unwrap().clone()
+    }
+
+    /// Starts tracking the signing session of a request.
+    /// Only one session can run at once for the same zkapp input of a transaction (across replicas),
+    /// as committee members keep a single set of nonces for it.
+    async fn start_session(&self, bob_request: &BobRequest) -> Result<Session<'_>> {
+        self.check_accepting().await?;
+        let key = (bob_request.txid()?, bob_request.zkapp_input);
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            ensure!(
+                !sessions.contains_key(&key),
+                "a signing session is already running for input {} of {}",
+                key.1,
+                key.0
+            );
+            sessions.insert(key, Round::Validating);
+        }
+        let mut session = Session {
+            sessions: &self.sessions,
+            key,
+            shared_state: None,
+        };
+
+        if let Some(shared_state) = &self.shared_state {
+            let status = SessionStatus {
+                txid: key.0,
+                zkapp_input: key.1,
+                round: Round::Validating,
             };
-            let resp = json_rpc_request(
-                &rpc_ctx,
-                "round_1_signing",
-                &[serde_json::value::to_raw_value(&bob_request).unwrap()],
-            )
-            .await
-            .context("rpc request to committee didn't work");
-            debug!("{:?}", resp);
-            let resp = resp?;
+            let claimed = shared_state
+                .claim_session(&status, SESSION_TTL)
+                .await
+                .context("couldn't claim the signing session")?;
+            ensure!(
+                claimed,
+                "a signing session is already running for input {} of {} (on another replica)",
+                key.1,
+                key.0
+            );
+            session.shared_state = Some(shared_state.clone());
+        }
+        Ok(session)
+    }
+
+    /// Subscribes to the events of signing sessions.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
 
-            let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&resp)?;
-            let resp: Round1Response = response.result()?;
+    /// Emits an event of the signing session of a request.
+    fn emit(&self, bob_request: &BobRequest, kind: EventKind) {
+        // there might be no subscriber
+        let _ = self.events.send(Event {
+            txid: bob_request.tx.txid(),
+            zkapp_txid: bob_request.zkapp_tx.txid(),
+            kind,
+        });
+    }
 
-            // store the commitment
-            commitments_map.insert(**member_id, resp.commitments);
+    /// Persists a request (if the orchestrator has storage), then handles it (and records its fee).
+    /// A request that was already signed gets the same response again.
+    /// If the orchestrator batches requests, the request is handled along with the other requests made for its transaction
+    /// within the batching window (see [super::batching]).
+    pub async fn unlock_funds(&self, bob_request: &BobRequest) -> Result<BobResponse> {
+        match &self.batcher {
+            Some(batcher) => {
+                batcher
+                    .submit(bob_request.clone(), |bob_requests| async move {
+                        self.unlock_funds_batch(&bob_requests).await
+                    })
+                    .await
+            }
+            None => {
+                self.unlock_funds_batch(std::slice::from_ref(bob_request))
+                    .await
+            }
         }
+    }
 
-        //
-        // Produce transaction and digest
-        //
-        let message = get_digest_to_hash(&bob_request.prev_outs, &bob_request.tx, &smart_contract)?;
+    /// Persists a batch of requests (if the orchestrator has storage), then handles it (and records its fee).
+    /// A batch that was already signed gets the same response again.
+    pub async fn unlock_funds_batch(&self, bob_requests: &[BobRequest]) -> Result<BobResponse> {
+        if let Some(bob_response) = self.signed_response(bob_requests) {
+            info!("- the request was already signed");
+            return Ok(bob_response);
+        }
+        self.check_replay(bob_requests)?;
 
-        //
-        // Round 2
-        //
+        // wait for the turn of the requests, if the orchestrator is busy
+        let priority = self.priority(bob_requests)?;
+        let _slot = match &self.queue {
+            Some(queue) => Some(queue.admit(priority).await?),
+            None => {
+                if let Some(deadline) = priority.deadline {
+                    ensure!(now() < deadline, "the deadline of the request passed");
+                }
+                None
+            }
+        };
 
-        let mut signature_shares = BTreeMap::new();
+        let request_hashes = self.record_requests(bob_requests);
+        self.handle_pending(bob_requests, &request_hashes).await
+    }
 
-        let round2_request = Round2Request {
-            txid: bob_request.txid()?,
-            proof_hash: bob_request.proof.hash(),
-            commitments_map: commitments_map.clone(),
-            message,
+    /// Checks that requests are bound to the zkapp UTXO they spend, and that their nonces weren't used by other requests
+    /// (see [super::replay]).
+    fn check_replay(&self, bob_requests: &[BobRequest]) -> Result<()> {
+        let required = self.committee_cfg().replay_protection;
+        for bob_request in bob_requests {
+            bob_request
+                .check_binding(required)
+                .context("the request isn't bound to the zkapp it spends")?;
+            let Some(nonce) = &bob_request.nonce else {
+                continue;
+            };
+            let request_hash = bob_request.hash();
+            match &self.storage {
+                Some(storage) => storage.use_nonce(nonce, &hex::encode(request_hash))?,
+                None => self.seen_nonces.use_nonce(nonce, request_hash)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles requests that were persisted and marked as pending,
+    /// and keeps their response (see [Storage::complete]) so that they aren't resumed later.
+    async fn handle_pending(
+        &self,
+        bob_requests: &[BobRequest],
+        request_hashes: &[String],
+    ) -> Result<BobResponse> {
+        let res = match bob_requests {
+            [bob_request] => self.handle_request(bob_request).await,
+            _ => self.handle_batch(bob_requests).await,
         };
+        if let Some(storage) = self.storage.as_ref().filter(|_| !request_hashes.is_empty()) {
+            if let Err(err) = storage.complete(request_hashes, res.as_ref().ok()) {
+                error!("couldn't complete pending requests: {err}");
+            }
+        }
+        let bob_response = res?;
+        self.record_fees(bob_requests);
+        self.record_spends(bob_requests);
+        Ok(bob_response)
+    }
 
-        // TODO: do this concurrently with async
-        // TODO: take a random sample instead of the first `threshold` members
-        // TODO: what if we get a timeout or can't meet that threshold? loop? send to more members?
-        for (member_id, member) in &threshold_of_members {
-            // send json RPC request
-            let rpc_ctx = RpcCtx {
-                version: Some("2.0"),
-                wallet: None,
-                address: Some(member.address.clone()),
-                auth: None,
+    /// What a batch of requests is scheduled by: what its transaction pays on top of the committee's fee,
+    /// and the earliest deadline of its requests.
+    fn priority(&self, bob_requests: &[BobRequest]) -> Result<Priority> {
+        let Some(first) = bob_requests.first() else {
+            return Ok(Priority::default());
+        };
+        let schedule = self.committee_cfg().fee_schedule();
+        let owed = bob_requests
+            .iter()
+            .map(|bob_request| fee_owed(&schedule, bob_request))
+            .sum();
+        Ok(Priority {
+            fee: fee_paid(&first.tx)?.checked_sub(owed).unwrap_or_default(),
+            deadline: bob_requests
+                .iter()
+                .filter_map(|bob_request| bob_request.deadline)
+                .min(),
+        })
+    }
+
+    /// Returns the response to a batch of requests, if it was already signed (and the orchestrator has storage).
+    fn signed_response(&self, bob_requests: &[BobRequest]) -> Option<BobResponse> {
+        let storage = self.storage.as_ref()?;
+        let request_hash = hex::encode(bob_requests.first()?.hash());
+        let bob_response = match storage.response(&request_hash) {
+            Ok(bob_response) => bob_response?,
+            Err(err) => {
+                error!("couldn't read response: {err}");
+                return None;
+            }
+        };
+        // the response must be for the same batch
+        let txid = bob_response.unlocked_tx.txid();
+        bob_requests
+            .iter()
+            .all(|bob_request| bob_request.tx.txid() == txid)
+            .then_some(bob_response)
+    }
+
+    /// Resumes the requests that were being handled when the orchestrator (or the previous leader,
+    /// see [super::failover]) stopped. Requests whose payload was purged are given up on.
+    pub async fn resume_pending(&self) -> Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        for request_hashes in storage.pending()? {
+            let bob_requests = request_hashes
+                .iter()
+                .map(|request_hash| {
+                    storage
+                        .record(request_hash)
+                        .map(|record| record.and_then(|record| record.payload))
+                })
+                .collect::<Result<Option<Vec<_>>>>()?;
+            let Some(bob_requests) = bob_requests else {
+                warn!(
+                    "- giving up on pending request {}, its payload is gone",
+                    request_hashes[0]
+                );
+                storage.complete(&request_hashes, None)?;
+                continue;
             };
-            let resp = json_rpc_request(
-                &rpc_ctx,
-                "round_2_signing",
-                &[serde_json::value::to_raw_value(&round2_request)?],
-            )
-            .await;
-            debug!("resp to 2nd request: {:?}", resp);
 
-            let resp = resp.context("second rpc request to committee didn't work")?;
+            info!("- resuming pending request {}", request_hashes[0]);
+            if let Err(err) = self.handle_pending(&bob_requests, &request_hashes).await {
+                warn!("- pending request {} failed: {err:#}", request_hashes[0]);
+            }
+        }
+        Ok(())
+    }
 
-            let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&resp)?;
-            let round2_response: Round2Response = response.result()?;
+    /// Records the fee paid by the requests signed in a transaction (if the orchestrator has storage).
+    fn record_fees(&self, bob_requests: &[BobRequest]) {
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        let res = FeeRecord::for_requests(bob_requests, now()).and_then(|mut records| {
+            // the fees paid over Lightning aren't in the transaction
+            if let Some(lightning) = &self.lightning {
+                for (record, bob_request) in records.iter_mut().zip(bob_requests) {
+                    if let Some(fee) = bob_request
+                        .fee_payment_hash
+                        .as_deref()
+                        .and_then(|payment_hash| lightning.fee(payment_hash))
+                    {
+                        record.fee += fee;
+                    }
+                }
+            }
+            records
+                .iter()
+                .try_for_each(|record| storage.record_fee(record))
+        });
+        if let Err(err) = res {
+            error!("couldn't record fee: {err}");
+        }
+    }
 
-            // store the commitment
-            signature_shares.insert(**member_id, round2_response.signature_share);
+    /// Records what the requests signed in a transaction withdrew (if the orchestrator has storage),
+    /// for their future requests to be compared with (see [super::anomaly]).
+    fn record_spends(&self, bob_requests: &[BobRequest]) {
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        let res = SpendRecord::for_requests(bob_requests, now()).and_then(|records| {
+            records
+                .iter()
+                .try_for_each(|record| storage.record_spend(record))
+        });
+        if let Err(err) = res {
+            error!("couldn't record spend: {err}");
         }
+    }
 
-        //
-        // Aggregate signatures
-        //
+    /// Stores requests and marks them as pending (if the orchestrator has storage),
+    /// and returns their hashes (or nothing if they couldn't all be stored).
+    fn record_requests(&self, bob_requests: &[BobRequest]) -> Vec<String> {
+        let Some(storage) = self.storage.as_ref().filter(|_| !bob_requests.is_empty()) else {
+            return vec![];
+        };
+        let res = bob_requests
+            .iter()
+            .map(|bob_request| storage.record_request(bob_request))
+            .collect::<Result<Vec<_>>>()
+            .and_then(|request_hashes| {
+                storage.mark_pending(&request_hashes)?;
+                Ok(request_hashes)
+            });
+        res.unwrap_or_else(|err| {
+            error!("couldn't store request: {err}");
+            vec![]
+        })
+    }
 
-        debug!("- aggregate signature shares");
-        let signing_package = frost_secp256k1_tr::SigningPackage::new(commitments_map, &message);
-        let group_signature = {
-            let res = frost_secp256k1_tr::aggregate(
-                &signing_package,
-                &signature_shares,
-                &self.pubkey_package,
-            );
-            if let Some(err) = res.err() {
-                error!("error: {}", err);
+    /// Handles bob request from A to Z.
+    pub async fn handle_request(&self, bob_request: &BobRequest) -> Result<BobResponse> {
+        self.emit(bob_request, EventKind::RequestReceived);
+        let res = self.process_request(bob_request).await;
+
+        match &res {
+            Ok(_) => {
+                self.emit(bob_request, EventKind::Signed);
+                // we can only tell that the transaction was broadcast if we follow a node
+                if let Some(monitor) = &self.reorg_monitor {
+                    watch_broadcast(
+                        monitor.rpc_ctx().clone(),
+                        self.events.clone(),
+                        bob_request.tx.txid(),
+                        bob_request.zkapp_tx.txid(),
+                    );
+                }
             }
-            res.context("failed to aggregate signatures")?
+            Err(err) => self.emit(
+                bob_request,
+                EventKind::Failed {
+                    error: format!("{err:#}"),
+                },
+            ),
+        }
+
+        res
+    }
+
+    /// Validates a request, and has the committee sign it.
+    async fn process_request(&self, bob_request: &BobRequest) -> Result<BobResponse> {
+        let outpoint = bob_request
+            .tx
+            .input
+            .get(bob_request.zkapp_input)
+            .context("the zkapp input is not in the transaction")?
+            .previous_output;
+        let session = self.start_session(bob_request).await?;
+
+        // Validate transaction before forwarding it, and get smart contract
+        // (a groth16 proof being verified with the ones of other pending requests, if aggregating)
+        let smart_contract = match &self.aggregator {
+            Some(aggregator) if Statement::is_aggregatable(&bob_request.vk, &bob_request.proof) => {
+                let (smart_contract, public_inputs) =
+                    bob_request.validate_request_except_proof().await?;
+                aggregator
+                    .verify(Statement {
+                        vk: bob_request.vk.clone(),
+                        public_inputs,
+                        proof: bob_request.proof.clone(),
+                    })
+                    .await?;
+                smart_contract
+            }
+            _ => bob_request.validate_request().await?,
         };
+        self.emit(bob_request, EventKind::ProofVerified);
 
-        #[cfg(debug_assertions)]
-        {
-            // verify using FROST
-            let group_pubkey = self.pubkey_package.verifying_key();
-            assert!(group_pubkey.verify(&message, &group_signature).is_ok());
-            debug!("- the signature verified locally with FROST lib");
+        // only one spend of the zkapp's UTXO can be in flight
+        // (the lock is only taken for valid requests, so that invalid ones can't hold up the zkapp)
+        let mut utxo_lock = self
+            .utxo_locks
+            .lock(outpoint, bob_request.tx.txid(), DEFAULT_LOCK_TIMEOUT)
+            .await?;
 
-            // assert that the pubkey is the same
-            let deserialized_pubkey =
-                bitcoin::PublicKey::from_slice(&group_pubkey.serialize()).unwrap();
-            let zkbitcoin_pubkey: bitcoin::PublicKey =
-                bitcoin::PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap();
-            assert_eq!(deserialized_pubkey, zkbitcoin_pubkey);
-
-            // let's compare pubkeys
-            {
-                // from hardcoded
-                let secp = secp256k1::Secp256k1::default();
-                let zkbitcoin_pubkey: bitcoin::PublicKey =
-                    bitcoin::PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap();
-                let internal_key = UntweakedPublicKey::from(zkbitcoin_pubkey);
-                let (tweaked, _) = internal_key.tap_tweak(&secp, None);
-                let tweaked = tweaked.to_string();
-                debug!("tweaked: {}", tweaked);
-
-                // from FROST
-                let xone = XOnlyPublicKey::from_slice(&group_pubkey.serialize()[1..]).unwrap();
-                let (tweaked2, _) = xone.tap_tweak(&secp, None);
-                let tweaked2 = tweaked2.to_string();
-                debug!("tweaked2: {}", tweaked2);
-                assert_eq!(tweaked, tweaked2);
-
-                // twaked
-                let tweaked3 =
-                    frost_secp256k1_tr::Secp256K1Sha256::tweaked_public_key(group_pubkey.element());
-                let s = <frost_secp256k1_tr::Secp256K1Sha256 as Ciphersuite>::Group::serialize(
-                    &tweaked3,
+        // Fetch the policy the zkapp was registered with by its deployer (if any)
+        let policy = match (&self.storage, &smart_contract.deployer) {
+            (Some(storage), Some(deployer)) => storage.policy(&smart_contract.vk_hash, deployer)?,
+            _ => None,
+        };
+
+        let res = match &self.reorg_monitor {
+            Some(monitor) => {
+                self.sign_request_monitored(
+                    monitor,
+                    &session,
+                    bob_request,
+                    &smart_contract,
+                    policy.as_ref(),
+                )
+                .await
+            }
+            None => {
+                self.sign_request(&session, bob_request, &smart_contract, policy.as_ref())
+                    .await
+            }
+        };
+
+        // Let the zkapp's owner know that someone attempted to use it
+        if let Some(policy) = &policy {
+            policy.notify(&smart_contract, bob_request, &res);
+        }
+
+        if res.is_ok() {
+            utxo_lock.signed();
+        }
+        res
+    }
+
+    /// Returns true if the orchestrator can see the chain, to count the confirmations of zkapps.
+    pub fn can_count_confirmations(&self) -> bool {
+        self.indexer.is_some() || self.reorg_monitor.is_some()
+    }
+
+    /// Returns the number of confirmations of a zkapp, according to the index or to the node followed for reorgs.
+    /// A zkapp that isn't indexed (yet) has no confirmations.
+    async fn confirmations(&self, outpoint: OutPoint) -> Result<u64> {
+        if let Some(indexer) = &self.indexer {
+            let confirmations = indexer
+                .query(move |indexer| indexer.confirmations(outpoint))
+                .await?;
+            return Ok(confirmations.unwrap_or(0));
+        }
+        let monitor = self
+            .reorg_monitor
+            .as_ref()
+            .context("the orchestrator can't count the confirmations of zkapps")?;
+        Ok(monitor.confirmations(outpoint).await?.map_or(0, u64::from))
+    }
+
+    /// Runs [Self::sign_request] while the monitor watches the zkapp's UTXO.
+    /// If its deployment loses confirmations in a reorg, the session is aborted,
+    /// and restarted once the chain settles (as long as the deployment is still confirmed).
+    async fn sign_request_monitored(
+        &self,
+        monitor: &ReorgMonitor,
+        session: &Session<'_>,
+        bob_request: &BobRequest,
+        smart_contract: &SmartContract,
+        policy: Option<&ZkappPolicy>,
+    ) -> Result<BobResponse> {
+        let outpoint = OutPoint::new(smart_contract.txid, smart_contract.vout_of_zkbitcoin_utxo);
+        let mut retries = 0;
+        loop {
+            let mut session = monitor.track(outpoint).await?;
+            if retries > 0 {
+                ensure!(
+                    session.confirmations.is_some(),
+                    "zkapp {outpoint} is no longer confirmed after a reorg"
                 );
-                let tweaked3 = s.to_lower_hex_string();
-                debug!("tweaked3: {}", tweaked3);
-                //assert_eq!(tweaked2, tweaked3);
             }
 
-            // verify using bitcoin lib
-            let sig = secp256k1::schnorr::Signature::from_slice(&group_signature.serialize()[1..])
-                .unwrap();
+            let reorged = tokio::select! {
+                res = self.sign_request(session, bob_request, smart_contract, policy) => return res,
+                reorged = session.aborted() => reorged,
+            };
+            ensure!(
+                retries < REORG_RETRIES,
+                "signing session aborted: {reorged}"
+            );
+            warn!("- signing session aborted ({reorged}), restarting it once the chain settles");
+            retries += 1;
+        }
+    }
+
+    /// Flags a request that deviates from the history of its zkapp (see [super::anomaly]),
+    /// and fails if the policy of the zkapp holds it until an operator approves it (and none did yet).
+    async fn check_anomaly(
+        &self,
+        anomaly_policy: &AnomalyPolicy,
+        bob_request: &BobRequest,
+        smart_contract: &SmartContract,
+    ) -> Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+        let vk_hash = hex::encode(smart_contract.vk_hash);
+        let history = storage.spend_records(&vk_hash)?;
+        let withdrawn = withdrawn(bob_request);
+        let Some(anomaly) = anomaly_policy.check(&history, withdrawn, now()) else {
+            return Ok(());
+        };
+
+        let request_hash = hex::encode(bob_request.hash());
+        let approved = match &self.shared_state {
+            Some(shared_state) => shared_state.take_approval(&request_hash).await?,
+            None => self.holds.take_approval(&request_hash),
+        };
+        if approved {
+            info!("- signing anomalous request {request_hash}, approved by an operator");
+            return Ok(());
+        }
+        let held = anomaly_policy.action == AnomalyAction::Hold;
+        warn!("- request {request_hash} for zkapp {vk_hash} is anomalous: {anomaly}");
+        if let Some(alerts) = &self.alerts {
+            let alert = OperatorAlert::new(AlertKind::AnomalousRequest {
+                vk_hash: vk_hash.clone(),
+                request_hash: request_hash.clone(),
+                anomaly: anomaly.clone(),
+                held,
+            });
+            alerts.raise(&alert).await;
+        }
+        if held {
+            let held = HeldRequest {
+                request_hash: request_hash.clone(),
+                vk_hash,
+                txid: bob_request.tx.txid(),
+                withdrawn,
+                anomaly: anomaly.clone(),
+                held_at: now(),
+            };
+            match &self.shared_state {
+                Some(shared_state) => shared_state.hold(&held).await?,
+                None => self.holds.hold(held),
+            }
+            bail!("the request is held until an operator approves it, as {anomaly} (request {request_hash})");
+        }
+        Ok(())
+    }
+
+    /// Enforces the zkapp's policy and validation hooks,
+    /// then runs a signing session with the committee on a validated request.
+    async fn sign_request(
+        &self,
+        session: &Session<'_>,
+        bob_request: &BobRequest,
+        smart_contract: &SmartContract,
+        policy: Option<&ZkappPolicy>,
+    ) -> Result<BobResponse> {
+        // Enforce the constraints the zkapp was registered with
+        if let Some(policy) = policy {
+            policy.check(bob_request)?;
+        }
+
+        // Compare the request with the history of the zkapp
+        if let Some(anomaly_policy) = policy.and_then(|policy| policy.anomaly.as_ref()) {
+            self.check_anomaly(anomaly_policy, bob_request, smart_contract)
+                .await?;
+        }
+
+        // Run the zkapp's own sanity checks
+        if let Some(hooks) = &self.hooks {
+            hooks.check(smart_contract, bob_request)?;
+        }
+
+        // Check that the zkapp is unspent (if we know about it)
+        let outpoint = OutPoint::new(smart_contract.txid, smart_contract.vout_of_zkbitcoin_utxo);
+        if let Some(indexer) = &self.indexer {
+            let spent = indexer
+                .query(move |indexer| indexer.is_spent(outpoint))
+                .await?;
+            ensure!(spent != Some(true), "zkapp {outpoint} was already spent");
+        }
+
+        // Check that the zkapp is buried deep enough
+        let committee = self.committee();
+        let min_confirmations = committee.config.min_confirmations;
+        if min_confirmations > 0 {
+            let confirmations = self.confirmations(outpoint).await?;
+            ensure!(
+                confirmations >= u64::from(min_confirmations),
+                "zkapp {outpoint} has {confirmations} confirmations, but the committee requires {min_confirmations}"
+            );
+        }
+
+        // Check that the committee is paid (on chain, or over Lightning)
+        match &bob_request.fee_payment_hash {
+            Some(payment_hash) => {
+                let lightning = self
+                    .lightning
+                    .as_ref()
+                    .filter(|_| committee.config.lightning_fees)
+                    .context("the committee doesn't take fees over Lightning")?;
+                let owed = fee_owed(&committee.config.fee_schedule(), bob_request);
+                lightning.wait_paid(payment_hash, owed, outpoint).await?;
+            }
+            None => self
+                .fees_owed
+                .check(&committee.config.fee_schedule(), bob_request)?,
+        }
+
+        // Check that the request doesn't withdraw more than the committee allows
+        if let Some(max_spend_sats) = committee.config.max_spend_sats {
+            let withdrawn = withdrawn(bob_request);
+            ensure!(
+                withdrawn.to_sat() <= max_spend_sats,
+                "the request withdraws {withdrawn}, but the committee caps spends at {max_spend_sats} sats"
+            );
+        }
+
+        //
+        // Sign with a threshold of members
+        //
+
+        let message = get_digest_to_hash(
+            &bob_request.prev_outs,
+            &bob_request.tx,
+            smart_contract,
+            bob_request.sighash_type(),
+        )?;
+
+        let committee = &*committee;
+        let (sig, signers) = self
+            .with_signers(committee, &message, move |signers| async move {
+                self.run_rounds(committee, session, bob_request, message, &signers)
+                    .await
+            })
+            .await?;
+
+        #[cfg(debug_assertions)]
+        {
+            // assert that the pubkey is the same
             let zkbitcoin_pubkey: bitcoin::PublicKey =
-                bitcoin::PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap();
-            let internal_key = UntweakedPublicKey::from(zkbitcoin_pubkey);
-            let secp = secp256k1::Secp256k1::default();
-            let (tweaked, _) = internal_key.tap_tweak(&secp, None);
-            let msg = secp256k1::Message::from_digest(message);
-            assert!(secp.verify_schnorr(&sig, &msg, &tweaked.into()).is_ok());
+                bitcoin::PublicKey::from_str(&protocol_config().zkbitcoin_pubkey).unwrap();
+            assert_eq!(
+                self.pubkey_package.pubkey().unwrap(),
+                even_y(zkbitcoin_pubkey)
+            );
             debug!("- the signature verified locally with bitcoin lib");
         }
 
@@ -239,15 +1202,17 @@ impl Orchestrator {
         //
 
         debug!("- include signature in witness of transaction");
-        let serialized = group_signature.serialize();
-        debug!("- serialized: {:?}", serialized);
-        let sig = secp256k1::schnorr::Signature::from_slice(&serialized[1..])
-            .context("couldn't convert signature type")?;
-
-        let hash_ty = TapSighashType::All;
+        let hash_ty = bob_request.sighash_type();
         let final_signature = taproot::Signature { sig, hash_ty };
-        let mut witness = Witness::new();
-        witness.push(final_signature.to_vec());
+        let witness = match &smart_contract.recovery {
+            // recoverable zkapps are spent with the committee's script (see [crate::recovery])
+            Some(recovery) => recovery.committee_witness(&final_signature)?,
+            None => {
+                let mut witness = Witness::new();
+                witness.push(final_signature.to_vec());
+                witness
+            }
+        };
 
         let mut transaction = bob_request.tx.clone();
         transaction
@@ -256,17 +1221,474 @@ impl Orchestrator {
             .context("couldn't find zkapp input in transaction")?
             .witness = witness;
 
+        // keep track of what the committee signed, before letting it out
+        if let Some(audit_log) = &self.audit_log {
+            let record = SessionRecord::new(
+                bob_request.hash(),
+                &bob_request.tx,
+                bob_request.zkapp_input,
+                smart_contract,
+                signers,
+                &sig[..],
+            );
+            audit_log
+                .append(record)
+                .context("couldn't write the audit log")?;
+        }
+        if let Some(transparency_log) = &self.transparency_log {
+            let leaf = LogLeaf {
+                logged_at: now(),
+                txid: bob_request.tx.txid(),
+                zkapp_input: bob_request.zkapp_input,
+                zkapp_txid: smart_contract.txid,
+                signature: hex::encode(final_signature.to_vec()),
+            };
+            transparency_log
+                .append(leaf)
+                .context("couldn't write the transparency log")?;
+        }
+
         // return the signed transaction
         Ok(BobResponse {
             unlocked_tx: transaction,
         })
     }
+
+    /// Runs `rounds` with a threshold of members picked by the selector, and aggregates their signature shares
+    /// into a signature of `message` (returned along with the members that signed).
+    /// The rounds are restarted without any member that fails, and the members that misbehave are reported.
+    async fn with_signers<F, Fut>(
+        &self,
+        committee: &Committee,
+        message: &[u8; 32],
+        rounds: F,
+    ) -> Result<(schnorr::Signature, Vec<frost_secp256k1_tr::Identifier>)>
+    where
+        F: Fn(Vec<frost_secp256k1_tr::Identifier>) -> Fut,
+        Fut: Future<Output = Result<SessionShares, MemberError>>,
+    {
+        let res: Result<_> = async {
+            // leave out the quarantined members,
+            // and the members that didn't answer the last ping (unless too few would be left)
+            let threshold = committee.config.threshold;
+            let quarantined = self.quarantine.members();
+            let down = self.roster.unavailable();
+            let up = committee
+                .members
+                .keys()
+                .filter(|id| !quarantined.contains(id) && !down.contains(id));
+            let mut excluded = if up.count() >= threshold {
+                down.union(&quarantined).copied().collect()
+            } else {
+                quarantined
+            };
+            let mut last_error = None;
+            loop {
+                let signers = self
+                    .selector
+                    .select(committee.members.keys().copied(), threshold, &excluded)
+                    .map_err(|err| match last_error.take() {
+                        Some(last) => last.context(err.to_string()),
+                        None => err,
+                    })?;
+                let (member_id, err) = match rounds(signers.clone()).await {
+                    Ok((commitments_map, signature_shares)) => {
+                        debug!("- aggregate signature shares");
+                        match self.pubkey_package.aggregate(
+                            &commitments_map,
+                            &signature_shares,
+                            message,
+                        ) {
+                            Ok(sig) => return Ok((sig, signature_shares.into_keys().collect())),
+                            // only the members that sent invalid shares can be left out
+                            Err(err) => {
+                                match Misbehavior::of(&err).map(|misbehavior| misbehavior.member) {
+                                    Some(member_id) => (member_id, err),
+                                    None => {
+                                        error!("error: {err:#}");
+                                        return Err(err);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    Err(member_error) => member_error,
+                };
+
+                if let Some(misbehavior) = Misbehavior::of(&err) {
+                    self.report(misbehavior, message, &signers);
+                }
+                warn!("- committee member {member_id:?} failed, restarting the session without it: {err:#}");
+                excluded.insert(member_id);
+                last_error = Some(err);
+            }
+        }
+        .await;
+
+        if let Some(alerts) = &self.alerts {
+            alerts.record_signing(res.is_ok());
+        }
+        res
+    }
+
+    /// Records evidence of a member misbehaving in a session signing `message` with `signers`,
+    /// and quarantines the member if it comes to that (see [super::misbehavior]).
+    fn report(
+        &self,
+        misbehavior: &Misbehavior,
+        message: &[u8; 32],
+        signers: &[frost_secp256k1_tr::Identifier],
+    ) {
+        let evidence = Evidence::new(misbehavior, message, signers);
+        if !self.quarantine.record(&evidence) {
+            return;
+        }
+        warn!("- quarantined committee {misbehavior}");
+        if let Some(evidence_log) = &self.evidence_log {
+            if let Err(err) = evidence_log.append(evidence) {
+                error!("- couldn't record evidence of misbehavior: {err:#}");
+            }
+        }
+    }
+
+    /// The evidence of misbehavior recorded so far.
+    pub fn misbehavior_evidence(&self) -> Vec<SignedEvidence> {
+        self.evidence_log
+            .as_ref()
+            .map(EvidenceLog::entries)
+            .unwrap_or_default()
+    }
+
+    /// The members currently quarantined.
+    pub fn quarantined_members(&self) -> Vec<QuarantinedMember> {
+        self.quarantine.list()
+    }
+
+    /// Releases a quarantined member, so that it takes part in signing sessions again.
+    pub fn release_member(&self, member_id: frost_secp256k1_tr::Identifier) -> Result<()> {
+        ensure!(
+            self.quarantine.release(member_id),
+            "committee member {member_id:?} is not quarantined"
+        );
+        info!("- released committee member {member_id:?} from quarantine");
+        Ok(())
+    }
+
+    /// The anomalous requests held until an operator approves them.
+    pub async fn held_requests(&self) -> Result<Vec<HeldRequest>> {
+        match &self.shared_state {
+            Some(shared_state) => shared_state.held().await,
+            None => Ok(self.holds.list()),
+        }
+    }
+
+    /// Approves a held request, so that it's signed the next time it's sent.
+    pub async fn approve_request(&self, request_hash: &str) -> Result<HeldRequest> {
+        let held = match &self.shared_state {
+            Some(shared_state) => shared_state.approve(request_hash).await?,
+            None => self.holds.approve(request_hash)?,
+        };
+        info!(
+            "- approved request {request_hash} (withdrawing {} from zkapp {})",
+            held.withdrawn, held.vk_hash
+        );
+        Ok(held)
+    }
+
+    /// Runs the two rounds of signing with `signers`, and returns their commitments and signature shares,
+    /// or the first member that failed to answer (in time) along with its error.
+    async fn run_rounds(
+        &self,
+        committee: &Committee,
+        session: &Session<'_>,
+        bob_request: &BobRequest,
+        message: [u8; 32],
+        signers: &[frost_secp256k1_tr::Identifier],
+    ) -> Result<SessionShares, MemberError> {
+        //
+        // Round 1
+        //
+
+        session.advance(Round::Round1);
+        let round1_responses =
+            futures::future::try_join_all(signers.iter().map(|&member_id| async move {
+                let client = &committee.members[&member_id];
+                let resp = self
+                    .ask(member_id, async {
+                        client
+                            .round_1_signing(bob_request)
+                            .await
+                            .context("rpc request to committee didn't work")
+                    })
+                    .await?;
+                Ok::<_, MemberError>((member_id, resp.commitments))
+            }))
+            .await?;
+        let commitments_map: BTreeMap<_, _> = round1_responses.into_iter().collect();
+        self.emit(bob_request, EventKind::Round1Complete);
+
+        //
+        // Round 2
+        //
+
+        session.advance(Round::Round2);
+        let round2_request = Round2Request {
+            // the txid was already computed when the session started
+            txid: session.key.0,
+            zkapp_input: bob_request.zkapp_input,
+            proof_hash: bob_request.proof.hash(),
+            commitments_map: commitments_map.clone(),
+            message,
+        };
+
+        let round2_request = &round2_request;
+        let round2_responses =
+            futures::future::try_join_all(signers.iter().map(|&member_id| async move {
+                let client = &committee.members[&member_id];
+                let resp = self
+                    .ask(member_id, async {
+                        client
+                            .round_2_signing(round2_request)
+                            .await
+                            .context("second rpc request to committee didn't work")
+                    })
+                    .await?;
+                Ok::<_, MemberError>((member_id, resp.signature_share))
+            }))
+            .await?;
+        let signature_shares: BTreeMap<_, _> = round2_responses.into_iter().collect();
+
+        Ok((commitments_map, signature_shares))
+    }
+
+    /// Waits for a member to answer a request, for as long as the selector allows,
+    /// and records how long it took.
+    async fn ask<T>(
+        &self,
+        member_id: frost_secp256k1_tr::Identifier,
+        request: impl Future<Output = Result<T>>,
+    ) -> Result<T, MemberError> {
+        let start = Instant::now();
+        match tokio::time::timeout(self.selector.timeout, request).await {
+            Ok(Ok(resp)) => {
+                self.selector.record(member_id, start.elapsed());
+                self.quarantine.record_answer(member_id);
+                Ok(resp)
+            }
+            Ok(Err(err)) => Err((member_id, err)),
+            Err(_) => Err((
+                member_id,
+                anyhow!(Misbehavior::new(
+                    member_id,
+                    MisbehaviorKind::Timeout,
+                    format!("no answer within {}s", self.selector.timeout.as_secs_f64()),
+                )),
+            )),
+        }
+    }
+
+    /// Handles a batch of requests spending several zkapps in a single transaction,
+    /// sent together by the client or accumulated by the orchestrator (see [super::batching]).
+    /// All the requests must carry the same transaction (which all the proofs are bound to),
+    /// each for a different zkapp input. If any of them is rejected, the whole batch is.
+    /// A signing session is run for each zkapp input (concurrently), and the resulting witnesses are combined in one transaction.
+    pub async fn handle_batch(&self, bob_requests: &[BobRequest]) -> Result<BobResponse> {
+        let first = bob_requests.first().context("the batch is empty")?;
+        ensure!(
+            bob_requests.iter().all(|r| r.tx == first.tx),
+            "all the requests of a batch must share the same transaction"
+        );
+        ensure!(
+            bob_requests.iter().map(|r| r.zkapp_input).unique().count() == bob_requests.len(),
+            "all the requests of a batch must use different zkapp inputs"
+        );
+        // we can only find the updated zkapp in the outputs if there's one
+        ensure!(
+            bob_requests.iter().filter(|r| r.update.is_some()).count() <= 1,
+            "a batch can only contain one stateful zkapp"
+        );
+        // the requests share the fee output of the transaction
+        check_batch_fee(&self.committee().config.fee_schedule(), bob_requests)?;
+
+        let responses = futures::future::try_join_all(bob_requests.iter().map(|bob_request| {
+            debug!("- signing zkapp input {}", bob_request.zkapp_input);
+            self.handle_request(bob_request)
+        }))
+        .await?;
+
+        let unlocked = bob_requests
+            .iter()
+            .zip(responses)
+            .map(|(bob_request, bob_response)| (bob_request.zkapp_input, bob_response.unlocked_tx));
+        Ok(BobResponse {
+            unlocked_tx: combine_witnesses(&first.tx, unlocked)?,
+        })
+    }
+
+    /// Has a threshold of the committee attest to `data` (see [super::attestation]).
+    pub async fn attest(&self, data: &[u8]) -> Result<SignedAttestation> {
+        self.check_accepting().await?;
+        let attestation_request = &AttestationRequest::new(data);
+        let digest = attestation_request.digest()?;
+
+        let committee = self.committee();
+        let committee = &*committee;
+        let request = DigestRequest::Attestation(attestation_request);
+        let (sig, signers) = self
+            .with_signers(committee, &digest, move |signers| async move {
+                self.digest_rounds(committee, request, digest, &signers)
+                    .await
+            })
+            .await?;
+
+        // keep track of what the committee signed, before letting it out
+        if let Some(audit_log) = &self.audit_log {
+            let record = SessionRecord::attestation(digest, signers, &sig[..]);
+            audit_log
+                .append(record)
+                .context("couldn't write the audit log")?;
+        }
+
+        Ok(SignedAttestation::new(
+            data,
+            self.pubkey_package.pubkey()?,
+            &sig,
+        ))
+    }
+
+    /// Has a threshold of the committee vote for a proposal (see [super::governance]),
+    /// and once it is ratified, logs it and enforces its policy.
+    pub async fn ratify(&self, proposal: &Proposal) -> Result<RatifiedProposal> {
+        self.check_accepting().await?;
+        let committee = self.committee();
+        let committee = &*committee;
+        let governance = self
+            .governance
+            .as_ref()
+            .filter(|_| committee.config.governance)
+            .context("the policy of the committee is not governed")?;
+        ensure!(
+            proposal.sequence == governance.next_sequence(),
+            "the next proposal is #{}, not #{}",
+            governance.next_sequence(),
+            proposal.sequence
+        );
+        proposal.policy.validate()?;
+        ensure!(
+            proposal.policy.min_confirmations == 0 || self.can_count_confirmations(),
+            "the proposal requires {} confirmations, but the orchestrator can't see the chain",
+            proposal.policy.min_confirmations
+        );
+
+        let digest = proposal.hash()?;
+        let request = DigestRequest::Vote(proposal);
+        let (sig, signers) = self
+            .with_signers(committee, &digest, move |signers| async move {
+                self.digest_rounds(committee, request, digest, &signers)
+                    .await
+            })
+            .await?;
+
+        // keep track of what the committee signed, before letting it out
+        if let Some(audit_log) = &self.audit_log {
+            let record = SessionRecord::attestation(digest, signers, &sig[..]);
+            audit_log
+                .append(record)
+                .context("couldn't write the audit log")?;
+        }
+
+        // enforce the policy ratified
+        let ratified = governance.append(proposal.clone(), &sig)?;
+        self.set_committee_cfg(self.committee_cfg())?;
+        Ok(ratified)
+    }
+
+    /// Runs the two rounds of a session signing a digest (instead of a transaction) with `signers`
+    /// (like [Self::run_rounds]).
+    async fn digest_rounds(
+        &self,
+        committee: &Committee,
+        request: DigestRequest<'_>,
+        digest: [u8; 32],
+        signers: &[frost_secp256k1_tr::Identifier],
+    ) -> Result<SessionShares, MemberError> {
+        let commitments_map: BTreeMap<_, _> =
+            futures::future::try_join_all(signers.iter().map(|&member_id| async move {
+                let client = &committee.members[&member_id];
+                let resp = self
+                    .ask(member_id, async {
+                        match request {
+                            DigestRequest::Attestation(attestation_request) => {
+                                client.attest_round_1_signing(attestation_request).await
+                            }
+                            DigestRequest::Vote(proposal) => {
+                                client.vote_round_1_signing(proposal).await
+                            }
+                        }
+                        .context("rpc request to committee didn't work")
+                    })
+                    .await?;
+                Ok::<_, MemberError>((member_id, resp.commitments))
+            }))
+            .await?
+            .into_iter()
+            .collect();
+
+        // there's no transaction, the digest stands for the hash of the proof and for the message
+        // (and nodes sign it the same way, whether it's an attestation or a vote)
+        let round2_request = &Round2Request {
+            txid: Txid::all_zeros(),
+            zkapp_input: 0,
+            proof_hash: digest,
+            commitments_map: commitments_map.clone(),
+            message: digest,
+        };
+        let signature_shares: BTreeMap<_, _> =
+            futures::future::try_join_all(signers.iter().map(|&member_id| async move {
+                let client = &committee.members[&member_id];
+                let resp = self
+                    .ask(member_id, async {
+                        client
+                            .attest_round_2_signing(round2_request)
+                            .await
+                            .context("second rpc request to committee didn't work")
+                    })
+                    .await?;
+                Ok::<_, MemberError>((member_id, resp.signature_share))
+            }))
+            .await?
+            .into_iter()
+            .collect();
+
+        Ok((commitments_map, signature_shares))
+    }
 }
 
 //
 // Server logic
 //
 
+/// What an orchestrator reports about itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrchestratorInfo {
+    /// The version of zkBitcoin the orchestrator runs.
+    pub version: String,
+
+    /// The number of committee members needed to sign.
+    pub threshold: usize,
+
+    /// The number of committee members.
+    pub num_members: usize,
+
+    /// The JSON RPC methods the orchestrator supports.
+    pub methods: Vec<String>,
+}
+
+/// Asks an orchestrator about itself.
+pub async fn get_orchestrator_info(address: &str) -> Result<OrchestratorInfo> {
+    OrchestratorClient::new(address).info().await
+}
+
 /// Bob's request to unlock funds from a smart contract.
 async fn unlock_funds(
     params: Params<'static>,
@@ -275,9 +1697,10 @@ async fn unlock_funds(
     // get bob request
     let bob_request: [BobRequest; 1] = params.parse()?;
     let bob_request = &bob_request[0];
-    info!("received request: {:?}", bob_request);
+    info!("received request for {}", bob_request.tx.txid());
+    debug!("- request: {}", redact(&format!("{bob_request:?}")));
 
-    let bob_response = context.handle_request(bob_request).await.map_err(|e| {
+    let bob_response = context.unlock_funds(bob_request).await.map_err(|e| {
         ErrorObjectOwned::owned(
             jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
             "error while unlocking funds",
@@ -288,29 +1711,591 @@ async fn unlock_funds(
     RpcResult::Ok(bob_response)
 }
 
+/// A request to have the committee attest to some data.
+async fn attest(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<SignedAttestation> {
+    let attestation_request: [AttestationRequest; 1] = params.parse()?;
+    let attestation_request = &attestation_request[0];
+    info!(
+        "received a request to attest to {}",
+        attestation_request.data
+    );
+
+    let attest = || async {
+        let data = hex::decode(&attestation_request.data)
+            .context("the data to attest to is not hex-encoded")?;
+        context.attest(&data).await
+    };
+    let attestation = attest().await.map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while attesting",
+            Some(format!("{e:#}")),
+        )
+    })?;
+
+    RpcResult::Ok(attestation)
+}
+
+/// A request to put a proposal to the vote of the committee.
+async fn ratify_proposal(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<RatifiedProposal> {
+    let proposal: [Proposal; 1] = params.parse()?;
+    let proposal = &proposal[0];
+    info!("received proposal #{}", proposal.sequence);
+
+    let ratified = context.ratify(proposal).await.map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while ratifying the proposal",
+            Some(format!("{e:#}")),
+        )
+    })?;
+
+    RpcResult::Ok(ratified)
+}
+
+/// Alice's request to register the policy of her zkapp (signed with the deployer key committed in it).
+async fn register_zkapp(params: Params<'static>, context: Arc<Orchestrator>) -> RpcResult<String> {
+    let registration: [ZkappRegistration; 1] = params.parse()?;
+    let registration = &registration[0];
+    let vk_hash = &registration.vk_hash;
+    info!("received registration for zkapp {vk_hash}");
+
+    let register = || -> Result<()> {
+        let storage = context
+            .storage
+            .as_ref()
+            .context("this orchestrator doesn't store zkapp policies")?;
+        let (vk_hash, deployer) = registration.verify()?;
+        registration.policy.validate()?;
+        storage.register_policy(&vk_hash, &deployer, &registration.policy)
+    };
+
+    register().map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while registering zkapp",
+            Some(format!("{e}")),
+        )
+    })?;
+
+    RpcResult::Ok(vk_hash.clone())
+}
+
+/// Bob's request to unlock funds from several smart contracts in the same transaction.
+async fn unlock_funds_batch(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<BobResponse> {
+    // get bob requests
+    let bob_requests: [Vec<BobRequest>; 1] = params.parse()?;
+    let bob_requests = &bob_requests[0];
+    info!("received batch of {} requests", bob_requests.len());
+
+    let bob_response = context
+        .unlock_funds_batch(bob_requests)
+        .await
+        .map_err(|e| {
+            ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "error while unlocking funds",
+                Some(format!("the batch didn't validate: {e}")),
+            )
+        })?;
+
+    RpcResult::Ok(bob_response)
+}
+
+/// Bob's request for the fee the committee would take on the amount he withdraws (in satoshis),
+/// and optionally for an invoice to pay it over Lightning.
+async fn fee_quote(params: Params<'static>, context: Arc<Orchestrator>) -> RpcResult<FeeQuote> {
+    let mut params = params.sequence();
+    let amount: u64 = params.next()?;
+    let lightning: Option<bool> = params.optional_next()?;
+    let quote = context
+        .fee_quote(Amount::from_sat(amount), lightning.unwrap_or(false))
+        .await
+        .map_err(|e| {
+            ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "error while quoting the fee",
+                Some(format!("{e:#}")),
+            )
+        })?;
+    RpcResult::Ok(quote)
+}
+
+/// The current head of the transparency log.
+fn transparency_tree_head(context: &Orchestrator) -> RpcResult<SignedTreeHead> {
+    context
+        .transparency_log
+        .as_ref()
+        .map(TransparencyLog::tree_head)
+        .ok_or_else(|| {
+            ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "the orchestrator doesn't keep a transparency log",
+                None::<()>,
+            )
+        })
+}
+
+/// An auditor's request for a proof that the signature of a transaction (input) is in the transparency log.
+fn inclusion_proof(params: Params<'_>, context: &Orchestrator) -> RpcResult<InclusionProof> {
+    let (txid, zkapp_input): (Txid, usize) = params.parse()?;
+    context
+        .transparency_log
+        .as_ref()
+        .context("the orchestrator doesn't keep a transparency log")
+        .and_then(|transparency_log| transparency_log.prove(txid, zkapp_input))
+        .map_err(|e| {
+            ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "error while proving the inclusion of the signature",
+                Some(format!("{e:#}")),
+            )
+        })
+}
+
+/// An operator's request to reload the committee configuration.
+fn reload_committee_cfg(context: &Orchestrator) -> RpcResult<CommitteeConfig> {
+    context.reload_committee_cfg().map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while reloading the committee configuration",
+            Some(format!("{e:#}")),
+        )
+    })
+}
+
+/// An operator's request to release a quarantined member.
+fn release_member(params: Params<'_>, context: &Orchestrator) -> RpcResult<()> {
+    let member_id: [frost_secp256k1_tr::Identifier; 1] = params.parse()?;
+    context.release_member(member_id[0]).map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while releasing the member",
+            Some(format!("{e:#}")),
+        )
+    })
+}
+
+/// An operator's approval of a held request.
+async fn approve_request(
+    params: Params<'static>,
+    context: Arc<Orchestrator>,
+) -> RpcResult<HeldRequest> {
+    let request_hash: [String; 1] = params.parse()?;
+    context
+        .approve_request(&request_hash[0])
+        .await
+        .map_err(|e| {
+            ErrorObjectOwned::owned(
+                jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+                "error while approving the request",
+                Some(format!("{e:#}")),
+            )
+        })
+}
+
+/// Lists the held requests.
+async fn held_requests(context: Arc<Orchestrator>) -> RpcResult<Vec<HeldRequest>> {
+    context.held_requests().await.map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while listing held requests",
+            Some(format!("{e:#}")),
+        )
+    })
+}
+
+/// Lists the signing sessions in progress.
+async fn signing_sessions(context: Arc<Orchestrator>) -> RpcResult<Vec<SessionStatus>> {
+    context.all_sessions().await.map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while listing signing sessions",
+            Some(format!("{e:#}")),
+        )
+    })
+}
+
+/// A watchtower's request to halt the orchestrator.
+async fn halt(params: Params<'static>, context: Arc<Orchestrator>) -> RpcResult<()> {
+    let alert: [Alert; 1] = params.parse()?;
+    context.halt(&alert[0]).await.map_err(|e| {
+        ErrorObjectOwned::owned(
+            jsonrpsee_types::error::UNKNOWN_ERROR_CODE,
+            "error while halting",
+            Some(format!("{e:#}")),
+        )
+    })
+}
+
+/// Reloads the committee configuration every time the orchestrator gets a SIGHUP.
+#[cfg(unix)]
+async fn reload_on_hangup(context: Arc<Orchestrator>) -> Result<()> {
+    let mut hangups = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+    while hangups.recv().await.is_some() {
+        // a bad configuration is rejected, and the previous one kept
+        if let Err(err) = context.reload_committee_cfg() {
+            error!("{err:#}");
+        }
+    }
+    Ok(())
+}
+
+/// Streams the events of the signing session of a given (spend) transaction to a WebSocket client.
+/// The events of every session are only streamed on the admin API (see [subscribe_all_events]).
+async fn subscribe_events(
+    params: Params<'static>,
+    pending: PendingSubscriptionSink,
+    context: Arc<Orchestrator>,
+) -> SubscriptionResult {
+    let txid: bitcoin::Txid = match params.one() {
+        Ok(txid) => txid,
+        Err(err) => {
+            pending.reject(err).await;
+            return Ok(());
+        }
+    };
+    stream_events(pending, context, Some(txid)).await
+}
+
+/// Streams the events of every signing session to a WebSocket client of the admin API.
+async fn subscribe_all_events(
+    pending: PendingSubscriptionSink,
+    context: Arc<Orchestrator>,
+) -> SubscriptionResult {
+    stream_events(pending, context, None).await
+}
+
+/// Streams the events of signing sessions to a WebSocket client, only the ones of `txid` if given.
+async fn stream_events(
+    pending: PendingSubscriptionSink,
+    context: Arc<Orchestrator>,
+    txid: Option<bitcoin::Txid>,
+) -> SubscriptionResult {
+    let mut events = context.subscribe();
+    let sink = pending.accept().await?;
+    loop {
+        let event = tokio::select! {
+            _ = sink.closed() => return Ok(()),
+            event = events.recv() => event,
+        };
+        let event = match event {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("- an event subscriber missed {missed} events");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+
+        if txid.is_some() && txid != Some(event.txid) {
+            continue;
+        }
+        sink.send(SubscriptionMessage::from_json(&event)?).await?;
+    }
+}
+
+/// Serves the admin API (see [super::admin]).
+async fn serve_admin(admin: &AdminConfig, ctx: Arc<Orchestrator>) -> Result<ServerHandle> {
+    info!(
+        "- serving the admin API at address http://{}",
+        admin.address
+    );
+    let http_middleware =
+        tower::ServiceBuilder::new().layer(AdminAuthLayer::new(&admin.credentials));
+    let server = Server::builder()
+        .set_http_middleware(http_middleware)
+        .build(admin.address.parse::<SocketAddr>()?)
+        .await?;
+
+    Ok(server.start(admin_module(ctx)?))
+}
+
+/// The methods of the admin API (described in [OPENAPI], tagged `admin`).
+fn admin_module(ctx: Arc<Orchestrator>) -> Result<RpcModule<()>> {
+    let mut module = RpcModule::new(());
+    let context = ctx.clone();
+    module.register_async_method("held_requests", move |_, _| held_requests(context.clone()))?;
+    let context = ctx.clone();
+    module.register_async_method("approve_request", move |params, _| {
+        approve_request(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_method("release_member", move |params, _| {
+        release_member(params, &context)
+    })?;
+    let context = ctx.clone();
+    module.register_method("reload_committee_cfg", move |_, _| {
+        reload_committee_cfg(&context)
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("attest", move |params, _| attest(params, context.clone()))?;
+    let context = ctx.clone();
+    module.register_method("committee_status", move |_, _| {
+        RpcResult::Ok(context.committee_status())
+    })?;
+    let context = ctx.clone();
+    module.register_subscription(
+        "subscribe_all_events",
+        "event",
+        "unsubscribe_all_events",
+        move |_, pending, _| subscribe_all_events(pending, context.clone()),
+    )?;
+
+    Ok(module)
+}
+
+/// The methods of the public JSON RPC API (all of them described in [OPENAPI]).
+fn rpc_module(ctx: Arc<Orchestrator>) -> Result<RpcModule<()>> {
+    let mut module = RpcModule::new(());
+    let context = ctx.clone();
+    module.register_async_method("unlock_funds", move |params, _| {
+        unlock_funds(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("unlock_funds_batch", move |params, _| {
+        unlock_funds_batch(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("register_zkapp", move |params, _| {
+        register_zkapp(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("fee_quote", move |params, _| {
+        fee_quote(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("ratify_proposal", move |params, _| {
+        ratify_proposal(params, context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_method("ratified_proposals", move |_, _| {
+        RpcResult::Ok(
+            context
+                .governance
+                .as_ref()
+                .map(GovernanceLog::ratified)
+                .unwrap_or_default(),
+        )
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("halt", move |params, _| halt(params, context.clone()))?;
+    let context = ctx.clone();
+    module.register_method("misbehavior_evidence", move |_, _| {
+        RpcResult::Ok(context.misbehavior_evidence())
+    })?;
+    let context = ctx.clone();
+    module.register_method("quarantined_members", move |_, _| {
+        RpcResult::Ok(context.quarantined_members())
+    })?;
+    let context = ctx.clone();
+    module.register_method("transparency_tree_head", move |_, _| {
+        transparency_tree_head(&context)
+    })?;
+    let context = ctx.clone();
+    module.register_method("inclusion_proof", move |params, _| {
+        inclusion_proof(params, &context)
+    })?;
+    let context = ctx.clone();
+    module.register_async_method("signing_sessions", move |_, _| {
+        signing_sessions(context.clone())
+    })?;
+    let context = ctx.clone();
+    module.register_subscription(
+        "subscribe_events",
+        "event",
+        "unsubscribe_events",
+        move |params, pending, _| subscribe_events(params, pending, context.clone()),
+    )?;
+    let openapi: serde_json::Value = serde_json::from_str(OPENAPI)?;
+    module.register_method("openapi", move |_, _| RpcResult::Ok(openapi.clone()))?;
+
+    let methods: Vec<_> = module
+        .method_names()
+        .chain(["orchestrator_info"])
+        .map(str::to_string)
+        .collect();
+    // the committee can change when its configuration is reloaded
+    let context = ctx;
+    module.register_method("orchestrator_info", move |_, _| {
+        let committee_cfg = context.committee_cfg();
+        RpcResult::Ok(OrchestratorInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            threshold: committee_cfg.threshold,
+            num_members: committee_cfg.members.len(),
+            methods: methods.clone(),
+        })
+    })?;
+
+    Ok(module)
+}
+
 pub async fn run_server(
     address: Option<&str>,
-    pubkey_package: frost::PublicKeyPackage,
-    committee_cfg: CommitteeConfig,
+    grpc_address: Option<&str>,
+    ctx: Orchestrator,
 ) -> Result<SocketAddr> {
     let address = address.unwrap_or("127.0.0.1:6666");
     info!("- starting orchestrator at address http://{address}");
 
-    let ctx = Orchestrator {
-        pubkey_package,
-        committee_cfg,
-    };
-
+    // the OpenAPI description is also served to plain GET requests
+    let http_middleware =
+        tower::ServiceBuilder::new().layer(ProxyGetRequestLayer::new("/openapi.json", "openapi")?);
     let server = Server::builder()
+        .set_http_middleware(http_middleware)
         .build(address.parse::<SocketAddr>()?)
         .await?;
-    let mut module = RpcModule::new(ctx);
-    module.register_async_method("unlock_funds", unlock_funds)?;
 
+    // follow the chain, to abort signing sessions affected by reorgs
+    if let Some(monitor) = ctx.reorg_monitor.clone() {
+        tokio::spawn(async move { monitor.run().await });
+    }
+
+    // the gRPC API shares the orchestrator with the JSON RPC one
+    let ctx = Arc::new(ctx);
+
+    // pick up the requests left pending by a crash (or by the previous leader)
+    if ctx.storage.is_some() {
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            if let Err(err) = ctx.resume_pending().await {
+                error!("couldn't resume pending requests: {err:#}");
+            }
+        });
+    }
+
+    // keep track of which members are up
+    if let Some(interval) = ctx.heartbeat_interval {
+        tokio::spawn(heartbeat::run(ctx.clone(), interval));
+    }
+
+    // and alert the operators about critical events
+    if ctx.alerts.is_some() {
+        let interval = ctx
+            .heartbeat_interval
+            .unwrap_or(Duration::from_secs(heartbeat::DEFAULT_HEARTBEAT_INTERVAL));
+        tokio::spawn(alerts::run(ctx.clone(), interval));
+    }
+
+    // reload the committee configuration on SIGHUP
+    #[cfg(unix)]
+    if ctx.committee_cfg_path.is_some() {
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            if let Err(err) = reload_on_hangup(ctx).await {
+                error!("couldn't listen for SIGHUP: {err}");
+            }
+        });
+    }
+    if let Some(grpc_address) = grpc_address {
+        let grpc_address = grpc_address.parse::<SocketAddr>()?;
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            if let Err(err) = grpc::serve_orchestrator(grpc_address, ctx).await {
+                error!("the gRPC server stopped: {err}");
+            }
+        });
+    }
+
+    let module = rpc_module(ctx.clone())?;
     let addr = server.local_addr()?;
     let handle = server.start(module);
 
-    handle.stopped().await;
+    // operators act on the orchestrator through its own, authenticated, listener
+    let admin_handle = match &ctx.admin {
+        Some(admin) => Some(serve_admin(admin, ctx.clone()).await?),
+        None => {
+            info!("- no admin API (held requests can't be approved)");
+            None
+        }
+    };
+
+    // let systemd know that the orchestrator is up (and still responsive, if it watches it)
+    let context = ctx.clone();
+    service::notify_ready(move || context.is_healthy());
+
+    // when asked to terminate, stop taking requests, and let the signing sessions in progress finish first
+    tokio::select! {
+        _ = handle.clone().stopped() => {}
+        _ = shutdown::terminated() => {
+            info!(
+                "- shutting down, after the {} signing sessions in progress",
+                ctx.sessions.lock().unwrap().len()
+            );
+            service::notify("STOPPING=1");
+            ctx.shutdown.begin();
+            ctx.shutdown
+                .drain(ctx.shutdown_timeout, || ctx.sessions.lock().unwrap().len())
+                .await;
+            // the server was already stopped otherwise
+            let _ = handle.stop();
+            handle.stopped().await;
+            if let Some(admin_handle) = admin_handle {
+                let _ = admin_handle.stop();
+                admin_handle.stopped().await;
+            }
+            info!("- orchestrator stopped");
+        }
+    }
 
     Ok(addr)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::committee::{dealer, node::NodeState, signer::SignerKind};
+
+    #[test]
+    fn test_openapi_describes_every_method() {
+        let committee = dealer::generate(3, 2, 0, SignerKind::Frost).unwrap();
+        let members = committee
+            .key_packages
+            .iter()
+            .map(|(id, key_package)| {
+                let member: Box<dyn CommitteeMember> = Box::new(NodeState::new(
+                    key_package.clone(),
+                    committee.pubkey_package.clone(),
+                ));
+                (*id, member)
+            })
+            .collect();
+        let ctx = Arc::new(Orchestrator::with_members(
+            committee.pubkey_package.clone(),
+            committee.config.clone(),
+            members,
+        ));
+
+        // the methods of the document, split between the public and the admin API
+        let openapi: serde_json::Value = serde_json::from_str(OPENAPI).unwrap();
+        let (mut public, mut admin) = (BTreeSet::new(), BTreeSet::new());
+        for (path, item) in openapi["paths"].as_object().unwrap() {
+            let Some(method) = path.strip_prefix("/#") else {
+                continue;
+            };
+            let tags = item["post"]["tags"].as_array().cloned().unwrap_or_default();
+            if tags.iter().any(|tag| tag == "admin") {
+                admin.insert(method.to_string());
+            } else {
+                public.insert(method.to_string());
+            }
+        }
+
+        let names = |module: RpcModule<()>| -> BTreeSet<String> {
+            module.method_names().map(str::to_string).collect()
+        };
+        assert_eq!(names(rpc_module(ctx.clone()).unwrap()), public);
+        assert_eq!(names(admin_module(ctx).unwrap()), admin);
+    }
+}
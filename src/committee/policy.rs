@@ -0,0 +1,616 @@
+//! Per-zkapp policies.
+//!
+//! When deploying a zkapp, Alice can register with the orchestrator a policy bound to the zkapp's verifier key hash:
+//! extra constraints on the funds that can be withdrawn, and a webhook notified of every attempt to unlock them
+//! (by the orchestrator) and of every spend of the zkapp on chain (by the indexer, see [crate::indexer]).
+//! If the policy has a webhook secret, notifications are signed with it (see [sign_notification]).
+//!
+//! Policies are bound to the key of the deployer committed in the zkapp (see [crate::zkapp_data::ZkappData::deployer]):
+//! registrations must be signed with it (see [ZkappRegistration]), and a policy only applies to the zkapps committing
+//! to the key it was registered with, so that a zkapp deployed by someone else with the same verifier key
+//! (whose hash is public) can't change it. Policies can only be registered once per verifier key hash and deployer key.
+//! Zkapps deployed without a deployer key have no policy.
+//!
+//! Webhooks can't target loopback, private, or link-local addresses (see [ZkappPolicy::validate]),
+//! which is checked again when they are notified, against the addresses their host resolves to then.
+//! Redirects aren't followed, as they could lead anywhere.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{
+    hashes::{hmac, sha256, Hash, HashEngine},
+    Address, Amount, ScriptBuf, Txid,
+};
+use log::{debug, error};
+use reqwest::{header::CONTENT_TYPE, redirect, Client, Url};
+use secp256k1::{schnorr, Keypair, Message, Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bob_request::{BobRequest, BobResponse, SmartContract},
+    client::OrchestratorClient,
+    committee::{anomaly::AnomalyPolicy, storage::now},
+    get_network,
+    json_rpc_stuff::{http_client_builder, proxy},
+    scanner::{Zkapp, ZkappSpend},
+};
+
+/// Timeout (in seconds) for webhook notifications.
+const WEBHOOK_TIMEOUT: u64 = 10;
+
+/// The header carrying the UNIX timestamp at which a notification was signed.
+pub const TIMESTAMP_HEADER: &str = "X-Zkbitcoin-Timestamp";
+
+/// The header carrying the signature of a notification (see [sign_notification]).
+pub const SIGNATURE_HEADER: &str = "X-Zkbitcoin-Signature";
+
+/// The domain separator of the signatures of registrations.
+const REGISTRATION_DOMAIN: &[u8] = b"zkbitcoin zkapp registration";
+
+//
+// Data structures
+//
+
+/// The policy that a zkapp is registered with.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ZkappPolicy {
+    /// A URL to which every attempt to unlock the zkapp's funds, and every spend of it, is POSTed (see [Notification]).
+    pub webhook: Option<String>,
+
+    /// A secret shared with the webhook, to sign notifications with.
+    pub webhook_secret: Option<String>,
+
+    /// The maximum amount that can be withdrawn from the zkapp in a single transaction.
+    #[serde(default, with = "bitcoin::amount::serde::as_sat::opt")]
+    pub max_withdrawal: Option<Amount>,
+
+    /// The only addresses that can receive funds from the zkapp (if set).
+    pub allowed_recipients: Option<Vec<String>>,
+
+    /// How the requests of the zkapp are compared with its history (if they are, see [super::anomaly]).
+    #[serde(default)]
+    pub anomaly: Option<AnomalyPolicy>,
+}
+
+/// A request to register the policy of a zkapp, signed by its deployer (see [ZkappRegistration::sign]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZkappRegistration {
+    /// The hex-encoded verifier key hash of the zkapp.
+    pub vk_hash: String,
+
+    /// The policy to register.
+    pub policy: ZkappPolicy,
+
+    /// The hex-encoded (x-only) key of the deployer, committed in the zkapp.
+    pub deployer: String,
+
+    /// The hex-encoded Schnorr signature of the registration by the deployer.
+    pub signature: String,
+}
+
+impl ZkappRegistration {
+    fn digest(vk_hash: &[u8; 32], policy: &ZkappPolicy) -> Result<[u8; 32]> {
+        let mut engine = sha256::Hash::engine();
+        engine.input(REGISTRATION_DOMAIN);
+        engine.input(vk_hash);
+        engine.input(serde_json::to_string(policy)?.as_bytes());
+        Ok(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+
+    /// Signs the registration of a policy for the zkapps of `vk_hash` deployed with the key of `keypair`.
+    pub fn sign(vk_hash: &[u8; 32], policy: ZkappPolicy, keypair: &Keypair) -> Result<Self> {
+        let digest = Self::digest(vk_hash, &policy)?;
+        let signature = Secp256k1::signing_only()
+            .sign_schnorr_no_aux_rand(&Message::from_digest(digest), keypair);
+        Ok(Self {
+            vk_hash: hex::encode(vk_hash),
+            policy,
+            deployer: hex::encode(keypair.x_only_public_key().0.serialize()),
+            signature: hex::encode(signature.as_ref()),
+        })
+    }
+
+    /// Checks the signature of the registration,
+    /// and returns the verifier key hash and the deployer key of the zkapps it is for.
+    pub fn verify(&self) -> Result<([u8; 32], XOnlyPublicKey)> {
+        let vk_hash = hex::decode(&self.vk_hash)
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .context("the vk hash is not a hex-encoded 32-byte value")?;
+        let deployer = XOnlyPublicKey::from_slice(&hex::decode(&self.deployer)?)
+            .context("the registration has an invalid deployer key")?;
+        let signature = schnorr::Signature::from_slice(&hex::decode(&self.signature)?)
+            .context("the registration has an invalid signature")?;
+        let digest = Self::digest(&vk_hash, &self.policy)?;
+        Secp256k1::verification_only()
+            .verify_schnorr(&signature, &Message::from_digest(digest), &deployer)
+            .context("the registration isn't signed by the deployer key")?;
+        Ok((vk_hash, deployer))
+    }
+}
+
+/// What gets sent to a zkapp's webhook.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    /// Someone asked the orchestrator to unlock the zkapp's funds.
+    UnlockAttempt(UnlockAttempt),
+
+    /// The zkapp was spent on chain (and its state changed, for stateful zkapps).
+    ZkappSpent(ZkappSpent),
+}
+
+/// What gets sent to a zkapp's webhook on every attempt to unlock its funds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnlockAttempt {
+    /// The hex-encoded verifier key hash of the zkapp.
+    pub vk_hash: String,
+
+    /// The transaction ID of the zkapp being used.
+    pub zkapp_txid: Txid,
+
+    /// The transaction ID of the transaction unlocking the funds.
+    pub txid: Txid,
+
+    /// The amount withdrawn from the zkapp (in satoshis).
+    pub amount_out: u64,
+
+    /// Whether the committee signed the transaction.
+    pub accepted: bool,
+
+    /// Why the request was rejected, if it was.
+    pub error: Option<String>,
+}
+
+/// What gets sent to a zkapp's webhook when it's spent on chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZkappSpent {
+    /// The hex-encoded verifier key hash of the zkapp.
+    pub vk_hash: String,
+
+    /// The transaction ID of the zkapp that was spent.
+    pub zkapp_txid: Txid,
+
+    /// The transaction ID of the transaction spending it.
+    pub txid: Txid,
+
+    /// The height of the block containing the spend.
+    pub height: u64,
+
+    /// The state of the zkapp before the spend (for stateful zkapps).
+    pub old_state: Option<String>,
+
+    /// The state of the zkapp after the spend (for stateful zkapps that live on).
+    pub new_state: Option<String>,
+
+    /// The funds still locked in the zkapp after the spend (in satoshis).
+    pub locked_value: u64,
+}
+
+impl ZkappSpent {
+    pub fn new(zkapp: &Zkapp, spend: &ZkappSpend) -> Self {
+        Self {
+            vk_hash: zkapp.vk_hash.clone(),
+            zkapp_txid: zkapp.txid,
+            txid: spend.txid,
+            height: spend.height,
+            old_state: zkapp.state.clone(),
+            new_state: spend.zkapp.as_ref().and_then(|next| next.state.clone()),
+            locked_value: spend
+                .zkapp
+                .as_ref()
+                .map_or(0, |next| next.locked_value.to_sat()),
+        }
+    }
+}
+
+impl ZkappPolicy {
+    /// Returns the scripts of the allowed recipients (if restricted).
+    fn allowed_scripts(&self) -> Result<Option<Vec<ScriptBuf>>> {
+        self.allowed_recipients
+            .as_ref()
+            .map(|addresses| {
+                addresses
+                    .iter()
+                    .map(|address| {
+                        let address = Address::from_str(address)?
+                            .require_network(get_network())
+                            .with_context(|| format!("{address} is not for the current network"))?;
+                        Ok(address.script_pubkey())
+                    })
+                    .collect()
+            })
+            .transpose()
+    }
+
+    /// Ensures that the policy is well-formed, and that its webhook (if any) doesn't target a local address.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(webhook) = &self.webhook {
+            let url = Url::parse(webhook).context("the webhook is not a valid URL")?;
+            ensure!(
+                ["http", "https"].contains(&url.scheme()),
+                "the webhook must be an http(s) URL"
+            );
+            let host = url.host_str().context("the webhook has no host")?;
+            ensure!(
+                is_public_host(host),
+                "the webhook can't target a loopback, private, or link-local address"
+            );
+        }
+        ensure!(
+            self.webhook_secret.is_none() || self.webhook.is_some(),
+            "a webhook secret was given without a webhook"
+        );
+        if let Some(allowed) = self.allowed_scripts()? {
+            ensure!(
+                !allowed.is_empty(),
+                "the list of allowed recipients can't be empty"
+            );
+        }
+        if let Some(anomaly) = &self.anomaly {
+            anomaly.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Enforces the policy on a request.
+    pub fn check(&self, bob_request: &BobRequest) -> Result<()> {
+        if let Some(max_withdrawal) = self.max_withdrawal {
+            let withdrawn: Amount = bob_request.recipients.iter().map(|o| o.value).sum();
+            ensure!(
+                withdrawn <= max_withdrawal,
+                "the zkapp's policy doesn't allow withdrawing more than {max_withdrawal} at once"
+            );
+        }
+
+        if let Some(allowed) = self.allowed_scripts()? {
+            ensure!(
+                bob_request
+                    .recipients
+                    .iter()
+                    .all(|recipient| allowed.contains(&recipient.script_pubkey)),
+                "the zkapp's policy doesn't allow paying these recipients"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Notifies the zkapp's webhook (if any) of an attempt to unlock its funds.
+    /// This doesn't block: failures to notify are only logged.
+    pub fn notify(
+        &self,
+        smart_contract: &SmartContract,
+        bob_request: &BobRequest,
+        result: &Result<BobResponse>,
+    ) {
+        let attempt = UnlockAttempt {
+            vk_hash: hex::encode(smart_contract.vk_hash),
+            zkapp_txid: smart_contract.txid,
+            txid: bob_request.tx.txid(),
+            amount_out: bob_request
+                .recipients
+                .iter()
+                .map(|o| o.value.to_sat())
+                .sum(),
+            accepted: result.is_ok(),
+            error: result.as_ref().err().map(|err| format!("{err}")),
+        };
+        self.send(smart_contract.vk_hash, Notification::UnlockAttempt(attempt));
+    }
+
+    /// Notifies the zkapp's webhook (if any) of a notification.
+    /// This doesn't block: failures to notify are only logged.
+    pub fn send(&self, vk_hash: [u8; 32], notification: Notification) {
+        let webhook = match &self.webhook {
+            Some(webhook) => webhook.clone(),
+            None => return,
+        };
+        let secret = self.webhook_secret.clone();
+
+        tokio::spawn(async move {
+            let vk_hash = hex::encode(vk_hash);
+            match send_notification(&webhook, secret.as_deref(), &notification).await {
+                Ok(()) => debug!("- notified webhook of zkapp {vk_hash}"),
+                Err(err) => error!("couldn't notify webhook of zkapp {vk_hash}: {err}"),
+            }
+        });
+    }
+}
+
+/// Signs a notification, as the hex-encoded HMAC-SHA256 of `<timestamp>.<body>` keyed with the webhook secret.
+/// Webhooks should recompute it, compare it with the [SIGNATURE_HEADER] (minus its `sha256=` prefix),
+/// and reject notifications with an old [TIMESTAMP_HEADER] to prevent replays.
+pub fn sign_notification(secret: &str, timestamp: u64, body: &str) -> String {
+    hmac_sha256(secret.as_bytes(), format!("{timestamp}.{body}").as_bytes())
+}
+
+/// Returns the hex-encoded HMAC-SHA256 of a message.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> String {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(key);
+    engine.input(message);
+    hex::encode(hmac::Hmac::<sha256::Hash>::from_engine(engine).to_byte_array())
+}
+
+/// Returns false for the hosts webhooks can't target: local names, and addresses that aren't publicly routable
+/// (loopback, private, link-local, and the like), so that the orchestrator can't be made to reach its own network.
+fn is_public_host(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    match IpAddr::from_str(host) {
+        Ok(ip) => is_public_ip(ip),
+        Err(_) => {
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+            host.contains('.') && host != "localhost" && !host.ends_with(".localhost")
+        }
+    }
+}
+
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "this network", and the shared address space of carrier-grade NATs
+        || a == 0
+        || (a == 100 && (64..128).contains(&b)))
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // unique local (fc00::/7) and link-local (fe80::/10) addresses
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80)
+}
+
+/// A webhook host, along with the address it was resolved to (or `None` behind a proxy, which resolves hosts itself).
+type ResolvedHost = Option<(String, SocketAddr)>;
+
+/// The clients webhooks are notified with (see [webhook_client]), so that their connections are pooled.
+static WEBHOOK_CLIENTS: OnceLock<Mutex<HashMap<ResolvedHost, Client>>> = OnceLock::new();
+
+/// Returns the client to notify a webhook with, which connects to the address its host was resolved to
+/// once checked to be public (so that the host can't resolve to a local address by the time it's connected to),
+/// and doesn't follow redirects (which could lead to a local address).
+/// Behind a proxy, the proxy resolves the host instead.
+/// Clients are shared by the notifications of a host, as long as it resolves to the same address.
+async fn webhook_client(webhook: &Url) -> Result<Client> {
+    let resolved = match proxy() {
+        Some(_) => None,
+        None => {
+            let host = webhook.host_str().context("the webhook has no host")?;
+            let port = webhook
+                .port_or_known_default()
+                .context("the webhook has no port")?;
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.trim_start_matches('[').trim_end_matches(']'), port))
+                    .await
+                    .with_context(|| format!("couldn't resolve {host}"))?
+                    .collect();
+            ensure!(
+                addrs.iter().all(|addr| is_public_ip(addr.ip())),
+                "{host} resolves to a loopback, private, or link-local address"
+            );
+            let addr = addrs
+                .first()
+                .with_context(|| format!("{host} doesn't resolve to any address"))?;
+            Some((host.to_string(), *addr))
+        }
+    };
+
+    let mut clients = WEBHOOK_CLIENTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap();
+    if let Some(client) = clients.get(&resolved) {
+        return Ok(client.clone());
+    }
+    let mut builder = http_client_builder().redirect(redirect::Policy::none());
+    if let Some((host, addr)) = &resolved {
+        builder = builder.resolve(host, *addr);
+    }
+    let client = builder.build()?;
+    clients.insert(resolved, client.clone());
+    Ok(client)
+}
+
+async fn send_notification(
+    webhook: &str,
+    secret: Option<&str>,
+    notification: &Notification,
+) -> Result<()> {
+    let url = Url::parse(webhook)?;
+    ensure!(
+        url.host_str().is_some_and(is_public_host),
+        "the webhook can't target a loopback, private, or link-local address"
+    );
+    let body = serde_json::to_string(notification)?;
+    let mut request = webhook_client(&url)
+        .await?
+        .post(url)
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT))
+        .header(CONTENT_TYPE, "application/json");
+    if let Some(secret) = secret {
+        let timestamp = now();
+        let signature = sign_notification(secret, timestamp, &body);
+        request = request
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(SIGNATURE_HEADER, format!("sha256={signature}"));
+    }
+    let response = request.body(body).send().await?.error_for_status()?;
+    ensure!(
+        !response.status().is_redirection(),
+        "the webhook redirected the notification, which isn't followed"
+    );
+    Ok(())
+}
+
+/// Registers the policy of a zkapp with the orchestrator, signed with the deployer key committed in the zkapp.
+/// A webhook secret is only sent to an orchestrator reached over HTTPS (or Tor, or on the same machine).
+pub async fn register_policy(
+    address: &str,
+    vk_hash: &[u8; 32],
+    policy: ZkappPolicy,
+    deployer: &Keypair,
+) -> Result<()> {
+    if policy.webhook_secret.is_some() {
+        let url = Url::parse(address).context("the orchestrator address is not a valid URL")?;
+        let host = url.host_str().unwrap_or_default();
+        ensure!(
+            url.scheme() == "https"
+                || host.ends_with(".onion")
+                || IpAddr::from_str(host.trim_start_matches('[').trim_end_matches(']'))
+                    .is_ok_and(|ip| ip.is_loopback()),
+            "the webhook secret can only be sent to the orchestrator over HTTPS (or Tor)"
+        );
+    }
+    let registration = ZkappRegistration::sign(vk_hash, policy, deployer)?;
+    OrchestratorClient::new(address)
+        .register_zkapp(&registration)
+        .await
+        .context("zkapp registration failed")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_serialization() {
+        let policy = ZkappPolicy {
+            webhook: Some("https://example.com/zkapp".to_string()),
+            max_withdrawal: Some(Amount::from_sat(1000)),
+            allowed_recipients: None,
+            webhook_secret: None,
+            anomaly: None,
+        };
+        let json = serde_json::to_string(&policy).unwrap();
+        assert!(json.contains("\"max_withdrawal\":1000"));
+        assert_eq!(serde_json::from_str::<ZkappPolicy>(&json).unwrap(), policy);
+
+        // all fields are optional
+        assert_eq!(
+            serde_json::from_str::<ZkappPolicy>("{}").unwrap(),
+            ZkappPolicy::default()
+        );
+    }
+
+    #[test]
+    fn test_validate_webhook() {
+        let mut policy = ZkappPolicy {
+            webhook: Some("ftp://example.com".to_string()),
+            ..Default::default()
+        };
+        assert!(policy.validate().is_err());
+
+        policy.webhook = Some("http://example.com:9000/hook".to_string());
+        assert!(policy.validate().is_ok());
+
+        // webhooks can't target the network of the orchestrator
+        for webhook in [
+            "http://127.0.0.1:9000/hook",
+            "http://localhost/hook",
+            "http://10.0.0.1/hook",
+            "http://192.168.1.1/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://100.64.0.1/hook",
+            "http://0.0.0.0/hook",
+            "http://[::1]/hook",
+            "http://[fe80::1]/hook",
+            "http://[fd00::1]/hook",
+            "http://[::ffff:127.0.0.1]/hook",
+            "http://intranet/hook",
+        ] {
+            policy.webhook = Some(webhook.to_string());
+            assert!(policy.validate().is_err(), "{webhook}");
+        }
+        policy.webhook = Some("https://93.184.216.34/hook".to_string());
+        assert!(policy.validate().is_ok());
+
+        policy.allowed_recipients = Some(vec![]);
+        assert!(policy.validate().is_err());
+
+        let policy = ZkappPolicy {
+            webhook_secret: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert!(policy.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_client() {
+        let client = |webhook: &str| webhook_client(&Url::parse(webhook).unwrap());
+        assert!(client("http://127.0.0.1:9000/hook").await.is_err());
+
+        // the client of a host is shared by its notifications
+        client("https://93.184.216.34/hook").await.unwrap();
+        client("https://93.184.216.34/other").await.unwrap();
+        let clients = WEBHOOK_CLIENTS.get().unwrap().lock().unwrap();
+        let resolved = |addr: &str| Some(("93.184.216.34".to_string(), addr.parse().unwrap()));
+        assert!(clients.contains_key(&resolved("93.184.216.34:443")));
+        assert!(!clients.contains_key(&resolved("93.184.216.34:80")));
+    }
+
+    #[test]
+    fn test_registration_signature() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_seckey_slice(&secp, &[3; 32]).unwrap();
+        let policy = ZkappPolicy {
+            max_withdrawal: Some(Amount::from_sat(1000)),
+            ..Default::default()
+        };
+        let registration = ZkappRegistration::sign(&[7; 32], policy, &keypair).unwrap();
+        assert_eq!(
+            registration.verify().unwrap(),
+            ([7; 32], keypair.x_only_public_key().0)
+        );
+
+        // the signature covers the policy, the zkapp, and the deployer key
+        let mut tampered = registration.clone();
+        tampered.policy.max_withdrawal = None;
+        assert!(tampered.verify().is_err());
+        let mut tampered = registration.clone();
+        tampered.vk_hash = hex::encode([8; 32]);
+        assert!(tampered.verify().is_err());
+        let other = Keypair::from_seckey_slice(&secp, &[4; 32]).unwrap();
+        let mut tampered = registration;
+        tampered.deployer = hex::encode(other.x_only_public_key().0.serialize());
+        assert!(tampered.verify().is_err());
+    }
+
+    #[test]
+    fn test_sign_notification() {
+        assert_eq!(
+            hmac_sha256(b"key", b"The quick brown fox jumps over the lazy dog"),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+        assert_eq!(
+            sign_notification("key", 1700000000, "{}"),
+            hmac_sha256(b"key", b"1700000000.{}")
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Scheduling of the requests waiting for a signing session.
+//!
+//! The orchestrator can limit how many requests it handles at once (see [RequestQueue]), in which case the requests
+//! coming in while it's busy are queued. Every time a request is done, the next one is picked following the
+//! [Scheduling] policy: in the order they came in, by the priority fee they pay (what their transaction pays
+//! to the zkBitcoin fund on top of what the committee's fee schedule asks for), or by their deadline.
+//! Requests whose deadline (see [crate::bob_request::BobRequest::deadline]) passes while they're queued are dropped.
+
+use std::{sync::Mutex, time::Duration};
+
+use anyhow::{bail, ensure, Result};
+use bitcoin::Amount;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use super::storage::now;
+
+/// How the next request to handle is picked among the queued ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scheduling {
+    /// The first request to come in.
+    #[default]
+    Fifo,
+    /// The request paying the highest priority fee (then the first to come in).
+    Fee,
+    /// The request with the earliest deadline (then the first to come in, requests without a deadline going last).
+    Deadline,
+}
+
+/// What a request is scheduled by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Priority {
+    /// What the request pays on top of the fee the committee asks for.
+    pub fee: Amount,
+
+    /// When (as a UNIX timestamp) the request stops being worth handling, if ever.
+    pub deadline: Option<u64>,
+}
+
+/// A queued request, which is woken up when it's its turn.
+struct Waiter {
+    seq: u64,
+    priority: Priority,
+    wake: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    running: usize,
+    next_seq: u64,
+    waiting: Vec<Waiter>,
+}
+
+/// Limits how many requests are handled at once, and queues the other ones.
+pub struct RequestQueue {
+    /// How the next request to handle is picked.
+    pub scheduling: Scheduling,
+
+    /// How many requests are handled at once.
+    pub max_concurrent: usize,
+
+    state: Mutex<QueueState>,
+}
+
+/// The right to handle a request, given back to the queue when dropped.
+pub struct Slot<'a> {
+    queue: &'a RequestQueue,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.queue.release();
+    }
+}
+
+impl RequestQueue {
+    pub fn new(scheduling: Scheduling, max_concurrent: usize) -> Self {
+        Self {
+            scheduling,
+            max_concurrent: max_concurrent.max(1),
+            state: Mutex::new(QueueState::default()),
+        }
+    }
+
+    /// The number of requests waiting for their turn.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().waiting.len()
+    }
+
+    /// Whether no request is waiting for its turn.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Waits for the turn of a request, unless its deadline passes first.
+    pub async fn admit(&self, priority: Priority) -> Result<Slot<'_>> {
+        if let Some(deadline) = priority.deadline {
+            ensure!(now() < deadline, "the deadline of the request passed");
+        }
+
+        let (seq, mut woken) = {
+            let mut state = self.state.lock().unwrap();
+            if state.running < self.max_concurrent && state.waiting.is_empty() {
+                state.running += 1;
+                return Ok(Slot { queue: self });
+            }
+            let (wake, woken) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter {
+                seq,
+                priority,
+                wake,
+            });
+            (seq, woken)
+        };
+
+        let Some(deadline) = priority.deadline else {
+            woken.await?;
+            return Ok(Slot { queue: self });
+        };
+        let timeout = Duration::from_secs(deadline.saturating_sub(now()));
+        if tokio::time::timeout(timeout, &mut woken).await.is_ok() {
+            return Ok(Slot { queue: self });
+        }
+
+        // the request might have been woken up right as its deadline passed, in which case it's its turn anyway
+        let mut state = self.state.lock().unwrap();
+        match state.waiting.iter().position(|waiter| waiter.seq == seq) {
+            Some(idx) => {
+                state.waiting.remove(idx);
+                bail!("the deadline of the request passed while it was queued");
+            }
+            None => Ok(Slot { queue: self }),
+        }
+    }
+
+    /// Hands the slot of a request that's done over to the next queued one.
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(idx) = self.next(&state.waiting) {
+            let waiter = state.waiting.remove(idx);
+            // the request might have been given up on while queued
+            if waiter.wake.send(()).is_ok() {
+                return;
+            }
+        }
+        state.running -= 1;
+    }
+
+    /// The position of the next request to handle.
+    fn next(&self, waiting: &[Waiter]) -> Option<usize> {
+        let next = match self.scheduling {
+            Scheduling::Fifo => waiting
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, waiter)| waiter.seq),
+            Scheduling::Fee => waiting
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, waiter)| (std::cmp::Reverse(waiter.priority.fee), waiter.seq)),
+            Scheduling::Deadline => waiting.iter().enumerate().min_by_key(|(_, waiter)| {
+                (waiter.priority.deadline.unwrap_or(u64::MAX), waiter.seq)
+            }),
+        };
+        next.map(|(idx, _)| idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// Queues requests behind a running one, and returns the order they're handled in.
+    async fn order(scheduling: Scheduling, priorities: Vec<Priority>) -> Vec<usize> {
+        let queue = Arc::new(RequestQueue::new(scheduling, 1));
+        let running = queue.admit(Priority::default()).await.unwrap();
+
+        let (done, mut order) = tokio::sync::mpsc::unbounded_channel();
+        for (i, priority) in priorities.into_iter().enumerate() {
+            let queue = queue.clone();
+            let done = done.clone();
+            tokio::spawn(async move {
+                let _slot = queue.admit(priority).await.unwrap();
+                done.send(i).unwrap();
+            });
+            // wait for the request to be queued
+            while queue.len() <= i {
+                tokio::task::yield_now().await;
+            }
+        }
+        drop(done);
+        drop(running);
+
+        let mut handled = vec![];
+        while let Some(i) = order.recv().await {
+            handled.push(i);
+        }
+        handled
+    }
+
+    #[tokio::test]
+    async fn test_scheduling() {
+        let later = now() + 3600;
+        let priorities = vec![
+            Priority {
+                fee: Amount::from_sat(100),
+                deadline: None,
+            },
+            Priority {
+                fee: Amount::from_sat(1_000),
+                deadline: Some(later + 10),
+            },
+            Priority {
+                fee: Amount::ZERO,
+                deadline: Some(later),
+            },
+        ];
+        assert_eq!(
+            order(Scheduling::Fifo, priorities.clone()).await,
+            vec![0, 1, 2]
+        );
+        assert_eq!(
+            order(Scheduling::Fee, priorities.clone()).await,
+            vec![1, 0, 2]
+        );
+        assert_eq!(order(Scheduling::Deadline, priorities).await, vec![2, 1, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_deadline() {
+        let queue = RequestQueue::new(Scheduling::Fifo, 1);
+        let expired = Priority {
+            deadline: Some(now() - 1),
+            ..Default::default()
+        };
+        assert!(queue.admit(expired).await.is_err());
+
+        // a request whose deadline passes while queued is dropped
+        let running = queue.admit(Priority::default()).await.unwrap();
+        let soon = Priority {
+            deadline: Some(now() + 1),
+            ..Default::default()
+        };
+        assert!(queue.admit(soon).await.is_err());
+        assert!(queue.is_empty());
+
+        // and the queue keeps going
+        drop(running);
+        let _slot = queue.admit(Priority::default()).await.unwrap();
+    }
+}
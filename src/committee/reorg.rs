@@ -0,0 +1,299 @@
+//! Detection of chain reorganizations affecting the signing sessions of the orchestrator.
+//!
+//! A signing session spends a zkapp UTXO that is usually confirmed when the session starts.
+//! If the block confirming the deployment of the zkapp is reorged out while the committee signs,
+//! the signed spend might end up referring to an output that never makes it back into the chain.
+//! The [ReorgMonitor] follows a bitcoind node, aborts the sessions whose zkapp UTXO lost confirmations,
+//! and holds new sessions back until the chain settles.
+
+use std::{collections::HashMap, fmt, sync::Mutex, time::Duration};
+
+use anyhow::Result;
+use bitcoin::{BlockHash, OutPoint};
+use log::{error, info, warn};
+use tokio::sync::{broadcast, oneshot, watch};
+
+use crate::json_rpc_stuff::{get_block_count, get_block_hash, get_tx_out_confirmations, RpcCtx};
+
+//
+// Constants
+//
+
+/// The number of consecutive polls without any reorg after which the chain is considered settled.
+const SETTLE_POLLS: usize = 3;
+
+/// The number of aborted sessions kept for subscribers that are lagging behind.
+const REORGED_CAPACITY: usize = 64;
+
+//
+// Monitor
+//
+
+/// Why a signing session was aborted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reorged {
+    /// The zkapp UTXO spent by the session.
+    pub outpoint: OutPoint,
+
+    /// Its number of confirmations after the reorg (`None` if it's no longer in the UTXO set).
+    pub confirmations: Option<u32>,
+}
+
+impl fmt::Display for Reorged {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.confirmations {
+            Some(confirmations) => write!(
+                f,
+                "zkapp {} went back to {confirmations} confirmations in a reorg",
+                self.outpoint
+            ),
+            None => write!(f, "zkapp {} was reorged out of the chain", self.outpoint),
+        }
+    }
+}
+
+/// A signing session in flight.
+struct TrackedSession {
+    outpoint: OutPoint,
+
+    /// The highest number of confirmations seen for the zkapp UTXO since the session started.
+    confirmations: Option<u32>,
+
+    /// Taken when the session is aborted.
+    abort: Option<oneshot::Sender<Reorged>>,
+}
+
+#[derive(Default)]
+struct MonitorState {
+    /// The height and hash of the tip at the last poll.
+    tip: Option<(u64, BlockHash)>,
+
+    sessions: HashMap<u64, TrackedSession>,
+
+    next_id: u64,
+
+    /// The number of consecutive polls without any reorg, since the last one.
+    stable_polls: usize,
+}
+
+/// Follows the chain of a bitcoind node to detect reorgs affecting signing sessions.
+pub struct ReorgMonitor {
+    rpc_ctx: RpcCtx,
+    poll_interval: Duration,
+    state: Mutex<MonitorState>,
+
+    /// Whether the chain settled since the last reorg.
+    settled: watch::Sender<bool>,
+
+    /// Where the sessions aborted are sent (see [ReorgMonitor::subscribe]).
+    reorged: broadcast::Sender<Reorged>,
+}
+
+/// A signing session tracked by the [ReorgMonitor], until dropped.
+pub struct SessionGuard<'a> {
+    monitor: &'a ReorgMonitor,
+    id: u64,
+
+    /// The number of confirmations of the zkapp UTXO when the session started.
+    pub confirmations: Option<u32>,
+
+    aborted: oneshot::Receiver<Reorged>,
+}
+
+impl SessionGuard<'_> {
+    /// Resolves once the session must be aborted because of a reorg (if ever).
+    pub async fn aborted(&mut self) -> Reorged {
+        match (&mut self.aborted).await {
+            Ok(reorged) => reorged,
+            // the monitor only drops the sender when the session is dropped
+            Err(_) => std::future::pending().await,
+        }
+    }
+}
+
+impl Drop for SessionGuard<'_> {
+    fn drop(&mut self) {
+        self.monitor.state.lock().unwrap().sessions.remove(&self.id);
+    }
+}
+
+/// Returns true if a UTXO lost confirmations, which can only happen in a reorg.
+/// (`None` means that the UTXO is not in the UTXO set, which is less than any number of confirmations.)
+fn lost_confirmations(seen: Option<u32>, now: Option<u32>) -> bool {
+    now < seen
+}
+
+impl ReorgMonitor {
+    pub fn new(rpc_ctx: RpcCtx, poll_interval: Duration) -> Self {
+        let (settled, _) = watch::channel(true);
+        let (reorged, _) = broadcast::channel(REORGED_CAPACITY);
+        Self {
+            rpc_ctx,
+            poll_interval,
+            state: Mutex::default(),
+            settled,
+            reorged,
+        }
+    }
+
+    /// Subscribes to the signing sessions aborted because of reorgs.
+    pub fn subscribe(&self) -> broadcast::Receiver<Reorged> {
+        self.reorged.subscribe()
+    }
+
+    /// The node being followed.
+    pub fn rpc_ctx(&self) -> &RpcCtx {
+        &self.rpc_ctx
+    }
+
+    /// Returns false if a reorg happened recently, and the chain hasn't settled yet.
+    pub fn is_settled(&self) -> bool {
+        *self.settled.borrow()
+    }
+
+    /// Returns the number of confirmations of a zkapp UTXO (`None` if it's not in the UTXO set).
+    pub async fn confirmations(&self, outpoint: OutPoint) -> Result<Option<u32>> {
+        get_tx_out_confirmations(&self.rpc_ctx, outpoint).await
+    }
+
+    /// Waits for the chain to settle (if a reorg happened recently),
+    /// then tracks a signing session spending the given zkapp UTXO until the returned guard is dropped.
+    pub async fn track(&self, outpoint: OutPoint) -> Result<SessionGuard<'_>> {
+        if !self.is_settled() {
+            info!("- waiting for the chain to settle before signing a spend of zkapp {outpoint}");
+        }
+        self.settled
+            .subscribe()
+            .wait_for(|settled| *settled)
+            .await?;
+
+        let confirmations = get_tx_out_confirmations(&self.rpc_ctx, outpoint).await?;
+        let (abort, aborted) = oneshot::channel();
+
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.sessions.insert(
+            id,
+            TrackedSession {
+                outpoint,
+                confirmations,
+                abort: Some(abort),
+            },
+        );
+
+        Ok(SessionGuard {
+            monitor: self,
+            id,
+            confirmations,
+            aborted,
+        })
+    }
+
+    /// Polls the node forever.
+    pub async fn run(&self) {
+        info!(
+            "- monitoring the chain for reorgs every {} seconds",
+            self.poll_interval.as_secs()
+        );
+        let mut interval = tokio::time::interval(self.poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(err) = self.poll().await {
+                error!("couldn't check the chain for reorgs: {err}");
+            }
+        }
+    }
+
+    /// Checks whether the tip or any zkapp UTXO of a session in flight was reorged,
+    /// and aborts the affected sessions.
+    async fn poll(&self) -> Result<()> {
+        // has the previous tip left the chain?
+        let height = get_block_count(&self.rpc_ctx).await?;
+        let hash = get_block_hash(&self.rpc_ctx, height).await?;
+        let prev_tip = self.state.lock().unwrap().tip;
+        let mut reorg = false;
+        if let Some((prev_height, prev_hash)) = prev_tip {
+            if prev_hash != hash
+                && (prev_height > height
+                    || get_block_hash(&self.rpc_ctx, prev_height).await? != prev_hash)
+            {
+                warn!("- reorg detected: block {prev_hash} at height {prev_height} left the chain");
+                reorg = true;
+            }
+        }
+
+        // have the zkapps being spent lost confirmations?
+        let tracked = self
+            .state
+            .lock()
+            .unwrap()
+            .sessions
+            .iter()
+            .map(|(id, session)| (*id, session.outpoint))
+            .collect::<Vec<_>>();
+        let mut current = Vec::with_capacity(tracked.len());
+        for (id, outpoint) in tracked {
+            let confirmations = get_tx_out_confirmations(&self.rpc_ctx, outpoint).await?;
+            current.push((id, confirmations));
+        }
+
+        let mut state = self.state.lock().unwrap();
+        for (id, confirmations) in current {
+            // the session might have completed (or been aborted) in the meantime
+            let Some(session) = state
+                .sessions
+                .get_mut(&id)
+                .filter(|session| session.abort.is_some())
+            else {
+                continue;
+            };
+
+            if lost_confirmations(session.confirmations, confirmations) {
+                let reorged = Reorged {
+                    outpoint: session.outpoint,
+                    confirmations,
+                };
+                warn!("- {reorged}, aborting its signing session");
+                if let Some(abort) = session.abort.take() {
+                    let _ = abort.send(reorged);
+                }
+                // there might be no subscriber
+                let _ = self.reorged.send(reorged);
+                reorg = true;
+            } else {
+                session.confirmations = confirmations;
+            }
+        }
+
+        // new sessions wait until no reorg has been seen for a few polls
+        state.tip = Some((height, hash));
+        if reorg {
+            state.stable_polls = 0;
+            self.settled.send_replace(false);
+        } else if !self.is_settled() {
+            state.stable_polls += 1;
+            if state.stable_polls >= SETTLE_POLLS {
+                info!("- the chain settled after the reorg");
+                self.settled.send_replace(true);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lost_confirmations() {
+        assert!(!lost_confirmations(None, None));
+        assert!(!lost_confirmations(None, Some(0)));
+        assert!(!lost_confirmations(Some(1), Some(2)));
+        assert!(!lost_confirmations(Some(2), Some(2)));
+        assert!(lost_confirmations(Some(2), Some(1)));
+        assert!(lost_confirmations(Some(1), None));
+    }
+}
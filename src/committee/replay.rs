@@ -0,0 +1,110 @@
+//! Protection against replayed requests.
+//!
+//! A request is bound to the zkapp UTXO it spends (see [crate::bob_request::BobRequest::outpoint]),
+//! and carries a random nonce unique to it (see [crate::bob_request::BobRequest::nonce]).
+//! Both are covered by the hash of the request, so they can't be changed without it being a new request.
+//!
+//! The orchestrator and the nodes check the binding (see [crate::bob_request::BobRequest::check_binding]),
+//! which committees can require of every request (see `replay_protection` in [super::orchestrator::CommitteeConfig]),
+//! so that a captured request can't be used against another UTXO of the zkapp once its state changed.
+//! They also reject a nonce already used by another request: the orchestrator keeps the nonces it saw in its storage
+//! (or in memory without storage, see [SeenNonces]), and nodes in memory, which also covers replicas of the orchestrator
+//! (see [super::shared]) as they share the nodes. A request sent again as is (e.g. retried) isn't a replay.
+//!
+//! Neither the proof nor the transaction commit to the nonce, so the nonce only tells requests apart:
+//! what actually prevents replays is that the proof is bound to the transaction (through its truncated txid),
+//! which spends the zkapp UTXO. Replaying a request (with its nonce or another one) can only get the same transaction
+//! signed again, which can't be broadcast once the UTXO is spent. This is why nonces can be forgotten after a while
+//! (see [DEFAULT_MAX_NONCES], and the retention of the storage).
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use anyhow::{ensure, Result};
+
+//
+// Constants
+//
+
+/// How many nonces are remembered (by default), the oldest ones being forgotten first.
+/// Requests using forgotten nonces still spend UTXOs that are gone, unless a reorg brought them back.
+pub const DEFAULT_MAX_NONCES: usize = 100_000;
+
+//
+// Data structures
+//
+
+#[derive(Default)]
+struct Nonces {
+    /// The request (by hash) each nonce was used by.
+    requests: HashMap<String, [u8; 32]>,
+    /// The nonces, oldest first.
+    order: VecDeque<String>,
+}
+
+/// The nonces of the requests seen lately, along with the requests they were used by.
+pub struct SeenNonces {
+    max_nonces: usize,
+    nonces: Mutex<Nonces>,
+}
+
+impl Default for SeenNonces {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_NONCES)
+    }
+}
+
+impl SeenNonces {
+    pub fn new(max_nonces: usize) -> Self {
+        Self {
+            max_nonces: max_nonces.max(1),
+            nonces: Mutex::new(Nonces::default()),
+        }
+    }
+
+    /// Records the nonce of a request (by hash), unless another request already used it.
+    pub fn use_nonce(&self, nonce: &str, request_hash: [u8; 32]) -> Result<()> {
+        let mut nonces = self.nonces.lock().unwrap();
+        if let Some(used_by) = nonces.requests.get(nonce) {
+            ensure!(
+                *used_by == request_hash,
+                "nonce {nonce} was already used by request {}",
+                hex::encode(used_by)
+            );
+            return Ok(());
+        }
+
+        if nonces.order.len() >= self.max_nonces {
+            if let Some(oldest) = nonces.order.pop_front() {
+                nonces.requests.remove(&oldest);
+            }
+        }
+        nonces.requests.insert(nonce.to_string(), request_hash);
+        nonces.order.push_back(nonce.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seen_nonces() {
+        let seen = SeenNonces::new(2);
+        let (first, second, third) = ("01".repeat(32), "02".repeat(32), "03".repeat(32));
+        seen.use_nonce(&first, [1; 32]).unwrap();
+
+        // the same request can be sent again, but not another one with the same nonce
+        seen.use_nonce(&first, [1; 32]).unwrap();
+        assert!(seen.use_nonce(&first, [2; 32]).is_err());
+
+        // the oldest nonces are forgotten
+        seen.use_nonce(&second, [2; 32]).unwrap();
+        seen.use_nonce(&third, [3; 32]).unwrap();
+        assert!(seen.use_nonce(&second, [1; 32]).is_err());
+        seen.use_nonce(&first, [2; 32]).unwrap();
+    }
+}
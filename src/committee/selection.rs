@@ -0,0 +1,152 @@
+//! How the orchestrator picks the `threshold` committee members that sign a request.
+//!
+//! Only a threshold of members is needed to sign, so the orchestrator only contacts that many,
+//! picked in turn ([Selection::RoundRobin]) or by how fast they answered so far ([Selection::Latency]).
+//! A member that doesn't answer in time is replaced by another one, and the session is restarted.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::{ensure, Result};
+use clap::ValueEnum;
+use frost_secp256k1_tr::Identifier;
+
+/// How long a member has to answer a round, by default.
+pub const DEFAULT_MEMBER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The weight of the last latency of a member in its average.
+const LATENCY_WEIGHT: f64 = 0.2;
+
+/// A way to pick the members signing a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Selection {
+    /// Take turns, so that every member signs as often.
+    #[default]
+    RoundRobin,
+
+    /// Prefer the members that answered the fastest so far
+    /// (members that haven't answered yet are tried first).
+    Latency,
+}
+
+/// Picks the members signing each request.
+#[derive(Debug)]
+pub struct MemberSelector {
+    selection: Selection,
+
+    /// How long a member has to answer a round before being replaced.
+    pub timeout: Duration,
+
+    /// Where the next round-robin selection starts.
+    next: AtomicUsize,
+
+    /// The average latency of each member (that answered at least once).
+    latencies: Mutex<HashMap<Identifier, Duration>>,
+}
+
+impl Default for MemberSelector {
+    fn default() -> Self {
+        Self::new(Selection::default(), DEFAULT_MEMBER_TIMEOUT)
+    }
+}
+
+impl MemberSelector {
+    pub fn new(selection: Selection, timeout: Duration) -> Self {
+        Self {
+            selection,
+            timeout,
+            next: AtomicUsize::new(0),
+            latencies: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Picks `threshold` of `members`, leaving out the `excluded` ones (which failed to answer).
+    pub fn select(
+        &self,
+        members: impl IntoIterator<Item = Identifier>,
+        threshold: usize,
+        excluded: &HashSet<Identifier>,
+    ) -> Result<Vec<Identifier>> {
+        let mut candidates: Vec<_> = members
+            .into_iter()
+            .filter(|id| !excluded.contains(id))
+            .collect();
+        ensure!(
+            candidates.len() >= threshold,
+            "only {} committee members are left to sign, but {threshold} are needed",
+            candidates.len()
+        );
+        candidates.sort();
+
+        match self.selection {
+            Selection::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed) % candidates.len();
+                candidates.rotate_left(start);
+            }
+            Selection::Latency => {
+                let latencies = self.latencies.lock().unwrap();
+                candidates.sort_by_key(|id| latencies.get(id).copied().unwrap_or_default());
+            }
+        }
+        candidates.truncate(threshold);
+        Ok(candidates)
+    }
+
+    /// Records how long a member took to answer.
+    pub fn record(&self, member: Identifier, latency: Duration) {
+        self.latencies
+            .lock()
+            .unwrap()
+            .entry(member)
+            .and_modify(|average| {
+                *average = average.mul_f64(1.0 - LATENCY_WEIGHT) + latency.mul_f64(LATENCY_WEIGHT)
+            })
+            .or_insert(latency);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[u16]) -> Vec<Identifier> {
+        ids.iter()
+            .map(|&id| Identifier::try_from(id).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let members = ids(&[1, 2, 3]);
+        let none = HashSet::new();
+
+        // round-robin takes turns
+        let selector = MemberSelector::default();
+        let first = selector.select(members.clone(), 2, &none).unwrap();
+        let second = selector.select(members.clone(), 2, &none).unwrap();
+        assert_eq!(first, ids(&[1, 2]));
+        assert_eq!(second, ids(&[2, 3]));
+
+        // and replaces the members that failed
+        let excluded = HashSet::from([ids(&[2])[0]]);
+        assert_eq!(
+            selector.select(members.clone(), 2, &excluded).unwrap(),
+            ids(&[1, 3])
+        );
+        let excluded = HashSet::from_iter(ids(&[1, 2]));
+        assert!(selector.select(members.clone(), 2, &excluded).is_err());
+
+        // latency-based selection prefers the fastest members
+        let selector = MemberSelector::new(Selection::Latency, DEFAULT_MEMBER_TIMEOUT);
+        selector.record(ids(&[1])[0], Duration::from_millis(300));
+        selector.record(ids(&[2])[0], Duration::from_millis(100));
+        selector.record(ids(&[3])[0], Duration::from_millis(200));
+        assert_eq!(selector.select(members, 2, &none).unwrap(), ids(&[2, 3]));
+    }
+}
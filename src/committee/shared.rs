@@ -0,0 +1,380 @@
+//! State shared by orchestrator replicas.
+//!
+//! An orchestrator keeps the state of the signing sessions it runs in memory, which is enough when it runs alone.
+//! Several replicas can serve requests behind a load balancer if they share that state through a [SharedState]
+//! (e.g. [RedisState]): a replica claims the zkapp input it signs for (so that no other replica runs a session
+//! for it at the same time, see [SharedState::claim_session]), sees when another replica was halted by a watchtower,
+//! and shares the queue of requests held until an operator approves them (see [super::anomaly]).
+//!
+//! Everything else is still up to each replica, including its storage (see [super::storage]) and logs.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::{ensure, Context, Result};
+use async_trait::async_trait;
+use bitcoin::Txid;
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use crate::watchtower::Alert;
+
+use super::{
+    anomaly::{evictions, HeldRequest, Holds, HOLD_EXPIRY},
+    orchestrator::SessionStatus,
+    storage::now,
+};
+
+//
+// Constants
+//
+
+/// How long a replica's claim on a signing session lasts if it isn't refreshed (e.g. because the replica crashed).
+pub const SESSION_TTL: Duration = Duration::from_secs(5 * 60);
+
+//
+// Trait
+//
+
+/// Where the state of the signing sessions is shared between orchestrator replicas.
+#[async_trait]
+pub trait SharedState: Send + Sync {
+    /// A description of where the state is kept, for logs.
+    fn name(&self) -> String;
+
+    /// Claims the zkapp input of a transaction for a signing session, for `ttl`.
+    /// Returns false if a session is already running for it.
+    async fn claim_session(&self, session: &SessionStatus, ttl: Duration) -> Result<bool>;
+
+    /// Updates a claimed session (and extends the claim for `ttl`), unless the claim is gone.
+    async fn update_session(&self, session: &SessionStatus, ttl: Duration) -> Result<()>;
+
+    /// Releases the claim on a session.
+    async fn release_session(&self, txid: Txid, zkapp_input: usize) -> Result<()>;
+
+    /// The signing sessions running on all the replicas.
+    async fn sessions(&self) -> Result<Vec<SessionStatus>>;
+
+    /// Halts all the replicas on a watchtower's alert (see [crate::watchtower]).
+    async fn halt(&self, alert: &Alert) -> Result<()>;
+
+    /// The alert the replicas were halted with, if any.
+    async fn halted(&self) -> Result<Option<Alert>>;
+
+    /// Holds a request until an operator approves it.
+    async fn hold(&self, held: &HeldRequest) -> Result<()>;
+
+    /// The requests held.
+    async fn held(&self) -> Result<Vec<HeldRequest>>;
+
+    /// Approves a held request, so that it's signed the next time it's sent (to any replica).
+    async fn approve(&self, request_hash: &str) -> Result<HeldRequest>;
+
+    /// Whether a request was approved (the approval being used up).
+    async fn take_approval(&self, request_hash: &str) -> Result<bool>;
+}
+
+//
+// In memory
+//
+
+/// State shared by orchestrators running in the same process (mostly useful for tests).
+#[derive(Default)]
+pub struct MemoryState {
+    sessions: Mutex<HashMap<(Txid, usize), (SessionStatus, Instant)>>,
+    halted: Mutex<Option<Alert>>,
+    holds: Holds,
+}
+
+#[async_trait]
+impl SharedState for MemoryState {
+    fn name(&self) -> String {
+        "memory".to_string()
+    }
+
+    async fn claim_session(&self, session: &SessionStatus, ttl: Duration) -> Result<bool> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let key = (session.txid, session.zkapp_input);
+        if let Some((_, expires_at)) = sessions.get(&key) {
+            if Instant::now() < *expires_at {
+                return Ok(false);
+            }
+        }
+        sessions.insert(key, (session.clone(), Instant::now() + ttl));
+        Ok(true)
+    }
+
+    async fn update_session(&self, session: &SessionStatus, ttl: Duration) -> Result<()> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(claim) = sessions.get_mut(&(session.txid, session.zkapp_input)) {
+            *claim = (session.clone(), Instant::now() + ttl);
+        }
+        Ok(())
+    }
+
+    async fn release_session(&self, txid: Txid, zkapp_input: usize) -> Result<()> {
+        self.sessions.lock().unwrap().remove(&(txid, zkapp_input));
+        Ok(())
+    }
+
+    async fn sessions(&self) -> Result<Vec<SessionStatus>> {
+        let now = Instant::now();
+        Ok(self
+            .sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|(_, expires_at)| now < *expires_at)
+            .map(|(session, _)| session.clone())
+            .collect())
+    }
+
+    async fn halt(&self, alert: &Alert) -> Result<()> {
+        *self.halted.lock().unwrap() = Some(alert.clone());
+        Ok(())
+    }
+
+    async fn halted(&self) -> Result<Option<Alert>> {
+        Ok(self.halted.lock().unwrap().clone())
+    }
+
+    async fn hold(&self, held: &HeldRequest) -> Result<()> {
+        self.holds.hold(held.clone());
+        Ok(())
+    }
+
+    async fn held(&self) -> Result<Vec<HeldRequest>> {
+        Ok(self.holds.list())
+    }
+
+    async fn approve(&self, request_hash: &str) -> Result<HeldRequest> {
+        self.holds.approve(request_hash)
+    }
+
+    async fn take_approval(&self, request_hash: &str) -> Result<bool> {
+        Ok(self.holds.take_approval(request_hash))
+    }
+}
+
+//
+// Redis
+//
+
+/// State shared through a Redis server, under a namespace (so that several committees can share a server).
+pub struct RedisState {
+    url: String,
+    namespace: String,
+    conn: ConnectionManager,
+}
+
+impl RedisState {
+    /// Connects to the Redis server at `url` (e.g. `redis://127.0.0.1:6379`).
+    pub async fn connect(url: &str, namespace: &str) -> Result<Self> {
+        let client = redis::Client::open(url).context("invalid Redis URL")?;
+        let conn = ConnectionManager::new(client)
+            .await
+            .with_context(|| format!("couldn't connect to Redis at {url}"))?;
+        Ok(Self {
+            url: url.to_string(),
+            namespace: namespace.to_string(),
+            conn,
+        })
+    }
+
+    fn key(&self, suffix: &str) -> String {
+        format!("{}:{suffix}", self.namespace)
+    }
+
+    fn session_key(&self, txid: Txid, zkapp_input: usize) -> String {
+        self.key(&format!("session:{txid}:{zkapp_input}"))
+    }
+
+    /// Sets a session with the given condition (`NX` to create it, `XX` to update it).
+    async fn set_session(
+        &self,
+        session: &SessionStatus,
+        ttl: Duration,
+        condition: &str,
+    ) -> Result<bool> {
+        let set: Option<String> = redis::cmd("SET")
+            .arg(self.session_key(session.txid, session.zkapp_input))
+            .arg(serde_json::to_string(session)?)
+            .arg(condition)
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(set.is_some())
+    }
+
+    /// The requests held, expired ones included.
+    async fn all_held(&self) -> Result<Vec<HeldRequest>> {
+        let held: Vec<String> = self.conn.clone().hvals(self.key("held")).await?;
+        held.iter()
+            .map(|held| serde_json::from_str(held).context("malformed held request"))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl SharedState for RedisState {
+    fn name(&self) -> String {
+        // the URL might contain a password
+        let host = self.url.rsplit('@').next().unwrap_or_default();
+        format!("Redis at {host}")
+    }
+
+    async fn claim_session(&self, session: &SessionStatus, ttl: Duration) -> Result<bool> {
+        self.set_session(session, ttl, "NX").await
+    }
+
+    async fn update_session(&self, session: &SessionStatus, ttl: Duration) -> Result<()> {
+        self.set_session(session, ttl, "XX").await?;
+        Ok(())
+    }
+
+    async fn release_session(&self, txid: Txid, zkapp_input: usize) -> Result<()> {
+        let _: () = self
+            .conn
+            .clone()
+            .del(self.session_key(txid, zkapp_input))
+            .await?;
+        Ok(())
+    }
+
+    async fn sessions(&self) -> Result<Vec<SessionStatus>> {
+        let mut conn = self.conn.clone();
+        let mut keys: Vec<String> = vec![];
+        let mut cursor = 0u64;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(self.key("session:*"))
+                .query_async(&mut conn)
+                .await?;
+            keys.extend(batch);
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // sessions might have ended since they were listed
+        let sessions: Vec<Option<String>> =
+            redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+        sessions
+            .into_iter()
+            .flatten()
+            .map(|session| serde_json::from_str(&session).context("malformed session"))
+            .collect()
+    }
+
+    async fn halt(&self, alert: &Alert) -> Result<()> {
+        let _: () = self
+            .conn
+            .clone()
+            .set(self.key("halted"), serde_json::to_string(alert)?)
+            .await?;
+        Ok(())
+    }
+
+    async fn halted(&self) -> Result<Option<Alert>> {
+        let alert: Option<String> = self.conn.clone().get(self.key("halted")).await?;
+        alert
+            .map(|alert| serde_json::from_str(&alert).context("malformed alert"))
+            .transpose()
+    }
+
+    async fn hold(&self, held: &HeldRequest) -> Result<()> {
+        // make room for the request, as in [Holds::hold]
+        let evicted = evictions(&self.all_held().await?, held.held_at);
+        if !evicted.is_empty() {
+            let _: () = self.conn.clone().hdel(self.key("held"), evicted).await?;
+        }
+        let _: () = self
+            .conn
+            .clone()
+            .hset(
+                self.key("held"),
+                &held.request_hash,
+                serde_json::to_string(held)?,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn held(&self) -> Result<Vec<HeldRequest>> {
+        let now = now();
+        Ok(self
+            .all_held()
+            .await?
+            .into_iter()
+            .filter(|held| held.held_at + HOLD_EXPIRY > now)
+            .collect())
+    }
+
+    async fn approve(&self, request_hash: &str) -> Result<HeldRequest> {
+        let mut conn = self.conn.clone();
+        let held: Option<String> = conn.hget(self.key("held"), request_hash).await?;
+        let held = held.with_context(|| format!("request {request_hash} is not held"))?;
+        let held: HeldRequest = serde_json::from_str(&held).context("malformed held request")?;
+        // only one operator gets to approve it
+        let removed: usize = conn.hdel(self.key("held"), request_hash).await?;
+        ensure!(
+            removed == 1 && held.held_at + HOLD_EXPIRY > now(),
+            "request {request_hash} is not held"
+        );
+        let _: () = conn.sadd(self.key("approved"), request_hash).await?;
+        Ok(held)
+    }
+
+    async fn take_approval(&self, request_hash: &str) -> Result<bool> {
+        let removed: usize = self
+            .conn
+            .clone()
+            .srem(self.key("approved"), request_hash)
+            .await?;
+        Ok(removed == 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+    use crate::committee::orchestrator::Round;
+
+    #[tokio::test]
+    async fn test_session_claims() {
+        let state = MemoryState::default();
+        let mut session = SessionStatus {
+            txid: Txid::all_zeros(),
+            zkapp_input: 0,
+            round: Round::Validating,
+        };
+        assert!(state.claim_session(&session, SESSION_TTL).await.unwrap());
+        assert!(!state.claim_session(&session, SESSION_TTL).await.unwrap());
+
+        session.round = Round::Round1;
+        state.update_session(&session, SESSION_TTL).await.unwrap();
+        assert_eq!(state.sessions().await.unwrap()[0].round, Round::Round1);
+
+        // once released (or expired), the input can be claimed again
+        state.release_session(session.txid, 0).await.unwrap();
+        assert!(state.sessions().await.unwrap().is_empty());
+        assert!(state.claim_session(&session, Duration::ZERO).await.unwrap());
+        assert!(state.claim_session(&session, SESSION_TTL).await.unwrap());
+
+        // updates don't bring released sessions back
+        state.release_session(session.txid, 0).await.unwrap();
+        state.update_session(&session, SESSION_TTL).await.unwrap();
+        assert!(state.sessions().await.unwrap().is_empty());
+    }
+}
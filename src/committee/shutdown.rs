@@ -0,0 +1,126 @@
+//! Graceful shutdown of orchestrators and committee nodes.
+//!
+//! When asked to terminate (SIGTERM, or Ctrl-C), a server stops taking new signing sessions
+//! (rejecting them with an error saying so), waits for the ones in progress to finish (for a while),
+//! and then closes its connections, instead of dying in the middle of a round.
+
+use std::time::Duration;
+
+use log::warn;
+use tokio::{sync::watch, time::Instant};
+
+/// How long (in seconds) a server waits for signing sessions in progress to finish when shutting down, by default.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: u64 = 30;
+
+/// How often a server shutting down checks on the sessions in progress.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether a server is shutting down.
+#[derive(Debug)]
+pub struct Shutdown(watch::Sender<bool>);
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self(watch::channel(false).0)
+    }
+}
+
+impl Shutdown {
+    /// Starts shutting down: no new signing sessions are taken from now on.
+    pub fn begin(&self) {
+        self.0.send_replace(true);
+    }
+
+    /// Whether the server is shutting down.
+    pub fn is_draining(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once the server starts shutting down.
+    pub async fn started(&self) {
+        let mut receiver = self.0.subscribe();
+        // the sender lives as long as `self`
+        let _ = receiver.wait_for(|draining| *draining).await;
+    }
+
+    /// Waits for the signing sessions in progress (as counted by `in_progress`) to finish, for up to `timeout`,
+    /// and returns the number of sessions that didn't.
+    pub async fn drain(&self, timeout: Duration, in_progress: impl Fn() -> usize) -> usize {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let left = in_progress();
+            if left == 0 {
+                return 0;
+            }
+            if Instant::now() >= deadline {
+                warn!("- {left} signing sessions didn't finish before shutting down");
+                return left;
+            }
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Resolves when the process is asked to terminate.
+pub async fn terminated() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+                return;
+            }
+            Err(err) => warn!("couldn't listen for SIGTERM: {err}"),
+        }
+    }
+    // Ctrl-C is all there is to listen to
+    if tokio::signal::ctrl_c().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_drain() {
+        let shutdown = Arc::new(Shutdown::default());
+        assert!(!shutdown.is_draining());
+
+        let started = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.started().await }
+        });
+        shutdown.begin();
+        assert!(shutdown.is_draining());
+        started.await.unwrap();
+
+        // sessions that finish in time are waited for
+        let sessions = Arc::new(AtomicUsize::new(2));
+        tokio::spawn({
+            let sessions = sessions.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                sessions.store(0, Ordering::SeqCst);
+            }
+        });
+        let left = shutdown
+            .drain(Duration::from_secs(5), || sessions.load(Ordering::SeqCst))
+            .await;
+        assert_eq!(left, 0);
+
+        // but not forever
+        let left = shutdown.drain(Duration::from_millis(10), || 1).await;
+        assert_eq!(left, 1);
+    }
+}
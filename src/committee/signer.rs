@@ -0,0 +1,757 @@
+//! The threshold-signing protocols a committee can sign with (see [super::orchestrator::CommitteeConfig::signer]).
+//!
+//! Committees sign with FROST by default, which takes any threshold of the members.
+//! Small committees can sign with MuSig2 (BIP 327) instead, a simpler and standardized protocol,
+//! but an n-of-n one: every member takes part in every signing session.
+//!
+//! Either way, a signing session runs in two rounds: the members commit to nonces ([SignerBackend::commit]),
+//! then produce signature shares given the commitments of everyone taking part ([SignerBackend::sign]),
+//! which the orchestrator aggregates into a Schnorr signature for the output key of the zkBitcoin address
+//! ([SignatureAggregator::aggregate]). A member signs with its [KeyShare], and the orchestrator aggregates
+//! with the [CommitteeKey], which are read from the key files of either protocol.
+//!
+//! Like any taproot internal key, the committee key is only known by its X coordinate (see BIP 340),
+//! so its Y coordinate is taken to be the even one ([even_y]). FROST committees whose key has an odd Y coordinate
+//! (e.g. out of a DKG) sign with their shares negated ([even_y_key_package]), which every member can do on its own.
+//! MuSig2 keeps track of the parity of the aggregated key by itself (see BIP 327).
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use bitcoin::key::{TapTweak, UntweakedPublicKey};
+use frost_secp256k1_tr::{Ciphersuite, Group, Identifier, Secp256K1Sha256};
+use musig2::{AggNonce, KeyAggContext, LiftedSignature, PartialSignature, PubNonce, SecNonce};
+use rand::{thread_rng, RngCore};
+use secp256k1::{schnorr, Message, Parity, PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::frost;
+
+use super::misbehavior::{Misbehavior, MisbehaviorKind};
+
+//
+// Data structures
+//
+
+/// The threshold-signing protocol of a committee.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerKind {
+    /// FROST, with any threshold of the members.
+    #[default]
+    Frost,
+
+    /// MuSig2, with all the members.
+    Musig2,
+}
+
+/// What a member commits to in round 1 (the FROST commitments keep their format).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Commitment {
+    Frost(frost_secp256k1_tr::round1::SigningCommitments),
+    Musig2(PubNonce),
+}
+
+/// The signature share a member produces in round 2.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SignatureShare {
+    Frost(frost_secp256k1_tr::round2::SignatureShare),
+    Musig2(PartialSignature),
+}
+
+impl SignatureShare {
+    /// The serialized share (as recorded in audit logs).
+    pub fn serialize(&self) -> Vec<u8> {
+        match self {
+            SignatureShare::Frost(share) => share.serialize().to_vec(),
+            SignatureShare::Musig2(share) => share.serialize().to_vec(),
+        }
+    }
+}
+
+/// The secret nonces behind a commitment, which a member keeps between the two rounds (and uses once).
+pub enum SecretNonces {
+    Frost(frost_secp256k1_tr::round1::SigningNonces),
+    Musig2(SecNonce),
+}
+
+//
+// Traits
+//
+
+/// What a member signs with.
+pub trait SignerBackend: Send + Sync {
+    /// The protocol the member signs with.
+    fn kind(&self) -> SignerKind;
+
+    /// The identifier of the member in the committee.
+    fn identifier(&self) -> Identifier;
+
+    /// The committee key (the internal key of the zkBitcoin address, with an even Y coordinate).
+    fn committee_pubkey(&self) -> Result<bitcoin::PublicKey>;
+
+    /// Commits to fresh nonces (round 1).
+    fn commit(&self) -> Result<(SecretNonces, Commitment)>;
+
+    /// Produces a signature share of `message`, given the commitments of the members taking part (round 2).
+    fn sign(
+        &self,
+        nonces: SecretNonces,
+        commitments: &BTreeMap<Identifier, Commitment>,
+        message: &[u8; 32],
+    ) -> Result<SignatureShare>;
+}
+
+/// What the orchestrator aggregates signature shares with.
+pub trait SignatureAggregator: Send + Sync {
+    /// The protocol the committee signs with.
+    fn kind(&self) -> SignerKind;
+
+    /// The committee key (the internal key of the zkBitcoin address, with an even Y coordinate).
+    fn pubkey(&self) -> Result<bitcoin::PublicKey>;
+
+    /// Whether a member holds a share of the committee key.
+    fn holds_share(&self, identifier: &Identifier) -> bool;
+
+    /// Aggregates the signature shares of `message` into a signature for the output key of the zkBitcoin address
+    /// (which is checked).
+    fn aggregate(
+        &self,
+        commitments: &BTreeMap<Identifier, Commitment>,
+        shares: &BTreeMap<Identifier, SignatureShare>,
+        message: &[u8; 32],
+    ) -> Result<schnorr::Signature>;
+}
+
+/// The key with the same X coordinate as `pubkey`, and an even Y coordinate (the one BIP 340 lifts X coordinates to).
+pub fn even_y(pubkey: bitcoin::PublicKey) -> bitcoin::PublicKey {
+    let (xonly, _) = pubkey.inner.x_only_public_key();
+    bitcoin::PublicKey::new(xonly.public_key(Parity::Even))
+}
+
+/// The output key of the zkBitcoin address of `pubkey`, which the committee signs for:
+/// the committee key tweaked without a script tree, as BIP 341 recommends.
+pub fn output_key(pubkey: bitcoin::PublicKey) -> XOnlyPublicKey {
+    let secp = Secp256k1::verification_only();
+    let (output_key, _) = UntweakedPublicKey::from(pubkey).tap_tweak(&secp, None);
+    output_key.to_inner()
+}
+
+/// Checks a signature of `message` for the output key of the zkBitcoin address of `pubkey` (see [output_key]).
+fn verify(pubkey: bitcoin::PublicKey, message: &[u8; 32], sig: &schnorr::Signature) -> Result<()> {
+    Secp256k1::verification_only()
+        .verify_schnorr(sig, &Message::from_digest(*message), &output_key(pubkey))
+        .context("the signature of the committee doesn't verify")
+}
+
+//
+// FROST
+//
+
+impl SignerBackend for frost::KeyPackage {
+    fn kind(&self) -> SignerKind {
+        SignerKind::Frost
+    }
+
+    fn identifier(&self) -> Identifier {
+        *frost::KeyPackage::identifier(self)
+    }
+
+    fn committee_pubkey(&self) -> Result<bitcoin::PublicKey> {
+        let pubkey = bitcoin::PublicKey::from_slice(&self.verifying_key().serialize())
+            .context("the committee key is not a valid public key")?;
+        Ok(even_y(pubkey))
+    }
+
+    fn commit(&self) -> Result<(SecretNonces, Commitment)> {
+        let (nonces, commitments) =
+            frost_secp256k1_tr::round1::commit(self.signing_share(), &mut thread_rng());
+        Ok((SecretNonces::Frost(nonces), Commitment::Frost(commitments)))
+    }
+
+    fn sign(
+        &self,
+        nonces: SecretNonces,
+        commitments: &BTreeMap<Identifier, Commitment>,
+        message: &[u8; 32],
+    ) -> Result<SignatureShare> {
+        let SecretNonces::Frost(nonces) = nonces else {
+            bail!("the nonces are not FROST nonces");
+        };
+        let signing_package =
+            frost_secp256k1_tr::SigningPackage::new(frost_commitments(commitments)?, message);
+        let key_package = even_y_key_package(self)?;
+        check_frost_tweak(key_package.verifying_key())?;
+        let share = frost_secp256k1_tr::round2::sign(&signing_package, &nonces, &key_package)
+            .context("error while signing")?;
+        Ok(SignatureShare::Frost(share))
+    }
+}
+
+impl SignatureAggregator for frost::PublicKeyPackage {
+    fn kind(&self) -> SignerKind {
+        SignerKind::Frost
+    }
+
+    fn pubkey(&self) -> Result<bitcoin::PublicKey> {
+        let pubkey = bitcoin::PublicKey::from_slice(&self.verifying_key().serialize())
+            .context("the committee key is not a valid public key")?;
+        Ok(even_y(pubkey))
+    }
+
+    fn holds_share(&self, identifier: &Identifier) -> bool {
+        self.verifying_shares().contains_key(identifier)
+    }
+
+    fn aggregate(
+        &self,
+        commitments: &BTreeMap<Identifier, Commitment>,
+        shares: &BTreeMap<Identifier, SignatureShare>,
+        message: &[u8; 32],
+    ) -> Result<schnorr::Signature> {
+        let shares: BTreeMap<_, _> = shares
+            .iter()
+            .map(|(id, share)| match share {
+                SignatureShare::Frost(share) => Ok((*id, *share)),
+                SignatureShare::Musig2(_) => Err(anyhow!(Misbehavior::new(
+                    *id,
+                    MisbehaviorKind::OffProtocol,
+                    "sent a MuSig2 share to a FROST committee",
+                ))),
+            })
+            .collect::<Result<_>>()?;
+        let signing_package =
+            frost_secp256k1_tr::SigningPackage::new(frost_commitments(commitments)?, message);
+        let pubkey_package = even_y_pubkey_package(self)?;
+        check_frost_tweak(pubkey_package.verifying_key())?;
+        let group_signature =
+            frost_secp256k1_tr::aggregate(&signing_package, &shares, &pubkey_package).map_err(
+                |err| match err {
+                    // FROST checks every share when the aggregate doesn't verify, to tell which member misbehaved
+                    frost_secp256k1_tr::Error::InvalidSignatureShare { culprit } => {
+                        let share = shares
+                            .get(&culprit)
+                            .map(|share| hex::encode(share.serialize()))
+                            .unwrap_or_default();
+                        anyhow!(Misbehavior::new(
+                            culprit,
+                            MisbehaviorKind::InvalidShare,
+                            format!("sent the invalid signature share {share}"),
+                        ))
+                    }
+                    err => anyhow!(err).context("failed to aggregate signatures"),
+                },
+            )?;
+        let sig = schnorr::Signature::from_slice(&group_signature.serialize()[1..])
+            .context("couldn't convert signature type")?;
+        verify(SignatureAggregator::pubkey(self)?, message, &sig)?;
+        Ok(sig)
+    }
+}
+
+/// The key package of a member, with its share negated if the committee key has an odd Y coordinate,
+/// so that it signs for the committee key with an even Y coordinate (see [even_y]).
+pub fn even_y_key_package(key_package: &frost::KeyPackage) -> Result<frost::KeyPackage> {
+    if key_package.verifying_key().serialize()[0] == 2 {
+        return Ok(key_package.clone());
+    }
+    let signing_share = SecretKey::from_slice(&key_package.signing_share().serialize())
+        .context("the signing share is not a valid secret key")?
+        .negate();
+    Ok(frost::KeyPackage::new(
+        *frost::KeyPackage::identifier(key_package),
+        frost_secp256k1_tr::keys::SigningShare::deserialize(signing_share.secret_bytes())
+            .context("couldn't negate the signing share")?,
+        frost_secp256k1_tr::keys::VerifyingShare::deserialize(negate(
+            &key_package.verifying_share().serialize(),
+        )?)
+        .context("couldn't negate the verifying share")?,
+        frost_secp256k1_tr::VerifyingKey::deserialize(negate(
+            &key_package.verifying_key().serialize(),
+        )?)
+        .context("couldn't negate the committee key")?,
+        *key_package.min_signers(),
+    ))
+}
+
+/// The public key package of a committee, with the committee key and the verifying shares negated
+/// if the committee key has an odd Y coordinate (see [even_y_key_package]).
+pub fn even_y_pubkey_package(
+    pubkey_package: &frost::PublicKeyPackage,
+) -> Result<frost::PublicKeyPackage> {
+    if pubkey_package.verifying_key().serialize()[0] == 2 {
+        return Ok(pubkey_package.clone());
+    }
+    let verifying_shares = pubkey_package
+        .verifying_shares()
+        .iter()
+        .map(|(id, share)| {
+            let share =
+                frost_secp256k1_tr::keys::VerifyingShare::deserialize(negate(&share.serialize())?)
+                    .context("couldn't negate the verifying share")?;
+            Ok((*id, share))
+        })
+        .collect::<Result<_>>()?;
+    let verifying_key = frost_secp256k1_tr::VerifyingKey::deserialize(negate(
+        &pubkey_package.verifying_key().serialize(),
+    )?)
+    .context("couldn't negate the committee key")?;
+    Ok(frost::PublicKeyPackage::new(
+        verifying_shares,
+        verifying_key,
+    ))
+}
+
+/// Checks that FROST signs for the output key of the zkBitcoin address of `verifying_key` (see [output_key]).
+/// The FROST ciphersuite applies the taproot tweak itself: to the committee key (which the challenge commits to),
+/// and to the signature shares (the tweak times the challenge being added to the signature once),
+/// so a committee that signs without it would produce signatures for the internal key, which no zkapp is locked to.
+fn check_frost_tweak(verifying_key: &frost_secp256k1_tr::VerifyingKey) -> Result<()> {
+    let tweaked = Secp256K1Sha256::tweaked_public_key(verifying_key.element());
+    let tweaked = <Secp256K1Sha256 as Ciphersuite>::Group::serialize(&tweaked);
+    let pubkey = bitcoin::PublicKey::from_slice(&verifying_key.serialize())
+        .context("the committee key is not a valid public key")?;
+    ensure!(
+        tweaked[1..] == output_key(pubkey).serialize(),
+        "FROST doesn't sign for the taproot output key of the committee"
+    );
+    Ok(())
+}
+
+/// Negates a serialized (compressed) point.
+fn negate(point: &[u8]) -> Result<[u8; 33]> {
+    let point = PublicKey::from_slice(point).context("invalid point")?;
+    Ok(point.negate(&Secp256k1::verification_only()).serialize())
+}
+
+/// The FROST commitments of the members taking part.
+fn frost_commitments(
+    commitments: &BTreeMap<Identifier, Commitment>,
+) -> Result<BTreeMap<Identifier, frost_secp256k1_tr::round1::SigningCommitments>> {
+    commitments
+        .iter()
+        .map(|(id, commitment)| match commitment {
+            Commitment::Frost(commitment) => Ok((*id, *commitment)),
+            Commitment::Musig2(_) => Err(anyhow!(Misbehavior::new(
+                *id,
+                MisbehaviorKind::OffProtocol,
+                "committed to MuSig2 nonces in a FROST committee",
+            ))),
+        })
+        .collect()
+}
+
+//
+// MuSig2
+//
+
+/// The public keys of the members of a MuSig2 committee, whose aggregate (see BIP 327) is the committee key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Musig2CommitteeKey {
+    pub pubkeys: BTreeMap<Identifier, PublicKey>,
+}
+
+impl Musig2CommitteeKey {
+    /// The aggregation of the keys of the members (in the order of their identifiers).
+    fn key_agg_ctx(&self) -> Result<KeyAggContext> {
+        KeyAggContext::new(self.pubkeys.values().copied())
+            .map_err(|err| anyhow!("couldn't aggregate the keys of the committee: {err}"))
+    }
+
+    /// The aggregation of the keys of the members, tweaked for the output key of the zkBitcoin address.
+    fn signing_ctx(&self) -> Result<KeyAggContext> {
+        self.key_agg_ctx()?
+            .with_unspendable_taproot_tweak()
+            .map_err(|err| anyhow!("couldn't tweak the committee key: {err}"))
+    }
+
+    /// The committee key (with an even Y coordinate, see [even_y]).
+    pub fn pubkey(&self) -> Result<bitcoin::PublicKey> {
+        let pubkey: PublicKey = self.key_agg_ctx()?.aggregated_pubkey();
+        Ok(even_y(bitcoin::PublicKey::new(pubkey)))
+    }
+
+    /// The public nonces of every member, in the order of their identifiers.
+    fn pubnonces(&self, commitments: &BTreeMap<Identifier, Commitment>) -> Result<Vec<PubNonce>> {
+        ensure!(
+            commitments.keys().eq(self.pubkeys.keys()),
+            "MuSig2 committees sign with all of their {} members, not {}",
+            self.pubkeys.len(),
+            commitments.len()
+        );
+        commitments
+            .iter()
+            .map(|(id, commitment)| match commitment {
+                Commitment::Musig2(pubnonce) => Ok(pubnonce.clone()),
+                Commitment::Frost(_) => Err(anyhow!(Misbehavior::new(
+                    *id,
+                    MisbehaviorKind::OffProtocol,
+                    "committed to FROST nonces in a MuSig2 committee",
+                ))),
+            })
+            .collect()
+    }
+}
+
+impl SignatureAggregator for Musig2CommitteeKey {
+    fn kind(&self) -> SignerKind {
+        SignerKind::Musig2
+    }
+
+    fn pubkey(&self) -> Result<bitcoin::PublicKey> {
+        Musig2CommitteeKey::pubkey(self)
+    }
+
+    fn holds_share(&self, identifier: &Identifier) -> bool {
+        self.pubkeys.contains_key(identifier)
+    }
+
+    fn aggregate(
+        &self,
+        commitments: &BTreeMap<Identifier, Commitment>,
+        shares: &BTreeMap<Identifier, SignatureShare>,
+        message: &[u8; 32],
+    ) -> Result<schnorr::Signature> {
+        let ctx = self.signing_ctx()?;
+        let pubnonces = self.pubnonces(commitments)?;
+        let aggnonce = AggNonce::sum(&pubnonces);
+        ensure!(
+            shares.keys().eq(self.pubkeys.keys()),
+            "MuSig2 committees sign with all of their members"
+        );
+
+        // check every share, to tell which member misbehaved (if any)
+        let mut partial_signatures = vec![];
+        for (((id, share), pubkey), pubnonce) in
+            shares.iter().zip(self.pubkeys.values()).zip(&pubnonces)
+        {
+            let SignatureShare::Musig2(share) = share else {
+                bail!(Misbehavior::new(
+                    *id,
+                    MisbehaviorKind::OffProtocol,
+                    "sent a FROST share to a MuSig2 committee",
+                ));
+            };
+            musig2::verify_partial(&ctx, *share, &aggnonce, *pubkey, pubnonce, message).map_err(
+                |err| {
+                    anyhow!(Misbehavior::new(
+                        *id,
+                        MisbehaviorKind::InvalidShare,
+                        format!(
+                            "sent the invalid signature share {} ({err})",
+                            hex::encode(share.serialize())
+                        ),
+                    ))
+                },
+            )?;
+            partial_signatures.push(*share);
+        }
+
+        let signature: LiftedSignature =
+            musig2::aggregate_partial_signatures(&ctx, &aggnonce, partial_signatures, message)
+                .map_err(|err| anyhow!("failed to aggregate signatures: {err}"))?;
+        let sig = schnorr::Signature::from_slice(&signature.serialize())
+            .context("couldn't convert signature type")?;
+        verify(self.pubkey()?, message, &sig)?;
+        Ok(sig)
+    }
+}
+
+/// The key of a member of a MuSig2 committee.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Musig2KeyShare {
+    /// The identifier of the member in the committee.
+    pub identifier: Identifier,
+
+    /// The secret key of the member.
+    pub secret_key: SecretKey,
+
+    /// The keys of the committee.
+    pub committee: Musig2CommitteeKey,
+}
+
+impl SignerBackend for Musig2KeyShare {
+    fn kind(&self) -> SignerKind {
+        SignerKind::Musig2
+    }
+
+    fn identifier(&self) -> Identifier {
+        self.identifier
+    }
+
+    fn committee_pubkey(&self) -> Result<bitcoin::PublicKey> {
+        self.committee.pubkey()
+    }
+
+    fn commit(&self) -> Result<(SecretNonces, Commitment)> {
+        let mut nonce_seed = [0u8; 32];
+        thread_rng().fill_bytes(&mut nonce_seed);
+        let aggregated_pubkey: PublicKey = self.committee.signing_ctx()?.aggregated_pubkey();
+        let secnonce = SecNonce::build(nonce_seed)
+            .with_seckey(self.secret_key)
+            .with_aggregated_pubkey(aggregated_pubkey)
+            .build();
+        let pubnonce = secnonce.public_nonce();
+        Ok((SecretNonces::Musig2(secnonce), Commitment::Musig2(pubnonce)))
+    }
+
+    fn sign(
+        &self,
+        nonces: SecretNonces,
+        commitments: &BTreeMap<Identifier, Commitment>,
+        message: &[u8; 32],
+    ) -> Result<SignatureShare> {
+        let SecretNonces::Musig2(secnonce) = nonces else {
+            bail!("the nonces are not MuSig2 nonces");
+        };
+        let ctx = self.committee.signing_ctx()?;
+        let aggnonce = AggNonce::sum(&self.committee.pubnonces(commitments)?);
+        let share: PartialSignature =
+            musig2::sign_partial(&ctx, self.secret_key, secnonce, &aggnonce, message)
+                .map_err(|err| anyhow!("error while signing: {err}"))?;
+        Ok(SignatureShare::Musig2(share))
+    }
+}
+
+//
+// Key files
+//
+
+/// The key of a member, as read from its key file (`key-<id>.json`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyShare {
+    Frost(frost::KeyPackage),
+    Musig2(Musig2KeyShare),
+}
+
+impl KeyShare {
+    fn backend(&self) -> &dyn SignerBackend {
+        match self {
+            KeyShare::Frost(key_package) => key_package,
+            KeyShare::Musig2(key_share) => key_share,
+        }
+    }
+}
+
+impl From<frost::KeyPackage> for KeyShare {
+    fn from(key_package: frost::KeyPackage) -> Self {
+        KeyShare::Frost(key_package)
+    }
+}
+
+impl SignerBackend for KeyShare {
+    fn kind(&self) -> SignerKind {
+        self.backend().kind()
+    }
+
+    fn identifier(&self) -> Identifier {
+        self.backend().identifier()
+    }
+
+    fn committee_pubkey(&self) -> Result<bitcoin::PublicKey> {
+        self.backend().committee_pubkey()
+    }
+
+    fn commit(&self) -> Result<(SecretNonces, Commitment)> {
+        self.backend().commit()
+    }
+
+    fn sign(
+        &self,
+        nonces: SecretNonces,
+        commitments: &BTreeMap<Identifier, Commitment>,
+        message: &[u8; 32],
+    ) -> Result<SignatureShare> {
+        self.backend().sign(nonces, commitments, message)
+    }
+}
+
+/// The key of the committee, as read from its public key file (`publickey-package.json`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CommitteeKey {
+    Frost(frost::PublicKeyPackage),
+    Musig2(Musig2CommitteeKey),
+}
+
+impl CommitteeKey {
+    fn aggregator(&self) -> &dyn SignatureAggregator {
+        match self {
+            CommitteeKey::Frost(pubkey_package) => pubkey_package,
+            CommitteeKey::Musig2(committee_key) => committee_key,
+        }
+    }
+}
+
+impl From<frost::PublicKeyPackage> for CommitteeKey {
+    fn from(pubkey_package: frost::PublicKeyPackage) -> Self {
+        CommitteeKey::Frost(pubkey_package)
+    }
+}
+
+impl SignatureAggregator for CommitteeKey {
+    fn kind(&self) -> SignerKind {
+        self.aggregator().kind()
+    }
+
+    fn pubkey(&self) -> Result<bitcoin::PublicKey> {
+        self.aggregator().pubkey()
+    }
+
+    fn holds_share(&self, identifier: &Identifier) -> bool {
+        self.aggregator().holds_share(identifier)
+    }
+
+    fn aggregate(
+        &self,
+        commitments: &BTreeMap<Identifier, Commitment>,
+        shares: &BTreeMap<Identifier, SignatureShare>,
+        message: &[u8; 32],
+    ) -> Result<schnorr::Signature> {
+        self.aggregator().aggregate(commitments, shares, message)
+    }
+}
+
+/// Deals the keys of an n-of-n MuSig2 committee of `num` members.
+pub fn gen_musig2_keys(
+    num: u16,
+    rng: &mut impl RngCore,
+) -> Result<(BTreeMap<Identifier, Musig2KeyShare>, Musig2CommitteeKey)> {
+    let secp = Secp256k1::signing_only();
+    let mut secret_keys = BTreeMap::new();
+    for index in 1..=num {
+        let identifier = Identifier::try_from(index)
+            .map_err(|err| anyhow!("invalid member identifier {index}: {err}"))?;
+        let secret_key = loop {
+            let mut bytes = [0u8; 32];
+            rng.fill_bytes(&mut bytes);
+            if let Ok(secret_key) = SecretKey::from_slice(&bytes) {
+                break secret_key;
+            }
+        };
+        secret_keys.insert(identifier, secret_key);
+    }
+
+    let committee = Musig2CommitteeKey {
+        pubkeys: secret_keys
+            .iter()
+            .map(|(id, secret_key)| (*id, secret_key.public_key(&secp)))
+            .collect(),
+    };
+    let key_shares = secret_keys
+        .into_iter()
+        .map(|(identifier, secret_key)| {
+            let key_share = Musig2KeyShare {
+                identifier,
+                secret_key,
+                committee: committee.clone(),
+            };
+            (identifier, key_share)
+        })
+        .collect();
+    Ok((key_shares, committee))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+
+    /// Runs a signing session with all the members, as the orchestrator does.
+    fn sign_with(
+        key_shares: &[KeyShare],
+        committee_key: &CommitteeKey,
+        message: &[u8; 32],
+    ) -> Result<schnorr::Signature> {
+        let mut nonces = BTreeMap::new();
+        let mut commitments = BTreeMap::new();
+        for key_share in key_shares {
+            let (secret, commitment) = key_share.commit()?;
+            nonces.insert(key_share.identifier(), secret);
+            commitments.insert(key_share.identifier(), commitment);
+        }
+        let mut shares = BTreeMap::new();
+        for key_share in key_shares {
+            let secret = nonces.remove(&key_share.identifier()).unwrap();
+            let share = key_share.sign(secret, &commitments, message)?;
+            shares.insert(key_share.identifier(), share);
+        }
+        committee_key.aggregate(&commitments, &shares, message)
+    }
+
+    #[test]
+    fn test_musig2() {
+        let rng = &mut ChaCha20Rng::seed_from_u64(42);
+        let (key_shares, committee) = gen_musig2_keys(3, rng).unwrap();
+        let committee_key = CommitteeKey::Musig2(committee);
+        let key_shares: Vec<_> = key_shares.into_values().map(KeyShare::Musig2).collect();
+        assert_eq!(committee_key.kind(), SignerKind::Musig2);
+        assert_eq!(
+            key_shares[0].committee_pubkey().unwrap(),
+            committee_key.pubkey().unwrap()
+        );
+
+        // the key files are told apart from FROST ones
+        let json = serde_json::to_string(&key_shares[1]).unwrap();
+        let key_share: KeyShare = serde_json::from_str(&json).unwrap();
+        assert_eq!(key_share.kind(), SignerKind::Musig2);
+        let json = serde_json::to_string(&committee_key).unwrap();
+        let parsed: CommitteeKey = serde_json::from_str(&json).unwrap();
+        assert!(parsed.holds_share(&key_shares[2].identifier()));
+
+        // every member signs
+        sign_with(&key_shares, &committee_key, &[7; 32]).unwrap();
+        assert!(sign_with(&key_shares[..2], &committee_key, &[7; 32]).is_err());
+    }
+
+    #[test]
+    fn test_frost() {
+        let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+        let committee_key = CommitteeKey::from(pubkey_package);
+        let key_shares: Vec<_> = key_packages.into_values().map(KeyShare::from).collect();
+        assert_eq!(key_shares[0].kind(), SignerKind::Frost);
+
+        // any threshold of the members signs, for the output key of the zkBitcoin address (not the committee key)
+        let sig = sign_with(&key_shares[1..], &committee_key, &[7; 32]).unwrap();
+        let secp = Secp256k1::verification_only();
+        let message = Message::from_digest([7; 32]);
+        let pubkey = committee_key.pubkey().unwrap();
+        assert!(secp
+            .verify_schnorr(&sig, &message, &output_key(pubkey))
+            .is_ok());
+        assert!(secp
+            .verify_schnorr(&sig, &message, &pubkey.inner.x_only_public_key().0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_frost_odd_y() {
+        // a committee key with an odd Y coordinate, as a DKG can end up with
+        let (key_packages, pubkey_package) = loop {
+            let (key_packages, pubkey_package) = frost::gen_frost_keys(3, 2).unwrap();
+            if pubkey_package.verifying_key().serialize()[0] == 3 {
+                break (key_packages, pubkey_package);
+            }
+        };
+        let committee_key = CommitteeKey::from(pubkey_package.clone());
+        let key_shares: Vec<_> = key_packages.into_values().map(KeyShare::from).collect();
+
+        // the committee key is the one with an even Y coordinate, which the committee signs for
+        let pubkey = committee_key.pubkey().unwrap();
+        assert_eq!(pubkey.inner.serialize()[0], 2);
+        assert_eq!(
+            pubkey.inner.serialize()[1..],
+            pubkey_package.verifying_key().serialize()[1..]
+        );
+        assert_eq!(key_shares[0].committee_pubkey().unwrap(), pubkey);
+        sign_with(&key_shares[..2], &committee_key, &[7; 32]).unwrap();
+
+        let even = even_y_pubkey_package(&pubkey_package).unwrap();
+        assert_eq!(even.verifying_key().serialize(), pubkey.inner.serialize());
+    }
+}
@@ -0,0 +1,823 @@
+//! Persistent storage for the orchestrator.
+//!
+//! Every request received by the orchestrator is written to disk,
+//! and its hash is committed to in an append-only, hash-chained log of digests.
+//! Payloads can later be purged following a [RetentionPolicy],
+//! while the digest log keeps an auditable trace of what was received and when.
+//!
+//! Requests are also marked as pending until they're handled, and their responses kept,
+//! so that another orchestrator instance can resume them after a crash (see [super::failover]).
+//! The nonces of the requests are kept as well, to reject replayed requests (see [super::replay]).
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    net::Ipv6Addr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use bitcoin::Txid;
+use itertools::Itertools;
+use log::debug;
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use crate::{
+    bob_request::{BobRequest, BobResponse},
+    zkbitcoin_folder,
+};
+
+use super::{
+    anomaly::SpendRecord,
+    fees::FeeRecord,
+    migrations::{self, CURRENT_VERSION},
+    policy::ZkappPolicy,
+};
+
+//
+// Constants
+//
+
+/// Number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The digest that starts the hash chain.
+const GENESIS_DIGEST: [u8; 32] = [0u8; 32];
+
+/// What redacted values are replaced with in exported logs.
+const REDACTED: &str = "[REDACTED]";
+
+//
+// Helpers
+//
+
+/// Returns the current UNIX timestamp (in seconds).
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is before the UNIX epoch")
+        .as_secs()
+}
+
+//
+// Data structures
+//
+
+/// How long the orchestrator keeps request payloads around.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Number of days after which request payloads are purged.
+    pub max_age_days: u64,
+}
+
+impl RetentionPolicy {
+    /// Returns the timestamp before which payloads must be purged.
+    pub fn cutoff(&self, now: u64) -> u64 {
+        now.saturating_sub(self.max_age_days.saturating_mul(SECONDS_PER_DAY))
+    }
+}
+
+/// A request received by the orchestrator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestRecord {
+    /// The hex-encoded hash of the request (see [BobRequest::hash]).
+    pub request_hash: String,
+
+    /// When the request was received (UNIX timestamp in seconds).
+    pub received_at: u64,
+
+    /// The transaction ID of the zkapp being used.
+    pub zkapp_txid: Txid,
+
+    /// The full request, or `None` if it was purged.
+    pub payload: Option<BobRequest>,
+}
+
+/// An entry of the hash-chained digest log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditDigest {
+    /// Position of the entry in the log.
+    pub index: u64,
+
+    /// When the request was received (UNIX timestamp in seconds).
+    pub received_at: u64,
+
+    /// The hex-encoded hash of the request.
+    pub request_hash: String,
+
+    /// The digest of the previous entry.
+    pub prev_digest: String,
+
+    /// The digest of this entry, which commits to all the fields above.
+    pub digest: String,
+}
+
+impl AuditDigest {
+    fn new(index: u64, received_at: u64, request_hash: String, prev_digest: String) -> Self {
+        let digest = Self::compute(index, received_at, &request_hash, &prev_digest);
+        Self {
+            index,
+            received_at,
+            request_hash,
+            prev_digest,
+            digest,
+        }
+    }
+
+    fn compute(index: u64, received_at: u64, request_hash: &str, prev_digest: &str) -> String {
+        let mut hasher = Keccak256::new();
+        hasher.update(index.to_be_bytes());
+        hasher.update(received_at.to_be_bytes());
+        hasher.update(request_hash.as_bytes());
+        hasher.update(prev_digest.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+}
+
+/// The outcome of [Storage::purge].
+#[derive(Debug, Default)]
+pub struct PurgeReport {
+    /// The hashes of the requests whose payloads were (or would have been, in dry-run mode) purged.
+    pub purged: Vec<String>,
+
+    /// The number of records left untouched.
+    pub kept: usize,
+
+    /// The number of nonces forgotten (see [Storage::use_nonce]).
+    pub nonces_purged: usize,
+}
+
+/// The orchestrator's on-disk storage.
+pub struct Storage {
+    /// The root directory of the storage.
+    dir: PathBuf,
+
+    /// The last entry of the digest log (also serializes writes to it).
+    last_digest: Mutex<Option<AuditDigest>>,
+}
+
+impl Storage {
+    /// Returns the default location of the orchestrator storage.
+    pub fn default_dir() -> PathBuf {
+        zkbitcoin_folder().join("orchestrator")
+    }
+
+    /// Opens (and creates if needed) the storage at the given directory.
+    /// The storage must have been migrated to the current version (see [migrations]).
+    pub fn open(dir: &Path) -> Result<Self> {
+        let version = migrations::version(dir)?;
+        ensure!(
+            version == CURRENT_VERSION,
+            "the orchestrator storage is at version {version} but version {CURRENT_VERSION} is expected (see `zkbtc orchestrator migrate`)"
+        );
+
+        fs::create_dir_all(dir.join("requests"))
+            .and_then(|_| fs::create_dir_all(dir.join("policies")))
+            .and_then(|_| fs::create_dir_all(dir.join("pending")))
+            .and_then(|_| fs::create_dir_all(dir.join("responses")))
+            .and_then(|_| fs::create_dir_all(dir.join("nonces")))
+            .context("couldn't create the orchestrator storage directory")?;
+        migrations::set_version(dir, CURRENT_VERSION)?;
+
+        let storage = Self {
+            dir: dir.to_path_buf(),
+            last_digest: Mutex::new(None),
+        };
+        let last_digest = storage.digests()?.pop();
+        *storage.last_digest.lock().unwrap() = last_digest;
+
+        Ok(storage)
+    }
+
+    fn requests_dir(&self) -> PathBuf {
+        self.dir.join("requests")
+    }
+
+    fn digests_path(&self) -> PathBuf {
+        self.dir.join("digests.jsonl")
+    }
+
+    fn fees_path(&self) -> PathBuf {
+        self.dir.join("fees.jsonl")
+    }
+
+    fn spends_path(&self) -> PathBuf {
+        self.dir.join("spends.jsonl")
+    }
+
+    fn policy_path(&self, vk_hash: &[u8; 32], deployer: &XOnlyPublicKey) -> PathBuf {
+        self.dir
+            .join("policies")
+            .join(format!("{}-{deployer}.json", hex::encode(vk_hash)))
+    }
+
+    /// Pending batches are named after the first request they contain.
+    fn pending_path(&self, request_hashes: &[String]) -> PathBuf {
+        self.dir
+            .join("pending")
+            .join(format!("{}.json", request_hashes[0]))
+    }
+
+    fn response_path(&self, request_hash: &str) -> PathBuf {
+        self.dir
+            .join("responses")
+            .join(format!("{request_hash}.json"))
+    }
+
+    fn nonce_path(&self, nonce: &str) -> PathBuf {
+        self.dir.join("nonces").join(nonce)
+    }
+
+    /// Records are sharded by the first byte of their hash.
+    fn record_path(&self, request_hash: &str) -> PathBuf {
+        self.requests_dir()
+            .join(&request_hash[..2])
+            .join(format!("{request_hash}.json"))
+    }
+
+    /// Appends a new entry to the digest log.
+    fn append_digest(&self, request_hash: String, received_at: u64) -> Result<AuditDigest> {
+        let mut last_digest = self.last_digest.lock().unwrap();
+        let (index, prev_digest) = match last_digest.as_ref() {
+            Some(last) => (last.index + 1, last.digest.clone()),
+            None => (0, hex::encode(GENESIS_DIGEST)),
+        };
+        let entry = AuditDigest::new(index, received_at, request_hash, prev_digest);
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.digests_path())
+            .context("couldn't open the digest log")?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+
+        *last_digest = Some(entry.clone());
+        Ok(entry)
+    }
+
+    /// Stores a request and commits to it in the digest log.
+    /// Returns the hex-encoded hash of the request.
+    pub fn record_request(&self, request: &BobRequest) -> Result<String> {
+        let request_hash = hex::encode(request.hash());
+        let received_at = now();
+
+        self.append_digest(request_hash.clone(), received_at)?;
+
+        let record = RequestRecord {
+            request_hash: request_hash.clone(),
+            received_at,
+            zkapp_txid: request.txid()?,
+            payload: Some(request.clone()),
+        };
+        self.write_record(&record)?;
+
+        debug!("- stored request {request_hash}");
+        Ok(request_hash)
+    }
+
+    fn write_record(&self, record: &RequestRecord) -> Result<()> {
+        let path = self.record_path(&record.request_hash);
+        if let Some(shard_dir) = path.parent() {
+            fs::create_dir_all(shard_dir)?;
+        }
+        let file = File::create(path).context("couldn't create request record")?;
+        serde_json::to_writer(file, record)?;
+        Ok(())
+    }
+
+    /// Returns the stored record of a request, if any.
+    pub fn record(&self, request_hash: &str) -> Result<Option<RequestRecord>> {
+        let path = self.record_path(request_hash);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(&path)?;
+        let record = serde_json::from_reader(file)
+            .with_context(|| format!("couldn't parse record {}", path.display()))?;
+        Ok(Some(record))
+    }
+
+    /// Marks a batch of (stored) requests as being handled.
+    pub fn mark_pending(&self, request_hashes: &[String]) -> Result<()> {
+        ensure!(!request_hashes.is_empty(), "empty batch of requests");
+        let file = File::create(self.pending_path(request_hashes))
+            .context("couldn't mark the requests as pending")?;
+        serde_json::to_writer(file, request_hashes)?;
+        Ok(())
+    }
+
+    /// Marks a batch of requests as handled, and keeps their response if they were signed.
+    pub fn complete(
+        &self,
+        request_hashes: &[String],
+        bob_response: Option<&BobResponse>,
+    ) -> Result<()> {
+        if let Some(bob_response) = bob_response {
+            for request_hash in request_hashes {
+                let file = File::create(self.response_path(request_hash))
+                    .context("couldn't store the response")?;
+                serde_json::to_writer(file, bob_response)?;
+            }
+        }
+        let path = self.pending_path(request_hashes);
+        if path.exists() {
+            fs::remove_file(path).context("couldn't unmark the pending requests")?;
+        }
+        Ok(())
+    }
+
+    /// Returns the batches of requests that were never handled to completion.
+    pub fn pending(&self) -> Result<Vec<Vec<String>>> {
+        let mut pending = vec![];
+        for entry in fs::read_dir(self.dir.join("pending"))? {
+            let path = entry?.path();
+            let file = File::open(&path)?;
+            let request_hashes = serde_json::from_reader(file)
+                .with_context(|| format!("couldn't parse pending requests {}", path.display()))?;
+            pending.push(request_hashes);
+        }
+        Ok(pending)
+    }
+
+    /// Records the (well-formed) nonce of a request, unless another request already used it.
+    pub fn use_nonce(&self, nonce: &str, request_hash: &str) -> Result<()> {
+        let path = self.nonce_path(nonce);
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                file.write_all(request_hash.as_bytes())
+                    .context("couldn't record the nonce")?;
+                Ok(())
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                let used_by = fs::read_to_string(&path).context("couldn't read the nonce")?;
+                ensure!(
+                    used_by == request_hash,
+                    "nonce {nonce} was already used by request {used_by}"
+                );
+                Ok(())
+            }
+            Err(err) => Err(err).context("couldn't record the nonce"),
+        }
+    }
+
+    /// Returns the response to a request that was signed, if any.
+    pub fn response(&self, request_hash: &str) -> Result<Option<BobResponse>> {
+        let path = self.response_path(request_hash);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(&path)?;
+        let bob_response = serde_json::from_reader(file)
+            .with_context(|| format!("couldn't parse response {}", path.display()))?;
+        Ok(Some(bob_response))
+    }
+
+    /// Returns all the stored request records.
+    pub fn records(&self) -> Result<Vec<RequestRecord>> {
+        let mut records = vec![];
+        for shard in fs::read_dir(self.requests_dir())? {
+            for entry in fs::read_dir(shard?.path())? {
+                let path = entry?.path();
+                let file = File::open(&path)?;
+                let record: RequestRecord = serde_json::from_reader(file)
+                    .with_context(|| format!("couldn't parse record {}", path.display()))?;
+                records.push(record);
+            }
+        }
+        records.sort_by_key(|record| record.received_at);
+        Ok(records)
+    }
+
+    /// Records the fee paid by a signed request.
+    pub fn record_fee(&self, record: &FeeRecord) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.fees_path())
+            .context("couldn't open the fee ledger")?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    /// Returns all the fees recorded, in order.
+    pub fn fee_records(&self) -> Result<Vec<FeeRecord>> {
+        let path = self.fees_path();
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+
+        let file = File::open(path)?;
+        let mut records = vec![];
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            records.push(serde_json::from_str(&line).context("malformed fee ledger entry")?);
+        }
+        Ok(records)
+    }
+
+    /// Records what a signed request withdrew from its zkapp.
+    pub fn record_spend(&self, record: &SpendRecord) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.spends_path())
+            .context("couldn't open the spend ledger")?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    /// Returns the spends recorded for a zkapp (by hex-encoded verifier key hash), in order.
+    pub fn spend_records(&self, vk_hash: &str) -> Result<Vec<SpendRecord>> {
+        let path = self.spends_path();
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+
+        let file = File::open(path)?;
+        let mut records = vec![];
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let record: SpendRecord =
+                serde_json::from_str(&line).context("malformed spend ledger entry")?;
+            if record.vk_hash == vk_hash {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Registers the policy of the zkapps of a verifier key deployed with a deployer key.
+    /// A zkapp can only be registered once.
+    pub fn register_policy(
+        &self,
+        vk_hash: &[u8; 32],
+        deployer: &XOnlyPublicKey,
+        policy: &ZkappPolicy,
+    ) -> Result<()> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.policy_path(vk_hash, deployer))
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::AlreadyExists => {
+                    anyhow!("a policy is already registered for this zkapp")
+                }
+                _ => anyhow::Error::new(err).context("couldn't create policy file"),
+            })?;
+        serde_json::to_writer(file, policy)?;
+        Ok(())
+    }
+
+    /// Returns the policy registered for the zkapps of a verifier key deployed with a deployer key (if any).
+    pub fn policy(
+        &self,
+        vk_hash: &[u8; 32],
+        deployer: &XOnlyPublicKey,
+    ) -> Result<Option<ZkappPolicy>> {
+        let path = self.policy_path(vk_hash, deployer);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = File::open(path)?;
+        let policy = serde_json::from_reader(file).context("couldn't parse zkapp policy")?;
+        Ok(Some(policy))
+    }
+
+    /// Returns all the entries of the digest log.
+    pub fn digests(&self) -> Result<Vec<AuditDigest>> {
+        let path = self.digests_path();
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+
+        let file = File::open(path)?;
+        let mut digests = vec![];
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            digests.push(serde_json::from_str(&line).context("malformed digest log entry")?);
+        }
+        Ok(digests)
+    }
+
+    /// Checks that the digest log has not been tampered with.
+    /// Returns the number of entries verified.
+    pub fn verify_digests(&self) -> Result<usize> {
+        let digests = self.digests()?;
+        let mut prev_digest = hex::encode(GENESIS_DIGEST);
+        for (index, entry) in digests.iter().enumerate() {
+            ensure!(
+                entry.index == index as u64,
+                "digest log entry {index} has an unexpected index {}",
+                entry.index
+            );
+            ensure!(
+                entry.prev_digest == prev_digest,
+                "digest log entry {index} does not chain to the previous entry"
+            );
+            let expected = AuditDigest::compute(
+                entry.index,
+                entry.received_at,
+                &entry.request_hash,
+                &entry.prev_digest,
+            );
+            ensure!(
+                entry.digest == expected,
+                "digest log entry {index} has an incorrect digest"
+            );
+            prev_digest = entry.digest.clone();
+        }
+        Ok(digests.len())
+    }
+
+    /// Purges the payloads of the requests that are older than what the policy allows,
+    /// and forgets the nonces used before then (the transactions of these requests can't be replayed, see [super::replay]).
+    /// The digest log is left untouched.
+    pub fn purge(&self, policy: &RetentionPolicy, dry_run: bool) -> Result<PurgeReport> {
+        let cutoff = policy.cutoff(now());
+        let mut report = PurgeReport::default();
+
+        for mut record in self.records()? {
+            if record.payload.is_none() || record.received_at >= cutoff {
+                report.kept += 1;
+                continue;
+            }
+
+            if !dry_run {
+                record.payload = None;
+                self.write_record(&record)?;
+            }
+            report.purged.push(record.request_hash);
+        }
+
+        for entry in fs::read_dir(self.dir.join("nonces"))? {
+            let path = entry?.path();
+            let used_at = fs::metadata(&path)?
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |used_at| used_at.as_secs());
+            if used_at >= cutoff {
+                continue;
+            }
+            if !dry_run {
+                fs::remove_file(&path).context("couldn't forget the nonce")?;
+            }
+            report.nonces_purged += 1;
+        }
+
+        Ok(report)
+    }
+}
+
+//
+// Log redaction
+//
+
+/// Redacts IP addresses (v4 and v6), Bitcoin addresses and keys (bech32 or base58),
+/// and txids (or any other hex string of 20 bytes or more, like keys, hashes in scripts, and transactions) from a log line,
+/// so that logs can be exported without leaking user identifiers.
+pub fn redact(line: &str) -> String {
+    let mut redacted = String::with_capacity(line.len());
+    let mut token = String::new();
+    for c in line.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == ':' {
+            token.push(c);
+        } else {
+            redacted.push_str(&redact_token(&token));
+            redacted.push(c);
+            token.clear();
+        }
+    }
+    redacted.push_str(&redact_token(&token));
+    redacted
+}
+
+fn redact_token(token: &str) -> String {
+    // tokens might end with a full stop
+    let trimmed = token.trim_end_matches('.');
+    let suffix = &token[trimmed.len()..];
+    if trimmed.parse::<Ipv6Addr>().is_ok() {
+        return format!("{REDACTED}{suffix}");
+    }
+
+    // other tokens are only joined by colons (e.g. `host:port`, or `txid:vout`)
+    let redacted = trimmed
+        .split(':')
+        .map(|part| {
+            if is_ipv4(part) || is_bech32_address(part) || is_base58check(part) || is_long_hex(part)
+            {
+                REDACTED
+            } else {
+                part
+            }
+        })
+        .join(":");
+    format!("{redacted}{suffix}")
+}
+
+fn is_ipv4(token: &str) -> bool {
+    let parts: Vec<&str> = token.split('.').collect();
+    parts.len() == 4
+        && parts.iter().all(|part| {
+            !part.is_empty()
+                && part.len() <= 3
+                && part.chars().all(|c| c.is_ascii_digit())
+                && part.parse::<u8>().is_ok()
+        })
+}
+
+/// Base58check strings: legacy addresses, WIF private keys, and extended keys.
+fn is_base58check(token: &str) -> bool {
+    token.len() >= 26 && bitcoin::base58::decode_check(token).is_ok()
+}
+
+/// Txids, hashes (including the public key hashes in scripts), public keys, and serialized transactions.
+fn is_long_hex(token: &str) -> bool {
+    token.len() >= 40 && token.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn is_bech32_address(token: &str) -> bool {
+    let lowercase = token.to_ascii_lowercase();
+    ["bc1", "tb1", "bcrt1"]
+        .iter()
+        .any(|hrp| lowercase.starts_with(hrp))
+        && token.len() >= 26
+        && token.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_digest_chain() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        let storage = Storage::open(tmp_dir.path()).unwrap();
+        for i in 0..3 {
+            storage.append_digest(format!("{i:064x}"), i).unwrap();
+        }
+        assert_eq!(storage.verify_digests().unwrap(), 3);
+
+        // reopening the storage continues the chain
+        let storage = Storage::open(tmp_dir.path()).unwrap();
+        storage.append_digest(format!("{:064x}", 3), 3).unwrap();
+        assert_eq!(storage.verify_digests().unwrap(), 4);
+
+        // tampering with an entry breaks the chain
+        let mut digests = storage.digests().unwrap();
+        digests[1].received_at = 42;
+        let mut file = File::create(storage.digests_path()).unwrap();
+        for entry in digests {
+            writeln!(file, "{}", serde_json::to_string(&entry).unwrap()).unwrap();
+        }
+        assert!(storage.verify_digests().is_err());
+    }
+
+    #[test]
+    fn test_register_policy_once() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        let storage = Storage::open(tmp_dir.path()).unwrap();
+        let vk_hash = [7u8; 32];
+        let secp = secp256k1::Secp256k1::new();
+        let deployer = secp256k1::Keypair::from_seckey_slice(&secp, &[3; 32])
+            .unwrap()
+            .x_only_public_key()
+            .0;
+        let other = secp256k1::Keypair::from_seckey_slice(&secp, &[4; 32])
+            .unwrap()
+            .x_only_public_key()
+            .0;
+        assert!(storage.policy(&vk_hash, &deployer).unwrap().is_none());
+
+        let policy = ZkappPolicy {
+            webhook: Some("https://example.com/zkapp".to_string()),
+            ..Default::default()
+        };
+        storage
+            .register_policy(&vk_hash, &deployer, &policy)
+            .unwrap();
+        assert_eq!(
+            storage.policy(&vk_hash, &deployer).unwrap(),
+            Some(policy.clone())
+        );
+        assert!(storage
+            .register_policy(&vk_hash, &deployer, &policy)
+            .is_err());
+
+        // the zkapps of the same verifier key deployed with another key don't share the policy
+        assert!(storage.policy(&vk_hash, &other).unwrap().is_none());
+        storage
+            .register_policy(&vk_hash, &other, &ZkappPolicy::default())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_pending_requests() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        let storage = Storage::open(tmp_dir.path()).unwrap();
+        let batch = vec![format!("{:064x}", 1), format!("{:064x}", 2)];
+        storage.mark_pending(&batch).unwrap();
+        storage.mark_pending(&[format!("{:064x}", 3)]).unwrap();
+        assert_eq!(storage.pending().unwrap().len(), 2);
+
+        // a failed request isn't pending anymore, but has no response
+        storage.complete(&[format!("{:064x}", 3)], None).unwrap();
+        assert_eq!(storage.pending().unwrap(), vec![batch.clone()]);
+        assert!(storage.response(&batch[0]).unwrap().is_none());
+
+        // every request of a signed batch gets the response
+        let bob_response = BobResponse {
+            unlocked_tx: bitcoin::Transaction {
+                version: bitcoin::transaction::Version::TWO,
+                lock_time: bitcoin::absolute::LockTime::ZERO,
+                input: vec![],
+                output: vec![],
+            },
+        };
+        storage.complete(&batch, Some(&bob_response)).unwrap();
+        assert!(storage.pending().unwrap().is_empty());
+        for request_hash in &batch {
+            let response = storage.response(request_hash).unwrap().unwrap();
+            assert_eq!(response.unlocked_tx, bob_response.unlocked_tx);
+        }
+    }
+
+    #[test]
+    fn test_use_nonce() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        let storage = Storage::open(tmp_dir.path()).unwrap();
+        let nonce = "01".repeat(32);
+        let (first, second) = (format!("{:064x}", 1), format!("{:064x}", 2));
+        storage.use_nonce(&nonce, &first).unwrap();
+
+        // a request sent again keeps its nonce, which no other request can use (even after a restart)
+        storage.use_nonce(&nonce, &first).unwrap();
+        let storage = Storage::open(tmp_dir.path()).unwrap();
+        assert!(storage.use_nonce(&nonce, &second).is_err());
+        storage.use_nonce(&"02".repeat(32), &second).unwrap();
+
+        // until it's older than what the retention policy allows
+        let policy = RetentionPolicy { max_age_days: 2 };
+        let used_at = SystemTime::now() - std::time::Duration::from_secs(3 * SECONDS_PER_DAY);
+        File::options()
+            .write(true)
+            .open(storage.nonce_path(&nonce))
+            .unwrap()
+            .set_modified(used_at)
+            .unwrap();
+        assert_eq!(storage.purge(&policy, true).unwrap().nonces_purged, 1);
+        assert!(storage.use_nonce(&nonce, &second).is_err());
+        assert_eq!(storage.purge(&policy, false).unwrap().nonces_purged, 1);
+        storage.use_nonce(&nonce, &second).unwrap();
+    }
+
+    #[test]
+    fn test_retention_cutoff() {
+        let policy = RetentionPolicy { max_age_days: 2 };
+        assert_eq!(policy.cutoff(3 * SECONDS_PER_DAY), SECONDS_PER_DAY);
+        assert_eq!(policy.cutoff(SECONDS_PER_DAY), 0);
+    }
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(
+            redact("- sending request to http://127.0.0.1:8891 now."),
+            "- sending request to http://[REDACTED]:8891 now."
+        );
+        assert_eq!(
+            redact("paying tb1q6nkpv2j9lxrm6h3w4skrny3thswgdcca8cx9k6."),
+            "paying [REDACTED]."
+        );
+        assert_eq!(
+            redact("version 1.2.3 at 999.0.0.1"),
+            "version 1.2.3 at 999.0.0.1"
+        );
+        assert_eq!(
+            redact("paying 1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2 from [2001:db8::1]:8891 and ::1"),
+            "paying [REDACTED] from [[REDACTED]]:8891 and [REDACTED]"
+        );
+        assert_eq!(
+            redact("- key xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8 at 12:30:45"),
+            "- key [REDACTED] at 12:30:45"
+        );
+        let txid = "e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836";
+        assert_eq!(
+            redact(&format!("spending {txid}:0 in zkbitcoin::committee")),
+            "spending [REDACTED]:0 in zkbitcoin::committee"
+        );
+    }
+}
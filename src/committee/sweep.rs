@@ -0,0 +1,649 @@
+//! Emergency sweeps of the zkapps to a recovery descriptor, should the committee be compromised (see `zkbtc emergency-sweep`).
+//!
+//! The committee configuration pre-commits to a recovery descriptor (see [super::orchestrator::CommitteeConfig::emergency_sweep_descriptor]),
+//! typically the cold storage of the operators of the committee.
+//! Once a quorum of members decides that the committee key is at risk, the zkapps locked in the zkBitcoin address are swept to it:
+//! the sweeps (transactions spending up to [MAX_SWEEP_INPUTS] zkapps each, and paying all of their funds but the network fee
+//! to the descriptor) are prepared, then signed by a threshold of members with a signing session per input (see [sign]).
+//! The sessions are run with the members directly, as the orchestrator might be compromised too.
+//!
+//! Every node asks its operator to confirm a sweep twice before signing it (see [SweepApproval]):
+//! the node must be restarted with the recovery descriptor, which must be the one the committee pre-committed to,
+//! and with the hash of the sweep (see [EmergencySweep::hash]), once its operator checked it.
+//! A node restarted that way is in break-glass mode: it signs the sweeps it was given, and no spend of zkapps.
+//!
+//! Recoverable zkapps (see [crate::recovery]) aren't swept, as they aren't locked in the zkBitcoin address
+//! (their depositors can recover them). Timelocks (see [crate::timelock]) don't hold back sweeps.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    str::FromStr,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::{
+    absolute::LockTime,
+    hashes::Hash,
+    sighash::{Prevouts, SighashCache},
+    taproot,
+    transaction::Version,
+    Address, Amount, FeeRate, OutPoint, ScriptBuf, Sequence, TapSighashType, Transaction, TxIn,
+    TxOut, Witness,
+};
+use log::{info, warn};
+use secp256k1::{Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+
+use crate::{
+    amounts::check_not_dust, client::CommitteeMember, get_network, p2tr_script_to, scanner::Zkapp,
+};
+
+use super::{
+    node::Round2Request,
+    orchestrator::CommitteeConfig,
+    signer::{CommitteeKey, SignatureAggregator},
+};
+
+//
+// Constants
+//
+
+/// The most zkapps spent by a single sweep (the zkapps are swept by as many sweeps as needed).
+pub const MAX_SWEEP_INPUTS: usize = 200;
+
+/// The characters descriptors are written with, in the order their checksum expects (see BIP 380).
+const DESCRIPTOR_CHARSET: &str = "0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+
+/// The characters of the checksum of a descriptor.
+const CHECKSUM_CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+//
+// Recovery descriptor
+//
+
+/// The output descriptor the zkapps are swept to: `addr(<address>)`, `raw(<hex script>)`, or `tr(<x-only key>)`,
+/// optionally followed by its checksum (`#<checksum>`, which is checked).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SweepDescriptor {
+    /// The descriptor, without its checksum.
+    descriptor: String,
+
+    /// The script of the output it describes.
+    script_pubkey: ScriptBuf,
+}
+
+impl SweepDescriptor {
+    /// The script of the output the zkapps are swept to.
+    pub fn script_pubkey(&self) -> &ScriptBuf {
+        &self.script_pubkey
+    }
+}
+
+/// Computes the checksum of a descriptor (see BIP 380).
+fn descriptor_checksum(descriptor: &str) -> Result<String> {
+    fn polymod(checksum: u64, value: u64) -> u64 {
+        const GENERATOR: [u64; 5] = [
+            0xf5dee51989,
+            0xa9fdca3312,
+            0x1bab10e32d,
+            0x3706b1677a,
+            0x644d626ffd,
+        ];
+        let top = checksum >> 35;
+        let mut checksum = ((checksum & 0x7ffffffff) << 5) ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+        checksum
+    }
+
+    let mut checksum = 1;
+    let mut groups = vec![];
+    for c in descriptor.chars() {
+        let position = DESCRIPTOR_CHARSET
+            .find(c)
+            .with_context(|| format!("the descriptor contains an invalid character ({c:?})"))?
+            as u64;
+        checksum = polymod(checksum, position & 31);
+        groups.push(position >> 5);
+        if groups.len() == 3 {
+            checksum = polymod(checksum, groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    match groups[..] {
+        [group] => checksum = polymod(checksum, group),
+        [first, second] => checksum = polymod(checksum, first * 3 + second),
+        _ => (),
+    }
+    for _ in 0..8 {
+        checksum = polymod(checksum, 0);
+    }
+    checksum ^= 1;
+
+    Ok((0..8)
+        .map(|i| CHECKSUM_CHARSET[((checksum >> (5 * (7 - i))) & 31) as usize] as char)
+        .collect())
+}
+
+impl fmt::Display for SweepDescriptor {
+    /// Formats the descriptor with its checksum.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let checksum = descriptor_checksum(&self.descriptor).map_err(|_| fmt::Error)?;
+        write!(f, "{}#{checksum}", self.descriptor)
+    }
+}
+
+impl FromStr for SweepDescriptor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (descriptor, checksum) = match s.split_once('#') {
+            Some((descriptor, checksum)) => (descriptor, Some(checksum)),
+            None => (s, None),
+        };
+        let expected = descriptor_checksum(descriptor)?;
+        if let Some(checksum) = checksum {
+            ensure!(
+                checksum == expected,
+                "the checksum of the descriptor {descriptor} is {expected}, not {checksum}"
+            );
+        }
+
+        let (kind, arg) = descriptor
+            .strip_suffix(')')
+            .and_then(|descriptor| descriptor.split_once('('))
+            .with_context(|| {
+                format!("{descriptor} is not a descriptor (like `addr(<address>)`)")
+            })?;
+        let script_pubkey = match kind {
+            "addr" => Address::from_str(arg)
+                .with_context(|| format!("{arg} is not an address"))?
+                .require_network(get_network())
+                .with_context(|| format!("{arg} is not an address of the current network"))?
+                .script_pubkey(),
+            "raw" => ScriptBuf::from_hex(arg)
+                .with_context(|| format!("{arg} is not a hex-encoded script"))?,
+            "tr" => {
+                let key = XOnlyPublicKey::from_str(arg)
+                    .with_context(|| format!("{arg} is not an x-only public key"))?;
+                ScriptBuf::new_p2tr(&Secp256k1::verification_only(), key, None)
+            }
+            _ => bail!("only addr(), raw(), and tr() descriptors can be swept to, not {kind}()"),
+        };
+        ensure!(
+            !script_pubkey.is_op_return(),
+            "the descriptor {descriptor} can't be spent"
+        );
+
+        Ok(Self {
+            descriptor: descriptor.to_string(),
+            script_pubkey,
+        })
+    }
+}
+
+impl TryFrom<String> for SweepDescriptor {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<SweepDescriptor> for String {
+    fn from(descriptor: SweepDescriptor) -> Self {
+        descriptor.to_string()
+    }
+}
+
+//
+// Sweeps
+//
+
+/// The script of the zkBitcoin address of a committee, which locks the zkapps swept.
+pub fn zkapp_script(pubkey_package: &CommitteeKey) -> Result<ScriptBuf> {
+    Ok(p2tr_script_to(pubkey_package.pubkey()?))
+}
+
+/// Parses the hex-encoded hash of a sweep (see [EmergencySweep::hash]).
+pub fn parse_sweep_hash(s: &str) -> Result<[u8; 32]> {
+    hex::decode(s)
+        .ok()
+        .and_then(|hash| hash.try_into().ok())
+        .with_context(|| format!("{s} is not the hex-encoded hash of a sweep"))
+}
+
+/// A transaction sweeping zkapps to the recovery descriptor, along with the zkapp outputs it spends.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmergencySweep {
+    /// The unsigned transaction.
+    pub tx: Transaction,
+
+    /// The outputs spent by the transaction (in the order of its inputs).
+    pub prev_outs: Vec<TxOut>,
+}
+
+impl EmergencySweep {
+    /// Prepares the sweeps of `zkapps` (locked in `zkapp_script`) to `descriptor`,
+    /// each paying its network fee at `fee_rate` out of the funds swept.
+    pub fn prepare(
+        zkapps: &[Zkapp],
+        zkapp_script: &ScriptBuf,
+        descriptor: &SweepDescriptor,
+        fee_rate: FeeRate,
+    ) -> Result<Vec<Self>> {
+        ensure!(!zkapps.is_empty(), "there are no zkapps to sweep");
+        zkapps
+            .chunks(MAX_SWEEP_INPUTS)
+            .map(|zkapps| {
+                let mut tx = Transaction {
+                    version: Version::TWO,
+                    lock_time: LockTime::ZERO,
+                    input: zkapps
+                        .iter()
+                        .map(|zkapp| TxIn {
+                            previous_output: OutPoint::new(zkapp.txid, zkapp.vout),
+                            script_sig: ScriptBuf::new(),
+                            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                            // the fee is paid for the signed transaction (with 64-byte signatures)
+                            witness: Witness::from_slice(&[[0u8; 64]]),
+                        })
+                        .collect(),
+                    output: vec![TxOut {
+                        value: Amount::ZERO,
+                        script_pubkey: descriptor.script_pubkey().clone(),
+                    }],
+                };
+                let fee = fee_rate
+                    .fee_vb(tx.vsize() as u64)
+                    .context("fee rate too large")?;
+                let swept: Amount = zkapps.iter().map(|zkapp| zkapp.locked_value).sum();
+                tx.output[0].value = swept
+                    .checked_sub(fee)
+                    .context("the zkapps swept don't hold enough to pay the network fee")?;
+                check_not_dust("the swept output", &tx.output[0])?;
+                for input in &mut tx.input {
+                    input.witness = Witness::new();
+                }
+
+                let prev_outs = zkapps
+                    .iter()
+                    .map(|zkapp| TxOut {
+                        value: zkapp.locked_value,
+                        script_pubkey: zkapp_script.clone(),
+                    })
+                    .collect();
+                Ok(Self { tx, prev_outs })
+            })
+            .collect()
+    }
+
+    /// The hash of the sweep, which the operators of the members approve it with.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Keccak256::new();
+        hasher.update(bitcoin::consensus::encode::serialize(&self.tx));
+        for prev_out in &self.prev_outs {
+            hasher.update(bitcoin::consensus::encode::serialize(prev_out));
+        }
+        let hash = hasher.finalize().to_vec();
+        hash.try_into().unwrap()
+    }
+
+    /// The funds swept to the recovery descriptor.
+    pub fn swept(&self) -> Amount {
+        self.tx.output.iter().map(|output| output.value).sum()
+    }
+
+    /// Ensures that the sweep only spends zkapps locked in `zkapp_script`, and only pays to `descriptor`.
+    pub fn validate(&self, zkapp_script: &ScriptBuf, descriptor: &SweepDescriptor) -> Result<()> {
+        ensure!(
+            !self.tx.input.is_empty() && self.tx.input.len() <= MAX_SWEEP_INPUTS,
+            "a sweep spends between 1 and {MAX_SWEEP_INPUTS} zkapps, not {}",
+            self.tx.input.len()
+        );
+        ensure!(
+            self.prev_outs.len() == self.tx.input.len(),
+            "the sweep spends {} zkapps, but {} outputs spent were given",
+            self.tx.input.len(),
+            self.prev_outs.len()
+        );
+        ensure!(
+            self.prev_outs
+                .iter()
+                .all(|prev_out| prev_out.script_pubkey == *zkapp_script),
+            "the sweep spends outputs that are not locked in the zkBitcoin address"
+        );
+        ensure!(
+            self.tx.output.len() == 1
+                && self.tx.output[0].script_pubkey == *descriptor.script_pubkey(),
+            "the sweep doesn't pay everything to the recovery descriptor {descriptor}"
+        );
+        let spent: Amount = self.prev_outs.iter().map(|prev_out| prev_out.value).sum();
+        ensure!(
+            self.swept() <= spent,
+            "the sweep pays more than the zkapps it spends hold"
+        );
+        Ok(())
+    }
+
+    /// The digest the committee signs to spend input `input` of the sweep (with its key).
+    pub fn sighash(&self, input: usize) -> Result<[u8; 32]> {
+        ensure!(
+            input < self.tx.input.len(),
+            "the sweep has no input {input}"
+        );
+        let sighash = SighashCache::new(&self.tx).taproot_key_spend_signature_hash(
+            input,
+            &Prevouts::All(&self.prev_outs),
+            TapSighashType::Default,
+        )?;
+        Ok(sighash.to_byte_array())
+    }
+}
+
+/// A request to sign one input of a sweep.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepRequest {
+    /// The sweep.
+    pub sweep: EmergencySweep,
+
+    /// The input of the sweep to sign.
+    pub input: usize,
+}
+
+//
+// Approval
+//
+
+/// The sweeps a node in break-glass mode signs, as confirmed by its operator.
+#[derive(Debug, Clone)]
+pub struct SweepApproval {
+    /// The recovery descriptor the sweeps pay to.
+    pub descriptor: SweepDescriptor,
+
+    /// The hashes of the sweeps approved.
+    pub approved: Vec<[u8; 32]>,
+}
+
+impl SweepApproval {
+    /// The approval of sweeps to `confirmed`, which must be the recovery descriptor the committee pre-committed to.
+    pub fn new(
+        committed: Option<&SweepDescriptor>,
+        confirmed: SweepDescriptor,
+        approved: Vec<[u8; 32]>,
+    ) -> Result<Self> {
+        let committed = committed.context(
+            "the committee didn't pre-commit to a recovery descriptor (emergency_sweep_descriptor)",
+        )?;
+        ensure!(
+            committed.script_pubkey() == confirmed.script_pubkey(),
+            "the recovery descriptor {confirmed} is not the one the committee pre-committed to ({committed})"
+        );
+        ensure!(!approved.is_empty(), "no sweep was approved");
+        Ok(Self {
+            descriptor: confirmed,
+            approved,
+        })
+    }
+
+    /// Ensures that the sweep was approved, and is valid. Returns its hash.
+    pub fn check(&self, zkapp_script: &ScriptBuf, sweep: &EmergencySweep) -> Result<[u8; 32]> {
+        let hash = sweep.hash();
+        ensure!(
+            self.approved.contains(&hash),
+            "the sweep {} wasn't approved by the operator of the node",
+            hex::encode(hash)
+        );
+        sweep.validate(zkapp_script, &self.descriptor)?;
+        Ok(hash)
+    }
+}
+
+//
+// Signing
+//
+
+/// Has a threshold of the members that are in break-glass mode sign every input of `sweep`,
+/// and returns the signed transaction.
+pub async fn sign(
+    committee_cfg: &CommitteeConfig,
+    members: &HashMap<frost_secp256k1_tr::Identifier, Box<dyn CommitteeMember>>,
+    pubkey_package: &CommitteeKey,
+    sweep: &EmergencySweep,
+) -> Result<Transaction> {
+    let descriptor = committee_cfg.emergency_sweep_descriptor.as_ref().context(
+        "the committee didn't pre-commit to a recovery descriptor (emergency_sweep_descriptor)",
+    )?;
+    let zkapp_script = zkapp_script(pubkey_package)?;
+    sweep.validate(&zkapp_script, descriptor)?;
+
+    // pick a threshold of the members in break-glass mode
+    let mut signers = vec![];
+    for (member_id, member) in members {
+        match member.info().await {
+            Ok(info) if info.emergency_sweep => signers.push(*member_id),
+            Ok(_) => warn!("- committee member {member_id:?} is not in break-glass mode"),
+            Err(err) => warn!("- committee member {member_id:?} didn't answer: {err:#}"),
+        }
+    }
+    let threshold = committee_cfg.threshold;
+    ensure!(
+        signers.len() >= threshold,
+        "only {} members are in break-glass mode, but {threshold} are needed",
+        signers.len()
+    );
+    signers.sort();
+    signers.truncate(threshold);
+
+    // sign every input with a signing session
+    let sweep_hash = sweep.hash();
+    let txid = sweep.tx.txid();
+    let mut tx = sweep.tx.clone();
+    for input in 0..tx.input.len() {
+        let sweep_request = SweepRequest {
+            sweep: sweep.clone(),
+            input,
+        };
+        let sweep_request = &sweep_request;
+        let commitments_map: BTreeMap<_, _> =
+            futures::future::try_join_all(signers.iter().map(|&member_id| async move {
+                let resp = members[&member_id]
+                    .sweep_round_1_signing(sweep_request)
+                    .await
+                    .with_context(|| format!("committee member {member_id:?} refused the sweep"))?;
+                Ok::<_, anyhow::Error>((member_id, resp.commitments))
+            }))
+            .await?
+            .into_iter()
+            .collect();
+
+        let message = sweep.sighash(input)?;
+        let round2_request = Round2Request {
+            txid,
+            zkapp_input: input,
+            proof_hash: sweep_hash,
+            commitments_map: commitments_map.clone(),
+            message,
+        };
+        let round2_request = &round2_request;
+        let signature_shares: BTreeMap<_, _> =
+            futures::future::try_join_all(signers.iter().map(|&member_id| async move {
+                let resp = members[&member_id]
+                    .sweep_round_2_signing(round2_request)
+                    .await
+                    .with_context(|| {
+                        format!("committee member {member_id:?} didn't sign the sweep")
+                    })?;
+                Ok::<_, anyhow::Error>((member_id, resp.signature_share))
+            }))
+            .await?
+            .into_iter()
+            .collect();
+
+        let sig = pubkey_package
+            .aggregate(&commitments_map, &signature_shares, &message)
+            .context("the committee's signature of the sweep doesn't verify")?;
+
+        let signature = taproot::Signature {
+            sig,
+            hash_ty: TapSighashType::Default,
+        };
+        tx.input[input].witness = Witness::from_slice(&[signature.to_vec()]);
+        info!("- signed input {input} of the sweep {txid}");
+    }
+
+    Ok(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Txid;
+
+    use super::*;
+    use crate::committee::{dealer, node::NodeState, signer::SignerKind};
+
+    fn zkapp(byte: u8, sats: u64) -> Zkapp {
+        Zkapp {
+            txid: Txid::from_byte_array([byte; 32]),
+            vout: 0,
+            vk_hash: hex::encode([byte; 32]),
+            locked_value: Amount::from_sat(sats),
+            state: None,
+            metadata_hash: None,
+            deployer: None,
+            height: 100,
+        }
+    }
+
+    #[test]
+    fn test_descriptor() {
+        // the example of BIP 380
+        let descriptor = SweepDescriptor::from_str("raw(deadbeef)#89f8spxm").unwrap();
+        assert_eq!(descriptor.to_string(), "raw(deadbeef)#89f8spxm");
+        assert_eq!(
+            descriptor.script_pubkey().as_bytes(),
+            [0xde, 0xad, 0xbe, 0xef]
+        );
+        assert_eq!(
+            SweepDescriptor::from_str("raw(deadbeef)").unwrap(),
+            descriptor
+        );
+        assert!(SweepDescriptor::from_str("raw(deadbeef)#89f8spxn").is_err());
+
+        let key = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let descriptor = SweepDescriptor::from_str(&format!("tr({key})")).unwrap();
+        assert!(descriptor.script_pubkey().is_p2tr());
+        let json = serde_json::to_string(&descriptor).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SweepDescriptor>(&json).unwrap(),
+            descriptor
+        );
+
+        assert!(SweepDescriptor::from_str(&format!("wpkh({key})")).is_err());
+        assert!(SweepDescriptor::from_str("raw(6a)").is_err());
+        assert!(SweepDescriptor::from_str("deadbeef").is_err());
+    }
+
+    #[test]
+    fn test_prepare() {
+        let committee = dealer::generate(3, 2, 0, SignerKind::Frost).unwrap();
+        let zkapp_script = zkapp_script(&committee.pubkey_package).unwrap();
+        let descriptor = SweepDescriptor::from_str("raw(51)").unwrap();
+        let fee_rate = FeeRate::from_sat_per_vb(2).unwrap();
+
+        let zkapps: Vec<_> = (0..MAX_SWEEP_INPUTS + 1)
+            .map(|i| zkapp(i as u8, 10_000))
+            .collect();
+        let sweeps =
+            EmergencySweep::prepare(&zkapps, &zkapp_script, &descriptor, fee_rate).unwrap();
+        assert_eq!(sweeps.len(), 2);
+        assert_eq!(sweeps[0].tx.input.len(), MAX_SWEEP_INPUTS);
+        assert_eq!(sweeps[1].tx.input.len(), 1);
+        for sweep in &sweeps {
+            sweep.validate(&zkapp_script, &descriptor).unwrap();
+            assert!(sweep.swept() < Amount::from_sat(10_000) * sweep.tx.input.len() as u64);
+        }
+        assert_ne!(sweeps[0].hash(), sweeps[1].hash());
+
+        // sweeps only pay to the descriptor
+        let other = SweepDescriptor::from_str("raw(52)").unwrap();
+        assert!(sweeps[1].validate(&zkapp_script, &other).is_err());
+        assert!(
+            EmergencySweep::prepare(&[zkapp(1, 300)], &zkapp_script, &descriptor, fee_rate)
+                .is_err()
+        );
+        assert!(EmergencySweep::prepare(&[], &zkapp_script, &descriptor, fee_rate).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sign() {
+        let mut committee = dealer::generate(3, 2, 0, SignerKind::Frost).unwrap();
+        let descriptor = SweepDescriptor::from_str("raw(51)").unwrap();
+        committee.config.emergency_sweep_descriptor = Some(descriptor.clone());
+        let zkapp_script = zkapp_script(&committee.pubkey_package).unwrap();
+        let fee_rate = FeeRate::from_sat_per_vb(1).unwrap();
+        let sweep = EmergencySweep::prepare(
+            &[zkapp(1, 10_000), zkapp(2, 20_000)],
+            &zkapp_script,
+            &descriptor,
+            fee_rate,
+        )
+        .unwrap()
+        .remove(0);
+
+        // two of the members approve the sweep, the last one isn't in break-glass mode
+        let members = |approved: [u8; 32]| -> HashMap<_, Box<dyn CommitteeMember>> {
+            committee
+                .key_packages
+                .iter()
+                .enumerate()
+                .map(|(i, (id, key_package))| {
+                    let mut node =
+                        NodeState::new(key_package.clone(), committee.pubkey_package.clone());
+                    if i < 2 {
+                        node.emergency_sweep = Some(
+                            SweepApproval::new(
+                                Some(&descriptor),
+                                descriptor.clone(),
+                                vec![approved],
+                            )
+                            .unwrap(),
+                        );
+                    }
+                    (*id, Box::new(node) as Box<dyn CommitteeMember>)
+                })
+                .collect()
+        };
+
+        let tx = sign(
+            &committee.config,
+            &members(sweep.hash()),
+            &committee.pubkey_package,
+            &sweep,
+        )
+        .await
+        .unwrap();
+        assert_eq!(tx.txid(), sweep.tx.txid());
+        assert!(tx.input.iter().all(|input| input.witness.len() == 1));
+
+        // sweeps that weren't approved aren't signed
+        assert!(sign(
+            &committee.config,
+            &members([0; 32]),
+            &committee.pubkey_package,
+            &sweep,
+        )
+        .await
+        .is_err());
+
+        // nor are sweeps to another descriptor
+        let other = SweepDescriptor::from_str("raw(52)").unwrap();
+        assert!(SweepApproval::new(Some(&descriptor), other, vec![sweep.hash()]).is_err());
+    }
+}
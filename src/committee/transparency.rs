@@ -0,0 +1,398 @@
+//! A public transparency log of the transactions signed by the committee, in the style of Certificate Transparency
+//! (RFC 6962): the signatures are the leaves of a Merkle tree, whose root is regularly signed by the orchestrator.
+//!
+//! Anyone can ask the orchestrator to prove that a signature is in the log (see [InclusionProof]).
+//! A zkapp spent on chain with a signature that can't be proven to be in the log was signed off the record.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    Txid,
+};
+use log::debug;
+use secp256k1::{schnorr, Keypair, Message, Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+
+use super::{audit, storage::now};
+
+/// The domain separator of the digests of tree heads.
+const TREE_HEAD_DOMAIN: &[u8] = b"zkbitcoin tree head";
+
+//
+// Data structures
+//
+
+/// A signature produced by the committee, as a leaf of the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogLeaf {
+    /// When the signature was logged (UNIX timestamp in seconds).
+    pub logged_at: u64,
+
+    /// The transaction signed.
+    pub txid: Txid,
+
+    /// The input of the transaction spending the zkapp.
+    pub zkapp_input: usize,
+
+    /// The transaction that deployed (or last updated) the zkapp.
+    pub zkapp_txid: Txid,
+
+    /// The hex-encoded Schnorr signature of the input, as found in its witness.
+    pub signature: String,
+}
+
+impl LogLeaf {
+    /// The hash of the leaf in the tree.
+    pub fn hash(&self) -> Result<[u8; 32]> {
+        let mut engine = sha256::Hash::engine();
+        engine.input(&[0x00]);
+        engine.input(serde_json::to_string(self)?.as_bytes());
+        Ok(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+}
+
+/// The size and root of the tree at some point, signed by the orchestrator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedTreeHead {
+    /// The number of leaves in the tree.
+    pub tree_size: u64,
+
+    /// The hex-encoded root of the tree.
+    pub root_hash: String,
+
+    /// When the tree head was signed (UNIX timestamp in seconds).
+    pub timestamp: u64,
+
+    /// The hex-encoded (x-only) public key of the orchestrator.
+    pub signer: String,
+
+    /// The hex-encoded Schnorr signature of the tree head.
+    pub signature: String,
+}
+
+impl SignedTreeHead {
+    fn digest(tree_size: u64, timestamp: u64, root_hash: &[u8; 32]) -> [u8; 32] {
+        let mut engine = sha256::Hash::engine();
+        engine.input(TREE_HEAD_DOMAIN);
+        engine.input(&tree_size.to_be_bytes());
+        engine.input(&timestamp.to_be_bytes());
+        engine.input(root_hash);
+        sha256::Hash::from_engine(engine).to_byte_array()
+    }
+
+    /// Checks the signature of the tree head (by `pubkey`, if given, which should be the one published by the operator).
+    pub fn verify(&self, pubkey: Option<&XOnlyPublicKey>) -> Result<()> {
+        let signer = XOnlyPublicKey::from_slice(&hex::decode(&self.signer)?)
+            .context("the tree head has an invalid signer")?;
+        if let Some(pubkey) = pubkey {
+            ensure!(
+                &signer == pubkey,
+                "the tree head is signed by another key ({signer})"
+            );
+        }
+        let root_hash: [u8; 32] = hex::decode(&self.root_hash)?
+            .try_into()
+            .ok()
+            .context("the tree head has an invalid root")?;
+        let signature = schnorr::Signature::from_slice(&hex::decode(&self.signature)?)
+            .context("the tree head has an invalid signature")?;
+        let digest = Self::digest(self.tree_size, self.timestamp, &root_hash);
+        Secp256k1::verification_only()
+            .verify_schnorr(&signature, &Message::from_digest(digest), &signer)
+            .context("the tree head has an incorrect signature")
+    }
+}
+
+/// A proof that a signature is in the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    /// The signature.
+    pub leaf: LogLeaf,
+
+    /// The position of the signature in the log.
+    pub leaf_index: u64,
+
+    /// The hex-encoded hashes of the siblings of the path from the leaf to the root, from the bottom up.
+    pub audit_path: Vec<String>,
+
+    /// The tree the signature is proven to be in.
+    pub tree_head: SignedTreeHead,
+}
+
+impl InclusionProof {
+    /// Checks the proof, and the signature of its tree head (by `pubkey`, if given).
+    pub fn verify(&self, pubkey: Option<&XOnlyPublicKey>) -> Result<()> {
+        self.tree_head.verify(pubkey)?;
+        let path = self
+            .audit_path
+            .iter()
+            .map(|hash| -> Result<[u8; 32]> {
+                hex::decode(hash)?
+                    .try_into()
+                    .ok()
+                    .context("malformed audit path")
+            })
+            .collect::<Result<Vec<_>>>()?;
+        ensure!(
+            verify_path(
+                self.leaf_index,
+                self.tree_head.tree_size,
+                self.leaf.hash()?,
+                &path,
+                &self.tree_head.root_hash
+            ),
+            "the signature is not in the tree"
+        );
+        Ok(())
+    }
+}
+
+//
+// Merkle tree
+//
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut engine = sha256::Hash::engine();
+    engine.input(&[0x01]);
+    engine.input(left);
+    engine.input(right);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// The largest power of two smaller than `n` (> 1), where trees are split.
+fn split(n: usize) -> usize {
+    let mut k = 1;
+    while k * 2 < n {
+        k *= 2;
+    }
+    k
+}
+
+/// The root of the tree with these leaves.
+fn root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    match leaves.len() {
+        0 => sha256::Hash::hash(&[]).to_byte_array(),
+        1 => leaves[0],
+        n => {
+            let k = split(n);
+            node_hash(&root(&leaves[..k]), &root(&leaves[k..]))
+        }
+    }
+}
+
+/// The audit path of the leaf at `index`.
+fn path(index: usize, leaves: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    let n = leaves.len();
+    if n <= 1 {
+        return vec![];
+    }
+    let k = split(n);
+    if index < k {
+        let mut path = path(index, &leaves[..k]);
+        path.push(root(&leaves[k..]));
+        path
+    } else {
+        let mut path = path(index - k, &leaves[k..]);
+        path.push(root(&leaves[..k]));
+        path
+    }
+}
+
+/// Checks that the leaf at `index` of a tree of `size` leaves has the given audit path (RFC 9162, 2.1.3.2).
+fn verify_path(index: u64, size: u64, leaf: [u8; 32], path: &[[u8; 32]], root: &str) -> bool {
+    if index >= size {
+        return false;
+    }
+    let (mut index, mut last) = (index, size - 1);
+    let mut hash = leaf;
+    for sibling in path {
+        if last == 0 {
+            return false;
+        }
+        if index & 1 == 1 || index == last {
+            hash = node_hash(sibling, &hash);
+            while index & 1 == 0 && index != 0 {
+                index >>= 1;
+                last >>= 1;
+            }
+        } else {
+            hash = node_hash(&hash, sibling);
+        }
+        index >>= 1;
+        last >>= 1;
+    }
+    last == 0 && hex::encode(hash) == root
+}
+
+//
+// Log
+//
+
+/// The transparency log, in a directory (as `transparency.jsonl`),
+/// signed with the key of the audit log kept there (see [audit::KEY_FILE]).
+pub struct TransparencyLog {
+    path: PathBuf,
+    keypair: Keypair,
+    /// The leaves of the tree, and their hashes.
+    leaves: Mutex<(Vec<LogLeaf>, Vec<[u8; 32]>)>,
+}
+
+impl TransparencyLog {
+    /// Opens (and creates if needed) the transparency log in `dir`.
+    pub fn open(dir: &Path) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+        let keypair = audit::load_or_create_key(&dir.join(audit::KEY_FILE))?;
+        let path = dir.join("transparency.jsonl");
+
+        let mut leaves = vec![];
+        if path.exists() {
+            let file = fs::File::open(&path)
+                .with_context(|| format!("couldn't open {}", path.display()))?;
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                if !line.is_empty() {
+                    let leaf: LogLeaf =
+                        serde_json::from_str(&line).context("malformed transparency log leaf")?;
+                    leaves.push(leaf);
+                }
+            }
+        }
+        let hashes = leaves.iter().map(LogLeaf::hash).collect::<Result<_>>()?;
+        Ok(Self {
+            path,
+            keypair,
+            leaves: Mutex::new((leaves, hashes)),
+        })
+    }
+
+    /// The public key the tree heads are signed with.
+    pub fn pubkey(&self) -> XOnlyPublicKey {
+        self.keypair.x_only_public_key().0
+    }
+
+    /// Appends a signature to the log, and returns its position.
+    pub fn append(&self, leaf: LogLeaf) -> Result<u64> {
+        let mut leaves = self.leaves.lock().unwrap();
+        let hash = leaf.hash()?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .context("couldn't open the transparency log")?;
+        writeln!(file, "{}", serde_json::to_string(&leaf)?)?;
+        file.sync_data()?;
+
+        debug!("- logged the signature of {}", leaf.txid);
+        leaves.0.push(leaf);
+        leaves.1.push(hash);
+        Ok(leaves.1.len() as u64 - 1)
+    }
+
+    fn sign(&self, hashes: &[[u8; 32]]) -> SignedTreeHead {
+        let tree_size = hashes.len() as u64;
+        let root_hash = root(hashes);
+        let timestamp = now();
+        let digest = SignedTreeHead::digest(tree_size, timestamp, &root_hash);
+        let signature = Secp256k1::signing_only()
+            .sign_schnorr_no_aux_rand(&Message::from_digest(digest), &self.keypair);
+        SignedTreeHead {
+            tree_size,
+            root_hash: hex::encode(root_hash),
+            timestamp,
+            signer: hex::encode(self.pubkey().serialize()),
+            signature: hex::encode(signature.as_ref()),
+        }
+    }
+
+    /// The current head of the tree, signed.
+    pub fn tree_head(&self) -> SignedTreeHead {
+        let leaves = self.leaves.lock().unwrap();
+        self.sign(&leaves.1)
+    }
+
+    /// Proves that the (last) signature of input `zkapp_input` of `txid` is in the log.
+    pub fn prove(&self, txid: Txid, zkapp_input: usize) -> Result<InclusionProof> {
+        let leaves = self.leaves.lock().unwrap();
+        let index = leaves
+            .0
+            .iter()
+            .rposition(|leaf| leaf.txid == txid && leaf.zkapp_input == zkapp_input)
+            .with_context(|| format!("input {zkapp_input} of {txid} was never signed"))?;
+        Ok(InclusionProof {
+            leaf: leaves.0[index].clone(),
+            leaf_index: index as u64,
+            audit_path: path(index, &leaves.1).iter().map(hex::encode).collect(),
+            tree_head: self.sign(&leaves.1),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    fn leaf(i: u8) -> LogLeaf {
+        LogLeaf {
+            logged_at: i.into(),
+            txid: Txid::from_byte_array([i; 32]),
+            zkapp_input: 0,
+            zkapp_txid: Txid::all_zeros(),
+            signature: hex::encode([i; 64]),
+        }
+    }
+
+    #[test]
+    fn test_merkle_tree() {
+        for size in 1..=9u8 {
+            let hashes: Vec<_> = (0..size).map(|i| leaf(i).hash().unwrap()).collect();
+            let root = hex::encode(root(&hashes));
+            for index in 0..size as usize {
+                let path = path(index, &hashes);
+                let size = size as u64;
+                assert!(verify_path(index as u64, size, hashes[index], &path, &root));
+                // the path doesn't prove anything else
+                assert!(!verify_path(index as u64, size, [0; 32], &path, &root));
+                if size > 1 {
+                    let other = (index as u64 + 1) % size;
+                    assert!(!verify_path(other, size, hashes[index], &path, &root));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_transparency_log() {
+        let dir = TempDir::new("zkbitcoin_transparency").unwrap();
+        let log = TransparencyLog::open(dir.path()).unwrap();
+        for i in 0..5 {
+            assert_eq!(log.append(leaf(i)).unwrap(), i as u64);
+        }
+        let pubkey = log.pubkey();
+
+        // the log survives restarts
+        drop(log);
+        let log = TransparencyLog::open(dir.path()).unwrap();
+        let head = log.tree_head();
+        assert_eq!(head.tree_size, 5);
+        head.verify(Some(&pubkey)).unwrap();
+
+        let mut proof = log.prove(leaf(3).txid, 0).unwrap();
+        assert_eq!(proof.leaf_index, 3);
+        proof.verify(Some(&pubkey)).unwrap();
+        assert!(log.prove(leaf(7).txid, 0).is_err());
+
+        // a signature off the record can't be proven to be in the log
+        proof.leaf.signature = hex::encode([9; 64]);
+        assert!(proof.verify(Some(&pubkey)).is_err());
+    }
+}
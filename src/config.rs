@@ -0,0 +1,653 @@
+//! Configuration.
+//!
+//! The keys of the zkBitcoin committee, the address of its orchestrator, and the fee it takes
+//! are not hardcoded, so that alternative deployments (test committees, forks, private instances)
+//! can run without recompiling. They are read from a JSON file (see [ProtocolConfig::path]),
+//! and can then be overridden with environment variables (see [ProtocolConfig::load]).
+//!
+//! Separately, users can save the options they would otherwise pass to every `zkbtc` invocation
+//! in a TOML file (see [UserConfig]).
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{Amount, PublicKey};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "node")]
+use crate::json_rpc_stuff::RetryPolicy;
+use crate::{
+    constants::{
+        DEFAULT_FEE_ZKBITCOIN_SAT, DEFAULT_ORCHESTRATOR_ADDRESS, DEFAULT_ZKBITCOIN_FEE_PUBKEY,
+        DEFAULT_ZKBITCOIN_PUBKEY,
+    },
+    p2tr_script_to, zkbitcoin_folder,
+};
+
+//
+// Protocol configuration
+//
+
+/// The protocol configuration in use, once set (see [protocol_config]).
+static PROTOCOL_CONFIG: OnceLock<ProtocolConfig> = OnceLock::new();
+
+/// The parameters of a zkBitcoin deployment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProtocolConfig {
+    /// The (hex-encoded) public key of the committee, which zkapps are locked to.
+    pub zkbitcoin_pubkey: String,
+
+    /// The (hex-encoded) public key that receives the zkBitcoin fee.
+    pub zkbitcoin_fee_pubkey: String,
+
+    /// The address of the orchestrator.
+    pub orchestrator_address: String,
+
+    /// The fee payable to the zkBitcoin fund on every use of a zkapp (in satoshis).
+    pub fee_sat: u64,
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            zkbitcoin_pubkey: DEFAULT_ZKBITCOIN_PUBKEY.to_string(),
+            zkbitcoin_fee_pubkey: DEFAULT_ZKBITCOIN_FEE_PUBKEY.to_string(),
+            orchestrator_address: DEFAULT_ORCHESTRATOR_ADDRESS.to_string(),
+            fee_sat: DEFAULT_FEE_ZKBITCOIN_SAT,
+        }
+    }
+}
+
+impl ProtocolConfig {
+    /// Returns the path of the configuration file:
+    /// `ZKBITCOIN_PROTOCOL_CONFIG` if set, or `~/.zkbitcoin/protocol.json` otherwise.
+    pub fn path() -> PathBuf {
+        match std::env::var("ZKBITCOIN_PROTOCOL_CONFIG") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => zkbitcoin_folder().join("protocol.json"),
+        }
+    }
+
+    /// Loads the configuration from a file (missing fields take their default value).
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("couldn't open protocol config {}", path.display()))?;
+        serde_json::from_reader(file)
+            .with_context(|| format!("couldn't parse protocol config {}", path.display()))
+    }
+
+    /// Loads the configuration from [ProtocolConfig::path] (if the file exists),
+    /// then overrides it with the `ZKBITCOIN_PUBKEY`, `ZKBITCOIN_FEE_PUBKEY`,
+    /// `ZKBITCOIN_ORCHESTRATOR_ADDRESS`, and `ZKBITCOIN_FEE_SAT` environment variables (if set).
+    pub fn load() -> Result<Self> {
+        let path = Self::path();
+        let mut config = if path.exists() {
+            Self::from_file(&path)?
+        } else {
+            Self::default()
+        };
+        config.override_with(|var| std::env::var(var).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Overrides the configuration with the variables returned by `var`.
+    fn override_with(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<()> {
+        if let Some(pubkey) = var("ZKBITCOIN_PUBKEY") {
+            self.zkbitcoin_pubkey = pubkey;
+        }
+        if let Some(pubkey) = var("ZKBITCOIN_FEE_PUBKEY") {
+            self.zkbitcoin_fee_pubkey = pubkey;
+        }
+        if let Some(address) = var("ZKBITCOIN_ORCHESTRATOR_ADDRESS") {
+            self.orchestrator_address = address;
+        }
+        if let Some(fee) = var("ZKBITCOIN_FEE_SAT") {
+            self.fee_sat = fee.parse().context("ZKBITCOIN_FEE_SAT is not an amount")?;
+        }
+        Ok(())
+    }
+
+    /// Ensures that the configuration is well-formed.
+    pub fn validate(&self) -> Result<()> {
+        PublicKey::from_str(&self.zkbitcoin_pubkey)
+            .context("the zkBitcoin public key is not a valid public key")?;
+        let fee_pubkey = PublicKey::from_str(&self.zkbitcoin_fee_pubkey)
+            .context("the zkBitcoin fee public key is not a valid public key")?;
+        ensure!(
+            !self.orchestrator_address.is_empty(),
+            "the orchestrator address can't be empty"
+        );
+        ensure!(
+            self.fee() >= p2tr_script_to(fee_pubkey).dust_value(),
+            "the zkBitcoin fee can't be dust"
+        );
+        Ok(())
+    }
+
+    /// The fee payable to the zkBitcoin fund on every use of a zkapp.
+    pub fn fee(&self) -> Amount {
+        Amount::from_sat(self.fee_sat)
+    }
+}
+
+/// Sets the protocol configuration used by the rest of the library.
+/// This can only be done once, before the configuration is first used.
+pub fn set_protocol_config(config: ProtocolConfig) -> Result<()> {
+    config.validate()?;
+    PROTOCOL_CONFIG
+        .set(config)
+        .ok()
+        .context("the protocol config was already set")
+}
+
+/// Returns the protocol configuration in use
+/// (the default one if none was set with [set_protocol_config]).
+pub fn protocol_config() -> &'static ProtocolConfig {
+    PROTOCOL_CONFIG.get_or_init(ProtocolConfig::default)
+}
+
+//
+// Fee schedule
+//
+
+/// How the committee computes the fee it takes on every use of a zkapp,
+/// from the amount withdrawn to the recipients (set in the `fee_schedule` of the committee configuration).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FeeSchedule {
+    /// The same fee on every use.
+    Fixed { sats: u64 },
+
+    /// A share of the amount withdrawn (in basis points, i.e. hundredths of a percent),
+    /// but no less than `min_sats`.
+    Percentage { basis_points: u64, min_sats: u64 },
+
+    /// A fee depending on the amount withdrawn: the one of the first tier it doesn't exceed.
+    Tiered { tiers: Vec<FeeTier> },
+}
+
+/// A tier of a [FeeSchedule::Tiered] schedule.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeTier {
+    /// The largest amount withdrawn (in satoshis) the tier applies to, or none for the last tier.
+    pub up_to_sats: Option<u64>,
+
+    /// The fee of the tier (in satoshis).
+    pub sats: u64,
+}
+
+impl Default for FeeSchedule {
+    /// The fee of the protocol configuration (see [ProtocolConfig::fee]).
+    fn default() -> Self {
+        Self::Fixed {
+            sats: protocol_config().fee_sat,
+        }
+    }
+}
+
+impl FeeSchedule {
+    /// Ensures that the schedule never asks for a dust fee, and covers any amount.
+    pub fn validate(&self) -> Result<()> {
+        let fee_pubkey = PublicKey::from_str(&protocol_config().zkbitcoin_fee_pubkey)
+            .context("the zkBitcoin fee public key is not a valid public key")?;
+        let dust = p2tr_script_to(fee_pubkey).dust_value().to_sat();
+        match self {
+            Self::Fixed { sats } => ensure!(*sats >= dust, "the fee can't be dust"),
+            Self::Percentage {
+                basis_points,
+                min_sats,
+            } => {
+                ensure!(
+                    *basis_points <= 10_000,
+                    "the fee can't be more than 100% of the amount withdrawn"
+                );
+                ensure!(*min_sats >= dust, "the minimum fee can't be dust");
+            }
+            Self::Tiered { tiers } => {
+                let (last, tiers_before) = tiers
+                    .split_last()
+                    .context("a tiered fee schedule needs at least one tier")?;
+                ensure!(
+                    last.up_to_sats.is_none(),
+                    "the last tier of a fee schedule must apply to any amount"
+                );
+                let mut prev = None;
+                for tier in tiers_before {
+                    let up_to = tier
+                        .up_to_sats
+                        .context("only the last tier of a fee schedule can apply to any amount")?;
+                    ensure!(
+                        prev < Some(up_to),
+                        "the tiers of a fee schedule must be sorted by amount"
+                    );
+                    prev = Some(up_to);
+                }
+                ensure!(
+                    tiers.iter().all(|tier| tier.sats >= dust),
+                    "the fee of a tier can't be dust"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The fee taken when `amount` is withdrawn to the recipients.
+    pub fn fee(&self, amount: Amount) -> Amount {
+        let sats = match self {
+            Self::Fixed { sats } => *sats,
+            Self::Percentage {
+                basis_points,
+                min_sats,
+            } => {
+                let share = u128::from(amount.to_sat()) * u128::from(*basis_points) / 10_000;
+                (share as u64).max(*min_sats)
+            }
+            Self::Tiered { tiers } => tiers
+                .iter()
+                .find(|tier| !tier.up_to_sats.is_some_and(|up_to| amount.to_sat() > up_to))
+                .or(tiers.last())
+                .map_or(0, |tier| tier.sats),
+        };
+        Amount::from_sat(sats)
+    }
+}
+
+/// The fee the committee would take on a use of a zkapp, returned to Bob before he makes his request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeQuote {
+    /// The amount withdrawn to the recipients.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub amount: Amount,
+
+    /// The fee taken.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub fee: Amount,
+
+    /// The schedule the fee was computed with.
+    pub schedule: FeeSchedule,
+
+    /// A Lightning invoice for the fee, when asked for: paying it instead of the fee output
+    /// (which the transaction then doesn't have) is what the orchestrator waits for before signing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invoice: Option<LightningInvoice>,
+}
+
+/// A Lightning invoice issued by the orchestrator, for Bob to pay the committee's fee off chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightningInvoice {
+    /// The BOLT11 payment request.
+    pub bolt11: String,
+
+    /// The hex-encoded payment hash, which Bob's request refers to (see `BobRequest::fee_payment_hash`).
+    pub payment_hash: String,
+
+    /// The amount to pay.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub amount: Amount,
+
+    /// When the invoice expires (UNIX timestamp in seconds).
+    pub expires_at: u64,
+}
+
+impl FeeQuote {
+    /// Quotes the fee of `schedule` for `amount`.
+    pub fn new(schedule: &FeeSchedule, amount: Amount) -> Self {
+        Self {
+            amount,
+            fee: schedule.fee(amount),
+            schedule: schedule.clone(),
+            invoice: None,
+        }
+    }
+}
+
+//
+// User configuration
+//
+
+#[cfg(feature = "node")]
+/// The options of `zkbtc` that can be saved in `~/.zkbitcoin/config.toml`
+/// (or in the file pointed to by `ZKBITCOIN_CONFIG`).
+///
+/// Most options are the default values of the command line arguments read from an environment variable
+/// (see [UserConfig::arg_defaults]), so that options passed on the command line take precedence over the environment,
+/// which takes precedence over the configuration file. The environment is never modified.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    /// How to reach the Bitcoin node.
+    pub rpc: RpcConfig,
+
+    /// The address of the orchestrator, overriding the one of the protocol configuration
+    /// (unless `ZKBITCOIN_ORCHESTRATOR_ADDRESS` is set, see [UserConfig::configure_protocol]).
+    /// Commands can still be pointed to another orchestrator (e.g. with `ENDPOINT`).
+    pub orchestrator_address: Option<String>,
+
+    /// The network to use: `mainnet` or `testnet` (unless `MAINNET` or `REGTEST` is set, see [UserConfig::network]).
+    pub network: Option<ConfigNetwork>,
+
+    /// Fee settings.
+    pub fees: FeeConfig,
+
+    /// Where artifacts are kept.
+    pub dirs: DirsConfig,
+
+    /// The proving service proving zkapps, instead of this machine.
+    pub prover: ProverConfig,
+}
+
+#[cfg(feature = "node")]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    /// The wallet name of the RPC full node (`RPC_WALLET`).
+    pub wallet: Option<String>,
+
+    /// The `http(s)://address:port` of the RPC full node (`RPC_ADDRESS`).
+    pub address: Option<String>,
+
+    /// The `user:password` of the RPC full node (`RPC_AUTH`).
+    pub auth: Option<String>,
+
+    /// The cookie file of the RPC full node, if no `auth` is given (`RPC_COOKIE_FILE`).
+    pub cookie_file: Option<PathBuf>,
+
+    /// How requests to the RPC full node are retried.
+    pub retry: RetryPolicy,
+}
+
+#[cfg(feature = "node")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigNetwork {
+    Mainnet,
+    Testnet,
+}
+
+#[cfg(feature = "node")]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeConfig {
+    /// The fee rate (in sat/vB) to pay for transactions (`ZKBITCOIN_FEE_RATE`).
+    pub fee_rate: Option<u64>,
+
+    /// The number of blocks transactions should be confirmed within,
+    /// when estimating the fee rate (`ZKBITCOIN_CONF_TARGET`).
+    pub conf_target: Option<u16>,
+}
+
+#[cfg(feature = "node")]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirsConfig {
+    /// The directory where the orchestrator persists requests (`ZKBITCOIN_STORAGE_DIR`).
+    pub storage_dir: Option<PathBuf>,
+
+    /// The directory of zkapp validation hooks of the orchestrator (`ZKBITCOIN_HOOKS_DIR`).
+    pub hooks_dir: Option<PathBuf>,
+
+    /// The SQLite database of the zkapp indexer (`ZKBITCOIN_INDEX`).
+    pub index_path: Option<PathBuf>,
+}
+
+#[cfg(feature = "node")]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProverConfig {
+    /// The URL of the proving service (`ZKBITCOIN_PROVER_URL`).
+    pub url: Option<String>,
+
+    /// The public key of the proving service, to encrypt proof inputs to (`ZKBITCOIN_PROVER_PUBKEY`).
+    pub pubkey: Option<String>,
+}
+
+#[cfg(feature = "node")]
+impl UserConfig {
+    /// Returns the path of the configuration file:
+    /// `ZKBITCOIN_CONFIG` if set, or `~/.zkbitcoin/config.toml` otherwise.
+    pub fn path() -> PathBuf {
+        match std::env::var("ZKBITCOIN_CONFIG") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => zkbitcoin_folder().join("config.toml"),
+        }
+    }
+
+    /// Loads the configuration from a file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("couldn't read config {}", path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("couldn't parse config {}", path.display()))
+    }
+
+    /// Loads the configuration from [UserConfig::path], if the file exists.
+    pub fn load() -> Result<Option<Self>> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        Self::from_file(&path).map(Some)
+    }
+
+    /// Returns the environment variables whose command line arguments get their default value from the configuration,
+    /// along with these values.
+    pub fn arg_defaults(&self) -> Vec<(&'static str, String)> {
+        let path_to_string = |path: &PathBuf| path.display().to_string();
+        let vars = [
+            ("RPC_WALLET", self.rpc.wallet.clone()),
+            ("RPC_ADDRESS", self.rpc.address.clone()),
+            ("RPC_AUTH", self.rpc.auth.clone()),
+            (
+                "RPC_COOKIE_FILE",
+                self.rpc.cookie_file.as_ref().map(path_to_string),
+            ),
+            (
+                "ZKBITCOIN_FEE_RATE",
+                self.fees.fee_rate.map(|fee_rate| fee_rate.to_string()),
+            ),
+            (
+                "ZKBITCOIN_CONF_TARGET",
+                self.fees.conf_target.map(|target| target.to_string()),
+            ),
+            (
+                "ZKBITCOIN_STORAGE_DIR",
+                self.dirs.storage_dir.as_ref().map(path_to_string),
+            ),
+            (
+                "ZKBITCOIN_HOOKS_DIR",
+                self.dirs.hooks_dir.as_ref().map(path_to_string),
+            ),
+            (
+                "ZKBITCOIN_INDEX",
+                self.dirs.index_path.as_ref().map(path_to_string),
+            ),
+            ("ZKBITCOIN_PROVER_URL", self.prover.url.clone()),
+            ("ZKBITCOIN_PROVER_PUBKEY", self.prover.pubkey.clone()),
+        ];
+        vars.into_iter()
+            .filter_map(|(var, value)| value.map(|value| (var, value)))
+            .collect()
+    }
+
+    /// Returns the network of the configuration, unless the environment picks one
+    /// (to pass to [crate::set_network] before anything else).
+    pub fn network(&self) -> Option<bitcoin::Network> {
+        if std::env::var_os("MAINNET").is_some() || std::env::var_os("REGTEST").is_some() {
+            return None;
+        }
+        self.network.map(|network| match network {
+            ConfigNetwork::Mainnet => bitcoin::Network::Bitcoin,
+            ConfigNetwork::Testnet => bitcoin::Network::Testnet,
+        })
+    }
+
+    /// Overrides the orchestrator address of the protocol configuration with the one of this configuration
+    /// (unless `ZKBITCOIN_ORCHESTRATOR_ADDRESS` is set), so that there's a single address to fall back to.
+    pub fn configure_protocol(&self, protocol_config: &mut ProtocolConfig) {
+        if let Some(address) = &self.orchestrator_address {
+            if std::env::var_os("ZKBITCOIN_ORCHESTRATOR_ADDRESS").is_none() {
+                protocol_config.orchestrator_address = address.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_default_config_is_valid() {
+        ProtocolConfig::default().validate().unwrap();
+    }
+
+    #[test]
+    fn test_config_from_file() {
+        let tmp_dir = TempDir::new("zkbitcoin_").unwrap();
+        let path = tmp_dir.path().join("protocol.json");
+
+        // missing fields take their default value
+        std::fs::write(
+            &path,
+            r#"{"orchestrator_address": "http://127.0.0.1:8888", "fee_sat": 1000}"#,
+        )
+        .unwrap();
+        let config = ProtocolConfig::from_file(&path).unwrap();
+        assert_eq!(config.orchestrator_address, "http://127.0.0.1:8888");
+        assert_eq!(config.fee(), Amount::from_sat(1000));
+        assert_eq!(config.zkbitcoin_pubkey, DEFAULT_ZKBITCOIN_PUBKEY);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_config_overrides() {
+        let vars = HashMap::from([
+            ("ZKBITCOIN_PUBKEY", DEFAULT_ZKBITCOIN_FEE_PUBKEY),
+            ("ZKBITCOIN_FEE_SAT", "600"),
+        ]);
+        let mut config = ProtocolConfig::default();
+        config
+            .override_with(|var| vars.get(var).map(|value| value.to_string()))
+            .unwrap();
+        assert_eq!(config.zkbitcoin_pubkey, DEFAULT_ZKBITCOIN_FEE_PUBKEY);
+        assert_eq!(config.fee_sat, 600);
+        assert_eq!(config.orchestrator_address, DEFAULT_ORCHESTRATOR_ADDRESS);
+
+        // invalid values are rejected
+        config.zkbitcoin_pubkey = "nope".to_string();
+        assert!(config.validate().is_err());
+        config.zkbitcoin_pubkey = DEFAULT_ZKBITCOIN_PUBKEY.to_string();
+        config.fee_sat = 1;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_fee_schedule() {
+        let amount = Amount::from_sat;
+
+        let schedule: FeeSchedule =
+            serde_json::from_str(r#"{"kind": "percentage", "basis_points": 50, "min_sats": 1000}"#)
+                .unwrap();
+        schedule.validate().unwrap();
+        assert_eq!(schedule.fee(amount(100_000)), amount(1000));
+        assert_eq!(schedule.fee(amount(1_000_000)), amount(5000));
+
+        let schedule: FeeSchedule = serde_json::from_str(
+            r#"{"kind": "tiered", "tiers": [
+                {"up_to_sats": 100000, "sats": 1000},
+                {"up_to_sats": 1000000, "sats": 2000},
+                {"up_to_sats": null, "sats": 5000}
+            ]}"#,
+        )
+        .unwrap();
+        schedule.validate().unwrap();
+        assert_eq!(schedule.fee(amount(100_000)), amount(1000));
+        assert_eq!(schedule.fee(amount(100_001)), amount(2000));
+        assert_eq!(schedule.fee(amount(10_000_000)), amount(5000));
+
+        // schedules that don't cover every amount, or take dust, are rejected
+        let tiered = |tiers: &[(Option<u64>, u64)]| FeeSchedule::Tiered {
+            tiers: tiers
+                .iter()
+                .map(|&(up_to_sats, sats)| FeeTier { up_to_sats, sats })
+                .collect(),
+        };
+        assert!(tiered(&[]).validate().is_err());
+        assert!(tiered(&[(Some(1000), 1000)]).validate().is_err());
+        assert!(
+            tiered(&[(Some(1000), 1000), (Some(10), 1000), (None, 1000)])
+                .validate()
+                .is_err()
+        );
+        assert!(FeeSchedule::Fixed { sats: 1 }.validate().is_err());
+        FeeSchedule::default().validate().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "node")]
+    fn test_user_config() {
+        let config: UserConfig = toml::from_str(
+            r#"
+            orchestrator_address = "http://127.0.0.1:8888"
+            network = "mainnet"
+
+            [rpc]
+            wallet = "mywallet"
+            address = "http://127.0.0.1:18331"
+
+            [rpc.retry]
+            max_retries = 5
+
+            [fees]
+            conf_target = 2
+
+            [dirs]
+            storage_dir = "/var/lib/zkbitcoin"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.rpc.retry.max_retries, 5);
+        assert_eq!(
+            config.rpc.retry.timeout_secs,
+            RetryPolicy::default().timeout_secs
+        );
+
+        let defaults = config.arg_defaults();
+        assert_eq!(
+            defaults,
+            vec![
+                ("RPC_WALLET", "mywallet".to_string()),
+                ("RPC_ADDRESS", "http://127.0.0.1:18331".to_string()),
+                ("ZKBITCOIN_CONF_TARGET", "2".to_string()),
+                ("ZKBITCOIN_STORAGE_DIR", "/var/lib/zkbitcoin".to_string()),
+            ]
+        );
+        assert_eq!(config.network(), Some(bitcoin::Network::Bitcoin));
+
+        // the orchestrator address is the one of the protocol configuration
+        let mut protocol_config = ProtocolConfig::default();
+        config.configure_protocol(&mut protocol_config);
+        assert_eq!(
+            protocol_config.orchestrator_address,
+            "http://127.0.0.1:8888"
+        );
+
+        // an empty file is a valid config, and typos are caught
+        assert!(toml::from_str::<UserConfig>("")
+            .unwrap()
+            .arg_defaults()
+            .is_empty());
+        assert!(toml::from_str::<UserConfig>("[rpc]\nwalet = \"x\"").is_err());
+    }
+}
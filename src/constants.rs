@@ -1,15 +1,16 @@
 //! Constants used in the zkBitcoin library.
 
-/// The public key of zkBitcoin.
+/// The default public key of zkBitcoin (see [crate::config::ProtocolConfig]).
 // TODO: do we ever need this pubkey or can we just use the address?
-pub const ZKBITCOIN_PUBKEY: &str =
+pub const DEFAULT_ZKBITCOIN_PUBKEY: &str =
     "02bd84fcbb2ad2f274079c68580a5a1e234bd88ed6ee38f2b33a303fd38a104942"; // TODO: change this to a real pubkey in prod
 
-// The address is associated to [ZKBITCOIN_FEE_PUBKEY]
+// The address is associated to [DEFAULT_ZKBITCOIN_FEE_PUBKEY]
 // TODO: obviously change this in prod
 //pub const ZKBITCOIN_FEE_ADDRESS: &str = "tb1q6nkpv2j9lxrm6h3w4skrny3thswgdcca8cx9k6";
 
-pub const ZKBITCOIN_FEE_PUBKEY: &str =
+/// The default public key receiving the zkBitcoin fee (see [crate::config::ProtocolConfig]).
+pub const DEFAULT_ZKBITCOIN_FEE_PUBKEY: &str =
     "037299ffd702cdc0537d8bb92f216ccc6058ad804741c4293cc82288f453dadadc"; // TODO: change this to a real pubkey in prod
 
 /// Number of confirmation required for a transaction to be considered final.
@@ -18,10 +19,11 @@ pub const MINIMUM_CONFIRMATIONS: usize = 0; // TODO: bad in prod?
 /// The JSON-RPC version to use with bitcoind.
 pub const BITCOIN_JSON_RPC_VERSION: &str = "1.0";
 
-/// The fee payable to the zkBitcoin fund.
-pub const FEE_ZKBITCOIN_SAT: u64 = 546; // see https://whattodevnow.medium.com/how-to-calculate-the-real-minimum-satoshis-amount-for-a-utxo-5941628ad3e8
+/// The default fee payable to the zkBitcoin fund (see [crate::config::ProtocolConfig]).
+pub const DEFAULT_FEE_ZKBITCOIN_SAT: u64 = 546; // see https://whattodevnow.medium.com/how-to-calculate-the-real-minimum-satoshis-amount-for-a-utxo-5941628ad3e8
 
-pub const ORCHESTRATOR_ADDRESS: &str = "http://64.23.171.48:8888";
+/// The default address of the orchestrator (see [crate::config::ProtocolConfig]).
+pub const DEFAULT_ORCHESTRATOR_ADDRESS: &str = "http://64.23.171.48:8888";
 
 pub const CIRCOM_ETH_PRIME: &str =
     "21888242871839275222246405745257275088548364400416034343698204186575808495617";
@@ -37,3 +39,19 @@ pub const STATELESS_ZKAPP_PUBLIC_INPUT_LEN: usize = 1 /* truncated txid */;
 
 /// The expected number of public inputs for a stateful zkapp.
 pub const STATEFUL_ZKAPP_PUBLIC_INPUT_LEN: usize = 1 * 2 /* new state + prev state */ + 1 /* truncated txid */ + 1 /* amount_out */ + 1 /* amount_in */;
+
+/// The expected number of public inputs for a stateful zkapp that can be upgraded to another circuit:
+/// the ones of a stateful zkapp, followed by the verifier key it is upgraded to (see [crate::truncate_vk_hash]).
+pub const UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN: usize =
+    STATEFUL_ZKAPP_PUBLIC_INPUT_LEN + 1 /* upgrade_to */;
+
+/// The expected number of public inputs for an upgradable zkapp with an administrator:
+/// the ones of an upgradable zkapp, followed by the admin key recorded on-chain with it (see [crate::zkapp_data::ZkappData::admin]).
+pub const ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN: usize =
+    UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN + 1 /* admin */;
+
+/// The `upgrade_to` public input of the spends of upgradable zkapps that don't upgrade them.
+pub const NO_UPGRADE: &str = "0";
+
+/// The length (in bytes) of the nonce binding a request to its zkapp UTXO (see [crate::bob_request::BobRequest::nonce]).
+pub const NONCE_LEN: usize = 32;
@@ -0,0 +1,354 @@
+//! Decoding of transactions, to see what they do with zkapps (see `zkbtc decode-tx`).
+//!
+//! Every output is labeled with what it is to zkBitcoin: funds locked in a zkapp (at the zkBitcoin address),
+//! the metadata of the zkapp (the `OP_RETURN` output with the hash of its verifier key, and its state if stateful),
+//! the fee paid to the zkBitcoin fund, or anything else (recipients, change, etc.).
+//! When the outputs spent by the transaction are known, the inputs spending zkapps are labeled too
+//! (recoverable zkapps, which aren't locked in the zkBitcoin address, are told by the committee's script in their witness).
+
+use std::str::FromStr;
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{taproot, Address, Amount, OutPoint, PublicKey, Transaction, TxOut, Txid};
+use serde::Serialize;
+
+use crate::{
+    chain::ChainBackend, config::protocol_config, get_network, p2tr_script_to, recovery::Recovery,
+    taproot_addr_from, zkapp_data::ZkappData,
+};
+
+//
+// Data structures
+//
+
+/// What an output is to zkBitcoin.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum OutputKind {
+    /// Funds locked in a zkapp.
+    Zkapp,
+
+    /// The metadata of the zkapp: the hex-encoded hash of its verifier key, its state (if it is stateful),
+    /// the hex-encoded hash of its name and the like (if it was deployed with some),
+    /// the public key of its administrator (if it has one), its timelock (if it has one, see [crate::timelock::Timelock]),
+    /// who can recover it after how many blocks (if it is recoverable, see [Recovery]),
+    /// and the hex-encoded key of its deployer (if it was deployed with one).
+    ZkappMetadata {
+        vk_hash: String,
+        state: Option<String>,
+        metadata_hash: Option<String>,
+        admin: Option<String>,
+        timelock: Option<String>,
+        recovery: Option<String>,
+        deployer: Option<String>,
+    },
+
+    /// The fee paid to the zkBitcoin fund.
+    Fee,
+
+    /// An `OP_RETURN` output that isn't the metadata of a zkapp, with its hex-encoded script.
+    OpReturn { script: String },
+
+    /// Any other output.
+    Other,
+}
+
+/// An output of a decoded transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DecodedOutput {
+    pub vout: u32,
+
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub value: Amount,
+
+    /// The address the output pays to (if it has one).
+    pub address: Option<String>,
+
+    #[serde(flatten)]
+    pub kind: OutputKind,
+}
+
+/// An input of a decoded transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DecodedInput {
+    /// The output spent.
+    pub previous_output: OutPoint,
+
+    pub sequence: u32,
+
+    /// The sighash type of the signature, if the input is spent with a taproot key (as zkapps are).
+    pub sighash_type: Option<String>,
+
+    /// The value of the output spent (if known).
+    #[serde(with = "bitcoin::amount::serde::as_sat::opt")]
+    pub value: Option<Amount>,
+
+    /// Whether the output spent is a zkapp (if known).
+    pub spends_zkapp: Option<bool>,
+}
+
+/// A transaction, broken down by what its inputs and outputs are to zkBitcoin.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DecodedTx {
+    pub txid: Txid,
+
+    pub vsize: usize,
+
+    pub inputs: Vec<DecodedInput>,
+
+    pub outputs: Vec<DecodedOutput>,
+
+    /// The fee paid to the zkBitcoin fund.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub zkbitcoin_fee: Amount,
+
+    /// The fee paid to miners (if the outputs spent are known).
+    #[serde(with = "bitcoin::amount::serde::as_sat::opt")]
+    pub network_fee: Option<Amount>,
+}
+
+impl DecodedTx {
+    /// Whether the transaction deploys (or updates) a zkapp.
+    pub fn has_zkapp(&self) -> bool {
+        self.outputs
+            .iter()
+            .any(|output| matches!(output.kind, OutputKind::ZkappMetadata { .. }))
+    }
+
+    /// Whether the transaction spends a zkapp (if the outputs spent are known).
+    pub fn spends_zkapp(&self) -> bool {
+        self.inputs
+            .iter()
+            .any(|input| input.spends_zkapp == Some(true))
+    }
+}
+
+//
+// Decoding
+//
+
+/// Decodes a transaction, given the outputs it spends (in the order of its inputs) if they are known.
+pub fn decode(tx: &Transaction, spent: Option<&[TxOut]>) -> Result<DecodedTx> {
+    if let Some(spent) = spent {
+        ensure!(
+            spent.len() == tx.input.len(),
+            "the transaction has {} inputs, but {} outputs spent were given",
+            tx.input.len(),
+            spent.len()
+        );
+    }
+
+    let zkbitcoin_pubkey = PublicKey::from_str(&protocol_config().zkbitcoin_pubkey)
+        .context("the zkBitcoin public key is not a valid public key")?;
+    let zkapp_script = p2tr_script_to(zkbitcoin_pubkey);
+    let fee_script = taproot_addr_from(&protocol_config().zkbitcoin_fee_pubkey)?.script_pubkey();
+    let committee_script = Recovery::committee_script()?;
+
+    // the zkapp created (if any), whose funds are locked in the zkBitcoin address unless it is recoverable
+    let created = tx
+        .output
+        .iter()
+        .filter(|output| output.script_pubkey.is_op_return())
+        .find_map(|output| ZkappData::from_script(&output.script_pubkey).ok());
+    let created_script = created.as_ref().map(ZkappData::script_pubkey).transpose()?;
+    let locks_zkapp = tx.output.iter().any(|output| {
+        output.script_pubkey == zkapp_script
+            || Some(&output.script_pubkey) == created_script.as_ref()
+    });
+
+    let inputs = tx
+        .input
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            let spent = spent.map(|spent| &spent[i]);
+            let committee_spend =
+                input.witness.len() == 3 && input.witness[1] == *committee_script.as_bytes();
+            let sighash_type = match input.witness.len() {
+                1 => Some(&input.witness[0]),
+                3 if committee_spend => Some(&input.witness[0]),
+                _ => None,
+            }
+            .and_then(|signature| taproot::Signature::from_slice(signature).ok())
+            .map(|signature| signature.hash_ty.to_string());
+            DecodedInput {
+                previous_output: input.previous_output,
+                sequence: input.sequence.to_consensus_u32(),
+                sighash_type,
+                value: spent.map(|output| output.value),
+                spends_zkapp: spent
+                    .map(|output| output.script_pubkey == zkapp_script || committee_spend),
+            }
+        })
+        .collect();
+
+    let outputs: Vec<_> = tx
+        .output
+        .iter()
+        .enumerate()
+        .map(|(vout, output)| {
+            let script = &output.script_pubkey;
+            let kind = if *script == zkapp_script || Some(script) == created_script.as_ref() {
+                OutputKind::Zkapp
+            } else if *script == fee_script {
+                OutputKind::Fee
+            } else if script.is_op_return() {
+                match metadata(script, locks_zkapp) {
+                    Some(data) => OutputKind::ZkappMetadata {
+                        vk_hash: hex::encode(data.vk_hash),
+                        state: data.state,
+                        metadata_hash: data.metadata_hash.map(hex::encode),
+                        admin: data.admin,
+                        timelock: data.timelock.map(|timelock| timelock.to_string()),
+                        recovery: data.recovery.map(|recovery| recovery.to_string()),
+                        deployer: data.deployer.map(|deployer| deployer.to_string()),
+                    },
+                    None => OutputKind::OpReturn {
+                        script: hex::encode(script.as_bytes()),
+                    },
+                }
+            } else {
+                OutputKind::Other
+            };
+            DecodedOutput {
+                vout: vout as u32,
+                value: output.value,
+                address: Address::from_script(script, get_network())
+                    .ok()
+                    .map(|address| address.to_string()),
+                kind,
+            }
+        })
+        .collect();
+
+    let zkbitcoin_fee = outputs
+        .iter()
+        .filter(|output| output.kind == OutputKind::Fee)
+        .map(|output| output.value)
+        .sum();
+    let network_fee = spent
+        .map(|spent| {
+            let spent: Amount = spent.iter().map(|output| output.value).sum();
+            let sent: Amount = tx.output.iter().map(|output| output.value).sum();
+            spent
+                .checked_sub(sent)
+                .context("the transaction sends more than it spends")
+        })
+        .transpose()?;
+
+    Ok(DecodedTx {
+        txid: tx.txid(),
+        vsize: tx.vsize(),
+        inputs,
+        outputs,
+        zkbitcoin_fee,
+        network_fee,
+    })
+}
+
+/// Decodes the metadata of a zkapp out of an `OP_RETURN` script,
+/// if the transaction locks funds in a zkapp and the script is the metadata of one.
+fn metadata(script: &bitcoin::ScriptBuf, locks_zkapp: bool) -> Option<ZkappData> {
+    if !locks_zkapp {
+        return None;
+    }
+    ZkappData::from_script(script).ok()
+}
+
+/// Fetches the outputs spent by a transaction (in the order of its inputs).
+pub async fn fetch_spent(chain: &dyn ChainBackend, tx: &Transaction) -> Result<Vec<TxOut>> {
+    let mut spent = Vec::with_capacity(tx.input.len());
+    for input in &tx.input {
+        let outpoint = input.previous_output;
+        let (prev_tx, _) = chain.get_transaction(outpoint.txid).await?;
+        let output = prev_tx
+            .output
+            .get(outpoint.vout as usize)
+            .with_context(|| format!("the output {outpoint} spent doesn't exist"))?;
+        spent.push(output.clone());
+    }
+    Ok(spent)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        absolute::LockTime, hashes::Hash, transaction::Version, ScriptBuf, Sequence, TxIn, Witness,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let zkbitcoin_pubkey = PublicKey::from_str(&protocol_config().zkbitcoin_pubkey).unwrap();
+        let zkapp_script = p2tr_script_to(zkbitcoin_pubkey);
+        let fee_script = taproot_addr_from(&protocol_config().zkbitcoin_fee_pubkey)
+            .unwrap()
+            .script_pubkey();
+        let output = |sats: u64, script_pubkey: ScriptBuf| TxOut {
+            value: Amount::from_sat(sats),
+            script_pubkey,
+        };
+
+        // a stateful zkapp spent, and updated
+        let mut witness = Witness::new();
+        witness.push([1; 65]);
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness,
+            }],
+            output: vec![
+                output(1000, fee_script),
+                output(5000, zkapp_script.clone()),
+                output(
+                    0,
+                    ZkappData::new([7; 32], Some("42".to_string()))
+                        .to_script()
+                        .unwrap(),
+                ),
+                output(3000, ScriptBuf::new_op_return(&[1, 2, 3])),
+            ],
+        };
+
+        let decoded = decode(&tx, None).unwrap();
+        assert!(decoded.has_zkapp());
+        assert!(!decoded.spends_zkapp());
+        assert_eq!(decoded.zkbitcoin_fee, Amount::from_sat(1000));
+        assert_eq!(decoded.network_fee, None);
+        assert_eq!(
+            decoded.inputs[0].sighash_type.as_deref(),
+            Some("SIGHASH_ALL")
+        );
+        assert_eq!(decoded.outputs[0].kind, OutputKind::Fee);
+        assert_eq!(decoded.outputs[1].kind, OutputKind::Zkapp);
+        assert_eq!(
+            decoded.outputs[2].kind,
+            OutputKind::ZkappMetadata {
+                vk_hash: hex::encode([7; 32]),
+                state: Some("42".to_string()),
+                metadata_hash: None,
+                admin: None,
+                timelock: None,
+                recovery: None,
+                deployer: None,
+            }
+        );
+        assert!(matches!(
+            decoded.outputs[3].kind,
+            OutputKind::OpReturn { .. }
+        ));
+
+        // knowing the outputs spent, the zkapp spent and the network fee are too
+        let spent = [output(10_000, zkapp_script)];
+        let decoded = decode(&tx, Some(&spent)).unwrap();
+        assert!(decoded.spends_zkapp());
+        assert_eq!(decoded.inputs[0].value, Some(Amount::from_sat(10_000)));
+        assert_eq!(decoded.network_fee, Some(Amount::from_sat(1000)));
+        assert!(decode(&tx, Some(&[])).is_err());
+    }
+}
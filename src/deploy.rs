@@ -0,0 +1,488 @@
+//! Deploying a zkapp (what `zkbtc deploy-zkapp` does), for other binaries and services to embed,
+//! and verifying that a deployed zkapp is the circuit it claims to be (what `zkbtc verify-deployment` does).
+//!
+//! ```no_run
+//! # async fn example(rpc_ctx: zkbitcoin::json_rpc_stuff::RpcCtx) -> anyhow::Result<()> {
+//! use zkbitcoin::{coin_selection::Funding, deploy::{self, Signer}, plonk::ProofSystemKind};
+//!
+//! let zkapp = deploy::prepare(
+//!     "examples/circuit/stateless.circom".as_ref(),
+//!     ProofSystemKind::Plonk,
+//!     None,
+//!     10_000,
+//!     None,
+//!     None,
+//!     None,
+//! )
+//! .await?;
+//! let deployed = zkapp.execute(&rpc_ctx, &Funding::default(), &Signer::Wallet).await?;
+//! println!("{deployed:?}");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::{Transaction, Txid};
+use log::info;
+use secp256k1::Keypair;
+
+use crate::{
+    alice_sign_tx::{generate_and_broadcast_transaction, generate_psbt},
+    amounts::check_deploy_amount,
+    bob_request::{extract_smart_contract_from_tx, SmartContract},
+    chain::ChainBackend,
+    circom_field_to_bytes,
+    coin_selection::Funding,
+    committee::policy::{register_policy, ZkappPolicy},
+    constants::{
+        ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN, STATEFUL_ZKAPP_PUBLIC_INPUT_LEN,
+        UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN,
+    },
+    hwi,
+    json_rpc_stuff::RpcCtx,
+    plonk::{self, ProofSystemKind},
+    proof_system,
+    recovery::Recovery,
+    snarkjs,
+    timelock::Timelock,
+    zkapp_data::{ZkappData, ZkappMetadata},
+    zkbitcoin_folder,
+};
+
+//
+// Signing
+//
+
+/// Who signs the transactions of the wallet (deploying a zkapp, or funding its spend).
+#[derive(Debug, Clone, Default)]
+pub enum Signer {
+    /// The wallet of the node signs and broadcasts them.
+    #[default]
+    Wallet,
+
+    /// They are returned as (base64-encoded) PSBTs processed by the wallet, for an external signer.
+    Psbt,
+
+    /// A hardware wallet signs them (through HWI), and the node broadcasts them.
+    HardwareWallet {
+        /// The fingerprint of the device to use, if several are connected.
+        fingerprint: Option<String>,
+    },
+
+    /// They are returned as (base64-encoded) PSBTs that no wallet processed, for an external signer
+    /// holding the keys of their inputs (no node is needed, see [crate::coin_selection::Funding::fund_without_wallet]).
+    /// Only spends can be signed this way.
+    External,
+}
+
+/// What happened to a transaction once signed (see [Signer]).
+#[derive(Debug, Clone)]
+pub enum Signed {
+    /// It was broadcast to the network.
+    Broadcast(Txid),
+
+    /// It is waiting to be signed, as a (base64-encoded) PSBT.
+    Psbt(String),
+
+    /// It only spends the zkapp, and is waiting for a sponsor to attach the inputs paying its fees (see [crate::sponsor]).
+    Sponsorable(Transaction),
+}
+
+//
+// Deploying
+//
+
+/// A zkapp ready to be deployed (see [prepare]).
+#[derive(Debug, Clone)]
+pub struct PreparedDeploy {
+    /// The verifier key of the circuit.
+    pub vk: plonk::VerifierKey,
+
+    /// The hash of `vk`, which is what gets committed on-chain.
+    pub vk_hash: [u8; 32],
+
+    /// The initial state of a stateful zkapp.
+    pub initial_state: Option<String>,
+
+    /// The amount locked in the zkapp.
+    pub satoshi_amount: u64,
+
+    /// The name (and the like) of the zkapp, whose hash gets committed on-chain.
+    pub metadata: Option<ZkappMetadata>,
+
+    /// The public key of the administrator of an administered zkapp, committed on-chain (see [ZkappData::admin]).
+    pub admin: Option<String>,
+
+    /// When the zkapp can be spent, committed on-chain and honored by the committee (see [ZkappData::timelock]).
+    pub timelock: Option<Timelock>,
+
+    /// Who can recover the zkapp should the committee stop signing, committed on-chain (see [PreparedDeploy::recoverable]).
+    pub recovery: Option<Recovery>,
+
+    /// The key the zkapp's policy is registered with, committed on-chain (see [PreparedDeploy::deployed_by]).
+    pub deployer: Option<Keypair>,
+}
+
+/// Compiles a circuit for `proof_system` (for circom circuits, see [proof_system::for_circuit]),
+/// and ensures that it can be deployed as a zkapp (with an initial state if the circuit is stateful,
+/// and an admin key if it is administered), optionally with a `timelock`.
+/// With groth16, the prover key the users of the zkapp need is created next to the circuit
+/// (see [snarkjs::groth16_prover_key_path]), unless it already exists.
+pub async fn prepare(
+    circom_circuit_path: &Path,
+    proof_system: ProofSystemKind,
+    initial_state: Option<String>,
+    satoshi_amount: u64,
+    metadata: Option<ZkappMetadata>,
+    admin: Option<String>,
+    timelock: Option<Timelock>,
+) -> Result<PreparedDeploy> {
+    // check the amount and the metadata before spending time compiling
+    check_deploy_amount(satoshi_amount)?;
+    if let Some(metadata) = &metadata {
+        metadata.validate()?;
+    }
+    if let Some(admin) = &admin {
+        circom_field_to_bytes(admin)
+            .with_context(|| format!("the admin key {admin} is not a field element"))?;
+    }
+    if let Some(timelock) = &timelock {
+        timelock.validate()?;
+    }
+
+    // compile to get VK (and its digest)
+    let vk = proof_system::for_circuit(circom_circuit_path, proof_system)?
+        .verifier_key(circom_circuit_path)
+        .await?;
+    let vk_hash = vk.hash();
+
+    // (zkVM programs check their public inputs themselves)
+    if let Some(n_public) = vk.n_public() {
+        check_public_inputs(n_public, initial_state.as_deref(), admin.is_some())?;
+    } else {
+        ensure!(
+            admin.is_none(),
+            "only circom circuits can be deployed with an admin key"
+        );
+    }
+
+    Ok(PreparedDeploy {
+        vk,
+        vk_hash,
+        initial_state,
+        satoshi_amount,
+        metadata,
+        admin,
+        timelock,
+        recovery: None,
+        deployer: None,
+    })
+}
+
+/// Ensures that a circuit is either a stateless zkapp (expecting the txid only),
+/// or a stateful zkapp (expecting its state, the txid, and the amounts moved) given an initial state,
+/// which is given an admin key if (and only if) it is administered.
+fn check_public_inputs(
+    num_public_inputs: usize,
+    initial_state: Option<&str>,
+    has_admin: bool,
+) -> Result<()> {
+    ensure!(
+        num_public_inputs > 0,
+        "the circuit must have at least one public input (the txid)"
+    );
+
+    // sanity check for stateful zkapps
+    if num_public_inputs > 1 {
+        // for now we only allow states of a single element
+        // (followed by the VK it's upgraded to, if it can be, and by its admin key, if it has one)
+        ensure!(
+            matches!(
+                num_public_inputs,
+                STATEFUL_ZKAPP_PUBLIC_INPUT_LEN
+                    | UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN
+                    | ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN
+            ),
+            "the circuit passed does not expect the right number of public inputs for a stateful zkapp (we only allow states of a single field element)"
+        );
+        ensure!(
+            initial_state.is_some(),
+            "an initial state should be passed for a stateful zkapp"
+        );
+    }
+
+    let administered = num_public_inputs == ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN;
+    ensure!(
+        has_admin == administered,
+        "an admin key should be passed for an administered zkapp (whose circuit takes it last), and only for one"
+    );
+
+    Ok(())
+}
+
+/// Returns where the deployer key of the zkapps of a verifier key is kept (`~/.zkbitcoin/deployers/<vk hash>.key`),
+/// to register their policy with (see [PreparedDeploy::deployed_by]).
+pub fn deployer_key_path(vk_hash: &[u8; 32]) -> PathBuf {
+    zkbitcoin_folder()
+        .join("deployers")
+        .join(format!("{}.key", hex::encode(vk_hash)))
+}
+
+impl PreparedDeploy {
+    /// Makes the zkapp recoverable by its depositor after a delay, should the committee stop signing
+    /// (its funds are then locked in an output of their own, see [crate::recovery]).
+    pub fn recoverable(self, recovery: Recovery) -> Result<Self> {
+        recovery.validate()?;
+        Ok(Self {
+            recovery: Some(recovery),
+            ..self
+        })
+    }
+
+    /// Commits the key of the deployer on-chain, so that the orchestrator only accepts registrations
+    /// of the zkapp's policy signed with it (see [PreparedDeploy::register_policy]).
+    pub fn deployed_by(self, deployer: Keypair) -> Self {
+        Self {
+            deployer: Some(deployer),
+            ..self
+        }
+    }
+
+    /// What gets committed on-chain.
+    pub fn data(&self) -> ZkappData {
+        ZkappData {
+            metadata_hash: self.metadata.as_ref().map(ZkappMetadata::hash),
+            admin: self.admin.clone(),
+            timelock: self.timelock,
+            recovery: self.recovery,
+            deployer: self
+                .deployer
+                .as_ref()
+                .map(|deployer| deployer.x_only_public_key().0),
+            ..ZkappData::new(self.vk_hash, self.initial_state.clone())
+        }
+    }
+
+    /// Registers the policy of the zkapp with the orchestrator at `address`, unless it's the default one,
+    /// signed with the key of the deployer (which the zkapp must be deployed by, see [PreparedDeploy::deployed_by]).
+    pub async fn register_policy(&self, address: &str, policy: ZkappPolicy) -> Result<()> {
+        if policy == ZkappPolicy::default() {
+            return Ok(());
+        }
+        let deployer = self
+            .deployer
+            .as_ref()
+            .context("a policy can only be registered for a zkapp deployed with a deployer key")?;
+        policy.validate()?;
+        register_policy(address, &self.vk_hash, policy, deployer).await?;
+        info!("- registered the zkapp's policy with the orchestrator");
+        Ok(())
+    }
+
+    /// Creates the transaction deploying the zkapp, funded by the wallet behind `rpc_ctx` (following `funding`),
+    /// and has it signed by `signer`.
+    pub async fn execute(
+        &self,
+        rpc_ctx: &RpcCtx,
+        funding: &Funding,
+        signer: &Signer,
+    ) -> Result<Signed> {
+        let data = self.data();
+        let signed = match signer {
+            Signer::Wallet => {
+                let txid = generate_and_broadcast_transaction(
+                    rpc_ctx,
+                    &data,
+                    self.satoshi_amount,
+                    funding,
+                )
+                .await?;
+                Signed::Broadcast(txid)
+            }
+            Signer::Psbt => {
+                let psbt = generate_psbt(rpc_ctx, &data, self.satoshi_amount, funding).await?;
+                Signed::Psbt(psbt)
+            }
+            Signer::HardwareWallet { fingerprint } => {
+                let psbt = generate_psbt(rpc_ctx, &data, self.satoshi_amount, funding).await?;
+                let txid = hwi::sign_and_broadcast(rpc_ctx, fingerprint.as_deref(), &psbt).await?;
+                Signed::Broadcast(txid)
+            }
+            Signer::External => bail!("deploying a zkapp needs the wallet of a node"),
+        };
+        Ok(signed)
+    }
+}
+
+//
+// Verifying
+//
+
+/// A deployed zkapp, compared with a circuit compiled locally (see [verify]).
+#[derive(Debug, Clone)]
+pub struct Verification {
+    /// The zkapp as deployed on-chain.
+    pub deployed: SmartContract,
+
+    /// The number of confirmations of the deployment.
+    pub confirmations: usize,
+
+    /// The hash of the verifier key of the circuit.
+    pub vk_hash: [u8; 32],
+
+    /// Whether the circuit is the one of a stateful zkapp.
+    pub stateful: bool,
+
+    /// The state the zkapp is expected to have been deployed with, if any.
+    pub expected_state: Option<String>,
+}
+
+impl Verification {
+    /// Returns the reasons the deployed zkapp doesn't match the circuit (none if it does).
+    pub fn mismatches(&self) -> Vec<String> {
+        let mut mismatches = vec![];
+        if self.vk_hash != self.deployed.vk_hash {
+            mismatches.push(format!(
+                "the zkapp was deployed with the verifier key hash {}, but the circuit has {}",
+                hex::encode(self.deployed.vk_hash),
+                hex::encode(self.vk_hash)
+            ));
+        }
+        if self.stateful != self.deployed.is_stateful() {
+            let kind = |stateful| if stateful { "stateful" } else { "stateless" };
+            mismatches.push(format!(
+                "the zkapp was deployed as a {} zkapp, but the circuit is {}",
+                kind(self.deployed.is_stateful()),
+                kind(self.stateful)
+            ));
+        }
+        if let Some(expected_state) = &self.expected_state {
+            if self.deployed.state.as_ref() != Some(expected_state) {
+                mismatches.push(format!(
+                    "the zkapp was deployed with the state {}, not {expected_state}",
+                    self.deployed.state.as_deref().unwrap_or("(none)")
+                ));
+            }
+        }
+        mismatches
+    }
+}
+
+/// Compiles a circuit, and compares it with the zkapp deployed by the transaction `txid`
+/// (and with `expected_state`, if the zkapp is expected to be deployed with a given state).
+pub async fn verify(
+    chain: &dyn ChainBackend,
+    txid: Txid,
+    circom_circuit_path: &Path,
+    expected_state: Option<String>,
+) -> Result<Verification> {
+    let (transaction, confirmations) = chain.get_transaction(txid).await?;
+    let deployed = extract_smart_contract_from_tx(&transaction)
+        .with_context(|| format!("transaction {txid} doesn't deploy a zkapp"))?;
+
+    let vk = proof_system::for_circuit(
+        circom_circuit_path,
+        snarkjs::proof_system_of(circom_circuit_path),
+    )?
+    .verifier_key(circom_circuit_path)
+    .await?;
+    info!("- compiled {}", circom_circuit_path.display());
+
+    // (whether zkVM programs are stateful is only known once they run)
+    let stateful = vk.n_public().map_or(deployed.state.is_some(), |n_public| {
+        matches!(
+            n_public,
+            STATEFUL_ZKAPP_PUBLIC_INPUT_LEN
+                | UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN
+                | ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN
+        )
+    });
+    Ok(Verification {
+        deployed,
+        confirmations,
+        vk_hash: vk.hash(),
+        stateful,
+        expected_state,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    #[test]
+    fn test_check_public_inputs() {
+        // stateless zkapps only expect the txid
+        check_public_inputs(1, None, false).unwrap();
+
+        // stateful zkapps expect their state, the txid, and the amounts moved
+        check_public_inputs(STATEFUL_ZKAPP_PUBLIC_INPUT_LEN, Some("1"), false).unwrap();
+        assert!(check_public_inputs(STATEFUL_ZKAPP_PUBLIC_INPUT_LEN, None, false).is_err());
+
+        // and upgradable ones the VK they are upgraded to
+        check_public_inputs(UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN, Some("1"), false).unwrap();
+
+        // and administered ones their admin key, which must be given (and only to them)
+        check_public_inputs(ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN, Some("1"), true).unwrap();
+        assert!(
+            check_public_inputs(ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN, Some("1"), false).is_err()
+        );
+        assert!(check_public_inputs(UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN, Some("1"), true).is_err());
+
+        for n_public in [0, 2, 8] {
+            assert!(check_public_inputs(n_public, Some("1"), false).is_err());
+        }
+    }
+
+    #[test]
+    fn test_vk_proof_system() {
+        let vk: plonk::VerifierKey =
+            serde_json::from_str(include_str!("../examples/circuit/vk.json")).unwrap();
+        assert_eq!(vk.proof_system(), ProofSystemKind::Plonk);
+        assert!(vk.is_consistent());
+
+        // plonk verifier keys (and thus their hashes) are serialized as they always were
+        let plonk::VerifierKey::Plonk(inner) = &vk else {
+            unreachable!()
+        };
+        assert_eq!(
+            serde_json::to_string(&vk).unwrap(),
+            serde_json::to_string(inner).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_verification_mismatches() {
+        let mut verification = Verification {
+            deployed: SmartContract {
+                txid: Txid::all_zeros(),
+                locked_value: bitcoin::Amount::from_sat(1000),
+                vk_hash: [1; 32],
+                state: Some("1".to_string()),
+                metadata_hash: None,
+                admin: None,
+                timelock: None,
+                recovery: None,
+                deployer: None,
+                vout_of_zkbitcoin_utxo: 0,
+            },
+            confirmations: 1,
+            vk_hash: [1; 32],
+            stateful: true,
+            expected_state: Some("1".to_string()),
+        };
+        assert!(verification.mismatches().is_empty());
+
+        verification.vk_hash = [2; 32];
+        verification.expected_state = Some("2".to_string());
+        assert_eq!(verification.mismatches().len(), 2);
+
+        verification.stateful = false;
+        verification.expected_state = None;
+        assert_eq!(verification.mismatches().len(), 2);
+    }
+}
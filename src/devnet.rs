@@ -0,0 +1,302 @@
+//! A local development network (see `zkbtc devnet`):
+//! a regtest bitcoind with a funded wallet, and a committee (nodes and orchestrator) running in-process.
+
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::Network;
+use log::info;
+use tokio::task::JoinHandle;
+
+use crate::{
+    committee::{
+        self,
+        dealer::GeneratedCommittee,
+        node::{self, NodeState},
+        orchestrator::{self, Orchestrator},
+        signer::SignerKind,
+    },
+    config::{set_protocol_config, ProtocolConfig},
+    constants::BITCOIN_JSON_RPC_VERSION,
+    get_network,
+    json_rpc_stuff::{get_block_count, json_rpc_request, RpcCtx},
+    set_network,
+};
+
+/// The RPC port of the bitcoind started by the devnet.
+const BITCOIND_RPC_PORT: u16 = 18443;
+
+/// The `user:password` of the bitcoind started by the devnet.
+const BITCOIND_RPC_AUTH: &str = "zkbitcoin:zkbitcoin";
+
+/// The wallet created (or loaded) on bitcoind.
+const WALLET: &str = "devnet";
+
+/// The address the orchestrator listens on.
+const ORCHESTRATOR_ADDRESS: &str = "127.0.0.1:8888";
+
+/// How long to wait for bitcoind to accept RPC requests.
+const BITCOIND_STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Coinbase outputs can only be spent after that many blocks.
+const COINBASE_MATURITY: u64 = 100;
+
+/// How to set up a devnet.
+#[derive(Debug, Clone)]
+pub struct DevnetConfig {
+    /// Where bitcoind's data, the committee's keys, and the protocol configuration are kept.
+    pub dir: PathBuf,
+
+    /// The address of a regtest bitcoind to use, instead of starting one.
+    pub rpc_address: Option<String>,
+
+    /// The `user:password` of that bitcoind (its cookie file is used otherwise).
+    pub rpc_auth: Option<String>,
+
+    /// The number of members of the committee.
+    pub num: u16,
+
+    /// The number of members required for a signature.
+    pub threshold: u16,
+}
+
+impl DevnetConfig {
+    /// A 3-of-5 committee, and a bitcoind started in `dir`.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            rpc_address: None,
+            rpc_auth: None,
+            num: 5,
+            threshold: 3,
+        }
+    }
+}
+
+/// A running devnet (see [start]). Dropping it stops the bitcoind it started.
+pub struct Devnet {
+    /// The funded wallet.
+    pub rpc_ctx: RpcCtx,
+
+    /// The keys and configuration of the committee.
+    pub committee: GeneratedCommittee,
+
+    /// Where the protocol configuration (pointing to the committee) was written.
+    pub protocol_config_path: PathBuf,
+
+    bitcoind: Option<Child>,
+    tasks: Vec<JoinHandle<Result<SocketAddr>>>,
+}
+
+impl Drop for Devnet {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        if let Some(bitcoind) = &mut self.bitcoind {
+            let _ = bitcoind.kill();
+            let _ = bitcoind.wait();
+        }
+    }
+}
+
+/// Starts a devnet: bitcoind (unless one is given), a funded wallet, and the nodes and orchestrator of a new committee.
+/// This sets the network and the protocol configuration of the process, so it must be done before they are used.
+pub async fn start(config: &DevnetConfig) -> Result<Devnet> {
+    if get_network() != Network::Regtest {
+        set_network(Network::Regtest)?;
+    }
+    std::fs::create_dir_all(&config.dir)
+        .with_context(|| format!("couldn't create {}", config.dir.display()))?;
+
+    // start bitcoind, or connect to the one given
+    let (bitcoind, rpc_address, rpc_auth) = match &config.rpc_address {
+        Some(address) => (None, address.clone(), config.rpc_auth.clone()),
+        None => {
+            let bitcoind = start_bitcoind(&config.dir.join("bitcoind"))?;
+            let address = format!("http://127.0.0.1:{BITCOIND_RPC_PORT}");
+            (Some(bitcoind), address, Some(BITCOIND_RPC_AUTH.to_string()))
+        }
+    };
+    let node_ctx = RpcCtx::new(
+        Some(BITCOIN_JSON_RPC_VERSION),
+        None,
+        Some(rpc_address.clone()),
+        rpc_auth.clone(),
+    );
+    wait_for_bitcoind(&node_ctx).await?;
+
+    // fund a wallet
+    let rpc_ctx = RpcCtx::new(
+        Some(BITCOIN_JSON_RPC_VERSION),
+        Some(WALLET.to_string()),
+        Some(rpc_address),
+        rpc_auth,
+    );
+    fund_wallet(&node_ctx, &rpc_ctx).await?;
+
+    // deal the keys of the committee, and point the protocol configuration to it
+    let committee = committee::generate(config.num, config.threshold, 0, SignerKind::Frost)?;
+    let committee_dir = config.dir.join("committee");
+    std::fs::create_dir_all(&committee_dir)
+        .with_context(|| format!("couldn't create {}", committee_dir.display()))?;
+    committee.save(&committee_dir)?;
+    info!("- committee written to {}", committee_dir.display());
+
+    let protocol_config = ProtocolConfig {
+        zkbitcoin_pubkey: hex::encode(committee.pubkey()),
+        orchestrator_address: format!("http://{ORCHESTRATOR_ADDRESS}"),
+        ..ProtocolConfig::default()
+    };
+    let protocol_config_path = config.dir.join("protocol.json");
+    let file = std::fs::File::create(&protocol_config_path)
+        .with_context(|| format!("couldn't create {}", protocol_config_path.display()))?;
+    serde_json::to_writer_pretty(file, &protocol_config)?;
+    set_protocol_config(protocol_config)?;
+
+    // launch the nodes and the orchestrator
+    let mut tasks = vec![];
+    for (id, key_package) in &committee.key_packages {
+        let member = &committee.config.members[id];
+        let address = member
+            .address
+            .trim_start_matches("http://")
+            .trim_start_matches("https://")
+            .to_string();
+        let key_package = key_package.clone();
+        let pubkey_package = committee.pubkey_package.clone();
+        tasks.push(tokio::spawn(async move {
+            let state = NodeState::new(key_package, pubkey_package);
+            node::run_server(Some(&address), None, state, Duration::ZERO).await
+        }));
+    }
+    let orchestrator =
+        Orchestrator::new(committee.pubkey_package.clone(), committee.config.clone())?;
+    tasks.push(tokio::spawn(orchestrator::run_server(
+        Some(ORCHESTRATOR_ADDRESS),
+        None,
+        orchestrator,
+    )));
+
+    Ok(Devnet {
+        rpc_ctx,
+        committee,
+        protocol_config_path,
+        bitcoind,
+        tasks,
+    })
+}
+
+impl Devnet {
+    /// The environment variables pointing `zkbtc` (in another shell) to the devnet.
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![
+            ("REGTEST", "1".to_string()),
+            ("RPC_ADDRESS", self.rpc_ctx.address().to_string()),
+            ("RPC_WALLET", WALLET.to_string()),
+            (
+                "ZKBITCOIN_PROTOCOL_CONFIG",
+                self.protocol_config_path.display().to_string(),
+            ),
+        ];
+        if let Some(auth) = self.rpc_ctx.auth() {
+            vars.push(("RPC_AUTH", auth.to_string()));
+        }
+        vars
+    }
+
+    /// Runs until one of the nodes or the orchestrator stops.
+    pub async fn wait(mut self) -> Result<()> {
+        let tasks = std::mem::take(&mut self.tasks);
+        let (stopped, _, _) = futures::future::select_all(tasks).await;
+        let address = stopped.context("a task of the devnet panicked")??;
+        anyhow::bail!("the server at {address} stopped")
+    }
+}
+
+//
+// bitcoind
+//
+
+fn start_bitcoind(datadir: &std::path::Path) -> Result<Child> {
+    std::fs::create_dir_all(datadir)
+        .with_context(|| format!("couldn't create {}", datadir.display()))?;
+    let (user, password) = BITCOIND_RPC_AUTH.split_once(':').unwrap();
+    info!("- starting bitcoind in {}", datadir.display());
+    Command::new("bitcoind")
+        .arg("-regtest")
+        .arg(format!("-datadir={}", datadir.display()))
+        .arg(format!("-rpcport={BITCOIND_RPC_PORT}"))
+        .arg(format!("-rpcuser={user}"))
+        .arg(format!("-rpcpassword={password}"))
+        .args(["-server=1", "-txindex=1", "-fallbackfee=0.0002"])
+        .stdout(Stdio::null())
+        .spawn()
+        .context("couldn't start bitcoind (is it installed?)")
+}
+
+async fn wait_for_bitcoind(ctx: &RpcCtx) -> Result<()> {
+    let deadline = tokio::time::Instant::now() + BITCOIND_STARTUP_TIMEOUT;
+    loop {
+        match get_block_count(ctx).await {
+            Ok(_) => return Ok(()),
+            Err(err) if tokio::time::Instant::now() > deadline => {
+                return Err(err.context("bitcoind didn't come up"))
+            }
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+        }
+    }
+}
+
+async fn rpc_call(
+    ctx: &RpcCtx,
+    method: &'static str,
+    params: &[serde_json::Value],
+) -> Result<serde_json::Value> {
+    let params = params
+        .iter()
+        .map(serde_json::value::to_raw_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    let response = json_rpc_request(ctx, method, &params)
+        .await
+        .with_context(|| format!("{method} error"))?;
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    Ok(response.result()?)
+}
+
+/// Creates (or loads) the wallet, and mines blocks to it until it can spend some coins.
+async fn fund_wallet(node_ctx: &RpcCtx, wallet_ctx: &RpcCtx) -> Result<()> {
+    let wallets = rpc_call(node_ctx, "listwallets", &[]).await?;
+    let loaded = wallets
+        .as_array()
+        .is_some_and(|wallets| wallets.iter().any(|wallet| wallet == WALLET));
+    if !loaded {
+        if rpc_call(node_ctx, "loadwallet", &[WALLET.into()])
+            .await
+            .is_err()
+        {
+            rpc_call(node_ctx, "createwallet", &[WALLET.into()]).await?;
+        }
+    }
+
+    let balance = rpc_call(wallet_ctx, "getbalance", &[]).await?;
+    if balance.as_f64().unwrap_or_default() > 0.0 {
+        return Ok(());
+    }
+
+    let address = rpc_call(wallet_ctx, "getnewaddress", &[]).await?;
+    let blocks = COINBASE_MATURITY + 1;
+    rpc_call(wallet_ctx, "generatetoaddress", &[blocks.into(), address]).await?;
+    let balance = rpc_call(wallet_ctx, "getbalance", &[]).await?;
+    ensure!(
+        balance.as_f64().unwrap_or_default() > 0.0,
+        "the devnet wallet couldn't be funded"
+    );
+    info!("- wallet `{WALLET}` funded with {balance} BTC");
+    Ok(())
+}
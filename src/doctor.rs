@@ -0,0 +1,375 @@
+//! Diagnostics of the environment zkBitcoin runs in (see `zkbtc doctor`).
+//!
+//! Each check reports either some details on success, or an error along with a suggested fix.
+
+use std::{path::Path, process::Command, str::FromStr};
+
+use anyhow::{anyhow, ensure, Context, Result};
+
+use crate::{
+    client::NodeClient,
+    committee::{
+        orchestrator::{get_orchestrator_info, CommitteeConfig, Member},
+        signer::{even_y, CommitteeKey, KeyShare, SignatureAggregator, SignerBackend},
+    },
+    config::protocol_config,
+    get_chain,
+    json_rpc_stuff::{json_rpc_request, RpcCtx},
+};
+
+/// The oldest circom compiling the circuits of zkapps (see the `pragma` of the examples).
+const MIN_CIRCOM_VERSION: [u64; 3] = [2, 1, 3];
+
+/// The outcome of a diagnostic check.
+pub struct Check {
+    /// What was checked.
+    pub name: &'static str,
+
+    /// Some details if the check passed, or why it failed.
+    pub outcome: Result<String>,
+
+    /// How to fix the problem if the check failed.
+    pub fix: &'static str,
+}
+
+impl Check {
+    fn new(name: &'static str, outcome: Result<String>, fix: &'static str) -> Self {
+        Self { name, outcome, fix }
+    }
+}
+
+//
+// Helpers
+//
+
+/// Returns the first line printed by a program (which is usually its version).
+pub(crate) fn tool_version(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("couldn't execute {program}"))?;
+
+    // some tools (e.g. snarkjs) print their version with their usage, and exit with an error
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let version = stdout
+        .lines()
+        .chain(stderr.lines())
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .context("no version reported")?;
+    Ok(version.to_string())
+}
+
+/// Ensures that a version reported by a tool (e.g. `circom compiler 2.1.6`) is at least `min`.
+fn check_min_version(version: String, min: [u64; 3]) -> Result<String> {
+    let number = version
+        .split_whitespace()
+        .last()
+        .unwrap_or_default()
+        .trim_start_matches('v');
+    let parsed = number
+        .split('.')
+        .map(|part| part.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("couldn't parse version `{version}`"))?;
+    let [major, minor, patch] = min;
+    ensure!(
+        parsed.as_slice() >= min.as_slice(),
+        "version {number} is too old (at least {major}.{minor}.{patch} is needed)"
+    );
+    Ok(version)
+}
+
+/// Sends a JSON RPC request to the Bitcoin node, and returns its result as JSON.
+async fn rpc_value(ctx: &RpcCtx, method: &'static str) -> Result<serde_json::Value> {
+    let response = json_rpc_request(ctx, method, &[])
+        .await
+        .with_context(|| format!("{method} error"))?;
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    Ok(response.result()?)
+}
+
+//
+// Checks
+//
+
+/// Checks that the tools needed to compile circuits and create proofs are installed.
+pub fn check_tools() -> Vec<Check> {
+    vec![
+        Check::new(
+            "circom",
+            tool_version("circom", &["--version"])
+                .and_then(|version| check_min_version(version, MIN_CIRCOM_VERSION)),
+            "install (or upgrade) circom (https://docs.circom.io/getting-started/installation/)",
+        ),
+        Check::new(
+            "node",
+            tool_version("node", &["--version"]),
+            "install Node.js (e.g. `apt install nodejs npm`)",
+        ),
+        Check::new(
+            "snarkjs",
+            tool_version("snarkjs", &["--version"]),
+            "install snarkjs with `npm install -g snarkjs@latest`",
+        ),
+    ]
+}
+
+/// Checks that the Bitcoin node is reachable, on the right network, and has a usable wallet.
+pub async fn check_bitcoind(ctx: &RpcCtx) -> Vec<Check> {
+    let blockchain_info = rpc_value(ctx, "getblockchaininfo").await;
+    let chain = blockchain_info
+        .as_ref()
+        .ok()
+        .and_then(|info| info["chain"].as_str())
+        .map(str::to_string);
+
+    let node = blockchain_info.map(|info| {
+        let mut details = format!("{} blocks", info["blocks"]);
+        if info["initialblockdownload"].as_bool() == Some(true) {
+            details.push_str(" (still syncing)");
+        }
+        details
+    });
+
+    let wallet = rpc_value(ctx, "getwalletinfo")
+        .await
+        .and_then(|info| describe_wallet(&info));
+
+    vec![
+        Check::new(
+            "bitcoin node",
+            node,
+            "start bitcoind with `-server=1`, and check RPC_ADDRESS and RPC_AUTH (or RPC_COOKIE_FILE)",
+        ),
+        Check::new(
+            "network",
+            check_chain(chain),
+            "run bitcoind on testnet, or set MAINNET=1 to use zkbtc on mainnet",
+        ),
+        Check::new(
+            "wallet",
+            wallet,
+            "create or load a wallet with `bitcoin-cli createwallet <name>` or `bitcoin-cli loadwallet <name>`, and set RPC_WALLET",
+        ),
+    ]
+}
+
+fn check_chain(chain: Option<String>) -> Result<String> {
+    let chain = chain.context("couldn't get the chain of the Bitcoin node")?;
+    ensure!(
+        chain == get_chain(),
+        "the Bitcoin node is on chain `{chain}` but zkbtc expects `{}`",
+        get_chain()
+    );
+    Ok(chain)
+}
+
+fn describe_wallet(info: &serde_json::Value) -> Result<String> {
+    let name = info["walletname"].as_str().unwrap_or_default();
+    ensure!(
+        info["private_keys_enabled"].as_bool() != Some(false),
+        "wallet `{name}` is watch-only (only usable with --psbt-out or --hardware-wallet)"
+    );
+    Ok(format!(
+        "wallet `{name}` with a balance of {} BTC",
+        info["balance"]
+    ))
+}
+
+/// Checks that the orchestrator is reachable, and reports what it supports.
+pub async fn check_orchestrator(address: &str) -> Check {
+    let outcome = get_orchestrator_info(address).await.map(|info| {
+        format!(
+            "version {}, {}-out-of-{} committee, supports {}",
+            info.version,
+            info.threshold,
+            info.num_members,
+            info.methods.join(", ")
+        )
+    });
+
+    Check::new(
+        "orchestrator",
+        outcome,
+        "check the orchestrator address (ENDPOINT), or that the orchestrator is running",
+    )
+}
+
+/// Checks that every member of a committee is reachable, and is the member the committee config says it is.
+pub async fn check_members(committee_cfg_path: &Path) -> Vec<Check> {
+    let fix = "check the addresses in the committee config, or that the members are running (zkbtc start-committee-node)";
+    let committee_cfg: CommitteeConfig = match std::fs::File::open(committee_cfg_path)
+        .context("couldn't open file")
+        .and_then(|file| serde_json::from_reader(file).context("couldn't parse committee config"))
+    {
+        Ok(committee_cfg) => committee_cfg,
+        Err(err) => return vec![Check::new("committee members", Err(err), fix)],
+    };
+
+    let mut checks = vec![];
+    for (id, member) in &committee_cfg.members {
+        let outcome = check_member(id, member)
+            .await
+            .with_context(|| format!("member {id:?} at {}", member.address));
+        checks.push(Check::new("committee member", outcome, fix));
+    }
+    checks
+}
+
+async fn check_member(id: &frost_secp256k1_tr::Identifier, member: &Member) -> Result<String> {
+    let info = NodeClient::new(member)?.info().await?;
+    ensure!(
+        &info.identifier == id,
+        "the node has the key package of member {:?}",
+        info.identifier
+    );
+    let light_client = if info.light_client {
+        "with a light client"
+    } else {
+        "without a light client"
+    };
+    Ok(format!(
+        "member {id:?} at {} runs version {}, {light_client}",
+        member.address, info.version
+    ))
+}
+
+/// Checks that the key files of a committee member or orchestrator are valid and consistent.
+pub fn check_keys(
+    key_path: Option<&Path>,
+    publickey_package_path: Option<&Path>,
+    committee_cfg_path: Option<&Path>,
+) -> Vec<Check> {
+    let mut checks = vec![];
+
+    let mut group_key = None;
+    if let Some(path) = publickey_package_path {
+        let outcome = load_pubkey_package(path).and_then(|pubkey_package| {
+            let pubkey = hex::encode(pubkey_package.pubkey()?.inner.serialize());
+            group_key = Some(pubkey.clone());
+            Ok(format!("committee public key {pubkey}"))
+        });
+        checks.push(Check::new(
+            "public key package",
+            outcome,
+            "use the publickey-package.json file generated along with the committee",
+        ));
+    }
+
+    if let Some(pubkey) = &group_key {
+        // only the X coordinate of the key matters (see BIP 340)
+        let zkbitcoin_pubkey = &protocol_config().zkbitcoin_pubkey;
+        let matches = bitcoin::PublicKey::from_str(zkbitcoin_pubkey)
+            .map(|key| hex::encode(even_y(key).inner.serialize()) == *pubkey)
+            .unwrap_or(false);
+        let outcome = if matches {
+            Ok("matches the zkBitcoin public key".to_string())
+        } else {
+            Err(anyhow!(
+                "the committee public key is not the zkBitcoin public key {zkbitcoin_pubkey}"
+            ))
+        };
+        checks.push(Check::new(
+            "committee public key",
+            outcome,
+            "use the key files of the committee behind ZKBITCOIN_PUBKEY",
+        ));
+    }
+
+    if let Some(path) = key_path {
+        checks.push(Check::new(
+            "key package",
+            load_key_package(path, group_key.as_deref()),
+            "use the key-<id>.json file generated for this committee member",
+        ));
+    }
+
+    if let Some(path) = committee_cfg_path {
+        checks.push(Check::new(
+            "committee config",
+            load_committee_cfg(path),
+            "use the committee-cfg.json file generated along with the committee",
+        ));
+    }
+
+    checks
+}
+
+fn load_pubkey_package(path: &Path) -> Result<CommitteeKey> {
+    let file = std::fs::File::open(path).context("couldn't open file")?;
+    serde_json::from_reader(file).context("couldn't parse public key package")
+}
+
+fn load_key_package(path: &Path, group_key: Option<&str>) -> Result<String> {
+    let file = std::fs::File::open(path).context("couldn't open file")?;
+    let key_package: KeyShare =
+        serde_json::from_reader(file).context("couldn't parse key package")?;
+    if let Some(group_key) = group_key {
+        ensure!(
+            hex::encode(key_package.committee_pubkey()?.inner.serialize()) == group_key,
+            "the key package is not for the committee of the public key package"
+        );
+    }
+    Ok(format!(
+        "{:?} key package of member {:?}",
+        key_package.kind(),
+        key_package.identifier()
+    ))
+}
+
+fn load_committee_cfg(path: &Path) -> Result<String> {
+    let file = std::fs::File::open(path).context("couldn't open file")?;
+    let committee_cfg: CommitteeConfig =
+        serde_json::from_reader(file).context("couldn't parse committee config")?;
+    let num_members = committee_cfg.members.len();
+    ensure!(
+        committee_cfg.threshold > 0 && committee_cfg.threshold <= num_members,
+        "the threshold ({}) must be between 1 and the number of members ({num_members})",
+        committee_cfg.threshold
+    );
+    Ok(format!(
+        "{}-out-of-{num_members} committee",
+        committee_cfg.threshold
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_check_example_keys() {
+        let examples = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples/committee");
+        let checks = check_keys(
+            Some(&examples.join("key-0.json")),
+            Some(&examples.join("publickey-package.json")),
+            Some(&examples.join("committee-cfg.json")),
+        );
+        for check in checks {
+            // the example committee is not the one behind the zkBitcoin public key
+            if check.name == "committee public key" {
+                continue;
+            }
+            assert!(check.outcome.is_ok(), "{}: {:?}", check.name, check.outcome);
+        }
+    }
+
+    #[test]
+    fn test_check_min_version() {
+        assert!(check_min_version("circom compiler 2.1.6".to_string(), [2, 1, 3]).is_ok());
+        assert!(check_min_version("circom compiler 2.1.3".to_string(), [2, 1, 3]).is_ok());
+        assert!(check_min_version("circom compiler 2.0.9".to_string(), [2, 1, 3]).is_err());
+        assert!(check_min_version("v20.1.0".to_string(), [18, 0, 0]).is_ok());
+        assert!(check_min_version("nightly".to_string(), [2, 1, 3]).is_err());
+    }
+
+    #[test]
+    fn test_missing_key_file() {
+        let checks = check_keys(Some(Path::new("/nonexistent/key.json")), None, None);
+        assert!(checks[0].outcome.is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Folding (Nova) for stateful zkapps with frequent updates.
+//!
+//! A folded zkapp is a circom circuit describing a single state transition (a step),
+//! and many steps are folded into a single proof settled by a single spend.
+//! Its steps follow the Nova conventions of circom circuits (`signal input step_in[4]; signal output step_out[4];`),
+//! which is how step circuits are told apart from other circom circuits,
+//! on the running values `[state, truncated_txid, amount_out, amount_in]`: each step updates the state,
+//! passes the txid through, and adds the amounts it moves (private inputs of the step) to the running amounts.
+//! The folding starts from `[prev_state, truncated_txid, 0, 0]`, and the committee checks that it ends with
+//! `[new_state, truncated_txid, amount_out, amount_in]` (the public inputs of any stateful zkapp).
+//!
+//! The steps waiting to be settled are accumulated off-chain (see [Batch] and `zkbtc batch`).
+//! Folding the steps needs the `nova` feature.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+
+use crate::zkbitcoin_folder;
+
+/// The proof input holding the steps to fold (each as a JSON object of its inputs).
+/// Without it, the proof inputs are those of a single step.
+pub const STEPS_INPUT: &str = "steps";
+
+/// The proof inputs the folding starts from, which are not inputs of the steps.
+const INITIAL_INPUTS: [&str; 2] = ["prev_state", "truncated_txid"];
+
+/// Returns true if `path` is a circom step circuit (with `step_in` and `step_out` signals), to be folded.
+pub fn is_step_circuit(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "circom")
+        && std::fs::read_to_string(path).is_ok_and(|source| {
+            source.contains("signal input step_in[") && source.contains("signal output step_out[")
+        })
+}
+
+/// Returns the inputs of each step: the steps of [STEPS_INPUT], or the proof inputs as a single step.
+fn steps_of(
+    proof_inputs: &HashMap<String, Vec<String>>,
+) -> Result<Vec<HashMap<String, serde_json::Value>>> {
+    let steps = match proof_inputs.get(STEPS_INPUT) {
+        Some(steps) => steps
+            .iter()
+            .map(|step| serde_json::from_str(step).context("a step must be a JSON object"))
+            .collect::<Result<Vec<_>>>()?,
+        None => vec![proof_inputs
+            .iter()
+            .filter(|(name, _)| !INITIAL_INPUTS.contains(&name.as_str()))
+            .map(|(name, values)| (name.clone(), serde_json::json!(values)))
+            .collect()],
+    };
+    ensure!(!steps.is_empty(), "there are no steps to fold");
+    Ok(steps)
+}
+
+#[cfg(feature = "nova")]
+mod backend;
+#[cfg(feature = "nova")]
+pub use backend::*;
+
+//
+// Batches
+//
+
+/// The steps of a folded zkapp waiting to be settled, accumulated off-chain (in `~/.zkbitcoin/batches`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Batch {
+    /// The inputs of each step, in order.
+    pub steps: Vec<HashMap<String, serde_json::Value>>,
+}
+
+impl Batch {
+    /// Where the batch of the zkapp deployed by `txid` is kept.
+    pub fn path(txid: &Txid) -> PathBuf {
+        zkbitcoin_folder()
+            .join("batches")
+            .join(format!("{txid}.json"))
+    }
+
+    /// Loads the batch of a zkapp (empty if there's none).
+    pub fn load(txid: &Txid) -> Result<Self> {
+        let path = Self::path(txid);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let batch = std::fs::read_to_string(&path)
+            .with_context(|| format!("couldn't read {}", path.display()))?;
+        serde_json::from_str(&batch).with_context(|| format!("couldn't parse {}", path.display()))
+    }
+
+    /// Saves the batch of a zkapp.
+    pub fn save(&self, txid: &Txid) -> Result<()> {
+        let path = Self::path(txid);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("couldn't create {}", dir.display()))?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("couldn't write {}", path.display()))
+    }
+
+    /// Deletes the batch of a zkapp (once settled).
+    pub fn clear(txid: &Txid) -> Result<()> {
+        let path = Self::path(txid);
+        if path.exists() {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("couldn't delete {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// The total amount (in satoshis) a step input (`amount_out` or `amount_in`) adds up to over all the steps.
+    pub fn total(&self, name: &str) -> Result<u64> {
+        let mut total = 0u64;
+        for (index, step) in self.steps.iter().enumerate() {
+            let amount = match step.get(name) {
+                None => 0,
+                Some(value) => amount_of(value)
+                    .with_context(|| format!("the {name} of step {index} is not an amount"))?,
+            };
+            total = total.checked_add(amount).context("amounts overflow")?;
+        }
+        Ok(total)
+    }
+
+    /// The proof inputs settling the batch: its steps, and the amounts they move.
+    pub fn proof_inputs(&self) -> Result<HashMap<String, Vec<String>>> {
+        ensure!(!self.steps.is_empty(), "the batch has no steps");
+        let steps = self
+            .steps
+            .iter()
+            .map(serde_json::to_string)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(HashMap::from([
+            (STEPS_INPUT.to_string(), steps),
+            (
+                "amount_out".to_string(),
+                vec![self.total("amount_out")?.to_string()],
+            ),
+            (
+                "amount_in".to_string(),
+                vec![self.total("amount_in")?.to_string()],
+            ),
+        ]))
+    }
+}
+
+/// Reads an amount given as a number, a string, or a list of a single one of them.
+fn amount_of(value: &serde_json::Value) -> Option<u64> {
+    match value {
+        serde_json::Value::Number(number) => number.as_u64(),
+        serde_json::Value::String(string) => string.parse().ok(),
+        serde_json::Value::Array(values) if values.len() == 1 => amount_of(&values[0]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_batch() {
+        let step = |value: serde_json::Value| serde_json::from_value(value).unwrap();
+        let batch = Batch {
+            steps: vec![
+                step(json!({ "amount_out": "1000", "secret": ["1"] })),
+                step(json!({ "amount_in": [500] })),
+                step(json!({ "amount_out": 250 })),
+            ],
+        };
+        assert_eq!(batch.total("amount_out").unwrap(), 1250);
+        assert_eq!(batch.total("amount_in").unwrap(), 500);
+
+        let proof_inputs = batch.proof_inputs().unwrap();
+        assert_eq!(proof_inputs["amount_out"], ["1250"]);
+        assert_eq!(steps_of(&proof_inputs).unwrap(), batch.steps);
+
+        assert!(Batch::default().proof_inputs().is_err());
+        let wrong = Batch {
+            steps: vec![step(json!({ "amount_out": "-1" }))],
+        };
+        assert!(wrong.total("amount_out").is_err());
+    }
+
+    #[test]
+    fn test_is_step_circuit() {
+        assert!(is_step_circuit(Path::new("examples/circuit/folded.circom")));
+        assert!(!is_step_circuit(Path::new(
+            "examples/circuit/stateful.circom"
+        )));
+        assert!(!is_step_circuit(Path::new("halo2:counter")));
+    }
+
+    #[test]
+    fn test_single_step() {
+        // without steps, the proof inputs are a single step (without the inputs the folding starts from)
+        let proof_inputs = HashMap::from([
+            ("prev_state".to_string(), vec!["1".to_string()]),
+            ("truncated_txid".to_string(), vec!["2".to_string()]),
+            ("secret".to_string(), vec!["3".to_string()]),
+        ]);
+        let steps = steps_of(&proof_inputs).unwrap();
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].keys().collect::<Vec<_>>(), ["secret"]);
+    }
+}
@@ -0,0 +1,208 @@
+//! How the steps of folded zkapps are folded with Nova, and verified (with the `nova` feature).
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use ff::{Field, PrimeField};
+use log::info;
+use nova_scotia::{
+    circom::reader::load_r1cs, create_public_params, create_recursive_circuit, FileLocation, C1,
+    C2, F, S,
+};
+use nova_snark::{
+    provider::bn256_grumpkin::{bn256, grumpkin},
+    CompressedSNARK, PublicParams,
+};
+use num_bigint::BigUint;
+use tempdir::TempDir;
+
+use super::steps_of;
+use crate::{
+    constants::STATEFUL_ZKAPP_PUBLIC_INPUT_LEN,
+    plonk::{self, NovaProof, NovaVerifierKey, ProofSystemKind},
+    snarkjs,
+};
+
+// circom circuits are over the scalar field of BN254, which cycles with Grumpkin
+type G1 = bn256::Point;
+type G2 = grumpkin::Point;
+type Snark = CompressedSNARK<G1, G2, C1<G1>, C2<G2>, S<G1>, S<G2>>;
+type SnarkVerifierKey = nova_snark::VerifierKey<G1, G2, C1<G1>, C2<G2>, S<G1>, S<G2>>;
+
+/// The number of running values (`[state, truncated_txid, amount_out, amount_in]`).
+const ARITY: usize = STATEFUL_ZKAPP_PUBLIC_INPUT_LEN - 1;
+
+//
+// Keys
+//
+
+/// A circuit compiled in a temporary directory, with its public parameters.
+struct Compiled {
+    _tmp_dir: TempDir,
+    wasm_path: PathBuf,
+    r1cs: nova_scotia::circom::circuit::R1CS<F<G1>>,
+    pp: PublicParams<G1, G2, C1<G1>, C2<G2>>,
+}
+
+fn compile(circom_circuit_path: &Path) -> Result<Compiled> {
+    let tmp_dir = TempDir::new("zkbitcoin_").context("couldn't create tmp dir")?;
+    let r1cs_path = snarkjs::compile_circuit(&tmp_dir, circom_circuit_path)?;
+    let name = circom_circuit_path
+        .file_stem()
+        .context("failed to get circuit name from filename")?
+        .to_string_lossy();
+    let wasm_path = tmp_dir
+        .path()
+        .join(format!("{name}_js"))
+        .join(format!("{name}.wasm"));
+
+    // the public parameters (and the keys derived from them) only depend on the circuit: there's no setup
+    let r1cs = load_r1cs::<G1, G2>(&FileLocation::PathBuf(r1cs_path));
+    let pp = create_public_params::<G1, G2>(r1cs.clone());
+    Ok(Compiled {
+        _tmp_dir: tmp_dir,
+        wasm_path,
+        r1cs,
+        pp,
+    })
+}
+
+/// Compiles the step circuit of a folded zkapp, and returns its verifier key.
+pub fn verifier_key(circom_circuit_path: &Path) -> Result<plonk::VerifierKey> {
+    let compiled = compile(circom_circuit_path)?;
+    let (_, vk) = Snark::setup(&compiled.pp).map_err(|err| anyhow!("couldn't set up: {err:?}"))?;
+    nova_verifier_key(&vk)
+}
+
+fn nova_verifier_key(vk: &SnarkVerifierKey) -> Result<plonk::VerifierKey> {
+    Ok(plonk::VerifierKey::Nova(NovaVerifierKey {
+        protocol: ProofSystemKind::Nova.name().to_string(),
+        vk: hex::encode(bincode::serialize(vk)?),
+    }))
+}
+
+//
+// Proving
+//
+
+/// Folds the steps of `proof_inputs` (see [super::STEPS_INPUT]) into a single proof,
+/// and returns it with its public inputs (`[new_state, prev_state, truncated_txid, amount_out, amount_in]`) and verifier key.
+pub fn prove(
+    circom_circuit_path: &Path,
+    proof_inputs: &HashMap<String, Vec<String>>,
+) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
+    let single = |name: &str| -> Result<F<G1>> {
+        match proof_inputs.get(name).map(Vec::as_slice) {
+            Some([value]) => from_decimal(value),
+            _ => Err(anyhow!("{name} in proof inputs must be of length 1")),
+        }
+    };
+    let z0 = vec![
+        single("prev_state")?,
+        single("truncated_txid")?,
+        F::<G1>::ZERO,
+        F::<G1>::ZERO,
+    ];
+    let steps = steps_of(proof_inputs)?;
+    let num_steps = steps.len();
+
+    let compiled = compile(circom_circuit_path)?;
+    let (pk, vk) = Snark::setup(&compiled.pp).map_err(|err| anyhow!("couldn't set up: {err:?}"))?;
+    let recursive_snark = create_recursive_circuit(
+        FileLocation::PathBuf(compiled.wasm_path.clone()),
+        compiled.r1cs.clone(),
+        steps,
+        z0.clone(),
+        &compiled.pp,
+    )
+    .map_err(|err| anyhow!("couldn't fold the steps: {err:?}"))?;
+    let snark = Snark::prove(&compiled.pp, &pk, &recursive_snark)
+        .map_err(|err| anyhow!("couldn't create proof: {err:?}"))?;
+    info!("- folded {num_steps} steps");
+
+    // the running values the folding ends with
+    let (zn, _) = snark
+        .verify(&vk, num_steps, z0.clone(), vec![F::<G2>::ZERO])
+        .map_err(|err| anyhow!("the folded proof doesn't verify: {err:?}"))?;
+    ensure!(
+        zn.len() == ARITY,
+        "the circuit must have {ARITY} running values"
+    );
+    let public_inputs = [zn[0], z0[0], zn[1], zn[2], zn[3]]
+        .iter()
+        .map(to_decimal)
+        .collect();
+
+    let proof = plonk::Proof::Nova(NovaProof {
+        protocol: ProofSystemKind::Nova.name().to_string(),
+        num_steps,
+        snark: hex::encode(bincode::serialize(&snark)?),
+    });
+    Ok((
+        proof,
+        plonk::PublicInputs(public_inputs),
+        nova_verifier_key(&vk)?,
+    ))
+}
+
+/// Verifies a folded proof, starting from `[prev_state, truncated_txid, 0, 0]`
+/// and ending with `[new_state, truncated_txid, amount_out, amount_in]` (given in the order of `public_inputs`).
+pub fn verify_proof(
+    vk: &NovaVerifierKey,
+    public_inputs: &[String],
+    proof: &NovaProof,
+) -> Result<()> {
+    let public_inputs = public_inputs
+        .iter()
+        .map(|input| from_decimal(input))
+        .collect::<Result<Vec<_>>>()?;
+    let [new_state, prev_state, truncated_txid, amount_out, amount_in] = public_inputs[..] else {
+        return Err(anyhow!(
+            "expected {STATEFUL_ZKAPP_PUBLIC_INPUT_LEN} public inputs, got {}",
+            public_inputs.len()
+        ));
+    };
+    ensure!(proof.num_steps > 0, "the proof folds no steps");
+
+    let vk = hex::decode(&vk.vk).context("malformed verifier key")?;
+    let vk: SnarkVerifierKey = bincode::deserialize(&vk).context("malformed verifier key")?;
+    let snark = hex::decode(&proof.snark).context("malformed proof")?;
+    let snark: Snark = bincode::deserialize(&snark).context("malformed proof")?;
+
+    let z0 = vec![prev_state, truncated_txid, F::<G1>::ZERO, F::<G1>::ZERO];
+    let (zn, _) = snark
+        .verify(&vk, proof.num_steps, z0, vec![F::<G2>::ZERO])
+        .map_err(|_| anyhow!("failed to verify proof"))?;
+    ensure!(
+        zn == [new_state, truncated_txid, amount_out, amount_in],
+        "the folded steps don't end with the expected state, txid, and amounts"
+    );
+    Ok(())
+}
+
+fn from_decimal(value: &str) -> Result<F<G1>> {
+    match F::<G1>::from_str_vartime(value) {
+        Some(field) if value.chars().all(|c| c.is_ascii_digit()) => Ok(field),
+        _ => Err(anyhow!(
+            "`{value}` is not a field element written in decimal"
+        )),
+    }
+}
+
+fn to_decimal(field: &F<G1>) -> String {
+    BigUint::from_bytes_le(field.to_repr().as_ref()).to_str_radix(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decimal() {
+        assert_eq!(to_decimal(&from_decimal("1234").unwrap()), "1234");
+        assert!(from_decimal("-1").is_err());
+    }
+}
@@ -2,7 +2,7 @@ use bitcoin::sighash::{Prevouts, SighashCache};
 use bitcoin::{TapSighashType, Transaction, TxOut};
 use frost_secp256k1_tr as frost;
 use frost_secp256k1_tr::Signature;
-use rand::thread_rng;
+use rand::{thread_rng, CryptoRng, RngCore};
 use secp256k1::XOnlyPublicKey;
 use std::collections::{BTreeMap, HashMap};
 
@@ -23,8 +23,22 @@ pub fn gen_frost_keys(
     ),
     frost::Error,
 > {
-    let mut rng = thread_rng();
+    gen_frost_keys_with_rng(max_signers, min_signers, &mut thread_rng())
+}
 
+/// Same as [gen_frost_keys], with the given source of randomness
+/// (a seeded one gives the same keys every time).
+pub fn gen_frost_keys_with_rng(
+    max_signers: u16,
+    min_signers: u16,
+    mut rng: impl RngCore + CryptoRng,
+) -> Result<
+    (
+        BTreeMap<frost::Identifier, frost::keys::KeyPackage>,
+        frost::keys::PublicKeyPackage,
+    ),
+    frost::Error,
+> {
     ////////////////////////////////////////////////////////////////////////////
     // Key generation, Round 1
     ////////////////////////////////////////////////////////////////////////////
@@ -0,0 +1,44 @@
+//! Halo2 circuits as zkapps.
+//!
+//! Halo2 (with IPA commitments over the Pasta curves) needs no trusted setup,
+//! but its verifier keys can only be recreated from the Rust implementation of their circuit.
+//! So the halo2 circuits zkapps can be deployed with are built into zkBitcoin (see [Halo2Zkapp] and [circuits]),
+//! and are referred to as `halo2:<name>` wherever the path to a circuit is expected.
+//! The verifier key committed on-chain names the circuit, and the committee recreates it to verify proofs
+//! (once per circuit, the parameters and verifier key of each circuit being kept for the next proofs).
+//! Proving and verifying them needs the `halo2` feature.
+
+use std::path::Path;
+
+/// How halo2 circuits are referred to, instead of a path (followed by their name).
+pub const PATH_PREFIX: &str = "halo2:";
+
+/// Returns the name of the halo2 circuit `path` refers to (as `halo2:<name>`), if it refers to one.
+/// (References must be resolved with [crate::proof_system::resolve], not joined to a directory like the paths of circuits.)
+pub fn circuit_name(path: &Path) -> Option<&str> {
+    path.to_str()?.strip_prefix(PATH_PREFIX)
+}
+
+#[cfg(feature = "halo2")]
+mod backend;
+#[cfg(feature = "halo2")]
+pub use backend::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_name() {
+        assert_eq!(circuit_name(Path::new("halo2:counter")), Some("counter"));
+        assert_eq!(
+            circuit_name(&Path::new("/home/bob").join("halo2:counter")),
+            None
+        );
+        assert_eq!(circuit_name(Path::new("zkapps_halo2:counter")), None);
+        assert_eq!(
+            circuit_name(Path::new("examples/circuit/stateless.circom")),
+            None
+        );
+    }
+}
@@ -0,0 +1,389 @@
+//! The halo2 circuits built into zkBitcoin, and how they're proven and verified (with the `halo2` feature).
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    pasta::{group::ff::PrimeField, EqAffine, Fp},
+    plonk::{
+        create_proof, keygen_pk, keygen_vk, verify_proof, Advice, Circuit, Column,
+        ConstraintSystem, Error, Expression, Instance, Selector, SingleVerifier, VerifyingKey,
+    },
+    poly::{commitment::Params, Rotation},
+    transcript::{Blake2bRead, Blake2bWrite, Challenge255},
+};
+use num_bigint::BigUint;
+use rand::rngs::OsRng;
+use sha3::{Digest, Keccak256};
+
+use super::circuit_name;
+use crate::{
+    constants::STATEFUL_ZKAPP_PUBLIC_INPUT_LEN,
+    plonk::{self, Halo2Proof, Halo2VerifierKey, ProofSystemKind},
+};
+
+//
+// Circuits
+//
+
+/// A halo2 circuit zkapps can be deployed with.
+/// Its public inputs follow the conventions of circom zkapps (see the README).
+pub trait Halo2Zkapp: Circuit<Fp> + Default {
+    /// The name of the circuit (in `halo2:<name>`).
+    const NAME: &'static str;
+
+    /// The circuit has 2^K rows.
+    const K: u32;
+
+    /// The number of public inputs of the circuit.
+    const NUM_PUBLIC: usize;
+
+    /// Creates the circuit with its witness, and its public inputs, from proof inputs
+    /// (which include the public inputs zkBitcoin fills in).
+    fn from_inputs(proof_inputs: &HashMap<String, Vec<String>>) -> Result<(Self, Vec<Fp>)>;
+}
+
+/// A circuit built into zkBitcoin, with its operations.
+pub struct Registered {
+    pub name: &'static str,
+    verifier_key: fn() -> Result<Halo2VerifierKey>,
+    prove: fn(&HashMap<String, Vec<String>>) -> Result<(Vec<u8>, Vec<Fp>)>,
+    verify: fn(&[Fp], &[u8]) -> Result<()>,
+}
+
+impl Registered {
+    fn of<C: Halo2Zkapp>() -> Self {
+        Self {
+            name: C::NAME,
+            verifier_key: verifier_key::<C>,
+            prove: prove::<C>,
+            verify: verify::<C>,
+        }
+    }
+}
+
+/// The halo2 circuits zkapps can be deployed with.
+pub fn circuits() -> Vec<Registered> {
+    vec![Registered::of::<Counter>()]
+}
+
+fn registered(name: &str) -> Result<Registered> {
+    circuits()
+        .into_iter()
+        .find(|circuit| circuit.name == name)
+        .with_context(|| {
+            let names = circuits()
+                .iter()
+                .map(|circuit| circuit.name)
+                .collect::<Vec<_>>();
+            format!("there is no halo2 circuit named `{name}` (expected one of {names:?})")
+        })
+}
+
+//
+// Proving
+//
+
+/// Returns the verifier key of the halo2 circuit `path` refers to.
+pub fn verifier_key_of(path: &Path) -> Result<plonk::VerifierKey> {
+    let name = circuit_name(path).context("not a halo2 circuit")?;
+    let vk = (registered(name)?.verifier_key)()?;
+    Ok(plonk::VerifierKey::Halo2(vk))
+}
+
+/// Proves the halo2 circuit `path` refers to, and returns the proof, its public inputs, and the verifier key.
+pub fn prove_circuit(
+    path: &Path,
+    proof_inputs: &HashMap<String, Vec<String>>,
+) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
+    let name = circuit_name(path).context("not a halo2 circuit")?;
+    let circuit = registered(name)?;
+    let (transcript, public_inputs) = (circuit.prove)(proof_inputs)?;
+
+    let proof = plonk::Proof::Halo2(Halo2Proof {
+        protocol: ProofSystemKind::Halo2.name().to_string(),
+        transcript: hex::encode(transcript),
+    });
+    let public_inputs = public_inputs.iter().map(to_decimal).collect();
+    let vk = plonk::VerifierKey::Halo2((circuit.verifier_key)()?);
+    Ok((proof, plonk::PublicInputs(public_inputs), vk))
+}
+
+/// Verifies the proof of a halo2 circuit, after checking that the verifier key is the one of the circuit it names.
+pub fn verify_circuit_proof(
+    vk: &Halo2VerifierKey,
+    public_inputs: &[String],
+    proof: &Halo2Proof,
+) -> Result<()> {
+    let circuit = registered(&vk.circuit)?;
+    ensure!(
+        (circuit.verifier_key)()? == *vk,
+        "the verifier key is not the one of the halo2 circuit `{}`",
+        vk.circuit
+    );
+    let public_inputs = public_inputs
+        .iter()
+        .map(|input| from_decimal(input))
+        .collect::<Result<Vec<_>>>()?;
+    let transcript = hex::decode(&proof.transcript).context("malformed proof")?;
+    (circuit.verify)(&public_inputs, &transcript)
+}
+
+/// The parameters and verifier key of a circuit.
+type Keys = Arc<(Params<EqAffine>, VerifyingKey<EqAffine>)>;
+
+/// The keys of the circuits (by name), created the first time they're used.
+static KEYS: OnceLock<Mutex<HashMap<&'static str, Keys>>> = OnceLock::new();
+
+fn keys<C: Halo2Zkapp>() -> Result<Keys> {
+    let cache = KEYS.get_or_init(Default::default);
+    if let Some(keys) = cache.lock().unwrap().get(C::NAME) {
+        return Ok(keys.clone());
+    }
+
+    // the parameters are derived from the size of the circuit only (there's no setup)
+    let params = Params::<EqAffine>::new(C::K);
+    let vk = keygen_vk(&params, &C::default()).context("couldn't create the verifier key")?;
+    let keys = Arc::new((params, vk));
+    cache.lock().unwrap().insert(C::NAME, keys.clone());
+    Ok(keys)
+}
+
+fn verifier_key<C: Halo2Zkapp>() -> Result<Halo2VerifierKey> {
+    let keys = keys::<C>()?;
+    let (_, vk) = &*keys;
+    let mut hasher = Keccak256::new();
+    hasher.update(format!("{:?}", vk.pinned()));
+    Ok(Halo2VerifierKey {
+        protocol: ProofSystemKind::Halo2.name().to_string(),
+        circuit: C::NAME.to_string(),
+        k: C::K,
+        nPublic: C::NUM_PUBLIC,
+        pinned_hash: hex::encode(hasher.finalize()),
+    })
+}
+
+fn prove<C: Halo2Zkapp>(proof_inputs: &HashMap<String, Vec<String>>) -> Result<(Vec<u8>, Vec<Fp>)> {
+    let (circuit, public_inputs) = C::from_inputs(proof_inputs)?;
+    let keys = keys::<C>()?;
+    let (params, vk) = &*keys;
+    let pk =
+        keygen_pk(params, vk.clone(), &C::default()).context("couldn't create the prover key")?;
+
+    let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+    create_proof(
+        params,
+        &pk,
+        &[circuit],
+        &[&[&public_inputs]],
+        OsRng,
+        &mut transcript,
+    )
+    .context("couldn't create proof")?;
+    Ok((transcript.finalize(), public_inputs))
+}
+
+fn verify<C: Halo2Zkapp>(public_inputs: &[Fp], transcript: &[u8]) -> Result<()> {
+    ensure!(
+        public_inputs.len() == C::NUM_PUBLIC,
+        "expected {} public inputs, got {}",
+        C::NUM_PUBLIC,
+        public_inputs.len()
+    );
+    let keys = keys::<C>()?;
+    let (params, vk) = &*keys;
+    let mut transcript = Blake2bRead::<_, EqAffine, Challenge255<_>>::init(transcript);
+    verify_proof(
+        params,
+        vk,
+        SingleVerifier::new(params),
+        &[&[public_inputs]],
+        &mut transcript,
+    )
+    .context("failed to verify proof")
+}
+
+//
+// Field elements
+//
+
+fn from_decimal(value: &str) -> Result<Fp> {
+    match Fp::from_str_vartime(value) {
+        Some(field) if value.chars().all(|c| c.is_ascii_digit()) => Ok(field),
+        _ => bail!("`{value}` is not a field element written in decimal"),
+    }
+}
+
+fn to_decimal(field: &Fp) -> String {
+    BigUint::from_bytes_le(field.to_repr().as_ref()).to_str_radix(10)
+}
+
+/// Returns the single field element given as the proof input `name`.
+fn input(proof_inputs: &HashMap<String, Vec<String>>, name: &str) -> Result<Fp> {
+    match proof_inputs.get(name).map(Vec::as_slice) {
+        Some([value]) => from_decimal(value),
+        Some(_) => bail!("the input `{name}` expects a single value"),
+        None => bail!("the input `{name}` is missing"),
+    }
+}
+
+//
+// Built-in circuits
+//
+
+/// A stateful zkapp counting the number of times it was used.
+#[derive(Clone, Default)]
+pub struct Counter {
+    prev_state: Value<Fp>,
+}
+
+#[derive(Clone)]
+pub struct CounterConfig {
+    state: Column<Advice>,
+    instance: Column<Instance>,
+    increment: Selector,
+}
+
+/// The rows of the public inputs of stateful zkapps.
+const NEW_STATE_ROW: usize = 0;
+const PREV_STATE_ROW: usize = 1;
+
+impl Circuit<Fp> for Counter {
+    type Config = CounterConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        Self::default()
+    }
+
+    fn configure(meta: &mut ConstraintSystem<Fp>) -> CounterConfig {
+        let state = meta.advice_column();
+        let instance = meta.instance_column();
+        meta.enable_equality(state);
+        meta.enable_equality(instance);
+
+        // new_state = prev_state + 1
+        let increment = meta.selector();
+        meta.create_gate("increment", |meta| {
+            let selector = meta.query_selector(increment);
+            let prev_state = meta.query_advice(state, Rotation::cur());
+            let new_state = meta.query_advice(state, Rotation::next());
+            vec![selector * (new_state - prev_state - Expression::Constant(Fp::ONE))]
+        });
+
+        CounterConfig {
+            state,
+            instance,
+            increment,
+        }
+    }
+
+    fn synthesize(
+        &self,
+        config: CounterConfig,
+        mut layouter: impl Layouter<Fp>,
+    ) -> Result<(), Error> {
+        let (prev_state, new_state) = layouter.assign_region(
+            || "increment",
+            |mut region| {
+                config.increment.enable(&mut region, 0)?;
+                let prev_state =
+                    region.assign_advice(|| "prev_state", config.state, 0, || self.prev_state)?;
+                let new_state = region.assign_advice(
+                    || "new_state",
+                    config.state,
+                    1,
+                    || self.prev_state + Value::known(Fp::ONE),
+                )?;
+                Ok((prev_state, new_state))
+            },
+        )?;
+
+        // the txid and the amounts are public inputs that this zkapp doesn't constrain
+        layouter.constrain_instance(new_state.cell(), config.instance, NEW_STATE_ROW)?;
+        layouter.constrain_instance(prev_state.cell(), config.instance, PREV_STATE_ROW)?;
+        Ok(())
+    }
+}
+
+impl Halo2Zkapp for Counter {
+    const NAME: &'static str = "counter";
+    const K: u32 = 5;
+    const NUM_PUBLIC: usize = STATEFUL_ZKAPP_PUBLIC_INPUT_LEN;
+
+    fn from_inputs(proof_inputs: &HashMap<String, Vec<String>>) -> Result<(Self, Vec<Fp>)> {
+        let prev_state = input(proof_inputs, "prev_state")?;
+        let public_inputs = vec![
+            prev_state + Fp::ONE,
+            prev_state,
+            input(proof_inputs, "truncated_txid")?,
+            input(proof_inputs, "amount_out")?,
+            input(proof_inputs, "amount_in")?,
+        ];
+        let circuit = Counter {
+            prev_state: Value::known(prev_state),
+        };
+        Ok((circuit, public_inputs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter_inputs(prev_state: &str) -> HashMap<String, Vec<String>> {
+        [
+            ("prev_state", prev_state),
+            ("truncated_txid", "42"),
+            ("amount_out", "1000"),
+            ("amount_in", "0"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), vec![value.to_string()]))
+        .collect()
+    }
+
+    #[test]
+    fn test_prove_and_verify() {
+        let path = Path::new("halo2:counter");
+        let (proof, public_inputs, vk) = prove_circuit(path, &counter_inputs("1")).unwrap();
+        assert_eq!(public_inputs.new_state(), "2");
+        assert_eq!(verifier_key_of(path).unwrap().hash(), vk.hash());
+
+        let (plonk::VerifierKey::Halo2(vk), plonk::Proof::Halo2(proof)) = (&vk, &proof) else {
+            unreachable!()
+        };
+        verify_circuit_proof(vk, &public_inputs.0, proof).unwrap();
+
+        // the proof is bound to all of its public inputs
+        let mut wrong_inputs = public_inputs.0.clone();
+        wrong_inputs[2] = "43".to_string();
+        assert!(verify_circuit_proof(vk, &wrong_inputs, proof).is_err());
+
+        // and to the verifier key of the circuit
+        let mut wrong_vk = vk.clone();
+        wrong_vk.k += 1;
+        assert!(verify_circuit_proof(&wrong_vk, &public_inputs.0, proof).is_err());
+
+        // the keys of the circuit were only created once
+        assert!(Arc::ptr_eq(
+            &keys::<Counter>().unwrap(),
+            &keys::<Counter>().unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_inputs() {
+        assert!(registered("nope").is_err());
+
+        assert_eq!(to_decimal(&from_decimal("1234").unwrap()), "1234");
+        assert!(from_decimal("0x1").is_err());
+        assert!(Counter::from_inputs(&HashMap::new()).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+//! Signing with hardware wallets through [HWI](https://github.com/bitcoin-core/HWI).
+//!
+//! The Bitcoin Core wallet is expected to be a watch-only wallet importing the hardware wallet's descriptors,
+//! so that it can fund transactions and fill in the derivation paths of the inputs in the PSBTs it produces.
+
+use std::process::Command;
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::Txid;
+use log::{debug, info};
+use serde::Deserialize;
+
+use crate::{
+    get_chain,
+    json_rpc_stuff::{finalize_psbt, send_raw_transaction, RpcCtx, TransactionOrHex},
+};
+
+/// A hardware wallet as reported by `hwi enumerate`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Device {
+    /// The type of device (e.g. `coldcard`, `trezor`, `ledger`).
+    #[serde(rename = "type")]
+    pub device_type: String,
+
+    /// The model of the device.
+    pub model: String,
+
+    /// The fingerprint of the device's master key.
+    pub fingerprint: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SignTxResponse {
+    psbt: String,
+}
+
+/// Runs `hwi` with the given arguments and returns its standard output.
+fn run_hwi(args: &[&str]) -> Result<String> {
+    let output = Command::new("hwi")
+        .arg("--chain")
+        .arg(get_chain())
+        .args(args)
+        .output()
+        .context("failed to execute hwi (is it installed?)")?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    debug!("{stdout}");
+
+    if !output.status.success() {
+        info!("{}", String::from_utf8_lossy(&output.stderr));
+        bail!("hwi failed");
+    }
+
+    // hwi reports most errors as a JSON object on stdout
+    if let Ok(serde_json::Value::Object(obj)) = serde_json::from_str(&stdout) {
+        if let Some(err) = obj.get("error") {
+            bail!("hwi returned an error: {err}");
+        }
+    }
+
+    Ok(stdout)
+}
+
+/// Lists the hardware wallets connected to this machine.
+pub fn enumerate() -> Result<Vec<Device>> {
+    let stdout = run_hwi(&["enumerate"])?;
+    serde_json::from_str(&stdout).context("couldn't parse the devices listed by hwi")
+}
+
+/// Signs the inputs of a (base64-encoded) PSBT that belong to a hardware wallet.
+/// If no fingerprint is given, there must be exactly one device connected.
+pub fn sign_psbt(fingerprint: Option<&str>, psbt: &str) -> Result<String> {
+    let fingerprint = match fingerprint {
+        Some(fingerprint) => fingerprint.to_string(),
+        None => {
+            let devices = enumerate()?;
+            ensure!(
+                devices.len() == 1,
+                "expected exactly one hardware wallet connected, found {}",
+                devices.len()
+            );
+            devices[0]
+                .fingerprint
+                .clone()
+                .context("the hardware wallet is locked (no fingerprint available)")?
+        }
+    };
+
+    info!("- please confirm the transaction on the hardware wallet {fingerprint}");
+    let stdout = run_hwi(&["--fingerprint", &fingerprint, "signtx", psbt])?;
+    let response: SignTxResponse =
+        serde_json::from_str(&stdout).context("couldn't parse the PSBT signed by hwi")?;
+
+    Ok(response.psbt)
+}
+
+/// Signs a (base64-encoded) PSBT with a hardware wallet,
+/// and has the Bitcoin node finalize and broadcast it.
+pub async fn sign_and_broadcast(
+    ctx: &RpcCtx,
+    fingerprint: Option<&str>,
+    psbt: &str,
+) -> Result<Txid> {
+    let signed_psbt = sign_psbt(fingerprint, psbt)?;
+
+    let (tx_hex, complete) = finalize_psbt(ctx, &signed_psbt).await?;
+    ensure!(
+        complete,
+        "the PSBT is still missing signatures after signing with the hardware wallet"
+    );
+    let tx_hex = tx_hex.context("the finalized PSBT did not contain a transaction")?;
+
+    send_raw_transaction(ctx, TransactionOrHex::Hex(tx_hex)).await
+}
@@ -0,0 +1,683 @@
+//! A persistent index of the zkapps deployed on chain.
+//!
+//! Listing and following zkapps through the node (see [crate::scanner]) means scanning the UTXO set,
+//! or every block since a zkapp was deployed.
+//! Instead, the indexer goes through every block once, and records in a SQLite database
+//! every zkapp output it finds along with the transaction that spends it (if any).
+//! The zkapps created by the same stateful zkapp share the transaction that first deployed it,
+//! which gives us the state history of that zkapp.
+//!
+//! The indexer keeps the hash of every block it indexed, so that blocks that are reorged out can be rolled back.
+//! Given the storage of the orchestrator, it also notifies the webhooks registered for zkapps (see [crate::committee::policy])
+//! whenever they are spent (at least once: a spend that is reorged out and back in is notified again).
+//!
+//! SQLite is synchronous: from async code (e.g. the handlers of the orchestrator),
+//! the index is queried on the blocking thread pool (see [Indexer::query]).
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use bitcoin::{Amount, Block, BlockHash, OutPoint, Txid};
+use log::{debug, error, info};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use secp256k1::XOnlyPublicKey;
+
+use crate::{
+    bob_request::extract_smart_contract_from_tx,
+    committee::{
+        policy::{Notification, ZkappSpent},
+        storage::Storage,
+    },
+    json_rpc_stuff::{get_block, get_block_count, get_block_hash, RpcCtx},
+    scanner::{Zkapp, ZkappChain, ZkappSpend},
+    zkbitcoin_folder,
+};
+
+//
+// Constants
+//
+
+/// The tables of the index.
+/// Every zkapp output gets a row in `zkapps`,
+/// where `deployment_txid` is the transaction that first deployed the zkapp.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS blocks (
+    height INTEGER PRIMARY KEY,
+    hash TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS zkapps (
+    txid TEXT NOT NULL,
+    vout INTEGER NOT NULL,
+    vk_hash TEXT NOT NULL,
+    locked_value INTEGER NOT NULL,
+    state TEXT,
+    height INTEGER NOT NULL,
+    deployment_txid TEXT NOT NULL,
+    spent_by TEXT,
+    spent_height INTEGER,
+    metadata_hash TEXT,
+    deployer TEXT,
+    PRIMARY KEY (txid, vout)
+);
+
+CREATE INDEX IF NOT EXISTS zkapps_by_deployment ON zkapps (deployment_txid);
+CREATE INDEX IF NOT EXISTS zkapps_by_spend ON zkapps (spent_by);
+";
+
+/// The columns of the `zkapps` table, as read by [IndexedZkapp::from_row].
+const ZKAPP_COLUMNS: &str =
+    "txid, vout, vk_hash, locked_value, state, height, deployment_txid, spent_by, spent_height, metadata_hash, deployer";
+
+//
+// Data structures
+//
+
+/// A zkapp output, as recorded in the index.
+struct IndexedZkapp {
+    zkapp: Zkapp,
+    deployment_txid: Txid,
+    spent: Option<(Txid, u64)>,
+}
+
+impl IndexedZkapp {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let txid = |idx: usize| -> rusqlite::Result<Txid> {
+            let txid: String = row.get(idx)?;
+            Txid::from_str(&txid).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    idx,
+                    rusqlite::types::Type::Text,
+                    err.into(),
+                )
+            })
+        };
+
+        let zkapp = Zkapp {
+            txid: txid(0)?,
+            vout: row.get(1)?,
+            vk_hash: row.get(2)?,
+            locked_value: Amount::from_sat(row.get(3)?),
+            state: row.get(4)?,
+            metadata_hash: row.get(9)?,
+            deployer: row.get(10)?,
+            height: row.get(5)?,
+        };
+        let spent = match row.get::<_, Option<String>>(7)? {
+            Some(_) => Some((txid(7)?, row.get(8)?)),
+            None => None,
+        };
+
+        Ok(Self {
+            zkapp,
+            deployment_txid: txid(6)?,
+            spent,
+        })
+    }
+}
+
+/// The zkapp index.
+pub struct Indexer {
+    conn: Mutex<Connection>,
+    /// The storage of the orchestrator, holding the policies (and webhooks) of zkapps to notify of their spends.
+    pub policies: Option<Storage>,
+}
+
+impl Indexer {
+    /// Returns the default location of the index (`~/.zkbitcoin/index.sqlite`).
+    pub fn default_path() -> PathBuf {
+        zkbitcoin_folder().join("index.sqlite")
+    }
+
+    /// Opens (or creates) the index at the given path.
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("couldn't open index {}", path.display()))?;
+
+        // let readers (e.g. the orchestrator) query the index while it's being updated
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+
+        Self::from_connection(conn)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)
+            .context("couldn't create the tables of the index")?;
+
+        // indexes created before zkapps had metadata (none of the zkapps they hold do)
+        if conn
+            .prepare("SELECT metadata_hash FROM zkapps LIMIT 0")
+            .is_err()
+        {
+            conn.execute("ALTER TABLE zkapps ADD COLUMN metadata_hash TEXT", [])
+                .context("couldn't add the metadata of zkapps to the index")?;
+        }
+
+        // indexes created before zkapps had deployer keys (none of the zkapps they hold do)
+        if conn.prepare("SELECT deployer FROM zkapps LIMIT 0").is_err() {
+            conn.execute("ALTER TABLE zkapps ADD COLUMN deployer TEXT", [])
+                .context("couldn't add the deployers of zkapps to the index")?;
+        }
+        Ok(Self {
+            conn: Mutex::new(conn),
+            policies: None,
+        })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().expect("index lock poisoned")
+    }
+
+    /// Runs `query` on the blocking thread pool, so that the (synchronous) accesses to the index
+    /// don't stall the async runtime.
+    pub async fn query<T, F>(self: &Arc<Self>, query: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Indexer) -> Result<T> + Send + 'static,
+    {
+        let indexer = self.clone();
+        tokio::task::spawn_blocking(move || query(&indexer))
+            .await
+            .context("the query of the index panicked")?
+    }
+
+    //
+    // Indexing
+    //
+
+    /// Returns the height and hash of the last indexed block, if any.
+    pub fn tip(&self) -> Result<Option<(u64, BlockHash)>> {
+        let tip = self
+            .conn()
+            .query_row(
+                "SELECT height, hash FROM blocks ORDER BY height DESC LIMIT 1",
+                [],
+                |row| Ok((row.get::<_, u64>(0)?, row.get::<_, String>(1)?)),
+            )
+            .optional()?;
+
+        match tip {
+            Some((height, hash)) => Ok(Some((height, BlockHash::from_str(&hash)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Records the zkapps created and spent by a block.
+    /// The block must extend the last indexed block (if any).
+    /// Returns the number of zkapps found in the block.
+    pub fn index_block(&self, height: u64, block: &Block) -> Result<usize> {
+        let mut conn = self.conn();
+        let db = conn.transaction()?;
+
+        // make sure we're not mixing blocks of different chains
+        let previous: Option<(u64, String)> = db
+            .query_row(
+                "SELECT height, hash FROM blocks ORDER BY height DESC LIMIT 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        if let Some((previous_height, previous_hash)) = previous {
+            ensure!(
+                previous_height + 1 == height,
+                "block {height} doesn't follow the last indexed block {previous_height}"
+            );
+            ensure!(
+                block.header.prev_blockhash.to_string() == previous_hash,
+                "block {height} doesn't extend the last indexed block (reorg?)"
+            );
+        }
+
+        let mut found = 0;
+        for tx in &block.txdata {
+            let txid = tx.txid();
+
+            // mark the zkapps spent by the transaction
+            let mut spent = None;
+            for input in &tx.input {
+                let prev = input.previous_output;
+                let zkapp: Option<(String, bool)> = db
+                    .query_row(
+                        "SELECT deployment_txid, state IS NOT NULL FROM zkapps
+                         WHERE txid = ?1 AND vout = ?2 AND spent_by IS NULL",
+                        params![prev.txid.to_string(), prev.vout],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )
+                    .optional()?;
+                if let Some(zkapp) = zkapp {
+                    debug!("- zkapp {prev} spent by {txid} at height {height}");
+                    db.execute(
+                        "UPDATE zkapps SET spent_by = ?1, spent_height = ?2 WHERE txid = ?3 AND vout = ?4",
+                        params![txid.to_string(), height, prev.txid.to_string(), prev.vout],
+                    )?;
+                    spent = Some(zkapp);
+                }
+            }
+
+            // record the zkapp created by the transaction (if any)
+            let Ok(smart_contract) = extract_smart_contract_from_tx(tx) else {
+                continue;
+            };
+            let zkapp = Zkapp::new(smart_contract, height);
+
+            // a stateful zkapp lives on in a new output with the same verifier key
+            // (or another one, if it's upgraded, see [crate::upgrade])
+            let deployment_txid = match spent {
+                Some((deployment_txid, true)) if zkapp.is_stateful() => deployment_txid,
+                _ => txid.to_string(),
+            };
+            debug!("- found zkapp {} at height {height}", zkapp.outpoint());
+            db.execute(
+                "INSERT OR REPLACE INTO zkapps (txid, vout, vk_hash, locked_value, state, height, deployment_txid, metadata_hash, deployer)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    zkapp.txid.to_string(),
+                    zkapp.vout,
+                    zkapp.vk_hash,
+                    zkapp.locked_value.to_sat(),
+                    zkapp.state,
+                    zkapp.height,
+                    deployment_txid,
+                    zkapp.metadata_hash,
+                    zkapp.deployer,
+                ],
+            )?;
+            found += 1;
+        }
+
+        db.execute(
+            "INSERT INTO blocks (height, hash) VALUES (?1, ?2)",
+            params![height, block.block_hash().to_string()],
+        )?;
+        db.commit()?;
+
+        Ok(found)
+    }
+
+    /// Forgets everything that happened at or above the given height.
+    pub fn rollback(&self, height: u64) -> Result<()> {
+        let mut conn = self.conn();
+        let db = conn.transaction()?;
+        db.execute("DELETE FROM zkapps WHERE height >= ?1", [height])?;
+        db.execute(
+            "UPDATE zkapps SET spent_by = NULL, spent_height = NULL WHERE spent_height >= ?1",
+            [height],
+        )?;
+        db.execute("DELETE FROM blocks WHERE height >= ?1", [height])?;
+        db.commit()?;
+        Ok(())
+    }
+
+    /// Indexes the blocks of the node that are not indexed yet,
+    /// starting at `start_height` if the index is empty.
+    /// Blocks that are no longer part of the node's best chain are rolled back first.
+    /// Returns the number of blocks indexed.
+    pub async fn sync(self: &Arc<Self>, ctx: &RpcCtx, start_height: u64) -> Result<u64> {
+        let node_tip = get_block_count(ctx).await?;
+
+        let mut next = start_height;
+        while let Some((height, hash)) = self.query(|indexer| indexer.tip()).await? {
+            if height <= node_tip && get_block_hash(ctx, height).await? == hash {
+                next = height + 1;
+                break;
+            }
+            info!("- block {height} ({hash}) is no longer in the best chain, rolling it back");
+            self.query(move |indexer| indexer.rollback(height)).await?;
+        }
+
+        for height in next..=node_tip {
+            let block = get_block(ctx, get_block_hash(ctx, height).await?).await?;
+            let found = self
+                .query(move |indexer| indexer.index_block(height, &block))
+                .await?;
+            if found > 0 {
+                info!("- indexed {found} zkapps at height {height}");
+            }
+            if self.policies.is_some() {
+                let notified = self.query(move |indexer| indexer.notify_spends(height));
+                if let Err(err) = notified.await {
+                    error!("- couldn't notify the spends at height {height}: {err:#}");
+                }
+            }
+        }
+
+        Ok((node_tip + 1).saturating_sub(next))
+    }
+
+    /// Notifies the webhooks of the zkapps spent at the given height (registered by their deployer).
+    fn notify_spends(&self, height: u64) -> Result<()> {
+        let Some(policies) = &self.policies else {
+            return Ok(());
+        };
+        for (zkapp, spend) in self.spends_at(height)? {
+            let Some(deployer) = &zkapp.deployer else {
+                continue;
+            };
+            let deployer = XOnlyPublicKey::from_str(deployer)
+                .with_context(|| format!("invalid deployer key {deployer}"))?;
+            let vk_hash: [u8; 32] = hex::decode(&zkapp.vk_hash)?
+                .try_into()
+                .map_err(|_| anyhow!("invalid vk hash {}", zkapp.vk_hash))?;
+            if let Some(policy) = policies.policy(&vk_hash, &deployer)? {
+                let spent = ZkappSpent::new(&zkapp, &spend);
+                policy.send(vk_hash, Notification::ZkappSpent(spent));
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the index up to date with the node, checking for new blocks every `poll_interval`.
+    pub async fn run(self: &Arc<Self>, ctx: &RpcCtx, start_height: u64, poll_interval: Duration) {
+        loop {
+            match self.sync(ctx, start_height).await {
+                Ok(0) => (),
+                Ok(indexed) => info!("- indexed {indexed} new blocks"),
+                Err(err) => error!("- couldn't update the index: {err:#}"),
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    //
+    // Queries
+    //
+
+    /// Returns the zkapps that are currently unspent, as of the last indexed block.
+    pub fn list_zkapps(&self) -> Result<Vec<Zkapp>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ZKAPP_COLUMNS} FROM zkapps WHERE spent_by IS NULL ORDER BY height, rowid"
+        ))?;
+        let zkapps = stmt
+            .query_map([], IndexedZkapp::from_row)?
+            .map(|row| Ok(row?.zkapp))
+            .collect::<Result<_>>()?;
+        Ok(zkapps)
+    }
+
+    /// Follows a zkapp from the given transaction, through all its spends (see [crate::scanner::follow_zkapp]).
+    pub fn follow_zkapp(&self, txid: Txid) -> Result<ZkappChain> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ZKAPP_COLUMNS} FROM zkapps WHERE deployment_txid = (
+                SELECT deployment_txid FROM zkapps WHERE txid = ?1
+            ) ORDER BY height, rowid"
+        ))?;
+        let zkapps = stmt
+            .query_map([txid.to_string()], IndexedZkapp::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut current = zkapps
+            .iter()
+            .find(|indexed| indexed.zkapp.txid == txid)
+            .ok_or_else(|| anyhow!("zkapp {txid} is not in the index"))?;
+        let deployment = current.zkapp.clone();
+        debug!(
+            "- zkapp {txid} was first deployed by {}",
+            current.deployment_txid
+        );
+
+        let mut spends = vec![];
+        while let Some((spend_txid, spend_height)) = current.spent {
+            let next = zkapps
+                .iter()
+                .find(|indexed| indexed.zkapp.txid == spend_txid);
+            spends.push(ZkappSpend {
+                txid: spend_txid,
+                height: spend_height,
+                zkapp: next.map(|indexed| indexed.zkapp.clone()),
+            });
+            match next {
+                Some(next) => current = next,
+                None => break,
+            }
+        }
+
+        Ok(ZkappChain { deployment, spends })
+    }
+
+    /// Returns the zkapps spent by the block at the given height, along with their spends.
+    pub fn spends_at(&self, height: u64) -> Result<Vec<(Zkapp, ZkappSpend)>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ZKAPP_COLUMNS} FROM zkapps WHERE spent_height = ?1 ORDER BY rowid"
+        ))?;
+        let spent = stmt
+            .query_map([height], IndexedZkapp::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        // a stateful zkapp lives on in an output of the spending transaction
+        let mut next_stmt = conn.prepare(&format!(
+            "SELECT {ZKAPP_COLUMNS} FROM zkapps WHERE txid = ?1 AND deployment_txid = ?2"
+        ))?;
+        spent
+            .into_iter()
+            .filter_map(|indexed| Some((indexed.spent?, indexed)))
+            .map(|((txid, height), indexed)| {
+                let next = next_stmt
+                    .query_row(
+                        params![txid.to_string(), indexed.deployment_txid.to_string()],
+                        IndexedZkapp::from_row,
+                    )
+                    .optional()?;
+                let spend = ZkappSpend {
+                    txid,
+                    height,
+                    zkapp: next.map(|next| next.zkapp),
+                };
+                Ok((indexed.zkapp, spend))
+            })
+            .collect()
+    }
+
+    /// Returns whether a zkapp output was spent, or `None` if the index doesn't know about it.
+    pub fn is_spent(&self, outpoint: OutPoint) -> Result<Option<bool>> {
+        let spent = self
+            .conn()
+            .query_row(
+                "SELECT spent_by IS NOT NULL FROM zkapps WHERE txid = ?1 AND vout = ?2",
+                params![outpoint.txid.to_string(), outpoint.vout],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(spent)
+    }
+
+    /// Returns the number of confirmations of a zkapp output as of the last indexed block,
+    /// or `None` if the index doesn't know about it.
+    pub fn confirmations(&self, outpoint: OutPoint) -> Result<Option<u64>> {
+        let height: Option<u64> = self
+            .conn()
+            .query_row(
+                "SELECT height FROM zkapps WHERE txid = ?1 AND vout = ?2",
+                params![outpoint.txid.to_string(), outpoint.vout],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let tip = self.tip()?;
+
+        Ok(height
+            .zip(tip)
+            .map(|(height, (tip, _))| (tip + 1).saturating_sub(height)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        absolute::LockTime,
+        block::{Header, Version},
+        hashes::Hash,
+        transaction, CompactTarget, ScriptBuf, Sequence, Transaction, TxIn, TxMerkleNode, Witness,
+    };
+
+    use super::*;
+
+    fn indexer() -> Indexer {
+        Indexer::from_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    fn block(prev_blockhash: BlockHash, txdata: Vec<Transaction>) -> Block {
+        Block {
+            header: Header {
+                version: Version::ONE,
+                prev_blockhash,
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 0,
+                bits: CompactTarget::from_consensus(0),
+                nonce: 0,
+            },
+            txdata,
+        }
+    }
+
+    fn spend(outpoint: OutPoint) -> Transaction {
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![],
+        }
+    }
+
+    fn insert_zkapp(indexer: &Indexer, zkapp: &Zkapp) {
+        indexer
+            .conn()
+            .execute(
+                "INSERT INTO zkapps (txid, vout, vk_hash, locked_value, state, height, deployment_txid)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?1)",
+                params![
+                    zkapp.txid.to_string(),
+                    zkapp.vout,
+                    zkapp.vk_hash,
+                    zkapp.locked_value.to_sat(),
+                    zkapp.state,
+                    zkapp.height,
+                ],
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_spend_and_rollback() {
+        let indexer = indexer();
+        let zkapp = Zkapp {
+            txid: Txid::from_str(
+                "e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836",
+            )
+            .unwrap(),
+            vout: 0,
+            vk_hash: "00".repeat(32),
+            locked_value: Amount::from_sat(1000),
+            state: None,
+            metadata_hash: None,
+            deployer: None,
+            height: 1,
+        };
+        insert_zkapp(&indexer, &zkapp);
+
+        let block1 = block(BlockHash::all_zeros(), vec![]);
+        indexer.index_block(1, &block1).unwrap();
+        assert_eq!(indexer.list_zkapps().unwrap(), vec![zkapp.clone()]);
+        assert_eq!(indexer.is_spent(zkapp.outpoint()).unwrap(), Some(false));
+        assert_eq!(indexer.confirmations(zkapp.outpoint()).unwrap(), Some(1));
+
+        // a block that doesn't extend the tip is refused
+        let orphan = block(BlockHash::all_zeros(), vec![]);
+        assert!(indexer.index_block(2, &orphan).is_err());
+
+        // the zkapp gets spent
+        let spend_tx = spend(zkapp.outpoint());
+        let block2 = block(block1.block_hash(), vec![spend_tx.clone()]);
+        indexer.index_block(2, &block2).unwrap();
+        assert!(indexer.list_zkapps().unwrap().is_empty());
+        assert_eq!(indexer.is_spent(zkapp.outpoint()).unwrap(), Some(true));
+        assert_eq!(indexer.confirmations(zkapp.outpoint()).unwrap(), Some(2));
+        let spends = indexer.spends_at(2).unwrap();
+        assert_eq!(spends.len(), 1);
+        assert_eq!(spends[0].0, zkapp);
+        assert_eq!(spends[0].1.txid, spend_tx.txid());
+
+        let chain = indexer.follow_zkapp(zkapp.txid).unwrap();
+        assert_eq!(chain.deployment, zkapp);
+        assert_eq!(
+            chain.spends,
+            vec![ZkappSpend {
+                txid: spend_tx.txid(),
+                height: 2,
+                zkapp: None,
+            }]
+        );
+        assert_eq!(chain.latest(), None);
+
+        // the spend is reorged out
+        indexer.rollback(2).unwrap();
+        assert_eq!(indexer.tip().unwrap(), Some((1, block1.block_hash())));
+        assert_eq!(indexer.list_zkapps().unwrap(), vec![zkapp.clone()]);
+
+        // unknown outputs
+        assert_eq!(
+            indexer.is_spent(OutPoint::new(spend_tx.txid(), 0)).unwrap(),
+            None
+        );
+        assert!(indexer.follow_zkapp(spend_tx.txid()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        let indexer = Arc::new(indexer());
+        let block1 = block(BlockHash::all_zeros(), vec![]);
+        let hash = block1.block_hash();
+        indexer
+            .query(move |indexer| indexer.index_block(1, &block1))
+            .await
+            .unwrap();
+        let tip = indexer.query(|indexer| indexer.tip()).await.unwrap();
+        assert_eq!(tip, Some((1, hash)));
+    }
+
+    #[test]
+    fn test_index_without_metadata() {
+        // an index created before zkapps had metadata
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE zkapps (
+                txid TEXT NOT NULL,
+                vout INTEGER NOT NULL,
+                vk_hash TEXT NOT NULL,
+                locked_value INTEGER NOT NULL,
+                state TEXT,
+                height INTEGER NOT NULL,
+                deployment_txid TEXT NOT NULL,
+                spent_by TEXT,
+                spent_height INTEGER,
+                PRIMARY KEY (txid, vout)
+            );",
+        )
+        .unwrap();
+        let indexer = Indexer::from_connection(conn).unwrap();
+
+        let zkapp = Zkapp {
+            txid: Txid::all_zeros(),
+            vout: 0,
+            vk_hash: "00".repeat(32),
+            locked_value: Amount::from_sat(1000),
+            state: None,
+            metadata_hash: None,
+            deployer: None,
+            height: 1,
+        };
+        insert_zkapp(&indexer, &zkapp);
+        assert_eq!(indexer.list_zkapps().unwrap(), vec![zkapp]);
+    }
+}
@@ -0,0 +1,187 @@
+//! Publication of the verifier keys of zkapps on IPFS, so that their users don't need the circuit to check proofs.
+//!
+//! The hash of a verifier key committed on chain is the Keccak-256 of its JSON serialization,
+//! which IPFS can address directly: the verifier key is published as a raw block hashed with Keccak-256,
+//! whose CID is derived from the on-chain hash alone (see [cid_of]). So the deployment transaction references it
+//! without committing to anything more, and whatever a gateway returns is checked against the hash.
+//! (zkVM programs are committed to by their image ID instead, so their verifier keys can't be published this way.)
+
+use std::time::Duration;
+
+use anyhow::{ensure, Context, Result};
+use log::{debug, info};
+use serde::Deserialize;
+use sha3::{Digest, Keccak256};
+
+use crate::{json_rpc_stuff::http_client, plonk::VerifierKey};
+
+//
+// Constants
+//
+
+/// The gateway verifier keys are fetched from by default.
+pub const DEFAULT_GATEWAY: &str = "https://ipfs.io";
+
+/// Timeout (in seconds) for requests to IPFS.
+const IPFS_TIMEOUT: u64 = 30;
+
+/// The largest block IPFS nodes exchange.
+const MAX_BLOCK_SIZE: usize = 1024 * 1024;
+
+/// The prefix of the CIDs of verifier keys: CIDv1, of a raw block, hashed with a 32-byte Keccak-256.
+const CID_PREFIX: [u8; 4] = [0x01, 0x55, 0x1b, 0x20];
+
+/// The multipart boundary of the blocks published.
+const BOUNDARY: &str = "zkbitcoin-verifier-key";
+
+//
+// CIDs
+//
+
+/// The CID of the verifier key committed to by `vk_hash`.
+pub fn cid_of(vk_hash: &[u8; 32]) -> String {
+    let mut bytes = CID_PREFIX.to_vec();
+    bytes.extend(vk_hash);
+    // multibase prefix of lowercase base32
+    format!("b{}", base32(&bytes))
+}
+
+/// Encodes bytes in lowercase base32 (RFC 4648), without padding.
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+    let mut encoded = String::with_capacity((bytes.len() * 8 + 4) / 5);
+    let (mut buffer, mut bits) = (0u16, 0);
+    for byte in bytes {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+    encoded
+}
+
+/// The block of a verifier key: its JSON serialization, which hashes to the hash committed on chain.
+fn block_of(vk: &VerifierKey) -> Result<Vec<u8>> {
+    let block = serde_json::to_vec(vk)?;
+    ensure!(
+        Keccak256::digest(&block).as_slice() == vk.hash(),
+        "the verifier key is committed to by its image ID, not by its hash, so it can't be published on IPFS"
+    );
+    ensure!(
+        block.len() <= MAX_BLOCK_SIZE,
+        "the verifier key is too large ({} bytes) to be published on IPFS",
+        block.len()
+    );
+    Ok(block)
+}
+
+//
+// Publishing and fetching
+//
+
+#[derive(Deserialize)]
+struct BlockPut {
+    #[serde(rename = "Key")]
+    key: String,
+}
+
+/// Publishes (and pins) a verifier key through the RPC API of an IPFS node (e.g. `http://127.0.0.1:5001`).
+/// Returns its CID.
+pub async fn publish(api_url: &str, vk: &VerifierKey) -> Result<String> {
+    let block = block_of(vk)?;
+    let cid = cid_of(&vk.hash());
+
+    let url = format!(
+        "{}/api/v0/block/put?cid-codec=raw&mhtype=keccak-256&mhlen=32&pin=true",
+        api_url.trim_end_matches('/')
+    );
+    debug!("- POST {url}");
+    let mut body = format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"vk.json\"\r\nContent-Type: application/octet-stream\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend(&block);
+    body.extend(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+
+    let response = http_client()
+        .post(&url)
+        .header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        )
+        .body(body)
+        .timeout(Duration::from_secs(IPFS_TIMEOUT))
+        .send()
+        .await
+        .with_context(|| format!("couldn't reach the IPFS node at {api_url}"))?;
+    let status = response.status();
+    let text = response.text().await?;
+    ensure!(
+        status.is_success(),
+        "publishing the verifier key failed ({status}): {text}"
+    );
+    let put: BlockPut = serde_json::from_str(&text)
+        .with_context(|| format!("unexpected response from the IPFS node: {text}"))?;
+    ensure!(
+        put.key == cid,
+        "the IPFS node published the verifier key as {}, not {cid}",
+        put.key
+    );
+
+    info!("- published the verifier key on IPFS as {cid}");
+    Ok(cid)
+}
+
+/// Fetches the verifier key committed to by `vk_hash` from an IPFS gateway, and checks it against the hash.
+pub async fn fetch(gateway: &str, vk_hash: &[u8; 32]) -> Result<VerifierKey> {
+    let cid = cid_of(vk_hash);
+    let url = format!("{}/ipfs/{cid}", gateway.trim_end_matches('/'));
+    debug!("- GET {url}");
+    let response = http_client()
+        .get(&url)
+        .header("Accept", "application/vnd.ipld.raw")
+        .timeout(Duration::from_secs(IPFS_TIMEOUT))
+        .send()
+        .await
+        .with_context(|| format!("couldn't reach the IPFS gateway {gateway}"))?;
+    let status = response.status();
+    ensure!(
+        status.is_success(),
+        "the verifier key {cid} couldn't be fetched from {gateway} ({status}), was it published?"
+    );
+    let block = response.bytes().await?;
+
+    ensure!(
+        Keccak256::digest(&block).as_slice() == vk_hash,
+        "the IPFS gateway {gateway} returned something else than the verifier key {cid}"
+    );
+    let vk: VerifierKey =
+        serde_json::from_slice(&block).context("the block published is not a verifier key")?;
+    ensure!(
+        &vk.hash() == vk_hash,
+        "the block published is not the verifier key committed to"
+    );
+    info!("- fetched the verifier key from IPFS ({cid})");
+    Ok(vk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cid_of() {
+        assert_eq!(base32(b""), "");
+        assert_eq!(base32(b"f"), "my");
+        assert_eq!(base32(b"foobar"), "mzxw6ytboi");
+        assert_eq!(
+            cid_of(&[0; 32]),
+            "bafkrwiaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+        );
+    }
+}
@@ -2,32 +2,51 @@
 //! It heavily relies on the jsonrpc and bitcoincore_rpc crates (and its dependencies).
 //! It does not directly make use of these crates due to some issues (loss of information when getting 500 errors from bitcoind).
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use base64::{engine::general_purpose, Engine};
-use bitcoin::{Amount, Transaction, Txid};
-use log::{debug, info, log_enabled, Level};
+use bitcoin::{Amount, Block, BlockHash, FeeRate, OutPoint, Psbt, Transaction, Txid};
+use log::{debug, info, log_enabled, warn, Level};
 use reqwest::{
     header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
-    Client,
+    Client, ClientBuilder, Proxy, StatusCode,
 };
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::{path::PathBuf, sync::OnceLock, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::Instant,
+};
 
-use crate::constants::BITCOIN_JSON_RPC_VERSION;
+use crate::{coin_selection::Change, constants::BITCOIN_JSON_RPC_VERSION, get_network};
 
 /// Timeout (in seconds) for json rpc requests.
 const JSON_RPC_TIMEOUT: u64 = 10;
 
+/// How long (in seconds) idle connections are kept in the pool.
+const POOL_IDLE_TIMEOUT: u64 = 90;
+
+/// Interval (in seconds) of the TCP keep-alive probes sent on pooled connections.
+const TCP_KEEP_ALIVE_INTERVAL: u64 = 30;
+
+/// The error code of Bitcoin Core when it's still starting up.
+const RPC_IN_WARMUP: i64 = -28;
+
+/// The number of blocks we aim for transactions to be confirmed within, when estimating fees.
+pub const DEFAULT_CONF_TARGET: u16 = 6;
+
 //
 // Context
 //
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct RpcCtx {
     pub version: Option<&'static str>,
     pub wallet: Option<String>,
     pub address: Option<String>,
     pub auth: Option<String>,
+    /// The cookie file of Bitcoin Core, used if no `auth` is given.
+    pub cookie_file: Option<PathBuf>,
 }
 
 impl RpcCtx {
@@ -37,17 +56,28 @@ impl RpcCtx {
         address: Option<String>,
         auth: Option<String>,
     ) -> Self {
+        // Bitcoin Core creates a cookie file when no rpcauth is configured
+        // (a cookie file given explicitly must exist, see [RpcCtx::credentials])
+        let cookie_file = match auth {
+            Some(_) => None,
+            None => Self::explicit_cookie_file()
+                .or_else(|| Self::default_cookie_file().filter(|path| path.exists())),
+        };
+
         let ctx = Self {
             version,
             wallet,
             address,
             auth,
+            cookie_file,
         };
 
         info!("- using RPC node at address {}", ctx.address());
 
         if ctx.auth().is_some() {
             info!("- using given RPC credentials");
+        } else if let Some(cookie_file) = &ctx.cookie_file {
+            info!("- using RPC cookie file {}", cookie_file.display());
         } else {
             info!("- using no RPC credentials");
         }
@@ -79,6 +109,56 @@ impl RpcCtx {
         })*/
     }
 
+    /// Returns the path of the cookie file of Bitcoin Core given explicitly:
+    /// the one given to [set_cookie_file], or `RPC_COOKIE_FILE` if set.
+    fn explicit_cookie_file() -> Option<PathBuf> {
+        COOKIE_FILE
+            .get()
+            .cloned()
+            .or_else(|| std::env::var_os("RPC_COOKIE_FILE").map(PathBuf::from))
+    }
+
+    /// Returns the path of the cookie file of Bitcoin Core:
+    /// the one given explicitly (see [set_cookie_file]), or its default location for the current network otherwise.
+    pub fn default_cookie_file() -> Option<PathBuf> {
+        if let Some(path) = Self::explicit_cookie_file() {
+            return Some(path);
+        }
+
+        let home = home::home_dir()?;
+        let datadir = if cfg!(target_os = "macos") {
+            home.join("Library/Application Support/Bitcoin")
+        } else if cfg!(target_os = "windows") {
+            home.join("AppData/Roaming/Bitcoin")
+        } else {
+            home.join(".bitcoin")
+        };
+        let datadir = match get_network() {
+            bitcoin::Network::Bitcoin => datadir,
+            bitcoin::Network::Testnet => datadir.join("testnet3"),
+            bitcoin::Network::Signet => datadir.join("signet"),
+            _ => datadir.join("regtest"),
+        };
+        Some(datadir.join(".cookie"))
+    }
+
+    /// Returns the `user:password` to authenticate with,
+    /// reading the cookie file if needed (as it changes every time Bitcoin Core restarts).
+    fn credentials(&self) -> Result<Option<String>> {
+        if let Some(auth) = self.auth() {
+            return Ok(Some(auth.to_string()));
+        }
+        match &self.cookie_file {
+            Some(cookie_file) => {
+                let cookie = std::fs::read_to_string(cookie_file).with_context(|| {
+                    format!("couldn't read cookie file {}", cookie_file.display())
+                })?;
+                Ok(Some(cookie.trim().to_string()))
+            }
+            None => Ok(None),
+        }
+    }
+
     pub fn for_testing() -> Self {
         let endpoint = std::env::var("BITCOIN_JSON_RPC_ENDPOINT").unwrap();
         let auth = std::env::var("BITCOIN_JSON_RPC_AUTH").unwrap_or("root:hellohello".to_string());
@@ -89,21 +169,336 @@ impl RpcCtx {
             wallet: Some(wallet),
             address: Some(endpoint),
             auth: Some(auth),
+            cookie_file: None,
+        }
+    }
+}
+
+/// The cookie file of Bitcoin Core, once set (see [set_cookie_file]).
+static COOKIE_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the cookie file of Bitcoin Core to authenticate with when no credentials are given
+/// (see [RpcCtx::default_cookie_file]), which must exist. This can only be done once.
+pub fn set_cookie_file(path: PathBuf) -> Result<()> {
+    ensure!(
+        path.is_file(),
+        "cookie file {} doesn't exist",
+        path.display()
+    );
+    COOKIE_FILE
+        .set(path)
+        .map_err(|_| anyhow!("the cookie file was already set"))
+}
+
+//
+// HTTP client
+//
+
+/// The HTTP client shared by all outbound requests (see [http_client]).
+static HTTP_CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Returns the HTTP client shared by all outbound requests,
+/// so that connections (to the bitcoind node in particular) are pooled and kept alive between requests
+/// instead of being re-established for every call.
+/// HTTP/2 is negotiated with servers that support it over TLS.
+/// It has no timeout: callers set one on each request.
+pub fn http_client() -> &'static Client {
+    HTTP_CLIENT.get_or_init(|| {
+        http_client_builder()
+            .build()
+            .expect("couldn't build the HTTP client")
+    })
+}
+
+/// Returns a builder of clients configured like the shared one (see [http_client]),
+/// for the requests that need a client of their own (e.g. the notifications of webhooks, see [crate::committee::policy]).
+pub fn http_client_builder() -> ClientBuilder {
+    let mut builder = Client::builder()
+        .pool_idle_timeout(Duration::from_secs(POOL_IDLE_TIMEOUT))
+        .tcp_keepalive(Duration::from_secs(TCP_KEEP_ALIVE_INTERVAL))
+        .tcp_nodelay(true);
+    if let Some(proxy) = proxy() {
+        builder = builder.proxy(proxy.clone());
+    }
+    builder
+}
+
+//
+// Proxy
+//
+
+/// The proxy all outbound requests go through, once set (see [set_proxy]), along with its `host:port`.
+static PROXY: OnceLock<(Proxy, String)> = OnceLock::new();
+
+/// Parses the URL of a SOCKS5 proxy, returning it along with its `host:port`.
+/// With `socks5h://`, host names are resolved by the proxy, which is needed to reach `.onion` addresses over Tor.
+fn parse_proxy(url: &str) -> Result<(Proxy, String)> {
+    let address = url
+        .strip_prefix("socks5://")
+        .or_else(|| url.strip_prefix("socks5h://"))
+        .with_context(|| {
+            format!("only SOCKS5 proxies are supported (socks5://host:port or socks5h://host:port), got {url}")
+        })?;
+    let address = address.trim_end_matches('/').to_string();
+    let proxy = Proxy::all(url).with_context(|| format!("invalid proxy URL {url}"))?;
+    Ok((proxy, address))
+}
+
+/// Routes all outbound requests (to the bitcoind node, the orchestrator, the committee members,
+/// Electrum servers, light client peers, and Nostr relays) through a SOCKS5 proxy, e.g. `socks5h://127.0.0.1:9050` for Tor.
+/// This can only be done once, before any request is made.
+pub fn set_proxy(url: &str) -> Result<()> {
+    let proxy = parse_proxy(url)?;
+    ensure!(
+        HTTP_CLIENT.get().is_none(),
+        "the proxy must be set before any request is made"
+    );
+    PROXY
+        .set(proxy)
+        .map_err(|_| anyhow!("the proxy was already set"))
+}
+
+/// Returns the proxy outbound requests go through (if any).
+pub fn proxy() -> Option<&'static Proxy> {
+    PROXY.get().map(|(proxy, _)| proxy)
+}
+
+/// Returns the `host:port` of the proxy outbound requests go through (if any).
+pub fn proxy_address() -> Option<&'static str> {
+    PROXY.get().map(|(_, address)| address.as_str())
+}
+
+/// Opens a TCP connection to `address` (`host:port`), through the proxy if one is set.
+/// Host names are then always resolved by the proxy, so that no DNS request leaks around it.
+pub async fn connect_tcp(address: &str) -> Result<TcpStream> {
+    let Some(proxy_address) = proxy_address() else {
+        return Ok(TcpStream::connect(address).await?);
+    };
+    let (host, port) = address
+        .rsplit_once(':')
+        .with_context(|| format!("expected host:port, got {address}"))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port: u16 = port
+        .parse()
+        .with_context(|| format!("invalid port in {address}"))?;
+    ensure!(host.len() <= 255, "the host name {host} is too long");
+
+    let mut stream = TcpStream::connect(proxy_address)
+        .await
+        .with_context(|| format!("couldn't reach the proxy at {proxy_address}"))?;
+    socks5_connect(&mut stream, host, port)
+        .await
+        .with_context(|| format!("the proxy couldn't connect to {address}"))?;
+    Ok(stream)
+}
+
+/// Asks a SOCKS5 proxy (without authentication) to connect to `host:port` (see RFC 1928).
+async fn socks5_connect(stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+    // greeting: version 5, one method (no authentication)
+    stream.write_all(&[5, 1, 0]).await?;
+    let mut choice = [0u8; 2];
+    stream.read_exact(&mut choice).await?;
+    ensure!(
+        choice == [5, 0],
+        "the proxy requires authentication, which isn't supported"
+    );
+
+    // connect to the host by name (or address)
+    let mut request = vec![5, 1, 0, 3, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    ensure!(reply[0] == 5, "not a SOCKS5 proxy");
+    ensure!(
+        reply[1] == 0,
+        "connection refused (SOCKS5 error {})",
+        reply[1]
+    );
+    // skip the address the proxy bound to
+    let address_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => stream.read_u8().await? as usize,
+        atyp => bail!("invalid address type {atyp} in the reply of the proxy"),
+    };
+    let mut bound = vec![0u8; address_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+//
+// Retries
+//
+
+/// The retry policy in use, once set (see [retry_policy]).
+static RETRY_POLICY: OnceLock<RetryPolicy> = OnceLock::new();
+
+/// How requests to the bitcoind node are retried when it's unreachable, busy, or starting up.
+/// Errors returned by the node (e.g. an invalid transaction) are never retried,
+/// and timeouts only are for the methods that don't change anything (see [is_transient_error]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryPolicy {
+    /// The maximum number of times a request is retried.
+    pub max_retries: u32,
+
+    /// The delay (in milliseconds) before the first retry, doubled on every retry.
+    pub initial_backoff_ms: u64,
+
+    /// The maximum delay (in milliseconds) between two retries.
+    pub max_backoff_ms: u64,
+
+    /// The timeout (in seconds) of a single attempt.
+    pub timeout_secs: u64,
+
+    /// The maximum time (in seconds) spent on a request, retries included.
+    pub deadline_secs: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 8_000,
+            timeout_secs: JSON_RPC_TIMEOUT,
+            deadline_secs: 60,
         }
     }
 }
 
+impl RetryPolicy {
+    /// Returns the delay before the given retry (starting at 0).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff_ms
+            .saturating_mul(1 << retry.min(32))
+            .min(self.max_backoff_ms);
+        Duration::from_millis(backoff)
+    }
+}
+
+/// Sets the retry policy of requests to the bitcoind node.
+/// This can only be done once, before any request is made.
+pub fn set_retry_policy(policy: RetryPolicy) -> Result<()> {
+    RETRY_POLICY
+        .set(policy)
+        .map_err(|_| anyhow!("the retry policy was already set"))
+}
+
+/// Returns the retry policy of requests to the bitcoind node (the default one if none was set).
+pub fn retry_policy() -> &'static RetryPolicy {
+    RETRY_POLICY.get_or_init(RetryPolicy::default)
+}
+
+/// The methods of the node that only read its state, and can thus be sent again after timing out.
+/// Any other request (including the ones sent to the orchestrator and the committee, e.g. `unlock_funds`)
+/// might have been processed even though no response came back in time, so it's only retried if it never got sent.
+const IDEMPOTENT_METHODS: &[&str] = &[
+    "estimatesmartfee",
+    "getbestblockhash",
+    "getblock",
+    "getblockchaininfo",
+    "getblockcount",
+    "getblockhash",
+    "getblockheader",
+    "getrawtransaction",
+    "gettransaction",
+    "gettxout",
+    "listunspent",
+];
+
+/// Returns true if a request failed because the node couldn't be reached,
+/// or (for [IDEMPOTENT_METHODS] only) because it didn't answer in time.
+fn is_transient_error(method: &str, err: &anyhow::Error) -> bool {
+    match err.downcast_ref::<reqwest::Error>() {
+        Some(err) => err.is_connect() || (err.is_timeout() && IDEMPOTENT_METHODS.contains(&method)),
+        None => false,
+    }
+}
+
+/// Returns true if the node answered that it's busy or still starting up.
+fn is_transient_response(status: StatusCode, body: &str) -> bool {
+    if status == StatusCode::SERVICE_UNAVAILABLE {
+        return true;
+    }
+    serde_json::from_str::<serde_json::Value>(body)
+        .map(|response| response["error"]["code"].as_i64() == Some(RPC_IN_WARMUP))
+        .unwrap_or(false)
+}
+
 //
 // Main JSON RPC request function
 //
 
 /// Implements a JSON RPC request to the bitcoind node.
 /// Following the [JSON RPC 1.0 spec](https://www.jsonrpc.org/specification_v1).
+/// Requests are retried following the [retry_policy], when they couldn't be sent or were refused by a busy node
+/// (and when they timed out, if they only read the state of the node, see [is_transient_error]).
 pub async fn json_rpc_request<'a>(
     ctx: &RpcCtx,
     method: &'static str,
     params: &'a [Box<serde_json::value::RawValue>],
 ) -> Result<String> {
+    let policy = retry_policy();
+    let timeout = Duration::from_secs(policy.timeout_secs);
+    let deadline = Instant::now() + Duration::from_secs(policy.deadline_secs);
+
+    let mut retry = 0;
+    loop {
+        let res = tokio::time::timeout_at(
+            deadline,
+            send_json_rpc_request(http_client(), Some(timeout), ctx, method, params),
+        )
+        .await
+        .with_context(|| {
+            format!(
+                "{method} didn't complete within {} seconds",
+                policy.deadline_secs
+            )
+        })?;
+
+        let transient = match &res {
+            Ok((status, body)) => is_transient_response(*status, body),
+            Err(err) => is_transient_error(method, err),
+        };
+        let backoff = policy.backoff(retry);
+        if !transient || retry >= policy.max_retries || Instant::now() + backoff >= deadline {
+            return res.map(|(_, body)| body);
+        }
+
+        warn!(
+            "- {method} failed (attempt {}), retrying in {backoff:?}",
+            retry + 1
+        );
+        tokio::time::sleep(backoff).await;
+        retry += 1;
+    }
+}
+
+/// Same as [json_rpc_request], but reuses the connections of an existing client (and doesn't retry).
+pub async fn json_rpc_request_with_client<'a>(
+    client: &Client,
+    ctx: &RpcCtx,
+    method: &'static str,
+    params: &'a [Box<serde_json::value::RawValue>],
+) -> Result<String> {
+    let (_, body) = send_json_rpc_request(client, None, ctx, method, params).await?;
+    Ok(body)
+}
+
+/// Sends a JSON RPC request, and returns the HTTP status and body of the response.
+/// The `timeout` overrides the one of the client, if any.
+async fn send_json_rpc_request<'a>(
+    client: &Client,
+    timeout: Option<Duration>,
+    ctx: &RpcCtx,
+    method: &'static str,
+    params: &'a [Box<serde_json::value::RawValue>],
+) -> Result<(StatusCode, String)> {
     // create the request
     let request = bitcoincore_rpc::jsonrpc::Request::<'a> {
         // bitcoind doesn't seem to support anything else but json rpc 1.0
@@ -115,7 +510,8 @@ pub async fn json_rpc_request<'a>(
     };
 
     let mut headers = HeaderMap::new();
-    if let Some(auth) = ctx.auth() {
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if let Some(auth) = ctx.credentials()? {
         let user_n_pw = general_purpose::STANDARD.encode(auth);
         headers.insert(
             AUTHORIZATION,
@@ -125,11 +521,6 @@ pub async fn json_rpc_request<'a>(
 
     let body = serde_json::to_string(&request)?;
 
-    let client = Client::builder()
-        .default_headers(headers)
-        .timeout(Duration::from_secs(JSON_RPC_TIMEOUT))
-        .build()?;
-
     let endpoint = ctx.address();
     let url = match &ctx.wallet {
         Some(wallet) => format!("{}/wallet/{}", endpoint, wallet),
@@ -141,15 +532,15 @@ pub async fn json_rpc_request<'a>(
         debug!("- sending request to {url} with body: {body}");
     }
 
-    let response = client
-        .post(url)
-        .header(CONTENT_TYPE, "application/json")
-        .body(body)
-        .send()
-        .await?;
+    let mut request = client.post(url).headers(headers).body(body);
+    if let Some(timeout) = timeout {
+        request = request.timeout(timeout);
+    }
+    let response = request.send().await?;
 
+    let status = response.status();
     let res = response.text().await?;
-    Ok(res)
+    Ok((status, res))
 }
 
 //
@@ -161,21 +552,44 @@ pub enum TransactionOrHex<'a> {
     Transaction(&'a Transaction),
 }
 
+/// Funds a transaction with the wallet's inputs.
+/// If no fee rate is given, the wallet picks one according to its own settings.
+/// If `add_inputs` is false, the wallet only adds a change output to the transaction
+/// (its inputs must already cover the outputs and the fee).
 pub async fn fund_raw_transaction<'a>(
     ctx: &RpcCtx,
     tx: TransactionOrHex<'a>,
+    fee_rate: Option<FeeRate>,
+    add_inputs: bool,
+    change: &Change,
 ) -> Result<(String, Transaction, Amount)> {
     let tx_hex = match tx {
         TransactionOrHex::Hex(hex) => hex,
         TransactionOrHex::Transaction(tx) => bitcoin::consensus::encode::serialize_hex(tx),
     };
 
+    // the inputs added by the wallet signal replaceability (BIP 125), so that the fee can be bumped later
+    let mut options = serde_json::json!({
+        "replaceable": true,
+        "add_inputs": add_inputs,
+    });
+    if let Some(fee_rate) = fee_rate {
+        // bitcoind expects the fee rate in sat/vB
+        options["fee_rate"] = fee_rate.to_sat_per_vb_ceil().into();
+    }
+    match change {
+        Change::Wallet => (),
+        Change::Type(change_type) => options["change_type"] = change_type.as_str().into(),
+        Change::Address(address) => options["changeAddress"] = address.to_string().into(),
+    }
+
     let response = json_rpc_request(
         ctx,
         "fundrawtransaction",
-        &[serde_json::value::to_raw_value(
-            &serde_json::Value::String(tx_hex),
-        )?],
+        &[
+            serde_json::value::to_raw_value(&serde_json::Value::String(tx_hex))?,
+            serde_json::value::to_raw_value(&options)?,
+        ],
     )
     .await
     .context("fundrawtransaction error")?;
@@ -189,6 +603,89 @@ pub async fn fund_raw_transaction<'a>(
     Ok((actual_hex, tx, parsed.fee))
 }
 
+/// Asks the node for the fee rate needed for a transaction to be confirmed within `conf_target` blocks.
+pub async fn estimate_smart_fee(ctx: &RpcCtx, conf_target: u16) -> Result<FeeRate> {
+    let response = json_rpc_request(
+        ctx,
+        "estimatesmartfee",
+        &[serde_json::value::to_raw_value(&conf_target)?],
+    )
+    .await
+    .context("estimatesmartfee error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let parsed: bitcoincore_rpc::json::EstimateSmartFeeResult = response.result()?;
+    let fee_rate = parsed.fee_rate.with_context(|| {
+        format!(
+            "the node couldn't estimate fees: {}",
+            parsed.errors.unwrap_or_default().join(", ")
+        )
+    })?;
+
+    // bitcoind returns the fee rate in BTC/kvB
+    Ok(FeeRate::from_sat_per_kwu(fee_rate.to_sat() / 4))
+}
+
+/// Picks the fee rate to fund a transaction with:
+/// the one given if any, or else the one estimated by the node to be confirmed within `conf_target` blocks.
+/// If the node can't estimate fees (e.g. there's not enough data on regtest), the wallet decides.
+pub async fn choose_fee_rate(
+    ctx: &RpcCtx,
+    fee_rate: Option<FeeRate>,
+    conf_target: u16,
+) -> Option<FeeRate> {
+    if let Some(fee_rate) = fee_rate {
+        info!(
+            "- using fee rate of {} sat/vB",
+            fee_rate.to_sat_per_vb_ceil()
+        );
+        return Some(fee_rate);
+    }
+
+    match estimate_smart_fee(ctx, conf_target).await {
+        Ok(fee_rate) => {
+            info!(
+                "- using estimated fee rate of {} sat/vB (confirmation within {conf_target} blocks)",
+                fee_rate.to_sat_per_vb_ceil()
+            );
+            Some(fee_rate)
+        }
+        Err(err) => {
+            info!("- couldn't estimate fee rate, letting the wallet decide ({err})");
+            None
+        }
+    }
+}
+
+/// Asks the wallet to replace one of its unconfirmed transactions with one paying a higher fee (BIP 125).
+/// This only works if all the inputs of the transaction belong to the wallet.
+/// If no fee rate is given, the wallet picks one.
+/// Returns the transaction ID of the replacement.
+pub async fn bump_fee(ctx: &RpcCtx, txid: Txid, fee_rate: Option<FeeRate>) -> Result<Txid> {
+    let mut params = vec![serde_json::value::to_raw_value(&txid.to_string())?];
+    if let Some(fee_rate) = fee_rate {
+        // bitcoind expects the fee rate in sat/vB
+        params.push(serde_json::value::to_raw_value(&serde_json::json!({
+            "fee_rate": fee_rate.to_sat_per_vb_ceil(),
+        }))?);
+    }
+
+    let response = json_rpc_request(ctx, "bumpfee", &params)
+        .await
+        .context("bumpfee error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let parsed: bitcoincore_rpc::json::BumpFeeResult = response.result()?;
+    info!(
+        "- bumped fee from {} to {}",
+        parsed.original_fee, parsed.fee
+    );
+
+    parsed
+        .txid
+        .with_context(|| format!("the fee wasn't bumped: {}", parsed.errors.join(", ")))
+}
+
 pub async fn sign_transaction<'a>(
     ctx: &RpcCtx,
     tx: TransactionOrHex<'a>,
@@ -239,11 +736,56 @@ pub async fn send_raw_transaction<'a>(ctx: &RpcCtx, tx: TransactionOrHex<'a>) ->
     Ok(txid)
 }
 
+/// Asks the wallet to fill in what it knows about the inputs of a PSBT (UTXOs, derivation paths),
+/// without signing it, so that it can be signed by an external signer.
+/// Returns the base64-encoded PSBT.
+pub async fn wallet_process_psbt(ctx: &RpcCtx, psbt: &Psbt) -> Result<String> {
+    let psbt = general_purpose::STANDARD.encode(psbt.serialize());
+
+    let response = json_rpc_request(
+        ctx,
+        "walletprocesspsbt",
+        &[
+            serde_json::value::to_raw_value(&serde_json::Value::String(psbt))?,
+            // don't sign
+            serde_json::value::to_raw_value(&serde_json::Value::Bool(false))?,
+        ],
+    )
+    .await
+    .context("walletprocesspsbt error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let parsed: bitcoincore_rpc::json::WalletProcessPsbtResult = response.result()?;
+
+    Ok(parsed.psbt)
+}
+
+/// Finalizes a (base64-encoded) PSBT.
+/// Returns the hex-encoded transaction (if complete) and whether it is complete.
+pub async fn finalize_psbt(ctx: &RpcCtx, psbt: &str) -> Result<(Option<String>, bool)> {
+    let response = json_rpc_request(
+        ctx,
+        "finalizepsbt",
+        &[serde_json::value::to_raw_value(
+            &serde_json::Value::String(psbt.to_string()),
+        )?],
+    )
+    .await
+    .context("finalizepsbt error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let parsed: bitcoincore_rpc::json::FinalizePsbtResult = response.result()?;
+    let tx_hex = parsed.hex.map(hex::encode);
+
+    Ok((tx_hex, parsed.complete))
+}
+
 pub async fn createrawtransaction<'a>(
     ctx: &RpcCtx,
     inputs: Vec<serde_json::Value>,
     outputs: Vec<serde_json::Value>,
     lock_time: usize,
+    replaceable: bool,
 ) -> Result<(String, Transaction)> {
     let response = json_rpc_request(
         ctx,
@@ -255,6 +797,8 @@ pub async fn createrawtransaction<'a>(
             serde_json::value::to_raw_value(&serde_json::Value::Array(outputs))?,
             // lock time
             serde_json::value::to_raw_value(&serde_json::Number::from(lock_time))?,
+            // whether the inputs signal replaceability (BIP 125)
+            serde_json::value::to_raw_value(&serde_json::Value::Bool(replaceable))?,
         ],
     )
     .await
@@ -285,6 +829,208 @@ pub async fn get_transaction<'a>(ctx: &RpcCtx, txid: Txid) -> Result<(String, Tr
     Ok((tx_hex, tx, parsed.info.confirmations as usize))
 }
 
+/// Lists the UTXOs of the wallet.
+pub async fn list_unspent(
+    ctx: &RpcCtx,
+) -> Result<Vec<bitcoincore_rpc::json::ListUnspentResultEntry>> {
+    let response = json_rpc_request(ctx, "listunspent", &[])
+        .await
+        .context("listunspent error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let utxos: Vec<bitcoincore_rpc::json::ListUnspentResultEntry> = response.result()?;
+
+    Ok(utxos)
+}
+
+/// Returns the hash of the block at the given height.
+pub async fn get_block_hash(ctx: &RpcCtx, height: u64) -> Result<BlockHash> {
+    let response = json_rpc_request(
+        ctx,
+        "getblockhash",
+        &[serde_json::value::to_raw_value(&height)?],
+    )
+    .await
+    .context("getblockhash error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let block_hash: BlockHash = response.result()?;
+
+    Ok(block_hash)
+}
+
+/// Fetches any transaction (not only the wallet's ones).
+/// Unless the node maintains a transaction index, the block containing the transaction must be given.
+pub async fn get_raw_transaction(
+    ctx: &RpcCtx,
+    txid: Txid,
+    block_hash: Option<BlockHash>,
+) -> Result<Transaction> {
+    let mut params = vec![
+        serde_json::value::to_raw_value(&txid)?,
+        serde_json::value::to_raw_value(&false)?,
+    ];
+    if let Some(block_hash) = block_hash {
+        params.push(serde_json::value::to_raw_value(&block_hash)?);
+    }
+    let response = json_rpc_request(ctx, "getrawtransaction", &params)
+        .await
+        .context("getrawtransaction error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let tx_hex: String = response.result()?;
+    let tx: Transaction = bitcoin::consensus::encode::deserialize(&hex::decode(tx_hex)?)?;
+
+    Ok(tx)
+}
+
+/// Fetches any transaction (not only the wallet's ones), along with its number of confirmations.
+/// This only works for transactions in the mempool, unless the node maintains a transaction index.
+pub async fn get_raw_transaction_info(ctx: &RpcCtx, txid: Txid) -> Result<(Transaction, usize)> {
+    let response = json_rpc_request(
+        ctx,
+        "getrawtransaction",
+        &[
+            serde_json::value::to_raw_value(&txid)?,
+            serde_json::value::to_raw_value(&true)?,
+        ],
+    )
+    .await
+    .context("getrawtransaction error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let parsed: bitcoincore_rpc::json::GetRawTransactionResult = response.result()?;
+    let tx: Transaction = bitcoin::consensus::encode::deserialize(&parsed.hex)?;
+
+    Ok((tx, parsed.confirmations.unwrap_or(0) as usize))
+}
+
+/// Fetches any transaction (not only the wallet's ones), along with the hash of the block it's confirmed in (if it is).
+/// This only works for transactions in the mempool, unless the node maintains a transaction index.
+pub async fn get_raw_transaction_block(
+    ctx: &RpcCtx,
+    txid: Txid,
+) -> Result<(Transaction, Option<BlockHash>)> {
+    let response = json_rpc_request(
+        ctx,
+        "getrawtransaction",
+        &[
+            serde_json::value::to_raw_value(&txid)?,
+            serde_json::value::to_raw_value(&true)?,
+        ],
+    )
+    .await
+    .context("getrawtransaction error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let parsed: bitcoincore_rpc::json::GetRawTransactionResult = response.result()?;
+    let tx: Transaction = bitcoin::consensus::encode::deserialize(&parsed.hex)?;
+
+    Ok((tx, parsed.blockhash))
+}
+
+/// Fetches a transaction of the wallet, along with the hash of the block it's confirmed in (if it is).
+pub async fn get_wallet_transaction_block(
+    ctx: &RpcCtx,
+    txid: Txid,
+) -> Result<(Transaction, Option<BlockHash>)> {
+    let response = json_rpc_request(
+        ctx,
+        "gettransaction",
+        &[serde_json::value::to_raw_value(&txid)?],
+    )
+    .await
+    .context("gettransaction error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let parsed: bitcoincore_rpc::json::GetTransactionResult = response.result()?;
+    let tx: Transaction = bitcoin::consensus::encode::deserialize(&parsed.hex)?;
+
+    Ok((tx, parsed.info.blockhash))
+}
+
+/// Returns the height of a block, which must be part of the best chain.
+pub async fn get_block_height(ctx: &RpcCtx, block_hash: BlockHash) -> Result<u64> {
+    let response = json_rpc_request(
+        ctx,
+        "getblockheader",
+        &[
+            serde_json::value::to_raw_value(&block_hash)?,
+            serde_json::value::to_raw_value(&true)?,
+        ],
+    )
+    .await
+    .context("getblockheader error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let header: bitcoincore_rpc::json::GetBlockHeaderResult = response.result()?;
+    // blocks that were reorged out have -1 confirmations
+    ensure!(
+        header.confirmations > 0,
+        "block {block_hash} is not part of the best chain"
+    );
+
+    Ok(header.height as u64)
+}
+
+/// Returns the height of the most-work fully-validated chain.
+pub async fn get_block_count(ctx: &RpcCtx) -> Result<u64> {
+    let response = json_rpc_request(ctx, "getblockcount", &[])
+        .await
+        .context("getblockcount error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let height: u64 = response.result()?;
+
+    Ok(height)
+}
+
+/// Fetches a block.
+pub async fn get_block(ctx: &RpcCtx, block_hash: BlockHash) -> Result<Block> {
+    let response = json_rpc_request(
+        ctx,
+        "getblock",
+        &[
+            serde_json::value::to_raw_value(&block_hash)?,
+            serde_json::value::to_raw_value(&0)?,
+        ],
+    )
+    .await
+    .context("getblock error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let block_hex: String = response.result()?;
+    let block: Block = bitcoin::consensus::encode::deserialize(&hex::decode(block_hex)?)?;
+
+    Ok(block)
+}
+
+/// Returns true if the given output is unspent (ignoring the mempool).
+pub async fn is_unspent(ctx: &RpcCtx, outpoint: OutPoint) -> Result<bool> {
+    Ok(get_tx_out_confirmations(ctx, outpoint).await?.is_some())
+}
+
+/// Returns the number of confirmations of the given output,
+/// or `None` if it's not in the UTXO set (i.e. it's spent, unconfirmed, or doesn't exist).
+pub async fn get_tx_out_confirmations(ctx: &RpcCtx, outpoint: OutPoint) -> Result<Option<u32>> {
+    let response = json_rpc_request(
+        ctx,
+        "gettxout",
+        &[
+            serde_json::value::to_raw_value(&outpoint.txid)?,
+            serde_json::value::to_raw_value(&outpoint.vout)?,
+            serde_json::value::to_raw_value(&false)?,
+        ],
+    )
+    .await
+    .context("gettxout error")?;
+
+    let response: bitcoincore_rpc::jsonrpc::Response = serde_json::from_str(&response)?;
+    let txout: Option<bitcoincore_rpc::json::GetTxOutResult> = response.result()?;
+
+    Ok(txout.map(|txout| txout.confirmations))
+}
+
 pub async fn scan_txout_set<'a>(
     ctx: &RpcCtx,
     address: &str,
@@ -322,4 +1068,137 @@ mod tests {
         .await
         .unwrap();
     }
+
+    #[test]
+    fn test_parse_proxy() {
+        assert_eq!(
+            parse_proxy("socks5://127.0.0.1:9050").unwrap().1,
+            "127.0.0.1:9050"
+        );
+        assert_eq!(
+            parse_proxy("socks5h://localhost:9050/").unwrap().1,
+            "localhost:9050"
+        );
+        assert!(parse_proxy("http://127.0.0.1:8080").is_err());
+        assert!(parse_proxy("127.0.0.1:9050").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect() {
+        // a proxy that accepts the connection and checks what it's asked to connect to
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let proxy = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            stream.read_exact(&mut greeting).await.unwrap();
+            assert_eq!(greeting, [5, 1, 0]);
+            stream.write_all(&[5, 0]).await.unwrap();
+            let mut request = vec![0u8; 5 + "example.onion".len() + 2];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(&request[..5], &[5, 1, 0, 3, 13]);
+            assert_eq!(&request[5..18], b"example.onion");
+            assert_eq!(&request[18..], &50001u16.to_be_bytes());
+            stream
+                .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])
+                .await
+                .unwrap();
+        });
+
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        socks5_connect(&mut stream, "example.onion", 50001)
+            .await
+            .unwrap();
+        proxy.await.unwrap();
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_millis(2_000));
+        assert_eq!(policy.backoff(10), Duration::from_millis(8_000));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(8_000));
+    }
+
+    #[test]
+    fn test_transient_responses() {
+        let warmup = r#"{"result":null,"error":{"code":-28,"message":"Loading block index..."},"id":"whatevs"}"#;
+        assert!(is_transient_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            warmup
+        ));
+        assert!(is_transient_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Work queue depth exceeded"
+        ));
+
+        // errors of the node itself are final
+        let invalid = r#"{"result":null,"error":{"code":-26,"message":"bad-txns-inputs-missingorspent"},"id":"whatevs"}"#;
+        assert!(!is_transient_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            invalid
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_transient_errors() {
+        // nothing listens on the discard port, so the connection is refused
+        let err: anyhow::Error = http_client()
+            .post("http://127.0.0.1:9")
+            .send()
+            .await
+            .unwrap_err()
+            .into();
+        assert!(is_transient_error("getblockcount", &err));
+        assert!(is_transient_error("unlock_funds", &err));
+
+        // a request that timed out might have been processed
+        let server = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let err: anyhow::Error = http_client()
+            .post(format!("http://{}", server.local_addr().unwrap()))
+            .timeout(Duration::from_millis(100))
+            .send()
+            .await
+            .unwrap_err()
+            .into();
+        assert!(is_transient_error("getblockcount", &err));
+        assert!(!is_transient_error("unlock_funds", &err));
+        assert!(!is_transient_error("sendrawtransaction", &err));
+        assert!(!is_transient_error(
+            "getblockcount",
+            &anyhow!("invalid response")
+        ));
+    }
+
+    #[test]
+    fn test_cookie_credentials() {
+        let tmp_dir = tempdir::TempDir::new("zkbitcoin_").unwrap();
+        let cookie_file = tmp_dir.path().join(".cookie");
+        std::fs::write(&cookie_file, "__cookie__:secret\n").unwrap();
+
+        let ctx = RpcCtx {
+            cookie_file: Some(cookie_file),
+            ..Default::default()
+        };
+        assert_eq!(
+            ctx.credentials().unwrap().as_deref(),
+            Some("__cookie__:secret")
+        );
+
+        // given credentials take precedence
+        let ctx = RpcCtx {
+            auth: Some("user:password".to_string()),
+            ..ctx
+        };
+        assert_eq!(ctx.credentials().unwrap().as_deref(), Some("user:password"));
+
+        // a missing cookie file is an error, rather than no credentials
+        let ctx = RpcCtx {
+            cookie_file: Some(tmp_dir.path().join("missing")),
+            ..Default::default()
+        };
+        assert!(ctx.credentials().is_err());
+        assert!(set_cookie_file(tmp_dir.path().join("missing")).is_err());
+    }
 }
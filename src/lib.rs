@@ -3,13 +3,96 @@
 use anyhow::Context;
 use secp256k1::hashes::Hash;
 
-pub mod committee;
+pub mod amounts;
+pub mod bindings;
+pub mod config;
 pub mod constants;
+pub mod plonk;
+pub mod recovery;
+pub mod timelock;
+pub mod zkapp_data;
+
+// everything that talks to a node, a committee, or the filesystem
+// (the rest also builds for the browser, see the `wasm` feature)
+#[cfg(feature = "node")]
+pub mod aggregation;
+#[cfg(feature = "node")]
+pub mod artifacts;
+#[cfg(feature = "node")]
+pub mod chain;
+#[cfg(feature = "node")]
+pub mod client;
+#[cfg(feature = "node")]
+pub mod coin_selection;
+#[cfg(feature = "node")]
+pub mod committee;
+#[cfg(feature = "node")]
+pub mod decode;
+#[cfg(feature = "node")]
+pub mod deploy;
+#[cfg(feature = "node")]
+pub mod devnet;
+#[cfg(feature = "node")]
+pub mod doctor;
+#[cfg(feature = "node")]
+pub mod folding;
+#[cfg(feature = "node")]
 pub mod frost;
+#[cfg(feature = "node")]
+pub mod halo2;
+#[cfg(feature = "node")]
+pub mod hwi;
+#[cfg(feature = "node")]
+pub mod indexer;
+#[cfg(feature = "node")]
+pub mod ipfs;
+#[cfg(feature = "node")]
 pub mod json_rpc_stuff;
-pub mod plonk;
+#[cfg(feature = "node")]
+pub mod lint;
+#[cfg(feature = "node")]
+pub mod noir;
+#[cfg(feature = "node")]
+pub mod nostr_transport;
+#[cfg(feature = "node")]
+pub mod proof_inputs;
+#[cfg(feature = "node")]
+pub mod proof_system;
+#[cfg(feature = "node")]
+pub mod prover;
+#[cfg(feature = "node")]
+pub mod ptau;
+#[cfg(feature = "node")]
+pub mod rbf;
+#[cfg(feature = "node")]
+pub mod reserves;
+#[cfg(feature = "node")]
+pub mod risc0;
+#[cfg(feature = "node")]
+pub mod sandbox;
+#[cfg(feature = "node")]
+pub mod scaffold;
+#[cfg(feature = "node")]
+pub mod scanner;
+#[cfg(feature = "node")]
+pub mod service;
+#[cfg(feature = "node")]
 pub mod snarkjs;
-pub mod srs;
+#[cfg(feature = "node")]
+pub mod spend;
+#[cfg(feature = "node")]
+pub mod sponsor;
+#[cfg(feature = "node")]
+pub mod templates;
+#[cfg(feature = "node")]
+pub mod upgrade;
+#[cfg(feature = "node")]
+pub mod watchtower;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 /// 1. Alice signs a transaction to deploy a smart contract.
 pub mod alice_sign_tx;
@@ -19,6 +102,7 @@ pub mod alice_sign_tx;
 pub mod bob_request;
 
 /// 3. The zkBitcoin committee produce a collaborative schnorr signature to unlock the funds for Bob.
+#[cfg(feature = "node")]
 pub mod mpc_sign_tx;
 
 //
@@ -38,15 +122,43 @@ pub fn zkbitcoin_folder() -> std::path::PathBuf {
     zkbitcoin_dir
 }
 
-/// Returns the current network (mainnet or testnet).
+/// The network in use, once set (see [get_network]).
+static NETWORK: std::sync::OnceLock<bitcoin::Network> = std::sync::OnceLock::new();
+
+/// Sets the network used by the rest of the library,
+/// for environments without environment variables (e.g. the browser).
+/// This can only be done once, before the network is first used.
+pub fn set_network(network: bitcoin::Network) -> anyhow::Result<()> {
+    NETWORK
+        .set(network)
+        .ok()
+        .context("the network was already set")
+}
+
+/// Returns the current network:
+/// the one set with [set_network], or mainnet if `MAINNET` is set, or regtest if `REGTEST` is set, or testnet otherwise.
 pub fn get_network() -> bitcoin::Network {
-    if std::env::var("MAINNET").is_ok() {
+    if let Some(network) = NETWORK.get() {
+        *network
+    } else if std::env::var("MAINNET").is_ok() {
         bitcoin::Network::Bitcoin
+    } else if std::env::var("REGTEST").is_ok() {
+        bitcoin::Network::Regtest
     } else {
         bitcoin::Network::Testnet
     }
 }
 
+/// Returns the name of the current network, as reported by Bitcoin Core (and expected by HWI).
+pub fn get_chain() -> &'static str {
+    match get_network() {
+        bitcoin::Network::Bitcoin => "main",
+        bitcoin::Network::Testnet => "test",
+        bitcoin::Network::Signet => "signet",
+        _ => "regtest",
+    }
+}
+
 /// Truncates a transaction ID so that it can fit in a field element in Circom.
 pub fn truncate_txid(txid: bitcoin::Txid) -> String {
     //    let mut bytes = vec![];
@@ -61,7 +173,14 @@ pub fn truncate_txid(txid: bitcoin::Txid) -> String {
     big.to_str_radix(10)
 }
 
-/// Creates a P2TR script from a public key.
+/// Truncates the hash of a verifier key like a transaction ID (see [truncate_txid]),
+/// so that the circuits of upgradable zkapps can take the one they are upgraded to.
+pub fn truncate_vk_hash(vk_hash: &[u8; 32]) -> String {
+    num_bigint::BigUint::from_bytes_be(&vk_hash[..30]).to_str_radix(10)
+}
+
+/// Creates a P2TR script from a public key, without a script tree
+/// (only the X coordinate of the key matters, see BIP 340).
 pub fn p2tr_script_to(zkbitcoin_pubkey: bitcoin::PublicKey) -> bitcoin::ScriptBuf {
     let secp = secp256k1::Secp256k1::default();
     let internal_key = bitcoin::key::UntweakedPublicKey::from(zkbitcoin_pubkey);
@@ -93,19 +212,6 @@ pub fn circom_field_from_bytes(bytes: &[u8]) -> anyhow::Result<String> {
     Ok(big.to_str_radix(10))
 }
 
-pub fn op_return_script_for(
-    vk_hash: &[u8; 32],
-    initial_state: Option<&String>,
-) -> anyhow::Result<bitcoin::ScriptBuf> {
-    let mut data = vk_hash.to_vec();
-    if let Some(initial_state) = initial_state {
-        data.extend(circom_field_to_bytes(initial_state).context("incorrect initial state given")?);
-        assert!(data.len() < 64);
-    }
-    let thing: &bitcoin::script::PushBytes = data.as_slice().try_into().unwrap();
-    Ok(bitcoin::ScriptBuf::new_op_return(thing))
-}
-
 pub fn taproot_addr_from(pubkey_str: &str) -> anyhow::Result<bitcoin::Address> {
     let pubkey = <bitcoin::PublicKey as std::str::FromStr>::from_str(pubkey_str)?;
     let internal_key = bitcoin::key::UntweakedPublicKey::from(pubkey);
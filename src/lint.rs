@@ -0,0 +1,566 @@
+//! Static checks of the public inputs of zkapp circuits (see `zkbtc lint-circuit`),
+//! to catch circuits that can't be deployed, or can't be used as intended, before any money is locked in them.
+//!
+//! zkBitcoin fills in the public inputs of a circuit by position (outputs first, then the inputs listed in `main`):
+//! - a stateless zkapp has a single one, the truncated txid of the transaction spending it;
+//! - a stateful zkapp has its new state (its only output), its previous state, the truncated txid,
+//!   and the amounts withdrawn and deposited (in that order);
+//! - an upgradable zkapp is a stateful zkapp that also takes the verifier key it is upgraded to, last;
+//! - an administered zkapp is an upgradable zkapp that also takes the admin key recorded on-chain with it, last.
+//!
+//! The same layout is used to check proof inputs before proving (see [check_inputs]),
+//! whether it comes from the source of a circom circuit or is declared by the user (for other circuits).
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    process::Command,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use tempdir::TempDir;
+
+use crate::constants::{
+    ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN, STATEFUL_ZKAPP_PUBLIC_INPUT_LEN,
+    STATELESS_ZKAPP_PUBLIC_INPUT_LEN, UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN,
+};
+
+/// The names of the public inputs of a stateless zkapp, by convention.
+const STATELESS_INPUTS: [&str; 1] = ["truncated_txid"];
+
+/// The names of the public inputs of a stateful zkapp (after its new state), by convention.
+const STATEFUL_INPUTS: [&str; 4] = ["prev_state", "truncated_txid", "amount_out", "amount_in"];
+
+/// The names of the public inputs of an upgradable zkapp (after its new state), by convention.
+const UPGRADABLE_INPUTS: [&str; 5] = [
+    "prev_state",
+    "truncated_txid",
+    "amount_out",
+    "amount_in",
+    "upgrade_to",
+];
+
+/// The names of the public inputs of an administered zkapp (after its new state), by convention.
+const ADMINISTERED_INPUTS: [&str; 6] = [
+    "prev_state",
+    "truncated_txid",
+    "amount_out",
+    "amount_in",
+    "upgrade_to",
+    "admin",
+];
+
+/// How bad a finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// The zkapp can't be deployed, or won't behave as intended.
+    Error,
+
+    /// The zkapp departs from the conventions, which is likely a mistake.
+    Warning,
+}
+
+/// A problem found in a circuit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// How bad the problem is.
+    pub level: Level,
+
+    /// What the problem is.
+    pub message: String,
+}
+
+impl Finding {
+    fn error(message: String) -> Self {
+        Self {
+            level: Level::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Self {
+            level: Level::Warning,
+            message,
+        }
+    }
+}
+
+/// The signals of a circuit, as declared in its source (or by the user, in JSON).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layout {
+    /// The outputs of the main template, in order.
+    pub outputs: Vec<String>,
+
+    /// The inputs made public by the main component, in order.
+    pub public_inputs: Vec<String>,
+
+    /// The other inputs of the main template.
+    pub private_inputs: Vec<String>,
+}
+
+/// Parses the public signals of a circuit from its source.
+pub fn layout(source: &str) -> Result<Layout> {
+    let source = strip_comments(source);
+
+    // component main{public [a, b]} = Main(...);
+    let main = source
+        .split(';')
+        .map(str::trim)
+        .find(|statement| statement.starts_with("component main"))
+        .context("the circuit has no main component")?;
+    let (declaration, instance) = main
+        .split_once('=')
+        .context("couldn't parse the main component")?;
+    let public_inputs = match declaration.split_once('[') {
+        Some((_, list)) => list
+            .split(']')
+            .next()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect(),
+        None => vec![],
+    };
+    let template = instance
+        .split('(')
+        .next()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .context("couldn't parse the template of the main component")?;
+
+    // the outputs declared in the body of the main template
+    let start = source
+        .match_indices("template ")
+        .map(|(idx, _)| idx + "template ".len())
+        .find(|&idx| {
+            let rest = source[idx..].trim_start();
+            rest.strip_prefix(template)
+                .is_some_and(|rest| rest.trim_start().starts_with('('))
+        })
+        .with_context(|| format!("couldn't find the template {template}"))?;
+    let body_start = start
+        + source[start..]
+            .find('{')
+            .with_context(|| format!("couldn't parse the template {template}"))?;
+    let mut depth = 0;
+    let mut body_end = None;
+    for (idx, c) in source[body_start..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    body_end = Some(body_start + idx);
+                    break;
+                }
+            }
+            _ => (),
+        }
+    }
+    let body = &source[body_start..body_end.context("unbalanced braces in the main template")?];
+
+    let mut outputs = vec![];
+    for (idx, _) in body.match_indices("signal output") {
+        let declared = body[idx + "signal output".len()..]
+            .split([';', '<', '='])
+            .next()
+            .unwrap_or_default();
+        for name in declared.split(',') {
+            // arrays are kept with their size, which is caught when comparing with the compiled circuit
+            let name = name.trim();
+            if !name.is_empty() {
+                outputs.push(name.to_string());
+            }
+        }
+    }
+
+    let mut private_inputs = vec![];
+    for (idx, _) in body.match_indices("signal input") {
+        let declared = body[idx + "signal input".len()..]
+            .split(';')
+            .next()
+            .unwrap_or_default();
+        for name in declared.split(',') {
+            // arrays are given as a list of values under their name
+            let name = name.split('[').next().unwrap_or_default().trim();
+            if !name.is_empty() && !public_inputs.iter().any(|public| public == name) {
+                private_inputs.push(name.to_string());
+            }
+        }
+    }
+
+    Ok(Layout {
+        outputs,
+        public_inputs,
+        private_inputs,
+    })
+}
+
+fn strip_comments(source: &str) -> String {
+    let mut stripped = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(idx) = rest.find('/') {
+        stripped.push_str(&rest[..idx]);
+        let comment = &rest[idx..];
+        if comment.starts_with("//") {
+            rest = comment.find('\n').map_or("", |end| &comment[end..]);
+        } else if comment.starts_with("/*") {
+            rest = comment.find("*/").map_or("", |end| &comment[end + 2..]);
+        } else {
+            stripped.push('/');
+            rest = &comment[1..];
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Checks a layout against the conventions of stateless and stateful zkapps.
+pub fn check_layout(layout: &Layout) -> Vec<Finding> {
+    let mut findings = vec![];
+    let num_public = layout.outputs.len() + layout.public_inputs.len();
+
+    let expected: &[&str] = match num_public {
+        STATELESS_ZKAPP_PUBLIC_INPUT_LEN => {
+            if let Some(output) = layout.outputs.first() {
+                findings.push(Finding::error(format!(
+                    "a stateless zkapp can't have outputs, but `{output}` is one (its only public signal must be the txid)"
+                )));
+                return findings;
+            }
+            &STATELESS_INPUTS
+        }
+        STATEFUL_ZKAPP_PUBLIC_INPUT_LEN
+        | UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN
+        | ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN => {
+            if layout.outputs.len() != 1 {
+                findings.push(Finding::error(format!(
+                    "a stateful zkapp must have exactly one output (its new state), not {}",
+                    layout.outputs.len()
+                )));
+                return findings;
+            }
+            if layout.outputs[0] != "new_state" {
+                findings.push(Finding::warning(format!(
+                    "the output `{}` receives the new state of the zkapp, it's usually called `new_state`",
+                    layout.outputs[0]
+                )));
+            }
+            match num_public {
+                STATEFUL_ZKAPP_PUBLIC_INPUT_LEN => &STATEFUL_INPUTS,
+                UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN => &UPGRADABLE_INPUTS,
+                _ => &ADMINISTERED_INPUTS,
+            }
+        }
+        0 => {
+            findings.push(Finding::error(
+                "the circuit has no public inputs, it needs at least the txid (add `{public [truncated_txid]}` to the main component)".to_string(),
+            ));
+            return findings;
+        }
+        _ => {
+            findings.push(Finding::error(format!(
+                "the circuit has {num_public} public signals, but a stateless zkapp has {STATELESS_ZKAPP_PUBLIC_INPUT_LEN} (the txid) a stateful zkapp has {STATEFUL_ZKAPP_PUBLIC_INPUT_LEN} (new state, previous state, txid, amount out, amount in), an upgradable zkapp has {UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN} (the same, then the verifier key it is upgraded to), and an administered zkapp has {ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN} (the same, then its admin key)"
+            )));
+            return findings;
+        }
+    };
+
+    for (position, (name, expected)) in layout.public_inputs.iter().zip(expected).enumerate() {
+        if name == expected {
+            continue;
+        }
+        // an input named after another one is most likely out of order
+        if let Some(filled) = expected_position(expected, name) {
+            findings.push(Finding::error(format!(
+                "the public input `{name}` is at position {position}, where zkBitcoin passes `{expected}` (it expects `{name}` at position {filled})"
+            )));
+        } else {
+            findings.push(Finding::warning(format!(
+                "the public input `{name}` receives `{expected}`, it's usually called `{expected}`"
+            )));
+        }
+    }
+
+    findings
+}
+
+/// The position of `name` among the public inputs of the kind of zkapp expecting `expected`.
+fn expected_position(expected: &str, name: &str) -> Option<usize> {
+    [
+        STATELESS_INPUTS.as_slice(),
+        STATEFUL_INPUTS.as_slice(),
+        UPGRADABLE_INPUTS.as_slice(),
+        ADMINISTERED_INPUTS.as_slice(),
+    ]
+    .into_iter()
+    .filter(|inputs| inputs.contains(&expected))
+    .find_map(|inputs| inputs.iter().position(|input| *input == name))
+}
+
+/// Checks proof inputs against the layout of a circuit, before proving it:
+/// the circuit must have the public signals of the kind of zkapp used (stateful or not), with the names zkBitcoin passes,
+/// every other input of the circuit must be given (public inputs as single values, and amounts in satoshis),
+/// and no input the circuit doesn't have can be given.
+/// The inputs zkBitcoin fills in (`prev_state`, `truncated_txid`, `upgrade_to`, and `admin`) don't need to be given.
+pub fn check_inputs(
+    layout: &Layout,
+    stateful: bool,
+    proof_inputs: &HashMap<String, Vec<String>>,
+) -> Result<()> {
+    let (expected, outputs): (&[&str], usize) = if stateful {
+        // (upgradable and administered zkapps are stateful zkapps too)
+        match layout.public_inputs.len() {
+            len if len == UPGRADABLE_INPUTS.len() => (&UPGRADABLE_INPUTS, 1),
+            len if len == ADMINISTERED_INPUTS.len() => (&ADMINISTERED_INPUTS, 1),
+            _ => (&STATEFUL_INPUTS, 1),
+        }
+    } else {
+        (&STATELESS_INPUTS, 0)
+    };
+    let kind = if stateful { "stateful" } else { "stateless" };
+    ensure!(
+        layout.outputs.len() == outputs,
+        "the zkapp is {kind}, so its circuit must have {outputs} output(s), but it has {:?}",
+        layout.outputs
+    );
+    ensure!(
+        layout.public_inputs == expected,
+        "the zkapp is {kind}, so zkBitcoin passes its circuit the public inputs {expected:?} (in that order), but it has {:?}",
+        layout.public_inputs
+    );
+
+    let filled_in = ["prev_state", "truncated_txid", "upgrade_to", "admin"];
+    let declared: HashSet<&str> = layout
+        .public_inputs
+        .iter()
+        .chain(&layout.private_inputs)
+        .map(String::as_str)
+        .collect();
+    for name in &declared {
+        ensure!(
+            filled_in.contains(name) || proof_inputs.contains_key(*name),
+            "the circuit expects the input `{name}`, which is missing from the proof inputs"
+        );
+    }
+    for name in proof_inputs.keys() {
+        ensure!(
+            declared.contains(name.as_str()),
+            "the proof inputs give `{name}`, but the circuit has no such input (its inputs are {:?})",
+            layout
+                .public_inputs
+                .iter()
+                .chain(&layout.private_inputs)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    for name in &layout.public_inputs {
+        let Some(values) = proof_inputs.get(name) else {
+            continue;
+        };
+        let [value] = values.as_slice() else {
+            bail!(
+                "the public input `{name}` must be a single value, not {}",
+                values.len()
+            );
+        };
+        if name.starts_with("amount_") {
+            value.parse::<u64>().with_context(|| {
+                format!("the public input `{name}` must be an amount in satoshis, not {value:?}")
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Compiles a circuit (without setting up its keys), and returns its number of public outputs and public inputs.
+pub fn compiled_public_signals(circom_circuit_path: &Path) -> Result<(usize, usize)> {
+    let tmp_dir = TempDir::new("zkbitcoin_lint").context("couldn't create tmp dir")?;
+    let output = Command::new("circom")
+        .current_dir(tmp_dir.path())
+        .arg(circom_circuit_path)
+        .arg("--r1cs")
+        .output()
+        .context("couldn't execute circom (is it installed?)")?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        bail!(
+            "couldn't compile the circuit: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    // circom prints e.g. `public inputs: 4` and `public outputs: 1`
+    let count = |label: &str| -> Result<usize> {
+        stdout
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(label))
+            .with_context(|| format!("circom didn't report its {label}"))?
+            .trim_start_matches(':')
+            .trim()
+            .parse()
+            .with_context(|| format!("couldn't parse the {label} reported by circom"))
+    };
+    Ok((count("public outputs")?, count("public inputs")?))
+}
+
+/// Lints the circuit at `circom_circuit_path`: checks its source against the conventions of zkapps,
+/// and compiles it to make sure that the public signals are what the source says.
+pub fn lint(circom_circuit_path: &Path) -> Result<Vec<Finding>> {
+    let source = std::fs::read_to_string(circom_circuit_path)
+        .with_context(|| format!("couldn't read {}", circom_circuit_path.display()))?;
+    let layout = layout(&source)?;
+    let mut findings = check_layout(&layout);
+
+    let (outputs, public_inputs) = compiled_public_signals(circom_circuit_path)?;
+    if (outputs, public_inputs) != (layout.outputs.len(), layout.public_inputs.len()) {
+        findings.push(Finding::error(format!(
+            "the compiled circuit has {outputs} public outputs and {public_inputs} public inputs, but its main template declares {} and {} (public signals must be single field elements, not arrays)",
+            layout.outputs.len(),
+            layout.public_inputs.len()
+        )));
+    }
+
+    Ok(findings)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::ValueEnum;
+
+    use crate::templates::Template;
+
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        let stateful = layout(include_str!("../examples/circuit/stateful.circom")).unwrap();
+        assert_eq!(stateful.outputs, vec!["new_state"]);
+        assert_eq!(stateful.public_inputs, STATEFUL_INPUTS);
+        assert!(check_layout(&stateful).is_empty());
+
+        let stateless = layout(include_str!("../examples/circuit/stateless.circom")).unwrap();
+        assert!(stateless.outputs.is_empty());
+        assert!(check_layout(&stateless).is_empty());
+
+        // and so do the templates shipped with zkbtc
+        for template in Template::value_variants() {
+            let params = template
+                .params()
+                .iter()
+                .map(|param| (param.to_string(), "1".to_string()))
+                .collect();
+            let circuit = template.instantiate(&params).unwrap();
+            assert!(check_layout(&layout(&circuit).unwrap()).is_empty());
+        }
+
+        // comments and outputs of other templates are ignored
+        let source = "
+            template Other() { signal output unrelated; }
+            template Main() {
+                // signal output commented;
+                signal input txid;
+            }
+            component main{public [txid]} = Main();
+        ";
+        let parsed = layout(source).unwrap();
+        assert!(parsed.outputs.is_empty());
+        assert_eq!(parsed.public_inputs, vec!["txid"]);
+        assert!(parsed.private_inputs.is_empty());
+
+        // private inputs are the other inputs of the main template
+        let source = "
+            template Main() {
+                signal input truncated_txid;
+                signal input secret, path[4];
+            }
+            component main{public [truncated_txid]} = Main();
+        ";
+        assert_eq!(
+            layout(source).unwrap().private_inputs,
+            vec!["secret", "path"]
+        );
+    }
+
+    #[test]
+    fn test_check_inputs() {
+        let stateful = layout(include_str!("../examples/circuit/stateful.circom")).unwrap();
+        let inputs = |inputs: &[(&str, &str)]| -> HashMap<String, Vec<String>> {
+            inputs
+                .iter()
+                .map(|(name, value)| (name.to_string(), vec![value.to_string()]))
+                .collect()
+        };
+        let mut given = inputs(&[("amount_out", "1000"), ("amount_in", "0")]);
+        for private in &stateful.private_inputs {
+            given.insert(private.clone(), vec!["1".to_string()]);
+        }
+        check_inputs(&stateful, true, &given).unwrap();
+
+        // the layout must be the one of the zkapp
+        assert!(check_inputs(&stateful, false, &given).is_err());
+
+        // the inputs must be the ones of the circuit
+        let mut missing = given.clone();
+        missing.remove("amount_in");
+        assert!(check_inputs(&stateful, true, &missing).is_err());
+        let mut unknown = given.clone();
+        unknown.insert("amount".to_string(), vec!["1".to_string()]);
+        assert!(check_inputs(&stateful, true, &unknown).is_err());
+
+        // and amounts must be amounts
+        let mut not_an_amount = given;
+        not_an_amount.insert("amount_out".to_string(), vec!["-1".to_string()]);
+        assert!(check_inputs(&stateful, true, &not_an_amount).is_err());
+    }
+
+    #[test]
+    fn test_check_layout() {
+        let stateful = |outputs: &[&str], inputs: &[&str]| Layout {
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+            public_inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            private_inputs: vec![],
+        };
+
+        // renamed inputs are fine, but out of order ones are not
+        let renamed = stateful(
+            &["state"],
+            &["prev_state", "txid", "amount_out", "amount_in"],
+        );
+        let findings = check_layout(&renamed);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(|f| f.level == Level::Warning));
+
+        let swapped = stateful(
+            &["new_state"],
+            &["prev_state", "truncated_txid", "amount_in", "amount_out"],
+        );
+        let findings = check_layout(&swapped);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(|f| f.level == Level::Error));
+
+        // a state pair needs its output
+        let no_output = stateful(&[], &["prev_state", "txid", "amount_out", "amount_in", "x"]);
+        assert_eq!(check_layout(&no_output)[0].level, Level::Error);
+
+        // administered zkapps take their admin key last
+        let administered = stateful(&["new_state"], &ADMINISTERED_INPUTS);
+        assert!(check_layout(&administered).is_empty());
+        let mut misplaced = ADMINISTERED_INPUTS;
+        misplaced.swap(4, 5);
+        let findings = check_layout(&stateful(&["new_state"], &misplaced));
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(|f| f.level == Level::Error));
+
+        let wrong_count = stateful(&[], &["truncated_txid", "secret"]);
+        assert_eq!(check_layout(&wrong_count)[0].level, Level::Error);
+        assert_eq!(check_layout(&Layout::default())[0].level, Level::Error);
+    }
+}
@@ -5,18 +5,17 @@ use bitcoin::{
 };
 use secp256k1::hashes::Hash;
 
-use crate::bob_request::SmartContract;
+use crate::{bob_request::SmartContract, recovery::Recovery};
 
-/// Gets the digest to hash for signing a transaction containing a zkapp.
+/// Gets the digest to hash for signing a transaction containing a zkapp
+/// (with `SIGHASH_ALL`, or `SIGHASH_ALL|ANYONECANPAY` for sponsored transactions, see [crate::bob_request::BobRequest::sighash_type]).
+/// Recoverable zkapps are spent with the committee's script rather than its key (see [crate::recovery]).
 pub fn get_digest_to_hash(
     prev_outs: &[TxOut],
     transaction: &bitcoin::Transaction,
     smart_contract: &SmartContract,
+    hash_ty: TapSighashType,
 ) -> Result<[u8; 32]> {
-    // TODO: return Result
-    // the sighash flag is always ALL
-    let hash_ty = TapSighashType::All;
-
     // sighash
     let mut cache = SighashCache::new(transaction);
     let mut sig_msg = Vec::new();
@@ -40,15 +39,19 @@ pub fn get_digest_to_hash(
 
     // get hash
     let prev_outs = Prevouts::All(prev_outs);
+    let leaf_hash = match smart_contract.recovery {
+        Some(_) => Some((Recovery::committee_leaf_hash()?, 0xFFFFFFFF)),
+        None => None,
+    };
     cache.taproot_encode_signing_data_to(
         &mut sig_msg,
         input_idx,
         &prev_outs,
         None,
-        None,
+        leaf_hash,
         hash_ty,
     )?;
-    let sighash = cache.taproot_signature_hash(input_idx, &prev_outs, None, None, hash_ty)?;
+    let sighash = cache.taproot_signature_hash(input_idx, &prev_outs, None, leaf_hash, hash_ty)?;
     Ok(sighash.to_byte_array())
 }
 
@@ -70,16 +73,17 @@ mod tests {
     use secp256k1::{hashes::Hash, All, Secp256k1, XOnlyPublicKey};
 
     use crate::frost::{gen_frost_keys, sign_transaction_frost, to_xonly_pubkey};
+    use crate::json_rpc_stuff::RpcCtx;
     use crate::taproot_addr_from;
     use crate::{
         bob_request::SmartContract,
-        constants::{FEE_ZKBITCOIN_SAT, ZKBITCOIN_FEE_PUBKEY},
+        coin_selection::Change,
+        config::protocol_config,
         json_rpc_stuff::{
             fund_raw_transaction, send_raw_transaction, sign_transaction, TransactionOrHex,
         },
         p2tr_script_to,
     };
-    use crate::{constants::ZKBITCOIN_PUBKEY, json_rpc_stuff::RpcCtx};
 
     /*
     - privkey: b2f7f581d6de3c06a822fd6e7e8265fbc00f8401696a5bdc34f5a6d2ff3f922f
@@ -155,15 +159,16 @@ mod tests {
 
         // second output is to zkBitcoin fund
         {
-            let zkbitcoin_pubkey: PublicKey = PublicKey::from_str(ZKBITCOIN_FEE_PUBKEY).unwrap();
+            let zkbitcoin_pubkey: PublicKey =
+                PublicKey::from_str(&protocol_config().zkbitcoin_fee_pubkey).unwrap();
             outputs.push(TxOut {
-                value: Amount::from_sat(FEE_ZKBITCOIN_SAT),
+                value: protocol_config().fee(),
                 script_pubkey: p2tr_script_to(zkbitcoin_pubkey),
             });
         }
 
         // we need to subtract the amount to cover for the fee
-        let amount_for_bob = smart_contract.locked_value - Amount::from_sat(FEE_ZKBITCOIN_SAT);
+        let amount_for_bob = smart_contract.locked_value - protocol_config().fee();
 
         {
             // first output is a P2TR to Bob
@@ -174,9 +179,10 @@ mod tests {
 
             // second output is to zkBitcoin
             // TODO: obviously we shouldn't send it to this address no? This is controlled by an MPC instead of by us
-            let zkbitcoin_pubkey: PublicKey = PublicKey::from_str(ZKBITCOIN_PUBKEY).unwrap();
+            let zkbitcoin_pubkey: PublicKey =
+                PublicKey::from_str(&protocol_config().zkbitcoin_pubkey).unwrap();
             outputs.push(TxOut {
-                value: Amount::from_sat(FEE_ZKBITCOIN_SAT),
+                value: protocol_config().fee(),
                 script_pubkey: p2tr_script_to(zkbitcoin_pubkey),
             });
         }
@@ -209,9 +215,15 @@ mod tests {
         };
 
         // fund that transaction with our wallet
-        let (tx_hex, _, _fee) = fund_raw_transaction(&ctx, TransactionOrHex::Transaction(&tx))
-            .await
-            .unwrap();
+        let (tx_hex, _, _fee) = fund_raw_transaction(
+            &ctx,
+            TransactionOrHex::Transaction(&tx),
+            None,
+            true,
+            &Change::Wallet,
+        )
+        .await
+        .unwrap();
 
         // sign that transaction with our wallet
         let (tx_hex, _) = sign_transaction(&ctx, TransactionOrHex::Hex(tx_hex))
@@ -294,13 +306,18 @@ mod tests {
         // Spend this fucker now
         let satoshi_amount = Amount::from_sat(amount);
 
-        let bob_address = taproot_addr_from(ZKBITCOIN_PUBKEY).unwrap();
+        let bob_address = taproot_addr_from(&protocol_config().zkbitcoin_pubkey).unwrap();
 
         let smart_contract = SmartContract {
             txid,
             locked_value: satoshi_amount,
             vk_hash: [0; 32],
             state: None,
+            metadata_hash: None,
+            admin: None,
+            timelock: None,
+            recovery: None,
+            deployer: None,
             vout_of_zkbitcoin_utxo: 0,
         };
         let mut tx = create_transaction(&smart_contract, txid, bob_address);
@@ -336,13 +353,18 @@ mod tests {
                 .unwrap();
         let satoshi_amount = Amount::from_sat(1000);
 
-        let bob_address = taproot_addr_from(ZKBITCOIN_PUBKEY).unwrap();
+        let bob_address = taproot_addr_from(&protocol_config().zkbitcoin_pubkey).unwrap();
 
         let smart_contract = SmartContract {
             txid,
             locked_value: satoshi_amount,
             vk_hash: [0; 32],
             state: None,
+            metadata_hash: None,
+            admin: None,
+            timelock: None,
+            recovery: None,
+            deployer: None,
             vout_of_zkbitcoin_utxo: 0,
         };
         let mut tx = create_transaction(&smart_contract, txid, bob_address);
@@ -0,0 +1,540 @@
+//! Noir programs as zkapps, as an alternative to circom circuits.
+//!
+//! A Noir zkapp is a Nargo package (a directory with a `Nargo.toml`, see [is_noir]).
+//! It's compiled with `nargo`, and proven and verified with Barretenberg (`bb`) using UltraHonk.
+//! Its public inputs follow the conventions of circom zkapps:
+//! values returned by the program come first (like the outputs of circom circuits), followed by its `pub` parameters.
+//! So a stateful zkapp looks like
+//! `fn main(prev_state: pub Field, truncated_txid: pub Field, amount_out: pub Field, amount_in: pub Field, ...) -> pub Field`,
+//! returning its new state.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::info;
+use num_bigint::BigUint;
+use serde::Deserialize;
+use tempdir::TempDir;
+
+use crate::{
+    artifacts::copy_dir,
+    plonk::{self, NoirProof, NoirVerifierKey, ProofSystemKind},
+};
+
+/// The manifest of a Nargo package.
+const MANIFEST_FILENAME: &str = "Nargo.toml";
+
+/// The name of the file the inputs of a proof are written to (without its `.toml` extension).
+const PROVER_NAME: &str = "zkbitcoin_inputs";
+
+//
+// Packages
+//
+
+/// Returns true if `path` is a Noir program: a Nargo package, its manifest, or one of its `.nr` files.
+pub fn is_noir(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "nr")
+        || path
+            .file_name()
+            .is_some_and(|name| name == MANIFEST_FILENAME)
+        || path.join(MANIFEST_FILENAME).exists()
+}
+
+/// Returns the directory of the Nargo package `path` is part of.
+pub fn package_dir(path: &Path) -> Result<PathBuf> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("couldn't find {}", path.display()))?;
+    path.ancestors()
+        .find(|dir| dir.join(MANIFEST_FILENAME).is_file())
+        .map(Path::to_path_buf)
+        .with_context(|| format!("{} is not part of a Nargo package", path.display()))
+}
+
+/// Returns the name of a Nargo package (which names its compiled program).
+fn package_name(package_dir: &Path) -> Result<String> {
+    #[derive(Deserialize)]
+    struct Manifest {
+        package: Package,
+    }
+    #[derive(Deserialize)]
+    struct Package {
+        name: String,
+    }
+
+    let path = package_dir.join(MANIFEST_FILENAME);
+    let manifest = std::fs::read_to_string(&path)
+        .with_context(|| format!("couldn't read {}", path.display()))?;
+    let manifest: Manifest =
+        toml::from_str(&manifest).with_context(|| format!("couldn't parse {}", path.display()))?;
+    Ok(manifest.package.name)
+}
+
+//
+// ABI
+//
+
+/// The parts of the ABI of a compiled program (`target/<name>.json`) we need.
+#[derive(Debug, Deserialize)]
+struct Program {
+    abi: Abi,
+}
+
+#[derive(Debug, Deserialize)]
+struct Abi {
+    parameters: Vec<AbiParameter>,
+    return_type: Option<AbiReturnType>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AbiParameter {
+    name: String,
+    #[serde(rename = "type")]
+    typ: AbiType,
+    visibility: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AbiReturnType {
+    abi_type: AbiType,
+    visibility: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum AbiType {
+    Field,
+    Boolean,
+    // (the fields of a variant, e.g. the width of integers, are ignored unless listed)
+    Integer {},
+    Array {
+        length: usize,
+        #[serde(rename = "type")]
+        typ: Box<AbiType>,
+    },
+    String {
+        length: usize,
+    },
+    Struct {
+        fields: Vec<AbiField>,
+    },
+    Tuple {
+        fields: Vec<AbiType>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct AbiField {
+    #[serde(rename = "type")]
+    typ: AbiType,
+}
+
+impl AbiType {
+    /// The number of field elements a value of this type is made of.
+    fn size(&self) -> usize {
+        match self {
+            AbiType::Field | AbiType::Boolean | AbiType::Integer {} => 1,
+            AbiType::Array { length, typ } => length * typ.size(),
+            AbiType::String { length } => *length,
+            AbiType::Struct { fields } => fields.iter().map(|field| field.typ.size()).sum(),
+            AbiType::Tuple { fields } => fields.iter().map(AbiType::size).sum(),
+        }
+    }
+}
+
+impl Abi {
+    /// The number of public inputs of the program: its `pub` parameters, and what it returns.
+    fn num_public(&self) -> usize {
+        let params: usize = self
+            .parameters
+            .iter()
+            .filter(|param| param.visibility == "public")
+            .map(|param| param.typ.size())
+            .sum();
+        params + self.num_returned()
+    }
+
+    /// The number of public inputs returned by the program.
+    fn num_returned(&self) -> usize {
+        self.return_type
+            .as_ref()
+            .filter(|ret| ret.visibility == "public")
+            .map_or(0, |ret| ret.abi_type.size())
+    }
+
+    /// Writes proof inputs in the format of `Prover.toml`.
+    /// Inputs are lists of field elements (like the inputs of circom circuits),
+    /// which are written as is for array parameters, and as their single element otherwise.
+    fn prover_toml(&self, proof_inputs: &HashMap<String, Vec<String>>) -> Result<String> {
+        let mut table = toml::Table::new();
+        for param in &self.parameters {
+            let Some(values) = proof_inputs.get(&param.name) else {
+                continue;
+            };
+            let value = match &param.typ {
+                AbiType::Array { typ, .. } if matches!(**typ, AbiType::Array { .. }) => {
+                    bail!("the parameter `{}` can't be given (nested arrays are not supported)", param.name)
+                }
+                AbiType::Array { .. } => toml::Value::Array(
+                    values.iter().cloned().map(toml::Value::String).collect(),
+                ),
+                AbiType::Field | AbiType::Boolean | AbiType::Integer {} => {
+                    let [value] = values.as_slice() else {
+                        bail!("the parameter `{}` expects a single value", param.name);
+                    };
+                    if matches!(param.typ, AbiType::Boolean) {
+                        toml::Value::Boolean(value == "1" || value == "true")
+                    } else {
+                        toml::Value::String(value.clone())
+                    }
+                }
+                _ => bail!(
+                    "the parameter `{}` can't be given (only fields, booleans, integers, and arrays of them are supported)",
+                    param.name
+                ),
+            };
+            table.insert(param.name.clone(), value);
+        }
+        Ok(toml::to_string(&table)?)
+    }
+}
+
+//
+// Compiling
+//
+
+/// A Noir program compiled in a temporary directory.
+struct Compiled {
+    /// The copy of the package, where the program is compiled.
+    package_dir: PathBuf,
+
+    /// The name of the package.
+    name: String,
+
+    /// The compiled program.
+    program_path: PathBuf,
+
+    /// The ABI of the program.
+    abi: Abi,
+}
+
+/// Copies the package of a Noir program to `tmp_dir` (so that nothing is written to it), and compiles it there.
+fn compile(tmp_dir: &TempDir, path: &Path) -> Result<Compiled> {
+    let source_dir = package_dir(path)?;
+    let name = package_name(&source_dir)?;
+    let package_dir = tmp_dir.path().join(&name);
+    copy_dir(&source_dir, &package_dir)?;
+
+    // nargo compile
+    let output = Command::new("nargo")
+        .current_dir(&package_dir)
+        .arg("compile")
+        .output()
+        .context("couldn't execute nargo")?;
+
+    info!("{}", String::from_utf8_lossy(&output.stdout));
+
+    if !output.status.success() {
+        info!("{}", String::from_utf8_lossy(&output.stderr));
+        bail!("couldn't compile noir program");
+    }
+
+    let program_path = package_dir.join("target").join(format!("{name}.json"));
+    let program = std::fs::read_to_string(&program_path)
+        .with_context(|| format!("couldn't read {}", program_path.display()))?;
+    let Program { abi } = serde_json::from_str(&program)
+        .with_context(|| format!("couldn't parse {}", program_path.display()))?;
+
+    Ok(Compiled {
+        package_dir,
+        name,
+        program_path,
+        abi,
+    })
+}
+
+/// Runs a Barretenberg command (with UltraHonk) in `dir`, with the paths it takes as `(flag, path)`.
+fn bb(dir: &Path, command: &str, paths: &[(&str, &Path)], error: &str) -> Result<()> {
+    let mut bb = Command::new("bb");
+    bb.current_dir(dir)
+        .arg(command)
+        .arg("--scheme")
+        .arg(ProofSystemKind::UltraHonk.name());
+    for (flag, path) in paths {
+        bb.arg(flag).arg(path);
+    }
+    let output = bb.output().context("couldn't execute bb")?;
+
+    info!("{}", String::from_utf8_lossy(&output.stdout));
+
+    if !output.status.success() {
+        info!("{}", String::from_utf8_lossy(&output.stderr));
+        bail!("{error}");
+    }
+    Ok(())
+}
+
+/// Compiles a Noir program, and returns its verifier key.
+pub fn verifier_key(path: &Path) -> Result<plonk::VerifierKey> {
+    let tmp_dir = TempDir::new("zkbitcoin_").context("couldn't create tmp dir")?;
+    let compiled = compile(&tmp_dir, path)?;
+    write_verifier_key(&compiled)
+}
+
+/// Writes the verifier key of a compiled program (with `bb write_vk`).
+fn write_verifier_key(compiled: &Compiled) -> Result<plonk::VerifierKey> {
+    let target_dir = compiled.package_dir.join("target");
+    bb(
+        &compiled.package_dir,
+        "write_vk",
+        &[("-b", &compiled.program_path), ("-o", &target_dir)],
+        "couldn't write verifier key",
+    )?;
+    let vk_path = target_dir.join("vk");
+    let vk =
+        std::fs::read(&vk_path).with_context(|| format!("couldn't read {}", vk_path.display()))?;
+
+    Ok(plonk::VerifierKey::Noir(NoirVerifierKey {
+        protocol: ProofSystemKind::UltraHonk.name().to_string(),
+        nPublic: compiled.abi.num_public(),
+        nReturn: compiled.abi.num_returned(),
+        vk: hex::encode(vk),
+    }))
+}
+
+//
+// Proving
+//
+
+/// Proves a Noir program on `proof_inputs` (see [Abi::prover_toml] for their format),
+/// and returns the proof, the public inputs (in the order of circom zkapps), and the verifier key.
+pub fn prove(
+    path: &Path,
+    proof_inputs: &HashMap<String, Vec<String>>,
+) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
+    let tmp_dir = TempDir::new("zkbitcoin_").context("couldn't create tmp dir")?;
+    let compiled = compile(&tmp_dir, path)?;
+    let vk = write_verifier_key(&compiled)?;
+
+    // write inputs to file
+    let inputs_path = compiled.package_dir.join(format!("{PROVER_NAME}.toml"));
+    std::fs::write(&inputs_path, compiled.abi.prover_toml(proof_inputs)?)
+        .with_context(|| format!("couldn't write {}", inputs_path.display()))?;
+
+    // create witness using nargo
+    {
+        // nargo execute --prover-name zkbitcoin_inputs witness
+        let output = Command::new("nargo")
+            .current_dir(&compiled.package_dir)
+            .arg("execute")
+            .arg("--prover-name")
+            .arg(PROVER_NAME)
+            .arg("witness")
+            .output()
+            .context("couldn't execute nargo")?;
+
+        info!("{}", String::from_utf8_lossy(&output.stdout));
+
+        if !output.status.success() {
+            info!("{}", String::from_utf8_lossy(&output.stderr));
+            bail!("couldn't create witness");
+        }
+    }
+
+    // create proof using bb
+    let target_dir = compiled.package_dir.join("target");
+    bb(
+        &compiled.package_dir,
+        "prove",
+        &[
+            ("-b", &compiled.program_path),
+            ("-w", &target_dir.join("witness.gz")),
+            ("-o", &target_dir),
+        ],
+        "couldn't create proof",
+    )?;
+    info!("- proved {}", compiled.name);
+
+    let proof_path = target_dir.join("proof");
+    let proof = std::fs::read(&proof_path)
+        .with_context(|| format!("couldn't read {}", proof_path.display()))?;
+    let public_inputs_path = target_dir.join("public_inputs");
+    let public_inputs = std::fs::read(&public_inputs_path)
+        .with_context(|| format!("couldn't read {}", public_inputs_path.display()))?;
+    let mut public_inputs = decode_fields(&public_inputs)?;
+
+    // bb lists the returned values last
+    ensure!(
+        public_inputs.len() == compiled.abi.num_public(),
+        "bb returned {} public inputs, but the program has {}",
+        public_inputs.len(),
+        compiled.abi.num_public()
+    );
+    public_inputs.rotate_right(compiled.abi.num_returned());
+
+    let proof = plonk::Proof::Noir(NoirProof {
+        protocol: ProofSystemKind::UltraHonk.name().to_string(),
+        proof: hex::encode(proof),
+    });
+    Ok((proof, plonk::PublicInputs(public_inputs), vk))
+}
+
+/// Verifies the proof of a Noir program, on public inputs in the order of circom zkapps.
+pub fn verify_proof(
+    vk: &NoirVerifierKey,
+    public_inputs: &[String],
+    proof: &NoirProof,
+) -> Result<()> {
+    ensure!(
+        public_inputs.len() == vk.nPublic && vk.nReturn <= vk.nPublic,
+        "expected {} public inputs, got {}",
+        vk.nPublic,
+        public_inputs.len()
+    );
+
+    // bb lists the returned values last
+    let mut public_inputs = public_inputs.to_vec();
+    public_inputs.rotate_left(vk.nReturn);
+
+    // write vk, inputs, proof to file
+    let tmp_dir = TempDir::new("zkbitcoin_").context("couldn't create tmp dir")?;
+    let vk_path = tmp_dir.path().join("vk");
+    let proof_path = tmp_dir.path().join("proof");
+    let public_inputs_path = tmp_dir.path().join("public_inputs");
+    std::fs::write(
+        &vk_path,
+        hex::decode(&vk.vk).context("malformed verifier key")?,
+    )?;
+    std::fs::write(
+        &proof_path,
+        hex::decode(&proof.proof).context("malformed proof")?,
+    )?;
+    std::fs::write(&public_inputs_path, encode_fields(&public_inputs)?)?;
+
+    // verify proof using bb
+    bb(
+        tmp_dir.path(),
+        "verify",
+        &[
+            ("-k", &vk_path),
+            ("-p", &proof_path),
+            ("-i", &public_inputs_path),
+        ],
+        "failed to verify proof",
+    )
+}
+
+//
+// Field elements
+//
+
+/// Decodes the field elements written by bb (32 bytes each, big-endian) to decimal.
+fn decode_fields(bytes: &[u8]) -> Result<Vec<String>> {
+    ensure!(bytes.len() % 32 == 0, "malformed field elements");
+    Ok(bytes
+        .chunks(32)
+        .map(|chunk| BigUint::from_bytes_be(chunk).to_str_radix(10))
+        .collect())
+}
+
+/// Encodes field elements written in decimal the way bb reads them (32 bytes each, big-endian).
+fn encode_fields(fields: &[String]) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(32 * fields.len());
+    for field in fields {
+        let field = BigUint::parse_bytes(field.as_bytes(), 10)
+            .with_context(|| format!("`{field}` is not a field element written in decimal"))?
+            .to_bytes_be();
+        ensure!(
+            field.len() <= 32,
+            "a public input doesn't fit in a field element"
+        );
+        bytes.extend(std::iter::repeat(0).take(32 - field.len()));
+        bytes.extend(field);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The ABI of `fn main(prev_state: pub Field, truncated_txid: pub Field, amount_out: pub Field, amount_in: pub Field, secret: [Field; 2]) -> pub Field`.
+    fn stateful_abi() -> Abi {
+        let field = r#"{"kind":"field"}"#;
+        let param = |name: &str, typ: &str, visibility: &str| {
+            format!(r#"{{"name":"{name}","type":{typ},"visibility":"{visibility}"}}"#)
+        };
+        let params = ["prev_state", "truncated_txid", "amount_out", "amount_in"]
+            .iter()
+            .map(|name| param(name, field, "public"))
+            .chain([param(
+                "secret",
+                r#"{"kind":"array","length":2,"type":{"kind":"field"}}"#,
+                "private",
+            )])
+            .collect::<Vec<_>>()
+            .join(",");
+        let program = format!(
+            r#"{{"noir_version":"1.0.0","abi":{{"parameters":[{params}],"return_type":{{"abi_type":{field},"visibility":"public"}},"error_types":{{}}}},"bytecode":""}}"#
+        );
+        serde_json::from_str::<Program>(&program).unwrap().abi
+    }
+
+    #[test]
+    fn test_abi() {
+        let abi = stateful_abi();
+        assert_eq!(abi.num_public(), 5);
+        assert_eq!(abi.num_returned(), 1);
+
+        let mut proof_inputs = HashMap::new();
+        proof_inputs.insert("prev_state".to_string(), vec!["1".to_string()]);
+        proof_inputs.insert("secret".to_string(), vec!["2".to_string(), "3".to_string()]);
+        let toml = abi.prover_toml(&proof_inputs).unwrap();
+        assert!(toml.contains(r#"prev_state = "1""#));
+        assert!(toml.contains(r#"secret = ["2", "3"]"#));
+
+        proof_inputs.insert(
+            "amount_in".to_string(),
+            vec!["1".to_string(), "2".to_string()],
+        );
+        assert!(abi.prover_toml(&proof_inputs).is_err());
+    }
+
+    #[test]
+    fn test_fields() {
+        let fields = vec!["0".to_string(), "258".to_string()];
+        let bytes = encode_fields(&fields).unwrap();
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(&bytes[62..], &[1, 2]);
+        assert_eq!(decode_fields(&bytes).unwrap(), fields);
+
+        assert!(encode_fields(&["0x1".to_string()]).is_err());
+        assert!(encode_fields(&["1".repeat(78)]).is_err());
+        assert!(decode_fields(&[0; 31]).is_err());
+    }
+
+    #[test]
+    fn test_is_noir() {
+        let tmp_dir = TempDir::new("zkbitcoin_noir").unwrap();
+        std::fs::create_dir(tmp_dir.path().join("src")).unwrap();
+        std::fs::write(
+            tmp_dir.path().join(MANIFEST_FILENAME),
+            "[package]\nname = \"zkapp\"\ntype = \"bin\"\n",
+        )
+        .unwrap();
+        let main = tmp_dir.path().join("src").join("main.nr");
+        std::fs::write(&main, "fn main() {}").unwrap();
+
+        assert!(is_noir(tmp_dir.path()));
+        assert!(is_noir(&main));
+        assert!(!is_noir(Path::new("examples/circuit/stateless.circom")));
+
+        let package = package_dir(&main).unwrap();
+        assert_eq!(package, tmp_dir.path().canonicalize().unwrap());
+        assert_eq!(package_name(&package).unwrap(), "zkapp");
+    }
+}
@@ -0,0 +1,332 @@
+//! Submitting Bob requests over Nostr.
+//!
+//! Users that can't reach the orchestrator's URL (e.g. behind a restrictive network)
+//! can send their requests as encrypted direct messages (NIP-04) to the orchestrator's Nostr public key, through public relays.
+//! The orchestrator's bridge decrypts them, forwards them to the orchestrator's JSON RPC API,
+//! and answers with an encrypted direct message to the one-time key the request came from.
+//! Note that relays limit the size of events (often to 64-128 KB).
+//!
+//! As anyone can send direct messages to the bridge, it answers a bounded number of requests at once
+//! (see [MAX_PENDING_REQUESTS]), dropping the ones coming meanwhile, and only remembers the latest events it saw
+//! (see [MAX_SEEN_EVENTS]) to skip the copies coming from other relays.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use log::{debug, error, info, warn};
+use nostr_sdk::{
+    nips::{
+        nip04,
+        nip19::{FromBech32, ToBech32},
+    },
+    secp256k1::{SecretKey, XOnlyPublicKey},
+    Client, Event, EventId, Filter, Keys, Kind, RelayOptions, RelayPoolNotification, Timestamp,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast::error::RecvError, Semaphore};
+
+use crate::{
+    bob_request::{send_bob_batch_request, send_bob_request, BobRequest, BobResponse},
+    json_rpc_stuff::proxy_address,
+};
+
+//
+// Constants
+//
+
+/// The relays used when none are given.
+pub const DEFAULT_RELAYS: &[&str] = &[
+    "wss://relay.damus.io",
+    "wss://nos.lol",
+    "wss://relay.nostr.band",
+];
+
+/// How long (in seconds) Bob waits for the orchestrator to answer.
+const RESPONSE_TIMEOUT: u64 = 180;
+
+/// The most requests the bridge answers at once (the ones coming meanwhile are dropped).
+pub const MAX_PENDING_REQUESTS: usize = 32;
+
+/// How many events the bridge remembers, to skip the copies of an event coming from other relays.
+pub const MAX_SEEN_EVENTS: usize = 10_000;
+
+//
+// Messages
+//
+
+/// A request sent to the orchestrator over Nostr.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "method", content = "params", rename_all = "snake_case")]
+pub enum NostrRequest {
+    /// See `unlock_funds`.
+    UnlockFunds(BobRequest),
+
+    /// See `unlock_funds_batch`.
+    UnlockFundsBatch(Vec<BobRequest>),
+}
+
+/// The orchestrator's answer to a [NostrRequest].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NostrResponse {
+    Ok(BobResponse),
+    Err(String),
+}
+
+//
+// Keys and relays
+//
+
+/// Parses a Nostr public key, as an `npub` or in hex.
+pub fn parse_public_key(key: &str) -> Result<XOnlyPublicKey> {
+    XOnlyPublicKey::from_bech32(key)
+        .ok()
+        .or_else(|| XOnlyPublicKey::from_str(key).ok())
+        .context("invalid Nostr public key (expected an npub or hex)")
+}
+
+/// Parses a Nostr secret key, as an `nsec` or in hex.
+pub fn parse_secret_key(key: &str) -> Result<Keys> {
+    let secret_key = SecretKey::from_bech32(key)
+        .ok()
+        .or_else(|| SecretKey::from_str(key).ok())
+        .context("invalid Nostr secret key (expected an nsec or hex)")?;
+    Ok(Keys::new(secret_key))
+}
+
+/// Returns the given relays, or the default ones if none are given.
+pub fn relays_or_default(relays: &[String]) -> Vec<String> {
+    if relays.is_empty() {
+        DEFAULT_RELAYS.iter().map(ToString::to_string).collect()
+    } else {
+        relays.to_vec()
+    }
+}
+
+/// Connects to the relays (through the proxy, if one is set), and subscribes to the direct messages sent to `keys`.
+async fn connect(keys: &Keys, relays: &[String], filter: Filter) -> Result<Client> {
+    let proxy = match proxy_address() {
+        Some(address) => Some(
+            tokio::net::lookup_host(address)
+                .await?
+                .next()
+                .with_context(|| format!("couldn't resolve the proxy {address}"))?,
+        ),
+        None => None,
+    };
+    let client = Client::new(keys);
+    for relay in relays {
+        client
+            .add_relay_with_opts(relay.as_str(), RelayOptions::new().proxy(proxy))
+            .await
+            .with_context(|| format!("invalid relay {relay}"))?;
+    }
+    client.connect().await;
+    client.subscribe(vec![filter]).await;
+    Ok(client)
+}
+
+//
+// Bob
+//
+
+/// Sends a request to the orchestrator with the given public key, and waits for its answer.
+pub async fn send_request(
+    orchestrator: XOnlyPublicKey,
+    relays: &[String],
+    request: &NostrRequest,
+) -> Result<BobResponse> {
+    // a one-time key, so that requests can't be linked together
+    let keys = Keys::generate();
+    let filter = Filter::new()
+        .author(orchestrator)
+        .pubkey(keys.public_key())
+        .kind(Kind::EncryptedDirectMessage)
+        .since(Timestamp::now());
+    let client = connect(&keys, relays, filter).await?;
+    let mut notifications = client.notifications();
+
+    client
+        .send_direct_msg(orchestrator, serde_json::to_string(request)?, None)
+        .await
+        .context("couldn't send the request to the relays")?;
+    info!("- request sent to the orchestrator over Nostr, waiting for its answer");
+
+    let answer = async {
+        loop {
+            let RelayPoolNotification::Event { event, .. } = notifications.recv().await? else {
+                continue;
+            };
+            if event.kind != Kind::EncryptedDirectMessage || event.pubkey != orchestrator {
+                continue;
+            }
+            match nip04::decrypt(&keys.secret_key()?, &event.pubkey, &event.content) {
+                Ok(answer) => return anyhow::Ok(answer),
+                Err(err) => warn!("- couldn't decrypt a message from the orchestrator: {err}"),
+            }
+        }
+    };
+    let answer = tokio::time::timeout(Duration::from_secs(RESPONSE_TIMEOUT), answer)
+        .await
+        .context("the orchestrator didn't answer over Nostr in time")??;
+    let _ = client.disconnect().await;
+
+    match serde_json::from_str(&answer).context("couldn't parse the orchestrator's answer")? {
+        NostrResponse::Ok(bob_response) => Ok(bob_response),
+        NostrResponse::Err(err) => bail!("bob request failed: {err}"),
+    }
+}
+
+//
+// Orchestrator
+//
+
+/// Answers the requests sent to `keys` over Nostr, by forwarding them to the orchestrator's JSON RPC API at `address`.
+pub async fn serve(keys: Keys, relays: &[String], address: String) -> Result<()> {
+    let filter = Filter::new()
+        .pubkey(keys.public_key())
+        .kind(Kind::EncryptedDirectMessage)
+        .since(Timestamp::now());
+    let client = connect(&keys, relays, filter).await?;
+    let mut notifications = client.notifications();
+    info!(
+        "- accepting requests over Nostr for {} on {} relays",
+        keys.public_key().to_bech32()?,
+        relays.len()
+    );
+
+    // the same event comes from every relay it was sent to
+    let mut seen = SeenEvents::new(MAX_SEEN_EVENTS);
+    let pending = Arc::new(Semaphore::new(MAX_PENDING_REQUESTS));
+    loop {
+        let notification = match notifications.recv().await {
+            Ok(notification) => notification,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("- missed {missed} Nostr notifications");
+                continue;
+            }
+            Err(RecvError::Closed) => bail!("the Nostr client stopped"),
+        };
+        let RelayPoolNotification::Event { event, .. } = notification else {
+            continue;
+        };
+        if event.kind != Kind::EncryptedDirectMessage || !seen.insert(event.id) {
+            continue;
+        }
+        let Ok(permit) = pending.clone().try_acquire_owned() else {
+            warn!(
+                "- too many pending requests, dropping Nostr request {}",
+                event.id
+            );
+            continue;
+        };
+
+        let (client, keys, address) = (client.clone(), keys.clone(), address.clone());
+        tokio::spawn(async move {
+            if let Err(err) = answer(&client, &keys, &address, &event).await {
+                error!("couldn't answer Nostr request {}: {err:#}", event.id);
+            }
+            drop(permit);
+        });
+    }
+}
+
+/// The latest events seen, the oldest ones being forgotten first.
+struct SeenEvents {
+    max_events: usize,
+    ids: HashSet<EventId>,
+    order: VecDeque<EventId>,
+}
+
+impl SeenEvents {
+    fn new(max_events: usize) -> Self {
+        Self {
+            max_events: max_events.max(1),
+            ids: HashSet::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Records an event, and returns whether it wasn't seen (lately) already.
+    fn insert(&mut self, id: EventId) -> bool {
+        if !self.ids.insert(id) {
+            return false;
+        }
+        self.order.push_back(id);
+        if self.order.len() > self.max_events {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Forwards a request received over Nostr to the orchestrator, and sends back its answer.
+async fn answer(client: &Client, keys: &Keys, address: &str, event: &Event) -> Result<()> {
+    let content = nip04::decrypt(&keys.secret_key()?, &event.pubkey, &event.content)?;
+    let request: NostrRequest =
+        serde_json::from_str(&content).context("couldn't parse the request")?;
+    debug!("- received request {} over Nostr", event.id);
+
+    let result = match request {
+        NostrRequest::UnlockFunds(bob_request) => send_bob_request(address, bob_request).await,
+        NostrRequest::UnlockFundsBatch(bob_requests) => {
+            send_bob_batch_request(address, bob_requests).await
+        }
+    };
+    let response = match result {
+        Ok(bob_response) => NostrResponse::Ok(bob_response),
+        Err(err) => NostrResponse::Err(format!("{err:#}")),
+    };
+
+    client
+        .send_direct_msg(
+            event.pubkey,
+            serde_json::to_string(&response)?,
+            Some(event.id),
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        let keys = Keys::generate();
+        let npub = keys.public_key().to_bech32().unwrap();
+        let hex = keys.public_key().to_string();
+        assert_eq!(parse_public_key(&npub).unwrap(), keys.public_key());
+        assert_eq!(parse_public_key(&hex).unwrap(), keys.public_key());
+        assert!(parse_public_key("npub1nope").is_err());
+
+        let nsec = keys.secret_key().unwrap().to_bech32().unwrap();
+        assert_eq!(
+            parse_secret_key(&nsec).unwrap().public_key(),
+            keys.public_key()
+        );
+    }
+
+    #[test]
+    fn test_seen_events() {
+        let mut seen = SeenEvents::new(2);
+        let [first, second, third] = [1, 2, 3].map(|id| EventId::from_slice(&[id; 32]).unwrap());
+        assert!(seen.insert(first));
+        assert!(!seen.insert(first));
+
+        // the oldest events are forgotten
+        assert!(seen.insert(second));
+        assert!(seen.insert(third));
+        assert!(!seen.insert(third));
+        assert!(seen.insert(first));
+        assert_eq!(seen.ids.len(), 2);
+    }
+}
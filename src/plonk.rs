@@ -1,14 +1,136 @@
+//! The snarkjs formats of verifier keys and proofs, for the proof systems a zkapp can be deployed with.
+//!
+//! The proof system of a zkapp is part of its verifier key (its `protocol`),
+//! so it is committed to on-chain by the hash of the verifier key.
+
 #![allow(non_snake_case)]
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use sha3::{Digest, Keccak256};
 
-use crate::bob_request::Update;
+use crate::{bob_request::Update, constants::STATEFUL_ZKAPP_PUBLIC_INPUT_LEN};
+
+/// The proof systems a zkapp can be deployed with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "node", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum ProofSystemKind {
+    /// PLONK, set up with the universal powers of tau (no ceremony per circuit).
+    #[default]
+    Plonk,
+
+    /// Groth16, with smaller proofs that are faster to verify,
+    /// but set up for each circuit (whose prover key must then be shared with its users).
+    Groth16,
+
+    /// UltraHonk, the proof system of Noir programs (which can't be chosen for circom circuits).
+    #[cfg_attr(feature = "node", value(skip))]
+    #[serde(rename = "ultra_honk")]
+    UltraHonk,
+
+    /// Halo2 (with IPA commitments, so without any setup), for the circuits built into zkBitcoin (see the `halo2` module).
+    #[cfg_attr(feature = "node", value(skip))]
+    Halo2,
+
+    /// The RISC Zero zkVM, proving the execution of programs (see the `risc0` module).
+    #[cfg_attr(feature = "node", value(skip))]
+    #[serde(rename = "risc0")]
+    RiscZero,
+
+    /// Nova, folding many state transitions of a circom step circuit into a single proof (see the `folding` module).
+    #[cfg_attr(feature = "node", value(skip))]
+    Nova,
+}
+
+impl ProofSystemKind {
+    /// The name of the proof system in snarkjs (both in its commands and in its `protocol` fields).
+    pub fn name(self) -> &'static str {
+        match self {
+            ProofSystemKind::Plonk => "plonk",
+            ProofSystemKind::Groth16 => "groth16",
+            ProofSystemKind::UltraHonk => "ultra_honk",
+            ProofSystemKind::Halo2 => "halo2",
+            ProofSystemKind::RiscZero => "risc0",
+            ProofSystemKind::Nova => "nova",
+        }
+    }
+}
+
+/// A snarkjs verifier key, of any of the supported proof systems.
+/// (The serialization, and thus the hash, of a verifier key is the one of the inner key,
+/// except for zkVM programs whose image ID is the hash.)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum VerifierKey {
+    Plonk(PlonkVerifierKey),
+    Groth16(Groth16VerifierKey),
+    Noir(NoirVerifierKey),
+    Halo2(Halo2VerifierKey),
+    RiscZero(RiscZeroVerifierKey),
+    Nova(NovaVerifierKey),
+}
+
+impl VerifierKey {
+    /// hashes a verifier key.
+    pub fn hash(&self) -> [u8; 32] {
+        // zkVM programs are committed to by their image ID
+        if let Some(image_id) = match self {
+            VerifierKey::RiscZero(vk) => vk.image_id(),
+            _ => None,
+        } {
+            return image_id;
+        }
+
+        let mut hasher = Keccak256::new();
+        // TODO: find a better way :D
+        hasher.update(serde_json::to_string(&self).unwrap());
+        let hash = hasher.finalize().to_vec();
+        hash.try_into().unwrap()
+    }
+
+    /// The number of public inputs (including outputs) of the circuit,
+    /// unless it's not known ahead of time (for zkVM programs, which commit to their public inputs in their journal).
+    pub fn n_public(&self) -> Option<usize> {
+        match self {
+            VerifierKey::Plonk(vk) => Some(vk.nPublic),
+            VerifierKey::Groth16(vk) => Some(vk.nPublic),
+            VerifierKey::Noir(vk) => Some(vk.nPublic),
+            VerifierKey::Halo2(vk) => Some(vk.nPublic),
+            VerifierKey::RiscZero(_) => None,
+            VerifierKey::Nova(_) => Some(STATEFUL_ZKAPP_PUBLIC_INPUT_LEN),
+        }
+    }
+
+    /// The proof system of the verifier key.
+    pub fn proof_system(&self) -> ProofSystemKind {
+        match self {
+            VerifierKey::Plonk(_) => ProofSystemKind::Plonk,
+            VerifierKey::Groth16(_) => ProofSystemKind::Groth16,
+            VerifierKey::Noir(_) => ProofSystemKind::UltraHonk,
+            VerifierKey::Halo2(_) => ProofSystemKind::Halo2,
+            VerifierKey::RiscZero(_) => ProofSystemKind::RiscZero,
+            VerifierKey::Nova(_) => ProofSystemKind::Nova,
+        }
+    }
+
+    /// Returns true if the verifier key claims (in its `protocol`) to be of the proof system it was parsed as.
+    pub fn is_consistent(&self) -> bool {
+        let protocol = match self {
+            VerifierKey::Plonk(vk) => &vk.protocol,
+            VerifierKey::Groth16(vk) => &vk.protocol,
+            VerifierKey::Noir(vk) => &vk.protocol,
+            VerifierKey::Halo2(vk) => &vk.protocol,
+            VerifierKey::RiscZero(vk) => &vk.protocol,
+            VerifierKey::Nova(vk) => &vk.protocol,
+        };
+        protocol == self.proof_system().name()
+    }
+}
 
 /// The snarkjs plonk verifier key format.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct VerifierKey {
+pub struct PlonkVerifierKey {
     protocol: String,   // "plonk",
     curve: String,      // "bn128",
     pub nPublic: usize, // 96,
@@ -27,20 +149,107 @@ pub struct VerifierKey {
     w: String, //"6837567842312086091520287814181175430087169027974246751610506942214842701774"
 }
 
-impl VerifierKey {
-    /// hashes a verifier key.
+/// The snarkjs groth16 verifier key format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Groth16VerifierKey {
+    protocol: String, // "groth16",
+    curve: String,    // "bn128",
+    pub nPublic: usize,
+    pub(crate) vk_alpha_1: Vec<String>,
+    pub(crate) vk_beta_2: Vec<Vec<String>>,
+    pub(crate) vk_gamma_2: Vec<Vec<String>>,
+    pub(crate) vk_delta_2: Vec<Vec<String>>,
+    vk_alphabeta_12: Vec<Vec<Vec<String>>>,
+    pub(crate) IC: Vec<Vec<String>>,
+}
+
+/// The verifier key of a Noir program (see the `noir` module).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoirVerifierKey {
+    pub protocol: String, // "ultra_honk",
+    /// The number of public inputs, including the values returned by the program.
+    pub nPublic: usize,
+    /// The number of public inputs returned by the program (which come first, like the outputs of circom circuits).
+    pub nReturn: usize,
+    /// The verifier key written by `bb write_vk`, hex-encoded.
+    pub vk: String,
+}
+
+/// The verifier key of a halo2 circuit (see the `halo2` module).
+/// Halo2 verifier keys can only be recreated from their circuit, so they are committed to by
+/// the name of the circuit (built into zkBitcoin), the size of the circuit, and a hash of the key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Halo2VerifierKey {
+    pub protocol: String, // "halo2",
+    pub circuit: String,
+    pub k: u32,
+    pub nPublic: usize,
+    /// The hash of the (pinned) verifier key, hex-encoded.
+    pub pinned_hash: String,
+}
+
+/// The "verifier key" of a RISC Zero program (see the `risc0` module): its image ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiscZeroVerifierKey {
+    pub protocol: String, // "risc0",
+    /// The image ID of the program, hex-encoded.
+    pub image_id: String,
+}
+
+impl RiscZeroVerifierKey {
+    /// The image ID of the program, if well-formed.
+    pub fn image_id(&self) -> Option<[u8; 32]> {
+        hex::decode(&self.image_id).ok()?.try_into().ok()
+    }
+}
+
+/// The verifier key of the step circuit of a folded zkapp (see the `folding` module).
+/// Folded zkapps are always stateful, so their public inputs are the ones of stateful zkapps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NovaVerifierKey {
+    pub protocol: String, // "nova",
+    /// The verifier key of the compressed SNARK (serialized with bincode), hex-encoded.
+    pub vk: String,
+}
+
+/// A snarkjs proof, of any of the supported proof systems.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Proof {
+    Plonk(PlonkProof),
+    Groth16(Groth16Proof),
+    Noir(NoirProof),
+    Halo2(Halo2Proof),
+    RiscZero(RiscZeroProof),
+    Nova(NovaProof),
+}
+
+impl Proof {
+    /// Hashes a proof.
     pub fn hash(&self) -> [u8; 32] {
         let mut hasher = Keccak256::new();
-        // TODO: find a better way :D
+        // TODO: find a better way :D ?
         hasher.update(serde_json::to_string(&self).unwrap());
         let hash = hasher.finalize().to_vec();
         hash.try_into().unwrap()
     }
+
+    /// The proof system of the proof.
+    pub fn proof_system(&self) -> ProofSystemKind {
+        match self {
+            Proof::Plonk(_) => ProofSystemKind::Plonk,
+            Proof::Groth16(_) => ProofSystemKind::Groth16,
+            Proof::Noir(_) => ProofSystemKind::UltraHonk,
+            Proof::Halo2(_) => ProofSystemKind::Halo2,
+            Proof::RiscZero(_) => ProofSystemKind::RiscZero,
+            Proof::Nova(_) => ProofSystemKind::Nova,
+        }
+    }
 }
 
 /// A snarkjs plonk proof.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Proof {
+pub struct PlonkProof {
     A: Vec<String>,
     B: Vec<String>,
     C: Vec<String>,
@@ -60,15 +269,48 @@ pub struct Proof {
     curve: String,    //"bn128"
 }
 
-impl Proof {
-    /// Hashes a proof.
-    pub fn hash(&self) -> [u8; 32] {
-        let mut hasher = Keccak256::new();
-        // TODO: find a better way :D ?
-        hasher.update(serde_json::to_string(&self).unwrap());
-        let hash = hasher.finalize().to_vec();
-        hash.try_into().unwrap()
-    }
+/// A snarkjs groth16 proof.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Groth16Proof {
+    pub(crate) pi_a: Vec<String>,
+    pub(crate) pi_b: Vec<Vec<String>>,
+    pub(crate) pi_c: Vec<String>,
+    protocol: String, // "groth16",
+    curve: String,    // "bn128"
+}
+
+/// A proof of a Noir program.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoirProof {
+    pub protocol: String, // "ultra_honk",
+    /// The proof written by `bb prove` (without its public inputs), hex-encoded.
+    pub proof: String,
+}
+
+/// A proof of a halo2 circuit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Halo2Proof {
+    pub protocol: String, // "halo2",
+    /// The transcript of the proof, hex-encoded.
+    pub transcript: String,
+}
+
+/// A receipt of the execution of a RISC Zero program.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RiscZeroProof {
+    pub protocol: String, // "risc0",
+    /// The receipt (serialized with bincode), hex-encoded.
+    pub receipt: String,
+}
+
+/// A proof folding the state transitions of a folded zkapp.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NovaProof {
+    pub protocol: String, // "nova",
+    /// The number of state transitions folded.
+    pub num_steps: usize,
+    /// The compressed SNARK (serialized with bincode), hex-encoded.
+    pub snark: String,
 }
 
 /// The public input that has to be used by the verifier
@@ -102,6 +344,17 @@ impl PublicInputs {
         self.0[4].clone()
     }
 
+    /// The verifier key the zkapp is upgraded to, for upgradable zkapps (see [Update::upgrade_to]).
+    pub fn upgrade_to(&self) -> Option<String> {
+        self.0.get(5).cloned()
+    }
+
+    /// The admin key of administered zkapps (see [crate::zkapp_data::ZkappData::admin]),
+    /// which isn't part of the [Update] as it is read from the chain.
+    pub fn admin(&self) -> Option<String> {
+        self.0.get(6).cloned()
+    }
+
     /// Recover the [Update] responsible for the given the public inputs.
     pub fn to_update(&self) -> Update {
         Update {
@@ -110,6 +363,7 @@ impl PublicInputs {
             truncated_txid: None, // doesn't get serialized
             amount_out: self.amount_out(),
             amount_in: self.amount_in(),
+            upgrade_to: self.upgrade_to(),
         }
     }
 
@@ -120,6 +374,7 @@ impl PublicInputs {
         public_inputs.push(truncated_txid);
         public_inputs.push(update.amount_out.clone());
         public_inputs.push(update.amount_in.clone());
+        public_inputs.extend(update.upgrade_to.clone());
 
         Ok(Self(public_inputs))
     }
@@ -0,0 +1,141 @@
+//! The proof inputs given to `zkbtc` (inline with `--proof-inputs`, or in a file with `--proof-inputs-path`):
+//! a JSON object mapping each input of the circuit to its list of values,
+//! which are field elements given as decimal strings (or integers), e.g. `{"amount_out": ["1000"]}`.
+//!
+//! The inputs are checked before proving, so that a mistake points at the input it's in
+//! instead of failing somewhere in the witness generation.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde_json::Value;
+
+use crate::folding::STEPS_INPUT;
+
+/// The private input the circuits of administered zkapps take the secret of their administrator as, by convention
+/// (see [crate::zkapp_data::ZkappData::admin]).
+pub const ADMIN_SECRET_INPUT: &str = "admin_secret";
+
+/// Parses proof inputs from a JSON string.
+pub fn parse(json: &str) -> Result<HashMap<String, Vec<String>>> {
+    let value: Value = serde_json::from_str(json).context("the proof inputs are not valid JSON")?;
+    from_value(value)
+}
+
+/// Reads proof inputs from a JSON file.
+pub fn read(path: &Path) -> Result<HashMap<String, Vec<String>>> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("couldn't read {}", path.display()))?;
+    parse(&json).with_context(|| format!("invalid proof inputs in {}", path.display()))
+}
+
+/// Checks that `value` maps each input to a list of field elements, and returns it.
+pub fn from_value(value: Value) -> Result<HashMap<String, Vec<String>>> {
+    let Value::Object(inputs) = value else {
+        bail!(
+            "the proof inputs must be a JSON object mapping each input to its values, not {}",
+            describe(&value)
+        );
+    };
+    inputs
+        .into_iter()
+        .map(|(name, values)| {
+            let values = field_elements(&name, values)?;
+            Ok((name, values))
+        })
+        .collect()
+}
+
+/// Authorizes a privileged operation of an administered zkapp (given with `--admin-secret`),
+/// by giving its circuit the secret of the administrator (the admin key it is checked against is filled in from the chain).
+pub fn insert_admin_secret(inputs: &mut HashMap<String, Vec<String>>, secret: &str) -> Result<()> {
+    ensure!(
+        is_decimal(secret),
+        "the admin secret must be a decimal number"
+    );
+    ensure!(
+        !inputs.contains_key(ADMIN_SECRET_INPUT),
+        "the proof inputs already give `{ADMIN_SECRET_INPUT}`"
+    );
+    inputs.insert(ADMIN_SECRET_INPUT.to_string(), vec![secret.to_string()]);
+    Ok(())
+}
+
+fn field_elements(name: &str, values: Value) -> Result<Vec<String>> {
+    let Value::Array(values) = values else {
+        bail!(
+            "the proof input `{name}` must be a list of values (e.g. [\"1000\"]), not {}",
+            describe(&values)
+        );
+    };
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| match value {
+            // the steps of folded zkapps are JSON objects themselves
+            Value::String(s) if name == STEPS_INPUT || is_decimal(&s) => Ok(s),
+            Value::Number(n) if n.is_u64() || n.is_i64() => Ok(n.to_string()),
+            Value::String(s) => {
+                bail!("the proof input `{name}[{i}]` must be a decimal number, not {s:?}")
+            }
+            value => bail!(
+                "the proof input `{name}[{i}]` must be a decimal number, not {}",
+                describe(&value)
+            ),
+        })
+        .collect()
+}
+
+fn is_decimal(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn describe(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => format!("the boolean {b}"),
+        Value::Number(n) => format!("the number {n}"),
+        Value::String(s) => format!("the string {s:?}"),
+        Value::Array(_) => "a list".to_string(),
+        Value::Object(_) => "an object".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let inputs =
+            parse(r#"{"amount_out": ["1000"], "amount_in": [0], "secret": ["-1"]}"#).unwrap();
+        assert_eq!(inputs["amount_out"], vec!["1000"]);
+        assert_eq!(inputs["amount_in"], vec!["0"]);
+        assert_eq!(inputs["secret"], vec!["-1"]);
+
+        // errors point at the offending input
+        let err = |json: &str| format!("{:#}", parse(json).unwrap_err());
+        assert!(err(r#"["1000"]"#).contains("must be a JSON object"));
+        assert!(err(r#"{"amount_out": "1000"}"#).contains("`amount_out` must be a list"));
+        assert!(err(r#"{"amount_out": ["1000", "1e3"]}"#).contains("`amount_out[1]`"));
+        assert!(err(r#"{"amount_out": [1.5]}"#).contains("`amount_out[0]`"));
+        assert!(err(r#"{"amount_out": ["1000"],}"#).contains("not valid JSON"));
+
+        // but the steps of folded zkapps are JSON
+        let steps = parse(r#"{"steps": ["{\"amount_out\": [\"10\"]}"]}"#).unwrap();
+        assert_eq!(steps[STEPS_INPUT].len(), 1);
+    }
+
+    #[test]
+    fn test_insert_admin_secret() {
+        let mut inputs = parse(r#"{"paused": ["1"]}"#).unwrap();
+        insert_admin_secret(&mut inputs, "42").unwrap();
+        assert_eq!(inputs[ADMIN_SECRET_INPUT], vec!["42"]);
+
+        // it's a field element, given once
+        assert!(insert_admin_secret(&mut inputs, "42").is_err());
+        let mut inputs = HashMap::new();
+        assert!(insert_admin_secret(&mut inputs, "0x2a").is_err());
+    }
+}
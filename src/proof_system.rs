@@ -0,0 +1,506 @@
+//! The proof systems zkapps can be written for, behind a common interface ([ProofSystem]).
+//!
+//! Circuits are given by path, and the proof system of a circuit is picked from it:
+//! circom circuits go through snarkjs (with plonk, or groth16 if they have a groth16 prover key),
+//! except for step circuits which are folded with Nova (see [folding]),
+//! Nargo packages through nargo and bb (see [noir]), `halo2:<name>` through the halo2 circuits built in (see [halo2]),
+//! and `risc0:<path>` through the RISC Zero zkVM (see [risc0]).
+//! Proofs are verified with the proof system their verifier key is for.
+//! The halo2, RISC Zero, and Nova backends each have their own feature (`halo2`, `risc0`, and `nova`):
+//! without it, their proofs can't be made nor verified.
+//!
+//! Several proofs of a circuit can be made at once (see [prove_all]),
+//! and the native proof systems (halo2 and Nova) prove on the threads of the global rayon pool (see [set_threads]).
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use async_trait::async_trait;
+use log::info;
+use tempdir::TempDir;
+use tokio::{runtime::Handle, sync::Semaphore};
+
+use crate::{
+    folding, halo2, noir,
+    plonk::{self, ProofSystemKind},
+    risc0,
+    snarkjs::{self, CompilationResult},
+};
+
+/// A proof system, and the circuits it proves.
+#[async_trait]
+pub trait ProofSystem: Send + Sync {
+    /// Which proof system it is.
+    fn kind(&self) -> ProofSystemKind;
+
+    /// Compiles a circuit (if needed), and returns its verifier key (whose hash is what a zkapp commits to).
+    async fn verifier_key(&self, circuit_path: &Path) -> Result<plonk::VerifierKey>;
+
+    /// Proves a circuit on `proof_inputs`, and returns the proof, the full public inputs, and the verifier key.
+    async fn prove(
+        &self,
+        circuit_path: &Path,
+        proof_inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)>;
+
+    /// Verifies a proof (whose verifier key and proof were checked to be of [Self::kind]).
+    fn verify(
+        &self,
+        vk: &plonk::VerifierKey,
+        public_inputs: &[String],
+        proof: &plonk::Proof,
+    ) -> Result<()>;
+}
+
+//
+// Picking a proof system
+//
+
+/// Resolves the path of a circuit given relative to `dir` (e.g. the working directory):
+/// paths are joined to `dir`, the ELF of a RISC Zero program is (keeping its `risc0:` prefix),
+/// and halo2 circuits are referred to by name.
+pub fn resolve(dir: &Path, circuit_path: &Path) -> PathBuf {
+    if halo2::circuit_name(circuit_path).is_some() {
+        return circuit_path.to_path_buf();
+    }
+    match risc0::elf_path(circuit_path) {
+        Some(elf_path) => {
+            let mut resolved = std::ffi::OsString::from(risc0::PATH_PREFIX);
+            resolved.push(dir.join(elf_path));
+            PathBuf::from(resolved)
+        }
+        None => dir.join(circuit_path),
+    }
+}
+
+/// Returns the proof system of a circuit:
+/// `kind` for circom circuits (which can be set up with plonk or groth16), and the only one possible otherwise.
+pub fn for_circuit(circuit_path: &Path, kind: ProofSystemKind) -> Result<Box<dyn ProofSystem>> {
+    let implied = if noir::is_noir(circuit_path) {
+        Some(ProofSystemKind::UltraHonk)
+    } else if halo2::circuit_name(circuit_path).is_some() {
+        Some(ProofSystemKind::Halo2)
+    } else if risc0::elf_path(circuit_path).is_some() {
+        Some(ProofSystemKind::RiscZero)
+    } else if folding::is_step_circuit(circuit_path) {
+        Some(ProofSystemKind::Nova)
+    } else {
+        None
+    };
+    match implied {
+        Some(implied) => {
+            ensure!(
+                kind == ProofSystemKind::default() || kind == implied,
+                "the proof system of {} can't be chosen (it's proven with {})",
+                circuit_path.display(),
+                implied.name()
+            );
+            Ok(of(implied))
+        }
+        None => {
+            ensure!(
+                matches!(kind, ProofSystemKind::Plonk | ProofSystemKind::Groth16),
+                "circom circuits can't be proven with {}",
+                kind.name()
+            );
+            Ok(of(kind))
+        }
+    }
+}
+
+/// Returns the implementation of a proof system.
+pub fn of(kind: ProofSystemKind) -> Box<dyn ProofSystem> {
+    match kind {
+        ProofSystemKind::Plonk | ProofSystemKind::Groth16 => Box::new(Snarkjs(kind)),
+        ProofSystemKind::UltraHonk => Box::new(Noir),
+        #[cfg(feature = "halo2")]
+        ProofSystemKind::Halo2 => Box::new(Halo2),
+        #[cfg(feature = "risc0")]
+        ProofSystemKind::RiscZero => Box::new(RiscZero),
+        #[cfg(feature = "nova")]
+        ProofSystemKind::Nova => Box::new(Nova),
+        #[allow(unreachable_patterns)]
+        kind => Box::new(Disabled(kind)),
+    }
+}
+
+/// Proves a circuit with its proof system
+/// (for circom circuits, the one they're set up with, see [snarkjs::proof_system_of]).
+pub async fn prove(
+    circuit_path: &Path,
+    proof_inputs: &HashMap<String, Vec<String>>,
+) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
+    for_circuit(circuit_path, snarkjs::proof_system_of(circuit_path))?
+        .prove(circuit_path, proof_inputs)
+        .await
+}
+
+/// Proves a circuit on each of `proof_inputs`, with up to `jobs` proofs made at once,
+/// and returns the proofs in the order of their inputs.
+/// The circuit is compiled once (or its artifacts looked up) before proving,
+/// and each proof (and its witness) is then made on its own thread.
+pub async fn prove_all(
+    circuit_path: &Path,
+    proof_inputs: Vec<HashMap<String, Vec<String>>>,
+    jobs: usize,
+) -> Result<Vec<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)>> {
+    for_circuit(circuit_path, snarkjs::proof_system_of(circuit_path))?
+        .verifier_key(circuit_path)
+        .await?;
+
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut tasks = Vec::with_capacity(proof_inputs.len());
+    for inputs in proof_inputs {
+        let permit = semaphore.clone().acquire_owned().await?;
+        let circuit_path = circuit_path.to_path_buf();
+        let handle = Handle::current();
+        tasks.push(tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            handle.block_on(prove(&circuit_path, &inputs))
+        }));
+    }
+
+    let mut proofs = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        proofs.push(task.await.context("a proving thread panicked")??);
+    }
+    Ok(proofs)
+}
+
+/// Sets the number of threads the native proof systems prove with (all the CPUs by default).
+/// This must be called before anything is proven.
+pub fn set_threads(threads: usize) -> Result<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build_global()
+        .context("the proving threads are already set up")?;
+    info!("- proving with {threads} threads");
+    Ok(())
+}
+
+/// Verifies a proof with the proof system of its verifier key.
+pub fn verify_proof(
+    vk: &plonk::VerifierKey,
+    public_inputs: &[String],
+    proof: &plonk::Proof,
+) -> Result<()> {
+    ensure!(vk.is_consistent(), "the verifier key is malformed");
+    ensure!(
+        proof.proof_system() == vk.proof_system(),
+        "the proof is a {} proof, but the verifier key is a {} one",
+        proof.proof_system().name(),
+        vk.proof_system().name()
+    );
+    of(vk.proof_system()).verify(vk, public_inputs, proof)
+}
+
+//
+// Implementations
+//
+
+/// Circom circuits, with snarkjs.
+struct Snarkjs(ProofSystemKind);
+
+#[async_trait]
+impl ProofSystem for Snarkjs {
+    fn kind(&self) -> ProofSystemKind {
+        self.0
+    }
+
+    async fn verifier_key(&self, circuit_path: &Path) -> Result<plonk::VerifierKey> {
+        let tmp_dir = TempDir::new("zkbitcoin_").context("couldn't create tmp dir")?;
+        let CompilationResult { verifier_key, .. } =
+            snarkjs::compile(&tmp_dir, circuit_path, self.0).await?;
+        Ok(verifier_key)
+    }
+
+    async fn prove(
+        &self,
+        circuit_path: &Path,
+        proof_inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
+        snarkjs::prove(circuit_path, self.0, proof_inputs).await
+    }
+
+    fn verify(
+        &self,
+        vk: &plonk::VerifierKey,
+        public_inputs: &[String],
+        proof: &plonk::Proof,
+    ) -> Result<()> {
+        snarkjs::verify_proof(vk, public_inputs, proof)
+    }
+}
+
+/// Noir programs, with nargo and bb.
+struct Noir;
+
+#[async_trait]
+impl ProofSystem for Noir {
+    fn kind(&self) -> ProofSystemKind {
+        ProofSystemKind::UltraHonk
+    }
+
+    async fn verifier_key(&self, circuit_path: &Path) -> Result<plonk::VerifierKey> {
+        noir::verifier_key(circuit_path)
+    }
+
+    async fn prove(
+        &self,
+        circuit_path: &Path,
+        proof_inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
+        noir::prove(circuit_path, proof_inputs)
+    }
+
+    fn verify(
+        &self,
+        vk: &plonk::VerifierKey,
+        public_inputs: &[String],
+        proof: &plonk::Proof,
+    ) -> Result<()> {
+        let (plonk::VerifierKey::Noir(vk), plonk::Proof::Noir(proof)) = (vk, proof) else {
+            bail!("not a Noir proof");
+        };
+        noir::verify_proof(vk, public_inputs, proof)
+    }
+}
+
+/// The halo2 circuits built into zkBitcoin.
+#[cfg(feature = "halo2")]
+struct Halo2;
+
+#[cfg(feature = "halo2")]
+#[async_trait]
+impl ProofSystem for Halo2 {
+    fn kind(&self) -> ProofSystemKind {
+        ProofSystemKind::Halo2
+    }
+
+    async fn verifier_key(&self, circuit_path: &Path) -> Result<plonk::VerifierKey> {
+        halo2::verifier_key_of(circuit_path)
+    }
+
+    async fn prove(
+        &self,
+        circuit_path: &Path,
+        proof_inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
+        halo2::prove_circuit(circuit_path, proof_inputs)
+    }
+
+    fn verify(
+        &self,
+        vk: &plonk::VerifierKey,
+        public_inputs: &[String],
+        proof: &plonk::Proof,
+    ) -> Result<()> {
+        let (plonk::VerifierKey::Halo2(vk), plonk::Proof::Halo2(proof)) = (vk, proof) else {
+            bail!("not a halo2 proof");
+        };
+        halo2::verify_circuit_proof(vk, public_inputs, proof)
+    }
+}
+
+/// RISC Zero programs.
+#[cfg(feature = "risc0")]
+struct RiscZero;
+
+#[cfg(feature = "risc0")]
+#[async_trait]
+impl ProofSystem for RiscZero {
+    fn kind(&self) -> ProofSystemKind {
+        ProofSystemKind::RiscZero
+    }
+
+    async fn verifier_key(&self, circuit_path: &Path) -> Result<plonk::VerifierKey> {
+        risc0::verifier_key(circuit_path)
+    }
+
+    async fn prove(
+        &self,
+        circuit_path: &Path,
+        proof_inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
+        risc0::prove(circuit_path, proof_inputs)
+    }
+
+    fn verify(
+        &self,
+        vk: &plonk::VerifierKey,
+        public_inputs: &[String],
+        proof: &plonk::Proof,
+    ) -> Result<()> {
+        let (plonk::VerifierKey::RiscZero(vk), plonk::Proof::RiscZero(proof)) = (vk, proof) else {
+            bail!("not a RISC Zero receipt");
+        };
+        risc0::verify_receipt(vk, public_inputs, proof)
+    }
+}
+
+/// Circom step circuits, folded with Nova.
+#[cfg(feature = "nova")]
+struct Nova;
+
+#[cfg(feature = "nova")]
+#[async_trait]
+impl ProofSystem for Nova {
+    fn kind(&self) -> ProofSystemKind {
+        ProofSystemKind::Nova
+    }
+
+    async fn verifier_key(&self, circuit_path: &Path) -> Result<plonk::VerifierKey> {
+        folding::verifier_key(circuit_path)
+    }
+
+    async fn prove(
+        &self,
+        circuit_path: &Path,
+        proof_inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
+        folding::prove(circuit_path, proof_inputs)
+    }
+
+    fn verify(
+        &self,
+        vk: &plonk::VerifierKey,
+        public_inputs: &[String],
+        proof: &plonk::Proof,
+    ) -> Result<()> {
+        let (plonk::VerifierKey::Nova(vk), plonk::Proof::Nova(proof)) = (vk, proof) else {
+            bail!("not a Nova proof");
+        };
+        folding::verify_proof(vk, public_inputs, proof)
+    }
+}
+
+/// A proof system zkBitcoin was built without (each of halo2, RISC Zero, and Nova has its own feature).
+struct Disabled(ProofSystemKind);
+
+impl Disabled {
+    fn error(&self) -> anyhow::Error {
+        anyhow!(
+            "zkBitcoin was built without the `{}` feature, needed for {} proofs",
+            self.0.name(),
+            self.0.name()
+        )
+    }
+}
+
+#[async_trait]
+impl ProofSystem for Disabled {
+    fn kind(&self) -> ProofSystemKind {
+        self.0
+    }
+
+    async fn verifier_key(&self, _circuit_path: &Path) -> Result<plonk::VerifierKey> {
+        Err(self.error())
+    }
+
+    async fn prove(
+        &self,
+        _circuit_path: &Path,
+        _proof_inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
+        Err(self.error())
+    }
+
+    fn verify(
+        &self,
+        _vk: &plonk::VerifierKey,
+        _public_inputs: &[String],
+        _proof: &plonk::Proof,
+    ) -> Result<()> {
+        Err(self.error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_circuit() {
+        let circom = Path::new("examples/circuit/stateless.circom");
+        let kind = |path: &Path, kind| for_circuit(path, kind).map(|system| system.kind());
+        assert_eq!(
+            kind(circom, ProofSystemKind::Groth16).unwrap(),
+            ProofSystemKind::Groth16
+        );
+        assert!(kind(circom, ProofSystemKind::Halo2).is_err());
+        assert!(kind(circom, ProofSystemKind::Nova).is_err());
+
+        let halo2 = Path::new("halo2:counter");
+        assert_eq!(
+            kind(halo2, ProofSystemKind::default()).unwrap(),
+            ProofSystemKind::Halo2
+        );
+        assert!(kind(halo2, ProofSystemKind::Groth16).is_err());
+
+        assert_eq!(
+            kind(Path::new("risc0:zkapp"), ProofSystemKind::default()).unwrap(),
+            ProofSystemKind::RiscZero
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let dir = Path::new("/home/bob");
+        assert_eq!(
+            resolve(dir, Path::new("examples/circuit/stateless.circom")),
+            Path::new("/home/bob/examples/circuit/stateless.circom")
+        );
+        assert_eq!(
+            resolve(dir, Path::new("halo2:counter")),
+            Path::new("halo2:counter")
+        );
+        let program = resolve(dir, Path::new("risc0:target/zkapp"));
+        assert_eq!(program, Path::new("risc0:/home/bob/target/zkapp"));
+        assert_eq!(
+            risc0::elf_path(&program),
+            Some(Path::new("/home/bob/target/zkapp").to_path_buf())
+        );
+        assert_eq!(
+            resolve(dir, Path::new("risc0:/zkapps/zkapp")),
+            Path::new("risc0:/zkapps/zkapp")
+        );
+        // a directory whose name contains a prefix is just a directory
+        let circuit = resolve(dir, Path::new("zkapps/risc0:v2/circuit.circom"));
+        assert_eq!(
+            circuit,
+            Path::new("/home/bob/zkapps/risc0:v2/circuit.circom")
+        );
+        assert_eq!(risc0::elf_path(&circuit), None);
+    }
+
+    #[cfg(feature = "halo2")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_prove_all() {
+        let inputs = |prev_state: u64| -> HashMap<String, Vec<String>> {
+            [
+                ("prev_state", prev_state.to_string()),
+                ("truncated_txid", "42".to_string()),
+                ("amount_out", "1000".to_string()),
+                ("amount_in", "0".to_string()),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), vec![value]))
+            .collect()
+        };
+        let path = Path::new("halo2:counter");
+        let proofs = prove_all(path, (0..4).map(inputs).collect(), 2)
+            .await
+            .unwrap();
+
+        // the proofs are in the order of their inputs
+        assert_eq!(proofs.len(), 4);
+        for (prev_state, (proof, public_inputs, vk)) in proofs.iter().enumerate() {
+            assert_eq!(public_inputs.new_state(), (prev_state + 1).to_string());
+            verify_proof(vk, &public_inputs.0, proof).unwrap();
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Where the proofs of zkapps are made: locally, or by a remote proving service.
+//!
+//! Proving large circuits takes more memory and time than a laptop might have,
+//! so users can have a proving service prove their zkapps instead (see [RemoteProver]).
+//! The service is only told which circuit to prove (by the hash the zkapp commits to) and the proof inputs,
+//! which can be encrypted to the public key of the service (with NIP-04, as over Nostr) so that only it can read them.
+//!
+//! A proving service answers `POST <url>/prove` with a [ProveRequest], with a [ProveResponse].
+
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use anyhow::{ensure, Context, Result};
+use async_trait::async_trait;
+use log::info;
+use nostr_sdk::{nips::nip04, secp256k1::XOnlyPublicKey, Keys};
+use reqwest::header::CONTENT_TYPE;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    folding,
+    json_rpc_stuff::http_client,
+    lint::{self, Layout},
+    plonk::{self, PublicInputs},
+    proof_system,
+};
+
+/// How long (in seconds) to wait for a proving service to prove a circuit.
+const REMOTE_PROVING_TIMEOUT: u64 = 30 * 60;
+
+/// Proves the circuit of a zkapp.
+#[async_trait]
+pub trait Prover: Send + Sync {
+    /// Proves the circuit of the zkapp committed to by `vk_hash` on `proof_inputs`,
+    /// and returns the proof, the full public inputs, and the verifier key.
+    async fn prove(
+        &self,
+        vk_hash: &[u8; 32],
+        proof_inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<(plonk::Proof, PublicInputs, plonk::VerifierKey)>;
+
+    /// The public and private inputs of the circuit, to check proof inputs against before proving (if known).
+    fn layout(&self) -> Result<Option<Layout>> {
+        Ok(None)
+    }
+}
+
+//
+// Local proving
+//
+
+/// Proves a circuit on this machine (see [proof_system::prove]).
+pub struct LocalProver {
+    pub circuit_path: PathBuf,
+
+    /// The inputs of the circuit, as declared by the user
+    /// (read from the source of circom circuits otherwise).
+    pub layout: Option<Layout>,
+}
+
+impl LocalProver {
+    pub fn new(circuit_path: impl Into<PathBuf>) -> Self {
+        Self {
+            circuit_path: circuit_path.into(),
+            layout: None,
+        }
+    }
+}
+
+#[async_trait]
+impl Prover for LocalProver {
+    async fn prove(
+        &self,
+        _vk_hash: &[u8; 32],
+        proof_inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<(plonk::Proof, PublicInputs, plonk::VerifierKey)> {
+        proof_system::prove(&self.circuit_path, proof_inputs).await
+    }
+
+    fn layout(&self) -> Result<Option<Layout>> {
+        if self.layout.is_some() {
+            return Ok(self.layout.clone());
+        }
+
+        // step circuits take the inputs of their steps instead
+        let is_circom = self
+            .circuit_path
+            .extension()
+            .is_some_and(|extension| extension == "circom");
+        if !is_circom || folding::is_step_circuit(&self.circuit_path) {
+            return Ok(None);
+        }
+        let source = std::fs::read_to_string(&self.circuit_path)
+            .with_context(|| format!("couldn't read {}", self.circuit_path.display()))?;
+        lint::layout(&source).map(Some)
+    }
+}
+
+//
+// Remote proving
+//
+
+/// What is sent to a proving service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProveRequest {
+    /// The hash the zkapp commits to (of its verifier key, or its image ID), hex-encoded,
+    /// which the service knows the circuit of.
+    pub circuit_hash: String,
+
+    /// The proof inputs.
+    pub inputs: RemoteInputs,
+}
+
+/// The proof inputs sent to a proving service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteInputs {
+    /// In the clear.
+    Plain(HashMap<String, Vec<String>>),
+
+    /// Encrypted (with NIP-04) to the public key of the service, from a one-time key.
+    Encrypted {
+        /// The one-time public key, hex-encoded.
+        pubkey: String,
+
+        /// The encrypted JSON of the proof inputs.
+        content: String,
+    },
+}
+
+/// What a proving service answers with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProveResponse {
+    pub proof: plonk::Proof,
+    pub public_inputs: Vec<String>,
+    pub vk: plonk::VerifierKey,
+}
+
+/// Has a proving service prove circuits.
+pub struct RemoteProver {
+    /// The URL of the service.
+    pub url: String,
+
+    /// The public key of the service, to encrypt the proof inputs to (if set).
+    pub pubkey: Option<XOnlyPublicKey>,
+
+    /// The inputs of the circuit, as declared by the user (if they were).
+    pub layout: Option<Layout>,
+}
+
+impl RemoteProver {
+    /// The request proving the circuit committed to by `vk_hash` on `proof_inputs`.
+    pub fn request(
+        &self,
+        vk_hash: &[u8; 32],
+        proof_inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<ProveRequest> {
+        let inputs = match &self.pubkey {
+            Some(pubkey) => {
+                let keys = Keys::generate();
+                let content = nip04::encrypt(
+                    &keys.secret_key()?,
+                    pubkey,
+                    serde_json::to_string(proof_inputs)?,
+                )
+                .context("couldn't encrypt the proof inputs")?;
+                RemoteInputs::Encrypted {
+                    pubkey: keys.public_key().to_string(),
+                    content,
+                }
+            }
+            None => RemoteInputs::Plain(proof_inputs.clone()),
+        };
+        Ok(ProveRequest {
+            circuit_hash: hex::encode(vk_hash),
+            inputs,
+        })
+    }
+}
+
+#[async_trait]
+impl Prover for RemoteProver {
+    async fn prove(
+        &self,
+        vk_hash: &[u8; 32],
+        proof_inputs: &HashMap<String, Vec<String>>,
+    ) -> Result<(plonk::Proof, PublicInputs, plonk::VerifierKey)> {
+        let request = self.request(vk_hash, proof_inputs)?;
+        let url = format!("{}/prove", self.url.trim_end_matches('/'));
+        info!("- proving with {url}");
+
+        let response: ProveResponse = http_client()
+            .post(&url)
+            .timeout(Duration::from_secs(REMOTE_PROVING_TIMEOUT))
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_string(&request)?)
+            .send()
+            .await
+            .with_context(|| format!("couldn't reach the proving service at {url}"))?
+            .error_for_status()
+            .context("the proving service couldn't prove the circuit")?
+            .json()
+            .await
+            .context("couldn't parse the answer of the proving service")?;
+
+        ensure!(
+            &response.vk.hash() == vk_hash,
+            "the proving service proved another circuit than the one of the zkapp"
+        );
+        Ok((
+            response.proof,
+            PublicInputs(response.public_inputs),
+            response.vk,
+        ))
+    }
+
+    fn layout(&self) -> Result<Option<Layout>> {
+        Ok(self.layout.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request() {
+        let proof_inputs = HashMap::from([("secret".to_string(), vec!["42".to_string()])]);
+
+        let plain = RemoteProver {
+            url: "http://127.0.0.1:8890".to_string(),
+            pubkey: None,
+            layout: None,
+        };
+        let request = plain.request(&[1; 32], &proof_inputs).unwrap();
+        assert_eq!(request.circuit_hash, hex::encode([1; 32]));
+        assert!(matches!(request.inputs, RemoteInputs::Plain(inputs) if inputs == proof_inputs));
+
+        // only the service can read encrypted inputs
+        let service = Keys::generate();
+        let encrypted = RemoteProver {
+            pubkey: Some(service.public_key()),
+            ..plain
+        };
+        let RemoteInputs::Encrypted { pubkey, content } =
+            encrypted.request(&[1; 32], &proof_inputs).unwrap().inputs
+        else {
+            panic!("the inputs are not encrypted");
+        };
+        assert!(!content.contains("42"));
+        let sender = crate::nostr_transport::parse_public_key(&pubkey).unwrap();
+        let decrypted = nip04::decrypt(&service.secret_key().unwrap(), &sender, content).unwrap();
+        assert_eq!(
+            serde_json::from_str::<HashMap<String, Vec<String>>>(&decrypted).unwrap(),
+            proof_inputs
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! The powers of tau (the universal setup of plonk) that circuits are set up with.
+//!
+//! They come from the Hermez ceremony (see https://github.com/iden3/snarkjs#7-prepare-phase-2),
+//! which published one file per maximum circuit size (2^power constraints).
+//! The smallest file fitting a circuit is downloaded to `~/.zkbitcoin/ptau`, and checked every time it is used:
+//! against a pinned hash if we have one, or else with `snarkjs powersoftau verify`
+//! (which checks every contribution to the ceremony) the first time, and against the hash it had then afterwards.
+
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use log::info;
+use tokio::{fs::File, io::AsyncWriteExt};
+use tokio_stream::StreamExt;
+
+use crate::{json_rpc_stuff::http_client, zkbitcoin_folder};
+
+//
+// Constants
+//
+
+/// The name of the files of the ceremony, followed by their power.
+pub const CEREMONY: &str = "powersOfTau28_hez_final";
+
+/// Where the files of the ceremony are published.
+const URL_PREFIX: &str = "https://storage.googleapis.com/zkevm/ptau";
+
+/// The smallest file used. It's the one zkBitcoin always used,
+/// so that small circuits keep being set up (and downloaded) the same way.
+pub const MIN_POWER: u8 = 16;
+
+/// The largest file of the ceremony.
+pub const MAX_POWER: u8 = 28;
+
+/// The SHA-256 hashes of the files we know, by power.
+const PINNED_HASHES: [(u8, &str); 1] = [(
+    16,
+    "1c401abb57c9ce531370f3015c3e75c0892e0f32b8b1e94ace0f6682d9695922",
+)];
+
+/// Where the file of power 16 used to be downloaded to.
+const LEGACY_SRS_FILENAME: &str = "srs_28.ptau";
+
+//
+// Sizing
+//
+
+/// Returns the smallest power of the ceremony fitting a circuit of `num_constraints` plonk constraints.
+pub fn power_for(num_constraints: usize) -> Result<u8> {
+    let power = (MIN_POWER..=MAX_POWER)
+        .find(|power| num_constraints < 1 << power)
+        .with_context(|| {
+            format!("the circuit is too big ({num_constraints} plonk constraints, at most 2^{MAX_POWER} are supported)")
+        })?;
+    Ok(power)
+}
+
+/// Estimates the number of plonk constraints of the circuit compiled to `r1cs_path`.
+pub fn plonk_constraints(r1cs_path: &Path) -> Result<usize> {
+    let mut r1cs = vec![];
+    std::fs::File::open(r1cs_path)
+        .and_then(|mut file| file.read_to_end(&mut r1cs))
+        .with_context(|| format!("couldn't read {}", r1cs_path.display()))?;
+    plonk_constraints_of(&r1cs).with_context(|| format!("couldn't parse {}", r1cs_path.display()))
+}
+
+/// Reads the r1cs format of circom (see https://github.com/iden3/r1csfile/blob/master/doc/r1cs_bin_format.md),
+/// and counts a plonk constraint per public signal, and per r1cs constraint and addition it needs.
+/// This is an upper bound of what `snarkjs plonk setup` ends up with.
+fn plonk_constraints_of(r1cs: &[u8]) -> Result<usize> {
+    let mut reader = Reader(r1cs);
+    ensure!(reader.bytes(4)? == b"r1cs", "not an r1cs file");
+    let _version = reader.u32()?;
+    let num_sections = reader.u32()?;
+
+    // the sections can come in any order
+    let mut header = None;
+    let mut constraints = None;
+    for _ in 0..num_sections {
+        let kind = reader.u32()?;
+        let size = usize::try_from(reader.u64()?)?;
+        let content = reader.bytes(size)?;
+        match kind {
+            1 => header = Some(content),
+            2 => constraints = Some(content),
+            _ => (),
+        }
+    }
+
+    let mut header = Reader(header.context("no header section")?);
+    let field_size = header.u32()? as usize;
+    header.bytes(field_size)?; // prime
+    let _num_wires = header.u32()?;
+    let num_public = header.u32()? as usize + header.u32()? as usize; // outputs + public inputs
+    let _num_private = header.u32()?;
+    let _num_labels = header.u64()?;
+    let num_constraints = header.u32()?;
+
+    let mut reader = Reader(constraints.context("no constraints section")?);
+    let mut total = num_public;
+    for _ in 0..num_constraints {
+        total += 1;
+        // A * B = C, each a linear combination
+        for _ in 0..3 {
+            let num_terms = reader.u32()? as usize;
+            total += num_terms.saturating_sub(1);
+            reader.bytes(num_terms * (4 + field_size))?;
+        }
+    }
+    Ok(total)
+}
+
+/// Reads little-endian values from a buffer.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.0.len() < len {
+            bail!("truncated file");
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+}
+
+//
+// Files
+//
+
+/// Returns the directory the files are downloaded to (`~/.zkbitcoin/ptau`).
+pub fn ptau_dir() -> PathBuf {
+    zkbitcoin_folder().join("ptau")
+}
+
+/// The name of the file of a given power.
+pub fn filename(power: u8) -> String {
+    format!("{CEREMONY}_{power:02}.ptau")
+}
+
+/// Returns the local path to the file of a given power, once downloaded (if needed) and checked.
+pub async fn ptau_path(power: u8) -> Result<PathBuf> {
+    ensure!(
+        (MIN_POWER..=MAX_POWER).contains(&power),
+        "there is no powers of tau file of power {power}"
+    );
+    let dir = ptau_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+    let path = dir.join(filename(power));
+
+    if !path.exists() {
+        // reuse the file that used to be downloaded elsewhere
+        let legacy_path = zkbitcoin_folder().join(LEGACY_SRS_FILENAME);
+        if power == MIN_POWER && legacy_path.exists() {
+            std::fs::rename(&legacy_path, &path)
+                .with_context(|| format!("couldn't move {}", legacy_path.display()))?;
+        } else {
+            download(&format!("{URL_PREFIX}/{}", filename(power)), &path).await?;
+        }
+    }
+
+    check(power, &path)?;
+    Ok(path)
+}
+
+/// Downloads a file, next to `path` first so that incomplete downloads are never used.
+async fn download(url: &str, path: &Path) -> Result<()> {
+    info!("- downloading {url}...");
+    let partial_path = path.with_extension("ptau.part");
+    let mut file = File::create(&partial_path)
+        .await
+        .with_context(|| format!("couldn't create {}", partial_path.display()))?;
+    let response = http_client()
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("couldn't download {url}"))?;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.with_context(|| format!("couldn't download {url}"))?;
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    std::fs::rename(&partial_path, path)
+        .with_context(|| format!("couldn't create {}", path.display()))?;
+    info!("- downloaded {}", path.display());
+    Ok(())
+}
+
+/// Checks the integrity of the file of a given power.
+fn check(power: u8, path: &Path) -> Result<()> {
+    let hash =
+        sha256::try_digest(path).with_context(|| format!("couldn't read {}", path.display()))?;
+
+    // the hash is either pinned, or the one the file had when it was verified
+    let verified_path = path.with_extension("ptau.verified");
+    let expected = match PINNED_HASHES.iter().find(|(pinned, _)| *pinned == power) {
+        Some((_, expected)) => expected.to_string(),
+        None if verified_path.exists() => std::fs::read_to_string(&verified_path)
+            .with_context(|| format!("couldn't read {}", verified_path.display()))?
+            .trim()
+            .to_string(),
+        None => {
+            verify_ceremony(path)?;
+            std::fs::write(&verified_path, &hash)
+                .with_context(|| format!("couldn't write {}", verified_path.display()))?;
+            hash.clone()
+        }
+    };
+
+    ensure!(
+        hash == expected,
+        "{} is corrupted (its SHA-256 is {hash} instead of {expected}), delete it to download it again",
+        path.display()
+    );
+    Ok(())
+}
+
+/// Verifies all the contributions to the ceremony in a file (which takes a while).
+fn verify_ceremony(path: &Path) -> Result<()> {
+    info!("- verifying {} (this can take a while)...", path.display());
+    let output = Command::new("snarkjs")
+        .arg("powersoftau")
+        .arg("verify")
+        .arg(path)
+        .output()
+        .context("couldn't execute snarkjs")?;
+    info!("{}", String::from_utf8_lossy(&output.stdout));
+    ensure!(
+        output.status.success(),
+        "{} is not a valid powers of tau file, delete it to download it again",
+        path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An r1cs file with a single constraint `a * (b + c) = d`, and 1 public output.
+    fn r1cs() -> Vec<u8> {
+        let field_size = 32u32;
+        let mut header = vec![];
+        header.extend(field_size.to_le_bytes());
+        header.extend([0; 32]); // prime
+        for n in [5u32, 1, 0, 3] {
+            // wires, outputs, public inputs, private inputs
+            header.extend(n.to_le_bytes());
+        }
+        header.extend(5u64.to_le_bytes()); // labels
+        header.extend(1u32.to_le_bytes()); // constraints
+
+        let mut constraints = vec![];
+        for wires in [vec![2u32], vec![3, 4], vec![1]] {
+            constraints.extend((wires.len() as u32).to_le_bytes());
+            for wire in wires {
+                constraints.extend(wire.to_le_bytes());
+                constraints.extend([1; 32]);
+            }
+        }
+
+        let mut r1cs = b"r1cs".to_vec();
+        r1cs.extend(1u32.to_le_bytes()); // version
+        r1cs.extend(2u32.to_le_bytes()); // sections
+        for (kind, content) in [(2u32, constraints), (1, header)] {
+            r1cs.extend(kind.to_le_bytes());
+            r1cs.extend((content.len() as u64).to_le_bytes());
+            r1cs.extend(content);
+        }
+        r1cs
+    }
+
+    #[test]
+    fn test_plonk_constraints() {
+        // the public output, the constraint, and the addition b + c
+        assert_eq!(plonk_constraints_of(&r1cs()).unwrap(), 3);
+
+        let r1cs = r1cs();
+        assert!(plonk_constraints_of(&r1cs[..r1cs.len() - 1]).is_err());
+        assert!(plonk_constraints_of(b"nope").is_err());
+    }
+
+    #[test]
+    fn test_power_for() {
+        assert_eq!(power_for(3).unwrap(), MIN_POWER);
+        assert_eq!(power_for((1 << 16) - 1).unwrap(), 16);
+        assert_eq!(power_for(1 << 16).unwrap(), 17);
+        assert!(power_for(1 << MAX_POWER).is_err());
+        assert_eq!(filename(9), "powersOfTau28_hez_final_09.ptau");
+    }
+}
@@ -0,0 +1,58 @@
+//! Replace-by-fee (BIP 125) support.
+//!
+//! Deploy transactions only spend wallet inputs, so the wallet can bump their fee on its own.
+//! Spend transactions also spend a zkapp, and their proof is bound to their transaction ID,
+//! so bumping their fee means rebuilding, re-proving, and having the committee sign them again.
+//! To do that, we keep a local record of the spends we make.
+
+use std::{fs, path::PathBuf};
+
+use anyhow::{Context, Result};
+use bitcoin::Txid;
+use serde::{Deserialize, Serialize};
+
+use crate::zkbitcoin_folder;
+
+/// A spend of a zkapp made by this machine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpendRecord {
+    /// The transaction ID of the spend.
+    pub txid: Txid,
+
+    /// The transaction ID of the zkapp being spent.
+    pub zkapp_txid: Txid,
+
+    /// The recipients of the funds, as given to `use-zkapp` (see [crate::bob_request::Recipient]).
+    pub recipients: Vec<String>,
+}
+
+impl SpendRecord {
+    /// Returns the directory where spend records are kept.
+    fn dir() -> PathBuf {
+        zkbitcoin_folder().join("spends")
+    }
+
+    fn path(txid: Txid) -> PathBuf {
+        Self::dir().join(format!("{txid}.json"))
+    }
+
+    /// Persists the record.
+    pub fn save(&self) -> Result<()> {
+        fs::create_dir_all(Self::dir()).context("couldn't create spends directory")?;
+        let file =
+            fs::File::create(Self::path(self.txid)).context("couldn't create spend record")?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Loads the record of a spend, if this transaction is a spend made by this machine.
+    pub fn load(txid: Txid) -> Result<Option<Self>> {
+        let path = Self::path(txid);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file = fs::File::open(path)?;
+        let record = serde_json::from_reader(file).context("couldn't parse spend record")?;
+        Ok(Some(record))
+    }
+}
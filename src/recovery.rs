@@ -0,0 +1,426 @@
+//! Recovery of the funds of a zkapp by its depositor, should the committee stop signing (see `zkbtc recover`).
+//!
+//! A recoverable zkapp isn't locked in the zkBitcoin address, but in a taproot output with two scripts:
+//! one for the committee (`<committee key> OP_CHECKSIG`, the committee key being the output key of the zkBitcoin address),
+//! and one for the depositor (`<blocks> OP_CHECKSEQUENCEVERIFY OP_DROP <depositor key> OP_CHECKSIG`),
+//! which can only be used once the zkapp has been left untouched for that many blocks.
+//! Its internal key is unspendable (the one suggested by BIP 341), as the committee only signs for its output key:
+//! the committee spends it through its script instead.
+//!
+//! The depositor key and the delay are committed on chain along with the zkapp (see [crate::zkapp_data::ZkappData::recovery]),
+//! so that anyone can tell the output of a recoverable zkapp from the transaction that created it.
+//! They are kept when the zkapp is updated, which starts the delay over.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use bitcoin::{
+    absolute::LockTime,
+    key::{TapTweak, UntweakedPublicKey},
+    opcodes::all::{OP_CHECKSIG, OP_CSV, OP_DROP},
+    script::Builder,
+    sighash::{Prevouts, SighashCache},
+    taproot::{self, LeafVersion, TapLeafHash, TaprootBuilder, TaprootSpendInfo},
+    transaction::Version,
+    Address, Amount, FeeRate, OutPoint, PublicKey, ScriptBuf, Sequence, TapSighashType,
+    Transaction, TxIn, TxOut, Witness,
+};
+use secp256k1::{hashes::Hash, Keypair, Message, Secp256k1, SecretKey, XOnlyPublicKey};
+
+use crate::{amounts::check_not_dust, bob_request::SmartContract, config::protocol_config};
+
+//
+// Constants
+//
+
+/// The length of an encoded recovery: the x-only key of the depositor, then the delay (big-endian).
+pub const RECOVERY_LEN: usize = 32 + 2;
+
+/// The shortest delay (in blocks, about a month) before a depositor can recover a zkapp,
+/// so that the committee can't be raced by the depositor while it is up.
+pub const MIN_RECOVERY_BLOCKS: u16 = 4_320;
+
+/// The default delay (in blocks, about a year) before a depositor can recover a zkapp.
+pub const DEFAULT_RECOVERY_BLOCKS: u16 = 52_560;
+
+/// The unspendable internal key of recoverable zkapps (see BIP 341).
+const UNSPENDABLE_KEY: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+//
+// Data structures
+//
+
+/// Who can recover the funds of a zkapp, and after how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recovery {
+    /// The x-only public key of the depositor.
+    pub depositor: XOnlyPublicKey,
+
+    /// How many blocks the zkapp has to be left untouched before the depositor can recover it.
+    pub blocks: u16,
+}
+
+impl Recovery {
+    /// Ensures that the delay is long enough.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.blocks >= MIN_RECOVERY_BLOCKS,
+            "the recovery delay must be at least {MIN_RECOVERY_BLOCKS} blocks, not {}",
+            self.blocks
+        );
+        Ok(())
+    }
+
+    //
+    // Encoding
+    //
+
+    /// Encodes the recovery (see [RECOVERY_LEN]).
+    pub fn encode(&self) -> [u8; RECOVERY_LEN] {
+        let mut bytes = [0; RECOVERY_LEN];
+        bytes[..32].copy_from_slice(&self.depositor.serialize());
+        bytes[32..].copy_from_slice(&self.blocks.to_be_bytes());
+        bytes
+    }
+
+    /// Decodes a recovery (see [RECOVERY_LEN]).
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.len() == RECOVERY_LEN,
+            "a recovery is {RECOVERY_LEN} bytes, not {}",
+            bytes.len()
+        );
+        let recovery = Self {
+            depositor: XOnlyPublicKey::from_slice(&bytes[..32])
+                .context("the depositor key of the recovery is not a valid public key")?,
+            blocks: u16::from_be_bytes(bytes[32..].try_into().unwrap()),
+        };
+        recovery.validate()?;
+        Ok(recovery)
+    }
+
+    //
+    // Scripts
+    //
+
+    /// The script the committee spends the zkapp with.
+    pub fn committee_script() -> Result<ScriptBuf> {
+        Ok(Builder::new()
+            .push_x_only_key(&committee_key()?)
+            .push_opcode(OP_CHECKSIG)
+            .into_script())
+    }
+
+    /// The script the depositor recovers the zkapp with.
+    pub fn recovery_script(&self) -> ScriptBuf {
+        Builder::new()
+            .push_int(i64::from(self.blocks))
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_x_only_key(&self.depositor)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+
+    /// The taproot tree of the zkapp's output.
+    fn spend_info(&self) -> Result<TaprootSpendInfo> {
+        let secp = Secp256k1::verification_only();
+        let internal_key = XOnlyPublicKey::from_slice(&UNSPENDABLE_KEY).unwrap();
+        TaprootBuilder::new()
+            .add_leaf(1, Self::committee_script()?)
+            .and_then(|builder| builder.add_leaf(1, self.recovery_script()))
+            .map_err(|err| anyhow!("couldn't build the taproot tree of the zkapp: {err}"))?
+            .finalize(&secp, internal_key)
+            .map_err(|_| anyhow!("couldn't finalize the taproot tree of the zkapp"))
+    }
+
+    /// The script of the output locking the funds of the zkapp.
+    pub fn script_pubkey(&self) -> Result<ScriptBuf> {
+        Ok(ScriptBuf::new_p2tr_tweaked(self.spend_info()?.output_key()))
+    }
+
+    /// The hash of the committee's script, which its signatures commit to.
+    pub fn committee_leaf_hash() -> Result<TapLeafHash> {
+        Ok(TapLeafHash::from_script(
+            &Self::committee_script()?,
+            LeafVersion::TapScript,
+        ))
+    }
+
+    /// The witness spending the zkapp with `script` (one of its two), given a signature for it.
+    fn witness(&self, script: ScriptBuf, signature: &taproot::Signature) -> Result<Witness> {
+        let control_block = self
+            .spend_info()?
+            .control_block(&(script.clone(), LeafVersion::TapScript))
+            .context("the script is not in the taproot tree of the zkapp")?;
+        Ok(Witness::from_slice(&[
+            signature.to_vec(),
+            script.to_bytes(),
+            control_block.serialize(),
+        ]))
+    }
+
+    /// The witness spending the zkapp with the committee's signature.
+    pub fn committee_witness(&self, signature: &taproot::Signature) -> Result<Witness> {
+        self.witness(Self::committee_script()?, signature)
+    }
+
+    //
+    // Recovery
+    //
+
+    /// Creates the transaction recovering all the funds of `smart_contract` to `address` (minus the network fee),
+    /// signed with the secret key of the depositor.
+    /// It can only be mined once the zkapp has `blocks` confirmations.
+    pub fn recover(
+        &self,
+        smart_contract: &SmartContract,
+        address: &Address,
+        fee_rate: FeeRate,
+        secret_key: &SecretKey,
+    ) -> Result<Transaction> {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, secret_key);
+        ensure!(
+            keypair.x_only_public_key().0 == self.depositor,
+            "the secret key is not the one of the depositor ({})",
+            self.depositor
+        );
+
+        let prev_out = TxOut {
+            value: smart_contract.locked_value,
+            script_pubkey: self.script_pubkey()?,
+        };
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(
+                    smart_contract.txid,
+                    smart_contract.vout_of_zkbitcoin_utxo,
+                ),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::from_height(self.blocks),
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+
+        // pay the fee of the signed transaction (with a 64-byte signature)
+        let dummy = taproot::Signature {
+            sig: secp256k1::schnorr::Signature::from_slice(&[0; 64]).unwrap(),
+            hash_ty: TapSighashType::Default,
+        };
+        tx.input[0].witness = self.witness(self.recovery_script(), &dummy)?;
+        let fee = fee_rate
+            .fee_vb(tx.vsize() as u64)
+            .context("fee rate too large")?;
+        tx.output[0].value = smart_contract
+            .locked_value
+            .checked_sub(fee)
+            .context("the zkapp doesn't hold enough to pay the network fee")?;
+        check_not_dust("the recovered output", &tx.output[0])?;
+
+        // sign with the depositor's script
+        let leaf_hash = TapLeafHash::from_script(&self.recovery_script(), LeafVersion::TapScript);
+        let sighash = SighashCache::new(&tx).taproot_script_spend_signature_hash(
+            0,
+            &Prevouts::All(&[prev_out]),
+            leaf_hash,
+            TapSighashType::Default,
+        )?;
+        let sig =
+            secp.sign_schnorr_no_aux_rand(&Message::from_digest(sighash.to_byte_array()), &keypair);
+        let signature = taproot::Signature {
+            sig,
+            hash_ty: TapSighashType::Default,
+        };
+        tx.input[0].witness = self.witness(self.recovery_script(), &signature)?;
+        Ok(tx)
+    }
+}
+
+impl fmt::Display for Recovery {
+    /// Formats the recovery as `<depositor key>:<blocks>`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.depositor, self.blocks)
+    }
+}
+
+impl FromStr for Recovery {
+    type Err = anyhow::Error;
+
+    /// Parses a recovery formatted as `<depositor key>:<blocks>`, or just `<depositor key>`
+    /// (recoverable after [DEFAULT_RECOVERY_BLOCKS]).
+    /// The depositor key is a hex-encoded x-only (or compressed) public key.
+    fn from_str(s: &str) -> Result<Self> {
+        let (key, blocks) = match s.split_once(':') {
+            Some((key, blocks)) => (
+                key,
+                blocks.parse().with_context(|| {
+                    format!("the recovery delay {blocks} is not a number of blocks (at most 65535)")
+                })?,
+            ),
+            None => (s, DEFAULT_RECOVERY_BLOCKS),
+        };
+        let depositor = match XOnlyPublicKey::from_str(key) {
+            Ok(depositor) => depositor,
+            Err(_) => {
+                PublicKey::from_str(key)
+                    .with_context(|| format!("the depositor key {key} is not a public key"))?
+                    .inner
+                    .x_only_public_key()
+                    .0
+            }
+        };
+        let recovery = Self { depositor, blocks };
+        recovery.validate()?;
+        Ok(recovery)
+    }
+}
+
+/// The key the committee signs for: the output key of the zkBitcoin address.
+fn committee_key() -> Result<XOnlyPublicKey> {
+    let pubkey = PublicKey::from_str(&protocol_config().zkbitcoin_pubkey)
+        .context("the zkBitcoin public key is not a valid public key")?;
+    let secp = Secp256k1::verification_only();
+    let (tweaked, _) = UntweakedPublicKey::from(pubkey).tap_tweak(&secp, None);
+    Ok(tweaked.to_inner())
+}
+
+/// The script of the output locking the funds of a zkapp, recoverable or not.
+pub fn zkapp_script_pubkey(recovery: Option<&Recovery>) -> Result<ScriptBuf> {
+    match recovery {
+        Some(recovery) => recovery.script_pubkey(),
+        None => {
+            let pubkey = PublicKey::from_str(&protocol_config().zkbitcoin_pubkey)
+                .context("the zkBitcoin public key is not a valid public key")?;
+            Ok(crate::p2tr_script_to(pubkey))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Txid;
+
+    use super::*;
+
+    fn recovery() -> (SecretKey, Recovery) {
+        let secp = Secp256k1::new();
+        let secret_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let depositor = secret_key.x_only_public_key(&secp).0;
+        (
+            secret_key,
+            Recovery {
+                depositor,
+                blocks: MIN_RECOVERY_BLOCKS,
+            },
+        )
+    }
+
+    #[test]
+    fn test_encoding() {
+        let (_, recovery) = recovery();
+        assert_eq!(Recovery::decode(&recovery.encode()).unwrap(), recovery);
+        assert_eq!(recovery.to_string().parse::<Recovery>().unwrap(), recovery);
+        assert_eq!(
+            recovery.depositor.to_string().parse::<Recovery>().unwrap(),
+            Recovery {
+                blocks: DEFAULT_RECOVERY_BLOCKS,
+                ..recovery
+            }
+        );
+
+        // the delay can't be too short
+        let short = format!("{}:144", recovery.depositor);
+        assert!(short.parse::<Recovery>().is_err());
+        let mut bytes = recovery.encode();
+        bytes[32..].copy_from_slice(&144u16.to_be_bytes());
+        assert!(Recovery::decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_scripts() {
+        let (_, recovery) = recovery();
+
+        // recoverable zkapps aren't locked in the zkBitcoin address, and each depositor gets their own output
+        let script_pubkey = recovery.script_pubkey().unwrap();
+        assert!(script_pubkey.is_p2tr());
+        assert_ne!(script_pubkey, zkapp_script_pubkey(None).unwrap());
+        let other = Recovery {
+            blocks: DEFAULT_RECOVERY_BLOCKS,
+            ..recovery
+        };
+        assert_ne!(script_pubkey, other.script_pubkey().unwrap());
+
+        // both scripts are in its tree
+        let spend_info = recovery.spend_info().unwrap();
+        for script in [
+            Recovery::committee_script().unwrap(),
+            recovery.recovery_script(),
+        ] {
+            let control_block = spend_info
+                .control_block(&(script.clone(), LeafVersion::TapScript))
+                .unwrap();
+            assert!(control_block.verify_taproot_commitment(
+                &Secp256k1::verification_only(),
+                spend_info.output_key().to_inner(),
+                &script
+            ));
+        }
+    }
+
+    #[test]
+    fn test_recover() {
+        let (secret_key, recovery) = recovery();
+        let smart_contract = SmartContract {
+            txid: Txid::all_zeros(),
+            locked_value: Amount::from_sat(10_000),
+            vk_hash: [0; 32],
+            state: None,
+            metadata_hash: None,
+            admin: None,
+            timelock: None,
+            recovery: Some(recovery),
+            deployer: None,
+            vout_of_zkbitcoin_utxo: 0,
+        };
+        let address = crate::taproot_addr_from(&protocol_config().zkbitcoin_fee_pubkey).unwrap();
+
+        let tx = recovery
+            .recover(
+                &smart_contract,
+                &address,
+                FeeRate::from_sat_per_vb(2).unwrap(),
+                &secret_key,
+            )
+            .unwrap();
+        assert_eq!(
+            tx.input[0].sequence,
+            Sequence::from_height(MIN_RECOVERY_BLOCKS)
+        );
+        assert_eq!(tx.input[0].witness.len(), 3);
+        assert_eq!(
+            tx.output[0].value,
+            Amount::from_sat(10_000 - 2 * tx.vsize() as u64)
+        );
+
+        // only the depositor can recover the zkapp
+        let other = SecretKey::from_slice(&[8; 32]).unwrap();
+        assert!(recovery
+            .recover(
+                &smart_contract,
+                &address,
+                FeeRate::from_sat_per_vb(2).unwrap(),
+                &other
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,289 @@
+//! Proofs of reserves: the funds held by the zkBitcoin address on chain, against what the zkapps locked there are owed.
+//!
+//! Every zkapp indexed as unspent (see [crate::indexer]) is a liability, which must be backed by its UTXO.
+//! The report of the reconciliation is signed by the operator (with the key of its logs, see [crate::committee::audit]),
+//! so that it can be published as an attestation.
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    Amount, BlockHash, OutPoint,
+};
+use secp256k1::{schnorr, Keypair, Message, Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::protocol_config,
+    indexer::Indexer,
+    json_rpc_stuff::{scan_txout_set, RpcCtx},
+    scanner::Zkapp,
+    taproot_addr_from,
+};
+
+/// The domain separator of the digests of attestations.
+const ATTESTATION_DOMAIN: &[u8] = b"zkbitcoin proof of reserves";
+
+//
+// Data structures
+//
+
+/// An output held by the zkBitcoin address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReserveUtxo {
+    /// The output.
+    pub outpoint: OutPoint,
+
+    /// The value of the output.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub value: Amount,
+
+    /// The height of the block that contains the output.
+    pub height: u64,
+
+    /// The hex-encoded verifier key hash of the zkapp the output backs (if any).
+    pub vk_hash: Option<String>,
+}
+
+/// The funds held by the zkBitcoin address, reconciled with the zkapps locked there.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReservesReport {
+    /// The zkBitcoin address.
+    pub address: String,
+
+    /// The height of the chain the UTXO set was scanned at.
+    pub height: u64,
+
+    /// The block the UTXO set was scanned at.
+    pub block_hash: Option<BlockHash>,
+
+    /// The height of the last block indexed.
+    pub index_height: Option<u64>,
+
+    /// The funds held by the zkBitcoin address.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub reserves: Amount,
+
+    /// The funds locked in zkapps.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub liabilities: Amount,
+
+    /// The funds held that don't back any zkapp (e.g. sent to the address without zkapp metadata).
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub unallocated: Amount,
+
+    /// The outputs held by the zkBitcoin address.
+    pub utxos: Vec<ReserveUtxo>,
+
+    /// The zkapps indexed as unspent, whose output isn't in the UTXO set.
+    pub missing: Vec<Zkapp>,
+}
+
+impl ReservesReport {
+    /// Reconciles the outputs held by the zkBitcoin address (`(outpoint, value, height)`) with the zkapps locked there.
+    pub fn reconcile(
+        address: String,
+        height: u64,
+        utxos: Vec<(OutPoint, Amount, u64)>,
+        zkapps: Vec<Zkapp>,
+    ) -> Self {
+        let mut zkapps: HashMap<OutPoint, Zkapp> = zkapps
+            .into_iter()
+            .map(|zkapp| (zkapp.outpoint(), zkapp))
+            .collect();
+        let liabilities = zkapps.values().map(|zkapp| zkapp.locked_value).sum();
+
+        let mut unallocated = Amount::ZERO;
+        let utxos: Vec<_> = utxos
+            .into_iter()
+            .map(|(outpoint, value, height)| {
+                let zkapp = zkapps.remove(&outpoint);
+                if zkapp.is_none() {
+                    unallocated += value;
+                }
+                ReserveUtxo {
+                    outpoint,
+                    value,
+                    height,
+                    vk_hash: zkapp.map(|zkapp| zkapp.vk_hash),
+                }
+            })
+            .collect();
+        let mut missing: Vec<_> = zkapps.into_values().collect();
+        missing.sort_by_key(|zkapp| (zkapp.height, zkapp.outpoint()));
+
+        Self {
+            address,
+            height,
+            block_hash: None,
+            index_height: None,
+            reserves: utxos.iter().map(|utxo| utxo.value).sum(),
+            liabilities,
+            unallocated,
+            utxos,
+            missing,
+        }
+    }
+
+    /// Whether the funds on chain back every zkapp.
+    pub fn is_balanced(&self) -> bool {
+        self.missing.is_empty() && self.reserves >= self.liabilities
+    }
+}
+
+/// Scans the UTXO set for the outputs held by the zkBitcoin address, and reconciles them with the indexed zkapps.
+pub async fn prove_reserves(ctx: &RpcCtx, indexer: &Arc<Indexer>) -> Result<ReservesReport> {
+    let address = taproot_addr_from(&protocol_config().zkbitcoin_pubkey)?.to_string();
+    let (index_height, zkapps) = indexer
+        .query(|indexer| {
+            let index_height = indexer.tip()?.map(|(height, _)| height);
+            Ok((index_height, indexer.list_zkapps()?))
+        })
+        .await?;
+    let scan = scan_txout_set(ctx, &address).await?;
+    let height = scan
+        .height
+        .context("the node didn't say at which height it scanned")?;
+
+    let utxos = scan
+        .unspents
+        .into_iter()
+        .map(|utxo| {
+            (
+                OutPoint::new(utxo.txid, utxo.vout),
+                utxo.amount,
+                utxo.height,
+            )
+        })
+        .collect();
+    let mut report = ReservesReport::reconcile(address, height, utxos, zkapps);
+    report.block_hash = scan.best_block_hash;
+    report.index_height = index_height;
+    Ok(report)
+}
+
+//
+// Attestation
+//
+
+/// A reserves report, signed by the operator.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    /// The report.
+    pub report: ReservesReport,
+
+    /// When the report was signed (UNIX timestamp in seconds).
+    pub attested_at: u64,
+
+    /// The hex-encoded (x-only) public key of the operator.
+    pub signer: String,
+
+    /// The hex-encoded Schnorr signature of the report.
+    pub signature: String,
+}
+
+impl Attestation {
+    fn digest(report: &ReservesReport, attested_at: u64) -> Result<[u8; 32]> {
+        let mut engine = sha256::Hash::engine();
+        engine.input(ATTESTATION_DOMAIN);
+        engine.input(&attested_at.to_be_bytes());
+        engine.input(serde_json::to_string(report)?.as_bytes());
+        Ok(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+
+    /// Signs a report.
+    pub fn sign(report: ReservesReport, attested_at: u64, keypair: &Keypair) -> Result<Self> {
+        let digest = Self::digest(&report, attested_at)?;
+        let signature = Secp256k1::signing_only()
+            .sign_schnorr_no_aux_rand(&Message::from_digest(digest), keypair);
+        Ok(Self {
+            report,
+            attested_at,
+            signer: hex::encode(keypair.x_only_public_key().0.serialize()),
+            signature: hex::encode(signature.as_ref()),
+        })
+    }
+
+    /// Checks the signature of the report (by `pubkey`, if given, which should be the one published by the operator).
+    pub fn verify(&self, pubkey: Option<&XOnlyPublicKey>) -> Result<()> {
+        let signer = XOnlyPublicKey::from_slice(&hex::decode(&self.signer)?)
+            .context("the attestation has an invalid signer")?;
+        if let Some(pubkey) = pubkey {
+            ensure!(
+                &signer == pubkey,
+                "the attestation is signed by another key ({signer})"
+            );
+        }
+        let signature = schnorr::Signature::from_slice(&hex::decode(&self.signature)?)
+            .context("the attestation has an invalid signature")?;
+        let digest = Self::digest(&self.report, self.attested_at)?;
+        Secp256k1::verification_only()
+            .verify_schnorr(&signature, &Message::from_digest(digest), &signer)
+            .context("the attestation has an incorrect signature")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::Txid;
+    use secp256k1::SecretKey;
+
+    use super::*;
+
+    fn zkapp(i: u8, sats: u64) -> Zkapp {
+        Zkapp {
+            txid: Txid::from_byte_array([i; 32]),
+            vout: 0,
+            vk_hash: hex::encode([i; 32]),
+            locked_value: Amount::from_sat(sats),
+            state: None,
+            metadata_hash: None,
+            deployer: None,
+            height: i.into(),
+        }
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let zkapps = vec![zkapp(1, 1000), zkapp(2, 2000)];
+        let utxo = |zkapp: &Zkapp| (zkapp.outpoint(), zkapp.locked_value, zkapp.height);
+        let stray = (
+            OutPoint::new(Txid::from_byte_array([9; 32]), 1),
+            Amount::from_sat(500),
+            9,
+        );
+
+        let report = ReservesReport::reconcile(
+            "address".to_string(),
+            10,
+            vec![utxo(&zkapps[0]), utxo(&zkapps[1]), stray],
+            zkapps.clone(),
+        );
+        assert!(report.is_balanced());
+        assert_eq!(report.reserves, Amount::from_sat(3500));
+        assert_eq!(report.liabilities, Amount::from_sat(3000));
+        assert_eq!(report.unallocated, Amount::from_sat(500));
+        assert_eq!(report.utxos[0].vk_hash, Some(zkapps[0].vk_hash.clone()));
+
+        // a zkapp whose funds are gone isn't backed, whatever else the address holds
+        let report = ReservesReport::reconcile(
+            "address".to_string(),
+            10,
+            vec![utxo(&zkapps[0]), stray],
+            zkapps.clone(),
+        );
+        assert!(!report.is_balanced());
+        assert_eq!(report.missing, vec![zkapps[1].clone()]);
+
+        // the attestation can't be altered
+        let keypair =
+            Keypair::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[1; 32]).unwrap());
+        let mut attestation = Attestation::sign(report, 1_700_000_000, &keypair).unwrap();
+        attestation
+            .verify(Some(&keypair.x_only_public_key().0))
+            .unwrap();
+        attestation.report.missing.clear();
+        assert!(attestation.verify(None).is_err());
+    }
+}
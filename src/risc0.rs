@@ -0,0 +1,55 @@
+//! RISC Zero programs as zkapps, to write the logic of a zkapp in Rust instead of as a circuit.
+//!
+//! A zkapp can be a RISC Zero guest program, referred to as `risc0:<path to its ELF>` wherever the path to a circuit is expected.
+//! Its image ID is what gets committed on-chain (in place of the hash of a verifier key).
+//! The program reads its proof inputs (a `HashMap<String, Vec<String>>`, with the public inputs zkBitcoin fills in) with `env::read()`,
+//! and commits to its full public inputs (a `Vec<String>`, in the order of circom zkapps) with `env::commit()`.
+//! The committee verifies the receipt of its execution against the image ID,
+//! and checks that its journal is the public inputs it expects.
+//! Proving and verifying the programs needs the `risc0` feature.
+
+use std::path::{Path, PathBuf};
+
+/// How RISC Zero programs are referred to, instead of the path of a circuit (followed by the path of their ELF).
+pub const PATH_PREFIX: &str = "risc0:";
+
+/// Returns the path of the ELF of the RISC Zero program `path` refers to (as `risc0:<path>`), if it refers to one.
+/// (References must be resolved with [crate::proof_system::resolve], not joined to a directory like the paths of circuits.)
+pub fn elf_path(path: &Path) -> Option<PathBuf> {
+    let elf = path.to_str()?.strip_prefix(PATH_PREFIX)?;
+    Some(PathBuf::from(elf))
+}
+
+#[cfg(feature = "risc0")]
+mod backend;
+#[cfg(feature = "risc0")]
+pub use backend::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plonk::{self, ProofSystemKind, RiscZeroVerifierKey};
+
+    #[test]
+    fn test_verifier_key() {
+        assert_eq!(
+            elf_path(Path::new("risc0:target/zkapp")),
+            Some(PathBuf::from("target/zkapp"))
+        );
+        assert_eq!(elf_path(Path::new("halo2:counter")), None);
+        // circuits in directories whose name contains the prefix aren't programs
+        assert_eq!(
+            elf_path(&Path::new("/home/bob").join("risc0:target/zkapp")),
+            None
+        );
+
+        // the image ID is committed as is
+        let vk = plonk::VerifierKey::RiscZero(RiscZeroVerifierKey {
+            protocol: ProofSystemKind::RiscZero.name().to_string(),
+            image_id: hex::encode([7; 32]),
+        });
+        assert_eq!(vk.hash(), [7; 32]);
+        assert_eq!(vk.n_public(), None);
+        assert!(vk.is_consistent());
+    }
+}
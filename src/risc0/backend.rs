@@ -0,0 +1,77 @@
+//! How RISC Zero programs are proven and verified (with the `risc0` feature).
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{ensure, Context, Result};
+use log::info;
+use risc0_zkvm::{compute_image_id, default_prover, sha::Digest, ExecutorEnv, Receipt};
+
+use super::elf_path;
+use crate::plonk::{self, ProofSystemKind, RiscZeroProof, RiscZeroVerifierKey};
+
+fn read_elf(path: &Path) -> Result<Vec<u8>> {
+    let elf_path = elf_path(path).context("not a RISC Zero program")?;
+    std::fs::read(&elf_path).with_context(|| format!("couldn't read {}", elf_path.display()))
+}
+
+/// Returns the verifier key of a RISC Zero program (its image ID).
+pub fn verifier_key(path: &Path) -> Result<plonk::VerifierKey> {
+    let elf = read_elf(path)?;
+    let image_id =
+        compute_image_id(&elf).context("couldn't compute the image ID of the program")?;
+    Ok(plonk::VerifierKey::RiscZero(RiscZeroVerifierKey {
+        protocol: ProofSystemKind::RiscZero.name().to_string(),
+        image_id: hex::encode(image_id.as_bytes()),
+    }))
+}
+
+/// Runs a RISC Zero program on `proof_inputs` and proves its execution,
+/// and returns the receipt, the public inputs it committed to, and its verifier key.
+pub fn prove(
+    path: &Path,
+    proof_inputs: &HashMap<String, Vec<String>>,
+) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
+    let elf = read_elf(path)?;
+    let vk = verifier_key(path)?;
+
+    let env = ExecutorEnv::builder()
+        .write(proof_inputs)
+        .and_then(|builder| builder.build())
+        .context("couldn't pass the proof inputs to the program")?;
+    let receipt = default_prover()
+        .prove(env, &elf)
+        .context("couldn't prove the execution of the program")?
+        .receipt;
+    info!("- proved {}", path.display());
+
+    let public_inputs: Vec<String> = receipt
+        .journal
+        .decode()
+        .context("the program must commit to its public inputs (as a list of field elements)")?;
+    let proof = plonk::Proof::RiscZero(RiscZeroProof {
+        protocol: ProofSystemKind::RiscZero.name().to_string(),
+        receipt: hex::encode(bincode::serialize(&receipt)?),
+    });
+    Ok((proof, plonk::PublicInputs(public_inputs), vk))
+}
+
+/// Verifies the receipt of a RISC Zero program, and that it committed to `public_inputs`.
+pub fn verify_receipt(
+    vk: &RiscZeroVerifierKey,
+    public_inputs: &[String],
+    proof: &RiscZeroProof,
+) -> Result<()> {
+    let image_id = vk.image_id().context("malformed image ID")?;
+    let receipt = hex::decode(&proof.receipt).context("malformed receipt")?;
+    let receipt: Receipt = bincode::deserialize(&receipt).context("malformed receipt")?;
+    receipt
+        .verify(Digest::from(image_id))
+        .context("failed to verify proof")?;
+
+    let journal: Vec<String> = receipt.journal.decode().context("malformed journal")?;
+    ensure!(
+        journal == public_inputs,
+        "the program committed to the public inputs {journal:?} instead of {public_inputs:?}"
+    );
+    Ok(())
+}
@@ -0,0 +1,201 @@
+//! Resource limits for the subprocesses run by [crate::snarkjs] (circom, node, and snarkjs).
+//!
+//! The orchestrator runs snarkjs on what users send it (verifier keys, proofs, public inputs),
+//! so a malicious request shouldn't be able to take down the machine it runs on.
+//! Each subprocess runs in its own working directory (which is also its `HOME` and `TMPDIR`), with a cleared environment,
+//! and can be given caps on its CPU time and memory (enforced by the kernel with `ulimit`) and on its wall-clock time
+//! (after which it's killed).
+
+use std::{
+    io::Read,
+    path::Path,
+    process::{Command, Output, Stdio},
+    sync::OnceLock,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// How often a subprocess with a wall-clock cap is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// The limits of subprocesses (none by default).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxConfig {
+    /// The CPU time (in seconds) a subprocess can use.
+    pub cpu_secs: Option<u64>,
+
+    /// The memory (in MiB) a subprocess can allocate.
+    pub memory_mb: Option<u64>,
+
+    /// How long (in seconds) a subprocess can run.
+    pub timeout_secs: Option<u64>,
+}
+
+static SANDBOX: OnceLock<SandboxConfig> = OnceLock::new();
+
+/// Sets the limits of all the subprocesses run from now on.
+/// This must be called before any is run.
+pub fn set_sandbox(config: SandboxConfig) -> Result<()> {
+    ensure!(
+        cfg!(unix) || (config.cpu_secs.is_none() && config.memory_mb.is_none()),
+        "CPU time and memory caps are only supported on unix"
+    );
+    SANDBOX
+        .set(config)
+        .map_err(|_| anyhow!("the sandbox was already set"))
+}
+
+/// Returns the limits of subprocesses.
+pub fn sandbox() -> &'static SandboxConfig {
+    SANDBOX.get_or_init(SandboxConfig::default)
+}
+
+impl SandboxConfig {
+    /// Returns a command running `program` in `dir`, within the limits.
+    pub fn command(&self, program: &str, dir: impl AsRef<Path>) -> Command {
+        let dir = dir.as_ref();
+        let mut limits = vec![];
+        if let Some(cpu_secs) = self.cpu_secs {
+            limits.push(format!("ulimit -t {cpu_secs}"));
+        }
+        if let Some(memory_mb) = self.memory_mb {
+            // the data segment rather than the address space, which node reserves a lot of without using it
+            limits.push(format!("ulimit -d {}", memory_mb * 1024));
+        }
+
+        let mut command = if limits.is_empty() {
+            Command::new(program)
+        } else {
+            let mut command = Command::new("sh");
+            command
+                .arg("-c")
+                .arg(format!("{} && exec \"$0\" \"$@\"", limits.join(" && ")))
+                .arg(program);
+            command
+        };
+        command
+            .current_dir(dir)
+            .env_clear()
+            .env("HOME", dir)
+            .env("TMPDIR", dir);
+        if let Some(path) = std::env::var_os("PATH") {
+            command.env("PATH", path);
+        }
+        if let Some(memory_mb) = self.memory_mb {
+            // so that node fails with an error instead of being killed
+            command.env("NODE_OPTIONS", format!("--max-old-space-size={memory_mb}"));
+        }
+        command
+    }
+
+    /// Runs a command (see [SandboxConfig::command]) to completion, or until it runs out of time,
+    /// and returns its output.
+    pub fn output(&self, command: &mut Command) -> Result<Output> {
+        let program = command.get_program().to_string_lossy().to_string();
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("couldn't execute {program}"))?;
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+
+        let status = match self.timeout_secs {
+            None => child.wait()?,
+            Some(timeout_secs) => {
+                let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+                loop {
+                    if let Some(status) = child.try_wait()? {
+                        break status;
+                    }
+                    if Instant::now() >= deadline {
+                        // the process is gone either way
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        bail!("{program} didn't finish within {timeout_secs}s, and was killed");
+                    }
+                    std::thread::sleep(POLL_INTERVAL);
+                }
+            }
+        };
+
+        let joined = |reader: JoinHandle<Vec<u8>>| {
+            reader
+                .join()
+                .map_err(|_| anyhow!("couldn't read the output of {program}"))
+        };
+        Ok(Output {
+            status,
+            stdout: joined(stdout)?,
+            stderr: joined(stderr)?,
+        })
+    }
+}
+
+/// Reads a pipe until it's closed, so that a process writing a lot doesn't block on it.
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut read = vec![];
+        if let Some(mut pipe) = pipe {
+            // a broken pipe only truncates the output
+            let _ = pipe.read_to_end(&mut read);
+        }
+        read
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_sandbox() {
+        let dir = TempDir::new("zkbitcoin_sandbox").unwrap();
+
+        // without limits, commands run as usual (in their own directory)
+        let unlimited = SandboxConfig::default();
+        let output = unlimited
+            .output(
+                unlimited
+                    .command("sh", &dir)
+                    .args(["-c", "pwd && echo $HOME"]),
+            )
+            .unwrap();
+        assert!(output.status.success());
+        let canonical = dir.path().canonicalize().unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout.lines().count(), 2);
+        assert!(stdout
+            .lines()
+            .all(|line| Path::new(line).canonicalize().unwrap() == canonical));
+
+        // commands running for too long are killed
+        let timeout = SandboxConfig {
+            timeout_secs: Some(1),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let err = timeout
+            .output(timeout.command("sleep", &dir).arg("10"))
+            .unwrap_err();
+        assert!(err.to_string().contains("killed"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // and so are commands using too much CPU
+        let cpu = SandboxConfig {
+            cpu_secs: Some(1),
+            ..Default::default()
+        };
+        let output = cpu
+            .output(cpu.command("sh", &dir).args(["-c", "while :; do :; done"]))
+            .unwrap();
+        assert!(!output.status.success());
+    }
+}
@@ -0,0 +1,165 @@
+//! Scaffolding of zkapp projects (see `zkbtc new`), laid out with the public inputs zkBitcoin expects.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use clap::ValueEnum;
+
+/// The kinds of zkapps a project can be created for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ZkappKind {
+    /// A single-use zkapp, whose only public input is the txid spending it.
+    #[default]
+    Stateless,
+
+    /// A zkapp with a state, updated every time it's used.
+    Stateful,
+}
+
+impl ZkappKind {
+    fn circuit(self) -> &'static str {
+        match self {
+            ZkappKind::Stateless => include_str!("scaffold/stateless.circom"),
+            ZkappKind::Stateful => include_str!("scaffold/stateful.circom"),
+        }
+    }
+
+    /// The inputs passed to `zkbtc use-zkapp`.
+    fn proof_inputs(self) -> serde_json::Value {
+        match self {
+            ZkappKind::Stateless => serde_json::json!({ "secret": ["7"] }),
+            ZkappKind::Stateful => {
+                serde_json::json!({ "amount_in": ["1000"], "amount_out": ["0"] })
+            }
+        }
+    }
+
+    /// All the inputs of the circuit, including the ones filled in by `zkbtc use-zkapp`.
+    fn test_inputs(self) -> serde_json::Value {
+        match self {
+            ZkappKind::Stateless => serde_json::json!({ "truncated_txid": "0", "secret": "7" }),
+            ZkappKind::Stateful => serde_json::json!({
+                "prev_state": "1",
+                "truncated_txid": "0",
+                "amount_out": "0",
+                "amount_in": "1000",
+            }),
+        }
+    }
+}
+
+/// The files of a new project named `name`, with their content.
+pub fn project_files(name: &str, kind: ZkappKind) -> Result<Vec<(&'static str, String)>> {
+    let (kind_name, initial_state) = match kind {
+        ZkappKind::Stateless => ("stateless", ""),
+        ZkappKind::Stateful => ("stateful", " --initial-state 1"),
+    };
+    let readme = include_str!("scaffold/README.md")
+        .replace("{name}", name)
+        .replace("{kind}", kind_name)
+        .replace("{initial_state}", initial_state);
+
+    Ok(vec![
+        ("circuit.circom", kind.circuit().to_string()),
+        (
+            "proof_inputs.json",
+            serde_json::to_string_pretty(&kind.proof_inputs())? + "\n",
+        ),
+        (
+            "test_inputs.json",
+            serde_json::to_string_pretty(&kind.test_inputs())? + "\n",
+        ),
+        ("test.sh", include_str!("scaffold/test.sh").to_string()),
+        ("README.md", readme),
+    ])
+}
+
+/// Creates a new project in `dir` (which must not exist, or be empty), and returns the files created.
+pub fn create_project(dir: &Path, kind: ZkappKind) -> Result<Vec<PathBuf>> {
+    if dir.exists() {
+        let mut entries =
+            std::fs::read_dir(dir).with_context(|| format!("couldn't read {}", dir.display()))?;
+        ensure!(
+            entries.next().is_none(),
+            "{} already exists and is not empty",
+            dir.display()
+        );
+    }
+    std::fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "zkapp".to_string());
+    let mut created = vec![];
+    for (filename, content) in project_files(&name, kind)? {
+        let path = dir.join(filename);
+        std::fs::write(&path, content)
+            .with_context(|| format!("couldn't write {}", path.display()))?;
+        created.push(path);
+    }
+
+    // the test script is meant to be run directly
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let script = dir.join("test.sh");
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("couldn't make {} executable", script.display()))?;
+    }
+
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::constants::STATEFUL_ZKAPP_PUBLIC_INPUT_LEN;
+
+    use super::*;
+
+    /// Returns the public inputs listed by the `main` component of a circuit.
+    fn public_inputs(circuit: &str) -> Vec<&str> {
+        let line = circuit
+            .lines()
+            .find(|line| line.starts_with("component main"))
+            .unwrap();
+        let list = line.split('[').nth(1).unwrap().split(']').next().unwrap();
+        list.split(',').map(str::trim).collect()
+    }
+
+    #[test]
+    fn test_public_input_layout() {
+        assert_eq!(
+            public_inputs(ZkappKind::Stateless.circuit()),
+            vec!["truncated_txid"]
+        );
+
+        // the new state (the only output) is also a public input
+        let stateful = public_inputs(ZkappKind::Stateful.circuit());
+        assert_eq!(
+            stateful,
+            vec!["prev_state", "truncated_txid", "amount_out", "amount_in"]
+        );
+        assert_eq!(stateful.len() + 1, STATEFUL_ZKAPP_PUBLIC_INPUT_LEN);
+    }
+
+    #[test]
+    fn test_create_project() {
+        let tmp_dir = tempdir::TempDir::new("zkbitcoin_new").unwrap();
+        let dir = tmp_dir.path().join("my-zkapp");
+        let created = create_project(&dir, ZkappKind::Stateful).unwrap();
+        assert_eq!(created.len(), 5);
+
+        let readme = std::fs::read_to_string(dir.join("README.md")).unwrap();
+        assert!(readme.starts_with("# my-zkapp"));
+        assert!(readme.contains("--initial-state 1"));
+
+        // the proof inputs are the ones `zkbtc use-zkapp` expects
+        let proof_inputs = std::fs::read_to_string(dir.join("proof_inputs.json")).unwrap();
+        let _: std::collections::HashMap<String, Vec<String>> =
+            serde_json::from_str(&proof_inputs).unwrap();
+
+        // projects are not created over existing ones
+        assert!(create_project(&dir, ZkappKind::Stateless).is_err());
+    }
+}
@@ -0,0 +1,25 @@
+pragma circom 2.1.3;
+
+// A stateful zkapp: every use of it proves a transition from its current state to a new one,
+// along with the amounts deposited into it and withdrawn from it.
+template Main() {
+    // The new state of the zkapp, which must be the only output.
+    signal output new_state;
+
+    // The current state of the zkapp, filled in by `zkbtc use-zkapp`.
+    signal input prev_state;
+
+    // The (truncated) txid of the transaction spending the zkapp, filled in by `zkbtc use-zkapp`.
+    // It binds the proof to that transaction, so that the proof can't be reused elsewhere.
+    signal input truncated_txid;
+
+    // The amounts (in satoshis) withdrawn from and deposited into the zkapp, passed with `--proof-inputs`.
+    signal input amount_out;
+    signal input amount_in;
+
+    // TODO: replace with your own logic (here: the state is the balance of the zkapp).
+    new_state <== prev_state + amount_in - amount_out;
+}
+
+// The public inputs of a stateful zkapp must be exactly these, in this order.
+component main{public [prev_state, truncated_txid, amount_out, amount_in]} = Main();
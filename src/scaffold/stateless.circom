@@ -0,0 +1,17 @@
+pragma circom 2.1.3;
+
+// A stateless zkapp: the bitcoin it locks can be spent (once) by anyone who can prove this circuit.
+template Main() {
+    // The (truncated) txid of the transaction spending the zkapp, filled in by `zkbtc use-zkapp`.
+    // It binds the proof to that transaction, so that the proof can't be reused to spend the zkapp elsewhere.
+    signal input truncated_txid;
+
+    // Your private inputs, passed with `--proof-inputs`.
+    signal input secret;
+
+    // TODO: replace with your own logic (here: knowing a number whose square is 49).
+    secret * secret === 49;
+}
+
+// The txid must be the one and only public input of a stateless zkapp.
+component main{public [truncated_txid]} = Main();
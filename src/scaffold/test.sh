@@ -0,0 +1,12 @@
+#!/usr/bin/env sh
+# Compiles the circuit, and checks that it is satisfied by test_inputs.json
+# (which also contains the inputs `zkbtc use-zkapp` fills in), without deploying anything.
+set -e
+cd "$(dirname "$0")"
+
+mkdir -p build
+circom circuit.circom --r1cs --wasm -o build
+node build/circuit_js/generate_witness.js build/circuit_js/circuit.wasm test_inputs.json build/witness.wtns
+snarkjs wtns check build/circuit.r1cs build/witness.wtns
+
+echo "the circuit is satisfied by test_inputs.json"
@@ -0,0 +1,577 @@
+//! Discovery of the zkapps deployed on chain.
+//!
+//! All zkapps are locked to the same taproot address (the one of the zkBitcoin committee),
+//! so we scan the UTXO set for that address and decode the metadata of every transaction found.
+//!
+//! A stateful zkapp moves to a new output every time it is used.
+//! Bitcoin Core doesn't index which transaction spends an output,
+//! so to follow a zkapp we look for its spends in every block since its deployment.
+
+use anyhow::{Context, Result};
+use bitcoin::{Amount, OutPoint, Transaction, Txid};
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bob_request::{extract_smart_contract_from_tx, SmartContract},
+    config::protocol_config,
+    json_rpc_stuff::{
+        get_block, get_block_count, get_block_hash, get_block_height, get_raw_transaction,
+        get_raw_transaction_block, get_wallet_transaction_block, is_unspent, scan_txout_set,
+        RpcCtx,
+    },
+    taproot_addr_from,
+    zkapp_data::{load_metadata, ZkappMetadata, METADATA_HASH_LEN},
+};
+
+/// A zkapp that is currently unspent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Zkapp {
+    /// The transaction that deployed (or last updated) the zkapp.
+    pub txid: Txid,
+
+    /// The output of the transaction holding the zkapp's funds.
+    pub vout: u32,
+
+    /// The hex-encoded hash of the zkapp's verifier key.
+    pub vk_hash: String,
+
+    /// The funds locked in the zkapp.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub locked_value: Amount,
+
+    /// The state of the zkapp, if it is stateful.
+    pub state: Option<String>,
+
+    /// The hex-encoded hash of the zkapp's metadata, if it was deployed with some (see [crate::zkapp_data::ZkappMetadata]).
+    #[serde(default)]
+    pub metadata_hash: Option<String>,
+
+    /// The hex-encoded key of the zkapp's deployer, if it was deployed with one (see [crate::zkapp_data::ZkappData::deployer]).
+    #[serde(default)]
+    pub deployer: Option<String>,
+
+    /// The height of the block that contains the transaction.
+    pub height: u64,
+}
+
+impl Zkapp {
+    pub(crate) fn new(smart_contract: SmartContract, height: u64) -> Self {
+        Self {
+            txid: smart_contract.txid,
+            vout: smart_contract.vout_of_zkbitcoin_utxo,
+            vk_hash: hex::encode(smart_contract.vk_hash),
+            locked_value: smart_contract.locked_value,
+            state: smart_contract.state,
+            metadata_hash: smart_contract.metadata_hash.map(hex::encode),
+            deployer: smart_contract.deployer.map(|deployer| deployer.to_string()),
+            height,
+        }
+    }
+
+    /// Returns true if the zkapp is stateful.
+    pub fn is_stateful(&self) -> bool {
+        self.state.is_some()
+    }
+
+    /// The output holding the zkapp's funds.
+    pub fn outpoint(&self) -> OutPoint {
+        OutPoint::new(self.txid, self.vout)
+    }
+
+    /// The metadata of the zkapp, if it was deployed with some and it is known (see [crate::zkapp_data::save_metadata]).
+    pub fn metadata(&self) -> Result<Option<ZkappMetadata>> {
+        let Some(metadata_hash) = &self.metadata_hash else {
+            return Ok(None);
+        };
+        let metadata_hash: [u8; METADATA_HASH_LEN] = hex::decode(metadata_hash)?
+            .try_into()
+            .ok()
+            .with_context(|| format!("invalid metadata hash {metadata_hash}"))?;
+        load_metadata(&metadata_hash)
+    }
+}
+
+/// A transaction that spent a zkapp.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZkappSpend {
+    /// The transaction ID of the spend.
+    pub txid: Txid,
+
+    /// The height of the block that contains the spend.
+    pub height: u64,
+
+    /// The updated zkapp created by the spend (for stateful zkapps).
+    pub zkapp: Option<Zkapp>,
+}
+
+/// A zkapp and all the (confirmed) transactions that spent it since its deployment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZkappChain {
+    /// The zkapp, as deployed.
+    pub deployment: Zkapp,
+
+    /// The spends of the zkapp, in order.
+    pub spends: Vec<ZkappSpend>,
+}
+
+/// A change of a zkapp's state (or the spend of its funds).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateTransition {
+    /// The transaction ID of the spend.
+    pub txid: Txid,
+
+    /// The height of the block that contains the spend.
+    pub height: u64,
+
+    /// The state before the spend.
+    pub old_state: Option<String>,
+
+    /// The state after the spend (if the zkapp lives on).
+    pub new_state: Option<String>,
+
+    /// The amount deposited in the zkapp.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub amount_in: Amount,
+
+    /// The amount withdrawn from the zkapp.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub amount_out: Amount,
+}
+
+impl ZkappChain {
+    /// Returns the current version of the zkapp, or `None` if the zkapp was spent for good.
+    pub fn latest(&self) -> Option<&Zkapp> {
+        match self.spends.last() {
+            Some(spend) => spend.zkapp.as_ref(),
+            None => Some(&self.deployment),
+        }
+    }
+
+    /// Returns the state transitions of the zkapp, in order.
+    /// Only the change in locked value is visible on chain,
+    /// so a spend that both deposits and withdraws funds is reported with its net amount.
+    pub fn transitions(&self) -> Vec<StateTransition> {
+        let mut previous = &self.deployment;
+        let mut transitions = vec![];
+        for spend in &self.spends {
+            let new_value = spend
+                .zkapp
+                .as_ref()
+                .map_or(Amount::ZERO, |zkapp| zkapp.locked_value);
+            transitions.push(StateTransition {
+                txid: spend.txid,
+                height: spend.height,
+                old_state: previous.state.clone(),
+                new_state: spend.zkapp.as_ref().and_then(|zkapp| zkapp.state.clone()),
+                amount_in: new_value
+                    .checked_sub(previous.locked_value)
+                    .unwrap_or(Amount::ZERO),
+                amount_out: previous
+                    .locked_value
+                    .checked_sub(new_value)
+                    .unwrap_or(Amount::ZERO),
+            });
+            match &spend.zkapp {
+                Some(zkapp) => previous = zkapp,
+                None => break,
+            }
+        }
+        transitions
+    }
+}
+
+/// Scans the UTXO set for the zkapps that are currently unspent.
+/// Outputs sent to the zkBitcoin address that are not zkapps (e.g. without metadata) are skipped.
+pub async fn list_zkapps(ctx: &RpcCtx) -> Result<Vec<Zkapp>> {
+    let zkbitcoin_address = taproot_addr_from(&protocol_config().zkbitcoin_pubkey)?;
+    let scan = scan_txout_set(ctx, &zkbitcoin_address.to_string()).await?;
+    debug!(
+        "- found {} outputs to the zkBitcoin address",
+        scan.unspents.len()
+    );
+
+    let mut zkapps = vec![];
+    for utxo in scan.unspents {
+        // the node might not have a transaction index, so we tell it where to look
+        let block_hash = get_block_hash(ctx, utxo.height).await?;
+        let tx = get_raw_transaction(ctx, utxo.txid, Some(block_hash))
+            .await
+            .with_context(|| format!("couldn't fetch transaction {}", utxo.txid))?;
+
+        zkapps.extend(zkapp_at(&tx, utxo.vout, utxo.height));
+    }
+
+    zkapps.sort_by_key(|zkapp| zkapp.height);
+    Ok(zkapps)
+}
+
+/// Returns the zkapp held by output `vout` of `tx` (mined at `height`),
+/// or `None` if the output was sent to the zkBitcoin address without being a zkapp.
+fn zkapp_at(tx: &Transaction, vout: u32, height: u64) -> Option<Zkapp> {
+    match extract_smart_contract_from_tx(tx) {
+        Ok(smart_contract) if smart_contract.vout_of_zkbitcoin_utxo == vout => {
+            Some(Zkapp::new(smart_contract, height))
+        }
+        Ok(_) => {
+            debug!("- skipping {}:{vout} (not the zkapp output)", tx.txid());
+            None
+        }
+        Err(err) => {
+            debug!("- skipping {}:{vout} ({err})", tx.txid());
+            None
+        }
+    }
+}
+
+/// Which of the listed zkapps to keep, and which page of them to return.
+#[derive(Debug, Clone, Default)]
+pub struct ZkappFilter {
+    /// Only keep the zkapps with this (hex-encoded) verifier key hash.
+    pub vk_hash: Option<String>,
+
+    /// Only keep the stateful zkapps (if `true`), or the stateless ones (if `false`).
+    pub stateful: Option<bool>,
+
+    /// How many of the matching zkapps to skip.
+    pub offset: usize,
+
+    /// The most zkapps to return.
+    pub limit: Option<usize>,
+}
+
+impl ZkappFilter {
+    /// Returns the page of `zkapps` that match, in the order given.
+    pub fn apply(&self, zkapps: Vec<Zkapp>) -> Vec<Zkapp> {
+        zkapps
+            .into_iter()
+            .filter(|zkapp| match &self.vk_hash {
+                Some(vk_hash) => zkapp.vk_hash.eq_ignore_ascii_case(vk_hash),
+                None => true,
+            })
+            .filter(|zkapp| match self.stateful {
+                Some(stateful) => zkapp.is_stateful() == stateful,
+                None => true,
+            })
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+/// Fetches a confirmed transaction and the height of its block.
+/// Transactions that are not in the wallet can only be found if the node maintains a transaction index.
+/// The height is the one of the block the node says the transaction is in
+/// (not derived from its confirmations and the tip, which could move in between).
+async fn locate_transaction(ctx: &RpcCtx, txid: Txid) -> Result<(Transaction, u64)> {
+    let (tx, block_hash) =
+        match get_raw_transaction_block(ctx, txid).await {
+            Ok(found) => found,
+            Err(err) => {
+                debug!("- getrawtransaction failed ({err}), looking in the wallet");
+                get_wallet_transaction_block(ctx, txid).await.with_context(|| {
+                format!("couldn't find transaction {txid} (is the node running with -txindex=1?)")
+            })?
+            }
+        };
+    let block_hash =
+        block_hash.with_context(|| format!("transaction {txid} is not confirmed yet"))?;
+
+    let height = get_block_height(ctx, block_hash)
+        .await
+        .with_context(|| format!("couldn't find the block of transaction {txid}"))?;
+    Ok((tx, height))
+}
+
+/// Looks for the transaction spending an output, in the blocks from `from_height` to the tip.
+async fn find_spend(
+    ctx: &RpcCtx,
+    outpoint: OutPoint,
+    from_height: u64,
+) -> Result<Option<(Transaction, u64)>> {
+    let tip = get_block_count(ctx).await?;
+    for height in from_height..=tip {
+        let block = get_block(ctx, get_block_hash(ctx, height).await?).await?;
+        let spend = block.txdata.into_iter().find(|tx| {
+            tx.input
+                .iter()
+                .any(|input| input.previous_output == outpoint)
+        });
+        if let Some(spend) = spend {
+            return Ok(Some((spend, height)));
+        }
+    }
+    Ok(None)
+}
+
+/// Follows a zkapp from its deployment transaction, through all its (confirmed) spends.
+pub async fn follow_zkapp(ctx: &RpcCtx, txid: Txid) -> Result<ZkappChain> {
+    let (tx, height) = locate_transaction(ctx, txid).await?;
+    let smart_contract =
+        extract_smart_contract_from_tx(&tx).context("the transaction is not a zkapp")?;
+    let deployment = Zkapp::new(smart_contract, height);
+
+    let mut spends = vec![];
+    let mut current = deployment.clone();
+    while !is_unspent(ctx, current.outpoint()).await? {
+        // the output can be spent in the same block it was created in
+        let (spend_tx, spend_height) = find_spend(ctx, current.outpoint(), current.height)
+            .await?
+            .with_context(|| {
+            format!(
+                "couldn't find the transaction spending {}",
+                current.outpoint()
+            )
+        })?;
+        debug!(
+            "- zkapp {} spent by {} at height {spend_height}",
+            current.outpoint(),
+            spend_tx.txid()
+        );
+
+        // a stateful zkapp lives on in a new output with the same verifier key
+        // (or another one, if it's upgraded, see [crate::upgrade])
+        let zkapp = extract_smart_contract_from_tx(&spend_tx)
+            .ok()
+            .map(|smart_contract| Zkapp::new(smart_contract, spend_height))
+            .filter(|zkapp| current.is_stateful() && zkapp.is_stateful());
+        spends.push(ZkappSpend {
+            txid: spend_tx.txid(),
+            height: spend_height,
+            zkapp: zkapp.clone(),
+        });
+
+        match zkapp {
+            Some(zkapp) => current = zkapp,
+            None => break,
+        }
+    }
+
+    Ok(ZkappChain { deployment, spends })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bitcoin::{absolute::LockTime, transaction, TxOut};
+
+    use super::*;
+    use crate::zkapp_data::ZkappData;
+
+    fn zkapp(txid: &str, state: Option<&str>) -> Zkapp {
+        Zkapp {
+            txid: Txid::from_str(txid).unwrap(),
+            vout: 0,
+            vk_hash: "00".repeat(32),
+            locked_value: Amount::from_sat(1000),
+            state: state.map(str::to_string),
+            metadata_hash: None,
+            deployer: None,
+            height: 1,
+        }
+    }
+
+    /// A transaction deploying a zkapp, with its funds in output 1.
+    fn deployment(vk_hash: [u8; 32], state: Option<&str>, sats: u64) -> Transaction {
+        let data = ZkappData::new(vk_hash, state.map(str::to_string));
+        Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: data.to_script().unwrap(),
+                },
+                TxOut {
+                    value: Amount::from_sat(sats),
+                    script_pubkey: data.script_pubkey().unwrap(),
+                },
+            ],
+        }
+    }
+
+    /// The zkapps found in a scan, sorted by height.
+    fn fixtures() -> Vec<Zkapp> {
+        [
+            ([1; 32], None),
+            ([2; 32], Some("1")),
+            ([1; 32], Some("2")),
+            ([2; 32], None),
+            ([1; 32], None),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (vk_hash, state))| {
+            let tx = deployment(vk_hash, state, 1000 * (i as u64 + 1));
+            zkapp_at(&tx, 1, i as u64 + 1).unwrap()
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_zkapp_at() {
+        let tx = deployment([1; 32], Some("1"), 1000);
+        let zkapp = zkapp_at(&tx, 1, 7).unwrap();
+        assert_eq!(zkapp.outpoint(), OutPoint::new(tx.txid(), 1));
+        assert_eq!(zkapp.vk_hash, "01".repeat(32));
+        assert_eq!(zkapp.locked_value, Amount::from_sat(1000));
+        assert_eq!(zkapp.state.as_deref(), Some("1"));
+        assert_eq!(zkapp.height, 7);
+
+        // the OP_RETURN output isn't the zkapp
+        assert_eq!(zkapp_at(&tx, 0, 7), None);
+
+        // nor is an output sent to the zkBitcoin address without metadata
+        let mut not_a_zkapp = tx.clone();
+        not_a_zkapp.output.remove(0);
+        assert_eq!(zkapp_at(&not_a_zkapp, 0, 7), None);
+    }
+
+    #[test]
+    fn test_filter() {
+        let zkapps = fixtures();
+        let heights = |filter: ZkappFilter| -> Vec<u64> {
+            filter
+                .apply(zkapps.clone())
+                .iter()
+                .map(|zkapp| zkapp.height)
+                .collect()
+        };
+
+        assert_eq!(heights(ZkappFilter::default()), vec![1, 2, 3, 4, 5]);
+
+        // by verifier key hash (in any case)
+        let vk_hash = |vk_hash: String| ZkappFilter {
+            vk_hash: Some(vk_hash),
+            ..Default::default()
+        };
+        assert_eq!(heights(vk_hash("01".repeat(32))), vec![1, 3, 5]);
+        assert_eq!(heights(vk_hash("0A".repeat(32))), Vec::<u64>::new());
+
+        // by kind
+        let stateful = |stateful| ZkappFilter {
+            stateful: Some(stateful),
+            ..Default::default()
+        };
+        assert_eq!(heights(stateful(true)), vec![2, 3]);
+        assert_eq!(heights(stateful(false)), vec![1, 4, 5]);
+
+        // both
+        assert_eq!(
+            heights(ZkappFilter {
+                vk_hash: Some("02".repeat(32)),
+                stateful: Some(false),
+                ..Default::default()
+            }),
+            vec![4]
+        );
+    }
+
+    #[test]
+    fn test_pagination() {
+        let zkapps = fixtures();
+        let page = |offset, limit| -> Vec<u64> {
+            ZkappFilter {
+                offset,
+                limit,
+                ..Default::default()
+            }
+            .apply(zkapps.clone())
+            .iter()
+            .map(|zkapp| zkapp.height)
+            .collect()
+        };
+
+        assert_eq!(page(0, Some(2)), vec![1, 2]);
+        assert_eq!(page(2, Some(2)), vec![3, 4]);
+        assert_eq!(page(4, Some(2)), vec![5]);
+        assert_eq!(page(5, Some(2)), Vec::<u64>::new());
+        assert_eq!(page(3, None), vec![4, 5]);
+        assert_eq!(page(0, Some(0)), Vec::<u64>::new());
+
+        // pages are taken from the zkapps that match
+        let filter = ZkappFilter {
+            vk_hash: Some("01".repeat(32)),
+            offset: 1,
+            limit: Some(1),
+            ..Default::default()
+        };
+        let page: Vec<_> = filter.apply(zkapps).iter().map(|z| z.height).collect();
+        assert_eq!(page, vec![3]);
+    }
+
+    #[test]
+    fn test_transitions() {
+        let deployment = zkapp(
+            "e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836",
+            Some("1"),
+        );
+        let mut updated = zkapp(
+            "d5fd8a2c3bf5ee4f3c44b4a10e2c3d4c1a7a8b3c0ae0ba4d3e0a7a56c2b1e0f0",
+            Some("2"),
+        );
+        updated.locked_value = Amount::from_sat(1500);
+        let chain = ZkappChain {
+            deployment,
+            spends: vec![
+                ZkappSpend {
+                    txid: updated.txid,
+                    height: 2,
+                    zkapp: Some(updated.clone()),
+                },
+                ZkappSpend {
+                    txid: updated.txid,
+                    height: 3,
+                    zkapp: None,
+                },
+            ],
+        };
+
+        let transitions = chain.transitions();
+        assert_eq!(transitions.len(), 2);
+
+        // a deposit
+        assert_eq!(transitions[0].old_state.as_deref(), Some("1"));
+        assert_eq!(transitions[0].new_state.as_deref(), Some("2"));
+        assert_eq!(transitions[0].amount_in, Amount::from_sat(500));
+        assert_eq!(transitions[0].amount_out, Amount::ZERO);
+
+        // everything is withdrawn
+        assert_eq!(transitions[1].old_state.as_deref(), Some("2"));
+        assert_eq!(transitions[1].new_state, None);
+        assert_eq!(transitions[1].amount_out, Amount::from_sat(1500));
+    }
+
+    #[test]
+    fn test_latest_zkapp() {
+        let deployment = zkapp(
+            "e793bdd8dfdd9912d971790a5f385ad3f1215dce97e25dbefe5449faba632836",
+            Some("1"),
+        );
+        let mut chain = ZkappChain {
+            deployment: deployment.clone(),
+            spends: vec![],
+        };
+        assert_eq!(chain.latest(), Some(&deployment));
+
+        let updated = zkapp(
+            "d5fd8a2c3bf5ee4f3c44b4a10e2c3d4c1a7a8b3c0ae0ba4d3e0a7a56c2b1e0f0",
+            Some("2"),
+        );
+        chain.spends.push(ZkappSpend {
+            txid: updated.txid,
+            height: 2,
+            zkapp: Some(updated.clone()),
+        });
+        assert_eq!(chain.latest(), Some(&updated));
+
+        chain.spends.push(ZkappSpend {
+            txid: deployment.txid,
+            height: 3,
+            zkapp: None,
+        });
+        assert_eq!(chain.latest(), None);
+    }
+}
@@ -0,0 +1,297 @@
+//! Running committee nodes and orchestrators as long-lived services:
+//! in the background (see [daemonize]), logging to a file that is rotated by size or age (see [RotatingFile]),
+//! or supervised by systemd (see [notify_ready]).
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use log::warn;
+
+/// The flag running a command in the background, which the background process is started without.
+pub const DAEMON_FLAG: &str = "--daemon";
+
+//
+// Daemon
+//
+
+/// Starts this same command again in the background (without [DAEMON_FLAG]), detached from the terminal,
+/// and returns the ID of the background process, which is also written to `pid_file` (if set).
+/// Whatever the background process writes to stderr (e.g. panics) is appended to `<log_file>.stderr`
+/// (see [stderr_file]), which isn't rotated along with the log file.
+pub fn daemonize(log_file: &Path, pid_file: Option<&Path>) -> Result<u32> {
+    let program = std::env::current_exe().context("couldn't find the path of zkbtc")?;
+    let args: Vec<OsString> = std::env::args_os()
+        .skip(1)
+        .filter(|arg| arg != DAEMON_FLAG)
+        .collect();
+    let stderr = open_append(&stderr_file(log_file))?;
+
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(stderr);
+    // in its own process group, so that it doesn't get the signals meant for the terminal's
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let child = command
+        .spawn()
+        .context("couldn't start zkbtc in the background")?;
+
+    let pid = child.id();
+    if let Some(pid_file) = pid_file {
+        fs::write(pid_file, format!("{pid}\n"))
+            .with_context(|| format!("couldn't write {}", pid_file.display()))?;
+    }
+    Ok(pid)
+}
+
+/// The file the stderr of a background process logging to `log_file` goes to (see [daemonize]).
+pub fn stderr_file(log_file: &Path) -> PathBuf {
+    let mut path = log_file.as_os_str().to_owned();
+    path.push(".stderr");
+    path.into()
+}
+
+fn open_append(path: &Path) -> Result<File> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("couldn't open {}", path.display()))
+}
+
+//
+// systemd
+//
+
+/// Tells systemd about the state of the service (e.g. `READY=1`, see `sd_notify(3)`),
+/// if it started this process with `Type=notify`.
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    if let Some(socket_path) = std::env::var_os("NOTIFY_SOCKET") {
+        if let Err(err) = notify_systemd(&socket_path, state) {
+            warn!("couldn't notify systemd: {err}");
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+/// Sends the state of the service to the systemd socket at `socket_path` (`NOTIFY_SOCKET`, which starts with `@`
+/// for an abstract socket).
+#[cfg(unix)]
+fn notify_systemd(socket_path: &OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::{ffi::OsStrExt, net::UnixDatagram};
+
+    let socket = UnixDatagram::unbound()?;
+    match socket_path.as_bytes() {
+        // an abstract socket
+        #[cfg(target_os = "linux")]
+        [b'@', name @ ..] => {
+            use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+            let address = SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &address)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), Path::new(socket_path))?;
+        }
+    }
+    Ok(())
+}
+
+/// How often systemd expects to be pinged (half of `WatchdogSec=`), if it has a watchdog on this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Tells systemd that the service is ready, and pings its watchdog (if it has one) for as long as `healthy` says so.
+/// A service whose runtime or state is stuck stops pinging it, and gets restarted.
+pub fn notify_ready(healthy: impl Fn() -> bool + Send + 'static) {
+    notify("READY=1");
+    if let Some(interval) = watchdog_interval() {
+        tokio::spawn(async move {
+            loop {
+                if healthy() {
+                    notify("WATCHDOG=1");
+                } else {
+                    warn!("- the service is unhealthy, not pinging the systemd watchdog");
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+//
+// Log rotation
+//
+
+/// When a log file is rotated.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rotation {
+    /// Once it's bigger than this (in bytes).
+    pub max_size: Option<u64>,
+
+    /// Once it's been written to for this long.
+    pub max_age: Option<Duration>,
+
+    /// The number of rotated files kept (as `<file>.1`, the most recent, to `<file>.<keep>`).
+    pub keep: usize,
+}
+
+/// A log file, which is moved to `<file>.1` (and the previous ones to `<file>.2`, and so on) when it gets too big or too old.
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    size: u64,
+    opened_at: Instant,
+}
+
+impl RotatingFile {
+    /// Opens a log file to append to.
+    pub fn open(path: impl Into<PathBuf>, rotation: Rotation) -> Result<Self> {
+        let path = path.into();
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            rotation,
+            file,
+            size,
+            opened_at: Instant::now(),
+        })
+    }
+
+    fn rotated(&self, i: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{i}"));
+        path.into()
+    }
+
+    fn should_rotate(&self, len: usize) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let too_big = self
+            .rotation
+            .max_size
+            .is_some_and(|max_size| self.size + len as u64 > max_size);
+        let too_old = self
+            .rotation
+            .max_age
+            .is_some_and(|max_age| self.opened_at.elapsed() >= max_age);
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.rotation.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            // the oldest file is overwritten
+            for i in (1..self.rotation.keep).rev() {
+                let from = self.rotated(i);
+                if from.exists() {
+                    fs::rename(from, self.rotated(i + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.opened_at = Instant::now();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.should_rotate(buf.len()) {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_rotation() {
+        let dir = TempDir::new("zkbitcoin_logs").unwrap();
+        let path = dir.path().join("node.log");
+        let rotation = Rotation {
+            max_size: Some(10),
+            max_age: None,
+            keep: 2,
+        };
+        let mut log = RotatingFile::open(&path, rotation).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            log.write_all(line.as_bytes()).unwrap();
+        }
+        log.flush().unwrap();
+
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(read(&log.rotated(1)), "third\n");
+        assert_eq!(read(&log.rotated(2)), "second\n");
+        assert!(!log.rotated(3).exists());
+        assert_eq!(stderr_file(&path), dir.path().join("node.log.stderr"));
+
+        // logs are appended to after a restart
+        drop(log);
+        let mut log = RotatingFile::open(&path, Rotation::default()).unwrap();
+        log.write_all(b"fifth\n").unwrap();
+        assert_eq!(read(&path), "fourth\nfifth\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_notify() {
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = TempDir::new("zkbitcoin_systemd").unwrap();
+        let socket_path = dir.path().join("notify.sock");
+        let socket = UnixDatagram::bind(&socket_path).unwrap();
+
+        notify_systemd(socket_path.as_os_str(), "READY=1").unwrap();
+
+        let mut buf = [0; 16];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+
+        // a socket that isn't there can't be notified
+        assert!(notify_systemd(dir.path().join("gone.sock").as_os_str(), "READY=1").is_err());
+    }
+}
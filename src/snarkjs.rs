@@ -2,117 +2,266 @@ use std::{
     collections::HashMap,
     fs::File,
     path::{Path, PathBuf},
-    process::Command,
 };
 
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use log::info;
 use tempdir::TempDir;
 
 use crate::{
-    plonk::{self},
-    srs,
+    artifacts,
+    plonk::{self, ProofSystemKind},
+    ptau,
+    sandbox::sandbox,
 };
 
 pub struct CompilationResult {
     pub verifier_key: plonk::VerifierKey,
     pub circuit_r1cs_path: PathBuf,
     pub prover_key_path: PathBuf,
+    /// The directory with the wasm of the circuit, and the script generating its witnesses.
+    pub circuit_js_dir: PathBuf,
 }
 
-/// Compiles a circom circuit to a wasm and r1cs file.
-pub async fn compile(tmp_dir: &TempDir, circom_circuit_path: &Path) -> Result<CompilationResult> {
-    // SRS
-    let srs_path = srs::srs_path().await;
+/// Returns the path of the groth16 prover key of a circuit, next to it (`circuit.zkey` for `circuit.circom`).
+/// Unlike plonk keys, groth16 keys come from a setup using fresh randomness and can't be recreated,
+/// so the key created when a zkapp is deployed has to be kept, and shared with whoever uses the zkapp.
+pub fn groth16_prover_key_path(circom_circuit_path: &Path) -> PathBuf {
+    circom_circuit_path.with_extension("zkey")
+}
 
-    // set up new paths for files that will be created
+/// Returns the proof system a circuit is set up with:
+/// groth16 if it has a groth16 prover key (see [groth16_prover_key_path]), plonk otherwise.
+pub fn proof_system_of(circom_circuit_path: &Path) -> ProofSystemKind {
+    if groth16_prover_key_path(circom_circuit_path).exists() {
+        ProofSystemKind::Groth16
+    } else {
+        ProofSystemKind::Plonk
+    }
+}
+
+/// Compiles a circom circuit to a wasm and r1cs file, and sets up its keys for `proof_system`.
+///
+/// With plonk, the artifacts are reused from the cache if the circuit was already compiled (see [artifacts]),
+/// and are created in `tmp_dir` (then cached) otherwise.
+/// With groth16, the prover key next to the circuit is used (see [groth16_prover_key_path]),
+/// and is created by a new setup if there's none.
+pub async fn compile(
+    tmp_dir: &TempDir,
+    circom_circuit_path: &Path,
+    proof_system: ProofSystemKind,
+) -> Result<CompilationResult> {
+    if proof_system == ProofSystemKind::Groth16 {
+        let prover_key_path = groth16_prover_key_path(circom_circuit_path);
+        compile_groth16_into(tmp_dir, circom_circuit_path, &prover_key_path).await?;
+        return Ok(CompilationResult {
+            prover_key_path,
+            ..compilation_result(tmp_dir.path(), circom_circuit_path)?
+        });
+    }
+
+    let key = if artifacts::enabled() {
+        artifacts::key(circom_circuit_path)
+            .map_err(|err| info!("- not caching the artifacts of the circuit: {err:#}"))
+            .ok()
+    } else {
+        None
+    };
+    if let Some(dir) = key.as_deref().and_then(artifacts::lookup) {
+        info!("- using the compiled circuit cached in {}", dir.display());
+        return compilation_result(&dir, circom_circuit_path);
+    }
+
+    compile_into(tmp_dir, circom_circuit_path).await?;
+    if let Some(key) = key {
+        match artifacts::store(&key, tmp_dir.path()) {
+            Ok(dir) => info!("- compiled circuit cached in {}", dir.display()),
+            Err(err) => info!("- couldn't cache the compiled circuit: {err:#}"),
+        }
+    }
+    compilation_result(tmp_dir.path(), circom_circuit_path)
+}
+
+/// Returns the artifacts of a circuit compiled in `dir`.
+fn compilation_result(dir: &Path, circom_circuit_path: &Path) -> Result<CompilationResult> {
     let circuit_name = circom_circuit_path
         .file_stem()
-        .context("failed to get circuit name from filename")?;
+        .context("failed to get circuit name from filename")?
+        .to_string_lossy();
 
-    let circuit_r1cs_path = tmp_dir
-        .path()
-        .join(format!("{}.r1cs", circuit_name.to_string_lossy()));
+    let verifier_key_path = dir.join("verifier_key.json");
+    let vk_file = File::open(&verifier_key_path)
+        .with_context(|| format!("couldn't open {}", verifier_key_path.display()))?;
+    let verifier_key: plonk::VerifierKey = serde_json::from_reader(vk_file)
+        .with_context(|| format!("couldn't parse {}", verifier_key_path.display()))?;
+
+    Ok(CompilationResult {
+        verifier_key,
+        circuit_r1cs_path: dir.join(format!("{circuit_name}.r1cs")),
+        prover_key_path: dir.join("prover_key.zkey"),
+        circuit_js_dir: dir.join(format!("{circuit_name}_js")),
+    })
+}
+
+/// Compiles a circom circuit in `tmp_dir`, and sets up its plonk keys.
+async fn compile_into(tmp_dir: &TempDir, circom_circuit_path: &Path) -> Result<()> {
+    let circuit_r1cs_path = compile_circuit(tmp_dir, circom_circuit_path)?;
     let prover_key_path = tmp_dir.path().join("prover_key.zkey");
-    let verifier_key_path = tmp_dir.path().join("verifier_key.json");
 
-    // compile to wasm and r1cs
+    // get the powers of tau fitting the circuit
+    let num_constraints = ptau::plonk_constraints(&circuit_r1cs_path)?;
+    let ptau_path = ptau::ptau_path(ptau::power_for(num_constraints)?).await?;
+
+    // create prover key
     {
-        // circom circuit.circom --r1cs --wasm --sym
-        let output = Command::new("circom")
-            .current_dir(tmp_dir)
-            .arg(circom_circuit_path)
-            .arg("--wasm")
-            .arg("--r1cs")
-            .output()
-            .expect("failed to execute process");
+        // snarkjs plonk setup circuit.r1cs phase2_start.ptau circuit_final.zkey
+        let output = sandbox().output(
+            sandbox()
+                .command("snarkjs", tmp_dir)
+                .arg("plonk")
+                .arg("setup")
+                .arg(&circuit_r1cs_path)
+                .arg(ptau_path)
+                .arg(&prover_key_path),
+        )?;
 
         info!("{}", String::from_utf8_lossy(&output.stdout));
 
         if !output.status.success() {
-            info!("{}", String::from_utf8_lossy(&output.stderr));
-            bail!("couldn't compile circom circuit");
+            bail!("couldn't create prover key");
         }
     }
 
-    // create prover key
-    {
-        // snarkjs plonk setup circuit.r1cs phase2_start.ptau circuit_final.zkey
-        let output = Command::new("snarkjs")
-            .current_dir(tmp_dir)
-            .arg("plonk")
-            .arg("setup")
-            .arg(&circuit_r1cs_path)
-            .arg(srs_path)
-            .arg(&prover_key_path)
-            .output()
-            .expect("failed to execute process");
+    export_verifier_key(tmp_dir, &prover_key_path)
+}
+
+/// Compiles a circom circuit in `tmp_dir`, and exports the verifier key of its groth16 prover key
+/// (at `prover_key_path`, which is created by a new setup if it doesn't exist).
+async fn compile_groth16_into(
+    tmp_dir: &TempDir,
+    circom_circuit_path: &Path,
+    prover_key_path: &Path,
+) -> Result<()> {
+    let circuit_r1cs_path = compile_circuit(tmp_dir, circom_circuit_path)?;
+
+    if prover_key_path.exists() {
+        info!(
+            "- using the groth16 prover key {}",
+            prover_key_path.display()
+        );
+    } else {
+        // the powers of tau fitting plonk constraints fit the r1cs constraints too
+        let num_constraints = ptau::plonk_constraints(&circuit_r1cs_path)?;
+        let ptau_path = ptau::ptau_path(ptau::power_for(num_constraints)?).await?;
+
+        // snarkjs groth16 setup circuit.r1cs phase2_start.ptau circuit_0000.zkey
+        let initial_key_path = tmp_dir.path().join("prover_key_0000.zkey");
+        let output = sandbox().output(
+            sandbox()
+                .command("snarkjs", tmp_dir)
+                .arg("groth16")
+                .arg("setup")
+                .arg(&circuit_r1cs_path)
+                .arg(ptau_path)
+                .arg(&initial_key_path),
+        )?;
 
         info!("{}", String::from_utf8_lossy(&output.stdout));
 
         if !output.status.success() {
             bail!("couldn't create prover key");
         }
+
+        // the phase 2 of the setup: a single contribution, whose randomness is thrown away
+        // snarkjs zkey contribute circuit_0000.zkey circuit_final.zkey --name=zkbitcoin -e=<entropy>
+        let final_key_path = tmp_dir.path().join("prover_key.zkey");
+        let entropy = hex::encode(rand::random::<[u8; 32]>());
+        let output = sandbox().output(
+            sandbox()
+                .command("snarkjs", tmp_dir)
+                .arg("zkey")
+                .arg("contribute")
+                .arg(&initial_key_path)
+                .arg(&final_key_path)
+                .arg("--name=zkbitcoin")
+                .arg(format!("-e={entropy}")),
+        )?;
+
+        info!("{}", String::from_utf8_lossy(&output.stdout));
+
+        if !output.status.success() {
+            bail!("couldn't contribute to the prover key");
+        }
+
+        std::fs::copy(&final_key_path, prover_key_path)
+            .with_context(|| format!("couldn't write {}", prover_key_path.display()))?;
+        info!(
+            "- created the groth16 prover key {} (share it with the users of the zkapp)",
+            prover_key_path.display()
+        );
     }
 
-    // create verifier key
+    export_verifier_key(tmp_dir, prover_key_path)
+}
+
+/// Compiles a circom circuit to wasm and r1cs in `tmp_dir`, and returns the path of the r1cs file.
+pub(crate) fn compile_circuit(tmp_dir: &TempDir, circom_circuit_path: &Path) -> Result<PathBuf> {
+    let circuit_name = circom_circuit_path
+        .file_stem()
+        .context("failed to get circuit name from filename")?;
+    let circuit_r1cs_path = tmp_dir
+        .path()
+        .join(format!("{}.r1cs", circuit_name.to_string_lossy()));
+
     {
-        // snarkjs zkey export verificationkey circuit_final.zkey verification_key.json
-        let output = Command::new("snarkjs")
-            .current_dir(tmp_dir)
-            .arg("zkey")
-            .arg("export")
-            .arg("verificationkey")
-            .arg(&prover_key_path)
-            .arg(&verifier_key_path)
-            .output()
-            .expect("failed to execute process");
+        // circom circuit.circom --r1cs --wasm --sym
+        let output = sandbox().output(
+            sandbox()
+                .command("circom", tmp_dir)
+                .arg(circom_circuit_path)
+                .arg("--wasm")
+                .arg("--r1cs"),
+        )?;
 
         info!("{}", String::from_utf8_lossy(&output.stdout));
 
         if !output.status.success() {
-            bail!("couldn't export verifier key");
+            info!("{}", String::from_utf8_lossy(&output.stderr));
+            bail!("couldn't compile circom circuit");
         }
     }
 
-    // deserialize vk
-    let vk = {
-        let vk_file = File::open(verifier_key_path).expect("file creation failed");
-        let vk: plonk::VerifierKey = serde_json::from_reader(vk_file).expect("read failed");
-        vk
-    };
+    Ok(circuit_r1cs_path)
+}
 
-    Ok(CompilationResult {
-        verifier_key: vk,
-        circuit_r1cs_path,
-        prover_key_path,
-    })
+/// Exports the verifier key of a prover key to `verifier_key.json` in `tmp_dir`.
+fn export_verifier_key(tmp_dir: &TempDir, prover_key_path: &Path) -> Result<()> {
+    // snarkjs zkey export verificationkey circuit_final.zkey verification_key.json
+    let output = sandbox().output(
+        sandbox()
+            .command("snarkjs", tmp_dir)
+            .arg("zkey")
+            .arg("export")
+            .arg("verificationkey")
+            .arg(prover_key_path)
+            .arg(tmp_dir.path().join("verifier_key.json")),
+    )?;
+
+    info!("{}", String::from_utf8_lossy(&output.stdout));
+
+    if !output.status.success() {
+        bail!("couldn't export verifier key");
+    }
+
+    Ok(())
 }
 
 // should we implement these things?
 // perhaps I can just use snarkjs as a library directly?
+/// Proves a circuit with `proof_system` (usually the one it's set up with, see [proof_system_of]).
 pub async fn prove(
     circom_circuit_path: &Path,
+    proof_system: ProofSystemKind,
     proof_inputs: &HashMap<String, Vec<String>>,
 ) -> Result<(plonk::Proof, plonk::PublicInputs, plonk::VerifierKey)> {
     // create tmp dir
@@ -123,7 +272,8 @@ pub async fn prove(
         verifier_key,
         circuit_r1cs_path: _,
         prover_key_path,
-    } = compile(&tmp_dir, circom_circuit_path).await?;
+        circuit_js_dir,
+    } = compile(&tmp_dir, circom_circuit_path, proof_system).await?;
 
     // write inputs to file
     let public_inputs_path = tmp_dir.path().join("proof_inputs.json");
@@ -140,22 +290,19 @@ pub async fn prove(
         let circuit_name = circom_circuit_path
             .file_stem()
             .context("failed to get circuit name from filename")?;
-        let output_folder = tmp_dir
-            .path()
-            .join(format!("{}_js", circuit_name.to_string_lossy()));
-        let generate_witness_path = output_folder.join("generate_witness.js");
+        let generate_witness_path = circuit_js_dir.join("generate_witness.js");
         let circuit_wasm_path =
-            output_folder.join(format!("{}.wasm", circuit_name.to_string_lossy()));
+            circuit_js_dir.join(format!("{}.wasm", circuit_name.to_string_lossy()));
 
         // node output/circuit_js/generate_witness.js output/circuit_js/circuit.wasm public_input.json output/witness.wtns
-        let output = Command::new("node")
-            .current_dir(&tmp_dir)
-            .arg(generate_witness_path)
-            .arg(circuit_wasm_path)
-            .arg(&public_inputs_path)
-            .arg(&witness_path)
-            .output()
-            .expect("failed to execute process");
+        let output = sandbox().output(
+            sandbox()
+                .command("node", &tmp_dir)
+                .arg(generate_witness_path)
+                .arg(circuit_wasm_path)
+                .arg(&public_inputs_path)
+                .arg(&witness_path),
+        )?;
 
         info!("{}", String::from_utf8_lossy(&output.stdout));
 
@@ -167,16 +314,16 @@ pub async fn prove(
 
     // create proof using snarkjs
     {
-        let output = Command::new("snarkjs")
-            .current_dir(&tmp_dir)
-            .arg("plonk")
-            .arg("prove")
-            .arg(prover_key_path)
-            .arg(&witness_path)
-            .arg(&proof_path)
-            .arg(&full_public_inputs_path)
-            .output()
-            .expect("failed to execute process");
+        let output = sandbox().output(
+            sandbox()
+                .command("snarkjs", &tmp_dir)
+                .arg(proof_system.name())
+                .arg("prove")
+                .arg(prover_key_path)
+                .arg(&witness_path)
+                .arg(&proof_path)
+                .arg(&full_public_inputs_path),
+        )?;
 
         info!("{}", String::from_utf8_lossy(&output.stdout));
 
@@ -197,11 +344,21 @@ pub async fn prove(
     Ok((proof, full_public_inputs, verifier_key))
 }
 
+/// Verifies a plonk or groth16 proof (see [crate::proof_system::verify_proof] for any proof).
 pub fn verify_proof(
     vk: &plonk::VerifierKey,
     public_inputs: &[String],
     proof: &plonk::Proof,
 ) -> Result<()> {
+    ensure!(
+        matches!(
+            vk.proof_system(),
+            ProofSystemKind::Plonk | ProofSystemKind::Groth16
+        ),
+        "snarkjs can't verify {} proofs",
+        vk.proof_system().name()
+    );
+
     // create tmp dir
     let tmp_dir = TempDir::new("zkbitcoin_").expect("couldn't create tmp dir");
 
@@ -222,15 +379,15 @@ pub fn verify_proof(
 
     // verify proof using snarkjs
     {
-        let output = Command::new("snarkjs")
-            .current_dir(&tmp_dir)
-            .arg("plonk")
-            .arg("verify")
-            .arg("verification_key.json")
-            .arg("public_inputs.json")
-            .arg("proof.json")
-            .output()
-            .expect("failed to execute process");
+        let output = sandbox().output(
+            sandbox()
+                .command("snarkjs", &tmp_dir)
+                .arg(vk.proof_system().name())
+                .arg("verify")
+                .arg("verification_key.json")
+                .arg("public_inputs.json")
+                .arg("proof.json"),
+        )?;
 
         info!("{}", String::from_utf8_lossy(&output.stdout));
 
@@ -264,7 +421,10 @@ mod tests {
                     .to_string(),
             ],
         );
-        let (proof, full_inputs, vk) = prove(&circom_circuit_path, &proof_inputs).await.unwrap();
+        let (proof, full_inputs, vk) =
+            prove(&circom_circuit_path, ProofSystemKind::Plonk, &proof_inputs)
+                .await
+                .unwrap();
 
         // verify
         verify_proof(&vk, &full_inputs.0, &proof).unwrap();
@@ -286,13 +446,16 @@ mod tests {
         //         verifier_key,
         //         circuit_r1cs_path: _,
         //         prover_key_path: _,
-        //     } = compile(tmp_dir, &circom_circuit_path).unwrap();
+        //     } = compile(tmp_dir, &circom_circuit_path, ProofSystemKind::Plonk).unwrap();
         //     verifier_key
         // };
 
         // prove
         let public_inputs = HashMap::new();
-        let (proof, full_inputs, vk) = prove(&circom_circuit_path, &public_inputs).await.unwrap();
+        let (proof, full_inputs, vk) =
+            prove(&circom_circuit_path, ProofSystemKind::Plonk, &public_inputs)
+                .await
+                .unwrap();
 
         // verify
         verify_proof(&vk, &full_inputs.0, &proof).unwrap();
@@ -0,0 +1,422 @@
+//! Spending a zkapp (what `zkbtc use-zkapp` does), for other binaries and services to embed.
+//!
+//! ```no_run
+//! # async fn example(rpc_ctx: zkbitcoin::json_rpc_stuff::RpcCtx, zkapp_txid: bitcoin::Txid) -> anyhow::Result<()> {
+//! use std::str::FromStr;
+//! use zkbitcoin::{bob_request::Recipient, spend::{self, SpendParams, Transport}};
+//!
+//! let recipients = vec![Recipient::from_str("tb1q6nkpv2j9lxrm6h3w4skrny3thswgdcca8cx9k6")?];
+//! let params = SpendParams {
+//!     transport: Transport::Http("http://127.0.0.1:8888".to_string()),
+//!     ..SpendParams::new(&rpc_ctx, &rpc_ctx, zkapp_txid, recipients, "examples/circuit/stateless.circom".into())
+//! };
+//! let spend = spend::execute(&params).await?;
+//! println!("{:?}", spend.signed);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use anyhow::{ensure, Context, Result};
+use base64::{engine::general_purpose, Engine};
+use bitcoin::{Amount, TxOut, Txid};
+use log::{info, warn};
+use nostr_sdk::secp256k1::XOnlyPublicKey;
+
+use crate::{
+    bob_request::{
+        extract_smart_contract_from_tx, fetch_zkapp_tx, new_nonce, send_bob_request,
+        string_to_amount, BobRequest, BobResponse, PreparedSpend, Recipient,
+    },
+    chain::ChainBackend,
+    client::OrchestratorClient,
+    coin_selection::Funding,
+    committee::orchestrator::Orchestrator,
+    config::{protocol_config, FeeQuote, FeeSchedule},
+    deploy::{Signed, Signer},
+    hwi,
+    json_rpc_stuff::{sign_transaction, wallet_process_psbt, RpcCtx, TransactionOrHex},
+    lint::Layout,
+    nostr_transport::{self, NostrRequest},
+    plonk::{self, PublicInputs},
+    prover::{LocalProver, Prover, RemoteProver},
+    rbf::SpendRecord,
+};
+
+/// How Bob's request reaches the orchestrator.
+#[derive(Clone)]
+pub enum Transport {
+    /// Through the JSON RPC API of the orchestrator at the given address.
+    Http(String),
+
+    /// As an encrypted direct message to the orchestrator's Nostr public key, through the given relays.
+    Nostr {
+        orchestrator: XOnlyPublicKey,
+        relays: Vec<String>,
+    },
+
+    /// Directly to an orchestrator running in the same process (e.g. in tests, see `crate::testing`).
+    InProcess(Arc<Orchestrator>),
+}
+
+impl Default for Transport {
+    /// The orchestrator of the protocol configuration.
+    fn default() -> Self {
+        Self::Http(protocol_config().orchestrator_address.clone())
+    }
+}
+
+impl Transport {
+    /// Sends a request to the orchestrator, and returns its response.
+    pub async fn send(&self, bob_request: BobRequest) -> Result<BobResponse> {
+        match self {
+            Transport::Http(address) => send_bob_request(address, bob_request).await,
+            Transport::Nostr {
+                orchestrator,
+                relays,
+            } => {
+                let request = NostrRequest::UnlockFunds(bob_request);
+                nostr_transport::send_request(*orchestrator, relays, &request).await
+            }
+            Transport::InProcess(orchestrator) => orchestrator.unlock_funds(&bob_request).await,
+        }
+        .context("error while sending request to orchestrator")
+    }
+
+    /// Asks the orchestrator for the fee the committee takes when `amount` is withdrawn from a zkapp,
+    /// along with an invoice to pay it over Lightning if `lightning` is set.
+    /// Quotes can't be asked for over Nostr, where the fee of the protocol configuration is assumed
+    /// (and can't be paid over Lightning).
+    pub async fn fee_quote(&self, amount: Amount, lightning: bool) -> Result<FeeQuote> {
+        match self {
+            Transport::Http(address) => OrchestratorClient::new(address.clone())
+                .fee_quote(amount, lightning)
+                .await
+                .context("couldn't get a fee quote from the orchestrator"),
+            Transport::Nostr { .. } => {
+                ensure!(
+                    !lightning,
+                    "fees can't be paid over Lightning through Nostr"
+                );
+                warn!("- fee quotes aren't available over Nostr, assuming the protocol's fee");
+                Ok(FeeQuote::new(&FeeSchedule::default(), amount))
+            }
+            Transport::InProcess(orchestrator) => orchestrator.fee_quote(amount, lightning).await,
+        }
+    }
+}
+
+/// Asks the orchestrator (through `transport`) for the fee the committee takes on a spend of the zkapp deployed
+/// (or last updated) by `zkapp_txid`, before it is made: for stateful zkapps,
+/// it depends on the `amount_out` of the proof inputs, and on the zkapp's locked value otherwise.
+/// If `lightning` is set, the quote comes with an invoice paying it over Lightning.
+pub async fn quote_fee(
+    transport: &Transport,
+    chain: &dyn ChainBackend,
+    zkapp_txid: Txid,
+    proof_inputs: &HashMap<String, Vec<String>>,
+    lightning: bool,
+) -> Result<FeeQuote> {
+    let zkapp_tx = fetch_zkapp_tx(chain, zkapp_txid).await?;
+    let smart_contract = extract_smart_contract_from_tx(&zkapp_tx)?;
+    let amount = if smart_contract.is_stateful() {
+        proof_inputs
+            .get("amount_out")
+            .and_then(|x| x.first())
+            .context("amount_out in proof inputs must be of length 1")
+            .and_then(|amount| string_to_amount(amount))?
+    } else {
+        smart_contract.locked_value
+    };
+    let quote = transport.fee_quote(amount, lightning).await?;
+    info!(
+        "- the committee takes a fee of {} on the {} withdrawn",
+        quote.fee, quote.amount
+    );
+    if let Some(invoice) = &quote.invoice {
+        info!(
+            "- pay it over Lightning (the committee only signs once it's paid): {}",
+            invoice.bolt11
+        );
+    }
+    Ok(quote)
+}
+
+/// What's needed to spend a zkapp.
+pub struct SpendParams<'a> {
+    /// The node whose wallet funds (and signs, see [SpendParams::signer]) the transaction.
+    pub rpc_ctx: &'a RpcCtx,
+
+    /// Where transactions are fetched from, and broadcast to.
+    pub chain: &'a dyn ChainBackend,
+
+    /// The transaction that deployed (or last updated) the zkapp.
+    pub zkapp_txid: Txid,
+
+    /// Who gets the funds withdrawn from the zkapp.
+    pub recipients: Vec<Recipient>,
+
+    /// The circuit of the zkapp, and the inputs to prove it with.
+    pub circom_circuit_path: PathBuf,
+    pub proof_inputs: HashMap<String, Vec<String>>,
+
+    /// The public and private inputs of the circuit, to check the proof inputs against before proving
+    /// (read from the source of circom circuits if not given).
+    pub input_layout: Option<Layout>,
+
+    /// A proving service to prove the circuit with, instead of this machine
+    /// (which doesn't need the circuit then).
+    pub remote_prover: Option<RemoteProver>,
+
+    /// A proof made elsewhere for a prepared spend (see [prepare]), to use instead of proving the circuit.
+    pub precomputed: Option<PrecomputedProof>,
+
+    /// How to fund the transaction (the fee of the committee being quoted by the orchestrator).
+    pub funding: Funding,
+
+    /// Pay the fee of the committee over Lightning (with the invoice the orchestrator quotes it with)
+    /// instead of with an output of the transaction.
+    pub pay_over_lightning: bool,
+
+    /// How to reach the orchestrator.
+    pub transport: Transport,
+
+    /// When (as a UNIX timestamp) the request stops being worth signing, if the orchestrator has it queued
+    /// (see [BobRequest::deadline]).
+    pub deadline: Option<u64>,
+
+    /// Who signs the inputs of the wallet, once the committee signed the zkapp's.
+    pub signer: Signer,
+}
+
+impl<'a> SpendParams<'a> {
+    /// Parameters to spend a zkapp without proof inputs (other than the ones of the zkapp),
+    /// with the defaults for everything else.
+    pub fn new(
+        rpc_ctx: &'a RpcCtx,
+        chain: &'a dyn ChainBackend,
+        zkapp_txid: Txid,
+        recipients: Vec<Recipient>,
+        circom_circuit_path: PathBuf,
+    ) -> Self {
+        Self {
+            rpc_ctx,
+            chain,
+            zkapp_txid,
+            recipients,
+            circom_circuit_path,
+            proof_inputs: HashMap::new(),
+            input_layout: None,
+            remote_prover: None,
+            precomputed: None,
+            funding: Funding::default(),
+            pay_over_lightning: false,
+            transport: Transport::default(),
+            deadline: None,
+            signer: Signer::default(),
+        }
+    }
+}
+
+/// A proof made elsewhere (e.g. on a beefier machine, or with another toolchain) for a prepared spend.
+pub struct PrecomputedProof {
+    /// The spend the proof was made for.
+    pub spend: PreparedSpend,
+
+    /// The verifier key of the zkapp, and the proof with its public inputs.
+    pub vk: plonk::VerifierKey,
+    pub proof: plonk::Proof,
+    pub public_inputs: PublicInputs,
+}
+
+/// Creates and funds the transaction spending a zkapp without proving it,
+/// so that it can be proven elsewhere (and then spent with [SpendParams::precomputed]).
+/// Stateful zkapps need the `new_state` the proof will output
+/// (and the `amount_in` and `amount_out` of the proof inputs).
+/// The fee of the committee is quoted by the orchestrator first (see [quote_fee]).
+pub async fn prepare(params: &SpendParams<'_>, new_state: Option<&str>) -> Result<PreparedSpend> {
+    let funding = quoted_funding(params).await?;
+    let zkapp_tx = fetch_zkapp_tx(params.chain, params.zkapp_txid).await?;
+    PreparedSpend::new(
+        params.rpc_ctx,
+        params.chain,
+        &params.recipients,
+        zkapp_tx,
+        new_state,
+        &params.proof_inputs,
+        &funding,
+    )
+    .await
+}
+
+/// How to fund the spend, paying the fee quoted by the orchestrator
+/// (over Lightning if `pay_over_lightning` is set, with the invoice attached to the quote).
+async fn quoted_funding(params: &SpendParams<'_>) -> Result<Funding> {
+    let quote = quote_fee(
+        &params.transport,
+        params.chain,
+        params.zkapp_txid,
+        &params.proof_inputs,
+        params.pay_over_lightning,
+    )
+    .await?;
+    Ok(Funding {
+        zkbitcoin_fee: quote.schedule,
+        fee_invoice: quote.invoice,
+        ..params.funding.clone()
+    })
+}
+
+/// A spend of a zkapp (see [execute]).
+#[derive(Debug, Clone)]
+pub struct Spend {
+    /// The transaction signed by the committee.
+    pub response: BobResponse,
+
+    /// The new state of a stateful zkapp.
+    pub new_state: Option<String>,
+
+    /// The fee paid by the transaction (if it could be computed).
+    pub fee: Option<Amount>,
+
+    /// What happened to the transaction once signed by the wallet.
+    pub signed: Signed,
+}
+
+/// Creates a request to spend a zkapp (paying the fee quoted by the orchestrator, see [quote_fee]),
+/// has the committee sign it, then has `signer` sign the rest.
+/// A record of the spend is kept, so that its fee can be bumped later (see [crate::rbf]).
+pub async fn execute(params: &SpendParams<'_>) -> Result<Spend> {
+    // create bob request (or package the proof made elsewhere)
+    let bob_request = match &params.precomputed {
+        Some(precomputed) => {
+            ensure!(
+                precomputed.spend.zkapp_tx.txid() == params.zkapp_txid,
+                "the prepared spend doesn't use the zkapp {}",
+                params.zkapp_txid
+            );
+            precomputed.spend.clone().into_request(
+                precomputed.vk.clone(),
+                precomputed.proof.clone(),
+                &precomputed.public_inputs,
+            )?
+        }
+        None => {
+            let local_prover;
+            let prover: &dyn Prover = match &params.remote_prover {
+                Some(remote_prover) => remote_prover,
+                None => {
+                    local_prover = LocalProver {
+                        circuit_path: params.circom_circuit_path.clone(),
+                        layout: params.input_layout.clone(),
+                    };
+                    &local_prover
+                }
+            };
+            let funding = quoted_funding(params).await?;
+            BobRequest::new(
+                params.rpc_ctx,
+                params.chain,
+                &params.recipients,
+                params.zkapp_txid,
+                prover,
+                params.proof_inputs.clone(),
+                &funding,
+            )
+            .await?
+        }
+    };
+
+    // a fresh nonce, so that the request can't be replayed (see `committee::replay`)
+    let bob_request = BobRequest {
+        deadline: params.deadline,
+        nonce: Some(new_nonce()),
+        ..bob_request
+    };
+
+    // send bob's request to the orchestartor.
+    let prev_outs = bob_request.prev_outs.clone();
+    let new_state = bob_request
+        .update
+        .as_ref()
+        .map(|update| update.new_state.clone());
+    let response = params.transport.send(bob_request).await?;
+
+    // keep a record of the spend, in case its fee needs to be bumped later
+    // (which is up to the sponsor for sponsored spends)
+    if !params.funding.sponsored {
+        SpendRecord {
+            txid: response.unlocked_tx.txid(),
+            zkapp_txid: params.zkapp_txid,
+            recipients: params.recipients.iter().map(ToString::to_string).collect(),
+        }
+        .save()?;
+    }
+
+    let inputs_value: Amount = prev_outs.iter().map(|output| output.value).sum();
+    let outputs_value: Amount = response
+        .unlocked_tx
+        .output
+        .iter()
+        .map(|output| output.value)
+        .sum();
+    let fee = inputs_value.checked_sub(outputs_value);
+
+    // sign the inputs of the wallet (sponsored spends have none, the sponsor attaching its own)
+    let signed = if params.funding.sponsored {
+        Signed::Sponsorable(response.unlocked_tx.clone())
+    } else {
+        sign(
+            params.rpc_ctx,
+            params.chain,
+            &params.signer,
+            &response,
+            &prev_outs,
+        )
+        .await?
+    };
+
+    Ok(Spend {
+        response,
+        new_state,
+        fee,
+        signed,
+    })
+}
+
+/// Has `signer` sign the inputs of the wallet in a transaction signed by the committee,
+/// given the outputs spent by its inputs (`prev_outs`).
+pub(crate) async fn sign(
+    rpc_ctx: &RpcCtx,
+    chain: &dyn ChainBackend,
+    signer: &Signer,
+    response: &BobResponse,
+    prev_outs: &[TxOut],
+) -> Result<Signed> {
+    let signed = match signer {
+        Signer::Wallet => {
+            let (_signed_tx_hex, signed_tx) = sign_transaction(
+                rpc_ctx,
+                TransactionOrHex::Transaction(&response.unlocked_tx),
+            )
+            .await?;
+            Signed::Broadcast(chain.broadcast(&signed_tx).await?)
+        }
+        Signer::Psbt => {
+            let psbt = response.to_psbt(prev_outs)?;
+            Signed::Psbt(wallet_process_psbt(rpc_ctx, &psbt).await?)
+        }
+        Signer::HardwareWallet { fingerprint } => {
+            let psbt = response.to_psbt(prev_outs)?;
+            let psbt = wallet_process_psbt(rpc_ctx, &psbt).await?;
+            let txid = hwi::sign_and_broadcast(rpc_ctx, fingerprint.as_deref(), &psbt).await?;
+            Signed::Broadcast(txid)
+        }
+        Signer::External => {
+            let psbt = response.to_psbt(prev_outs)?;
+            Signed::Psbt(general_purpose::STANDARD.encode(psbt.serialize()))
+        }
+    };
+    Ok(signed)
+}
@@ -0,0 +1,137 @@
+//! Fee sponsorship: a third party (e.g. the application Bob uses) paying the fees of his spend.
+//!
+//! A sponsored spend (see [crate::bob_request::BobRequest::sponsored]) only spends the zkapp,
+//! whose input the committee signs with `SIGHASH_ALL|ANYONECANPAY`: the signature commits to the outputs,
+//! but not to the other inputs. The sponsor attaches the inputs paying for the zkBitcoin fee and the network fee
+//! (and for what's deposited into a stateful zkapp), signs them, and broadcasts the transaction.
+//! As the outputs can't change, the sponsor's inputs can't get any change back (see [select_sponsoring_inputs]).
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{taproot, Amount, FeeRate, TapSighashType, Transaction};
+use log::info;
+
+use crate::{
+    chain::ChainBackend,
+    coin_selection::{add_inputs, select_sponsoring_inputs},
+    json_rpc_stuff::{sign_transaction, RpcCtx, TransactionOrHex},
+};
+
+/// The most the sponsor gives to the miners on top of the fee, by default (in satoshis).
+pub const DEFAULT_MAX_OVERPAY_SAT: u64 = 1_000;
+
+/// A sponsored transaction, signed by the sponsor.
+#[derive(Debug, Clone)]
+pub struct Sponsored {
+    /// The transaction, ready to be broadcast.
+    pub tx: Transaction,
+
+    /// The network fee it pays.
+    pub fee: Amount,
+}
+
+/// Checks that a transaction signed by the committee can be sponsored:
+/// it only spends the zkapp, signed with `SIGHASH_ALL|ANYONECANPAY`.
+pub fn check_template(tx: &Transaction) -> Result<()> {
+    ensure!(
+        tx.input.len() == 1,
+        "a sponsored transaction must only spend the zkapp"
+    );
+    let signature = tx.input[0]
+        .witness
+        .nth(0)
+        .context("the zkapp input isn't signed by the committee")?;
+    let signature = taproot::Signature::from_slice(signature)
+        .context("the witness of the zkapp input isn't a signature")?;
+    ensure!(
+        signature.hash_ty == TapSighashType::AllPlusAnyoneCanPay,
+        "the zkapp input isn't signed with SIGHASH_ALL|ANYONECANPAY, so no input can be attached to the transaction"
+    );
+    Ok(())
+}
+
+/// Attaches inputs of the wallet behind `ctx` to a sponsored transaction signed by the committee (fetching the zkapp through `chain`),
+/// so that it pays its outputs and a fee at `fee_rate`, then signs them.
+/// Up to `max_overpay` more can go to the miners, for lack of a change output.
+pub async fn sponsor(
+    ctx: &RpcCtx,
+    chain: &dyn ChainBackend,
+    mut tx: Transaction,
+    fee_rate: FeeRate,
+    max_overpay: Amount,
+) -> Result<Sponsored> {
+    check_template(&tx)?;
+
+    // what the zkapp brings
+    let zkapp_outpoint = tx.input[0].previous_output;
+    let (zkapp_tx, _) = chain.get_transaction(zkapp_outpoint.txid).await?;
+    let zkapp_value = zkapp_tx
+        .output
+        .get(zkapp_outpoint.vout as usize)
+        .with_context(|| format!("the zkapp {zkapp_outpoint} doesn't exist"))?
+        .value;
+
+    // what the sponsor brings
+    let utxos = select_sponsoring_inputs(ctx, &tx, zkapp_value, fee_rate, max_overpay).await?;
+    info!("- sponsoring the transaction with {} inputs", utxos.len());
+    let outpoints: Vec<_> = utxos.iter().map(|utxo| utxo.outpoint).collect();
+    add_inputs(&mut tx, &outpoints);
+
+    let (_tx_hex, tx) = sign_transaction(ctx, TransactionOrHex::Transaction(&tx)).await?;
+    ensure!(
+        tx.input
+            .iter()
+            .all(|input| !input.witness.is_empty() || !input.script_sig.is_empty()),
+        "the wallet couldn't sign the inputs it sponsors the transaction with"
+    );
+
+    let inputs_value = utxos.iter().map(|utxo| utxo.value).sum::<Amount>() + zkapp_value;
+    let outputs_value: Amount = tx.output.iter().map(|output| output.value).sum();
+    let fee = inputs_value
+        .checked_sub(outputs_value)
+        .context("the sponsoring inputs don't pay for the outputs")?;
+    info!("- sponsored the transaction with a fee of {fee}");
+
+    Ok(Sponsored { tx, fee })
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        absolute::LockTime, hashes::Hash, transaction::Version, OutPoint, TxIn, Txid, Witness,
+    };
+
+    use super::*;
+
+    fn signed_by_committee(hash_ty: u8) -> Transaction {
+        let mut signature = vec![1; 64];
+        signature.push(hash_ty);
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                witness: Witness::from_slice(&[signature]),
+                ..Default::default()
+            }],
+            output: vec![],
+        }
+    }
+
+    #[test]
+    fn test_check_template() {
+        check_template(&signed_by_committee(0x81)).unwrap();
+
+        // signed with SIGHASH_ALL, the committee's signature would break
+        assert!(check_template(&signed_by_committee(0x01)).is_err());
+
+        // not signed yet
+        let mut tx = signed_by_committee(0x81);
+        tx.input[0].witness.clear();
+        assert!(check_template(&tx).is_err());
+
+        // spending more than the zkapp
+        let mut tx = signed_by_committee(0x81);
+        tx.input.push(tx.input[0].clone());
+        assert!(check_template(&tx).is_err());
+    }
+}
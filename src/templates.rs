@@ -0,0 +1,216 @@
+//! Ready-to-deploy circuits shipped with zkBitcoin (see `zkbtc deploy-template`).
+//! Their parameters (e.g. the hash of a hash-lock) are fixed when they are instantiated,
+//! so that every instance of a template is a different zkapp.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::ValueEnum;
+
+/// The name of the circuit written by [Template::write].
+pub const CIRCUIT_FILENAME: &str = "circuit.circom";
+
+/// The libraries the templates include, written next to them.
+const CIRCOM_LIB: [(&str, &str); 3] = [
+    (
+        "poseidon.circom",
+        include_str!("../examples/circuit/circom_lib/poseidon.circom"),
+    ),
+    (
+        "poseidon_constants.circom",
+        include_str!("../examples/circuit/circom_lib/poseidon_constants.circom"),
+    ),
+    ("utils.circom", include_str!("templates/utils.circom")),
+];
+
+/// The largest number of digits of a field element (of the BN254 scalar field).
+const MAX_PARAM_DIGITS: usize = 77;
+
+/// The circuits that can be deployed as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Template {
+    /// Spendable (once) by anyone who knows a preimage of `hash` (a Poseidon hash).
+    Hashlock,
+
+    /// A stateful zkapp keeping track of its balance, usable by whoever knows the preimage of `password_hash`.
+    PasswordVault,
+
+    /// Spendable (once) with the secrets of 2 of 3 approvers, given as the hashes `approver_1` to `approver_3`.
+    ThresholdApproval,
+
+    /// A stateful zkapp counting the number of times it was used.
+    Counter,
+
+    /// A password vault (see [Template::PasswordVault]) that whoever knows the preimage of `admin_hash`
+    /// can upgrade to another circuit (see `zkbtc upgrade-zkapp`).
+    UpgradableVault,
+
+    /// A password vault that its administrator (whose admin key is recorded on-chain, see `zkbtc deploy-template --admin-key`)
+    /// can pause, and upgrade to another circuit.
+    AdministeredVault,
+}
+
+impl Template {
+    fn circuit(self) -> &'static str {
+        match self {
+            Template::Hashlock => include_str!("templates/hashlock.circom"),
+            Template::PasswordVault => include_str!("templates/password_vault.circom"),
+            Template::ThresholdApproval => include_str!("templates/threshold_approval.circom"),
+            Template::Counter => include_str!("templates/counter.circom"),
+            Template::UpgradableVault => include_str!("templates/upgradable_vault.circom"),
+            Template::AdministeredVault => include_str!("templates/administered_vault.circom"),
+        }
+    }
+
+    /// The names of the parameters of the template.
+    pub fn params(self) -> &'static [&'static str] {
+        match self {
+            Template::Hashlock => &["hash"],
+            Template::PasswordVault => &["password_hash"],
+            Template::ThresholdApproval => &["approver_1", "approver_2", "approver_3"],
+            Template::Counter => &[],
+            Template::UpgradableVault => &["password_hash", "admin_hash"],
+            Template::AdministeredVault => &["password_hash"],
+        }
+    }
+
+    /// The state stateful templates start from.
+    pub fn initial_state(self) -> Option<&'static str> {
+        match self {
+            Template::Hashlock | Template::ThresholdApproval => None,
+            Template::PasswordVault
+            | Template::Counter
+            | Template::UpgradableVault
+            | Template::AdministeredVault => Some("0"),
+        }
+    }
+
+    /// The circuit of the template, with its parameters set to `params`.
+    /// Parameters must be field elements, written in decimal.
+    pub fn instantiate(self, params: &HashMap<String, String>) -> Result<String> {
+        let name = self.to_possible_value().unwrap();
+        let name = name.get_name();
+        for param in params.keys() {
+            ensure!(
+                self.params().contains(&param.as_str()),
+                "the {name} template has no parameter `{param}` (expected: {:?})",
+                self.params()
+            );
+        }
+
+        let mut circuit = self.circuit().to_string();
+        for param in self.params() {
+            let value = params
+                .get(*param)
+                .with_context(|| format!("the {name} template needs a `{param}` parameter"))?;
+            ensure!(
+                !value.is_empty()
+                    && value.len() <= MAX_PARAM_DIGITS
+                    && value.chars().all(|c| c.is_ascii_digit()),
+                "the `{param}` parameter must be a field element written in decimal"
+            );
+            circuit = circuit.replace(&format!("{{{param}}}"), value);
+        }
+        Ok(circuit)
+    }
+
+    /// Writes the instantiated circuit (and the libraries it includes) to `dir`, and returns the path of the circuit.
+    pub fn write(self, params: &HashMap<String, String>, dir: &Path) -> Result<PathBuf> {
+        let circuit = self.instantiate(params)?;
+
+        let lib_dir = dir.join("circom_lib");
+        std::fs::create_dir_all(&lib_dir)
+            .with_context(|| format!("couldn't create {}", lib_dir.display()))?;
+        for (filename, content) in CIRCOM_LIB {
+            let path = lib_dir.join(filename);
+            std::fs::write(&path, content)
+                .with_context(|| format!("couldn't write {}", path.display()))?;
+        }
+
+        let path = dir.join(CIRCUIT_FILENAME);
+        std::fs::write(&path, circuit)
+            .with_context(|| format!("couldn't write {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Parses parameters given as `name=value`.
+pub fn parse_params(params: &[String]) -> Result<HashMap<String, String>> {
+    let mut parsed = HashMap::new();
+    for param in params {
+        let Some((name, value)) = param.split_once('=') else {
+            bail!("parameters must be given as `name=value`, not `{param}`");
+        };
+        ensure!(
+            parsed
+                .insert(name.trim().to_string(), value.trim().to_string())
+                .is_none(),
+            "the parameter `{name}` is given more than once"
+        );
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(params: &[&str]) -> HashMap<String, String> {
+        parse_params(&params.iter().map(|p| p.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_instantiate() {
+        for template in Template::value_variants() {
+            let values = template
+                .params()
+                .iter()
+                .map(|param| format!("{param}=42"))
+                .collect::<Vec<_>>();
+            let circuit = template
+                .instantiate(&parse_params(&values).unwrap())
+                .unwrap();
+            for param in template.params() {
+                assert!(!circuit.contains(&format!("{{{param}}}")));
+            }
+            assert_eq!(
+                template.initial_state().is_some(),
+                circuit.contains("signal output new_state")
+            );
+        }
+
+        let hashlock = Template::Hashlock;
+        assert!(hashlock
+            .instantiate(&params(&["hash=42"]))
+            .unwrap()
+            .contains("Main(42)"));
+        assert!(hashlock.instantiate(&params(&[])).is_err());
+        assert!(hashlock.instantiate(&params(&["hash=0x2a"])).is_err());
+        assert!(hashlock.instantiate(&params(&["hash=1); x("])).is_err());
+        assert!(hashlock
+            .instantiate(&params(&["hash=42", "salt=1"]))
+            .is_err());
+        assert!(parse_params(&["hash".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_write() {
+        let tmp_dir = tempdir::TempDir::new("zkbitcoin_template").unwrap();
+        let path = Template::ThresholdApproval
+            .write(
+                &params(&["approver_1=1", "approver_2=2", "approver_3=3"]),
+                tmp_dir.path(),
+            )
+            .unwrap();
+        let circuit = std::fs::read_to_string(path).unwrap();
+
+        // the libraries included are written too
+        for line in circuit.lines().filter(|line| line.starts_with("include")) {
+            let include = line.split('"').nth(1).unwrap();
+            assert!(tmp_dir.path().join(include).exists(), "{include} missing");
+        }
+    }
+}
@@ -0,0 +1,51 @@
+pragma circom 2.1.3;
+
+include "./circom_lib/poseidon.circom";
+include "./circom_lib/utils.circom";
+
+// An administered vault: a password vault whose administrator (whose admin key, the Poseidon hash of their secret,
+// is recorded on-chain with `zkbtc deploy-template --admin-key`) can pause it, and upgrade it to another circuit.
+// Proof inputs: `{"password": ["<password>"], "admin_secret": ["0"], "paused": ["0"], "amount_in": ["<satoshis>"], "amount_out": ["<satoshis>"]}`,
+// or `{"password": ["0"], "paused": ["<0 or 1>"], "amount_in": ["0"], "amount_out": ["0"]}` with `--admin-secret <secret>`
+// to pause (or unpause) it with `zkbtc use-zkapp` (without any recipient), or to upgrade it with `zkbtc upgrade-zkapp`.
+template Main(password_hash) {
+    signal output new_state;
+    signal input prev_state;
+    signal input truncated_txid;
+    signal input amount_out;
+    signal input amount_in;
+    signal input upgrade_to; // 0 unless the vault is upgraded (filled in by `zkbtc`)
+    signal input admin; // the admin key recorded on-chain (filled in by `zkbtc`)
+
+    signal input password[1];
+    signal input admin_secret[1];
+    signal input paused;
+
+    // the state is the balance of the vault (64 bits), and whether it is paused (the bit above)
+    component prev = Num2Bits(65);
+    prev.in <== prev_state;
+    signal was_paused <== prev.out[64];
+
+    // users (whose admin secret is 0) need the password, can't use a paused vault, and can't upgrade it
+    signal by_user <== IsZero()(admin_secret[0]);
+    signal password_digest <== Poseidon(1)(password);
+    by_user * (password_digest - password_hash) === 0;
+    by_user * was_paused === 0;
+    by_user * upgrade_to === 0;
+
+    // the administrator moves no funds, and sets whether the vault is paused
+    signal admin_digest <== Poseidon(1)(admin_secret);
+    (1 - by_user) * (admin_digest - admin) === 0;
+    (1 - by_user) * amount_out === 0;
+    (1 - by_user) * amount_in === 0;
+    paused * (1 - paused) === 0;
+    signal is_paused <== paused + by_user * (was_paused - paused);
+
+    // the balance can't go below zero (and wrap around the field)
+    signal balance <== prev_state - was_paused * 2**64 + amount_in - amount_out;
+    component check = Num2Bits(64);
+    check.in <== balance;
+    new_state <== balance + is_paused * 2**64;
+}
+
+component main{public [prev_state, truncated_txid, amount_out, amount_in, upgrade_to, admin]} = Main({password_hash});
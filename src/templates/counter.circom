@@ -0,0 +1,15 @@
+pragma circom 2.1.3;
+
+// A counter: a stateful zkapp whose state counts the number of times it was used.
+// Proof inputs: `{"amount_in": ["<satoshis>"], "amount_out": ["<satoshis>"]}`.
+template Main() {
+    signal output new_state;
+    signal input prev_state;
+    signal input truncated_txid;
+    signal input amount_out;
+    signal input amount_in;
+
+    new_state <== prev_state + 1;
+}
+
+component main{public [prev_state, truncated_txid, amount_out, amount_in]} = Main();
@@ -0,0 +1,17 @@
+pragma circom 2.1.3;
+
+include "./circom_lib/poseidon.circom";
+
+// A hash-lock: the bitcoin it locks can be spent (once) by anyone who knows a preimage of `hash`.
+// Proof inputs: `{"preimage": ["<preimage>"]}`.
+template Main(hash) {
+    // binds the proof to the transaction spending the zkapp (filled in by `zkbtc use-zkapp`)
+    signal input truncated_txid;
+
+    signal input preimage[1];
+
+    signal digest <== Poseidon(1)(preimage);
+    digest === hash;
+}
+
+component main{public [truncated_txid]} = Main({hash});
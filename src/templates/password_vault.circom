@@ -0,0 +1,26 @@
+pragma circom 2.1.3;
+
+include "./circom_lib/poseidon.circom";
+include "./circom_lib/utils.circom";
+
+// A password vault: a stateful zkapp keeping track of its balance, which only the holder of a password can use.
+// Proof inputs: `{"password": ["<password>"], "amount_in": ["<satoshis>"], "amount_out": ["<satoshis>"]}`.
+template Main(password_hash) {
+    signal output new_state;
+    signal input prev_state;
+    signal input truncated_txid;
+    signal input amount_out;
+    signal input amount_in;
+
+    signal input password[1];
+
+    signal digest <== Poseidon(1)(password);
+    digest === password_hash;
+
+    // the state is the balance of the vault, which can't go below zero (and wrap around the field)
+    new_state <== prev_state + amount_in - amount_out;
+    component balance = Num2Bits(64);
+    balance.in <== new_state;
+}
+
+component main{public [prev_state, truncated_txid, amount_out, amount_in]} = Main({password_hash});
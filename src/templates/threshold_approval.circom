@@ -0,0 +1,32 @@
+pragma circom 2.1.3;
+
+include "./circom_lib/poseidon.circom";
+include "./circom_lib/utils.circom";
+
+// A threshold approval: the bitcoin it locks can be spent (once) with the approval of 2 of its 3 approvers,
+// each approver being identified by the hash of a secret only they know.
+// Proof inputs: `{"secrets": ["<secret or 0>", "<secret or 0>", "<secret or 0>"]}`,
+// with 0 in place of the secret of the approvers who don't approve.
+template Main(approver_1, approver_2, approver_3) {
+    // binds the proof to the transaction spending the zkapp (filled in by `zkbtc use-zkapp`)
+    signal input truncated_txid;
+
+    signal input secrets[3];
+
+    var approvers[3] = [approver_1, approver_2, approver_3];
+    component hashes[3];
+    component approved[3];
+    var count = 0;
+    for (var i = 0; i < 3; i++) {
+        hashes[i] = Poseidon(1);
+        hashes[i].inputs[0] <== secrets[i];
+        approved[i] = IsZero();
+        approved[i].in <== hashes[i].out - approvers[i];
+        count += approved[i].out;
+    }
+
+    // at least 2 approvals
+    (count - 2) * (count - 3) === 0;
+}
+
+component main{public [truncated_txid]} = Main({approver_1}, {approver_2}, {approver_3});
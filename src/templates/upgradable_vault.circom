@@ -0,0 +1,35 @@
+pragma circom 2.1.3;
+
+include "./circom_lib/poseidon.circom";
+include "./circom_lib/utils.circom";
+
+// An upgradable vault: a password vault whose administrator (who knows the preimage of `admin_hash`)
+// can upgrade it to another circuit with `zkbtc upgrade-zkapp`, the new circuit starting from the balance of the vault.
+// Proof inputs: `{"password": ["<password>"], "admin_secret": ["0"], "amount_in": ["<satoshis>"], "amount_out": ["<satoshis>"]}`,
+// or `{"password": ["0"], "admin_secret": ["<secret>"]}` to upgrade it.
+template Main(password_hash, admin_hash) {
+    signal output new_state;
+    signal input prev_state;
+    signal input truncated_txid;
+    signal input amount_out;
+    signal input amount_in;
+    signal input upgrade_to; // 0 unless the vault is upgraded (filled in by `zkbtc`)
+
+    signal input password[1];
+    signal input admin_secret[1];
+
+    // using the vault needs the password, and upgrading it (without withdrawing anything) the secret of the administrator
+    signal using <== IsZero()(upgrade_to);
+    signal password_digest <== Poseidon(1)(password);
+    signal admin_digest <== Poseidon(1)(admin_secret);
+    using * (password_digest - password_hash) === 0;
+    (1 - using) * (admin_digest - admin_hash) === 0;
+    (1 - using) * amount_out === 0;
+
+    // the state is the balance of the vault, which can't go below zero (and wrap around the field)
+    new_state <== prev_state + amount_in - amount_out;
+    component balance = Num2Bits(64);
+    balance.in <== new_state;
+}
+
+component main{public [prev_state, truncated_txid, amount_out, amount_in, upgrade_to]} = Main({password_hash}, {admin_hash});
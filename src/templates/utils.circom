@@ -0,0 +1,28 @@
+pragma circom 2.1.3;
+
+// Decomposes `in` into `n` bits, which constrains it to be less than 2^n.
+template Num2Bits(n) {
+    signal input in;
+    signal output out[n];
+
+    var lc = 0;
+    var e2 = 1;
+    for (var i = 0; i < n; i++) {
+        out[i] <-- (in >> i) & 1;
+        out[i] * (out[i] - 1) === 0;
+        lc += out[i] * e2;
+        e2 = e2 + e2;
+    }
+    lc === in;
+}
+
+// Outputs 1 if `in` is zero, 0 otherwise.
+template IsZero() {
+    signal input in;
+    signal output out;
+
+    signal inv;
+    inv <-- in != 0 ? 1 / in : 0;
+    out <== -in * inv + 1;
+    in * out === 0;
+}
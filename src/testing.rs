@@ -0,0 +1,182 @@
+//! In-process stand-ins for the infrastructure of zkBitcoin (see the `testing` feature),
+//! so that downstream projects can test the deploy and use flows of their zkapps end to end
+//! without a committee, an orchestrator, or a network.
+//!
+//! The keys of a [MockCommittee] only depend on its size, so that the protocol configuration
+//! (which can only be set once per process) is the same for every test of a test binary.
+//!
+//! ```no_run
+//! # async fn example(bob_request: zkbitcoin::bob_request::BobRequest) -> anyhow::Result<()> {
+//! use std::sync::Arc;
+//! use zkbitcoin::{config::set_protocol_config, spend::Transport, testing::MockCommittee};
+//!
+//! let committee = MockCommittee::new(3, 2)?;
+//! set_protocol_config(committee.protocol_config())?;
+//!
+//! // either use the orchestrator directly...
+//! let orchestrator = Arc::new(committee.orchestrator());
+//! let bob_response = orchestrator.unlock_funds(&bob_request).await?;
+//!
+//! // ...or have `spend::execute` send requests to it
+//! let transport = Transport::InProcess(orchestrator);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use bitcoin::{Transaction, Txid};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+use crate::{
+    chain::ChainBackend,
+    client::CommitteeMember,
+    committee::{
+        dealer::{self, GeneratedCommittee},
+        node::NodeState,
+        orchestrator::Orchestrator,
+        signer::SignerKind,
+    },
+    config::ProtocolConfig,
+};
+
+/// The seed the keys of mock committees are derived from.
+const MOCK_SEED: u64 = 0;
+
+//
+// Committee
+//
+
+/// A committee whose members run in-process, with deterministic keys.
+#[derive(Debug, Clone)]
+pub struct MockCommittee {
+    /// The keys and configuration of the committee.
+    pub committee: GeneratedCommittee,
+}
+
+impl MockCommittee {
+    /// A `threshold`-of-`num` committee, which always gets the same keys for the same `num` and `threshold`.
+    pub fn new(num: u16, threshold: u16) -> Result<Self> {
+        let rng = &mut ChaCha20Rng::seed_from_u64(MOCK_SEED);
+        let committee = dealer::generate_with_rng(num, threshold, 0, SignerKind::Frost, rng)?;
+        Ok(Self { committee })
+    }
+
+    /// The protocol configuration locking zkapps to the committee
+    /// (to pass to [crate::config::set_protocol_config] before anything else).
+    pub fn protocol_config(&self) -> ProtocolConfig {
+        ProtocolConfig {
+            zkbitcoin_pubkey: hex::encode(self.committee.pubkey()),
+            ..ProtocolConfig::default()
+        }
+    }
+
+    /// The members of the committee, as nodes running in-process (without a light client).
+    pub fn members(&self) -> HashMap<frost_secp256k1_tr::Identifier, Box<dyn CommitteeMember>> {
+        self.committee
+            .key_packages
+            .iter()
+            .map(|(id, key_package)| {
+                let member: Box<dyn CommitteeMember> = Box::new(NodeState::new(
+                    key_package.clone(),
+                    self.committee.pubkey_package.clone(),
+                ));
+                (*id, member)
+            })
+            .collect()
+    }
+
+    /// An orchestrator talking to the members of the committee in-process.
+    pub fn orchestrator(&self) -> Orchestrator {
+        Orchestrator::with_members(
+            self.committee.pubkey_package.clone(),
+            self.committee.config.clone(),
+            self.members(),
+        )
+    }
+}
+
+//
+// Chain
+//
+
+/// A chain kept in memory, on which transactions are only confirmed when [MockChain::mine] is called.
+#[derive(Debug, Default)]
+pub struct MockChain {
+    /// The transactions on chain, along with their number of confirmations.
+    transactions: Mutex<HashMap<Txid, (Transaction, usize)>>,
+}
+
+impl MockChain {
+    /// Adds a transaction to the chain, with the given number of confirmations.
+    pub fn insert(&self, tx: Transaction, confirmations: usize) -> Txid {
+        let txid = tx.txid();
+        self.transactions
+            .lock()
+            .unwrap()
+            .insert(txid, (tx, confirmations));
+        txid
+    }
+
+    /// Mines a block, confirming every transaction one more time.
+    pub fn mine(&self) {
+        for (_, confirmations) in self.transactions.lock().unwrap().values_mut() {
+            *confirmations += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl ChainBackend for MockChain {
+    async fn get_transaction(&self, txid: Txid) -> Result<(Transaction, usize)> {
+        self.transactions
+            .lock()
+            .unwrap()
+            .get(&txid)
+            .cloned()
+            .with_context(|| format!("transaction {txid} is not on the mock chain"))
+    }
+
+    async fn broadcast(&self, tx: &Transaction) -> Result<Txid> {
+        Ok(self.insert(tx.clone(), 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{absolute::LockTime, hashes::Hash, transaction};
+
+    use super::*;
+
+    #[test]
+    fn test_mock_committee_is_deterministic() {
+        let committee = MockCommittee::new(3, 2).unwrap();
+        let again = MockCommittee::new(3, 2).unwrap();
+        assert_eq!(committee.committee.pubkey(), again.committee.pubkey());
+        assert_eq!(committee.committee.pubkey()[0], 2);
+        committee.protocol_config().validate().unwrap();
+
+        let orchestrator = committee.orchestrator();
+        assert_eq!(orchestrator.committee_cfg().threshold, 2);
+        assert_eq!(committee.members().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_mock_chain() {
+        let chain = MockChain::default();
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![],
+        };
+        let txid = chain.broadcast(&tx).await.unwrap();
+        assert_eq!(chain.get_transaction(txid).await.unwrap().1, 0);
+        chain.mine();
+        assert_eq!(chain.get_transaction(txid).await.unwrap().1, 1);
+        assert!(chain.get_transaction(Txid::all_zeros()).await.is_err());
+    }
+}
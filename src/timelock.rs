@@ -0,0 +1,340 @@
+//! Timelocks of zkapps: conditions on when a zkapp can be spent, committed on chain with it (see [crate::zkapp_data]),
+//! which the committee only signs transactions honoring.
+//!
+//! A timelock is enforced by Bitcoin itself once the committee signed: the transaction spending the zkapp carries it
+//! in its lock time (for an absolute timelock, like `OP_CHECKLOCKTIMEVERIFY`) or in the sequence of the zkapp's input
+//! (for a relative one, like `OP_CHECKSEQUENCEVERIFY`), which the signature commits to.
+//! So the committee only has to check that the transaction does, and the network refuses it until then.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::{absolute::LockTime, Sequence, Transaction};
+use serde::{Deserialize, Serialize};
+
+/// The length of an encoded timelock: its kind, then its consensus value (big-endian).
+pub const TIMELOCK_LEN: usize = 5;
+
+/// Lock times from this value on are UNIX timestamps, and block heights below it.
+const LOCK_TIME_THRESHOLD: u32 = 500_000_000;
+
+/// The flag of relative lock times in units of 512 seconds (see BIP 68).
+const SEQUENCE_TYPE_FLAG: u32 = 1 << 22;
+
+/// The flag disabling the relative lock time of an input (see BIP 68).
+const SEQUENCE_DISABLE_FLAG: u32 = 1 << 31;
+
+/// The bits of the value of a relative lock time.
+const SEQUENCE_MASK: u32 = 0xffff;
+
+/// The granularity (in seconds) of relative lock times in time units.
+const SEQUENCE_GRANULARITY: u32 = 512;
+
+const ABSOLUTE: u8 = 1;
+const RELATIVE: u8 = 2;
+
+/// When a zkapp can be spent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Timelock {
+    /// Not before a block height (below 500,000,000) or a UNIX timestamp (compared with the median time past),
+    /// as the lock time of the transaction.
+    Absolute(u32),
+
+    /// Not before some blocks, or some multiple of 512 seconds, since the zkapp was last updated,
+    /// as the sequence of the zkapp's input (encoded as in BIP 68).
+    Relative(u32),
+}
+
+impl Timelock {
+    /// Ensures that the timelock is a valid lock time.
+    pub fn validate(&self) -> Result<()> {
+        match *self {
+            Timelock::Absolute(lock_time) => {
+                ensure!(lock_time > 0, "an absolute timelock can't be 0")
+            }
+            Timelock::Relative(sequence) => {
+                ensure!(
+                    sequence & !(SEQUENCE_TYPE_FLAG | SEQUENCE_MASK) == 0,
+                    "the relative timelock {sequence:#x} sets bits BIP 68 doesn't use"
+                );
+                ensure!(
+                    sequence & SEQUENCE_MASK > 0,
+                    "a relative timelock can't be 0"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    //
+    // Encoding
+    //
+
+    /// Encodes the timelock (see [TIMELOCK_LEN]).
+    pub fn encode(&self) -> [u8; TIMELOCK_LEN] {
+        let (kind, value) = match *self {
+            Timelock::Absolute(lock_time) => (ABSOLUTE, lock_time),
+            Timelock::Relative(sequence) => (RELATIVE, sequence),
+        };
+        let mut bytes = [kind, 0, 0, 0, 0];
+        bytes[1..].copy_from_slice(&value.to_be_bytes());
+        bytes
+    }
+
+    /// Decodes a timelock (see [TIMELOCK_LEN]).
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        ensure!(
+            bytes.len() == TIMELOCK_LEN,
+            "a timelock is {TIMELOCK_LEN} bytes, not {}",
+            bytes.len()
+        );
+        let value = u32::from_be_bytes(bytes[1..].try_into().unwrap());
+        let timelock = match bytes[0] {
+            ABSOLUTE => Timelock::Absolute(value),
+            RELATIVE => Timelock::Relative(value),
+            kind => bail!("unknown kind of timelock {kind}"),
+        };
+        timelock.validate()?;
+        Ok(timelock)
+    }
+
+    //
+    // Transactions
+    //
+
+    /// Makes the transaction spending the zkapp (with its `input`) honor the timelock.
+    pub fn apply(&self, tx: &mut Transaction, input: usize) {
+        match *self {
+            // (the sequence of the input must not be final for the lock time to apply, which isn't the case of RBF ones)
+            Timelock::Absolute(lock_time) => tx.lock_time = LockTime::from_consensus(lock_time),
+            Timelock::Relative(sequence) => {
+                tx.input[input].sequence = Sequence::from_consensus(sequence)
+            }
+        }
+    }
+
+    /// Ensures that the transaction spending the zkapp (with its `input`) honors the timelock,
+    /// so that it can't be mined before the timelock expires.
+    pub fn check(&self, tx: &Transaction, input: usize) -> Result<()> {
+        let sequence = tx
+            .input
+            .get(input)
+            .context("the transaction doesn't have the zkapp's input")?
+            .sequence
+            .to_consensus_u32();
+        match *self {
+            Timelock::Absolute(lock_time) => {
+                let tx_lock_time = tx.lock_time.to_consensus_u32();
+                ensure!(
+                    sequence != Sequence::MAX.to_consensus_u32(),
+                    "the zkapp's input is final, which disables the lock time of the transaction"
+                );
+                ensure!(
+                    (tx_lock_time < LOCK_TIME_THRESHOLD) == (lock_time < LOCK_TIME_THRESHOLD),
+                    "the lock time of the transaction isn't in the unit of the timelock ({self})"
+                );
+                ensure!(
+                    tx_lock_time >= lock_time,
+                    "the lock time of the transaction ({tx_lock_time}) is before the timelock ({self})"
+                );
+            }
+            Timelock::Relative(lock) => {
+                ensure!(
+                    tx.version.0 >= 2,
+                    "relative timelocks only apply to transactions of version 2 and above"
+                );
+                ensure!(
+                    sequence & SEQUENCE_DISABLE_FLAG == 0,
+                    "the sequence of the zkapp's input disables relative lock times"
+                );
+                ensure!(
+                    sequence & SEQUENCE_TYPE_FLAG == lock & SEQUENCE_TYPE_FLAG,
+                    "the sequence of the zkapp's input isn't in the unit of the timelock ({self})"
+                );
+                ensure!(
+                    sequence & SEQUENCE_MASK >= lock & SEQUENCE_MASK,
+                    "the sequence of the zkapp's input is shorter than the timelock ({self})"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Timelock {
+    /// Formats the timelock as `height:<height>`, `time:<timestamp>`, `blocks:<blocks>`, or `seconds:<seconds>`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Timelock::Absolute(lock_time) if lock_time < LOCK_TIME_THRESHOLD => {
+                write!(f, "height:{lock_time}")
+            }
+            Timelock::Absolute(lock_time) => write!(f, "time:{lock_time}"),
+            Timelock::Relative(sequence) if sequence & SEQUENCE_TYPE_FLAG == 0 => {
+                write!(f, "blocks:{}", sequence & SEQUENCE_MASK)
+            }
+            Timelock::Relative(sequence) => write!(
+                f,
+                "seconds:{}",
+                (sequence & SEQUENCE_MASK) * SEQUENCE_GRANULARITY
+            ),
+        }
+    }
+}
+
+impl FromStr for Timelock {
+    type Err = anyhow::Error;
+
+    /// Parses a timelock formatted as `height:<height>` or `time:<timestamp>` (absolute),
+    /// or `blocks:<blocks>` or `seconds:<seconds>` (relative, in multiples of 512 seconds).
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, value) = s.split_once(':').with_context(|| {
+            format!("the timelock {s} is not `height:`, `time:`, `blocks:`, or `seconds:` followed by a number")
+        })?;
+        let value: u32 = value
+            .parse()
+            .with_context(|| format!("the timelock {s} is not followed by a number"))?;
+        let timelock = match kind {
+            "height" => {
+                ensure!(
+                    value < LOCK_TIME_THRESHOLD,
+                    "block heights are below {LOCK_TIME_THRESHOLD}"
+                );
+                Timelock::Absolute(value)
+            }
+            "time" => {
+                ensure!(
+                    value >= LOCK_TIME_THRESHOLD,
+                    "timestamps are from {LOCK_TIME_THRESHOLD} on"
+                );
+                Timelock::Absolute(value)
+            }
+            "blocks" => {
+                ensure!(
+                    value <= SEQUENCE_MASK,
+                    "relative timelocks are at most {SEQUENCE_MASK} blocks"
+                );
+                Timelock::Relative(value)
+            }
+            "seconds" => {
+                ensure!(
+                    value % SEQUENCE_GRANULARITY == 0,
+                    "relative timelocks in seconds are multiples of {SEQUENCE_GRANULARITY}"
+                );
+                let units = value / SEQUENCE_GRANULARITY;
+                ensure!(
+                    units <= SEQUENCE_MASK,
+                    "relative timelocks are at most {} seconds",
+                    SEQUENCE_MASK * SEQUENCE_GRANULARITY
+                );
+                Timelock::Relative(SEQUENCE_TYPE_FLAG | units)
+            }
+            _ => bail!("unknown kind of timelock `{kind}` (expected `height`, `time`, `blocks`, or `seconds`)"),
+        };
+        timelock.validate()?;
+        Ok(timelock)
+    }
+}
+
+impl TryFrom<String> for Timelock {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Timelock> for String {
+    fn from(timelock: Timelock) -> Self {
+        timelock.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{hashes::Hash, transaction::Version, OutPoint, TxIn, Txid};
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        for s in [
+            "height:840000",
+            "time:1700000000",
+            "blocks:144",
+            "seconds:5120",
+        ] {
+            let timelock: Timelock = s.parse().unwrap();
+            assert_eq!(timelock.to_string(), s);
+            assert_eq!(Timelock::decode(&timelock.encode()).unwrap(), timelock);
+        }
+        assert_eq!(
+            "seconds:1024".parse::<Timelock>().unwrap(),
+            Timelock::Relative(SEQUENCE_TYPE_FLAG | 2)
+        );
+
+        for s in [
+            "840000",
+            "height:500000000",
+            "time:1",
+            "blocks:65536",
+            "seconds:1000",
+            "blocks:0",
+            "weeks:2",
+        ] {
+            assert!(s.parse::<Timelock>().is_err(), "{s}");
+        }
+        assert!(Timelock::decode(&[3, 0, 0, 0, 1]).is_err());
+        assert!(Timelock::decode(&[RELATIVE, 0x80, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let spend = || Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(Txid::all_zeros(), 0),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                ..Default::default()
+            }],
+            output: vec![],
+        };
+
+        // transactions honor the timelocks applied to them, and not the others
+        for (timelock, shorter) in [
+            (Timelock::Absolute(840_000), Timelock::Absolute(839_999)),
+            (
+                Timelock::Absolute(1_700_000_000),
+                Timelock::Absolute(1_600_000_000),
+            ),
+            (Timelock::Relative(144), Timelock::Relative(143)),
+            (
+                Timelock::Relative(SEQUENCE_TYPE_FLAG | 10),
+                Timelock::Relative(SEQUENCE_TYPE_FLAG | 9),
+            ),
+        ] {
+            let mut tx = spend();
+            assert!(timelock.check(&tx, 0).is_err());
+            shorter.apply(&mut tx, 0);
+            assert!(timelock.check(&tx, 0).is_err());
+            timelock.apply(&mut tx, 0);
+            timelock.check(&tx, 0).unwrap();
+            shorter.check(&tx, 0).unwrap();
+        }
+
+        // in the same unit
+        let mut tx = spend();
+        Timelock::Absolute(1_700_000_000).apply(&mut tx, 0);
+        assert!(Timelock::Absolute(840_000).check(&tx, 0).is_err());
+        let mut tx = spend();
+        Timelock::Relative(SEQUENCE_TYPE_FLAG | 1000).apply(&mut tx, 0);
+        assert!(Timelock::Relative(144).check(&tx, 0).is_err());
+
+        // with an input that doesn't disable them
+        let mut tx = spend();
+        Timelock::Absolute(840_000).apply(&mut tx, 0);
+        tx.input[0].sequence = Sequence::MAX;
+        assert!(Timelock::Absolute(840_000).check(&tx, 0).is_err());
+    }
+}
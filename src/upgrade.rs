@@ -0,0 +1,219 @@
+//! Upgrading a stateful zkapp to another circuit (what `zkbtc upgrade-zkapp` does).
+//!
+//! Zkapps opt into upgrades with a last public input, `upgrade_to` (see [UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN]):
+//! the truncated hash of the verifier key the zkapp is upgraded to (see [truncate_vk_hash]),
+//! which is [NO_UPGRADE] when the zkapp is only used. The circuit decides when an upgrade is allowed
+//! (its upgrade condition, e.g. knowing the secret of an administrator), and how the state is migrated:
+//! the new state it outputs is the one the upgraded zkapp starts from.
+//!
+//! An upgrade is a spend of the zkapp moving all of its funds to a zkapp committing to the new verifier key,
+//! which the proof is bound to (through the txid) like any other spend.
+//! The committee checks that the upgraded zkapp commits to the verifier key the proof upgrades it to.
+//!
+//! The administrator of an administered zkapp (see [ZkappData::admin]) is kept by upgrades:
+//! the new circuit has to take the admin key too, and the upgrade condition is usually knowing its secret.
+//! The timelock of a timelocked zkapp is kept too (and honored by the upgrade, like by any other spend),
+//! and so is the recovery of a recoverable zkapp.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{Amount, Txid};
+use log::info;
+
+use crate::{
+    bob_request::{
+        extract_smart_contract_from_tx, fetch_zkapp_tx, new_nonce, BobResponse, PreparedSpend,
+    },
+    chain::ChainBackend,
+    coin_selection::Funding,
+    constants::{
+        ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN, NO_UPGRADE, STATEFUL_ZKAPP_PUBLIC_INPUT_LEN,
+        UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN,
+    },
+    deploy::{Signed, Signer},
+    json_rpc_stuff::RpcCtx,
+    plonk::{self, ProofSystemKind},
+    proof_system,
+    prover::{LocalProver, Prover},
+    spend::{self, Transport},
+    truncate_vk_hash,
+    zkapp_data::{ZkappData, ZkappMetadata},
+};
+
+/// What's needed to upgrade a zkapp.
+pub struct UpgradeParams<'a> {
+    /// The node whose wallet funds (and signs, see [UpgradeParams::signer]) the transaction.
+    pub rpc_ctx: &'a RpcCtx,
+
+    /// Where transactions are fetched from, and broadcast to.
+    pub chain: &'a dyn ChainBackend,
+
+    /// The transaction that deployed (or last updated) the zkapp.
+    pub zkapp_txid: Txid,
+
+    /// The circuit of the zkapp, and the inputs satisfying its upgrade condition
+    /// (the ones zkBitcoin fills in aside).
+    pub circom_circuit_path: PathBuf,
+    pub proof_inputs: HashMap<String, Vec<String>>,
+
+    /// The circuit the zkapp is upgraded to, and the proof system to set it up with.
+    pub new_circuit_path: PathBuf,
+    pub new_proof_system: ProofSystemKind,
+
+    /// The name (and the like) of the upgraded zkapp, if it changes with the upgrade.
+    pub metadata: Option<ZkappMetadata>,
+
+    /// How to fund the transaction (the fee of the committee being quoted by the orchestrator).
+    pub funding: Funding,
+
+    /// How to reach the orchestrator.
+    pub transport: Transport,
+
+    /// Who signs the inputs of the wallet, once the committee signed the zkapp's.
+    pub signer: Signer,
+}
+
+/// An upgrade of a zkapp (see [execute]).
+#[derive(Debug, Clone)]
+pub struct Upgrade {
+    /// The transaction signed by the committee.
+    pub response: BobResponse,
+
+    /// The verifier key of the new circuit, and its hash (which the upgraded zkapp commits to).
+    pub vk: plonk::VerifierKey,
+    pub vk_hash: [u8; 32],
+
+    /// The state the upgraded zkapp starts from, as migrated by the circuit of the zkapp.
+    pub new_state: String,
+
+    /// What happened to the transaction once signed by the wallet.
+    pub signed: Signed,
+}
+
+/// Compiles the new circuit, proves the upgrade condition of the zkapp, has the committee sign the transaction
+/// moving the funds of the zkapp to the upgraded zkapp (paying the fee quoted by the orchestrator),
+/// then has `signer` sign the rest.
+pub async fn execute(params: &UpgradeParams<'_>) -> Result<Upgrade> {
+    let zkapp_tx = fetch_zkapp_tx(params.chain, params.zkapp_txid).await?;
+    let smart_contract = extract_smart_contract_from_tx(&zkapp_tx)?;
+    let prev_state = smart_contract
+        .state
+        .clone()
+        .context("only stateful zkapps can be upgraded")?;
+    if let Some(metadata) = &params.metadata {
+        metadata.validate()?;
+    }
+
+    // compile the new circuit, which must be the one of a stateful zkapp too
+    let vk = proof_system::for_circuit(&params.new_circuit_path, params.new_proof_system)?
+        .verifier_key(&params.new_circuit_path)
+        .await?;
+    let vk_hash = vk.hash();
+    ensure!(
+        vk_hash != smart_contract.vk_hash,
+        "the zkapp already is this circuit"
+    );
+    if let Some(n_public) = vk.n_public() {
+        match &smart_contract.admin {
+            Some(_) => ensure!(
+                n_public == ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN,
+                "the zkapp has an administrator, so the new circuit must take its admin key ({ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN} public inputs, not {n_public})"
+            ),
+            None => ensure!(
+                matches!(
+                    n_public,
+                    STATEFUL_ZKAPP_PUBLIC_INPUT_LEN | UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN
+                ),
+                "the new circuit is not the one of a stateful zkapp without an administrator ({n_public} public inputs)"
+            ),
+        }
+    }
+    info!("- compiled {}", params.new_circuit_path.display());
+
+    // the upgrade moves no funds in or out
+    let upgrade_to = truncate_vk_hash(&vk_hash);
+    ensure!(
+        upgrade_to != NO_UPGRADE,
+        "the hash of the new circuit truncates to {NO_UPGRADE}, which means no upgrade"
+    );
+    let mut proof_inputs = params.proof_inputs.clone();
+    for (name, value) in [
+        ("prev_state", prev_state),
+        ("amount_out", "0".to_string()),
+        ("amount_in", "0".to_string()),
+        ("upgrade_to", upgrade_to),
+        ("truncated_txid", "0".to_string()),
+    ] {
+        proof_inputs.insert(name.to_string(), vec![value]);
+    }
+    if let Some(admin) = &smart_contract.admin {
+        proof_inputs.insert("admin".to_string(), vec![admin.clone()]);
+    }
+
+    // prove with a 0 txid to get the migrated state, which the transaction commits to
+    // (see [crate::bob_request::BobRequest::new])
+    let prover = LocalProver::new(&params.circom_circuit_path);
+    let (_proof, public_inputs, _vk) = prover.prove(&smart_contract.vk_hash, &proof_inputs).await?;
+    ensure!(
+        matches!(
+            public_inputs.0.len(),
+            UPGRADABLE_ZKAPP_PUBLIC_INPUT_LEN | ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN
+        ),
+        "the zkapp can't be upgraded (its circuit doesn't take the `upgrade_to` public input)"
+    );
+    let new_state = public_inputs.new_state();
+    info!("- the upgraded zkapp starts from the state {new_state}");
+
+    // create and fund the transaction (the committee's fee being the one on nothing withdrawn)
+    let new_zkapp = ZkappData {
+        metadata_hash: match &params.metadata {
+            Some(metadata) => Some(metadata.hash()),
+            None => smart_contract.metadata_hash,
+        },
+        admin: smart_contract.admin.clone(),
+        timelock: smart_contract.timelock,
+        recovery: smart_contract.recovery,
+        deployer: smart_contract.deployer,
+        ..ZkappData::new(vk_hash, Some(new_state.clone()))
+    };
+    let quote = params.transport.fee_quote(Amount::ZERO, false).await?;
+    let funding = Funding {
+        zkbitcoin_fee: quote.schedule,
+        ..params.funding.clone()
+    };
+    let prepared =
+        PreparedSpend::upgrade(params.rpc_ctx, params.chain, zkapp_tx, &new_zkapp, &funding)
+            .await?;
+
+    // prove again, bound to the transaction this time
+    proof_inputs.insert(
+        "truncated_txid".to_string(),
+        vec![prepared.truncated_txid.clone()],
+    );
+    let (proof, public_inputs, zkapp_vk) =
+        prover.prove(&smart_contract.vk_hash, &proof_inputs).await?;
+    let prev_outs = prepared.prev_outs.clone();
+    let bob_request = prepared
+        .into_request(zkapp_vk, proof, &public_inputs)?
+        .with_nonce(&new_nonce())?;
+
+    // have the committee sign it, then the wallet
+    let response = params.transport.send(bob_request).await?;
+    let signed = spend::sign(
+        params.rpc_ctx,
+        params.chain,
+        &params.signer,
+        &response,
+        &prev_outs,
+    )
+    .await?;
+
+    Ok(Upgrade {
+        response,
+        vk,
+        vk_hash,
+        new_state,
+        signed,
+    })
+}
@@ -0,0 +1,133 @@
+//! WebAssembly bindings, so that browser wallets can build and package Bob requests
+//! without a Bitcoin Core node (build with `wasm-pack build -- --no-default-features --features wasm`).
+//!
+//! The flow mirrors `BobRequest::new`, with the wallet and the prover of the user in place of Bitcoin Core and snarkjs:
+//!
+//! 1. [build_spend] creates the unsigned transaction spending the zkapp,
+//!    which the wallet funds (adding inputs for the fee, and a change output).
+//! 2. [truncated_txid] gives the `truncated_txid` public input of the funded transaction,
+//!    with which the user proves their circuit (e.g. with snarkjs in the browser).
+//! 3. [package_request] packages the proof into a request,
+//!    and [unlock_funds_payload] gives the body to POST to the orchestrator.
+//!
+//! Transactions are passed around hex-encoded, and everything else in JSON
+//! (verifier keys, proofs, and public inputs as produced by snarkjs), as with the other bindings (see [crate::bindings]).
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    bindings::{self, parse_network, parse_tx},
+    config::{set_protocol_config, ProtocolConfig},
+    set_network, truncate_txid,
+};
+
+/// Surfaces an error (and its context) to JavaScript.
+fn js_error(err: anyhow::Error) -> JsError {
+    JsError::new(&format!("{err:#}"))
+}
+
+//
+// Configuration
+//
+
+/// Sets the network (`mainnet`, `testnet`, `signet`, or `regtest`), testnet by default.
+/// This can only be done once, before anything else.
+#[wasm_bindgen(js_name = setNetwork)]
+pub fn js_set_network(network: &str) -> Result<(), JsError> {
+    let network = parse_network(network).map_err(js_error)?;
+    set_network(network).map_err(js_error)
+}
+
+/// Sets the protocol configuration (see [ProtocolConfig]), the default one otherwise.
+/// This can only be done once, before anything else.
+#[wasm_bindgen(js_name = setProtocolConfig)]
+pub fn js_set_protocol_config(config_json: &str) -> Result<(), JsError> {
+    let config: ProtocolConfig =
+        serde_json::from_str(config_json).map_err(|err| js_error(err.into()))?;
+    set_protocol_config(config).map_err(js_error)
+}
+
+//
+// Requests
+//
+
+/// Parses the zkapp deployed (or last updated) by a transaction,
+/// and returns its `txid`, `vout`, `locked_value` (in satoshis), `vk_hash`, and `state` (if stateful).
+#[wasm_bindgen(js_name = parseZkapp)]
+pub fn parse_zkapp(zkapp_tx_hex: &str) -> Result<String, JsError> {
+    let zkapp = bindings::parse_zkapp(zkapp_tx_hex).map_err(js_error)?;
+    Ok(zkapp.to_string())
+}
+
+/// Creates the unsigned transaction spending the zkapp deployed (or last updated) by `zkapp_tx_hex`,
+/// paying `recipients` (given as `address:amount`, e.g. `address:0.001btc` or `address:100000sat`, or just `address`).
+/// Stateful zkapps also take the `new_state` computed by the circuit,
+/// and the amounts deposited (`amount_in`) and withdrawn (`amount_out`) in satoshis.
+/// The fee of the committee follows `fee_schedule_json`, the `schedule` of a quote from the orchestrator
+/// (its `fee_quote` method), or the fee of the protocol configuration if not given.
+///
+/// Returns the transaction (as `tx`) and the outputs paying the recipients (as `recipients`),
+/// which have to be passed to [package_request] once the transaction is funded.
+#[wasm_bindgen(js_name = buildSpend)]
+pub fn build_spend(
+    zkapp_tx_hex: &str,
+    recipients: Vec<String>,
+    new_state: Option<String>,
+    amount_in: u64,
+    amount_out: u64,
+    fee_schedule_json: Option<String>,
+) -> Result<String, JsError> {
+    let spend = bindings::build_spend(
+        zkapp_tx_hex,
+        &recipients,
+        new_state.as_deref(),
+        amount_in,
+        amount_out,
+        fee_schedule_json.as_deref(),
+    )
+    .map_err(js_error)?;
+    Ok(spend.to_string())
+}
+
+/// Returns the `truncated_txid` public input of the circuit, for the (funded) transaction spending the zkapp.
+#[wasm_bindgen(js_name = truncatedTxid)]
+pub fn truncated_txid(tx_hex: &str) -> Result<String, JsError> {
+    let tx = parse_tx(tx_hex).map_err(js_error)?;
+    Ok(truncate_txid(tx.txid()))
+}
+
+/// Packages a proof into a request (see [crate::bob_request::BobRequest::package]), returned in JSON.
+/// `recipients_json` are the recipient outputs returned by [build_spend],
+/// and `prev_outs_json` the outputs spent by every input of the funded transaction, in order.
+/// `nonce_hex` is a fresh random nonce of 32 bytes (see [crate::bob_request::BobRequest::nonce]),
+/// which committees protecting against replays require (e.g. from `crypto.getRandomValues`).
+#[wasm_bindgen(js_name = packageRequest)]
+pub fn package_request(
+    tx_hex: &str,
+    zkapp_tx_hex: &str,
+    vk_json: &str,
+    proof_json: &str,
+    public_inputs_json: &str,
+    recipients_json: &str,
+    prev_outs_json: &str,
+    nonce_hex: Option<String>,
+) -> Result<String, JsError> {
+    bindings::package_request(
+        tx_hex,
+        zkapp_tx_hex,
+        vk_json,
+        proof_json,
+        public_inputs_json,
+        recipients_json,
+        prev_outs_json,
+        nonce_hex.as_deref(),
+    )
+    .map_err(js_error)
+}
+
+/// Returns the JSON RPC body asking the orchestrator to unlock the funds of a request (see [package_request]).
+#[wasm_bindgen(js_name = unlockFundsPayload)]
+pub fn unlock_funds_payload(request_json: &str) -> Result<String, JsError> {
+    let payload = bindings::unlock_funds_payload(request_json).map_err(js_error)?;
+    Ok(payload.to_string())
+}
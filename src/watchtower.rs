@@ -0,0 +1,562 @@
+//! A watchtower for the zkBitcoin address (what `zkbtc watchtower` runs).
+//!
+//! The funds held by the zkBitcoin address are only spent with signatures of the committee,
+//! produced in signing sessions that the orchestrator publishes in its transparency log (see [crate::committee::transparency])
+//! and that the orchestrator and the nodes record in their audit logs (see [crate::committee::audit]).
+//! A spend that doesn't correspond to any recorded session was signed off the record, by someone holding the committee key
+//! (or a threshold of its shares): the committee was compromised.
+//!
+//! The watchtower keeps track of the outputs held by the zkBitcoin address (starting from the UTXO set, then following every block),
+//! and checks every spend of them against the records (see [Records]). When it finds spends that weren't recorded,
+//! it raises an [Alert] signed with its own key: it logs it, POSTs it to a webhook (if any),
+//! and sends it to the orchestrator to halt it (if asked to), which only orchestrators trusting the key of the watchtower act on.
+//! Recoverable zkapps aren't held by the zkBitcoin address (see [crate::recovery]), so their spends aren't watched.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{ensure, Context, Result};
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    Amount, Block, BlockHash, OutPoint, ScriptBuf, Txid,
+};
+use log::{debug, error, info, warn};
+use reqwest::header::CONTENT_TYPE;
+use secp256k1::{schnorr, Keypair, Message, Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::OrchestratorClient,
+    committee::{audit, storage::now},
+    config::protocol_config,
+    json_rpc_stuff::{
+        get_block, get_block_count, get_block_hash, http_client, scan_txout_set, RpcCtx,
+    },
+    taproot_addr_from, zkbitcoin_folder,
+};
+
+/// How often (in seconds) the watchtower checks for new blocks, by default.
+pub const DEFAULT_POLL_INTERVAL: u64 = 60;
+
+/// The file the key of the watchtower is kept in (in its directory).
+pub const KEY_FILE: &str = "watchtower-key";
+
+/// How long (in seconds) after it was raised an alert halts an orchestrator.
+pub const ALERT_VALIDITY: u64 = 600;
+
+/// The domain separator of the digests of alerts.
+const ALERT_DOMAIN: &[u8] = b"zkbitcoin watchtower alert";
+
+/// The number of blocks the watchtower remembers, to follow reorgs.
+const MAX_REORG_DEPTH: usize = 100;
+
+/// Timeout (in seconds) for webhook alerts.
+const WEBHOOK_TIMEOUT: u64 = 10;
+
+//
+// Data structures
+//
+
+/// A spend of an output held by the zkBitcoin address.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Spend {
+    /// The spending transaction.
+    pub txid: Txid,
+
+    /// The input of the transaction spending the output.
+    pub input: usize,
+
+    /// The output spent.
+    pub outpoint: OutPoint,
+
+    /// The value of the output spent.
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub value: Amount,
+
+    /// The height of the block the spend was found in.
+    pub height: u64,
+}
+
+/// Spends of the zkBitcoin address that weren't recorded, as raised by a watchtower.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Alert {
+    /// The spends.
+    pub spends: Vec<Spend>,
+
+    /// When the alert was raised (UNIX timestamp in seconds).
+    pub raised_at: u64,
+
+    /// The hex-encoded (x-only) public key of the watchtower.
+    pub signer: String,
+
+    /// The hex-encoded Schnorr signature of the alert.
+    pub signature: String,
+}
+
+impl Alert {
+    fn digest(spends: &[Spend], raised_at: u64) -> Result<[u8; 32]> {
+        let mut engine = sha256::Hash::engine();
+        engine.input(ALERT_DOMAIN);
+        engine.input(&raised_at.to_be_bytes());
+        engine.input(serde_json::to_string(spends)?.as_bytes());
+        Ok(sha256::Hash::from_engine(engine).to_byte_array())
+    }
+
+    /// Signs an alert about `spends` with the key of the watchtower.
+    pub fn sign(spends: Vec<Spend>, raised_at: u64, keypair: &Keypair) -> Result<Self> {
+        let digest = Self::digest(&spends, raised_at)?;
+        let signature = Secp256k1::signing_only()
+            .sign_schnorr_no_aux_rand(&Message::from_digest(digest), keypair);
+        Ok(Self {
+            spends,
+            raised_at,
+            signer: hex::encode(keypair.x_only_public_key().0.serialize()),
+            signature: hex::encode(signature.as_ref()),
+        })
+    }
+
+    /// The key of the watchtower that raised the alert.
+    pub fn signer(&self) -> Result<XOnlyPublicKey> {
+        XOnlyPublicKey::from_slice(&hex::decode(&self.signer)?)
+            .context("the alert has an invalid signer")
+    }
+
+    /// Checks the signature of the alert (by `pubkey`, if given).
+    pub fn verify(&self, pubkey: Option<&XOnlyPublicKey>) -> Result<()> {
+        let signer = self.signer()?;
+        if let Some(pubkey) = pubkey {
+            ensure!(
+                &signer == pubkey,
+                "the alert is signed by another key ({signer})"
+            );
+        }
+        let signature = schnorr::Signature::from_slice(&hex::decode(&self.signature)?)
+            .context("the alert has an invalid signature")?;
+        let digest = Self::digest(&self.spends, self.raised_at)?;
+        Secp256k1::verification_only()
+            .verify_schnorr(&signature, &Message::from_digest(digest), &signer)
+            .context("the alert has an incorrect signature")
+    }
+}
+
+//
+// Records
+//
+
+/// Where the watchtower looks for the signing sessions of the committee.
+pub enum Records {
+    /// The transparency log of an orchestrator, whose tree heads are signed by `pubkey` (if given).
+    Transparency {
+        orchestrator: OrchestratorClient,
+        pubkey: Option<XOnlyPublicKey>,
+    },
+
+    /// The audit log of the orchestrator, or of a committee node.
+    AuditLog(PathBuf),
+}
+
+impl Records {
+    /// Returns the spends that don't correspond to any recorded signing session.
+    /// Fails if the records can't be looked at (so that the spends can be checked again later).
+    pub async fn unrecorded(&self, spends: &[Spend]) -> Result<Vec<Spend>> {
+        let mut unrecorded = vec![];
+        match self {
+            Records::Transparency {
+                orchestrator,
+                pubkey,
+            } => {
+                // make sure that a signature missing from the log isn't just an orchestrator we can't reach
+                orchestrator
+                    .info()
+                    .await
+                    .context("couldn't reach the orchestrator to check spends")?;
+                for spend in spends {
+                    let recorded = match orchestrator.inclusion_proof(spend.txid, spend.input).await
+                    {
+                        Ok(proof) => match proof.verify(pubkey.as_ref()) {
+                            Ok(()) => {
+                                proof.leaf.txid == spend.txid
+                                    && proof.leaf.zkapp_input == spend.input
+                            }
+                            Err(err) => {
+                                warn!(
+                                    "- the inclusion proof of {} doesn't verify: {err:#}",
+                                    spend.txid
+                                );
+                                false
+                            }
+                        },
+                        Err(err) => {
+                            debug!("- no inclusion proof for {}: {err:#}", spend.txid);
+                            false
+                        }
+                    };
+                    if !recorded {
+                        unrecorded.push(spend.clone());
+                    }
+                }
+            }
+            Records::AuditLog(path) => {
+                let entries = audit::read(path)?;
+                audit::verify(&entries, None).with_context(|| {
+                    format!("the audit log {} was tampered with", path.display())
+                })?;
+                unrecorded.extend(spends.iter().cloned().filter(|spend| {
+                    !entries.iter().any(|entry| {
+                        entry.session.txid == spend.txid && entry.session.zkapp_input == spend.input
+                    })
+                }));
+            }
+        }
+        Ok(unrecorded)
+    }
+}
+
+//
+// Watchtower
+//
+
+/// Watches the outputs held by the zkBitcoin address.
+pub struct Watchtower {
+    rpc_ctx: RpcCtx,
+    records: Records,
+    keypair: Keypair,
+    /// The script of the zkBitcoin address.
+    script_pubkey: ScriptBuf,
+    /// The outputs held by the zkBitcoin address, as of the last block checked.
+    utxos: HashMap<OutPoint, Amount>,
+    /// The hashes of the last blocks checked (by height).
+    blocks: BTreeMap<u64, BlockHash>,
+    /// The spends found, waiting to be checked against the records.
+    pending: Vec<Spend>,
+    /// Where alerts are POSTed (if anywhere).
+    pub webhook: Option<String>,
+    /// The orchestrator that alerts are sent to, to halt it (if any).
+    pub halt: Option<OrchestratorClient>,
+}
+
+impl Watchtower {
+    /// The default directory of the watchtower (where its key is kept).
+    pub fn default_dir() -> PathBuf {
+        zkbitcoin_folder().join("watchtower")
+    }
+
+    /// A watchtower following the chain of the node at `rpc_ctx`, signing its alerts with `keypair`.
+    pub fn new(rpc_ctx: RpcCtx, records: Records, keypair: Keypair) -> Result<Self> {
+        let address = taproot_addr_from(&protocol_config().zkbitcoin_pubkey)?;
+        Ok(Self {
+            rpc_ctx,
+            records,
+            keypair,
+            script_pubkey: address.script_pubkey(),
+            utxos: HashMap::new(),
+            blocks: BTreeMap::new(),
+            pending: vec![],
+            webhook: None,
+            halt: None,
+        })
+    }
+
+    /// The public key the alerts are signed with.
+    pub fn pubkey(&self) -> XOnlyPublicKey {
+        self.keypair.x_only_public_key().0
+    }
+
+    /// Records the outputs a block pays to the zkBitcoin address, and returns the spends of the ones it held.
+    pub fn check_block(&mut self, height: u64, block: &Block) -> Vec<Spend> {
+        let mut spends = vec![];
+        for tx in &block.txdata {
+            let txid = tx.txid();
+            for (input, txin) in tx.input.iter().enumerate() {
+                if let Some(value) = self.utxos.remove(&txin.previous_output) {
+                    spends.push(Spend {
+                        txid,
+                        input,
+                        outpoint: txin.previous_output,
+                        value,
+                        height,
+                    });
+                }
+            }
+            for (vout, output) in tx.output.iter().enumerate() {
+                if output.script_pubkey == self.script_pubkey {
+                    self.utxos
+                        .insert(OutPoint::new(txid, vout as u32), output.value);
+                }
+            }
+        }
+        spends
+    }
+
+    /// Starts over from the outputs held by the zkBitcoin address in the UTXO set.
+    async fn rescan(&mut self) -> Result<()> {
+        let address = taproot_addr_from(&protocol_config().zkbitcoin_pubkey)?.to_string();
+        let scan = scan_txout_set(&self.rpc_ctx, &address).await?;
+        let height = scan
+            .height
+            .context("the node didn't say at which height it scanned")?;
+        let hash = match scan.best_block_hash {
+            Some(hash) => hash,
+            None => get_block_hash(&self.rpc_ctx, height).await?,
+        };
+
+        self.utxos = scan
+            .unspents
+            .into_iter()
+            .map(|utxo| (OutPoint::new(utxo.txid, utxo.vout), utxo.amount))
+            .collect();
+        self.blocks = BTreeMap::from([(height, hash)]);
+        info!(
+            "- watching {} outputs held by {address}, from height {height}",
+            self.utxos.len()
+        );
+        Ok(())
+    }
+
+    /// Checks the blocks mined since the last poll (or the ones that replaced the blocks that left the chain),
+    /// and returns the spends that don't correspond to any recorded signing session.
+    pub async fn poll(&mut self) -> Result<Vec<Spend>> {
+        if self.blocks.is_empty() {
+            self.rescan().await?;
+        }
+
+        // forget the blocks that left the chain (the outputs they spent are already checked)
+        let node_tip = get_block_count(&self.rpc_ctx).await?;
+        while let Some((&height, &hash)) = self.blocks.last_key_value() {
+            if height <= node_tip && get_block_hash(&self.rpc_ctx, height).await? == hash {
+                break;
+            }
+            warn!("- block {hash} at height {height} left the chain");
+            self.blocks.remove(&height);
+            self.pending.retain(|spend| spend.height < height);
+        }
+        let Some((&last, _)) = self.blocks.last_key_value() else {
+            warn!("- the chain was reorged deeper than {MAX_REORG_DEPTH} blocks, starting over");
+            return Ok(vec![]);
+        };
+
+        for height in last + 1..=node_tip {
+            let hash = get_block_hash(&self.rpc_ctx, height).await?;
+            let block = get_block(&self.rpc_ctx, hash).await?;
+            let spends = self.check_block(height, &block);
+            if !spends.is_empty() {
+                info!(
+                    "- {} outputs of the zkBitcoin address spent at height {height}",
+                    spends.len()
+                );
+            }
+            self.pending.extend(spends);
+            self.blocks.insert(height, hash);
+            if self.blocks.len() > MAX_REORG_DEPTH {
+                self.blocks.pop_first();
+            }
+        }
+
+        let unrecorded = self.records.unrecorded(&self.pending).await?;
+        self.pending.clear();
+        Ok(unrecorded)
+    }
+
+    /// Raises an alert about spends that weren't recorded:
+    /// logs it, POSTs it to the webhook, and sends it to the orchestrator to halt it (if set).
+    pub async fn raise(&self, spends: Vec<Spend>) -> Result<Alert> {
+        let alert = Alert::sign(spends, now(), &self.keypair)?;
+        for spend in &alert.spends {
+            error!(
+                "- ALERT: {} spent {} ({}) without a recorded signing session",
+                spend.txid, spend.outpoint, spend.value
+            );
+        }
+
+        if let Some(webhook) = &self.webhook {
+            let sent = http_client()
+                .post(webhook)
+                .timeout(Duration::from_secs(WEBHOOK_TIMEOUT))
+                .header(CONTENT_TYPE, "application/json")
+                .body(serde_json::to_string(&alert)?)
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status());
+            if let Err(err) = sent {
+                error!("- couldn't send the alert to {webhook}: {err}");
+            }
+        }
+        if let Some(orchestrator) = &self.halt {
+            match orchestrator.halt(&alert).await {
+                Ok(()) => warn!("- halted the orchestrator"),
+                Err(err) => error!("- couldn't halt the orchestrator: {err:#}"),
+            }
+        }
+        Ok(alert)
+    }
+
+    /// Watches the chain forever, checking for new blocks every `poll_interval`.
+    pub async fn run(&mut self, poll_interval: Duration) {
+        let mut interval = tokio::time::interval(poll_interval);
+        loop {
+            interval.tick().await;
+            match self.poll().await {
+                Ok(spends) if spends.is_empty() => (),
+                Ok(spends) => {
+                    if let Err(err) = self.raise(spends).await {
+                        error!("- couldn't raise an alert: {err:#}");
+                    }
+                }
+                Err(err) => error!("- couldn't check the chain: {err:#}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bitcoin::{
+        absolute::LockTime, block, transaction::Version, CompactTarget, Sequence, Transaction,
+        TxIn, TxMerkleNode, TxOut, Witness,
+    };
+    use secp256k1::SecretKey;
+
+    use super::*;
+    use crate::committee::{
+        audit::{AuditLog, SessionRecord},
+        dealer,
+        orchestrator::Orchestrator,
+        shared::MemoryState,
+        signer::SignerKind,
+    };
+
+    fn block(txdata: Vec<Transaction>) -> Block {
+        Block {
+            header: block::Header {
+                version: block::Version::TWO,
+                prev_blockhash: BlockHash::all_zeros(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: 0,
+                bits: CompactTarget::from_consensus(0),
+                nonce: 0,
+            },
+            txdata,
+        }
+    }
+
+    fn tx(inputs: &[OutPoint], outputs: Vec<TxOut>) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|outpoint| TxIn {
+                    previous_output: *outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: outputs,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_watchtower() {
+        let dir = tempdir::TempDir::new("zkbitcoin_watchtower").unwrap();
+        let keypair =
+            Keypair::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[3; 32]).unwrap());
+        let records = Records::AuditLog(dir.path().join("audit.jsonl"));
+        let mut watchtower = Watchtower::new(RpcCtx::for_testing(), records, keypair).unwrap();
+
+        // a deposit to the zkBitcoin address is tracked, and other outputs aren't
+        let held = TxOut {
+            value: Amount::from_sat(10_000),
+            script_pubkey: watchtower.script_pubkey.clone(),
+        };
+        let other = TxOut {
+            value: Amount::from_sat(5_000),
+            script_pubkey: ScriptBuf::new(),
+        };
+        let deposit = tx(&[], vec![other.clone(), held]);
+        assert!(watchtower
+            .check_block(1, &block(vec![deposit.clone()]))
+            .is_empty());
+        assert_eq!(watchtower.utxos.len(), 1);
+
+        // its spend is found, but not the ones of other outputs
+        let zkapp = OutPoint::new(deposit.txid(), 1);
+        let spend = tx(&[OutPoint::new(deposit.txid(), 0), zkapp], vec![other]);
+        let spends = watchtower.check_block(2, &block(vec![spend.clone()]));
+        assert_eq!(spends.len(), 1);
+        assert_eq!(spends[0].input, 1);
+        assert_eq!(spends[0].outpoint, zkapp);
+        assert!(watchtower.utxos.is_empty());
+
+        // it isn't recorded yet
+        let unrecorded = watchtower.records.unrecorded(&spends).await.unwrap();
+        assert_eq!(unrecorded, spends);
+
+        // once a session signing it is
+        let audit_log = AuditLog::open(dir.path()).unwrap();
+        let mut record = SessionRecord::attestation([0; 32], vec![], &[]);
+        record.txid = spend.txid();
+        record.zkapp_input = 1;
+        audit_log.append(record).unwrap();
+        assert!(watchtower
+            .records
+            .unrecorded(&spends)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // alerts are signed by the watchtower
+        let alert = watchtower.raise(unrecorded).await.unwrap();
+        alert.verify(Some(&watchtower.pubkey())).unwrap();
+        let mut tampered = alert.clone();
+        tampered.spends[0].value = Amount::from_sat(1);
+        assert!(tampered.verify(None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_halt() {
+        let committee = dealer::generate(3, 2, 0, SignerKind::Frost).unwrap();
+        let shared_state = Arc::new(MemoryState::default());
+        let mut orchestrator =
+            Orchestrator::new(committee.pubkey_package.clone(), committee.config.clone()).unwrap();
+        orchestrator.shared_state = Some(shared_state.clone());
+        let mut replica = Orchestrator::new(committee.pubkey_package, committee.config).unwrap();
+        replica.shared_state = Some(shared_state);
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[3; 32]).unwrap());
+        let spend = Spend {
+            txid: Txid::all_zeros(),
+            input: 0,
+            outpoint: OutPoint::null(),
+            value: Amount::from_sat(10_000),
+            height: 1,
+        };
+        let alert = Alert::sign(vec![spend], now(), &keypair).unwrap();
+
+        // only trusted watchtowers halt the orchestrator
+        assert!(orchestrator.halt(&alert).await.is_err());
+        orchestrator.watchtowers = vec![keypair.x_only_public_key().0];
+
+        // with fresh alerts
+        let stale =
+            Alert::sign(alert.spends.clone(), now() - ALERT_VALIDITY - 1, &keypair).unwrap();
+        assert!(orchestrator.halt(&stale).await.is_err());
+        let mut tampered = alert.clone();
+        tampered.raised_at += 1;
+        assert!(orchestrator.halt(&tampered).await.is_err());
+
+        orchestrator.halt(&alert).await.unwrap();
+        let err = orchestrator.attest(b"hello").await.unwrap_err();
+        assert!(err.to_string().contains("halted"));
+
+        // replicas sharing their state are halted too
+        let err = replica.attest(b"hello").await.unwrap_err();
+        assert!(err.to_string().contains("halted"));
+        assert!(replica.halted().is_some());
+    }
+}
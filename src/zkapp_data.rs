@@ -0,0 +1,662 @@
+//! The metadata of a zkapp, committed on chain in the `OP_RETURN` output of the transactions that deploy (or update) it.
+//!
+//! The payload pushed by the `OP_RETURN` script is the 32-byte hash of the zkapp's verifier key,
+//! followed (for stateful zkapps) by its state, as the big-endian bytes of a Circom field element.
+//! This is the first version of the encoding ([Version::V0]). It isn't prefixed by a version byte
+//! (zkapps were deployed with it before it had a number), so it is recognized by its layout:
+//! later versions have to be told apart from it the same way.
+//!
+//! Zkapps can also be given a name, a version, and an author (see [ZkappMetadata]) so that they can be told apart.
+//! These don't fit in the `OP_RETURN` output, so only their hash is committed to ([Version::V1]),
+//! and they are shared off chain: a zkapp's metadata is only shown once it is known (see [save_metadata]).
+//!
+//! Zkapps can also be deployed with the public key of an administrator (see [ZkappData::admin]),
+//! which their circuit is given as a public input to authorize privileged operations (pausing the zkapp, upgrading it, etc.).
+//! It is committed in full ([Version::V2]), as the committee reads it from the chain:
+//! such payloads are larger than 80 bytes, which Bitcoin Core only relays by default since version 30.
+//!
+//! Zkapps can also be timelocked (see [ZkappData::timelock]): the committee only signs transactions spending them
+//! that can't be mined before the timelock expires. The timelock is committed after the admin key ([Version::V3]).
+//!
+//! Zkapps can also be made recoverable by their depositor (see [ZkappData::recovery]), which changes the output
+//! their funds are locked in: the depositor key and the delay are committed after the timelock ([Version::V4]),
+//! so that the output can be told from the metadata (see [ZkappData::script_pubkey]).
+//!
+//! Zkapps can also be deployed with the public key of their deployer (see [ZkappData::deployer]), the only key the
+//! orchestrator accepts registrations of a policy for the zkapp from (see [crate::committee::policy]).
+//! It is committed after the recovery ([Version::V5]).
+
+use std::fmt;
+
+use anyhow::{bail, ensure, Context, Result};
+use bitcoin::{
+    hashes::{sha256, Hash, HashEngine},
+    opcodes::all::OP_RETURN,
+    script::{Instruction, PushBytesBuf},
+    Script, ScriptBuf,
+};
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    circom_field_from_bytes, circom_field_to_bytes,
+    recovery::{self, Recovery, RECOVERY_LEN},
+    timelock::{Timelock, TIMELOCK_LEN},
+};
+
+/// The length of the hash of a verifier key.
+pub const VK_HASH_LEN: usize = 32;
+
+/// The length of the hash of a zkapp's metadata (a truncated SHA-256).
+pub const METADATA_HASH_LEN: usize = 16;
+
+/// The longest state (a Circom field element is less than 2^254).
+const MAX_STATE_LEN: usize = 32;
+
+/// The length of [Version::V1] payloads: the verifier key hash, the state slot, and the metadata hash
+/// (which is the most data Bitcoin Core relays in an `OP_RETURN` output by default).
+const V1_PAYLOAD_LEN: usize = VK_HASH_LEN + MAX_STATE_LEN + METADATA_HASH_LEN;
+
+/// The state slot of [Version::V1] payloads of stateless zkapps (which isn't a field element).
+const NO_STATE: [u8; MAX_STATE_LEN] = [0xff; MAX_STATE_LEN];
+
+/// The length of the admin key slot (a Circom field element).
+const ADMIN_LEN: usize = 32;
+
+/// The length of [Version::V2] payloads: a [Version::V1] payload followed by the admin key.
+const V2_PAYLOAD_LEN: usize = V1_PAYLOAD_LEN + ADMIN_LEN;
+
+/// The metadata slot of [Version::V2] payloads of zkapps deployed without metadata.
+const NO_METADATA: [u8; METADATA_HASH_LEN] = [0; METADATA_HASH_LEN];
+
+/// The length of [Version::V3] payloads: a [Version::V2] payload followed by the timelock.
+const V3_PAYLOAD_LEN: usize = V2_PAYLOAD_LEN + TIMELOCK_LEN;
+
+/// The admin key slot of [Version::V3] payloads of zkapps without an administrator (which isn't a field element).
+const NO_ADMIN: [u8; ADMIN_LEN] = [0xff; ADMIN_LEN];
+
+/// The length of [Version::V4] payloads: a [Version::V3] payload followed by the recovery.
+const V4_PAYLOAD_LEN: usize = V3_PAYLOAD_LEN + RECOVERY_LEN;
+
+/// The timelock slot of [Version::V4] payloads of zkapps without a timelock (which isn't a timelock).
+const NO_TIMELOCK: [u8; TIMELOCK_LEN] = [0; TIMELOCK_LEN];
+
+/// The length of the deployer key slot (an x-only public key).
+const DEPLOYER_LEN: usize = 32;
+
+/// The length of [Version::V5] payloads: a [Version::V4] payload followed by the deployer key.
+const V5_PAYLOAD_LEN: usize = V4_PAYLOAD_LEN + DEPLOYER_LEN;
+
+/// The recovery slot of [Version::V5] payloads of zkapps that aren't recoverable (which isn't a public key).
+const NO_RECOVERY: [u8; RECOVERY_LEN] = [0; RECOVERY_LEN];
+
+/// The longest field of a zkapp's metadata (in characters).
+pub const MAX_METADATA_FIELD_LEN: usize = 64;
+
+/// The domain separator of the hashes of metadata.
+const METADATA_DOMAIN: &[u8] = b"zkbitcoin zkapp metadata";
+
+//
+// Data structures
+//
+
+/// The versions of the encoding of [ZkappData].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Version {
+    /// The hash of the verifier key, then the state (if any), without a version byte.
+    V0,
+
+    /// The hash of the verifier key, the state (padded to 32 bytes, or all `0xff` if there's none),
+    /// then the hash of the metadata: 80 bytes, more than any [Version::V0] payload.
+    V1,
+
+    /// A [Version::V1] payload (with a metadata hash of all zeros if there's none), then the admin key padded to 32 bytes.
+    V2,
+
+    /// A [Version::V2] payload (with an admin key of all `0xff` if there's none), then the timelock (see [Timelock::encode]).
+    V3,
+
+    /// A [Version::V3] payload (with a timelock of all zeros if there's none), then the recovery (see [Recovery::encode]).
+    V4,
+
+    /// A [Version::V4] payload (with a recovery of all zeros if there's none), then the x-only deployer key.
+    V5,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V0 => write!(f, "0"),
+            Self::V1 => write!(f, "1"),
+            Self::V2 => write!(f, "2"),
+            Self::V3 => write!(f, "3"),
+            Self::V4 => write!(f, "4"),
+            Self::V5 => write!(f, "5"),
+        }
+    }
+}
+
+/// The metadata of a zkapp.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZkappData {
+    /// The hash of the zkapp's verifier key.
+    pub vk_hash: [u8; VK_HASH_LEN],
+
+    /// The state of the zkapp (as a decimal Circom field element), if it is stateful.
+    pub state: Option<String>,
+
+    /// The hash of the zkapp's [ZkappMetadata], if it was deployed with some.
+    pub metadata_hash: Option<[u8; METADATA_HASH_LEN]>,
+
+    /// The public key of the zkapp's administrator (as a decimal Circom field element, e.g. the Poseidon hash of a secret),
+    /// if it was deployed with one. Its circuit takes it as its last public input (see [crate::constants::ADMINISTERED_ZKAPP_PUBLIC_INPUT_LEN]).
+    pub admin: Option<String>,
+
+    /// When the zkapp can be spent, if it was deployed with a timelock.
+    pub timelock: Option<Timelock>,
+
+    /// Who can recover the funds of the zkapp should the committee stop signing, and after how long,
+    /// if it was deployed recoverable.
+    pub recovery: Option<Recovery>,
+
+    /// The key policies of the zkapp must be signed with to be registered (see [crate::committee::policy]),
+    /// if it was deployed with one.
+    pub deployer: Option<XOnlyPublicKey>,
+}
+
+impl ZkappData {
+    /// The metadata of a zkapp without a name (and the like).
+    pub fn new(vk_hash: [u8; VK_HASH_LEN], state: Option<String>) -> Self {
+        Self {
+            vk_hash,
+            state,
+            metadata_hash: None,
+            admin: None,
+            timelock: None,
+            recovery: None,
+            deployer: None,
+        }
+    }
+
+    /// The version of the encoding the metadata is committed with.
+    pub fn version(&self) -> Version {
+        if self.deployer.is_some() {
+            Version::V5
+        } else if self.recovery.is_some() {
+            Version::V4
+        } else if self.timelock.is_some() {
+            Version::V3
+        } else if self.admin.is_some() {
+            Version::V2
+        } else if self.metadata_hash.is_some() {
+            Version::V1
+        } else {
+            Version::V0
+        }
+    }
+
+    //
+    // Payload
+    //
+
+    /// Encodes the metadata into the payload of an `OP_RETURN` output.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let state = self
+            .state
+            .as_ref()
+            .map(|state| {
+                circom_field_to_bytes(state)
+                    .with_context(|| format!("the state {state} is not a field element"))
+            })
+            .transpose()?;
+
+        let admin = self
+            .admin
+            .as_ref()
+            .map(|admin| {
+                circom_field_to_bytes(admin)
+                    .with_context(|| format!("the admin key {admin} is not a field element"))
+            })
+            .transpose()?;
+
+        let mut payload = self.vk_hash.to_vec();
+        if self.version() == Version::V0 {
+            payload.extend(state.unwrap_or_default());
+            return Ok(payload);
+        }
+        match state {
+            Some(state) => {
+                payload.extend(vec![0; MAX_STATE_LEN - state.len()]);
+                payload.extend(state);
+            }
+            None => payload.extend(NO_STATE),
+        }
+        payload.extend(self.metadata_hash.unwrap_or(NO_METADATA));
+        match admin {
+            Some(admin) => {
+                payload.extend(vec![0; ADMIN_LEN - admin.len()]);
+                payload.extend(admin);
+            }
+            None if self.version() >= Version::V3 => payload.extend(NO_ADMIN),
+            None => (),
+        }
+        match self.timelock {
+            Some(timelock) => {
+                timelock.validate()?;
+                payload.extend(timelock.encode());
+            }
+            None if self.version() >= Version::V4 => payload.extend(NO_TIMELOCK),
+            None => (),
+        }
+        match self.recovery {
+            Some(recovery) => {
+                recovery.validate()?;
+                payload.extend(recovery.encode());
+            }
+            None if self.deployer.is_some() => payload.extend(NO_RECOVERY),
+            None => (),
+        }
+        if let Some(deployer) = self.deployer {
+            payload.extend(deployer.serialize());
+        }
+        Ok(payload)
+    }
+
+    /// Decodes the payload of an `OP_RETURN` output.
+    pub fn decode(payload: &[u8]) -> Result<Self> {
+        ensure!(
+            payload.len() >= VK_HASH_LEN,
+            "the OP_RETURN payload is too small, it should at least contain the {VK_HASH_LEN}-byte hash of the verifier key"
+        );
+        let (vk_hash, rest) = payload.split_at(VK_HASH_LEN);
+        let vk_hash = vk_hash.try_into().unwrap();
+
+        if [
+            V1_PAYLOAD_LEN,
+            V2_PAYLOAD_LEN,
+            V3_PAYLOAD_LEN,
+            V4_PAYLOAD_LEN,
+            V5_PAYLOAD_LEN,
+        ]
+        .contains(&payload.len())
+        {
+            let (state, rest) = rest.split_at(MAX_STATE_LEN);
+            let (metadata_hash, rest) = rest.split_at(METADATA_HASH_LEN);
+            let state = if state == NO_STATE {
+                None
+            } else {
+                Some(circom_field_from_bytes(state)?)
+            };
+            let metadata_hash: [u8; METADATA_HASH_LEN] = metadata_hash.try_into().unwrap();
+            if rest.is_empty() {
+                return Ok(Self {
+                    metadata_hash: Some(metadata_hash),
+                    ..Self::new(vk_hash, state)
+                });
+            }
+            let (admin, rest) = rest.split_at(ADMIN_LEN);
+            if rest.is_empty() {
+                return Ok(Self {
+                    metadata_hash: (metadata_hash != NO_METADATA).then_some(metadata_hash),
+                    admin: Some(circom_field_from_bytes(admin)?),
+                    ..Self::new(vk_hash, state)
+                });
+            }
+            let admin = if admin == NO_ADMIN {
+                None
+            } else {
+                Some(circom_field_from_bytes(admin)?)
+            };
+            let (timelock, rest) = rest.split_at(TIMELOCK_LEN);
+            if rest.is_empty() {
+                return Ok(Self {
+                    metadata_hash: (metadata_hash != NO_METADATA).then_some(metadata_hash),
+                    admin,
+                    timelock: Some(Timelock::decode(timelock)?),
+                    ..Self::new(vk_hash, state)
+                });
+            }
+            let timelock = if timelock == NO_TIMELOCK {
+                None
+            } else {
+                Some(Timelock::decode(timelock)?)
+            };
+            let (recovery, deployer) = rest.split_at(RECOVERY_LEN);
+            let (recovery, deployer) = if deployer.is_empty() {
+                (Some(Recovery::decode(recovery)?), None)
+            } else {
+                let recovery = if recovery == NO_RECOVERY {
+                    None
+                } else {
+                    Some(Recovery::decode(recovery)?)
+                };
+                let deployer = XOnlyPublicKey::from_slice(deployer)
+                    .context("the deployer key is not a valid public key")?;
+                (recovery, Some(deployer))
+            };
+            return Ok(Self {
+                metadata_hash: (metadata_hash != NO_METADATA).then_some(metadata_hash),
+                admin,
+                timelock,
+                recovery,
+                deployer,
+                ..Self::new(vk_hash, state)
+            });
+        }
+
+        ensure!(
+            rest.len() <= MAX_STATE_LEN,
+            "the OP_RETURN payload is too large, the state can't be more than {MAX_STATE_LEN} bytes"
+        );
+        let state = if rest.is_empty() {
+            None
+        } else {
+            Some(circom_field_from_bytes(rest)?)
+        };
+        Ok(Self::new(vk_hash, state))
+    }
+
+    //
+    // Scripts
+    //
+
+    /// The script of the output locking the funds of the zkapp:
+    /// the zkBitcoin address, unless the zkapp is recoverable (see [crate::recovery]).
+    pub fn script_pubkey(&self) -> Result<ScriptBuf> {
+        recovery::zkapp_script_pubkey(self.recovery.as_ref())
+    }
+
+    /// The `OP_RETURN` script committing to the metadata.
+    pub fn to_script(&self) -> Result<ScriptBuf> {
+        let payload =
+            PushBytesBuf::try_from(self.encode()?).context("the OP_RETURN payload is too large")?;
+        Ok(ScriptBuf::new_op_return(payload))
+    }
+
+    /// Decodes the metadata committed to by an `OP_RETURN` script.
+    pub fn from_script(script: &Script) -> Result<Self> {
+        Self::decode(&payload_of(script)?)
+    }
+}
+
+/// The payload of an `OP_RETURN` script, which must push it and nothing else.
+pub fn payload_of(script: &Script) -> Result<Vec<u8>> {
+    let mut instructions = script.instructions();
+    ensure!(
+        matches!(instructions.next(), Some(Ok(Instruction::Op(op))) if op == OP_RETURN),
+        "the script is not an OP_RETURN"
+    );
+    let payload = match instructions.next() {
+        Some(Ok(Instruction::PushBytes(bytes))) => bytes.as_bytes().to_vec(),
+        _ => bail!("the OP_RETURN script doesn't push a payload"),
+    };
+    ensure!(
+        instructions.next().is_none(),
+        "the OP_RETURN script pushes more than a payload"
+    );
+    Ok(payload)
+}
+
+//
+// Human-readable metadata
+//
+
+/// What a zkapp says it is, for explorers and `zkbtc list-zkapps` to show.
+/// Anyone can claim any name: only the hash of the metadata is committed on chain, not who wrote it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ZkappMetadata {
+    /// The name of the zkapp.
+    pub name: String,
+
+    /// The version of the zkapp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+
+    /// Who wrote the zkapp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+}
+
+impl ZkappMetadata {
+    /// Ensures that the metadata is short, and printable.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.name.trim().is_empty(), "the zkapp's name is empty");
+        for (what, field) in [
+            ("name", Some(&self.name)),
+            ("version", self.version.as_ref()),
+            ("author", self.author.as_ref()),
+        ] {
+            let Some(field) = field else {
+                continue;
+            };
+            ensure!(
+                field.chars().count() <= MAX_METADATA_FIELD_LEN,
+                "the zkapp's {what} is longer than {MAX_METADATA_FIELD_LEN} characters"
+            );
+            ensure!(
+                !field.chars().any(char::is_control),
+                "the zkapp's {what} contains control characters"
+            );
+        }
+        Ok(())
+    }
+
+    /// The hash of the metadata, committed on chain.
+    pub fn hash(&self) -> [u8; METADATA_HASH_LEN] {
+        let mut engine = sha256::Hash::engine();
+        engine.input(METADATA_DOMAIN);
+        engine.input(&serde_json::to_vec(self).expect("metadata always serializes"));
+        let hash = sha256::Hash::from_engine(engine).to_byte_array();
+        hash[..METADATA_HASH_LEN].try_into().unwrap()
+    }
+}
+
+impl fmt::Display for ZkappMetadata {
+    /// Formats the metadata as `name version (by author)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if let Some(version) = &self.version {
+            write!(f, " {version}")?;
+        }
+        if let Some(author) = &self.author {
+            write!(f, " (by {author})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the directory the known metadata of zkapps is kept in (`~/.zkbitcoin/metadata`),
+/// as JSON files named after their hash.
+#[cfg(feature = "node")]
+pub fn metadata_dir() -> std::path::PathBuf {
+    crate::zkbitcoin_folder().join("metadata")
+}
+
+/// Keeps the metadata of a zkapp (e.g. shared by its deployer), to show it along with the zkapp.
+/// Returns the path of the file written.
+#[cfg(feature = "node")]
+pub fn save_metadata(metadata: &ZkappMetadata) -> Result<std::path::PathBuf> {
+    metadata.validate()?;
+    let dir = metadata_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("couldn't create {}", dir.display()))?;
+    let path = dir.join(format!("{}.json", hex::encode(metadata.hash())));
+    std::fs::write(&path, serde_json::to_string_pretty(metadata)?)
+        .with_context(|| format!("couldn't write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Returns the metadata of a hash, if it is known (see [save_metadata]).
+#[cfg(feature = "node")]
+pub fn load_metadata(metadata_hash: &[u8; METADATA_HASH_LEN]) -> Result<Option<ZkappMetadata>> {
+    let path = metadata_dir().join(format!("{}.json", hex::encode(metadata_hash)));
+    if !path.exists() {
+        return Ok(None);
+    }
+    let metadata: ZkappMetadata = serde_json::from_slice(
+        &std::fs::read(&path).with_context(|| format!("couldn't read {}", path.display()))?,
+    )
+    .with_context(|| format!("{} is not the metadata of a zkapp", path.display()))?;
+    ensure!(
+        &metadata.hash() == metadata_hash,
+        "{} doesn't hash to its name",
+        path.display()
+    );
+    Ok(Some(metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zkapp_data() {
+        // stateless
+        let data = ZkappData::new([7; 32], None);
+        let script = data.to_script().unwrap();
+        assert!(script.is_op_return());
+        assert_eq!(payload_of(&script).unwrap(), vec![7; 32]);
+        assert_eq!(ZkappData::from_script(&script).unwrap(), data);
+
+        // stateful, up to the largest field element
+        for state in [
+            "0",
+            "42",
+            "21888242871839275222246405745257275088548364400416034343698204186575808495616",
+        ] {
+            let data = ZkappData::new([7; 32], Some(state.to_string()));
+            let decoded = ZkappData::from_script(&data.to_script().unwrap()).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(decoded.version(), Version::V0);
+        }
+
+        // payloads without a verifier key hash, with a state too large, or not in an OP_RETURN
+        assert!(ZkappData::decode(&[7; 31]).is_err());
+        assert!(ZkappData::decode(&[0xff; 64]).is_err());
+        assert!(ZkappData::decode(&[7; 65]).is_err());
+        assert!(ZkappData::new([7; 32], Some("x".to_string()))
+            .encode()
+            .is_err());
+        assert!(payload_of(&ScriptBuf::new()).is_err());
+        assert!(payload_of(ScriptBuf::new_op_return([1, 2, 3]).as_script()).is_ok());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let metadata = ZkappMetadata {
+            name: "escrow".to_string(),
+            version: Some("1.0.0".to_string()),
+            author: None,
+        };
+        metadata.validate().unwrap();
+
+        // the hash of the metadata is committed along with the state (if any)
+        for state in [None, Some("0".to_string()), Some("42".to_string())] {
+            let data = ZkappData {
+                metadata_hash: Some(metadata.hash()),
+                ..ZkappData::new([7; 32], state)
+            };
+            let payload = data.encode().unwrap();
+            assert_eq!(payload.len(), 80);
+            let decoded = ZkappData::from_script(&data.to_script().unwrap()).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(decoded.version(), Version::V1);
+        }
+
+        // the admin key is committed after the metadata (if any)
+        for metadata_hash in [None, Some(metadata.hash())] {
+            let data = ZkappData {
+                metadata_hash,
+                admin: Some("123456789".to_string()),
+                ..ZkappData::new([7; 32], Some("42".to_string()))
+            };
+            assert_eq!(data.encode().unwrap().len(), 112);
+            let decoded = ZkappData::from_script(&data.to_script().unwrap()).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(decoded.version(), Version::V2);
+        }
+        let not_a_field_element = ZkappData {
+            admin: Some("x".to_string()),
+            ..ZkappData::new([7; 32], Some("42".to_string()))
+        };
+        assert!(not_a_field_element.encode().is_err());
+
+        // the timelock is committed after the admin key (if any)
+        for admin in [None, Some("123456789".to_string())] {
+            let data = ZkappData {
+                admin,
+                timelock: Some(Timelock::Relative(144)),
+                ..ZkappData::new([7; 32], None)
+            };
+            assert_eq!(data.encode().unwrap().len(), 117);
+            let decoded = ZkappData::from_script(&data.to_script().unwrap()).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(decoded.version(), Version::V3);
+        }
+
+        // and the recovery after the timelock (if any)
+        let depositor = secp256k1::XOnlyPublicKey::from_slice(&[
+            0x79, 0xbe, 0x66, 0x7e, 0xf9, 0xdc, 0xbb, 0xac, 0x55, 0xa0, 0x62, 0x95, 0xce, 0x87,
+            0x0b, 0x07, 0x02, 0x9b, 0xfc, 0xdb, 0x2d, 0xce, 0x28, 0xd9, 0x59, 0xf2, 0x81, 0x5b,
+            0x16, 0xf8, 0x17, 0x98,
+        ])
+        .unwrap();
+        for timelock in [None, Some(Timelock::Relative(144))] {
+            let data = ZkappData {
+                timelock,
+                recovery: Some(Recovery {
+                    depositor,
+                    blocks: recovery::DEFAULT_RECOVERY_BLOCKS,
+                }),
+                ..ZkappData::new([7; 32], Some("42".to_string()))
+            };
+            assert_eq!(data.encode().unwrap().len(), 151);
+            let decoded = ZkappData::from_script(&data.to_script().unwrap()).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(decoded.version(), Version::V4);
+            assert_ne!(
+                decoded.script_pubkey().unwrap(),
+                ZkappData::new([7; 32], None).script_pubkey().unwrap()
+            );
+        }
+
+        // and the deployer key after the recovery (if any)
+        for recovery in [
+            None,
+            Some(Recovery {
+                depositor,
+                blocks: recovery::DEFAULT_RECOVERY_BLOCKS,
+            }),
+        ] {
+            let data = ZkappData {
+                recovery,
+                deployer: Some(depositor),
+                ..ZkappData::new([7; 32], None)
+            };
+            assert_eq!(data.encode().unwrap().len(), 183);
+            let decoded = ZkappData::from_script(&data.to_script().unwrap()).unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(decoded.version(), Version::V5);
+        }
+
+        // any change to the metadata changes its hash
+        let renamed = ZkappMetadata {
+            name: "escrow2".to_string(),
+            ..metadata.clone()
+        };
+        assert_ne!(renamed.hash(), metadata.hash());
+
+        // metadata has a name, and is short and printable
+        assert!(ZkappMetadata::default().validate().is_err());
+        let long = ZkappMetadata {
+            name: "x".repeat(MAX_METADATA_FIELD_LEN + 1),
+            ..Default::default()
+        };
+        assert!(long.validate().is_err());
+        let control = ZkappMetadata {
+            name: "escrow".to_string(),
+            author: Some("\u{1b}[31mme".to_string()),
+            ..Default::default()
+        };
+        assert!(control.validate().is_err());
+    }
+}